@@ -4,20 +4,190 @@
 
 use std::sync::Arc;
 
-use domain::UserRepository;
-use infra::session_store::{InfraSessionStore, SessionManagerLayer};
+use axum::extract::{FromRequestParts, Request};
+use axum::http::{header, request::Parts};
+use axum::middleware::Next;
+use axum::response::Response;
+use domain::{EventPublisher, LoginPolicy, Role, User, UserRepository};
+use infra::session_store::{DegradingSessionStore, SessionManagerLayer};
 
 use crate::error::ApiError;
+use crate::state::AppState;
 
 #[cfg(feature = "auth-axum-login")]
-pub use infra::auth::backend::{AuthManagerLayer, AuthSession, AuthUser, Credentials};
+pub use infra::auth::backend::{
+    AuthError, AuthManagerLayer, AuthSession, AuthSessionError, AuthUser, Claims, Credentials,
+};
+#[cfg(feature = "auth-axum-login")]
+pub use infra::auth::password::PasswordHashPolicy;
 
 #[cfg(feature = "auth-axum-login")]
 pub async fn setup_auth_layer(
-    session_layer: SessionManagerLayer<InfraSessionStore>,
+    session_layer: SessionManagerLayer<DegradingSessionStore>,
     user_repo: Arc<dyn UserRepository>,
+    login_policy: LoginPolicy,
+    password_policy: PasswordHashPolicy,
+    events: Arc<dyn EventPublisher>,
 ) -> Result<AuthManagerLayer, ApiError> {
-    infra::auth::backend::setup_auth_layer(session_layer, user_repo)
-        .await
-        .map_err(|e| ApiError::Internal(e.to_string()))
+    infra::auth::backend::setup_auth_layer(
+        session_layer,
+        user_repo,
+        login_policy,
+        password_policy,
+        events,
+    )
+    .await
+    .map_err(|e| ApiError::Internal(e.to_string()))
+}
+
+/// Middleware rejecting requests from anyone but admins, before the handler runs.
+///
+/// Apply to a router with `.route_layer(axum::middleware::from_fn(admin_only))`.
+#[cfg(feature = "auth-axum-login")]
+pub async fn admin_only(
+    auth_session: AuthSession,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    require_role(auth_session.user.as_ref().map(|user| &user.0), Role::Admin)?;
+    Ok(next.run(request).await)
+}
+
+/// Check that the current user holds at least `role`
+pub fn require_role(user: Option<&User>, role: Role) -> Result<(), ApiError> {
+    let user = user.ok_or(ApiError::Unauthorized("Not logged in".to_string()))?;
+    if !user.role.satisfies(role) {
+        return Err(ApiError::Forbidden(format!("{} access required", role)));
+    }
+    Ok(())
+}
+
+/// Session key holding the id of the admin impersonating the current user
+pub const IMPERSONATOR_KEY: &str = "impersonated_by";
+
+/// Session key holding the user who passed the password step but still owes a TOTP code
+pub const PENDING_2FA_KEY: &str = "pending_2fa_user";
+
+/// Session key counting TOTP attempts for the pending login
+pub const PENDING_2FA_ATTEMPTS_KEY: &str = "pending_2fa_attempts";
+
+/// Session key carrying "remember me" across the TOTP step
+pub const PENDING_2FA_REMEMBER_KEY: &str = "pending_2fa_remember_me";
+
+/// Session key carrying the validated `?next=` target across the TOTP step
+pub const PENDING_2FA_NEXT_KEY: &str = "pending_2fa_next";
+
+/// Session key carrying an OIDC login's claims across the TOTP step
+pub const PENDING_2FA_CLAIMS_KEY: &str = "pending_2fa_claims";
+
+/// TOTP attempts allowed before the pending login is discarded
+pub const MAX_2FA_ATTEMPTS: u32 = 5;
+
+/// Session key holding the [`OidcLoginAttempt`](domain::OidcLoginAttempt)
+/// sent to the OIDC provider, checked on the callback
+pub const OIDC_ATTEMPT_KEY: &str = "oidc_attempt";
+
+/// Session key carrying the `?invite_code=` for a first OIDC login
+pub const OIDC_INVITE_KEY: &str = "oidc_invite_code";
+
+/// Session key carrying the validated `?next=` target across the OIDC round trip
+pub const OIDC_NEXT_KEY: &str = "oidc_next";
+
+/// Authenticates requests bearing `Authorization: ApiKey <key>`
+pub struct ApiKeyAuth(pub User);
+
+impl FromRequestParts<AppState> for ApiKeyAuth {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let raw_key = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("ApiKey "))
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .ok_or(ApiError::Unauthorized("Missing API key".to_string()))?;
+
+        let user = state
+            .user_service
+            .authenticate_api_key(raw_key)
+            .await?
+            .ok_or(ApiError::Unauthorized("Invalid API key".to_string()))?;
+
+        Ok(Self(user))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use domain::Email;
+
+    fn user(role: Role) -> User {
+        let mut user = User::new("test|1", Email::try_from("user@example.com").unwrap()).unwrap();
+        user.role = role;
+        user
+    }
+
+    #[test]
+    fn test_non_admin_is_forbidden() {
+        let result = require_role(Some(&user(Role::User)), Role::Admin);
+        assert!(matches!(result, Err(ApiError::Forbidden(_))));
+    }
+
+    #[test]
+    fn test_anonymous_is_unauthorized() {
+        let result = require_role(None, Role::Admin);
+        assert!(matches!(result, Err(ApiError::Unauthorized(_))));
+    }
+
+    #[test]
+    fn test_admin_is_allowed() {
+        assert!(require_role(Some(&user(Role::Admin)), Role::Admin).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_admin_only_rejects_before_the_handler_runs() {
+        use crate::test_support::{TestClient, build_test_app_with, test_config, test_pool};
+        use axum::http::StatusCode;
+
+        let config = crate::config::Config {
+            first_user_is_admin: true,
+            ..test_config()
+        };
+        let (app, state) = build_test_app_with(test_pool().await, config).await;
+        let credentials =
+            |email: &str| serde_json::json!({"email": email, "password": "correct horse"});
+        let mut admin = TestClient::new(app.clone());
+        admin
+            .post_json("/api/v1/auth/register", credentials("admin@example.com"))
+            .await;
+        let mut alice = TestClient::new(app.clone());
+        alice
+            .post_json("/api/v1/auth/register", credentials("alice@example.com"))
+            .await;
+
+        // Minting an invite is a write, so a handler that ran would leave one behind
+        let minted = alice
+            .post_json("/api/v1/admin/invites", serde_json::json!({}))
+            .await;
+        assert_eq!(minted.status, StatusCode::FORBIDDEN);
+        let minted = TestClient::new(app)
+            .post_json("/api/v1/admin/invites", serde_json::json!({}))
+            .await;
+        assert_eq!(minted.status, StatusCode::UNAUTHORIZED);
+        let (_, total) = state.user_service.list_invites(10, 0).await.unwrap();
+        assert_eq!(total, 0);
+
+        let minted = admin
+            .post_json("/api/v1/admin/invites", serde_json::json!({}))
+            .await;
+        assert_eq!(minted.status, StatusCode::CREATED);
+        let (_, total) = state.user_service.list_invites(10, 0).await.unwrap();
+        assert_eq!(total, 1);
+    }
 }
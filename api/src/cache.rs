@@ -0,0 +1,91 @@
+//! Single-value cache with a time to live
+//!
+//! Holds a computed response body, e.g. `/config`, so it is not rebuilt on
+//! every request.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// One cached value, recomputed once older than the TTL
+#[derive(Debug)]
+pub struct TtlCache<T> {
+    ttl: Duration,
+    entry: Mutex<Option<(Instant, T)>>,
+}
+
+impl<T: Clone> TtlCache<T> {
+    /// A zero `ttl` disables caching
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entry: Mutex::new(None),
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// The cached value, or `compute`'s result if there is none or it expired
+    pub fn get_or_insert_with(&self, compute: impl FnOnce() -> T) -> T {
+        self.get_or_insert_with_at(Instant::now(), compute)
+    }
+
+    fn get_or_insert_with_at(&self, now: Instant, compute: impl FnOnce() -> T) -> T {
+        let mut entry = self.entry.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((stored_at, value)) = entry.as_ref()
+            && now.saturating_duration_since(*stored_at) < self.ttl
+        {
+            return value.clone();
+        }
+
+        let value = compute();
+        *entry = Some((now, value.clone()));
+        value
+    }
+
+    /// Drop the cached value, so the next read recomputes it
+    pub fn invalidate(&self) {
+        *self.entry.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_value_is_reused_until_it_expires() {
+        let cache = TtlCache::new(Duration::from_secs(10));
+        let computed = Cell::new(0);
+        let compute = || {
+            computed.set(computed.get() + 1);
+            computed.get()
+        };
+
+        let start = Instant::now();
+        assert_eq!(cache.get_or_insert_with_at(start, compute), 1);
+        let soon = start + Duration::from_secs(1);
+        assert_eq!(cache.get_or_insert_with_at(soon, compute), 1);
+        assert_eq!(computed.get(), 1);
+
+        let later = start + Duration::from_secs(10);
+        assert_eq!(cache.get_or_insert_with_at(later, compute), 2);
+    }
+
+    #[test]
+    fn test_invalidate_forces_recompute() {
+        let cache = TtlCache::new(Duration::from_secs(10));
+        assert_eq!(cache.get_or_insert_with(|| 1), 1);
+        cache.invalidate();
+        assert_eq!(cache.get_or_insert_with(|| 2), 2);
+    }
+
+    #[test]
+    fn test_zero_ttl_disables_caching() {
+        let cache = TtlCache::new(Duration::ZERO);
+        assert_eq!(cache.get_or_insert_with(|| 1), 1);
+        assert_eq!(cache.get_or_insert_with(|| 2), 2);
+    }
+}
@@ -0,0 +1,79 @@
+//! Command line interface
+//!
+//! Without a subcommand the binary serves the API.
+
+use std::io::BufRead;
+
+use clap::{Parser, Subcommand};
+use domain::Password;
+
+use crate::auth::PasswordHashPolicy;
+
+#[derive(Debug, Parser)]
+#[command(about = "User management API")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Read a password from stdin and print its Argon2 hash, for inserting users by hand
+    HashPassword,
+}
+
+/// Hash the first line of `input` with the configured Argon2 parameters.
+///
+/// The password is read from stdin rather than argv so it stays out of shell history.
+pub fn hash_password(
+    mut input: impl BufRead,
+    policy: &PasswordHashPolicy,
+) -> anyhow::Result<String> {
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    let password = line.strip_suffix('\n').unwrap_or(&line);
+    let password = password.strip_suffix('\r').unwrap_or(password);
+
+    let password = Password::new(password)?;
+    policy.hash(password.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode, header};
+    use domain::{Email, User};
+    use infra::factory::build_user_repository;
+    use tower::ServiceExt;
+
+    use crate::test_support::{build_test_app_on, test_pool};
+
+    #[tokio::test]
+    async fn test_hashed_password_logs_in() {
+        let policy = PasswordHashPolicy::default();
+        let hash = hash_password("correct horse\n".as_bytes(), &policy).unwrap();
+
+        let pool = test_pool().await;
+        let mut user = User::new("local|1", Email::try_from("ops@example.com").unwrap()).unwrap();
+        user.password_hash = Some(hash);
+        let repo = build_user_repository(&pool).await.unwrap();
+        repo.save(&user).await.unwrap();
+
+        let (app, _) = build_test_app_on(pool).await;
+        let login = Request::post("/api/v1/auth/login")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                r#"{"email": "ops@example.com", "password": "correct horse"}"#,
+            ))
+            .unwrap();
+        let response = app.oneshot(login).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_short_password_is_rejected() {
+        let policy = PasswordHashPolicy::default();
+        assert!(hash_password("short\n".as_bytes(), &policy).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+//! Client IP extraction
+//!
+//! Resolves the address of the client behind a request. Forwarding headers are
+//! only honored when configured, since anyone can send them when the server is
+//! reachable directly.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use axum::extract::{ConnectInfo, FromRef, FromRequestParts};
+use axum::http::request::Parts;
+
+/// Where the client address is taken from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ClientIpSource {
+    /// The TCP peer address
+    #[default]
+    Peer,
+    /// The first `X-Forwarded-For` entry, falling back to the peer address
+    XForwardedFor,
+}
+
+/// The client's IP address.
+///
+/// Resolves to `0.0.0.0` when no address is known (e.g. the server was started
+/// without connect info).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+impl ClientIp {
+    pub fn from_parts(parts: &Parts, source: ClientIpSource) -> Self {
+        let forwarded = match source {
+            ClientIpSource::XForwardedFor => parts
+                .headers
+                .get("x-forwarded-for")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.split(',').next())
+                .and_then(|first| first.trim().parse().ok()),
+            ClientIpSource::Peer => None,
+        };
+
+        let ip = forwarded
+            .or_else(|| {
+                parts
+                    .extensions
+                    .get::<ConnectInfo<SocketAddr>>()
+                    .map(|ConnectInfo(addr)| addr.ip())
+            })
+            .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        Self(ip)
+    }
+}
+
+impl<S> FromRequestParts<S> for ClientIp
+where
+    ClientIpSource: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_parts(parts, ClientIpSource::from_ref(state)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+
+    fn parts(forwarded_for: Option<&str>, peer: Option<&str>) -> Parts {
+        let mut request = Request::builder();
+        if let Some(value) = forwarded_for {
+            request = request.header("x-forwarded-for", value);
+        }
+        let (mut parts, _) = request.body(()).unwrap().into_parts();
+        if let Some(peer) = peer {
+            parts
+                .extensions
+                .insert(ConnectInfo::<SocketAddr>(peer.parse().unwrap()));
+        }
+        parts
+    }
+
+    fn ip(value: &str) -> ClientIp {
+        ClientIp(value.parse().unwrap())
+    }
+
+    #[test]
+    fn test_forwarded_for_is_ignored_by_default() {
+        let request = parts(Some("203.0.113.7"), Some("10.0.0.1:5000"));
+        assert_eq!(
+            ClientIp::from_parts(&request, ClientIpSource::Peer),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn test_first_forwarded_for_entry_is_used_when_trusted() {
+        let request = parts(Some("203.0.113.7, 10.0.0.2"), Some("10.0.0.1:5000"));
+        assert_eq!(
+            ClientIp::from_parts(&request, ClientIpSource::XForwardedFor),
+            ip("203.0.113.7")
+        );
+
+        let request = parts(Some("garbage"), Some("10.0.0.1:5000"));
+        assert_eq!(
+            ClientIp::from_parts(&request, ClientIpSource::XForwardedFor),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn test_unknown_address_is_unspecified() {
+        assert_eq!(
+            ClientIp::from_parts(&parts(None, None), ClientIpSource::Peer),
+            ip("0.0.0.0")
+        );
+    }
+}
@@ -3,15 +3,78 @@
 //! Loads configuration from environment variables.
 
 use std::env;
+use std::str::FromStr;
 
 use serde::Deserialize;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     pub database_url: String,
+
+    /// How long startup waits to open `db_min_connections` before giving up
+    #[serde(default = "default_db_warm_up_timeout_secs")]
+    pub db_warm_up_timeout_secs: u64,
+
+    /// Apply migrations at startup; when off, startup fails unless they were already applied
+    #[serde(default = "default_true")]
+    pub run_migrations_on_start: bool,
+
+    /// Probe the `users` columns at startup and refuse to serve a drifted schema
+    #[serde(default = "default_true")]
+    pub verify_schema_on_start: bool,
+
+    /// Directory of extra `<VERSION>_<DESCRIPTION>.sql` migrations run with the embedded ones
+    #[serde(default)]
+    pub migrations_dir: Option<String>,
+
+    /// Retries of startup migrations that fail on a locked database; 0 fails at once
+    #[serde(default)]
+    pub migration_lock_retries: u32,
+
+    #[serde(default = "default_migration_retry_delay_ms")]
+    pub migration_retry_delay_ms: u64,
+
+    /// Read-only replica for user reads; everything goes to the primary when unset
+    #[serde(default)]
+    pub database_replica_url: Option<String>,
+
+    /// How long users looked up by id or subject are cached in memory; 0 disables.
+    /// Changes made by other instances show up once this runs out.
+    #[serde(default)]
+    pub user_cache_ttl_secs: u64,
     pub session_secret: String,
+    /// Earlier session secrets, still accepted while sessions rotate to the current one
+    #[serde(default)]
+    pub session_secret_previous: Vec<String>,
+    /// Also accept session cookies set before cookies were signed. Turn on for
+    /// one session lifetime after upgrading; each is re-signed on its next response.
+    #[serde(default)]
+    pub session_accept_unsigned: bool,
+
+    /// Session cookie `Domain`, e.g. `example.com` to share sessions across subdomains
+    #[serde(default)]
+    pub session_cookie_domain: Option<String>,
+
+    #[serde(default = "default_session_cookie_path")]
+    pub session_cookie_path: String,
+
+    /// How the session id travels: `cookie`, `header` (`Authorization: Session <id>`) or `both`
+    #[serde(default = "default_session_transport")]
+    pub session_transport: String,
     pub cors_allowed_origins: Vec<String>,
 
+    /// Hosts a post-login `?next=` may send users to; relative paths are always allowed
+    #[serde(default)]
+    pub redirect_allowlist: Vec<String>,
+
+    /// Extra origins allowed on `cors_public_paths` only; no override when empty
+    #[serde(default)]
+    pub cors_public_origins: Vec<String>,
+
+    /// Path prefixes of public routes that accept `cors_public_origins`
+    #[serde(default = "default_cors_public_paths")]
+    pub cors_public_paths: Vec<String>,
+
     #[serde(default = "default_port")]
     pub port: u16,
 
@@ -26,6 +89,330 @@ pub struct Config {
 
     #[serde(default = "default_db_min_connections")]
     pub db_min_connections: u32,
+
+    /// Close pooled connections idle for longer than this
+    #[serde(default = "default_db_idle_timeout_secs")]
+    pub db_idle_timeout_secs: u64,
+
+    /// Recycle pooled connections older than this, idle or not
+    #[serde(default = "default_db_max_lifetime_secs")]
+    pub db_max_lifetime_secs: u64,
+
+    /// Postgres `sslmode`, e.g. `require` for managed databases; the URL's own
+    /// `sslmode` applies when unset. Ignored by SQLite
+    #[serde(default)]
+    pub db_ssl_mode: Option<String>,
+
+    /// CA certificate to verify the Postgres server with (`verify-ca`/`verify-full`)
+    #[serde(default)]
+    pub db_ssl_root_cert: Option<String>,
+
+    /// Requests processed at once before shedding load with 503; unlimited when unset
+    #[serde(default)]
+    pub max_concurrent_requests: Option<usize>,
+
+    /// Take the client IP from `X-Forwarded-For`; only enable behind a trusted proxy
+    #[serde(default)]
+    pub trust_forwarded_for: bool,
+
+    /// Window over which per-client traffic is counted for `/admin/abuse`
+    #[serde(default = "default_abuse_window_secs")]
+    pub abuse_window_secs: u64,
+
+    /// Header carrying the request id, which also tags the request's log events
+    #[serde(default = "default_request_id_header")]
+    pub request_id_header: String,
+
+    /// How long `/config` responses are cached, here and by clients; 0 disables
+    #[serde(default = "default_config_cache_secs")]
+    pub config_cache_secs: u64,
+
+    /// Clients tracked at once; the least recently seen are dropped first
+    #[serde(default = "default_abuse_max_clients")]
+    pub abuse_max_clients: usize,
+
+    /// `/auth/email-available` checks allowed per client per `email_check_window_secs`
+    #[serde(default = "default_email_check_limit")]
+    pub email_check_limit: u32,
+
+    #[serde(default = "default_email_check_window_secs")]
+    pub email_check_window_secs: u64,
+
+    /// Postgres `statement_timeout` / SQLite `busy_timeout`; driver default when unset
+    #[serde(default)]
+    pub statement_timeout_ms: Option<u64>,
+
+    /// How long a password reset token stays valid
+    #[serde(default = "default_reset_token_ttl_minutes")]
+    pub reset_token_ttl_minutes: u64,
+
+    /// Reset links are this URL with the token appended
+    #[serde(default = "default_password_reset_url")]
+    pub password_reset_url: String,
+
+    /// Answer login and reset requests the same whether or not the account
+    /// exists; turn off only to debug, as it reveals who is registered
+    #[serde(default = "default_enumeration_protection")]
+    pub enumeration_protection: bool,
+
+    /// How long an email verification link stays valid
+    #[serde(default = "default_verification_token_ttl_minutes")]
+    pub verification_token_ttl_minutes: u64,
+
+    /// Minimum time between verification emails to the same user
+    #[serde(default = "default_verification_resend_cooldown_secs")]
+    pub verification_resend_cooldown_secs: u64,
+
+    /// Verification links are this URL with the token appended
+    #[serde(default = "default_verification_url")]
+    pub verification_url: String,
+
+    /// Concurrent sessions allowed per user; unlimited when unset
+    #[serde(default)]
+    pub session_limit: Option<u32>,
+
+    /// At the session limit: `reject` the login or `evict_oldest` session
+    #[serde(default = "default_session_limit_mode")]
+    pub session_limit_mode: String,
+
+    /// Unexpired API keys allowed per user; unlimited when unset
+    #[serde(default)]
+    pub max_api_keys_per_user: Option<u32>,
+
+    /// Absolute lifetime of sessions opened with "remember me"
+    #[serde(default = "default_remember_me_days")]
+    pub remember_me_days: u32,
+
+    /// Log executed SQL; off by default
+    #[serde(default)]
+    pub log_sql: bool,
+
+    /// Level for SQL logs (`trace`, `debug`, `info`, ...)
+    #[serde(default = "default_sql_log_level")]
+    pub sql_log_level: String,
+
+    /// With `log_sql`, warn about statements slower than this
+    #[serde(default = "default_slow_query_ms")]
+    pub slow_query_ms: u64,
+
+    #[serde(default)]
+    pub require_verified_email: bool,
+
+    /// Let new accounts take the email of a soft-deleted account instead of rejecting it
+    #[serde(default)]
+    pub reuse_deleted_emails: bool,
+
+    /// Treat `ada+tag@host` as `ada@host` when checking that an email is free
+    #[serde(default)]
+    pub email_strip_plus_tags: bool,
+
+    /// Providers whose addresses ignore dots in the local part, e.g. `gmail.com`,
+    /// likewise folded for the uniqueness check
+    #[serde(default)]
+    pub email_dot_insensitive_domains: Vec<String>,
+
+    /// Make the first account registered on an empty database an admin
+    #[serde(default)]
+    pub first_user_is_admin: bool,
+
+    /// Local registration needs an unused invite code minted by an admin
+    #[serde(default)]
+    pub require_invite_code: bool,
+
+    /// `local`, `oidc` (password login and registration off) or `both`
+    #[serde(default = "default_auth_mode")]
+    pub auth_mode: String,
+
+    #[serde(default)]
+    pub envelope_responses: bool,
+
+    /// Indent JSON responses; on by default only with `APP_ENV=development`, never in production
+    #[serde(default)]
+    pub pretty_json: Option<bool>,
+
+    /// Reject JSON request bodies with fields the endpoint does not know
+    #[serde(default)]
+    pub strict_json: bool,
+
+    /// Answer JSON endpoints with 415 unless `Content-Type` is JSON; off parses any body as JSON
+    #[serde(default = "default_true")]
+    pub require_json_content_type: bool,
+
+    /// CAPTCHA provider for registration (`hcaptcha` or `turnstile`); disabled when unset
+    #[serde(default)]
+    pub captcha_provider: Option<String>,
+
+    #[serde(default)]
+    pub captcha_secret: Option<String>,
+
+    /// Reject registrations when the CAPTCHA provider cannot be reached
+    #[serde(default)]
+    pub captcha_strict: bool,
+
+    /// ID token claims holding the OIDC subject, email and name; may be dotted paths
+    #[serde(default = "default_oidc_subject_claim")]
+    pub oidc_subject_claim: String,
+
+    #[serde(default = "default_oidc_email_claim")]
+    pub oidc_email_claim: String,
+
+    #[serde(default = "default_oidc_name_claim")]
+    pub oidc_name_claim: String,
+
+    /// Further ID token claims kept in the session after an OIDC login
+    #[serde(default)]
+    pub oidc_session_claims: Vec<String>,
+
+    /// OIDC provider's login page; OIDC login is off unless this and the
+    /// endpoints and client below are set
+    #[serde(default)]
+    pub oidc_authorization_url: Option<String>,
+
+    #[serde(default)]
+    pub oidc_token_url: Option<String>,
+
+    #[serde(default)]
+    pub oidc_userinfo_url: Option<String>,
+
+    #[serde(default)]
+    pub oidc_client_id: Option<String>,
+
+    #[serde(default)]
+    pub oidc_client_secret: Option<String>,
+
+    /// Public URL of `/api/v1/auth/oidc/callback`, as registered with the provider
+    #[serde(default)]
+    pub oidc_redirect_url: Option<String>,
+
+    /// Limit on each request to the provider
+    #[serde(default = "default_oidc_timeout_secs")]
+    pub oidc_timeout_secs: u64,
+
+    /// Further attempts at a provider request that could not connect or timed out
+    #[serde(default = "default_oidc_retries")]
+    pub oidc_retries: u32,
+
+    /// Hex-encoded 32-byte key encrypting TOTP secrets; two-factor is disabled when unset
+    #[serde(default)]
+    pub totp_encryption_key: Option<String>,
+
+    /// Issuer shown in authenticator apps
+    #[serde(default = "default_totp_issuer")]
+    pub totp_issuer: String,
+
+    /// Send `X-Content-Type-Options: nosniff`
+    #[serde(default = "default_true")]
+    pub header_nosniff: bool,
+
+    /// Send `X-Frame-Options: DENY`
+    #[serde(default = "default_true")]
+    pub header_frame_deny: bool,
+
+    #[serde(default = "default_referrer_policy")]
+    pub referrer_policy: Option<String>,
+
+    #[serde(default = "default_content_security_policy")]
+    pub content_security_policy: Option<String>,
+
+    /// Send `Strict-Transport-Security` (only when `secure_cookie` is on)
+    #[serde(default = "default_true")]
+    pub hsts_enabled: bool,
+
+    #[serde(default = "default_hsts_max_age")]
+    pub hsts_max_age: u64,
+
+    /// Deployment environment, unset unless configured; `production` requires
+    /// secure cookies and HSTS at startup
+    #[serde(default)]
+    pub app_env: Option<String>,
+
+    /// Start in production without secure cookies or HSTS, with a warning
+    #[serde(default)]
+    pub allow_insecure_prod: bool,
+
+    /// Trim trailing slashes from request paths before routing
+    #[serde(default = "default_true")]
+    pub normalize_paths: bool,
+
+    /// Process accounts inactive for this many days; disabled when unset
+    #[serde(default)]
+    pub retention_days: Option<u32>,
+
+    /// `soft_delete` or `erase`
+    #[serde(default = "default_retention_mode")]
+    pub retention_mode: String,
+
+    #[serde(default = "default_retention_interval_secs")]
+    pub retention_interval_secs: u64,
+
+    /// Emails of service accounts retention leaves alone once verified
+    #[serde(default)]
+    pub retention_service_accounts: Vec<String>,
+
+    /// How often expired reset and verification tokens are deleted; 0 disables
+    #[serde(default = "default_token_prune_interval_secs")]
+    pub token_prune_interval_secs: u64,
+
+    /// How often the outbox dispatcher polls for pending events
+    #[serde(default = "default_outbox_poll_secs")]
+    pub outbox_poll_secs: u64,
+
+    /// Response timestamp format (`rfc3339` or `epoch_millis`); clients may override per request
+    #[serde(default = "default_timestamp_format")]
+    pub timestamp_format: String,
+
+    /// Startup summary as readable log fields (`human`) or one JSON document (`json`)
+    #[serde(default = "default_startup_log_format")]
+    pub startup_log_format: String,
+
+    /// The `.env` file `from_env` loaded, if any
+    #[serde(skip)]
+    pub env_file: Option<String>,
+
+    /// IANA zone (e.g. `Europe/Warsaw`) response timestamps are shown in; UTC when unset
+    #[serde(default)]
+    pub response_timezone: Option<String>,
+
+    /// Send a welcome email when a user is created
+    #[serde(default = "default_true")]
+    pub send_welcome_email: bool,
+
+    /// Welcome email subject; `{{email}}` is replaced with the address
+    #[serde(default)]
+    pub welcome_email_subject: Option<String>,
+
+    /// Files overriding the embedded welcome email bodies
+    #[serde(default)]
+    pub welcome_email_html_path: Option<String>,
+
+    #[serde(default)]
+    pub welcome_email_text_path: Option<String>,
+
+    /// Argon2id memory cost (KiB) for password hashes
+    #[serde(default = "default_argon2_memory_kib")]
+    pub argon2_memory_kib: u32,
+
+    #[serde(default = "default_argon2_iterations")]
+    pub argon2_iterations: u32,
+
+    #[serde(default = "default_argon2_parallelism")]
+    pub argon2_parallelism: u32,
+
+    /// Upgrade password hashes weaker than the Argon2 settings on login
+    #[serde(default = "default_true")]
+    pub password_rehash_on_login: bool,
+
+    /// Require a double-submit CSRF token on state-changing cookie-auth requests
+    #[serde(default = "default_true")]
+    pub csrf_protection: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_migration_retry_delay_ms() -> u64 {
+    1000
 }
 
 fn default_secure_cookie() -> bool {
@@ -36,10 +423,134 @@ fn default_db_max_connections() -> u32 {
     5
 }
 
+fn default_db_warm_up_timeout_secs() -> u64 {
+    10
+}
+
 fn default_db_min_connections() -> u32 {
     1
 }
 
+fn default_db_idle_timeout_secs() -> u64 {
+    600
+}
+
+fn default_db_max_lifetime_secs() -> u64 {
+    1800
+}
+
+fn default_oidc_subject_claim() -> String {
+    "sub".to_string()
+}
+
+fn default_oidc_email_claim() -> String {
+    "email".to_string()
+}
+
+fn default_oidc_name_claim() -> String {
+    "name".to_string()
+}
+
+fn default_oidc_timeout_secs() -> u64 {
+    5
+}
+
+fn default_oidc_retries() -> u32 {
+    2
+}
+
+fn default_timestamp_format() -> String {
+    "rfc3339".to_string()
+}
+
+fn default_startup_log_format() -> String {
+    "human".to_string()
+}
+
+fn default_totp_issuer() -> String {
+    "k-template".to_string()
+}
+
+fn default_sql_log_level() -> String {
+    "debug".to_string()
+}
+
+fn default_slow_query_ms() -> u64 {
+    1000
+}
+
+fn default_auth_mode() -> String {
+    "both".to_string()
+}
+
+fn default_session_cookie_path() -> String {
+    "/".to_string()
+}
+
+fn default_session_transport() -> String {
+    "cookie".to_string()
+}
+
+fn default_cors_public_paths() -> Vec<String> {
+    vec!["/api/v1/config".to_string(), "/health".to_string()]
+}
+
+fn default_reset_token_ttl_minutes() -> u64 {
+    30
+}
+
+fn default_password_reset_url() -> String {
+    "http://localhost:3000/reset-password?token=".to_string()
+}
+
+fn default_enumeration_protection() -> bool {
+    true
+}
+
+fn default_verification_token_ttl_minutes() -> u64 {
+    24 * 60
+}
+
+fn default_verification_resend_cooldown_secs() -> u64 {
+    60
+}
+
+fn default_verification_url() -> String {
+    "http://localhost:3000/verify-email?token=".to_string()
+}
+
+fn default_session_limit_mode() -> String {
+    "reject".to_string()
+}
+
+fn default_remember_me_days() -> u32 {
+    30
+}
+
+fn default_request_id_header() -> String {
+    "x-request-id".to_string()
+}
+
+fn default_config_cache_secs() -> u64 {
+    60
+}
+
+fn default_abuse_window_secs() -> u64 {
+    300
+}
+
+fn default_abuse_max_clients() -> usize {
+    10_000
+}
+
+fn default_email_check_limit() -> u32 {
+    10
+}
+
+fn default_email_check_window_secs() -> u64 {
+    60
+}
+
 fn default_port() -> u16 {
     3000
 }
@@ -48,6 +559,186 @@ fn default_host() -> String {
     "127.0.0.1".to_string()
 }
 
+fn default_referrer_policy() -> Option<String> {
+    Some("strict-origin-when-cross-origin".to_string())
+}
+
+fn default_content_security_policy() -> Option<String> {
+    Some("default-src 'none'; frame-ancestors 'none'".to_string())
+}
+
+fn default_hsts_max_age() -> u64 {
+    31_536_000
+}
+
+fn default_retention_mode() -> String {
+    "soft_delete".to_string()
+}
+
+fn default_retention_interval_secs() -> u64 {
+    86_400
+}
+
+fn default_token_prune_interval_secs() -> u64 {
+    3_600
+}
+
+fn default_outbox_poll_secs() -> u64 {
+    5
+}
+
+fn default_argon2_memory_kib() -> u32 {
+    19_456
+}
+
+fn default_argon2_iterations() -> u32 {
+    2
+}
+
+fn default_argon2_parallelism() -> u32 {
+    1
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            database_url: "sqlite:data.db?mode=rwc".to_string(),
+            db_warm_up_timeout_secs: default_db_warm_up_timeout_secs(),
+            run_migrations_on_start: true,
+            verify_schema_on_start: true,
+            migrations_dir: None,
+            migration_lock_retries: 0,
+            migration_retry_delay_ms: default_migration_retry_delay_ms(),
+            database_replica_url: None,
+            user_cache_ttl_secs: 0,
+            session_secret: "k-notes-super-secret-key-must-be-at-least-64-bytes-long!!!!"
+                .to_string(),
+            session_secret_previous: Vec::new(),
+            session_accept_unsigned: false,
+            session_cookie_domain: None,
+            session_cookie_path: default_session_cookie_path(),
+            session_transport: default_session_transport(),
+            cors_allowed_origins: vec!["http://localhost:5173".to_string()],
+            redirect_allowlist: Vec::new(),
+            cors_public_origins: Vec::new(),
+            cors_public_paths: default_cors_public_paths(),
+            port: default_port(),
+            host: default_host(),
+            secure_cookie: default_secure_cookie(),
+            db_max_connections: default_db_max_connections(),
+            db_min_connections: default_db_min_connections(),
+            db_idle_timeout_secs: default_db_idle_timeout_secs(),
+            db_max_lifetime_secs: default_db_max_lifetime_secs(),
+            db_ssl_mode: None,
+            db_ssl_root_cert: None,
+            max_concurrent_requests: None,
+            trust_forwarded_for: false,
+            abuse_window_secs: default_abuse_window_secs(),
+            request_id_header: default_request_id_header(),
+            config_cache_secs: default_config_cache_secs(),
+            abuse_max_clients: default_abuse_max_clients(),
+            email_check_limit: default_email_check_limit(),
+            email_check_window_secs: default_email_check_window_secs(),
+            statement_timeout_ms: None,
+            reset_token_ttl_minutes: default_reset_token_ttl_minutes(),
+            password_reset_url: default_password_reset_url(),
+            enumeration_protection: default_enumeration_protection(),
+            verification_token_ttl_minutes: default_verification_token_ttl_minutes(),
+            verification_resend_cooldown_secs: default_verification_resend_cooldown_secs(),
+            verification_url: default_verification_url(),
+            session_limit: None,
+            session_limit_mode: default_session_limit_mode(),
+            max_api_keys_per_user: None,
+            remember_me_days: default_remember_me_days(),
+            log_sql: false,
+            sql_log_level: default_sql_log_level(),
+            slow_query_ms: default_slow_query_ms(),
+            require_verified_email: false,
+            reuse_deleted_emails: false,
+            email_strip_plus_tags: false,
+            email_dot_insensitive_domains: Vec::new(),
+            first_user_is_admin: false,
+            require_invite_code: false,
+            auth_mode: default_auth_mode(),
+            envelope_responses: false,
+            pretty_json: None,
+            strict_json: false,
+            require_json_content_type: true,
+            captcha_provider: None,
+            captcha_secret: None,
+            captcha_strict: false,
+            oidc_subject_claim: default_oidc_subject_claim(),
+            oidc_email_claim: default_oidc_email_claim(),
+            oidc_name_claim: default_oidc_name_claim(),
+            oidc_session_claims: Vec::new(),
+            oidc_authorization_url: None,
+            oidc_token_url: None,
+            oidc_userinfo_url: None,
+            oidc_client_id: None,
+            oidc_client_secret: None,
+            oidc_redirect_url: None,
+            oidc_timeout_secs: default_oidc_timeout_secs(),
+            oidc_retries: default_oidc_retries(),
+            totp_encryption_key: None,
+            totp_issuer: default_totp_issuer(),
+            header_nosniff: true,
+            header_frame_deny: true,
+            referrer_policy: default_referrer_policy(),
+            content_security_policy: default_content_security_policy(),
+            hsts_enabled: true,
+            hsts_max_age: default_hsts_max_age(),
+            app_env: None,
+            allow_insecure_prod: false,
+            normalize_paths: true,
+            retention_days: None,
+            retention_mode: default_retention_mode(),
+            retention_interval_secs: default_retention_interval_secs(),
+            retention_service_accounts: Vec::new(),
+            token_prune_interval_secs: default_token_prune_interval_secs(),
+            outbox_poll_secs: default_outbox_poll_secs(),
+            timestamp_format: default_timestamp_format(),
+            startup_log_format: default_startup_log_format(),
+            env_file: None,
+            response_timezone: None,
+            send_welcome_email: true,
+            welcome_email_subject: None,
+            welcome_email_html_path: None,
+            welcome_email_text_path: None,
+            argon2_memory_kib: default_argon2_memory_kib(),
+            argon2_iterations: default_argon2_iterations(),
+            argon2_parallelism: default_argon2_parallelism(),
+            password_rehash_on_login: true,
+            csrf_protection: true,
+        }
+    }
+}
+
+/// Parse an environment variable, ignoring it if missing or malformed
+fn env_parse<T: FromStr>(key: &str) -> Option<T> {
+    env::var(key).ok().and_then(|s| s.parse().ok())
+}
+
+/// Read an optional string; an empty value explicitly disables it
+fn env_optional(key: &str, default: Option<String>) -> Option<String> {
+    match env::var(key) {
+        Ok(value) if value.trim().is_empty() => None,
+        Ok(value) => Some(value),
+        Err(_) => default,
+    }
+}
+
+/// Read a comma-separated list, trimming entries and dropping empty ones
+fn env_list(key: &str, default: Vec<String>) -> Vec<String> {
+    match env::var(key) {
+        Ok(value) => value
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect(),
+        Err(_) => default,
+    }
+}
+
 impl Config {
     pub fn new() -> Result<Self, config::ConfigError> {
         config::Config::builder()
@@ -59,54 +750,301 @@ impl Config {
 
     pub fn from_env() -> Self {
         // Load .env file if it exists, ignore errors if it doesn't
-        let _ = dotenvy::dotenv();
+        let env_file = dotenvy::dotenv().ok();
 
-        let host = env::var("HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
-        let port = env::var("PORT")
-            .ok()
-            .and_then(|p| p.parse().ok())
-            .unwrap_or(3000);
+        let defaults = Self::default();
 
-        let database_url =
-            env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite:data.db?mode=rwc".to_string());
+        Self {
+            host: env::var("HOST").unwrap_or(defaults.host),
+            port: env_parse("PORT").unwrap_or(defaults.port),
+            database_url: env::var("DATABASE_URL").unwrap_or(defaults.database_url),
+            db_warm_up_timeout_secs: env_parse("DB_WARM_UP_TIMEOUT_SECS")
+                .unwrap_or(defaults.db_warm_up_timeout_secs),
+            run_migrations_on_start: env_parse("RUN_MIGRATIONS_ON_START")
+                .unwrap_or(defaults.run_migrations_on_start),
+            verify_schema_on_start: env_parse("VERIFY_SCHEMA_ON_START")
+                .unwrap_or(defaults.verify_schema_on_start),
+            migrations_dir: env_optional("MIGRATIONS_DIR", defaults.migrations_dir),
+            migration_lock_retries: env_parse("MIGRATION_LOCK_RETRIES")
+                .unwrap_or(defaults.migration_lock_retries),
+            migration_retry_delay_ms: env_parse("MIGRATION_RETRY_DELAY_MS")
+                .unwrap_or(defaults.migration_retry_delay_ms),
+            database_replica_url: env_optional(
+                "DATABASE_REPLICA_URL",
+                defaults.database_replica_url,
+            ),
+            user_cache_ttl_secs: env_parse("USER_CACHE_TTL_SECS")
+                .unwrap_or(defaults.user_cache_ttl_secs),
+            session_secret: env::var("SESSION_SECRET").unwrap_or(defaults.session_secret),
+            session_secret_previous: env_list(
+                "SESSION_SECRET_PREVIOUS",
+                defaults.session_secret_previous,
+            ),
+            session_accept_unsigned: env_parse("SESSION_ACCEPT_UNSIGNED")
+                .unwrap_or(defaults.session_accept_unsigned),
+            session_cookie_domain: env_optional(
+                "SESSION_COOKIE_DOMAIN",
+                defaults.session_cookie_domain,
+            ),
+            session_cookie_path: env::var("SESSION_COOKIE_PATH")
+                .unwrap_or(defaults.session_cookie_path),
+            session_transport: env::var("SESSION_TRANSPORT").unwrap_or(defaults.session_transport),
+            cors_allowed_origins: env_list("CORS_ALLOWED_ORIGINS", defaults.cors_allowed_origins),
+            redirect_allowlist: env_list("REDIRECT_ALLOWLIST", defaults.redirect_allowlist),
+            cors_public_origins: env_list("CORS_PUBLIC_ORIGINS", defaults.cors_public_origins),
+            cors_public_paths: env_list("CORS_PUBLIC_PATHS", defaults.cors_public_paths),
+            secure_cookie: env_parse("SECURE_COOKIE").unwrap_or(defaults.secure_cookie),
+            db_max_connections: env_parse("DB_MAX_CONNECTIONS")
+                .unwrap_or(defaults.db_max_connections),
+            db_min_connections: env_parse("DB_MIN_CONNECTIONS")
+                .unwrap_or(defaults.db_min_connections),
+            db_idle_timeout_secs: env_parse("DB_IDLE_TIMEOUT_SECS")
+                .unwrap_or(defaults.db_idle_timeout_secs),
+            db_max_lifetime_secs: env_parse("DB_MAX_LIFETIME_SECS")
+                .unwrap_or(defaults.db_max_lifetime_secs),
+            db_ssl_mode: env_optional("DB_SSL_MODE", defaults.db_ssl_mode),
+            db_ssl_root_cert: env_optional("DB_SSL_ROOT_CERT", defaults.db_ssl_root_cert),
+            max_concurrent_requests: env_parse("MAX_CONCURRENT_REQUESTS")
+                .or(defaults.max_concurrent_requests),
+            trust_forwarded_for: env_parse("TRUST_FORWARDED_FOR")
+                .unwrap_or(defaults.trust_forwarded_for),
+            abuse_window_secs: env_parse("ABUSE_WINDOW_SECS").unwrap_or(defaults.abuse_window_secs),
+            request_id_header: env::var("REQUEST_ID_HEADER").unwrap_or(defaults.request_id_header),
+            config_cache_secs: env_parse("CONFIG_CACHE_SECS").unwrap_or(defaults.config_cache_secs),
+            abuse_max_clients: env_parse("ABUSE_MAX_CLIENTS").unwrap_or(defaults.abuse_max_clients),
+            email_check_limit: env_parse("EMAIL_CHECK_LIMIT").unwrap_or(defaults.email_check_limit),
+            email_check_window_secs: env_parse("EMAIL_CHECK_WINDOW_SECS")
+                .unwrap_or(defaults.email_check_window_secs),
+            statement_timeout_ms: env_parse("STATEMENT_TIMEOUT_MS")
+                .or(defaults.statement_timeout_ms),
+            reset_token_ttl_minutes: env_parse("RESET_TOKEN_TTL_MINUTES")
+                .unwrap_or(defaults.reset_token_ttl_minutes),
+            password_reset_url: env::var("PASSWORD_RESET_URL")
+                .unwrap_or(defaults.password_reset_url),
+            enumeration_protection: env_parse("ENUMERATION_PROTECTION")
+                .unwrap_or(defaults.enumeration_protection),
+            verification_token_ttl_minutes: env_parse("VERIFICATION_TOKEN_TTL_MINUTES")
+                .unwrap_or(defaults.verification_token_ttl_minutes),
+            verification_resend_cooldown_secs: env_parse("VERIFICATION_RESEND_COOLDOWN_SECS")
+                .unwrap_or(defaults.verification_resend_cooldown_secs),
+            verification_url: env::var("VERIFICATION_URL").unwrap_or(defaults.verification_url),
+            session_limit: env_parse("SESSION_LIMIT").or(defaults.session_limit),
+            session_limit_mode: env::var("SESSION_LIMIT_MODE")
+                .unwrap_or(defaults.session_limit_mode),
+            max_api_keys_per_user: env_parse("MAX_API_KEYS_PER_USER")
+                .or(defaults.max_api_keys_per_user),
+            remember_me_days: env_parse("REMEMBER_ME_DAYS").unwrap_or(defaults.remember_me_days),
+            log_sql: env_parse("LOG_SQL").unwrap_or(defaults.log_sql),
+            sql_log_level: env::var("SQL_LOG_LEVEL").unwrap_or(defaults.sql_log_level),
+            slow_query_ms: env_parse("SLOW_QUERY_MS").unwrap_or(defaults.slow_query_ms),
+            require_verified_email: env_parse("REQUIRE_VERIFIED_EMAIL")
+                .unwrap_or(defaults.require_verified_email),
+            reuse_deleted_emails: env_parse("REUSE_DELETED_EMAILS")
+                .unwrap_or(defaults.reuse_deleted_emails),
+            email_strip_plus_tags: env_parse("EMAIL_STRIP_PLUS_TAGS")
+                .unwrap_or(defaults.email_strip_plus_tags),
+            email_dot_insensitive_domains: env_list(
+                "EMAIL_DOT_INSENSITIVE_DOMAINS",
+                defaults.email_dot_insensitive_domains,
+            ),
+            first_user_is_admin: env_parse("FIRST_USER_IS_ADMIN")
+                .unwrap_or(defaults.first_user_is_admin),
+            require_invite_code: env_parse("REQUIRE_INVITE_CODE")
+                .unwrap_or(defaults.require_invite_code),
+            auth_mode: env::var("AUTH_MODE").unwrap_or(defaults.auth_mode),
+            envelope_responses: env_parse("ENVELOPE_RESPONSES")
+                .unwrap_or(defaults.envelope_responses),
+            pretty_json: env_parse("PRETTY_JSON").or(defaults.pretty_json),
+            strict_json: env_parse("STRICT_JSON").unwrap_or(defaults.strict_json),
+            require_json_content_type: env_parse("REQUIRE_JSON_CONTENT_TYPE")
+                .unwrap_or(defaults.require_json_content_type),
+            captcha_provider: env_optional("CAPTCHA_PROVIDER", defaults.captcha_provider),
+            captcha_secret: env_optional("CAPTCHA_SECRET", defaults.captcha_secret),
+            captcha_strict: env_parse("CAPTCHA_STRICT").unwrap_or(defaults.captcha_strict),
+            oidc_subject_claim: env::var("OIDC_SUBJECT_CLAIM")
+                .unwrap_or(defaults.oidc_subject_claim),
+            oidc_email_claim: env::var("OIDC_EMAIL_CLAIM").unwrap_or(defaults.oidc_email_claim),
+            oidc_name_claim: env::var("OIDC_NAME_CLAIM").unwrap_or(defaults.oidc_name_claim),
+            oidc_session_claims: env_list("OIDC_SESSION_CLAIMS", defaults.oidc_session_claims),
+            oidc_authorization_url: env_optional(
+                "OIDC_AUTHORIZATION_URL",
+                defaults.oidc_authorization_url,
+            ),
+            oidc_token_url: env_optional("OIDC_TOKEN_URL", defaults.oidc_token_url),
+            oidc_userinfo_url: env_optional("OIDC_USERINFO_URL", defaults.oidc_userinfo_url),
+            oidc_client_id: env_optional("OIDC_CLIENT_ID", defaults.oidc_client_id),
+            oidc_client_secret: env_optional("OIDC_CLIENT_SECRET", defaults.oidc_client_secret),
+            oidc_redirect_url: env_optional("OIDC_REDIRECT_URL", defaults.oidc_redirect_url),
+            oidc_timeout_secs: env_parse("OIDC_TIMEOUT_SECS").unwrap_or(defaults.oidc_timeout_secs),
+            oidc_retries: env_parse("OIDC_RETRIES").unwrap_or(defaults.oidc_retries),
+            totp_encryption_key: env_optional("TOTP_ENCRYPTION_KEY", defaults.totp_encryption_key),
+            totp_issuer: env::var("TOTP_ISSUER").unwrap_or(defaults.totp_issuer),
+            header_nosniff: env_parse("HEADER_NOSNIFF").unwrap_or(defaults.header_nosniff),
+            header_frame_deny: env_parse("HEADER_FRAME_DENY").unwrap_or(defaults.header_frame_deny),
+            referrer_policy: env_optional("REFERRER_POLICY", defaults.referrer_policy),
+            content_security_policy: env_optional(
+                "CONTENT_SECURITY_POLICY",
+                defaults.content_security_policy,
+            ),
+            hsts_enabled: env_parse("HSTS_ENABLED").unwrap_or(defaults.hsts_enabled),
+            hsts_max_age: env_parse("HSTS_MAX_AGE").unwrap_or(defaults.hsts_max_age),
+            app_env: env_optional("APP_ENV", defaults.app_env),
+            allow_insecure_prod: env_parse("ALLOW_INSECURE_PROD")
+                .unwrap_or(defaults.allow_insecure_prod),
+            normalize_paths: env_parse("NORMALIZE_PATHS").unwrap_or(defaults.normalize_paths),
+            retention_days: env_parse("RETENTION_DAYS").or(defaults.retention_days),
+            retention_mode: env::var("RETENTION_MODE").unwrap_or(defaults.retention_mode),
+            retention_interval_secs: env_parse("RETENTION_INTERVAL_SECS")
+                .unwrap_or(defaults.retention_interval_secs),
+            retention_service_accounts: env_list(
+                "RETENTION_SERVICE_ACCOUNTS",
+                defaults.retention_service_accounts,
+            ),
+            token_prune_interval_secs: env_parse("TOKEN_PRUNE_INTERVAL_SECS")
+                .unwrap_or(defaults.token_prune_interval_secs),
+            outbox_poll_secs: env_parse("OUTBOX_POLL_SECS").unwrap_or(defaults.outbox_poll_secs),
+            timestamp_format: env::var("TIMESTAMP_FORMAT").unwrap_or(defaults.timestamp_format),
+            startup_log_format: env::var("STARTUP_LOG_FORMAT")
+                .unwrap_or(defaults.startup_log_format),
+            env_file: env_file.map(|path| path.display().to_string()),
+            response_timezone: env_optional("RESPONSE_TIMEZONE", defaults.response_timezone),
+            send_welcome_email: env_parse("SEND_WELCOME_EMAIL")
+                .unwrap_or(defaults.send_welcome_email),
+            welcome_email_subject: env_optional(
+                "WELCOME_EMAIL_SUBJECT",
+                defaults.welcome_email_subject,
+            ),
+            welcome_email_html_path: env_optional(
+                "WELCOME_EMAIL_HTML_PATH",
+                defaults.welcome_email_html_path,
+            ),
+            welcome_email_text_path: env_optional(
+                "WELCOME_EMAIL_TEXT_PATH",
+                defaults.welcome_email_text_path,
+            ),
+            argon2_memory_kib: env_parse("ARGON2_MEMORY_KIB").unwrap_or(defaults.argon2_memory_kib),
+            argon2_iterations: env_parse("ARGON2_ITERATIONS").unwrap_or(defaults.argon2_iterations),
+            argon2_parallelism: env_parse("ARGON2_PARALLELISM")
+                .unwrap_or(defaults.argon2_parallelism),
+            password_rehash_on_login: env_parse("PASSWORD_REHASH_ON_LOGIN")
+                .unwrap_or(defaults.password_rehash_on_login),
+            csrf_protection: env_parse("CSRF_PROTECTION").unwrap_or(defaults.csrf_protection),
+        }
+    }
 
-        let session_secret = env::var("SESSION_SECRET").unwrap_or_else(|_| {
-            "k-notes-super-secret-key-must-be-at-least-64-bytes-long!!!!".to_string()
-        });
+    fn app_env_is(&self, env: &str) -> bool {
+        self.app_env
+            .as_deref()
+            .is_some_and(|app_env| app_env.eq_ignore_ascii_case(env))
+    }
 
-        let cors_origins_str = env::var("CORS_ALLOWED_ORIGINS")
-            .unwrap_or_else(|_| "http://localhost:5173".to_string());
+    pub fn is_production(&self) -> bool {
+        self.app_env_is("production")
+    }
 
-        let cors_allowed_origins = cors_origins_str
-            .split(',')
-            .map(|s| s.trim().to_string())
-            .filter(|s| !s.is_empty())
-            .collect();
+    /// Whether JSON responses are indented, per [`pretty_json`](Self::pretty_json)
+    pub fn pretty_json_enabled(&self) -> bool {
+        !self.is_production() && self.pretty_json.unwrap_or(self.app_env_is("development"))
+    }
 
-        let secure_cookie = env::var("SECURE_COOKIE")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(false);
+    /// Refuse a production deployment whose session cookies could travel over plain HTTP.
+    ///
+    /// With `allow_insecure_prod` this only warns, e.g. behind a proxy that
+    /// terminates TLS and sends HSTS itself.
+    pub fn check_production_security(&self) -> Result<(), String> {
+        if !self.is_production() {
+            return Ok(());
+        }
 
-        let db_max_connections = env::var("DB_MAX_CONNECTIONS")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(5);
+        let mut problems = Vec::new();
+        if !self.secure_cookie {
+            problems.push("SECURE_COOKIE is off");
+        }
+        if !self.hsts_enabled {
+            problems.push("HSTS_ENABLED is off");
+        }
+        if problems.is_empty() {
+            return Ok(());
+        }
+
+        let problems = problems.join(" and ");
+        if self.allow_insecure_prod {
+            tracing::warn!(
+                "🚨 INSECURE PRODUCTION DEPLOYMENT: {}; starting anyway because ALLOW_INSECURE_PROD is set",
+                problems
+            );
+            return Ok(());
+        }
+        Err(format!(
+            "Refusing to start with APP_ENV=production: {}. Enable them, or set ALLOW_INSECURE_PROD=true to override",
+            problems
+        ))
+    }
+}
 
-        let db_min_connections = env::var("DB_MIN_CONNECTIONS")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(1);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        Self {
-            host,
-            port,
-            database_url,
-            session_secret,
-            cors_allowed_origins,
-            secure_cookie,
-            db_max_connections,
-            db_min_connections,
+    fn production() -> Config {
+        Config {
+            app_env: Some("production".to_string()),
+            secure_cookie: true,
+            hsts_enabled: true,
+            ..Config::default()
         }
     }
+
+    #[test]
+    fn test_production_requires_secure_cookie_and_hsts() {
+        assert!(production().check_production_security().is_ok());
+
+        let insecure = Config {
+            secure_cookie: false,
+            ..production()
+        };
+        let err = insecure.check_production_security().unwrap_err();
+        assert!(err.contains("SECURE_COOKIE"));
+
+        let no_hsts = Config {
+            hsts_enabled: false,
+            ..production()
+        };
+        let err = no_hsts.check_production_security().unwrap_err();
+        assert!(err.contains("HSTS_ENABLED"));
+    }
+
+    #[test]
+    fn test_allow_insecure_prod_overrides_refusal() {
+        let config = Config {
+            secure_cookie: false,
+            hsts_enabled: false,
+            allow_insecure_prod: true,
+            ..production()
+        };
+        assert!(config.check_production_security().is_ok());
+    }
+
+    #[test]
+    fn test_other_environments_are_not_checked() {
+        // The defaults are insecure, which is fine outside production
+        assert!(Config::default().check_production_security().is_ok());
+    }
+
+    #[test]
+    fn test_json_is_pretty_by_default_only_in_development() {
+        assert!(!Config::default().pretty_json_enabled());
+
+        let development = Config {
+            app_env: Some("Development".to_string()),
+            ..Config::default()
+        };
+        assert!(development.pretty_json_enabled());
+
+        let forced = Config {
+            pretty_json: Some(true),
+            ..production()
+        };
+        assert!(!forced.pretty_json_enabled());
+    }
 }
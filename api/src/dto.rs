@@ -3,40 +3,546 @@
 //! Data Transfer Objects for the API.
 
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use uuid::Uuid;
 use validator::Validate;
 
+use crate::middleware::abuse::ClientTraffic;
+
+use domain::{
+    AuditAction, AuditEntry, AuditLogFilter, AuthMode, DisplayName, Email, EmailMatchMode,
+    InviteCode, LoginCommand, NewUserCommand, Password, Role, SessionFilter, SessionInfo,
+    UpdateProfileCommand, User, ValidationError,
+};
+
+/// `validator` rule accepting exactly the addresses [`Email`] does
+fn valid_email(email: &str) -> Result<(), validator::ValidationError> {
+    Email::new(email)
+        .map(|_| ())
+        .map_err(|e| validator::ValidationError::new("email").with_message(e.to_string().into()))
+}
+
 /// Login request
 #[derive(Debug, Deserialize, Validate)]
 pub struct LoginRequest {
-    #[validate(email(message = "Invalid email format"))]
+    #[validate(custom(function = "valid_email"))]
     pub email: String,
 
-    #[validate(length(min = 6, message = "Password must be at least 6 characters"))]
+    /// Checked against the stored hash only; the length policy is for new passwords
     pub password: String,
+
+    /// Keep the session for `REMEMBER_ME_DAYS` instead of until inactivity
+    #[serde(default)]
+    pub remember_me: bool,
+}
+
+/// Where to send the user after logging in
+#[derive(Debug, Default, Deserialize)]
+pub struct RedirectQuery {
+    #[serde(default)]
+    pub next: Option<String>,
+}
+
+/// Where to send the user after an OIDC login, and the invite a new account
+/// needs when invites are required
+#[derive(Debug, Default, Deserialize)]
+pub struct OidcLoginQuery {
+    #[serde(default)]
+    pub next: Option<String>,
+    #[serde(default)]
+    pub invite_code: Option<String>,
+}
+
+/// What the OIDC provider sends the browser back with: a code, or an error
+#[derive(Debug, Deserialize)]
+pub struct OidcCallbackQuery {
+    pub state: String,
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
 }
 
 /// Register request
 #[derive(Debug, Deserialize, Validate)]
 pub struct RegisterRequest {
-    #[validate(email(message = "Invalid email format"))]
+    #[validate(custom(function = "valid_email"))]
     pub email: String,
 
     #[validate(length(min = 6, message = "Password must be at least 6 characters"))]
     pub password: String,
+
+    /// Token from the CAPTCHA widget, required when CAPTCHA is enabled
+    #[serde(default)]
+    pub captcha_token: Option<String>,
+
+    /// Required when registration is invite-only
+    #[serde(default)]
+    pub invite_code: Option<String>,
+}
+
+impl TryFrom<LoginRequest> for LoginCommand {
+    type Error = ValidationError;
+
+    fn try_from(request: LoginRequest) -> Result<Self, Self::Error> {
+        LoginCommand::new(Email::try_from(request.email)?, request.password)
+    }
 }
 
-/// User response DTO
+impl TryFrom<RegisterRequest> for NewUserCommand {
+    type Error = ValidationError;
+
+    fn try_from(request: RegisterRequest) -> Result<Self, Self::Error> {
+        Ok(Self {
+            email: Email::try_from(request.email)?,
+            password: Password::try_from(request.password)?,
+            invite_code: request
+                .invite_code
+                .map(|code| code.trim().to_string())
+                .filter(|code| !code.is_empty()),
+        })
+    }
+}
+
+/// User response DTO.
+///
+/// Serializes as `{"id": "<hyphenated uuid>", "email": ..., "created_at": <timestamp>}`;
+/// see [`timestamps`](crate::timestamps) for the timestamp formats.
 #[derive(Debug, Serialize)]
 pub struct UserResponse {
     pub id: Uuid,
     pub email: String,
+    #[serde(serialize_with = "crate::timestamps::serialize")]
     pub created_at: DateTime<Utc>,
 }
 
-/// System configuration response
+impl From<User> for UserResponse {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            email: user.email.into_inner(),
+            created_at: user.created_at,
+        }
+    }
+}
+
+/// Registration response: the new user plus any non-fatal warnings
+#[derive(Debug, Serialize)]
+pub struct RegisterResponse {
+    #[serde(flatten)]
+    pub user: UserResponse,
+    /// E.g. a disposable email domain; omitted when there are none
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// Current user response, flagging an active impersonation
+#[derive(Debug, Serialize)]
+pub struct MeResponse {
+    #[serde(flatten)]
+    pub user: UserResponse,
+    /// The admin acting as this user, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub impersonated_by: Option<Uuid>,
+    /// CSRF token to send back in `X-CSRF-Token`, when protection is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub csrf_token: Option<String>,
+    /// `null` until the user picks one
+    pub display_name: Option<DisplayName>,
+    /// Claims kept from the OIDC provider at login, e.g. `groups`
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    pub claims: serde_json::Map<String, serde_json::Value>,
+}
+
+/// JSON Merge Patch (RFC 7386) of the signed-in user's profile
+#[derive(Debug, Deserialize)]
+pub struct ProfilePatch {
+    /// Left out: kept; `null`: removed; a string: replaced
+    #[serde(default, deserialize_with = "present")]
+    pub display_name: Option<Option<DisplayName>>,
+}
+
+/// Read a field that is in the body, even as `null`, as `Some`, so it can be
+/// told apart from one left out, which `default` makes `None`
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+impl From<ProfilePatch> for UpdateProfileCommand {
+    fn from(patch: ProfilePatch) -> Self {
+        Self {
+            display_name: patch.display_name,
+        }
+    }
+}
+
+/// Login accepted, pending a TOTP code at `POST /auth/2fa`
+#[derive(Debug, Serialize)]
+pub struct TwoFactorRequiredResponse {
+    pub two_factor_required: bool,
+}
+
+/// Ask for a password reset link
+#[derive(Debug, Deserialize)]
+pub struct PasswordResetRequest {
+    pub email: String,
+}
+
+/// Token from a reset email and the new password
+#[derive(Debug, Deserialize)]
+pub struct ConfirmPasswordResetRequest {
+    pub token: String,
+    pub password: String,
+}
+
+/// The signed-in user's current password and the one replacing it
+#[derive(Debug, Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+/// Resend the verification email; `email` is only read when not logged in
+#[derive(Debug, Default, Deserialize)]
+pub struct ResendVerificationRequest {
+    #[serde(default)]
+    pub email: Option<String>,
+}
+
+/// Token from a verification email
+#[derive(Debug, Deserialize)]
+pub struct VerifyEmailRequest {
+    pub token: String,
+}
+
+/// A TOTP code from the user's authenticator app
+#[derive(Debug, Deserialize)]
+pub struct TotpCodeRequest {
+    pub code: String,
+}
+
+/// Started TOTP enrollment; the secret is only shown this once
+#[derive(Debug, Serialize)]
+pub struct TotpEnrollmentResponse {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+/// Create API key request
+#[derive(Debug, Default, Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Newly created API key; the only time the raw key is returned
+#[derive(Debug, Serialize)]
+pub struct CreateApiKeyResponse {
+    pub id: Uuid,
+    pub key: String,
+    #[serde(serialize_with = "crate::timestamps::serialize_option")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// API key response DTO (never includes the key itself)
+#[derive(Debug, Serialize)]
+pub struct ApiKeyResponse {
+    pub id: Uuid,
+    #[serde(serialize_with = "crate::timestamps::serialize")]
+    pub created_at: DateTime<Utc>,
+    #[serde(serialize_with = "crate::timestamps::serialize_option")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(serialize_with = "crate::timestamps::serialize_option")]
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// Admin user search (`?q=&match_mode=prefix|contains`)
+#[derive(Debug, Deserialize)]
+pub struct UserSearchQuery {
+    #[serde(default)]
+    pub q: String,
+    #[serde(default)]
+    pub match_mode: EmailMatchMode,
+}
+
+/// Audit log query filters (`?user_id=&action=&from=&to=`)
+#[derive(Debug, Deserialize)]
+pub struct AuditLogQuery {
+    pub user_id: Option<Uuid>,
+    pub action: Option<AuditAction>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl From<AuditLogQuery> for AuditLogFilter {
+    fn from(query: AuditLogQuery) -> Self {
+        Self {
+            user_id: query.user_id,
+            action: query.action,
+            from: query.from,
+            to: query.to,
+        }
+    }
+}
+
+/// Audit log entry response DTO.
+///
+/// Emails are masked; they are only there to help recognize the accounts, and
+/// `null` for accounts since erased.
 #[derive(Debug, Serialize)]
+pub struct AuditEntryResponse {
+    pub id: Uuid,
+    pub actor_id: Uuid,
+    #[serde(serialize_with = "serialize_masked_option")]
+    pub actor_email: Option<Email>,
+    pub action: AuditAction,
+    pub target_id: Option<Uuid>,
+    #[serde(serialize_with = "serialize_masked_option")]
+    pub target_email: Option<Email>,
+    #[serde(serialize_with = "crate::timestamps::serialize")]
+    pub created_at: DateTime<Utc>,
+}
+
+impl AuditEntryResponse {
+    /// `users` holds the entry's actor and target, where they still exist
+    pub fn new(entry: AuditEntry, users: &[User]) -> Self {
+        let email = |id: Uuid| users.iter().find(|u| u.id == id).map(|u| u.email.clone());
+        Self {
+            id: entry.id,
+            actor_id: entry.actor_id,
+            actor_email: email(entry.actor_id),
+            action: entry.action,
+            target_id: entry.target_id,
+            target_email: entry.target_id.and_then(email),
+            created_at: entry.created_at,
+        }
+    }
+}
+
+fn serialize_masked_option<S: serde::Serializer>(
+    email: &Option<Email>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match email {
+        Some(email) => Email::serialize_masked(email, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Admin session list filters (`?user_id=`)
+#[derive(Debug, Deserialize)]
+pub struct SessionQuery {
+    pub user_id: Option<Uuid>,
+}
+
+impl From<SessionQuery> for SessionFilter {
+    fn from(query: SessionQuery) -> Self {
+        Self {
+            user_id: query.user_id,
+        }
+    }
+}
+
+/// Active session response DTO; the session id itself is never exposed
+#[derive(Debug, Serialize)]
+pub struct SessionResponse {
+    pub user_id: Uuid,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    #[serde(serialize_with = "crate::timestamps::serialize")]
+    pub created_at: DateTime<Utc>,
+    #[serde(serialize_with = "crate::timestamps::serialize")]
+    pub expires_at: DateTime<Utc>,
+}
+
+impl From<SessionInfo> for SessionResponse {
+    fn from(session: SessionInfo) -> Self {
+        Self {
+            user_id: session.user_id,
+            ip: session.ip,
+            user_agent: session.user_agent,
+            created_at: session.created_at,
+            expires_at: session.expires_at,
+        }
+    }
+}
+
+/// Invite code response DTO
+#[derive(Debug, Serialize)]
+pub struct InviteResponse {
+    pub code: String,
+    pub created_by: Uuid,
+    #[serde(serialize_with = "crate::timestamps::serialize")]
+    pub created_at: DateTime<Utc>,
+    pub used_by: Option<Uuid>,
+    #[serde(serialize_with = "crate::timestamps::serialize_option")]
+    pub used_at: Option<DateTime<Utc>>,
+}
+
+impl From<InviteCode> for InviteResponse {
+    fn from(invite: InviteCode) -> Self {
+        Self {
+            code: invite.code,
+            created_by: invite.created_by,
+            created_at: invite.created_at,
+            used_by: invite.used_by,
+            used_at: invite.used_at,
+        }
+    }
+}
+
+/// Top talkers query (`?limit=`)
+#[derive(Debug, Deserialize)]
+pub struct AbuseQuery {
+    pub limit: Option<u32>,
+}
+
+/// One client's traffic in the abuse window
+#[derive(Debug, Serialize)]
+pub struct ClientTrafficResponse {
+    pub ip: String,
+    pub requests: u64,
+    pub bytes: u64,
+}
+
+impl From<ClientTraffic> for ClientTrafficResponse {
+    fn from(traffic: ClientTraffic) -> Self {
+        Self {
+            ip: traffic.ip.to_string(),
+            requests: traffic.requests,
+            bytes: traffic.bytes,
+        }
+    }
+}
+
+/// System configuration response
+#[derive(Debug, Clone, Serialize)]
 pub struct ConfigResponse {
     pub allow_registration: bool,
+    /// Lets the frontend hide password forms in `oidc` mode
+    pub auth_mode: AuthMode,
+}
+
+/// `GET /auth/email-available` query
+#[derive(Debug, Deserialize)]
+pub struct EmailAvailableQuery {
+    pub email: String,
+}
+
+/// Whether an email is free to register
+#[derive(Debug, Serialize)]
+pub struct EmailAvailableResponse {
+    pub available: bool,
+}
+
+/// What the current user may do; everything is off when signed out
+#[derive(Debug, Serialize)]
+pub struct PermissionsResponse {
+    /// `null` when signed out
+    pub role: Option<Role>,
+    /// May use the `/admin` routes
+    pub admin: bool,
+}
+
+impl PermissionsResponse {
+    pub fn for_user(user: Option<&User>) -> Self {
+        Self {
+            role: user.map(|user| user.role),
+            admin: user.is_some_and(|user| user.role.satisfies(Role::Admin)),
+        }
+    }
+}
+
+/// Everything a frontend needs on load, in one response
+#[derive(Debug, Serialize)]
+pub struct BootstrapResponse {
+    /// As returned by `/auth/me`; `null` when signed out
+    pub user: Option<MeResponse>,
+    pub permissions: PermissionsResponse,
+    /// As returned by `/config`
+    pub config: ConfigResponse,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn register(email: &str, password: &str) -> RegisterRequest {
+        RegisterRequest {
+            email: email.to_string(),
+            password: password.to_string(),
+            captcha_token: None,
+            invite_code: None,
+        }
+    }
+
+    #[test]
+    fn test_valid_register_request_converts() {
+        let command = NewUserCommand::try_from(register(" User@Example.com ", "secret1")).unwrap();
+        assert_eq!(command.email.as_ref(), "user@example.com");
+        assert_eq!(command.password.as_ref(), "secret1");
+    }
+
+    #[test]
+    fn test_invalid_email_is_rejected() {
+        let result = NewUserCommand::try_from(register("not-an-email", "secret1"));
+        assert!(matches!(result, Err(ValidationError::InvalidEmail(_))));
+    }
+
+    #[test]
+    fn test_short_password_is_rejected() {
+        let result = NewUserCommand::try_from(register("user@example.com", "abc"));
+        assert_eq!(
+            result.unwrap_err(),
+            ValidationError::PasswordTooShort { min: 6, actual: 3 }
+        );
+    }
+
+    fn login(email: &str, password: &str) -> LoginRequest {
+        LoginRequest {
+            email: email.to_string(),
+            password: password.to_string(),
+            remember_me: false,
+        }
+    }
+
+    #[test]
+    fn test_login_request_is_validated() {
+        assert!(matches!(
+            LoginCommand::try_from(login("user@example", "secret1")),
+            Err(ValidationError::InvalidEmail(_))
+        ));
+    }
+
+    #[test]
+    fn test_login_does_not_apply_the_password_policy() {
+        // Set before the policy was tightened, say
+        let command = LoginCommand::try_from(login("user@example.com", "abc")).unwrap();
+        assert_eq!(command.password, "abc");
+
+        assert_eq!(
+            LoginCommand::try_from(login("user@example.com", "")).unwrap_err(),
+            ValidationError::EmptyPassword
+        );
+        let huge = "x".repeat(domain::commands::MAX_LOGIN_PASSWORD_LENGTH + 1);
+        assert!(matches!(
+            LoginCommand::try_from(login("user@example.com", &huge)),
+            Err(ValidationError::PasswordTooLong { .. })
+        ));
+    }
+
+    #[test]
+    fn test_audit_entries_mask_emails() {
+        let admin = User::new("oidc|admin", Email::try_from("admin@example.com").unwrap()).unwrap();
+        let erased = Uuid::new_v4();
+        let entry = AuditEntry::new(admin.id, AuditAction::UserErased, Some(erased));
+
+        let json = serde_json::to_value(AuditEntryResponse::new(entry, &[admin])).unwrap();
+        assert_eq!(json["actor_email"], "a***@example.com");
+        assert_eq!(json["target_id"], erased.to_string());
+        assert!(json["target_email"].is_null());
+    }
 }
@@ -2,15 +2,19 @@
 //!
 //! Maps domain errors to HTTP responses
 
+use std::collections::BTreeMap;
+use std::fmt;
+
 use axum::{
-    Json,
-    http::StatusCode,
+    http::{HeaderValue, StatusCode, header},
     response::{IntoResponse, Response},
 };
 use serde::Serialize;
 use thiserror::Error;
 
-use domain::DomainError;
+use domain::{DomainError, ValidationError};
+
+use crate::json::ApiJson;
 
 /// API-level errors
 #[derive(Debug, Error)]
@@ -18,8 +22,9 @@ pub enum ApiError {
     #[error("{0}")]
     Domain(#[from] DomainError),
 
+    /// Invalid input, by field; the same for value objects and `validator` rules
     #[error("Validation error: {0}")]
-    Validation(String),
+    InvalidFields(FieldErrors),
 
     #[error("Internal server error")]
     Internal(String),
@@ -29,6 +34,68 @@ pub enum ApiError {
 
     #[error("Unauthorized: {0}")]
     Unauthorized(String),
+
+    #[error("Service overloaded")]
+    Overloaded { retry_after_secs: u64 },
+
+    #[error("Unsupported media type: {0}")]
+    UnsupportedMediaType(String),
+
+    #[error("Session store unavailable")]
+    SessionStoreUnavailable,
+
+    /// The identity provider timed out or failed during a login; unlike a
+    /// rejected login, trying again later may work
+    #[error("Identity provider unavailable: {0}")]
+    ProviderUnavailable(String),
+}
+
+/// Retry hint sent when the identity provider is unavailable
+const PROVIDER_RETRY_AFTER_SECS: u64 = 30;
+
+/// [`FieldErrors`] key for problems with the request as a whole rather than
+/// one field, such as wrong credentials
+pub const REQUEST_FIELD: &str = "_request";
+
+/// Messages about invalid input, keyed by field name
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct FieldErrors(BTreeMap<String, Vec<String>>);
+
+impl FieldErrors {
+    pub fn add(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.0.entry(field.into()).or_default().push(message.into());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Display for FieldErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let messages: Vec<&str> = self.0.values().flatten().map(String::as_str).collect();
+        f.write_str(&messages.join("; "))
+    }
+}
+
+impl From<ValidationError> for ApiError {
+    fn from(error: ValidationError) -> Self {
+        ApiError::invalid_field(error.field(), error)
+    }
+}
+
+impl From<validator::ValidationErrors> for ApiError {
+    fn from(errors: validator::ValidationErrors) -> Self {
+        let mut fields = FieldErrors::default();
+        for (field, errors) in errors.field_errors() {
+            for error in errors {
+                let message = error.message.as_ref().unwrap_or(&error.code);
+                fields.add(field.to_string(), message.to_string());
+            }
+        }
+        ApiError::InvalidFields(fields)
+    }
 }
 
 /// Error response body
@@ -36,61 +103,111 @@ pub enum ApiError {
 pub struct ErrorResponse {
     pub error: String,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conflict_field: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub details: Option<String>,
+    /// Messages per invalid field, for validation errors
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<FieldErrors>,
 }
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
+        // Same shape as every other validation error
+        if let ApiError::Domain(DomainError::ValidationError(msg)) = &self {
+            return ApiError::invalid_field(REQUEST_FIELD, msg).into_response();
+        }
+        // Answered like the API's own provider failures, without the provider's message
+        if let ApiError::Domain(DomainError::ProviderUnavailable(msg)) = self {
+            return ApiError::ProviderUnavailable(msg).into_response();
+        }
+
         let (status, error_response) = match &self {
             ApiError::Domain(domain_error) => {
+                let code = match domain_error {
+                    DomainError::EmailNotVerified(_) => Some("email_not_verified"),
+                    DomainError::SessionLimitReached(_) => Some("session_limit_reached"),
+                    DomainError::ApiKeyLimitReached(_) => Some("api_key_limit_reached"),
+                    DomainError::InvalidInviteCode(_) => Some("invalid_invite_code"),
+                    DomainError::RateLimited(_) => Some("rate_limited"),
+                    _ => None,
+                };
+
                 let status = match domain_error {
-                    DomainError::UserNotFound(_) => StatusCode::NOT_FOUND,
+                    DomainError::UserNotFound(_) | DomainError::ApiKeyNotFound(_) => {
+                        StatusCode::NOT_FOUND
+                    }
 
-                    DomainError::UserAlreadyExists(_) => StatusCode::CONFLICT,
+                    DomainError::UserAlreadyExists(_)
+                    | DomainError::EmailAlreadyExists(_)
+                    | DomainError::SubjectAlreadyExists(_) => StatusCode::CONFLICT,
 
                     DomainError::ValidationError(_) => StatusCode::BAD_REQUEST,
 
                     DomainError::Unauthorized(_) => StatusCode::FORBIDDEN,
 
+                    DomainError::EmailNotVerified(_) => StatusCode::FORBIDDEN,
+
+                    DomainError::SessionLimitReached(_) | DomainError::ApiKeyLimitReached(_) => {
+                        StatusCode::CONFLICT
+                    }
+
+                    DomainError::InvalidInviteCode(_) => StatusCode::FORBIDDEN,
+
+                    DomainError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+
                     DomainError::RepositoryError(_) | DomainError::InfrastructureError(_) => {
                         StatusCode::INTERNAL_SERVER_ERROR
                     }
+
+                    DomainError::ProviderUnavailable(_) => StatusCode::BAD_GATEWAY,
                 };
 
                 (
                     status,
                     ErrorResponse {
                         error: domain_error.to_string(),
+                        code,
+                        conflict_field: domain_error.conflict_field(),
                         details: None,
+                        fields: None,
                     },
                 )
             }
 
-            ApiError::Validation(msg) => (
+            ApiError::InvalidFields(fields) => (
                 StatusCode::BAD_REQUEST,
                 ErrorResponse {
                     error: "Validation error".to_string(),
-                    details: Some(msg.clone()),
+                    code: None,
+                    conflict_field: None,
+                    details: Some(fields.to_string()),
+                    fields: Some(fields.clone()),
                 },
             ),
 
-            ApiError::Internal(msg) => {
-                // Log internal errors but don't expose details
-                tracing::error!("Internal error: {}", msg);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    ErrorResponse {
-                        error: "Internal server error".to_string(),
-                        details: None,
-                    },
-                )
-            }
+            // Logged below, but details are not exposed
+            ApiError::Internal(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorResponse {
+                    error: "Internal server error".to_string(),
+                    code: None,
+                    conflict_field: None,
+                    details: None,
+                    fields: None,
+                },
+            ),
 
             ApiError::Forbidden(msg) => (
                 StatusCode::FORBIDDEN,
                 ErrorResponse {
                     error: "Forbidden".to_string(),
+                    code: None,
+                    conflict_field: None,
                     details: Some(msg.clone()),
+                    fields: None,
                 },
             ),
 
@@ -98,18 +215,131 @@ impl IntoResponse for ApiError {
                 StatusCode::UNAUTHORIZED,
                 ErrorResponse {
                     error: "Unauthorized".to_string(),
+                    code: None,
+                    conflict_field: None,
                     details: Some(msg.clone()),
+                    fields: None,
+                },
+            ),
+
+            ApiError::Overloaded { .. } => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorResponse {
+                    error: "Service overloaded, try again later".to_string(),
+                    code: Some("overloaded"),
+                    conflict_field: None,
+                    details: None,
+                    fields: None,
+                },
+            ),
+
+            ApiError::UnsupportedMediaType(msg) => (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                ErrorResponse {
+                    error: "Unsupported media type".to_string(),
+                    code: Some("unsupported_media_type"),
+                    conflict_field: None,
+                    details: Some(msg.clone()),
+                    fields: None,
+                },
+            ),
+
+            ApiError::SessionStoreUnavailable => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorResponse {
+                    error: "Sessions are temporarily unavailable, try again later".to_string(),
+                    code: Some("session_store_unavailable"),
+                    conflict_field: None,
+                    details: None,
+                    fields: None,
+                },
+            ),
+
+            // Logged below with the request id; the provider's error stays private
+            ApiError::ProviderUnavailable(_) => (
+                StatusCode::BAD_GATEWAY,
+                ErrorResponse {
+                    error: "The sign-in provider is not responding, try again shortly".to_string(),
+                    code: Some("provider_unavailable"),
+                    conflict_field: None,
+                    details: None,
+                    fields: None,
                 },
             ),
         };
 
-        (status, Json(error_response)).into_response()
+        if matches!(
+            status,
+            StatusCode::INTERNAL_SERVER_ERROR | StatusCode::BAD_GATEWAY
+        ) {
+            self.log(status);
+        }
+
+        let mut response = (status, ApiJson(error_response)).into_response();
+        let retry_after_secs = match self {
+            ApiError::Overloaded { retry_after_secs }
+            | ApiError::Domain(DomainError::RateLimited(retry_after_secs)) => {
+                Some(retry_after_secs)
+            }
+            ApiError::ProviderUnavailable(_) => Some(PROVIDER_RETRY_AFTER_SECS),
+            _ => None,
+        };
+        if let Some(retry_after_secs) = retry_after_secs {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after_secs));
+        }
+        response
     }
 }
 
 impl ApiError {
-    pub fn validation(msg: impl Into<String>) -> Self {
-        Self::Validation(msg.into())
+    /// Variant name, e.g. `Internal`, or the domain error's kind
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ApiError::Domain(domain_error) => domain_error.kind(),
+            ApiError::InvalidFields(_) => "Validation",
+            ApiError::Internal(_) => "Internal",
+            ApiError::Forbidden(_) => "Forbidden",
+            ApiError::Unauthorized(_) => "Unauthorized",
+            ApiError::Overloaded { .. } => "Overloaded",
+            ApiError::UnsupportedMediaType(_) => "UnsupportedMediaType",
+            ApiError::SessionStoreUnavailable => "SessionStoreUnavailable",
+            ApiError::ProviderUnavailable(_) => "ProviderUnavailable",
+        }
+    }
+
+    /// Message carried by the error, without the `Display` prefix
+    pub fn detail(&self) -> String {
+        match self {
+            ApiError::Domain(domain_error) => domain_error.detail(),
+            ApiError::Internal(msg)
+            | ApiError::Forbidden(msg)
+            | ApiError::Unauthorized(msg)
+            | ApiError::UnsupportedMediaType(msg)
+            | ApiError::ProviderUnavailable(msg) => msg.clone(),
+            ApiError::InvalidFields(fields) => fields.to_string(),
+            ApiError::Overloaded { .. } | ApiError::SessionStoreUnavailable => self.to_string(),
+        }
+    }
+
+    /// Log as structured fields, so aggregators can filter on `error.kind`.
+    ///
+    /// `error.code` is the HTTP status the error is answered with.
+    pub fn log(&self, status: StatusCode) {
+        tracing::error!(
+            error.kind = self.kind(),
+            error.code = status.as_u16(),
+            error.detail = %self.detail(),
+            "Request failed"
+        );
+    }
+
+    /// A single invalid field
+    pub fn invalid_field(field: impl Into<String>, msg: impl ToString) -> Self {
+        let mut fields = FieldErrors::default();
+        fields.add(field, msg.to_string());
+        Self::InvalidFields(fields)
     }
 
     pub fn internal(msg: impl Into<String>) -> Self {
@@ -119,3 +349,131 @@ impl ApiError {
 
 /// Result type alias for API handlers
 pub type ApiResult<T> = Result<T, ApiError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+
+    type Fields = Arc<Mutex<Vec<HashMap<String, String>>>>;
+
+    /// Records the fields of every event
+    struct Capture(Fields);
+
+    struct Visitor<'a>(&'a mut HashMap<String, String>);
+
+    impl Visit for Visitor<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+    }
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for Capture {
+        fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+            let mut fields = HashMap::new();
+            event.record(&mut Visitor(&mut fields));
+            self.0.lock().unwrap().push(fields);
+        }
+    }
+
+    fn logged_fields(error: ApiError) -> (StatusCode, Vec<HashMap<String, String>>) {
+        let fields = Fields::default();
+        let subscriber = tracing_subscriber::registry().with(Capture(fields.clone()));
+        let status =
+            tracing::subscriber::with_default(subscriber, || error.into_response().status());
+        let events = fields.lock().unwrap().clone();
+        (status, events)
+    }
+
+    #[test]
+    fn test_internal_error_logs_structured_fields() {
+        let (status, events) = logged_fields(ApiError::internal("pool timed out"));
+
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["error.kind"], "Internal");
+        assert_eq!(events[0]["error.code"], "500");
+        assert_eq!(events[0]["error.detail"], "pool timed out");
+    }
+
+    #[test]
+    fn test_domain_error_kind_is_logged() {
+        let error = ApiError::from(DomainError::RepositoryError("disk full".into()));
+        let (_, events) = logged_fields(error);
+        assert_eq!(events[0]["error.kind"], "RepositoryError");
+        assert_eq!(events[0]["error.detail"], "disk full");
+    }
+
+    #[test]
+    fn test_client_errors_are_not_logged() {
+        let (status, events) = logged_fields(ApiError::invalid_field("email", "bad input"));
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn test_provider_outage_is_a_logged_bad_gateway() {
+        let error = ApiError::ProviderUnavailable("token endpoint timed out after 5s".into());
+        let (status, events) = logged_fields(error);
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(events[0]["error.kind"], "ProviderUnavailable");
+        assert_eq!(
+            events[0]["error.detail"],
+            "token endpoint timed out after 5s"
+        );
+
+        let response = ApiError::ProviderUnavailable("connection refused".into()).into_response();
+        assert_eq!(response.headers()[header::RETRY_AFTER], "30");
+    }
+
+    async fn body(error: ApiError) -> (StatusCode, serde_json::Value) {
+        let response = error.into_response();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_value_object_and_validator_errors_share_a_shape() {
+        use validator::Validate;
+
+        let value_object = ApiError::from(domain::Password::new("abc").unwrap_err());
+        let request = crate::dto::RegisterRequest {
+            email: "user@example.com".to_string(),
+            password: "abc".to_string(),
+            captcha_token: None,
+            invite_code: None,
+        };
+        let validator = ApiError::from(request.validate().unwrap_err());
+
+        let (status, value_object) = body(value_object).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, validator) = body(validator).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        for body in [&value_object, &validator] {
+            let mut keys: Vec<&String> = body.as_object().unwrap().keys().collect();
+            keys.sort();
+            assert_eq!(keys, ["details", "error", "fields"]);
+            assert_eq!(body["error"], "Validation error");
+            let fields = body["fields"].as_object().unwrap();
+            assert_eq!(fields.keys().collect::<Vec<_>>(), ["password"]);
+            assert!(
+                fields["password"][0]
+                    .as_str()
+                    .unwrap()
+                    .starts_with("Password must")
+            );
+        }
+    }
+}
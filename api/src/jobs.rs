@@ -0,0 +1,72 @@
+//! Background jobs
+//!
+//! Periodic tasks spawned at startup.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use domain::{OutboxDispatcher, RetentionPolicy, UserService};
+use tokio::task::JoinHandle;
+
+/// Periodically soft-delete or erase accounts past the retention period
+pub fn spawn_retention_job(
+    user_service: Arc<UserService>,
+    policy: RetentionPolicy,
+    interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match user_service.apply_retention(&policy).await {
+                Ok(processed) if !processed.is_empty() => {
+                    tracing::info!(
+                        "Retention processed {} inactive account(s) ({:?})",
+                        processed.len(),
+                        policy.mode
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => tracing::error!("Retention job failed: {}", e),
+            }
+        }
+    })
+}
+
+/// Periodically delete expired password reset and verification tokens
+pub fn spawn_token_pruning(user_service: Arc<UserService>, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match user_service.prune_expired_tokens().await {
+                Ok(pruned) if pruned.total() > 0 => {
+                    tracing::info!(
+                        "Pruned {} expired password reset and {} verification token(s)",
+                        pruned.password_reset,
+                        pruned.email_verification
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => tracing::error!("Token pruning failed: {}", e),
+            }
+        }
+    })
+}
+
+/// Deliver pending outbox events, polling at a fixed interval
+pub fn spawn_outbox_dispatcher(dispatcher: OutboxDispatcher, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match dispatcher.dispatch_once().await {
+                Ok(delivered) if delivered > 0 => {
+                    tracing::debug!("Delivered {} outbox event(s)", delivered);
+                }
+                Ok(_) => {}
+                Err(e) => tracing::error!("Outbox dispatch failed: {}", e),
+            }
+        }
+    })
+}
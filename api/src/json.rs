@@ -0,0 +1,304 @@
+//! JSON request and response bodies
+//!
+//! [`ApiJson`] behaves like axum's `Json`, but in strict mode rejects bodies
+//! carrying fields the target type does not know, so a typo like `emial` is
+//! reported instead of silently dropped. A missing or wrong `Content-Type` is
+//! answered with a structured 415, or ignored when [`JsonContentType::Any`].
+//! Bodies that are not valid JSON or do not fit the target type get a 400
+//! naming the offending field, in either mode.
+//!
+//! As a response it is compact, or indented inside a pretty [`scope`], which
+//! the [`pretty_json`](crate::middleware::pretty_json) middleware opens in
+//! development for reading responses with curl.
+
+use std::future::Future;
+
+use axum::body::Bytes;
+use axum::extract::{FromRef, FromRequest, Request};
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::error::{ApiError, FieldErrors, REQUEST_FIELD};
+
+tokio::task_local! {
+    static PRETTY: bool;
+}
+
+/// Run `f` with JSON responses indented when `pretty`
+pub async fn scope<F: Future>(pretty: bool, f: F) -> F::Output {
+    PRETTY.scope(pretty, f).await
+}
+
+/// Serialize a response body, indented inside a pretty [`scope`]
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> serde_json::Result<Vec<u8>> {
+    if PRETTY.try_with(|pretty| *pretty).unwrap_or(false) {
+        serde_json::to_vec_pretty(value)
+    } else {
+        serde_json::to_vec(value)
+    }
+}
+
+/// How unknown fields in request bodies are treated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JsonStrictness {
+    /// Ignore them
+    #[default]
+    Lenient,
+    /// Reject the request with 400
+    Strict,
+}
+
+/// Whether JSON bodies must be labelled as such
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JsonContentType {
+    /// `application/json` or a `+json` type, with any parameters
+    #[default]
+    Required,
+    /// Parse the body as JSON whatever its `Content-Type`
+    Any,
+}
+
+/// Whether `Content-Type` names JSON; parameters such as `charset` are ignored
+fn is_json(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    essence == "application/json"
+        || (essence.starts_with("application/") && essence.ends_with("+json"))
+}
+
+/// Field a deserialization error is about, e.g. `password` when it is missing
+fn error_field(error: &serde_path_to_error::Error<serde_json::Error>) -> String {
+    let path = error.path().to_string();
+    let message = error.inner().to_string();
+    let missing = message
+        .strip_prefix("missing field `")
+        .and_then(|rest| rest.split_once('`'))
+        .map(|(name, _)| name);
+    match (path.as_str(), missing) {
+        (".", Some(name)) => name.to_string(),
+        (".", None) => REQUEST_FIELD.to_string(),
+        (path, Some(name)) => format!("{}.{}", path, name),
+        (path, None) => path.to_string(),
+    }
+}
+
+/// JSON body extractor honoring [`JsonStrictness`] and [`JsonContentType`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ApiJson<T>(pub T);
+
+impl<T> ApiJson<T>
+where
+    T: DeserializeOwned,
+{
+    /// Deserialize, listing every unknown field in strict mode
+    fn from_value(value: serde_json::Value, strictness: JsonStrictness) -> Result<T, ApiError> {
+        let mut unknown = Vec::new();
+        let parsed = serde_path_to_error::deserialize(serde_ignored::Deserializer::new(
+            value,
+            &mut |path: serde_ignored::Path| unknown.push(path.to_string()),
+        ))
+        .map_err(|e| ApiError::invalid_field(error_field(&e), e.inner()))?;
+
+        if strictness == JsonStrictness::Strict && !unknown.is_empty() {
+            let mut fields = FieldErrors::default();
+            for path in unknown {
+                fields.add(path, "Unexpected field");
+            }
+            return Err(ApiError::InvalidFields(fields));
+        }
+        Ok(parsed)
+    }
+}
+
+impl<T, S> FromRequest<S> for ApiJson<T>
+where
+    T: DeserializeOwned,
+    JsonStrictness: FromRef<S>,
+    JsonContentType: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if JsonContentType::from_ref(state) == JsonContentType::Required && !is_json(req.headers())
+        {
+            return Err(ApiError::UnsupportedMediaType(
+                "Expected `Content-Type: application/json`".to_string(),
+            )
+            .into_response());
+        }
+        let bytes = Bytes::from_request(req, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        serde_json::from_slice(&bytes)
+            .map_err(|e| ApiError::invalid_field(REQUEST_FIELD, e))
+            .and_then(|value| Self::from_value(value, JsonStrictness::from_ref(state)))
+            .map(Self)
+            .map_err(IntoResponse::into_response)
+    }
+}
+
+impl<T: Serialize> IntoResponse for ApiJson<T> {
+    fn into_response(self) -> Response {
+        match to_vec(&self.0) {
+            Ok(body) => (
+                [(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/json"),
+                )],
+                body,
+            )
+                .into_response(),
+            // Not an ApiError, whose own body is ApiJson
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dto::LoginRequest;
+    use axum::{Router, body::Body, http::StatusCode, routing::post};
+    use tower::ServiceExt;
+
+    #[derive(Clone, Copy)]
+    struct Settings(JsonStrictness, JsonContentType);
+
+    impl FromRef<Settings> for JsonStrictness {
+        fn from_ref(settings: &Settings) -> Self {
+            settings.0
+        }
+    }
+
+    impl FromRef<Settings> for JsonContentType {
+        fn from_ref(settings: &Settings) -> Self {
+            settings.1
+        }
+    }
+
+    async fn login(ApiJson(payload): ApiJson<LoginRequest>) -> String {
+        payload.email
+    }
+
+    async fn send(strictness: JsonStrictness, body: &str) -> (StatusCode, serde_json::Value) {
+        send_as(
+            Settings(strictness, JsonContentType::Required),
+            Some("application/json"),
+            body,
+        )
+        .await
+    }
+
+    async fn send_as(
+        settings: Settings,
+        content_type: Option<&str>,
+        body: &str,
+    ) -> (StatusCode, serde_json::Value) {
+        let app = Router::new()
+            .route("/login", post(login))
+            .with_state(settings);
+        let mut request = Request::post("/login");
+        if let Some(content_type) = content_type {
+            request = request.header("content-type", content_type);
+        }
+        let request = request.body(Body::from(body.to_string())).unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = serde_json::from_slice(&bytes)
+            .unwrap_or_else(|_| serde_json::Value::String(String::from_utf8_lossy(&bytes).into()));
+        (status, body)
+    }
+
+    const TYPO: &str =
+        r#"{"email": "a@example.com", "emial": "b@example.com", "password": "secret"}"#;
+
+    #[tokio::test]
+    async fn test_unknown_fields_are_ignored_by_default() {
+        let (status, body) = send(JsonStrictness::Lenient, TYPO).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "a@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_strict_mode_lists_unexpected_fields() {
+        let (status, body) = send(JsonStrictness::Strict, TYPO).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["fields"]["emial"][0], "Unexpected field");
+
+        let valid = r#"{"email": "a@example.com", "password": "secret"}"#;
+        let (status, _) = send(JsonStrictness::Strict, valid).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_bad_bodies_are_the_same_error_in_either_mode() {
+        for strictness in [JsonStrictness::Lenient, JsonStrictness::Strict] {
+            let (status, body) = send(strictness, r#"{"email": "a@example.com"}"#).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{:?}", strictness);
+            assert_eq!(body["fields"]["password"][0], "missing field `password`");
+
+            let wrong_type = r#"{"email": "a@example.com", "password": 5}"#;
+            let (status, body) = send(strictness, wrong_type).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{:?}", strictness);
+            assert!(body["fields"]["password"][0].is_string());
+
+            let (status, body) = send(strictness, "{oops").await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{:?}", strictness);
+            assert!(body["fields"][REQUEST_FIELD][0].is_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wrong_content_type_is_unsupported_media_type() {
+        let valid = r#"{"email": "a@example.com", "password": "secret"}"#;
+        let required = Settings(JsonStrictness::Lenient, JsonContentType::Required);
+
+        for content_type in [
+            Some("text/plain"),
+            Some("application/x-www-form-urlencoded"),
+            None,
+        ] {
+            let (status, body) = send_as(required, content_type, valid).await;
+            assert_eq!(
+                status,
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "{:?}",
+                content_type
+            );
+            assert_eq!(body["code"], "unsupported_media_type");
+        }
+
+        for content_type in [
+            "application/json",
+            "application/json; charset=utf-8",
+            "Application/JSON;charset=UTF-8",
+            "application/merge-patch+json",
+        ] {
+            let (status, body) = send_as(required, Some(content_type), valid).await;
+            assert_eq!(status, StatusCode::OK, "{}", content_type);
+            assert_eq!(body, "a@example.com");
+        }
+
+        let any = Settings(JsonStrictness::Lenient, JsonContentType::Any);
+        let (status, _) = send_as(any, Some("text/plain"), valid).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
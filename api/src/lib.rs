@@ -0,0 +1,212 @@
+//! HTTP API over the domain and infra crates
+//!
+//! `main` assembles the server from these modules. With the `test-utils`
+//! feature, `test_support` is exported too, so integration tests can build
+//! the same app without a listening server.
+
+use std::sync::Arc;
+
+use axum::Router;
+use domain::{
+    CaptchaGuard, EmailCanonicalization, OidcProvider, UserService, WelcomeEmailTemplate,
+};
+
+pub mod auth;
+pub mod cache;
+pub mod cli;
+pub mod client_ip;
+pub mod config;
+pub mod dto;
+pub mod error;
+pub mod jobs;
+pub mod json;
+pub mod middleware;
+pub mod pagination;
+pub mod rate_limit;
+pub mod redirect;
+pub mod routes;
+pub mod session;
+pub mod startup;
+pub mod state;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_support;
+pub mod timestamps;
+
+use crate::auth::PasswordHashPolicy;
+use crate::config::Config;
+use crate::middleware::session_keys::SessionKeys;
+use crate::middleware::session_transport::SessionTransport;
+use crate::state::AppState;
+
+/// Routes behind the auth and session layers, shared with `test_support`
+pub fn app_router(
+    state: AppState,
+    auth_layer: auth::AuthManagerLayer,
+    config: &Config,
+    session_transport: SessionTransport,
+) -> Router {
+    routes::fallback::with_fallbacks(
+        Router::new()
+            .nest(
+                routes::API_V1_PREFIX,
+                routes::api_v1_router(state.auth_mode),
+            )
+            .nest(routes::API_V2_PREFIX, routes::api_v2_router())
+            .merge(routes::health::router()),
+    )
+    .layer(axum::middleware::from_fn(session::attach_claims))
+    .layer(auth_layer)
+    .layer(axum::middleware::from_fn(
+        middleware::session_outage::unavailable_instead_of_unauthorized,
+    ))
+    .layer(axum::middleware::from_fn_with_state(
+        Arc::new(
+            SessionKeys::new(&config.session_secret, &config.session_secret_previous)
+                .accepting_unsigned(config.session_accept_unsigned),
+        ),
+        middleware::session_keys::sign_session_cookie,
+    ))
+    // Outside the signing layer, so the token is the signed cookie value
+    .layer(axum::middleware::from_fn_with_state(
+        session_transport,
+        middleware::session_transport::carry_session_id,
+    ))
+    .with_state(state)
+}
+
+/// Argon2 parameters from config, shared by the server and `hash-password`
+pub fn password_policy(config: &Config) -> PasswordHashPolicy {
+    PasswordHashPolicy {
+        memory_kib: config.argon2_memory_kib,
+        iterations: config.argon2_iterations,
+        parallelism: config.argon2_parallelism,
+        rehash_on_login: config.password_rehash_on_login,
+    }
+}
+
+/// Email uniqueness rules from config, shared with `test_support`
+pub fn email_canonicalization(config: &Config) -> EmailCanonicalization {
+    EmailCanonicalization::new(
+        config.email_strip_plus_tags,
+        config
+            .email_dot_insensitive_domains
+            .iter()
+            .map(String::as_str),
+    )
+}
+
+/// Embedded welcome email, with any parts overridden by config
+pub fn welcome_email_template(config: &Config) -> anyhow::Result<WelcomeEmailTemplate> {
+    let mut template = WelcomeEmailTemplate::default();
+    if let Some(subject) = &config.welcome_email_subject {
+        template.subject = subject.clone();
+    }
+    if let Some(path) = &config.welcome_email_html_path {
+        template.html = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path, e))?;
+    }
+    if let Some(path) = &config.welcome_email_text_path {
+        template.text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path, e))?;
+    }
+    Ok(template)
+}
+
+#[cfg(feature = "captcha")]
+pub fn build_captcha_guard(config: &Config) -> anyhow::Result<Option<CaptchaGuard>> {
+    use infra::captcha::{CaptchaProvider, HttpCaptchaVerifier};
+    use std::time::Duration as StdDuration;
+    use tracing::info;
+
+    let Some(provider) = &config.captcha_provider else {
+        return Ok(None);
+    };
+
+    let provider: CaptchaProvider = provider.parse().map_err(anyhow::Error::msg)?;
+    let secret = config.captcha_secret.clone().ok_or_else(|| {
+        anyhow::anyhow!("CAPTCHA_SECRET is required when CAPTCHA_PROVIDER is set")
+    })?;
+
+    let verifier = HttpCaptchaVerifier::new(provider, secret, StdDuration::from_secs(5))?;
+    info!("🤖 CAPTCHA enabled on registration ({:?})", provider);
+
+    Ok(Some(CaptchaGuard::new(
+        Arc::new(verifier),
+        config.captcha_strict,
+    )))
+}
+
+#[cfg(not(feature = "captcha"))]
+pub fn build_captcha_guard(config: &Config) -> anyhow::Result<Option<CaptchaGuard>> {
+    if config.captcha_provider.is_some() {
+        tracing::warn!("CAPTCHA_PROVIDER is set but the `captcha` feature is disabled");
+    }
+    Ok(None)
+}
+
+#[cfg(feature = "oidc")]
+pub fn build_oidc_provider(config: &Config) -> anyhow::Result<Option<Arc<dyn OidcProvider>>> {
+    use infra::oidc::{HttpOidcProvider, OidcSettings};
+    use std::time::Duration as StdDuration;
+    use tracing::info;
+
+    let Some(client_id) = &config.oidc_client_id else {
+        return Ok(None);
+    };
+    let required = |value: &Option<String>, key: &str| {
+        value
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("{} is required when OIDC_CLIENT_ID is set", key))
+    };
+
+    let settings = OidcSettings {
+        authorization_url: required(&config.oidc_authorization_url, "OIDC_AUTHORIZATION_URL")?,
+        token_url: required(&config.oidc_token_url, "OIDC_TOKEN_URL")?,
+        userinfo_url: required(&config.oidc_userinfo_url, "OIDC_USERINFO_URL")?,
+        client_id: client_id.clone(),
+        client_secret: required(&config.oidc_client_secret, "OIDC_CLIENT_SECRET")?,
+        redirect_url: required(&config.oidc_redirect_url, "OIDC_REDIRECT_URL")?,
+    };
+    let provider = HttpOidcProvider::new(
+        settings,
+        StdDuration::from_secs(config.oidc_timeout_secs),
+        config.oidc_retries,
+    )?;
+    info!("🪪 OIDC login enabled for client {}", client_id);
+
+    Ok(Some(Arc::new(provider)))
+}
+
+#[cfg(not(feature = "oidc"))]
+pub fn build_oidc_provider(config: &Config) -> anyhow::Result<Option<Arc<dyn OidcProvider>>> {
+    if config.oidc_client_id.is_some() {
+        tracing::warn!("OIDC_CLIENT_ID is set but the `oidc` feature is disabled");
+    }
+    Ok(None)
+}
+
+#[cfg(feature = "totp")]
+pub fn configure_totp(user_service: UserService, config: &Config) -> anyhow::Result<UserService> {
+    use infra::totp::{AesGcmCipher, TotpRsProvider};
+    use tracing::info;
+
+    let Some(key) = &config.totp_encryption_key else {
+        return Ok(user_service);
+    };
+
+    let cipher = AesGcmCipher::from_hex(key)?;
+    info!("🔐 TOTP two-factor authentication enabled");
+
+    Ok(user_service.with_totp(
+        Arc::new(TotpRsProvider::new(config.totp_issuer.clone())),
+        Arc::new(cipher),
+    ))
+}
+
+#[cfg(not(feature = "totp"))]
+pub fn configure_totp(user_service: UserService, config: &Config) -> anyhow::Result<UserService> {
+    if config.totp_encryption_key.is_some() {
+        tracing::warn!("TOTP_ENCRYPTION_KEY is set but the `totp` feature is disabled");
+    }
+    Ok(user_service)
+}
@@ -1,90 +1,410 @@
 use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Duration as StdDuration;
 
-use axum::Router;
-use domain::UserService;
+use axum::ServiceExt;
+use axum::extract::{FromRef, Request};
+use axum::http::HeaderName;
+use clap::Parser;
+use domain::{
+    AuthMode, ClaimMapping, DisposableEmailWarning, Email, EventPublisher, LoginPolicy,
+    OutboxDispatcher, RetentionMode, RetentionPolicy, SessionLimit, SessionLimitMode, UserService,
+    WeakPasswordWarning, WelcomeEmailPublisher,
+};
+use infra::db::{ConnectionSettings, LevelFilter, SqlLogging, create_pool};
+use infra::factory::build_api_key_repository;
+use infra::factory::build_audit_log_repository;
+use infra::factory::build_outbox_repository;
+use infra::factory::build_session_repository;
 use infra::factory::build_session_store;
-use infra::factory::build_user_repository;
-use infra::run_migrations;
-use infra::session_store::{Expiry, SessionManagerLayer};
+use infra::factory::{
+    build_email_verification_repository, build_invite_repository, build_password_reset_repository,
+};
+use infra::factory::{build_routed_user_repository, build_user_repository};
+use infra::session_store::DegradingSessionStore;
+use infra::{CachingUserRepository, LoggingEmailSender, LoggingEventPublisher};
+use infra::{MigrationRetry, run_migrations_with_retry, verify_migrations, verify_schema};
 use k_core::http::server::ServerConfig;
 use k_core::http::server::apply_standard_middleware;
 use k_core::logging;
-use time::Duration;
 use tokio::net::TcpListener;
 use tracing::info;
 
-mod auth;
-mod config;
-mod dto;
-mod error;
-mod routes;
-mod state;
-
-use crate::auth::setup_auth_layer;
-use crate::config::Config;
-use crate::state::AppState;
+use api::auth::setup_auth_layer;
+use api::cli::{self, Cli, Command};
+use api::client_ip::ClientIpSource;
+use api::config::Config;
+use api::middleware::abuse::AbuseTracking;
+use api::middleware::cors::PublicCors;
+use api::middleware::csrf::CsrfSettings;
+use api::middleware::load_shed::LoadShed;
+use api::middleware::request_id::RequestIdHeader;
+use api::middleware::security_headers::SecurityHeaders;
+use api::middleware::session_transport::SessionTransport;
+use api::startup::{MigrationStatus, StartupLogFormat, StartupSummary};
+use api::state::AppState;
+use api::timestamps::{self, TimestampStyle};
+use api::{
+    app_router, build_captcha_guard, build_oidc_provider, configure_totp, email_canonicalization,
+    jobs, middleware, password_policy, session, welcome_email_template,
+};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let config = Config::from_env();
+
+    // Before logging starts, so stdout carries nothing but the hash
+    if let Some(Command::HashPassword) = cli.command {
+        let hash = cli::hash_password(std::io::stdin().lock(), &password_policy(&config))?;
+        println!("{}", hash);
+        return Ok(());
+    }
+
     logging::init("api");
 
-    let config = Config::from_env();
+    config
+        .check_production_security()
+        .map_err(anyhow::Error::msg)?;
 
     info!("Starting server on {}:{}", config.host, config.port);
 
     // Setup database
     tracing::info!("Connecting to database: {}", config.database_url);
-    let db_config = k_core::db::DatabaseConfig {
+    let db_config = infra::db::DatabaseConfig {
         url: config.database_url.clone(),
         max_connections: config.db_max_connections,
         min_connections: config.db_min_connections,
         acquire_timeout: StdDuration::from_secs(30),
     };
 
-    let db_pool = k_core::db::connect(&db_config).await?;
+    let sql_logging = if config.log_sql {
+        let level: LevelFilter = config
+            .sql_log_level
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid SQL_LOG_LEVEL: {}", config.sql_log_level))?;
+        Some(SqlLogging {
+            level,
+            slow_threshold: StdDuration::from_millis(config.slow_query_ms),
+        })
+    } else {
+        None
+    };
+    let connection_settings = ConnectionSettings {
+        statement_timeout: config.statement_timeout_ms.map(StdDuration::from_millis),
+        sql_logging,
+        idle_timeout: Some(StdDuration::from_secs(config.db_idle_timeout_secs)),
+        max_lifetime: Some(StdDuration::from_secs(config.db_max_lifetime_secs)),
+        ssl_mode: config.db_ssl_mode.clone(),
+        ssl_root_cert: config.db_ssl_root_cert.as_ref().map(PathBuf::from),
+    };
+
+    let db_pool = create_pool(db_config, &connection_settings).await?;
+
+    let migrations_dir = config.migrations_dir.as_deref().map(Path::new);
+    let migration_status = if config.run_migrations_on_start {
+        let retry = MigrationRetry {
+            attempts: config.migration_lock_retries,
+            delay: StdDuration::from_millis(config.migration_retry_delay_ms),
+        };
+        run_migrations_with_retry(&db_pool, migrations_dir, retry).await?;
+        MigrationStatus::Applied
+    } else {
+        // Applied by a separate job; never serve an outdated schema
+        verify_migrations(&db_pool, migrations_dir).await?;
+        info!("✅ Database schema is up to date");
+        MigrationStatus::Verified
+    };
+    if config.verify_schema_on_start {
+        verify_schema(
+            &db_pool,
+            StdDuration::from_secs(config.db_warm_up_timeout_secs),
+        )
+        .await?;
+    }
+
+    // Never migrated: the replica follows the primary's schema
+    let replica_pool = match &config.database_replica_url {
+        Some(url) => {
+            info!("📖 Routing user reads to the read replica");
+            let replica_config = infra::db::DatabaseConfig {
+                url: url.clone(),
+                max_connections: config.db_max_connections,
+                min_connections: config.db_min_connections,
+                acquire_timeout: StdDuration::from_secs(30),
+            };
+            Some(create_pool(replica_config, &connection_settings).await?)
+        }
+        None => None,
+    };
+
+    // Auth reads stay on the primary: a login must see a just-changed
+    // password or role, however far the replica lags
+    let mut user_repo = build_user_repository(&db_pool).await?;
+    // Only the auth backend reads through the cache; the service's writes evict from it
+    let mut auth_user_repo = user_repo.clone();
+    if config.user_cache_ttl_secs > 0 {
+        let ttl = StdDuration::from_secs(config.user_cache_ttl_secs);
+        let cache = CachingUserRepository::new(user_repo, ttl);
+        user_repo = Arc::new(cache.evicting());
+        auth_user_repo = Arc::new(cache);
+    }
+    let user_repo = build_routed_user_repository(user_repo, replica_pool.as_ref()).await?;
+    let api_key_repo = build_api_key_repository(&db_pool).await?;
+    let audit_log_repo = build_audit_log_repository(&db_pool).await?;
+    let password_policy = password_policy(&config);
+    let password_reset_repo = build_password_reset_repository(&db_pool).await?;
+    let email_verification_repo = build_email_verification_repository(&db_pool).await?;
+    let session_repo = build_session_repository(&db_pool).await?;
+    let invite_repo = build_invite_repository(&db_pool).await?;
+    let user_service = UserService::new(user_repo, api_key_repo, audit_log_repo)
+        .with_password_hasher(Arc::new(password_policy))
+        .with_password_reset(
+            password_reset_repo,
+            Arc::new(LoggingEmailSender),
+            chrono::Duration::minutes(config.reset_token_ttl_minutes as i64),
+            config.password_reset_url.clone(),
+        )
+        .with_email_verification(
+            email_verification_repo,
+            Arc::new(LoggingEmailSender),
+            chrono::Duration::minutes(config.verification_token_ttl_minutes as i64),
+            chrono::Duration::seconds(config.verification_resend_cooldown_secs as i64),
+            config.verification_url.clone(),
+        )
+        .with_session_repository(session_repo)
+        .with_invites(invite_repo, config.require_invite_code)
+        .with_deleted_email_reuse(config.reuse_deleted_emails)
+        .with_email_canonicalization(email_canonicalization(&config))
+        .with_first_user_admin(config.first_user_is_admin)
+        .with_registration_warning(Arc::new(DisposableEmailWarning::default()))
+        .with_registration_warning(Arc::new(WeakPasswordWarning))
+        .with_claim_mapping(ClaimMapping {
+            subject_claim: config.oidc_subject_claim.clone(),
+            email_claim: config.oidc_email_claim.clone(),
+            name_claim: config.oidc_name_claim.clone(),
+            session_claims: config.oidc_session_claims.clone(),
+        });
+    let user_service = configure_totp(user_service, &config)?;
+    let user_service = match config.session_limit {
+        Some(0) => anyhow::bail!("SESSION_LIMIT must be at least 1"),
+        Some(max) => {
+            let mode: SessionLimitMode = config
+                .session_limit_mode
+                .parse()
+                .map_err(anyhow::Error::msg)?;
+            info!("🔒 Session limit: {} per user ({:?})", max, mode);
+            user_service.with_session_limit(SessionLimit { max, mode })
+        }
+        None => user_service,
+    };
+    let user_service = match config.max_api_keys_per_user {
+        Some(max) => {
+            info!("🔑 API key limit: {} per user", max);
+            user_service.with_max_api_keys(max)
+        }
+        None => user_service,
+    };
 
-    run_migrations(&db_pool).await?;
+    let outbox_repo = build_outbox_repository(&db_pool).await?;
+    let mut publisher: Arc<dyn EventPublisher> = Arc::new(LoggingEventPublisher);
+    if config.send_welcome_email {
+        publisher = Arc::new(WelcomeEmailPublisher::new(
+            publisher,
+            Arc::new(LoggingEmailSender),
+            welcome_email_template(&config)?,
+        ));
+    }
+    jobs::spawn_outbox_dispatcher(
+        OutboxDispatcher::new(outbox_repo, publisher.clone(), 100),
+        StdDuration::from_secs(config.outbox_poll_secs),
+    );
 
-    let user_repo = build_user_repository(&db_pool).await?;
-    let user_service = UserService::new(user_repo.clone());
+    let auth_mode: AuthMode = config.auth_mode.parse().map_err(anyhow::Error::msg)?;
+    let startup_log_format: StartupLogFormat = config
+        .startup_log_format
+        .parse()
+        .map_err(anyhow::Error::msg)?;
+    if !config.enumeration_protection {
+        tracing::warn!(
+            "ENUMERATION_PROTECTION is off: login and reset responses reveal who has an account"
+        );
+    }
+    let mut state = AppState::new(user_service, config.clone()).with_auth_mode(auth_mode);
+    if let Some(captcha) = build_captcha_guard(&config)? {
+        state = state.with_captcha(captcha);
+    }
+    if let Some(oidc) = build_oidc_provider(&config)? {
+        state = state.with_oidc(oidc);
+    }
 
-    let state = AppState::new(user_service, config.clone());
+    if let Some(days) = config.retention_days {
+        let mode: RetentionMode = config.retention_mode.parse().map_err(anyhow::Error::msg)?;
+        let service_accounts = config
+            .retention_service_accounts
+            .iter()
+            .map(|email| Email::try_from(email.as_str()))
+            .collect::<Result<Vec<_>, _>>()?;
+        jobs::spawn_retention_job(
+            state.user_service.clone(),
+            RetentionPolicy::new(days, mode).with_service_accounts(service_accounts),
+            StdDuration::from_secs(config.retention_interval_secs),
+        );
+        info!("🧹 Data retention enabled: {} days ({:?})", days, mode);
+    }
 
+    if config.token_prune_interval_secs > 0 {
+        jobs::spawn_token_pruning(
+            state.user_service.clone(),
+            StdDuration::from_secs(config.token_prune_interval_secs),
+        );
+    }
+
+    // The session table comes from the migrations checked above
     let session_store = build_session_store(&db_pool)
         .await
         .map_err(|e| anyhow::anyhow!(e))?;
-    session_store
-        .migrate()
-        .await
-        .map_err(|e| anyhow::anyhow!(e))?;
+    state = state.with_session_store(Arc::new(session_store.clone()));
 
-    let session_layer = SessionManagerLayer::new(session_store)
-        .with_secure(config.secure_cookie)
-        .with_expiry(Expiry::OnInactivity(Duration::days(7)));
+    // Health checks use the store itself, so an outage still shows there
+    let session_layer = session::session_layer(DegradingSessionStore::new(session_store), &config)?;
+    let session_transport: SessionTransport = config
+        .session_transport
+        .parse()
+        .map_err(anyhow::Error::msg)?;
 
-    let auth_layer = setup_auth_layer(session_layer, user_repo).await?;
+    let login_policy = LoginPolicy::new(config.require_verified_email).with_auth_mode(auth_mode);
+    // Failed logins bypass the outbox and go to the publisher directly
+    let auth_layer = setup_auth_layer(
+        session_layer,
+        auth_user_repo,
+        login_policy,
+        password_policy,
+        publisher,
+    )
+    .await?;
 
     let server_config = ServerConfig {
         cors_origins: config.cors_allowed_origins.clone(),
         session_secret: Some(config.session_secret.clone()),
     };
 
-    let app = Router::new()
-        .nest("/api/v1", routes::api_v1_router())
-        .layer(auth_layer)
-        .with_state(state);
+    let mut app = app_router(state.clone(), auth_layer, &config, session_transport);
+
+    if config.csrf_protection {
+        let settings = Arc::new(CsrfSettings {
+            secure_cookie: config.secure_cookie,
+            session_transport,
+        });
+        app = app.layer(axum::middleware::from_fn_with_state(
+            settings,
+            middleware::csrf::csrf_protect,
+        ));
+    }
+
+    let timestamp_style = TimestampStyle {
+        format: config
+            .timestamp_format
+            .parse()
+            .map_err(anyhow::Error::msg)?,
+        zone: match &config.response_timezone {
+            Some(zone) => timestamps::parse_zone(zone).map_err(anyhow::Error::msg)?,
+            None => chrono_tz::Tz::UTC,
+        },
+    };
+    app = app.layer(axum::middleware::from_fn_with_state(
+        timestamp_style,
+        middleware::timestamp_format::timestamp_format,
+    ));
+
+    if config.envelope_responses {
+        app = app.layer(axum::middleware::from_fn(
+            middleware::envelope::wrap_responses,
+        ));
+    }
+
+    // Outside the envelope, which serializes the body again
+    if config.pretty_json_enabled() {
+        info!("📐 Indenting JSON responses");
+        app = app.layer(axum::middleware::from_fn(
+            middleware::pretty_json::pretty_json,
+        ));
+    }
+
+    // Inside the standard middleware, so shed responses still carry CORS headers
+    if let Some(max) = config.max_concurrent_requests {
+        info!("🚦 Shedding load above {} concurrent requests", max);
+        app = app.layer(axum::middleware::from_fn_with_state(
+            LoadShed::new(max),
+            middleware::load_shed::shed_load,
+        ));
+    }
+
+    // Counts shed requests too
+    let ip_source = ClientIpSource::from_ref(&state);
+    app = app.layer(axum::middleware::from_fn_with_state(
+        AbuseTracking {
+            monitor: state.abuse_monitor.clone(),
+            ip_source,
+        },
+        middleware::abuse::track_clients,
+    ));
+
+    let mut app = apply_standard_middleware(app, &server_config);
+
+    // Outside the standard middleware, so it answers preflights for public routes
+    if !config.cors_public_origins.is_empty() {
+        let origins: Vec<String> = config
+            .cors_allowed_origins
+            .iter()
+            .chain(&config.cors_public_origins)
+            .cloned()
+            .collect();
+        app = app.layer(axum::middleware::from_fn_with_state(
+            Arc::new(PublicCors::new(&config.cors_public_paths, &origins)),
+            middleware::cors::public_cors,
+        ));
+    }
+
+    let security_headers = Arc::new(SecurityHeaders::from_config(&config));
+    let app = app.layer(axum::middleware::from_fn_with_state(
+        security_headers,
+        middleware::security_headers::set_security_headers,
+    ));
+
+    // Outermost, so every event of the request, SQL logs included, is in its span
+    let request_id_header: HeaderName = config
+        .request_id_header
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid REQUEST_ID_HEADER: {}", config.request_id_header))?;
+    let app = app.layer(axum::middleware::from_fn_with_state(
+        Arc::new(RequestIdHeader(request_id_header)),
+        middleware::request_id::assign_request_id,
+    ));
 
-    let app = apply_standard_middleware(app, &server_config);
+    // Open the minimum pool before accepting traffic
+    infra::db::warm_up(
+        &db_pool,
+        config.db_min_connections,
+        StdDuration::from_secs(config.db_warm_up_timeout_secs),
+    )
+    .await?;
+    state.mark_ready();
 
     let addr: SocketAddr = format!("{}:{}", config.host, config.port).parse()?;
     let listener = TcpListener::bind(addr).await?;
 
-    tracing::info!("🚀 API server running at http://{}", addr);
-    tracing::info!("🔒 Authentication enabled (axum-login)");
-    tracing::info!("📝 API endpoints available at /api/v1/...");
+    StartupSummary::new(&config, addr, auth_mode, migration_status).log(startup_log_format);
 
-    axum::serve(listener, app).await?;
+    if config.normalize_paths {
+        let app = middleware::normalize_path::normalize_paths(app);
+        let service = ServiceExt::<Request>::into_make_service_with_connect_info::<SocketAddr>(app);
+        axum::serve(listener, service).await?;
+    } else {
+        let service = app.into_make_service_with_connect_info::<SocketAddr>();
+        axum::serve(listener, service).await?;
+    }
 
     Ok(())
 }
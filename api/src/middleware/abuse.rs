@@ -0,0 +1,245 @@
+//! Per-client traffic monitoring
+//!
+//! Counts requests and request bytes per client IP over a sliding window, so
+//! admins can spot abusive clients at `GET /admin/abuse`. The window is split
+//! into fixed buckets, and only the most recently seen clients are kept, so
+//! memory stays bounded no matter how many addresses show up.
+
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{FromRef, Request, State},
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use hashlink::LruCache;
+
+use crate::client_ip::{ClientIp, ClientIpSource};
+
+/// Buckets the window is split into; traffic older than the window ages out one bucket at a time
+const BUCKETS: usize = 10;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    slot: u64,
+    requests: u64,
+    bytes: u64,
+}
+
+/// One client's traffic in the current window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientTraffic {
+    pub ip: IpAddr,
+    pub requests: u64,
+    pub bytes: u64,
+}
+
+/// Sliding-window traffic counters for the most recently seen clients
+pub struct AbuseMonitor {
+    clients: Mutex<LruCache<IpAddr, [Bucket; BUCKETS]>>,
+    bucket_len: Duration,
+    started: Instant,
+}
+
+impl AbuseMonitor {
+    /// Track up to `max_clients` addresses over `window`
+    pub fn new(window: Duration, max_clients: usize) -> Self {
+        Self {
+            clients: Mutex::new(LruCache::new(max_clients.max(1))),
+            bucket_len: (window / BUCKETS as u32).max(Duration::from_millis(1)),
+            started: Instant::now(),
+        }
+    }
+
+    fn slot(&self, at: Instant) -> u64 {
+        (at.saturating_duration_since(self.started).as_millis() / self.bucket_len.as_millis())
+            as u64
+    }
+
+    pub fn record(&self, ip: IpAddr, bytes: u64) {
+        self.record_at(ip, bytes, Instant::now());
+    }
+
+    fn record_at(&self, ip: IpAddr, bytes: u64, at: Instant) {
+        let slot = self.slot(at);
+        let mut clients = self.clients.lock().unwrap();
+        // Inserting beyond capacity evicts the least recently seen client
+        if clients.get_mut(&ip).is_none() {
+            clients.insert(ip, [Bucket::default(); BUCKETS]);
+        }
+        let buckets = clients.get_mut(&ip).expect("just inserted");
+
+        let bucket = &mut buckets[slot as usize % BUCKETS];
+        if bucket.slot != slot {
+            *bucket = Bucket {
+                slot,
+                ..Bucket::default()
+            };
+        }
+        bucket.requests += 1;
+        bucket.bytes += bytes;
+    }
+
+    /// The `limit` busiest clients in the window, by request count
+    pub fn top_talkers(&self, limit: usize) -> Vec<ClientTraffic> {
+        self.top_talkers_at(limit, Instant::now())
+    }
+
+    fn top_talkers_at(&self, limit: usize, at: Instant) -> Vec<ClientTraffic> {
+        let slot = self.slot(at);
+        let clients = self.clients.lock().unwrap();
+
+        let mut traffic: Vec<ClientTraffic> = clients
+            .iter()
+            .map(|(ip, buckets)| {
+                let live = buckets
+                    .iter()
+                    .filter(|bucket| slot.saturating_sub(bucket.slot) < BUCKETS as u64);
+                let (requests, bytes) = live.fold((0, 0), |(requests, bytes), bucket| {
+                    (requests + bucket.requests, bytes + bucket.bytes)
+                });
+                ClientTraffic {
+                    ip: *ip,
+                    requests,
+                    bytes,
+                }
+            })
+            .filter(|client| client.requests > 0)
+            .collect();
+
+        traffic.sort_by(|a, b| b.requests.cmp(&a.requests).then(b.bytes.cmp(&a.bytes)));
+        traffic.truncate(limit);
+        traffic
+    }
+}
+
+/// State for [`track_clients`]
+#[derive(Clone)]
+pub struct AbuseTracking {
+    pub monitor: Arc<AbuseMonitor>,
+    pub ip_source: ClientIpSource,
+}
+
+impl FromRef<AbuseTracking> for ClientIpSource {
+    fn from_ref(tracking: &AbuseTracking) -> Self {
+        tracking.ip_source
+    }
+}
+
+/// Record the request against its client; size comes from `Content-Length`
+pub async fn track_clients(
+    State(tracking): State<AbuseTracking>,
+    ClientIp(ip): ClientIp,
+    request: Request,
+    next: Next,
+) -> Response {
+    let bytes = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0);
+    tracking.monitor.record(ip, bytes);
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, routing::post};
+    use tower::ServiceExt;
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_requests_accumulate_per_client_ordered_by_count() {
+        let monitor = Arc::new(AbuseMonitor::new(Duration::from_secs(60), 100));
+        let app = Router::new().route("/", post(|| async { "ok" })).layer(
+            axum::middleware::from_fn_with_state(
+                AbuseTracking {
+                    monitor: monitor.clone(),
+                    ip_source: ClientIpSource::XForwardedFor,
+                },
+                track_clients,
+            ),
+        );
+
+        for (client, body) in [
+            ("203.0.113.1", "a"),
+            ("203.0.113.2", "bb"),
+            ("203.0.113.2", "cccc"),
+            ("203.0.113.3", "d"),
+            ("203.0.113.2", "ee"),
+            ("203.0.113.3", "ff"),
+        ] {
+            let request = Request::post("/")
+                .header("x-forwarded-for", client)
+                .header(header::CONTENT_LENGTH, body.len())
+                .body(Body::from(body))
+                .unwrap();
+            app.clone().oneshot(request).await.unwrap();
+        }
+
+        assert_eq!(
+            monitor.top_talkers(10),
+            vec![
+                ClientTraffic {
+                    ip: ip("203.0.113.2"),
+                    requests: 3,
+                    bytes: 8
+                },
+                ClientTraffic {
+                    ip: ip("203.0.113.3"),
+                    requests: 2,
+                    bytes: 3
+                },
+                ClientTraffic {
+                    ip: ip("203.0.113.1"),
+                    requests: 1,
+                    bytes: 1
+                },
+            ]
+        );
+        assert_eq!(monitor.top_talkers(1).len(), 1);
+    }
+
+    #[test]
+    fn test_traffic_ages_out_of_the_window() {
+        let monitor = AbuseMonitor::new(Duration::from_secs(10), 100);
+        let start = monitor.started;
+        monitor.record_at(ip("10.0.0.1"), 100, start);
+        monitor.record_at(ip("10.0.0.1"), 100, start + Duration::from_secs(5));
+
+        let halfway = monitor.top_talkers_at(10, start + Duration::from_secs(9));
+        assert_eq!(halfway[0].requests, 2);
+
+        let later = monitor.top_talkers_at(10, start + Duration::from_secs(12));
+        assert_eq!(later[0].requests, 1);
+        assert_eq!(later[0].bytes, 100);
+
+        assert!(
+            monitor
+                .top_talkers_at(10, start + Duration::from_secs(30))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_least_recently_seen_client_is_evicted() {
+        let monitor = AbuseMonitor::new(Duration::from_secs(60), 2);
+        monitor.record(ip("10.0.0.1"), 0);
+        monitor.record(ip("10.0.0.2"), 0);
+        monitor.record(ip("10.0.0.1"), 0);
+        monitor.record(ip("10.0.0.3"), 0);
+
+        let mut seen: Vec<IpAddr> = monitor.top_talkers(10).iter().map(|c| c.ip).collect();
+        seen.sort();
+        assert_eq!(seen, vec![ip("10.0.0.1"), ip("10.0.0.3")]);
+    }
+}
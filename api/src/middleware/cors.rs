@@ -0,0 +1,160 @@
+//! Per-route CORS overrides
+//!
+//! The standard middleware applies one CORS policy, restricted to
+//! `cors_allowed_origins`, to every route. Public, read-only routes (such as
+//! `/config`) may additionally be opened to `cors_public_origins`. The override
+//! sits outside the standard middleware so it also answers preflight requests
+//! for those routes; everything else keeps the restricted policy.
+
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderValue, Method},
+    middleware::Next,
+    response::Response,
+};
+use tower::{Layer, ServiceExt, service_fn};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+/// Relaxed CORS policy for a set of public path prefixes
+#[derive(Clone)]
+pub struct PublicCors {
+    paths: Vec<String>,
+    layer: CorsLayer,
+}
+
+impl PublicCors {
+    /// Origins that fail to parse as header values are skipped
+    pub fn new(paths: &[String], origins: &[String]) -> Self {
+        let origins: Vec<HeaderValue> = origins
+            .iter()
+            .filter_map(|origin| HeaderValue::from_str(origin).ok())
+            .collect();
+
+        // No credentials: public routes never need the session cookie
+        let layer = CorsLayer::new()
+            .allow_origin(AllowOrigin::list(origins))
+            .allow_methods([Method::GET, Method::HEAD])
+            .allow_headers(Any);
+
+        Self {
+            paths: paths.to_vec(),
+            layer,
+        }
+    }
+
+    fn covers(&self, path: &str) -> bool {
+        self.paths.iter().any(|prefix| {
+            path.strip_prefix(prefix.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+    }
+}
+
+pub async fn public_cors(
+    State(cors): State<Arc<PublicCors>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !cors.covers(request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    let inner = service_fn(move |request: Request| {
+        let next = next.clone();
+        async move { Ok::<_, Infallible>(next.run(request).await) }
+    });
+    match cors.layer.layer(inner).oneshot(request).await {
+        Ok(response) => response,
+        Err(never) => match never {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, http::header, routing::get};
+
+    const APP_ORIGIN: &str = "https://app.example.com";
+    const PUBLIC_ORIGIN: &str = "https://partner.example.com";
+
+    /// The restricted global policy wrapped by the public override, as in `main`
+    fn app() -> Router {
+        let restricted = CorsLayer::new()
+            .allow_origin(AllowOrigin::list([HeaderValue::from_static(APP_ORIGIN)]))
+            .allow_methods([Method::GET, Method::POST])
+            .allow_credentials(true);
+        let public = PublicCors::new(
+            &["/api/v1/config".to_string()],
+            &[PUBLIC_ORIGIN.to_string(), APP_ORIGIN.to_string()],
+        );
+
+        Router::new()
+            .route("/api/v1/config", get(|| async { "config" }))
+            .route("/api/v1/configuration", get(|| async { "other" }))
+            .route("/api/v1/auth/me", get(|| async { "me" }))
+            .layer(restricted)
+            .layer(axum::middleware::from_fn_with_state(
+                Arc::new(public),
+                public_cors,
+            ))
+    }
+
+    async fn allowed_origin(request: Request) -> Option<String> {
+        let response = app().oneshot(request).await.unwrap();
+        response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .map(|value| value.to_str().unwrap().to_string())
+    }
+
+    fn get_from(path: &str, origin: &str) -> Request {
+        Request::get(path)
+            .header(header::ORIGIN, origin)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_public_route_allows_public_origin_but_auth_route_does_not() {
+        assert_eq!(
+            allowed_origin(get_from("/api/v1/config", PUBLIC_ORIGIN)).await,
+            Some(PUBLIC_ORIGIN.to_string())
+        );
+        assert_eq!(
+            allowed_origin(get_from("/api/v1/auth/me", PUBLIC_ORIGIN)).await,
+            None
+        );
+        assert_eq!(
+            allowed_origin(get_from("/api/v1/configuration", PUBLIC_ORIGIN)).await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_restricted_origin_still_reaches_auth_routes() {
+        assert_eq!(
+            allowed_origin(get_from("/api/v1/auth/me", APP_ORIGIN)).await,
+            Some(APP_ORIGIN.to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_public_route_answers_preflight() {
+        let preflight = |path: &str| {
+            Request::options(path)
+                .header(header::ORIGIN, PUBLIC_ORIGIN)
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        assert_eq!(
+            allowed_origin(preflight("/api/v1/config")).await,
+            Some(PUBLIC_ORIGIN.to_string())
+        );
+        assert_eq!(allowed_origin(preflight("/api/v1/auth/me")).await, None);
+    }
+}
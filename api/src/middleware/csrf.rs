@@ -0,0 +1,276 @@
+//! CSRF protection
+//!
+//! Double-submit cookie: every client gets a random token in a readable cookie,
+//! and state-changing requests must echo it in the `X-CSRF-Token` header.
+//! Requests authenticated with an `Authorization` header (API keys, or session
+//! tokens when the session transport reads them) are exempt, since browsers
+//! never attach those automatically. An exempt request loses its session
+//! cookie, so the header is the only credential it can authenticate with.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, Method, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use uuid::Uuid;
+
+use crate::error::ApiError;
+use crate::middleware::session_keys::SESSION_COOKIE;
+use crate::middleware::session_transport::{SessionTransport, header_token};
+
+pub const CSRF_COOKIE: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// The request's CSRF token, available to handlers as an extension
+#[derive(Debug, Clone)]
+pub struct CsrfToken(pub String);
+
+/// CSRF cookie settings
+#[derive(Debug, Clone, Default)]
+pub struct CsrfSettings {
+    pub secure_cookie: bool,
+    /// Decides whether an `Authorization: Session` header is a credential at all
+    pub session_transport: SessionTransport,
+}
+
+fn is_safe(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    )
+}
+
+/// Whether the request carries credentials a browser would not attach on its
+/// own, in a form the API key extractor or the session transport will read
+fn uses_header_auth(transport: SessionTransport, headers: &HeaderMap) -> bool {
+    let Some(authorization) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    let api_key = authorization
+        .strip_prefix("ApiKey ")
+        .is_some_and(|key| !key.trim().is_empty());
+    api_key || (transport.uses_header() && header_token(headers).is_some())
+}
+
+/// Drop the session cookie, so a cookie a browser attached can't ride along
+/// with an exempt request
+fn remove_session_cookie(headers: &mut HeaderMap) {
+    let pairs: Vec<String> = headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .filter(|pair| pair.split_once('=').map(|(name, _)| name) != Some(SESSION_COOKIE))
+        .map(str::to_string)
+        .collect();
+
+    headers.remove(header::COOKIE);
+    if pairs.is_empty() {
+        return;
+    }
+    if let Ok(value) = HeaderValue::from_str(&pairs.join("; ")) {
+        headers.insert(header::COOKIE, value);
+    }
+}
+
+fn cookie_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == CSRF_COOKIE)
+        .map(|(_, value)| value.to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Compare without short-circuiting on the first differing byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Whether the `X-CSRF-Token` header matches the cookie's token
+fn has_valid_token(headers: &HeaderMap, cookie: Option<&str>) -> bool {
+    let submitted = headers
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok());
+    match (cookie, submitted) {
+        (Some(cookie), Some(header)) => constant_time_eq(cookie.as_bytes(), header.as_bytes()),
+        _ => false,
+    }
+}
+
+fn generate_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+pub async fn csrf_protect(
+    State(settings): State<Arc<CsrfSettings>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let existing = cookie_token(request.headers());
+
+    if !is_safe(request.method()) {
+        if uses_header_auth(settings.session_transport, request.headers()) {
+            remove_session_cookie(request.headers_mut());
+        } else if !has_valid_token(request.headers(), existing.as_deref()) {
+            return ApiError::Forbidden("Missing or invalid CSRF token".to_string())
+                .into_response();
+        }
+    }
+
+    let issued = existing.is_none();
+    let token = existing.unwrap_or_else(generate_token);
+    request.extensions_mut().insert(CsrfToken(token.clone()));
+
+    let mut response = next.run(request).await;
+
+    if issued {
+        let secure = if settings.secure_cookie {
+            "; Secure"
+        } else {
+            ""
+        };
+        // Not HttpOnly: the client reads it to fill in the header
+        let cookie = format!(
+            "{}={}; Path=/; SameSite=Strict{}",
+            CSRF_COOKIE, token, secure
+        );
+        if let Ok(value) = HeaderValue::from_str(&cookie) {
+            response.headers_mut().append(header::SET_COOKIE, value);
+        }
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, http::StatusCode, routing::post};
+    use tower::ServiceExt;
+
+    fn app() -> Router {
+        app_with(SessionTransport::Cookie)
+    }
+
+    /// Answers with the `Cookie` header the handler received
+    fn app_with(session_transport: SessionTransport) -> Router {
+        let cookies = |headers: HeaderMap| async move {
+            headers
+                .get(header::COOKIE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string()
+        };
+        let settings = CsrfSettings {
+            session_transport,
+            ..CsrfSettings::default()
+        };
+        Router::new()
+            .route("/change", post(cookies).get(|| async { "ok" }))
+            .layer(axum::middleware::from_fn_with_state(
+                Arc::new(settings),
+                csrf_protect,
+            ))
+    }
+
+    fn post_request(headers: &[(&str, &str)]) -> Request {
+        let mut builder = Request::post("/change");
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_post_without_token_is_rejected() {
+        let response = app().oneshot(post_request(&[])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_post_with_mismatched_token_is_rejected() {
+        let request = post_request(&[("cookie", "csrf_token=abc"), (CSRF_HEADER, "xyz")]);
+        let response = app().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_post_with_matching_token_passes() {
+        let request = post_request(&[
+            ("cookie", "session=1; csrf_token=abc"),
+            (CSRF_HEADER, "abc"),
+        ]);
+        let response = app().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_api_key_requests_are_exempt() {
+        let request = post_request(&[("authorization", "ApiKey secret")]);
+        let response = app().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_exempt_request_loses_its_session_cookie() {
+        let request = post_request(&[
+            ("authorization", "ApiKey secret"),
+            ("cookie", "id=signed-session; theme=dark"),
+        ]);
+        let response = app().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"theme=dark");
+    }
+
+    #[tokio::test]
+    async fn test_session_header_is_exempt_only_when_the_transport_reads_it() {
+        let session = [("authorization", "Session signed-session")];
+
+        let response = app().oneshot(post_request(&session)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        for transport in [SessionTransport::Header, SessionTransport::Both] {
+            let response = app_with(transport)
+                .oneshot(post_request(&session))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unused_authorization_schemes_are_not_exempt() {
+        for authorization in ["Bearer token", "ApiKey ", "Basic dXNlcjpwYXNz"] {
+            let request = post_request(&[("authorization", authorization)]);
+            let response = app().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN, "{authorization}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_safe_request_issues_cookie() {
+        let request = Request::get("/change").body(Body::empty()).unwrap();
+        let response = app().oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(cookie.starts_with("csrf_token="));
+        assert!(cookie.contains("SameSite=Strict"));
+    }
+}
@@ -0,0 +1,190 @@
+//! Response envelope
+//!
+//! Wraps JSON responses as `{ "data": ..., "meta": ... }` and errors as `{ "error": ... }`.
+//! Paginated bodies also keep their `links`.
+
+use axum::{
+    body::{Body, to_bytes},
+    extract::Request,
+    http::{HeaderValue, header},
+    middleware::Next,
+    response::Response,
+};
+use serde_json::{Value, json};
+
+/// Wrap a JSON payload in the standard envelope
+pub fn envelope(payload: Value, is_error: bool) -> Value {
+    if is_error {
+        return json!({ "error": payload });
+    }
+
+    match payload {
+        // Paginated payloads carry their own meta alongside the items
+        Value::Object(mut map) if map.contains_key("items") && map.contains_key("meta") => {
+            let meta = map.remove("meta").unwrap_or(Value::Null);
+            let data = map.remove("items").unwrap_or(Value::Null);
+            match map.remove("links") {
+                Some(links) => json!({ "data": data, "meta": meta, "links": links }),
+                None => json!({ "data": data, "meta": meta }),
+            }
+        }
+        data => json!({ "data": data, "meta": null }),
+    }
+}
+
+/// Middleware applying [`envelope`] to every JSON response
+pub async fn wrap_responses(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !is_json {
+        return response;
+    }
+
+    let is_error = response.status().is_client_error() || response.status().is_server_error();
+    let (mut parts, body) = response.into_parts();
+
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::error!("Failed to buffer response body: {}", e);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+
+    let payload = match serde_json::from_slice::<Value>(&bytes) {
+        Ok(payload) => payload,
+        Err(_) => return Response::from_parts(parts, Body::from(bytes)),
+    };
+
+    let wrapped = crate::json::to_vec(&envelope(payload, is_error)).expect("JSON values serialize");
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+
+    Response::from_parts(parts, Body::from(wrapped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_success_is_wrapped_in_data() {
+        let me = json!({ "id": "1", "email": "user@example.com" });
+        let wrapped = envelope(me.clone(), false);
+        assert_eq!(wrapped["data"], me);
+        assert!(wrapped["meta"].is_null());
+    }
+
+    #[test]
+    fn test_list_meta_is_lifted() {
+        let list = json!({ "items": [{ "id": "1" }], "meta": { "page": 1, "total": 1 } });
+        let wrapped = envelope(list, false);
+        assert_eq!(wrapped["data"], json!([{ "id": "1" }]));
+        assert_eq!(wrapped["meta"]["total"], 1);
+    }
+
+    #[test]
+    fn test_error_moves_under_error_key() {
+        let error = json!({ "error": "Unauthorized", "details": "Not logged in" });
+        let wrapped = envelope(error.clone(), true);
+        assert_eq!(wrapped["error"], error);
+        assert!(wrapped.get("data").is_none());
+    }
+
+    /// The test app, with the envelope layered on as `main` does when `enabled`
+    async fn app(enabled: bool) -> axum::Router {
+        let config = crate::config::Config {
+            first_user_is_admin: true,
+            envelope_responses: enabled,
+            ..crate::test_support::test_config()
+        };
+        let pool = crate::test_support::test_pool().await;
+        let (app, _) = crate::test_support::build_test_app_with(pool, config.clone()).await;
+        if config.envelope_responses {
+            app.layer(axum::middleware::from_fn(wrap_responses))
+        } else {
+            app
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handler_responses_are_enveloped() {
+        use crate::test_support::TestClient;
+        use axum::http::StatusCode;
+
+        let mut client = TestClient::new(app(true).await);
+        let credentials = json!({ "email": "admin@example.com", "password": "correct horse" });
+
+        let registered = client
+            .post_json("/api/v1/auth/register", credentials.clone())
+            .await;
+        assert_eq!(registered.status, StatusCode::CREATED);
+        let body = registered.json();
+        assert_eq!(body["data"]["email"], "admin@example.com");
+        assert!(body["meta"].is_null());
+
+        let sessions = client.get("/api/v1/admin/sessions").await;
+        assert_eq!(sessions.status, StatusCode::OK);
+        let body = sessions.json();
+        assert_eq!(body["data"].as_array().unwrap().len(), 1);
+        assert_eq!(body["meta"]["total"], 1);
+        assert!(body["links"]["self"].is_string());
+
+        let me = client.post_json("/api/v1/auth/me", json!({})).await;
+        assert_eq!(me.status, StatusCode::OK);
+        let body = me.json();
+        assert_eq!(body["data"]["email"], "admin@example.com");
+        assert!(body["meta"].is_null());
+
+        let users = client.get("/api/v1/admin/users?q=admin").await;
+        assert_eq!(users.status, StatusCode::OK);
+        let body = users.json();
+        assert_eq!(body["data"][0]["email"], "admin@example.com");
+        assert_eq!(body["meta"]["total"], 1);
+        assert!(body.get("items").is_none());
+
+        let duplicate = client.post_json("/api/v1/auth/register", credentials).await;
+        assert_eq!(duplicate.status, StatusCode::CONFLICT);
+        let body = duplicate.json();
+        assert_eq!(body["error"]["conflict_field"], "email");
+        assert!(body.get("data").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_disabled_envelope_leaves_responses_unchanged() {
+        use crate::test_support::TestClient;
+        use axum::http::StatusCode;
+
+        let mut client = TestClient::new(app(false).await);
+        let credentials = json!({ "email": "admin@example.com", "password": "correct horse" });
+
+        let registered = client
+            .post_json("/api/v1/auth/register", credentials.clone())
+            .await;
+        assert_eq!(registered.status, StatusCode::CREATED);
+        assert_eq!(registered.json()["email"], "admin@example.com");
+
+        let me = client.post_json("/api/v1/auth/me", json!({})).await;
+        let body = me.json();
+        assert_eq!(body["email"], "admin@example.com");
+        assert!(body.get("data").is_none());
+
+        let users = client.get("/api/v1/admin/users?q=admin").await;
+        let body = users.json();
+        assert_eq!(body["items"][0]["email"], "admin@example.com");
+        assert_eq!(body["meta"]["total"], 1);
+        assert!(body.get("data").is_none());
+
+        let duplicate = client.post_json("/api/v1/auth/register", credentials).await;
+        assert_eq!(duplicate.status, StatusCode::CONFLICT);
+        assert_eq!(duplicate.json()["conflict_field"], "email");
+    }
+}
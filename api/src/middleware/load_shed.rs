@@ -0,0 +1,101 @@
+//! Load shedding
+//!
+//! Caps the number of requests in flight. Requests beyond the cap are rejected
+//! immediately with `503 Service Unavailable` and `Retry-After` instead of
+//! queueing, so a burst cannot pile up work on a small instance.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tokio::sync::Semaphore;
+
+use crate::error::ApiError;
+
+/// Paths that are never shed, so probes keep working under load.
+/// Matched exactly: a user route ending in `/health` is shed like any other.
+const BYPASS_PATHS: &[&str] = &["/health", "/healthz", "/ready", "/metrics"];
+
+/// Seconds clients are asked to wait before retrying
+const RETRY_AFTER_SECS: u64 = 1;
+
+/// In-flight request limit
+#[derive(Debug, Clone)]
+pub struct LoadShed {
+    permits: Arc<Semaphore>,
+}
+
+impl LoadShed {
+    pub fn new(max_concurrent_requests: usize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(max_concurrent_requests)),
+        }
+    }
+}
+
+fn bypasses(path: &str) -> bool {
+    BYPASS_PATHS.contains(&path)
+}
+
+pub async fn shed_load(State(limit): State<LoadShed>, request: Request, next: Next) -> Response {
+    if bypasses(request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    // Held until the response is produced
+    let Ok(_permit) = limit.permits.clone().try_acquire_owned() else {
+        return ApiError::Overloaded {
+            retry_after_secs: RETRY_AFTER_SECS,
+        }
+        .into_response();
+    };
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, http::StatusCode, http::header, routing::get};
+    use tower::ServiceExt;
+
+    fn app(limit: LoadShed) -> Router {
+        Router::new()
+            .route("/work", get(|| async { "done" }))
+            .route("/health", get(|| async { "ok" }))
+            .route("/notes/health", get(|| async { "ok" }))
+            .layer(axum::middleware::from_fn_with_state(limit, shed_load))
+    }
+
+    fn get_request(path: &str) -> Request {
+        Request::get(path).body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_requests_beyond_limit_are_shed() {
+        let limit = LoadShed::new(1);
+        let app = app(limit.clone());
+
+        // Stands in for a request still being processed
+        let in_flight = limit.permits.clone().try_acquire_owned().unwrap();
+
+        let response = app.clone().oneshot(get_request("/work")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+
+        let response = app.clone().oneshot(get_request("/health")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app
+            .clone()
+            .oneshot(get_request("/notes/health"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        drop(in_flight);
+        let response = app.oneshot(get_request("/work")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,17 @@
+//! HTTP middleware
+//!
+//! Optional layers applied to the router based on configuration.
+
+pub mod abuse;
+pub mod cors;
+pub mod csrf;
+pub mod envelope;
+pub mod load_shed;
+pub mod normalize_path;
+pub mod pretty_json;
+pub mod request_id;
+pub mod security_headers;
+pub mod session_keys;
+pub mod session_outage;
+pub mod session_transport;
+pub mod timestamp_format;
@@ -0,0 +1,35 @@
+//! Path normalization
+//!
+//! Trims trailing slashes before routing so `/auth/me/` reaches `/auth/me`.
+//! This has to wrap the router from the outside: layers added with
+//! `Router::layer` only run after a route has been matched.
+
+use axum::Router;
+use tower::Layer;
+use tower_http::normalize_path::{NormalizePath, NormalizePathLayer};
+
+pub fn normalize_paths(app: Router) -> NormalizePath<Router> {
+    NormalizePathLayer::trim_trailing_slash().layer(app)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request, http::StatusCode, routing::get};
+    use tower::ServiceExt;
+
+    async fn status(path: &str) -> StatusCode {
+        let app = Router::new().route("/api/v1/auth/me", get(|| async { "me" }));
+        normalize_paths(app)
+            .oneshot(Request::get(path).body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_trailing_slash_reaches_same_handler() {
+        assert_eq!(status("/api/v1/auth/me").await, StatusCode::OK);
+        assert_eq!(status("/api/v1/auth/me/").await, StatusCode::OK);
+    }
+}
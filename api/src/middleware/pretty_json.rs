@@ -0,0 +1,48 @@
+//! Pretty-printed JSON responses
+//!
+//! Development aid: responses written with [`ApiJson`](crate::json::ApiJson)
+//! come out indented, so they read well from curl. Production keeps them
+//! compact.
+
+use axum::{extract::Request, middleware::Next, response::Response};
+
+use crate::json;
+
+/// Indent every JSON response of the request
+pub async fn pretty_json(request: Request, next: Next) -> Response {
+    json::scope(true, next.run(request)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, routing::get};
+    use tower::ServiceExt;
+
+    use crate::json::ApiJson;
+
+    async fn body(app: Router) -> String {
+        let response = app
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_responses_are_indented_only_in_pretty_mode() {
+        let app = Router::new().route(
+            "/",
+            get(|| async { ApiJson(serde_json::json!({ "user": { "id": 1 } })) }),
+        );
+
+        let compact = body(app.clone()).await;
+        assert_eq!(compact, r#"{"user":{"id":1}}"#);
+
+        let pretty = body(app.layer(axum::middleware::from_fn(pretty_json))).await;
+        assert!(pretty.contains("\n  \"user\": {\n    \"id\": 1"));
+    }
+}
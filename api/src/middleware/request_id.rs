@@ -0,0 +1,262 @@
+//! Request ids
+//!
+//! Runs every request inside a `request` span carrying its id, so events
+//! logged while handling it, SQL statement logs included, can be tied back to
+//! it. A proxy-assigned id is kept; otherwise one is generated. Either way it
+//! is echoed in the response.
+//!
+//! The span also records the negotiated HTTP version as `protocol`, so the
+//! request completion log shows which clients still speak HTTP/1.1.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+use uuid::Uuid;
+
+/// Longest incoming id that is trusted; longer ones are replaced
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// Header the request id is read from and written to
+#[derive(Debug, Clone)]
+pub struct RequestIdHeader(pub HeaderName);
+
+/// An incoming id, if it is short and printable enough to log as is
+fn incoming_id(value: &HeaderValue) -> Option<String> {
+    let value = value.to_str().ok()?;
+    let printable = value.chars().all(|c| c.is_ascii_graphic());
+    (!value.is_empty() && value.len() <= MAX_REQUEST_ID_LENGTH && printable)
+        .then(|| value.to_string())
+}
+
+pub async fn assign_request_id(
+    State(header): State<Arc<RequestIdHeader>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let id = request
+        .headers()
+        .get(&header.0)
+        .and_then(incoming_id)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let span = tracing::info_span!(
+        "request",
+        request_id = %id,
+        method = %request.method(),
+        path = %request.uri().path(),
+        protocol = ?request.version(),
+    );
+    let mut response = next.run(request).instrument(span).await;
+
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(header.0.clone(), value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use axum::{Router, body::Body, routing::get};
+    use infra::db::{
+        ConnectionSettings, DatabaseConfig, DatabasePool, LevelFilter, SqlLogging, create_pool,
+    };
+    use tower::ServiceExt;
+    use tracing::field::{Field, Visit};
+    use tracing::instrument::WithSubscriber;
+    use tracing::span::{Attributes, Id};
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::registry::LookupSpan;
+
+    /// Fields of a span, kept in its extensions
+    #[derive(Default)]
+    struct SpanFields(HashMap<String, String>);
+
+    impl Visit for SpanFields {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    /// A captured event: its target, its fields joined, and the fields of the spans around it
+    type Events = Arc<Mutex<Vec<(String, String, HashMap<String, String>)>>>;
+
+    /// Span entries: the entering thread's name and the span's fields
+    type Entries = Arc<Mutex<Vec<(Option<String>, HashMap<String, String>)>>>;
+
+    #[derive(Clone, Default)]
+    struct Capture {
+        events: Events,
+        entries: Entries,
+    }
+
+    impl<S> tracing_subscriber::Layer<S> for Capture
+    where
+        S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+    {
+        fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+            let mut fields = SpanFields::default();
+            attrs.record(&mut fields);
+            ctx.span(id).unwrap().extensions_mut().insert(fields);
+        }
+
+        fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+            let span = ctx.span(id).unwrap();
+            let fields = span.extensions().get::<SpanFields>().unwrap().0.clone();
+            let thread = std::thread::current().name().map(str::to_string);
+            self.entries.lock().unwrap().push((thread, fields));
+        }
+
+        fn on_event(&self, event: &tracing::Event<'_>, ctx: Context<'_, S>) {
+            let mut fields = SpanFields::default();
+            event.record(&mut fields);
+            let text = fields.0.into_values().collect::<Vec<_>>().join(" ");
+
+            let mut scope_fields = HashMap::new();
+            if let Some(scope) = ctx.event_scope(event) {
+                for span in scope {
+                    if let Some(fields) = span.extensions().get::<SpanFields>() {
+                        scope_fields.extend(fields.0.clone());
+                    }
+                }
+            }
+            let target = event.metadata().target().to_string();
+            self.events
+                .lock()
+                .unwrap()
+                .push((target, text, scope_fields));
+        }
+    }
+
+    /// A subscriber to scope to one test, and what it captures
+    fn capture() -> (tracing::Dispatch, Capture) {
+        let capture = Capture::default();
+        let subscriber = tracing_subscriber::registry().with(capture.clone());
+        (tracing::Dispatch::new(subscriber), capture)
+    }
+
+    async fn logged_pool() -> DatabasePool {
+        let settings = ConnectionSettings {
+            sql_logging: Some(SqlLogging {
+                level: LevelFilter::Info,
+                slow_threshold: Duration::from_secs(1),
+            }),
+            ..Default::default()
+        };
+        let config = DatabaseConfig {
+            url: "sqlite::memory:".to_string(),
+            max_connections: 1,
+            min_connections: 1,
+            acquire_timeout: Duration::from_secs(5),
+        };
+        create_pool(config, &settings).await.unwrap()
+    }
+
+    /// SQLite runs statements on a worker thread, outside the test's scoped
+    /// subscriber, so this checks that the worker enters the request span
+    /// around the statement; everything it logs there carries the id.
+    #[tokio::test]
+    async fn test_sql_logs_carry_the_request_id() {
+        let (subscriber, capture) = capture();
+
+        let pool = match logged_pool().await {
+            DatabasePool::Sqlite(pool) => pool,
+            #[allow(unreachable_patterns)]
+            _ => panic!("expected a SQLite pool"),
+        };
+        let app = Router::new()
+            .route(
+                "/",
+                get(move || async move {
+                    sqlx::query("SELECT 42 AS correlated")
+                        .execute(&pool)
+                        .await
+                        .unwrap();
+                }),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                Arc::new(RequestIdHeader(HeaderName::from_static("x-request-id"))),
+                assign_request_id,
+            ));
+
+        let request = Request::get("/")
+            .header("x-request-id", "req-42")
+            .body(Body::empty())
+            .unwrap();
+        let response = app
+            .oneshot(request)
+            .with_subscriber(subscriber)
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["x-request-id"], "req-42");
+
+        let entries = capture.entries.lock().unwrap();
+        let on_worker = entries.iter().any(|(thread, fields)| {
+            thread
+                .as_deref()
+                .is_some_and(|name| name.starts_with("sqlx-sqlite-worker"))
+                && fields.get("request_id").map(String::as_str) == Some("req-42")
+        });
+        assert!(on_worker, "statement ran inside the request span");
+    }
+
+    #[tokio::test]
+    async fn test_completion_log_carries_the_protocol() {
+        use tower_http::trace::{DefaultOnResponse, TraceLayer};
+
+        let (subscriber, capture) = capture();
+        // Like the standard middleware's, inside the request span
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(
+                TraceLayer::new_for_http()
+                    .on_response(DefaultOnResponse::new().level(tracing::Level::INFO)),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                Arc::new(RequestIdHeader(HeaderName::from_static("x-request-id"))),
+                assign_request_id,
+            ));
+
+        let request = Request::get("/")
+            .version(axum::http::Version::HTTP_11)
+            .header("x-request-id", "req-protocol")
+            .body(Body::empty())
+            .unwrap();
+        app.oneshot(request)
+            .with_subscriber(subscriber)
+            .await
+            .unwrap();
+
+        let events = capture.events.lock().unwrap();
+        let (_, _, scope) = events
+            .iter()
+            .find(|(target, _, scope)| {
+                target.starts_with("tower_http::trace::on_response")
+                    && scope.get("request_id").map(String::as_str) == Some("req-protocol")
+            })
+            .expect("completion was logged");
+        assert_eq!(scope.get("protocol").map(String::as_str), Some("HTTP/1.1"));
+    }
+
+    #[test]
+    fn test_unprintable_or_oversized_ids_are_replaced() {
+        assert_eq!(
+            incoming_id(&HeaderValue::from_static("abc-123")).as_deref(),
+            Some("abc-123")
+        );
+        assert_eq!(incoming_id(&HeaderValue::from_static("two words")), None);
+        let long = "a".repeat(MAX_REQUEST_ID_LENGTH + 1);
+        assert_eq!(incoming_id(&HeaderValue::from_str(&long).unwrap()), None);
+    }
+}
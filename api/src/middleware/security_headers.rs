@@ -0,0 +1,134 @@
+//! Security headers
+//!
+//! Adds the response headers security scanners expect, each toggleable via config.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, header},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::config::Config;
+
+/// Headers to set on every response
+#[derive(Debug, Clone, Default)]
+pub struct SecurityHeaders {
+    headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl SecurityHeaders {
+    pub fn from_config(config: &Config) -> Self {
+        let mut headers = Vec::new();
+
+        if config.header_nosniff {
+            headers.push((
+                header::X_CONTENT_TYPE_OPTIONS,
+                HeaderValue::from_static("nosniff"),
+            ));
+        }
+
+        if config.header_frame_deny {
+            headers.push((header::X_FRAME_OPTIONS, HeaderValue::from_static("DENY")));
+        }
+
+        let mut push = |name: HeaderName, value: &Option<String>| {
+            let Some(value) = value else { return };
+            match HeaderValue::from_str(value) {
+                Ok(value) => headers.push((name, value)),
+                Err(_) => tracing::warn!("Ignoring invalid {} header value: {}", name, value),
+            }
+        };
+
+        push(header::REFERRER_POLICY, &config.referrer_policy);
+        push(
+            header::CONTENT_SECURITY_POLICY,
+            &config.content_security_policy,
+        );
+
+        // HSTS only makes sense when served over TLS
+        if config.hsts_enabled && config.secure_cookie {
+            push(
+                header::STRICT_TRANSPORT_SECURITY,
+                &Some(format!(
+                    "max-age={}; includeSubDomains",
+                    config.hsts_max_age
+                )),
+            );
+        }
+
+        Self { headers }
+    }
+
+    /// Set the headers, leaving any a handler already chose untouched
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        for (name, value) in &self.headers {
+            if !headers.contains_key(name) {
+                headers.insert(name.clone(), value.clone());
+            }
+        }
+    }
+}
+
+pub async fn set_security_headers(
+    State(security_headers): State<Arc<SecurityHeaders>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+    security_headers.apply(response.headers_mut());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(config: &Config) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        SecurityHeaders::from_config(config).apply(&mut headers);
+        headers
+    }
+
+    #[test]
+    fn test_default_headers_are_present() {
+        let headers = apply(&Config::default());
+
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(headers[header::X_FRAME_OPTIONS], "DENY");
+        assert!(headers.contains_key(header::REFERRER_POLICY));
+        assert!(headers.contains_key(header::CONTENT_SECURITY_POLICY));
+        // Not served over TLS by default
+        assert!(!headers.contains_key(header::STRICT_TRANSPORT_SECURITY));
+    }
+
+    #[test]
+    fn test_disabled_header_is_removed() {
+        let config = Config {
+            header_frame_deny: false,
+            content_security_policy: None,
+            ..Config::default()
+        };
+        let headers = apply(&config);
+
+        assert!(!headers.contains_key(header::X_FRAME_OPTIONS));
+        assert!(!headers.contains_key(header::CONTENT_SECURITY_POLICY));
+        assert!(headers.contains_key(header::X_CONTENT_TYPE_OPTIONS));
+    }
+
+    #[test]
+    fn test_hsts_requires_secure() {
+        let config = Config {
+            secure_cookie: true,
+            ..Config::default()
+        };
+        let headers = apply(&config);
+
+        assert_eq!(
+            headers[header::STRICT_TRANSPORT_SECURITY],
+            "max-age=31536000; includeSubDomains"
+        );
+    }
+}
@@ -0,0 +1,263 @@
+//! Session cookie signing with key rotation
+//!
+//! The session id cookie is signed with HMAC-SHA256 under `SESSION_SECRET`.
+//! Cookies signed with one of `SESSION_SECRET_PREVIOUS` are still accepted, so
+//! rotating the secret doesn't log everyone out; every cookie the server sets
+//! is signed with the current secret, so old signatures age out as sessions
+//! are saved. Cookies with a bad signature are dropped before the session
+//! layer sees them.
+//!
+//! Deploying signing logs out every existing session, whose cookies are
+//! unsigned, unless `SESSION_ACCEPT_UNSIGNED` is on. Sessions expire on
+//! inactivity, so their cookie is set again, signed, on the next response;
+//! after one session lifetime the flag can go.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, header},
+    middleware::Next,
+    response::Response,
+};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Cookie name used by tower-sessions
+pub const SESSION_COOKIE: &str = "id";
+
+/// Current signing key plus keys still accepted during a rotation
+#[derive(Clone)]
+pub struct SessionKeys {
+    current: Vec<u8>,
+    previous: Vec<Vec<u8>>,
+    accept_unsigned: bool,
+}
+
+impl SessionKeys {
+    pub fn new(current: &str, previous: &[String]) -> Self {
+        Self {
+            current: current.as_bytes().to_vec(),
+            previous: previous.iter().map(|key| key.as_bytes().to_vec()).collect(),
+            accept_unsigned: false,
+        }
+    }
+
+    /// Let session cookies from before signing through unchanged
+    pub fn accepting_unsigned(mut self, accept: bool) -> Self {
+        self.accept_unsigned = accept;
+        self
+    }
+
+    fn mac(key: &[u8], value: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+        mac.update(value.as_bytes());
+        mac
+    }
+
+    /// `value.signature`, signed with the current key
+    pub fn sign(&self, value: &str) -> String {
+        let tag = Self::mac(&self.current, value).finalize().into_bytes();
+        let signature: String = tag.iter().map(|byte| format!("{:02x}", byte)).collect();
+        format!("{}.{}", value, signature)
+    }
+
+    /// The unsigned value, if signed with the current or a previous key
+    pub fn verify<'a>(&self, signed: &'a str) -> Option<&'a str> {
+        let (value, signature) = signed.rsplit_once('.')?;
+        let signature = decode_hex(signature)?;
+        std::iter::once(&self.current)
+            .chain(&self.previous)
+            .any(|key| Self::mac(key, value).verify_slice(&signature).is_ok())
+            .then_some(value)
+    }
+
+    /// The session id a request cookie carries, if it is to be trusted
+    fn session_id<'a>(&self, cookie: &'a str) -> Option<&'a str> {
+        // Session ids are base64url, so only signed values contain a dot
+        if self.accept_unsigned && !cookie.contains('.') {
+            return Some(cookie);
+        }
+        self.verify(cookie)
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Rebuild the `Cookie` header with the session cookie verified and unsigned
+fn unsign_request_cookies(keys: &SessionKeys, headers: &mut HeaderMap) {
+    let pairs: Vec<String> = headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .filter_map(|pair| match pair.split_once('=') {
+            Some((SESSION_COOKIE, signed)) => keys
+                .session_id(signed)
+                .map(|value| format!("{}={}", SESSION_COOKIE, value)),
+            _ => Some(pair.to_string()),
+        })
+        .collect();
+
+    headers.remove(header::COOKIE);
+    if pairs.is_empty() {
+        return;
+    }
+    if let Ok(value) = HeaderValue::from_str(&pairs.join("; ")) {
+        headers.insert(header::COOKIE, value);
+    }
+}
+
+/// Sign the session cookie in any `Set-Cookie` header
+fn sign_response_cookies(keys: &SessionKeys, headers: &mut HeaderMap) {
+    let cookies: Vec<HeaderValue> = headers
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .cloned()
+        .collect();
+    if cookies.is_empty() {
+        return;
+    }
+
+    headers.remove(header::SET_COOKIE);
+    for cookie in cookies {
+        let signed = cookie.to_str().ok().and_then(|cookie| {
+            let (pair, attributes) = cookie.split_once(';').unwrap_or((cookie, ""));
+            let value = pair.strip_prefix(SESSION_COOKIE)?.strip_prefix('=')?;
+            // Removal cookies carry an empty value; nothing to sign
+            if value.is_empty() {
+                return None;
+            }
+            let separator = if attributes.is_empty() { "" } else { ";" };
+            HeaderValue::from_str(&format!(
+                "{}={}{}{}",
+                SESSION_COOKIE,
+                keys.sign(value),
+                separator,
+                attributes
+            ))
+            .ok()
+        });
+        headers.append(header::SET_COOKIE, signed.unwrap_or(cookie));
+    }
+}
+
+pub async fn sign_session_cookie(
+    State(keys): State<Arc<SessionKeys>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    unsign_request_cookies(&keys, request.headers_mut());
+    let mut response = next.run(request).await;
+    sign_response_cookies(&keys, response.headers_mut());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, routing::get};
+    use tower::ServiceExt;
+
+    fn keys() -> SessionKeys {
+        SessionKeys::new("new-secret", &["old-secret".to_string()])
+    }
+
+    /// Echoes the cookies the session layer would see, and sets a new session
+    fn app(keys: SessionKeys) -> Router {
+        Router::new()
+            .route(
+                "/",
+                get(|headers: HeaderMap| async move {
+                    let cookies = headers
+                        .get(header::COOKIE)
+                        .map(|value| value.to_str().unwrap().to_string())
+                        .unwrap_or_default();
+                    (
+                        [(header::SET_COOKIE, "id=fresh; Path=/; HttpOnly")],
+                        cookies,
+                    )
+                }),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                Arc::new(keys),
+                sign_session_cookie,
+            ))
+    }
+
+    async fn send(cookie: &str) -> (String, String) {
+        send_with(keys(), cookie).await
+    }
+
+    async fn send_with(keys: SessionKeys, cookie: &str) -> (String, String) {
+        let request = Request::get("/")
+            .header(header::COOKIE, cookie)
+            .body(Body::empty())
+            .unwrap();
+        let response = app(keys).oneshot(request).await.unwrap();
+        let set_cookie = response.headers()[header::SET_COOKIE]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (String::from_utf8(body.to_vec()).unwrap(), set_cookie)
+    }
+
+    #[tokio::test]
+    async fn test_previous_key_still_loads_and_new_cookie_uses_current_key() {
+        let old = SessionKeys::new("old-secret", &[]).sign("session-1");
+        let (seen, set_cookie) = send(&format!("csrf_token=abc; id={}", old)).await;
+
+        assert_eq!(seen, "csrf_token=abc; id=session-1");
+
+        let value = set_cookie
+            .strip_prefix("id=")
+            .and_then(|rest| rest.split(';').next())
+            .unwrap();
+        assert_eq!(
+            SessionKeys::new("new-secret", &[]).verify(value),
+            Some("fresh")
+        );
+        assert_eq!(SessionKeys::new("old-secret", &[]).verify(value), None);
+        assert!(set_cookie.ends_with("; Path=/; HttpOnly"));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_signature_is_dropped() {
+        let forged = SessionKeys::new("attacker", &[]).sign("session-1");
+        let (seen, _) = send(&format!("id={}; csrf_token=abc", forged)).await;
+        assert_eq!(seen, "csrf_token=abc");
+
+        let (seen, _) = send("id=unsigned").await;
+        assert_eq!(seen, "");
+    }
+
+    #[tokio::test]
+    async fn test_unsigned_cookies_load_during_the_transition_and_come_back_signed() {
+        let transition = || keys().accepting_unsigned(true);
+        let (seen, set_cookie) = send_with(transition(), "id=legacy-session").await;
+        assert_eq!(seen, "id=legacy-session");
+        let value = set_cookie
+            .strip_prefix("id=")
+            .and_then(|rest| rest.split(';').next())
+            .unwrap();
+        assert_eq!(keys().verify(value), Some("fresh"));
+
+        // A bad signature is still a bad signature
+        let forged = SessionKeys::new("attacker", &[]).sign("session-1");
+        let (seen, _) = send_with(transition(), &format!("id={}", forged)).await;
+        assert_eq!(seen, "");
+    }
+}
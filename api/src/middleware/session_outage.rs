@@ -0,0 +1,117 @@
+//! Session store outages
+//!
+//! While the session store is down, sessions load as empty
+//! ([`DegradingSessionStore`](infra::session_store::DegradingSessionStore)),
+//! so public routes keep working. Routes needing a signed-in user would then
+//! answer 401 and send clients to the login page over a session that is most
+//! likely fine; they answer 503 instead.
+
+use axum::{
+    extract::Request,
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use infra::session_store::track_load_failures;
+
+use crate::error::ApiError;
+
+pub async fn unavailable_instead_of_unauthorized(request: Request, next: Next) -> Response {
+    let (response, load_failed) = track_load_failures(next.run(request)).await;
+    if load_failed && response.status() == StatusCode::UNAUTHORIZED {
+        return ApiError::SessionStoreUnavailable.into_response();
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use axum::{Router, body::Body, http::header, routing::get};
+    use domain::LoginPolicy;
+    use infra::factory::build_user_repository;
+    use infra::session_store::{DegradingSessionStore, Id, Record, SessionStore, session_store};
+    use tower::ServiceExt;
+
+    use crate::auth::{AuthSession, PasswordHashPolicy, setup_auth_layer};
+    use crate::config::Config;
+    use crate::test_support::test_pool;
+
+    /// A store whose backend is down
+    #[derive(Debug)]
+    struct DownStore;
+
+    #[async_trait]
+    impl SessionStore for DownStore {
+        async fn save(&self, _: &Record) -> session_store::Result<()> {
+            Err(session_store::Error::Backend(
+                "connection refused".to_string(),
+            ))
+        }
+
+        async fn load(&self, _: &Id) -> session_store::Result<Option<Record>> {
+            Err(session_store::Error::Backend(
+                "connection refused".to_string(),
+            ))
+        }
+
+        async fn delete(&self, _: &Id) -> session_store::Result<()> {
+            Err(session_store::Error::Backend(
+                "connection refused".to_string(),
+            ))
+        }
+    }
+
+    async fn private(auth_session: AuthSession) -> Result<&'static str, ApiError> {
+        auth_session
+            .user
+            .map(|_| "secret")
+            .ok_or(ApiError::Unauthorized("Not logged in".to_string()))
+    }
+
+    #[tokio::test]
+    async fn test_public_routes_survive_a_session_store_outage() {
+        let pool = test_pool().await;
+        let session_layer = crate::session::session_layer(
+            DegradingSessionStore::new(DownStore),
+            &Config::default(),
+        )
+        .unwrap();
+        let auth_layer = setup_auth_layer(
+            session_layer,
+            build_user_repository(&pool).await.unwrap(),
+            LoginPolicy::default(),
+            PasswordHashPolicy::default(),
+            Arc::new(infra::LoggingEventPublisher),
+        )
+        .await
+        .unwrap();
+        let app = Router::new()
+            .route("/public", get(|| async { "hello" }))
+            .route("/private", get(private))
+            .layer(auth_layer)
+            .layer(axum::middleware::from_fn(
+                unavailable_instead_of_unauthorized,
+            ));
+
+        let cookie = format!("id={}", Id::default());
+        let send = |uri: &'static str| {
+            let request = Request::get(uri)
+                .header(header::COOKIE, &cookie)
+                .body(Body::empty())
+                .unwrap();
+            app.clone().oneshot(request)
+        };
+
+        let response = send("/public").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        // The session cookie is kept for when the store is back
+        assert!(response.headers().get(header::SET_COOKIE).is_none());
+
+        let response = send("/private").await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
@@ -0,0 +1,310 @@
+//! Session id transport
+//!
+//! By default the session id travels in the `id` cookie. SPAs served from
+//! another origin often can't rely on third-party cookies, so the id can
+//! instead be returned in the `X-Session-Token` response header and sent back
+//! as `Authorization: Session <token>`. The token is the same signed value the
+//! cookie would hold, backed by the same session store: this layer only moves
+//! it between the header and the `Cookie` / `Set-Cookie` headers the session
+//! layer reads and writes.
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, header},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::middleware::session_keys::SESSION_COOKIE;
+
+/// Response header carrying the session token in header mode
+pub const SESSION_TOKEN_HEADER: &str = "x-session-token";
+
+/// `Authorization` scheme for session tokens
+pub const SESSION_AUTH_SCHEME: &str = "Session";
+
+/// Where the session id is read from and written to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SessionTransport {
+    #[default]
+    Cookie,
+    /// `Authorization: Session <token>` only; no session cookie is set
+    Header,
+    /// Either; the header wins when a request carries both
+    Both,
+}
+
+impl SessionTransport {
+    fn uses_cookie(self) -> bool {
+        matches!(self, SessionTransport::Cookie | SessionTransport::Both)
+    }
+
+    pub(crate) fn uses_header(self) -> bool {
+        matches!(self, SessionTransport::Header | SessionTransport::Both)
+    }
+}
+
+impl std::str::FromStr for SessionTransport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cookie" => Ok(SessionTransport::Cookie),
+            "header" => Ok(SessionTransport::Header),
+            "both" => Ok(SessionTransport::Both),
+            other => Err(format!("Unknown session transport: {}", other)),
+        }
+    }
+}
+
+pub(crate) fn header_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case(SESSION_AUTH_SCHEME))
+        .map(|(_, token)| token.trim())
+        .filter(|token| !token.is_empty())
+}
+
+/// Rebuild the `Cookie` header so the session cookie comes from the configured transport
+fn rewrite_request_cookies(transport: SessionTransport, headers: &mut HeaderMap) {
+    let token = transport
+        .uses_header()
+        .then(|| header_token(headers))
+        .flatten()
+        .map(str::to_string);
+    let keep_session_cookie = transport.uses_cookie() && token.is_none();
+
+    let mut pairs: Vec<String> = headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .filter(|pair| {
+            keep_session_cookie
+                || pair.split_once('=').map(|(name, _)| name) != Some(SESSION_COOKIE)
+        })
+        .map(str::to_string)
+        .collect();
+    if let Some(token) = token {
+        pairs.push(format!("{}={}", SESSION_COOKIE, token));
+    }
+
+    headers.remove(header::COOKIE);
+    if pairs.is_empty() {
+        return;
+    }
+    if let Ok(value) = HeaderValue::from_str(&pairs.join("; ")) {
+        headers.insert(header::COOKIE, value);
+    }
+}
+
+/// The session cookie's value, if `cookie` is a `Set-Cookie` for it
+fn session_cookie_value(cookie: &HeaderValue) -> Option<&str> {
+    let cookie = cookie.to_str().ok()?;
+    let pair = cookie.split(';').next()?;
+    match pair.trim().split_once('=') {
+        Some((SESSION_COOKIE, value)) => Some(value),
+        _ => None,
+    }
+}
+
+/// Copy a newly set session id into the token header, dropping the cookie in header mode
+fn move_response_cookie(transport: SessionTransport, headers: &mut HeaderMap) {
+    if !transport.uses_header() {
+        return;
+    }
+
+    let cookies: Vec<HeaderValue> = headers
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .cloned()
+        .collect();
+    // Removal cookies carry an empty value; there's no token to hand out
+    let token = cookies
+        .iter()
+        .filter_map(session_cookie_value)
+        .find(|value| !value.is_empty())
+        .and_then(|value| HeaderValue::from_str(value).ok());
+
+    if !transport.uses_cookie() {
+        headers.remove(header::SET_COOKIE);
+        for cookie in cookies {
+            if session_cookie_value(&cookie).is_none() {
+                headers.append(header::SET_COOKIE, cookie);
+            }
+        }
+    }
+
+    if let Some(token) = token {
+        headers.insert(SESSION_TOKEN_HEADER, token);
+        // Cross-origin scripts can only read response headers they are told about
+        headers.append(
+            header::ACCESS_CONTROL_EXPOSE_HEADERS,
+            HeaderValue::from_static(SESSION_TOKEN_HEADER),
+        );
+    }
+}
+
+pub async fn carry_session_id(
+    State(transport): State<SessionTransport>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    rewrite_request_cookies(transport, request.headers_mut());
+    let mut response = next.run(request).await;
+    move_response_cookie(transport, response.headers_mut());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, http::StatusCode};
+    use domain::{Email, LoginPolicy, User, UserService};
+    use infra::db::{ConnectionSettings, DatabaseConfig, create_pool};
+    use infra::factory::{
+        build_api_key_repository, build_audit_log_repository, build_session_store,
+        build_user_repository,
+    };
+    use infra::session_store::DegradingSessionStore;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    use crate::auth::{PasswordHashPolicy, setup_auth_layer};
+    use crate::config::Config;
+    use crate::state::AppState;
+
+    async fn app(transport: SessionTransport) -> Router {
+        let db_config = DatabaseConfig {
+            url: "sqlite::memory:".to_string(),
+            max_connections: 1,
+            min_connections: 1,
+            acquire_timeout: std::time::Duration::from_secs(5),
+        };
+        let pool = create_pool(db_config, &ConnectionSettings::default())
+            .await
+            .unwrap();
+        infra::run_migrations(&pool).await.unwrap();
+
+        let password_policy = PasswordHashPolicy {
+            memory_kib: 8,
+            iterations: 1,
+            parallelism: 1,
+            rehash_on_login: false,
+        };
+        let user_repo = build_user_repository(&pool).await.unwrap();
+        let mut user = User::new("local|1", Email::try_from("user@example.com").unwrap()).unwrap();
+        user.password_hash = Some(password_policy.hash("secret1").unwrap());
+        user_repo.save(&user).await.unwrap();
+
+        let config = Config::default();
+        let store = DegradingSessionStore::new(build_session_store(&pool).await.unwrap());
+        let session_layer = crate::session::session_layer(store, &config).unwrap();
+        let auth_layer = setup_auth_layer(
+            session_layer,
+            user_repo.clone(),
+            LoginPolicy::default(),
+            password_policy,
+            Arc::new(infra::LoggingEventPublisher),
+        )
+        .await
+        .unwrap();
+
+        let user_service = UserService::new(
+            user_repo,
+            build_api_key_repository(&pool).await.unwrap(),
+            build_audit_log_repository(&pool).await.unwrap(),
+        );
+        Router::new()
+            .nest("/auth", crate::routes::auth::router(Default::default()))
+            .layer(auth_layer)
+            .layer(axum::middleware::from_fn_with_state(
+                transport,
+                carry_session_id,
+            ))
+            .with_state(AppState::new(user_service, config))
+    }
+
+    async fn login(app: &Router) -> Response {
+        let request = Request::post("/auth/login")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                r#"{"email":"user@example.com","password":"secret1"}"#,
+            ))
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap()
+    }
+
+    async fn me_status(app: &Router, header: (header::HeaderName, String)) -> StatusCode {
+        let request = Request::post("/auth/me")
+            .header(header.0, header.1)
+            .body(Body::empty())
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_header_mode_login_returns_token_accepted_via_authorization() {
+        let app = app(SessionTransport::Header).await;
+
+        let response = login(&app).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(header::SET_COOKIE).is_none());
+        let token = response.headers()[SESSION_TOKEN_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let authorization = (header::AUTHORIZATION, format!("Session {}", token));
+        assert_eq!(me_status(&app, authorization).await, StatusCode::OK);
+
+        // The cookie transport is off
+        let cookie = (header::COOKIE, format!("{}={}", SESSION_COOKIE, token));
+        assert_eq!(me_status(&app, cookie).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_cookie_mode_ignores_session_header() {
+        let app = app(SessionTransport::Cookie).await;
+
+        let response = login(&app).await;
+        assert!(response.headers().get(SESSION_TOKEN_HEADER).is_none());
+        let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        let pair = cookie.split(';').next().unwrap().to_string();
+        let token = pair.split_once('=').unwrap().1.to_string();
+
+        assert_eq!(
+            me_status(&app, (header::COOKIE, pair)).await,
+            StatusCode::OK
+        );
+        let authorization = (header::AUTHORIZATION, format!("Session {}", token));
+        assert_eq!(
+            me_status(&app, authorization).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn test_both_mode_sets_cookie_and_token() {
+        let app = app(SessionTransport::Both).await;
+
+        let response = login(&app).await;
+        assert!(response.headers().get(header::SET_COOKIE).is_some());
+        let token = response.headers()[SESSION_TOKEN_HEADER].to_str().unwrap();
+
+        let authorization = (header::AUTHORIZATION, format!("Session {}", token));
+        assert_eq!(me_status(&app, authorization).await, StatusCode::OK);
+    }
+
+    #[test]
+    fn test_session_transport_parses_config_values() {
+        assert_eq!("cookie".parse(), Ok(SessionTransport::Cookie));
+        assert_eq!("header".parse(), Ok(SessionTransport::Header));
+        assert_eq!("both".parse(), Ok(SessionTransport::Both));
+        assert!("bearer".parse::<SessionTransport>().is_err());
+    }
+}
@@ -0,0 +1,100 @@
+//! Timestamp format negotiation
+//!
+//! Clients may override the configured format per request with an `Accept`
+//! parameter, e.g. `Accept: application/json; timestamps=epoch_millis`. The
+//! display zone is fixed by configuration.
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, header},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::timestamps::{self, TimestampFormat, TimestampStyle};
+
+/// Serialize response timestamps in the requested or default format
+pub async fn timestamp_format(
+    State(default): State<TimestampStyle>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let style = TimestampStyle {
+        format: requested_format(request.headers()).unwrap_or(default.format),
+        ..default
+    };
+    timestamps::scope(style, next.run(request)).await
+}
+
+fn requested_format(headers: &HeaderMap) -> Option<TimestampFormat> {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split([',', ';']))
+        .filter_map(|param| param.trim().split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("timestamps"))
+        .and_then(|(_, value)| value.trim().trim_matches('"').parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dto::UserResponse;
+    use axum::{Json, Router, body::Body, routing::get};
+    use chrono::{TimeZone, Utc};
+    use tower::ServiceExt;
+    use uuid::Uuid;
+
+    fn app(default: TimestampFormat) -> Router {
+        Router::new()
+            .route(
+                "/user",
+                get(|| async {
+                    Json(UserResponse {
+                        id: Uuid::nil(),
+                        email: "user@example.com".to_string(),
+                        created_at: Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap(),
+                    })
+                }),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                TimestampStyle::from(default),
+                timestamp_format,
+            ))
+    }
+
+    async fn created_at(app: Router, accept: Option<&str>) -> serde_json::Value {
+        let mut request = Request::get("/user");
+        if let Some(accept) = accept {
+            request = request.header(header::ACCEPT, accept);
+        }
+        let response = app
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice::<serde_json::Value>(&body).unwrap()["created_at"].clone()
+    }
+
+    #[tokio::test]
+    async fn test_accept_parameter_overrides_default() {
+        let app = app(TimestampFormat::Rfc3339);
+        assert_eq!(
+            created_at(app.clone(), None).await,
+            "2024-01-02T03:04:05.000Z"
+        );
+        assert_eq!(
+            created_at(app, Some("application/json; timestamps=epoch_millis")).await,
+            1_704_164_645_000_i64
+        );
+    }
+
+    #[tokio::test]
+    async fn test_configured_default_applies() {
+        let app = app(TimestampFormat::EpochMillis);
+        assert_eq!(created_at(app, None).await, 1_704_164_645_000_i64);
+    }
+}
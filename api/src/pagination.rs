@@ -0,0 +1,273 @@
+//! Pagination
+//!
+//! Page query parameters and a paginated response body with navigation links.
+//! List endpoints take [`PageParams`] as an extractor and answer with
+//! [`Paginated`], so they agree on defaults, limits and link format.
+
+use std::convert::Infallible;
+
+use axum::extract::{FromRequestParts, Query};
+use axum::http::Uri;
+use axum::http::request::Parts;
+use serde::Serialize;
+
+/// Maximum page size a client may request
+pub const MAX_PER_PAGE: u32 = 100;
+
+fn default_page() -> u32 {
+    1
+}
+
+fn default_per_page() -> u32 {
+    20
+}
+
+/// Page query parameters (`?page=2&per_page=20`, or `?cursor=...`)
+///
+/// As an extractor it never rejects a request: missing or unparsable values
+/// take their defaults and out-of-range ones are clamped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageParams {
+    pub page: u32,
+    pub per_page: u32,
+    /// Opaque position for endpoints that page by key instead of by number;
+    /// page-numbered links leave it out
+    pub cursor: Option<String>,
+}
+
+impl Default for PageParams {
+    fn default() -> Self {
+        Self {
+            page: default_page(),
+            per_page: default_per_page(),
+            cursor: None,
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for PageParams {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_uri(&parts.uri))
+    }
+}
+
+impl PageParams {
+    /// Read the parameters from a request URI, normalized
+    pub fn from_uri(uri: &Uri) -> Self {
+        let pairs = Query::<Vec<(String, String)>>::try_from_uri(uri)
+            .map(|Query(pairs)| pairs)
+            .unwrap_or_default();
+        let value = |key: &str| {
+            pairs
+                .iter()
+                .rev()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.trim())
+        };
+        // i128 so negative and oversized numbers still parse, then clamp
+        let number = |key: &str, default: u32| {
+            value(key)
+                .and_then(|v| v.parse::<i128>().ok())
+                .map(|n| n.clamp(0, u32::MAX as i128) as u32)
+                .unwrap_or(default)
+        };
+
+        Self {
+            page: number("page", default_page()),
+            per_page: number("per_page", default_per_page()),
+            cursor: value("cursor")
+                .filter(|cursor| !cursor.is_empty())
+                .map(str::to_string),
+        }
+        .normalized()
+    }
+
+    /// Clamp to a valid page and page size
+    pub fn normalized(self) -> Self {
+        Self {
+            page: self.page.max(1),
+            per_page: self.per_page.clamp(1, MAX_PER_PAGE),
+            cursor: self.cursor,
+        }
+    }
+
+    pub fn offset(&self) -> usize {
+        (self.page.saturating_sub(1) as usize) * self.per_page as usize
+    }
+
+    pub fn limit(&self) -> usize {
+        self.per_page as usize
+    }
+}
+
+/// Page metadata
+#[derive(Debug, Serialize)]
+pub struct PageMeta {
+    pub page: u32,
+    pub per_page: u32,
+    pub total: u64,
+    pub total_pages: u32,
+}
+
+/// Navigation links; `next`/`prev` are null at the boundaries
+#[derive(Debug, Serialize)]
+pub struct PageLinks {
+    #[serde(rename = "self")]
+    pub self_link: String,
+    pub next: Option<String>,
+    pub prev: Option<String>,
+}
+
+/// A page of items
+#[derive(Debug, Serialize)]
+pub struct Paginated<T> {
+    pub items: Vec<T>,
+    pub meta: PageMeta,
+    pub links: PageLinks,
+}
+
+impl<T> Paginated<T> {
+    /// Build a page, deriving links from the request URI
+    pub fn new(items: Vec<T>, params: PageParams, total: u64, uri: &Uri) -> Self {
+        let params = params.normalized();
+        let total_pages = total.div_ceil(params.per_page as u64).max(1) as u32;
+
+        let link = |page: u32| page_url(uri, page, params.per_page);
+        let links = PageLinks {
+            self_link: link(params.page),
+            next: (params.page < total_pages).then(|| link(params.page + 1)),
+            prev: (params.page > 1).then(|| link(params.page - 1)),
+        };
+
+        Self {
+            items,
+            meta: PageMeta {
+                page: params.page,
+                per_page: params.per_page,
+                total,
+                total_pages,
+            },
+            links,
+        }
+    }
+
+    /// Paginate an already loaded collection
+    pub fn from_vec(items: Vec<T>, params: PageParams, uri: &Uri) -> Self {
+        let params = params.normalized();
+        let total = items.len() as u64;
+        let items = items
+            .into_iter()
+            .skip(params.offset())
+            .take(params.limit())
+            .collect();
+
+        Self::new(items, params, total, uri)
+    }
+}
+
+/// Rebuild the request URL for another page, keeping unrelated query parameters
+fn page_url(uri: &Uri, page: u32, per_page: u32) -> String {
+    let mut query: Vec<String> = uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .filter(|pair| {
+            let key = pair.split('=').next().unwrap_or_default();
+            !matches!(key, "page" | "per_page" | "cursor")
+        })
+        .map(str::to_string)
+        .collect();
+
+    query.push(format!("page={}", page));
+    query.push(format!("per_page={}", per_page));
+
+    format!("{}?{}", uri.path(), query.join("&"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_middle_page_has_next_and_prev() {
+        let uri: Uri = "/api/v2/api-keys?sort=desc&page=2&per_page=10"
+            .parse()
+            .unwrap();
+        let params = PageParams {
+            page: 2,
+            per_page: 10,
+            cursor: None,
+        };
+
+        let page = Paginated::new(vec![(); 10], params, 30, &uri);
+
+        assert_eq!(page.meta.total_pages, 3);
+        assert_eq!(
+            page.links.self_link,
+            "/api/v2/api-keys?sort=desc&page=2&per_page=10"
+        );
+        assert_eq!(
+            page.links.next.as_deref(),
+            Some("/api/v2/api-keys?sort=desc&page=3&per_page=10")
+        );
+        assert_eq!(
+            page.links.prev.as_deref(),
+            Some("/api/v2/api-keys?sort=desc&page=1&per_page=10")
+        );
+    }
+
+    fn params(uri: &str) -> PageParams {
+        PageParams::from_uri(&uri.parse().unwrap())
+    }
+
+    #[test]
+    fn test_missing_params_take_defaults() {
+        assert_eq!(params("/api/v1/users"), PageParams::default());
+        assert_eq!(params("/api/v1/users?q=alice"), PageParams::default());
+    }
+
+    #[test]
+    fn test_out_of_range_params_are_clamped() {
+        let clamped = params("/api/v1/users?page=0&per_page=1000");
+        assert_eq!((clamped.page, clamped.per_page), (1, MAX_PER_PAGE));
+
+        let clamped = params("/api/v1/users?page=-3&per_page=-1");
+        assert_eq!((clamped.page, clamped.per_page), (1, 1));
+
+        let huge = params("/api/v1/users?page=99999999999999999999");
+        assert_eq!(huge.page, u32::MAX);
+    }
+
+    #[test]
+    fn test_invalid_params_fall_back_to_defaults() {
+        let fallback = params("/api/v1/users?page=two&per_page=&cursor=");
+        assert_eq!(fallback, PageParams::default());
+
+        let cursor = params("/api/v1/users?cursor=abc%3D%3D&page=%202%20");
+        assert_eq!(cursor.cursor.as_deref(), Some("abc=="));
+        assert_eq!(cursor.page, 2);
+    }
+
+    #[test]
+    fn test_links_drop_the_cursor() {
+        let uri: Uri = "/api/v1/users?cursor=abc&q=a".parse().unwrap();
+        let page = Paginated::new(vec![(); 20], PageParams::from_uri(&uri), 40, &uri);
+        assert_eq!(
+            page.links.next.as_deref(),
+            Some("/api/v1/users?q=a&page=2&per_page=20")
+        );
+    }
+
+    #[test]
+    fn test_boundaries_have_no_links() {
+        let uri: Uri = "/api/v2/api-keys".parse().unwrap();
+        let page = Paginated::from_vec(vec![1, 2, 3], PageParams::default(), &uri);
+
+        assert_eq!(page.items, vec![1, 2, 3]);
+        assert!(page.links.next.is_none());
+        assert!(page.links.prev.is_none());
+    }
+}
@@ -0,0 +1,77 @@
+//! Per-client rate limiting
+//!
+//! A fixed window per client IP, for endpoints that are cheap to call but
+//! leak something when called in bulk. Only the most recently seen clients are
+//! kept, so memory stays bounded no matter how many addresses show up.
+
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use hashlink::LruCache;
+
+/// Allows `limit` requests per client in each `window`
+pub struct RateLimiter {
+    clients: Mutex<LruCache<IpAddr, (Instant, u32)>>,
+    limit: u32,
+    window: Duration,
+}
+
+impl RateLimiter {
+    /// Track up to `max_clients` addresses
+    pub fn new(limit: u32, window: Duration, max_clients: usize) -> Self {
+        Self {
+            clients: Mutex::new(LruCache::new(max_clients.max(1))),
+            limit,
+            window,
+        }
+    }
+
+    /// Count a request from `ip`; over the limit, fails with the seconds until its window resets
+    pub fn check(&self, ip: IpAddr) -> Result<(), u64> {
+        self.check_at(ip, Instant::now())
+    }
+
+    fn check_at(&self, ip: IpAddr, now: Instant) -> Result<(), u64> {
+        let mut clients = self.clients.lock().unwrap();
+        let (started, count) = match clients.get(&ip) {
+            Some(&(started, count)) if now.saturating_duration_since(started) < self.window => {
+                (started, count)
+            }
+            _ => (now, 0),
+        };
+
+        if count >= self.limit {
+            let reset = self.window - now.saturating_duration_since(started);
+            return Err(reset.as_secs_f64().ceil() as u64);
+        }
+        clients.insert(ip, (started, count + 1));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn test_limit_applies_per_client_until_the_window_resets() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60), 100);
+        let start = Instant::now();
+
+        assert!(limiter.check_at(ip("203.0.113.1"), start).is_ok());
+        assert!(limiter.check_at(ip("203.0.113.1"), start).is_ok());
+        assert_eq!(
+            limiter.check_at(ip("203.0.113.1"), start + Duration::from_secs(15)),
+            Err(45)
+        );
+        assert!(limiter.check_at(ip("203.0.113.2"), start).is_ok());
+
+        let later = start + Duration::from_secs(60);
+        assert!(limiter.check_at(ip("203.0.113.1"), later).is_ok());
+    }
+}
@@ -0,0 +1,105 @@
+//! Post-login redirect targets
+//!
+//! A `?next=` parameter is attacker-controlled, so it is only followed when it
+//! stays on this site (a relative path) or points at an allowlisted host.
+//! Anything else is rejected rather than silently rewritten, so a broken link
+//! is noticed.
+
+use axum::http::Uri;
+
+use crate::error::ApiError;
+
+/// Where to go when no target is given
+pub const DEFAULT_REDIRECT: &str = "/";
+
+/// Validate a redirect target, or fall back to [`DEFAULT_REDIRECT`].
+///
+/// Accepts paths like `/settings?tab=2` and `http(s)` URLs whose host exactly
+/// matches an `allowed_hosts` entry (case-insensitively; ports ignored).
+pub fn resolve(next: Option<&str>, allowed_hosts: &[String]) -> Result<String, ApiError> {
+    let Some(next) = next.filter(|next| !next.is_empty()) else {
+        return Ok(DEFAULT_REDIRECT.to_string());
+    };
+
+    if is_allowed(next, allowed_hosts) {
+        Ok(next.to_string())
+    } else {
+        Err(ApiError::invalid_field(
+            "next",
+            "Redirect target is not allowed",
+        ))
+    }
+}
+
+fn is_allowed(next: &str, allowed_hosts: &[String]) -> bool {
+    // Browsers read `\` as `/`, turning `/\evil.com` into `//evil.com`
+    if next.contains('\\') || next.chars().any(|c| c.is_ascii_control()) {
+        return false;
+    }
+
+    if next.starts_with('/') {
+        // `//evil.com` is protocol-relative, i.e. another site
+        return !next.starts_with("//");
+    }
+
+    let Ok(uri) = next.parse::<Uri>() else {
+        return false;
+    };
+    let web = matches!(uri.scheme_str(), Some("http" | "https"));
+    web && uri.host().is_some_and(|host| {
+        allowed_hosts
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(host))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hosts() -> Vec<String> {
+        vec!["app.example.com".to_string()]
+    }
+
+    #[test]
+    fn test_relative_paths_are_allowed() {
+        for next in ["/", "/settings", "/search?q=a&page=2", "/a#section"] {
+            assert_eq!(resolve(Some(next), &[]).unwrap(), next);
+        }
+        assert_eq!(resolve(None, &[]).unwrap(), DEFAULT_REDIRECT);
+        assert_eq!(resolve(Some(""), &[]).unwrap(), DEFAULT_REDIRECT);
+    }
+
+    #[test]
+    fn test_allowlisted_hosts_are_allowed() {
+        for next in [
+            "https://app.example.com/dashboard",
+            "http://APP.example.com:8080/",
+        ] {
+            assert_eq!(resolve(Some(next), &hosts()).unwrap(), next);
+        }
+    }
+
+    #[test]
+    fn test_external_urls_are_rejected() {
+        for next in [
+            "https://evil.com/",
+            "https://app.example.com.evil.com/",
+            "https://evil.com@app.example.com.evil.com/",
+            "//evil.com",
+            "/\\evil.com",
+            "javascript:alert(1)",
+            "ftp://app.example.com/",
+            "evil.com",
+            "/\tfoo",
+        ] {
+            assert!(
+                matches!(
+                    resolve(Some(next), &hosts()),
+                    Err(ApiError::InvalidFields(_))
+                ),
+                "{next} was allowed"
+            );
+        }
+    }
+}
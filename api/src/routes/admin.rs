@@ -0,0 +1,429 @@
+use axum::body::{Body, Bytes};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::{
+    Router,
+    extract::{OriginalUri, Path, Query, State},
+    response::IntoResponse,
+    routing::{get, post},
+};
+use domain::{User, UserFilter};
+use futures_util::{TryStreamExt, stream};
+use uuid::Uuid;
+
+use crate::{
+    auth::IMPERSONATOR_KEY,
+    client_ip::ClientIp,
+    dto::{
+        AbuseQuery, AuditEntryResponse, AuditLogQuery, ClientTrafficResponse, InviteResponse,
+        MeResponse, SessionQuery, SessionResponse, UserResponse, UserSearchQuery,
+    },
+    error::ApiError,
+    json::ApiJson,
+    pagination::{MAX_PER_PAGE, PageParams, Paginated},
+    session::{self, record_login},
+    state::AppState,
+    timestamps::{self, TimestampStyle},
+};
+
+/// Users fetched per query while streaming an export
+const EXPORT_PAGE_SIZE: u32 = 500;
+
+/// Admin routes; every route is guarded by [`admin_only`](crate::auth::admin_only)
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/abuse", get(top_talkers))
+        .route("/audit", get(list_audit_log))
+        .route("/invites", get(list_invites).post(create_invite))
+        .route("/sessions", get(list_sessions))
+        .route("/users", get(search_users))
+        .route("/users/export.jsonl", get(export_users))
+        .route("/users/{id}/impersonate", post(impersonate))
+        .route_layer(axum::middleware::from_fn(crate::auth::admin_only))
+}
+
+async fn impersonate(
+    State(state): State<AppState>,
+    ip: ClientIp,
+    headers: HeaderMap,
+    mut auth_session: crate::auth::AuthSession,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    let admin = auth_session
+        .user
+        .clone()
+        .ok_or(ApiError::Unauthorized("Not logged in".to_string()))?;
+
+    let target = state
+        .user_service
+        .start_impersonation(admin.0.id, id)
+        .await?;
+
+    session::login(
+        &mut auth_session,
+        &crate::auth::AuthUser::new(target.clone()),
+    )
+    .await?;
+    record_login(
+        &state.user_service,
+        &auth_session.session,
+        target.id,
+        ip,
+        &headers,
+    )
+    .await;
+
+    // Remember the real admin so the session can be restored
+    auth_session
+        .session
+        .insert(IMPERSONATOR_KEY, admin.0.id)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+
+    Ok(ApiJson(MeResponse {
+        display_name: target.display_name.clone(),
+        user: UserResponse::from(target),
+        impersonated_by: Some(admin.0.id),
+        csrf_token: None,
+        // The admin's own claims were dropped by the login above
+        claims: Default::default(),
+    }))
+}
+
+/// Busiest clients in the abuse window, by request count
+async fn top_talkers(
+    State(state): State<AppState>,
+    Query(query): Query<AbuseQuery>,
+) -> ApiJson<Vec<ClientTrafficResponse>> {
+    let limit = query.limit.unwrap_or(20).clamp(1, MAX_PER_PAGE);
+    let talkers = state.abuse_monitor.top_talkers(limit as usize);
+    ApiJson(
+        talkers
+            .into_iter()
+            .map(ClientTrafficResponse::from)
+            .collect(),
+    )
+}
+
+async fn list_audit_log(
+    State(state): State<AppState>,
+    OriginalUri(uri): OriginalUri,
+    params: PageParams,
+    Query(query): Query<AuditLogQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let (entries, total) = state
+        .user_service
+        .search_audit_log(&query.into(), params.per_page, params.offset() as u32)
+        .await?;
+
+    let mut ids: Vec<Uuid> = entries
+        .iter()
+        .flat_map(|entry| std::iter::once(entry.actor_id).chain(entry.target_id))
+        .collect();
+    ids.sort_unstable();
+    ids.dedup();
+    let users = state.user_service.get_many(&ids).await?;
+
+    let entries = entries
+        .into_iter()
+        .map(|entry| AuditEntryResponse::new(entry, &users))
+        .collect();
+    Ok(ApiJson(Paginated::new(entries, params, total, &uri)))
+}
+
+/// Mint a single-use invite code
+async fn create_invite(
+    State(state): State<AppState>,
+    auth_session: crate::auth::AuthSession,
+) -> Result<impl IntoResponse, ApiError> {
+    let admin = auth_session
+        .user
+        .ok_or(ApiError::Unauthorized("Not logged in".to_string()))?;
+
+    let invite = state.user_service.create_invite(admin.0.id).await?;
+    Ok((StatusCode::CREATED, ApiJson(InviteResponse::from(invite))))
+}
+
+/// Invite codes, used or not, newest first
+async fn list_invites(
+    State(state): State<AppState>,
+    OriginalUri(uri): OriginalUri,
+    params: PageParams,
+) -> Result<impl IntoResponse, ApiError> {
+    let (invites, total) = state
+        .user_service
+        .list_invites(params.per_page, params.offset() as u32)
+        .await?;
+
+    let invites = invites.into_iter().map(InviteResponse::from).collect();
+    Ok(ApiJson(Paginated::new(invites, params, total, &uri)))
+}
+
+/// Active sessions across all users, newest first
+async fn list_sessions(
+    State(state): State<AppState>,
+    OriginalUri(uri): OriginalUri,
+    params: PageParams,
+    Query(query): Query<SessionQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let (sessions, total) = state
+        .user_service
+        .list_sessions(&query.into(), params.per_page, params.offset() as u32)
+        .await?;
+
+    let sessions = sessions.into_iter().map(SessionResponse::from).collect();
+    Ok(ApiJson(Paginated::new(sessions, params, total, &uri)))
+}
+
+async fn search_users(
+    State(state): State<AppState>,
+    OriginalUri(uri): OriginalUri,
+    params: PageParams,
+    Query(query): Query<UserSearchQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let (users, total) = state
+        .user_service
+        .search_users(
+            &query.q,
+            query.match_mode,
+            params.per_page,
+            params.offset() as u32,
+        )
+        .await?;
+
+    let users = users
+        .into_iter()
+        .map(|user| UserResponse {
+            id: user.id,
+            email: user.email.into_inner(),
+            created_at: user.created_at,
+        })
+        .collect();
+    Ok(ApiJson(Paginated::new(users, params, total, &uri)))
+}
+
+/// Every user as JSON Lines, one [`UserResponse`] per line.
+///
+/// Pages are only fetched as the client reads, so memory stays bounded by
+/// one page however many users there are. An error after the headers went
+/// out can't change the status; it is logged and the body is cut short.
+async fn export_users(State(state): State<AppState>) -> impl IntoResponse {
+    // The body is polled after the handler returns, outside the request's scope
+    let style = timestamps::current();
+    let pages = stream::try_unfold(Some(0), move |offset: Option<u32>| {
+        let state = state.clone();
+        async move {
+            let Some(offset) = offset else {
+                return Ok(None);
+            };
+            let users = state
+                .user_service
+                .find_users(&UserFilter::default(), EXPORT_PAGE_SIZE, offset)
+                .await?;
+            if users.is_empty() {
+                return Ok(None);
+            }
+
+            let next =
+                (users.len() == EXPORT_PAGE_SIZE as usize).then(|| offset + EXPORT_PAGE_SIZE);
+            Ok(Some((json_lines(users, style)?, next)))
+        }
+    })
+    .inspect_err(|e: &axum::BoxError| tracing::error!(error = %e, "User export failed mid-stream"));
+
+    (
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(pages),
+    )
+}
+
+fn json_lines(users: Vec<User>, style: TimestampStyle) -> Result<Bytes, serde_json::Error> {
+    timestamps::scope_sync(style, || {
+        let mut buf = Vec::new();
+        for user in users {
+            serde_json::to_writer(&mut buf, &UserResponse::from(user))?;
+            buf.push(b'\n');
+        }
+        Ok(Bytes::from(buf))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    use crate::config::Config;
+    use crate::test_support::{build_test_app_with, test_config, test_pool};
+
+    /// Register over HTTP, returning the status and the session cookie
+    async fn register(
+        app: &Router,
+        email: &str,
+        invite_code: Option<&str>,
+    ) -> (StatusCode, Option<String>) {
+        let body = serde_json::json!({
+            "email": email,
+            "password": "correct horse",
+            "invite_code": invite_code,
+        });
+        let request = Request::post("/api/v1/auth/register")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let cookie = response.headers().get(header::SET_COOKIE).map(|cookie| {
+            let cookie = cookie.to_str().unwrap();
+            cookie.split(';').next().unwrap().to_string()
+        });
+        (response.status(), cookie)
+    }
+
+    #[tokio::test]
+    async fn test_export_streams_one_json_line_per_user() {
+        let config = Config {
+            first_user_is_admin: true,
+            ..test_config()
+        };
+        let (app, state) = build_test_app_with(test_pool().await, config).await;
+
+        // The first account becomes an admin and is logged in by registering
+        let (status, cookie) = register(&app, "admin@example.com", None).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let cookie = cookie.unwrap();
+
+        // Enough for more than one page
+        let seeded = EXPORT_PAGE_SIZE as usize + 5;
+        for i in 0..seeded {
+            state
+                .user_service
+                .find_or_create(&format!("oidc|{}", i), &format!("user{}@example.com", i))
+                .await
+                .unwrap();
+        }
+
+        let export = Request::get("/api/v1/admin/users/export.jsonl")
+            .header(header::COOKIE, cookie)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(export).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/x-ndjson"
+        );
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        let lines: Vec<serde_json::Value> = std::str::from_utf8(&bytes)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), seeded + 1);
+        let mut ids: Vec<&str> = lines.iter().map(|u| u["id"].as_str().unwrap()).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), seeded + 1);
+        assert!(lines.iter().all(|u| u["created_at"].is_string()));
+    }
+
+    #[tokio::test]
+    async fn test_sessions_are_listed_to_admins_only() {
+        let config = Config {
+            first_user_is_admin: true,
+            ..test_config()
+        };
+        let (app, _) = build_test_app_with(test_pool().await, config).await;
+        let (status, admin) = register(&app, "admin@example.com", None).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, alice) = register(&app, "alice@example.com", None).await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let list = |cookie: Option<String>| {
+            let mut request = Request::get("/api/v1/admin/sessions");
+            if let Some(cookie) = cookie {
+                request = request.header(header::COOKIE, cookie);
+            }
+            app.clone().oneshot(request.body(Body::empty()).unwrap())
+        };
+
+        let response = list(admin).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(page["items"].as_array().unwrap().len(), 2);
+        assert_eq!(page["meta"]["total"], 2);
+
+        let response = list(alice).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = list(None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_invite_code_registers_once() {
+        let config = Config {
+            first_user_is_admin: true,
+            require_invite_code: true,
+            ..test_config()
+        };
+        let (app, state) = build_test_app_with(test_pool().await, config).await;
+
+        let (status, _) = register(&app, "uninvited@example.com", None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        // Bootstrap the admin with a code minted out of band
+        let bootstrap = state.user_service.create_invite(Uuid::nil()).await.unwrap();
+        let (status, cookie) = register(&app, "admin@example.com", Some(&bootstrap.code)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let cookie = cookie.unwrap();
+
+        let mint = Request::post("/api/v1/admin/invites")
+            .header(header::COOKIE, &cookie)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(mint).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let invite: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        let code = invite["code"].as_str().unwrap().to_string();
+        assert!(invite["used_by"].is_null());
+
+        let (status, _) = register(&app, "alice@example.com", Some(&code)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (status, _) = register(&app, "bob@example.com", Some(&code)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(
+            state
+                .user_service
+                .find_by_email("bob@example.com")
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let list = Request::get("/api/v1/admin/invites")
+            .header(header::COOKIE, &cookie)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(list).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        let used = page["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|invite| !invite["used_by"].is_null())
+            .count();
+        assert_eq!(used, 2);
+    }
+}
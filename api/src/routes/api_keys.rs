@@ -0,0 +1,174 @@
+use axum::http::{HeaderValue, StatusCode, header};
+use axum::{
+    Router,
+    extract::{OriginalUri, Path, State},
+    response::IntoResponse,
+    routing::{MethodRouter, delete, get},
+};
+use uuid::Uuid;
+
+use crate::{
+    auth::ApiKeyAuth,
+    dto::{ApiKeyResponse, CreateApiKeyRequest, CreateApiKeyResponse, UserResponse},
+    error::ApiError,
+    json::ApiJson,
+    pagination::{PageParams, Paginated},
+    state::AppState,
+};
+
+use super::API_V2_PREFIX;
+
+/// v1, listing keys as a plain array
+pub fn router() -> Router<AppState> {
+    routes(get(list_api_keys))
+}
+
+/// v2, listing keys as a [`Paginated`] page
+pub fn router_v2() -> Router<AppState> {
+    routes(get(list_api_keys_page))
+}
+
+fn routes(list: MethodRouter<AppState>) -> Router<AppState> {
+    Router::new()
+        .route("/", list.post(create_api_key))
+        .route("/me", get(me))
+        .route("/{id}", delete(revoke_api_key))
+}
+
+async fn user_api_keys(
+    state: &AppState,
+    auth_session: crate::auth::AuthSession,
+) -> Result<Vec<ApiKeyResponse>, ApiError> {
+    let user = auth_session
+        .user
+        .ok_or(ApiError::Unauthorized("Not logged in".to_string()))?;
+
+    let keys = state.user_service.list_api_keys(user.0.id).await?;
+    Ok(keys
+        .into_iter()
+        .map(|key| ApiKeyResponse {
+            id: key.id,
+            created_at: key.created_at,
+            expires_at: key.expires_at,
+            last_used_at: key.last_used_at,
+        })
+        .collect())
+}
+
+/// Deprecated in favour of the v2 page, which clients are pointed to
+async fn list_api_keys(
+    State(state): State<AppState>,
+    auth_session: crate::auth::AuthSession,
+) -> Result<impl IntoResponse, ApiError> {
+    let keys = user_api_keys(&state, auth_session).await?;
+    let successor = format!("<{}/api-keys>; rel=\"successor-version\"", API_V2_PREFIX);
+
+    Ok((
+        [
+            (
+                header::HeaderName::from_static("deprecation"),
+                HeaderValue::from_static("true"),
+            ),
+            (
+                header::LINK,
+                HeaderValue::from_str(&successor).expect("static link header"),
+            ),
+        ],
+        ApiJson(keys),
+    ))
+}
+
+async fn list_api_keys_page(
+    State(state): State<AppState>,
+    auth_session: crate::auth::AuthSession,
+    OriginalUri(uri): OriginalUri,
+    params: PageParams,
+) -> Result<impl IntoResponse, ApiError> {
+    let keys = user_api_keys(&state, auth_session).await?;
+    Ok(ApiJson(Paginated::from_vec(keys, params, &uri)))
+}
+
+async fn create_api_key(
+    State(state): State<AppState>,
+    auth_session: crate::auth::AuthSession,
+    ApiJson(payload): ApiJson<CreateApiKeyRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let user = auth_session
+        .user
+        .ok_or(ApiError::Unauthorized("Not logged in".to_string()))?;
+
+    let (id, key) = state
+        .user_service
+        .create_api_key(user.0.id, payload.expires_at)
+        .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        ApiJson(CreateApiKeyResponse {
+            id,
+            key,
+            expires_at: payload.expires_at,
+        }),
+    ))
+}
+
+async fn revoke_api_key(
+    State(state): State<AppState>,
+    auth_session: crate::auth::AuthSession,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    let user = auth_session
+        .user
+        .ok_or(ApiError::Unauthorized("Not logged in".to_string()))?;
+
+    state.user_service.revoke_api_key(user.0.id, id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Resolve the user behind the presented API key
+async fn me(ApiKeyAuth(user): ApiKeyAuth) -> ApiJson<UserResponse> {
+    ApiJson(UserResponse {
+        id: user.id,
+        email: user.email.into_inner(),
+        created_at: user.created_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{TestClient, build_test_app};
+    use axum::http::{StatusCode, header};
+
+    #[tokio::test]
+    async fn test_v1_lists_keys_as_before_and_points_to_v2() {
+        let (app, _) = build_test_app().await;
+        let mut client = TestClient::new(app);
+        client
+            .post_json(
+                "/api/v1/auth/register",
+                serde_json::json!({"email": "alice@example.com", "password": "correct horse"}),
+            )
+            .await;
+        let created = client
+            .post_json("/api/v1/api-keys", serde_json::json!({}))
+            .await;
+        assert_eq!(created.status, StatusCode::CREATED);
+
+        let v1 = client.get("/api/v1/api-keys").await;
+        assert_eq!(v1.status, StatusCode::OK);
+        assert_eq!(v1.json().as_array().unwrap().len(), 1);
+        assert_eq!(v1.headers["deprecation"], "true");
+        assert_eq!(
+            v1.headers[header::LINK],
+            "</api/v2/api-keys>; rel=\"successor-version\""
+        );
+
+        let v2 = client.get("/api/v2/api-keys").await;
+        assert_eq!(v2.status, StatusCode::OK);
+        let page = v2.json();
+        assert_eq!(page["items"].as_array().unwrap().len(), 1);
+        assert_eq!(page["meta"]["total"], 1);
+        assert!(!v2.headers.contains_key("deprecation"));
+    }
+}
@@ -1,102 +1,650 @@
-use axum::http::StatusCode;
+use std::sync::Arc;
+
+use axum::http::{HeaderMap, StatusCode, header};
 use axum::{
     Router,
-    extract::{Json, State},
-    response::IntoResponse,
-    routing::post,
+    extract::{Extension, Query, State},
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
 };
 
+use infra::session_store::Session;
+use uuid::Uuid;
+use validator::Validate;
+
 use crate::{
-    dto::{LoginRequest, RegisterRequest, UserResponse},
-    error::ApiError,
+    auth::{
+        Claims, IMPERSONATOR_KEY, MAX_2FA_ATTEMPTS, OIDC_ATTEMPT_KEY, OIDC_INVITE_KEY,
+        OIDC_NEXT_KEY, PENDING_2FA_ATTEMPTS_KEY, PENDING_2FA_CLAIMS_KEY, PENDING_2FA_KEY,
+        PENDING_2FA_NEXT_KEY, PENDING_2FA_REMEMBER_KEY,
+    },
+    client_ip::ClientIp,
+    dto::{
+        ChangePasswordRequest, ConfirmPasswordResetRequest, EmailAvailableQuery,
+        EmailAvailableResponse, LoginRequest, MeResponse, OidcCallbackQuery, OidcLoginQuery,
+        PasswordResetRequest, ProfilePatch, RedirectQuery, RegisterRequest, RegisterResponse,
+        ResendVerificationRequest, TotpCodeRequest, TotpEnrollmentResponse,
+        TwoFactorRequiredResponse, UserResponse, VerifyEmailRequest,
+    },
+    error::{ApiError, REQUEST_FIELD},
+    json::ApiJson,
+    middleware::csrf::CsrfToken,
+    routes::users,
+    session::{self, record_login, remember},
     state::AppState,
 };
-use domain::{DomainError, Email};
+use domain::{
+    AuthMode, DomainError, Email, LoginCommand, LoginPolicy, NewUserCommand, OidcLoginAttempt,
+    OidcProvider, Password, UpdateProfileCommand,
+};
 
-pub fn router() -> Router<AppState> {
-    Router::new()
-        .route("/login", post(login))
-        .route("/register", post(register))
+/// Media type `PATCH /auth/me` takes, as plain JSON has no way to say "remove"
+const MERGE_PATCH_JSON: &str = "application/merge-patch+json";
+
+/// Auth routes; password login, registration and email verification, or OIDC
+/// login, only exist when `auth_mode` allows them
+pub fn router(auth_mode: AuthMode) -> Router<AppState> {
+    let router = if auth_mode.allows_password() {
+        Router::new()
+            .route("/login", post(login))
+            .route("/register", post(register))
+            .route("/email-available", get(email_available))
+            .route("/password-reset", post(request_password_reset))
+            .route("/password-reset/confirm", post(confirm_password_reset))
+            .route("/resend-verification", post(resend_verification))
+            .route("/verify-email", post(verify_email))
+            .route("/password", post(change_password))
+    } else {
+        Router::new()
+    };
+    let router = if auth_mode.allows_oidc() {
+        router
+            .route("/oidc/login", get(oidc_login))
+            .route("/oidc/callback", get(oidc_callback))
+    } else {
+        router
+    };
+
+    router
         .route("/logout", post(logout))
-        .route("/me", post(me))
+        .route("/me", post(me).patch(update_profile))
+        .route("/me/preferences", get(preferences).put(update_preferences))
+        .route("/stop-impersonation", post(stop_impersonation))
+        .route("/2fa", post(verify_two_factor))
+        .route("/2fa/enroll", post(enroll_two_factor))
+        .route("/2fa/confirm", post(confirm_two_factor))
 }
 
+/// Password login; answers with the user, or with `303 See Other` to a
+/// validated `?next=` target when one is given
 async fn login(
+    State(state): State<AppState>,
+    ip: ClientIp,
+    headers: HeaderMap,
+    Query(redirect): Query<RedirectQuery>,
     mut auth_session: crate::auth::AuthSession,
-    Json(payload): Json<LoginRequest>,
-) -> Result<impl IntoResponse, ApiError> {
+    ApiJson(payload): ApiJson<LoginRequest>,
+) -> Result<Response, ApiError> {
+    // Checked before the password, so a bad link never logs anyone in
+    let next = redirect
+        .next
+        .map(|next| crate::redirect::resolve(Some(&next), &state.config.redirect_allowlist))
+        .transpose()?;
+    payload.validate()?;
+    let remember_me = payload.remember_me;
+    let command = LoginCommand::try_from(payload)?;
+    let email = command.email.clone();
+
     let user = match auth_session
         .authenticate(crate::auth::Credentials {
-            email: payload.email,
-            password: payload.password,
+            email: command.email.into_inner(),
+            password: command.password,
+            remember_me,
         })
         .await
-        .map_err(|e| ApiError::Internal(e.to_string()))?
-    {
+        .map_err(|e| match e {
+            crate::auth::AuthSessionError::Backend(crate::auth::AuthError::Domain(e)) => {
+                ApiError::Domain(e)
+            }
+            e => ApiError::Internal(e.to_string()),
+        })? {
         Some(user) => user,
-        None => return Err(ApiError::Validation("Invalid credentials".to_string())),
+        None => return Err(failed_login(&state, email.as_ref()).await),
     };
 
-    auth_session
-        .login(&user)
-        .await
-        .map_err(|_| ApiError::Internal("Login failed".to_string()))?;
+    // The password was right, but the session stays anonymous until the code is checked
+    if user.0.requires_totp() {
+        return start_two_factor(
+            &auth_session.session,
+            user.0.id,
+            remember_me,
+            next,
+            Claims::new(),
+        )
+        .await;
+    }
+
+    state.user_service.enforce_session_limit(user.0.id).await?;
+    session::login(&mut auth_session, &user).await?;
+    if remember_me {
+        remember(&auth_session.session, state.config.remember_me_days);
+    }
+    record_login(
+        &state.user_service,
+        &auth_session.session,
+        user.0.id,
+        ip,
+        &headers,
+    )
+    .await;
+
+    state.user_service.record_login(user.0.id).await?;
 
+    if let Some(next) = next {
+        return Ok(Redirect::to(&next).into_response());
+    }
     Ok((
         StatusCode::OK,
-        Json(UserResponse {
+        ApiJson(UserResponse {
             id: user.0.id,
             email: user.0.email.into_inner(),
             created_at: user.0.created_at,
         }),
-    ))
+    )
+        .into_response())
 }
 
-async fn register(
+/// Whether an email is free to register.
+///
+/// Always `200` with a boolean, and limited per client to slow down anyone
+/// probing it for accounts.
+async fn email_available(
     State(state): State<AppState>,
-    mut auth_session: crate::auth::AuthSession,
-    Json(payload): Json<RegisterRequest>,
+    ClientIp(ip): ClientIp,
+    Query(query): Query<EmailAvailableQuery>,
 ) -> Result<impl IntoResponse, ApiError> {
-    if state
+    state
+        .email_check_limiter
+        .check(ip)
+        .map_err(DomainError::RateLimited)?;
+    let email = Email::try_from(query.email)?;
+
+    let available = state.user_service.email_available(&email).await?;
+    Ok(ApiJson(EmailAvailableResponse { available }))
+}
+
+/// Error for a wrong email or password.
+///
+/// The same either way, unless `enumeration_protection` is turned off to
+/// debug, in which case it says whether the account exists.
+async fn failed_login(state: &AppState, email: &str) -> ApiError {
+    if state.config.enumeration_protection {
+        return ApiError::invalid_field(REQUEST_FIELD, "Invalid credentials");
+    }
+    match state.user_service.find_by_email(email).await {
+        Ok(Some(_)) => ApiError::invalid_field("password", "Invalid password"),
+        Ok(None) => ApiError::invalid_field("email", "No account with this email"),
+        Err(e) => e.into(),
+    }
+}
+
+/// Hold a login whose first step passed until [`verify_two_factor`] checks
+/// the TOTP code, carrying what the login would have put in the session
+async fn start_two_factor(
+    session: &Session,
+    user_id: Uuid,
+    remember_me: bool,
+    next: Option<String>,
+    claims: Claims,
+) -> Result<Response, ApiError> {
+    session
+        .insert(PENDING_2FA_KEY, user_id)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    session
+        .insert(PENDING_2FA_ATTEMPTS_KEY, 0u32)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    session
+        .insert(PENDING_2FA_REMEMBER_KEY, remember_me)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    session
+        .insert(PENDING_2FA_CLAIMS_KEY, claims)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    // Replaces any target left by an earlier, abandoned login
+    match &next {
+        Some(next) => session.insert(PENDING_2FA_NEXT_KEY, next).await,
+        None => session
+            .remove::<String>(PENDING_2FA_NEXT_KEY)
+            .await
+            .map(|_| ()),
+    }
+    .map_err(|e| ApiError::Internal(e.to_string()))?;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        ApiJson(TwoFactorRequiredResponse {
+            two_factor_required: true,
+        }),
+    )
+        .into_response())
+}
+
+/// Second login step for users with two-factor enabled; redirects like
+/// [`login`] when it was given a `?next=` target
+async fn verify_two_factor(
+    State(state): State<AppState>,
+    ip: ClientIp,
+    headers: HeaderMap,
+    mut auth_session: crate::auth::AuthSession,
+    ApiJson(payload): ApiJson<TotpCodeRequest>,
+) -> Result<Response, ApiError> {
+    let session = auth_session.session.clone();
+    let user_id = session
+        .get::<Uuid>(PENDING_2FA_KEY)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?
+        .ok_or(ApiError::Unauthorized(
+            "No login awaiting a two-factor code".to_string(),
+        ))?;
+
+    let attempts = session
+        .get::<u32>(PENDING_2FA_ATTEMPTS_KEY)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?
+        .unwrap_or(0)
+        + 1;
+    if attempts > MAX_2FA_ATTEMPTS {
+        let _ = session.remove::<Uuid>(PENDING_2FA_KEY).await;
+        let _ = session.remove::<u32>(PENDING_2FA_ATTEMPTS_KEY).await;
+        return Err(ApiError::Unauthorized(
+            "Too many two-factor attempts, log in again".to_string(),
+        ));
+    }
+    session
+        .insert(PENDING_2FA_ATTEMPTS_KEY, attempts)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+
+    // A wrong or replayed code fails the login like a wrong password
+    let user = state
         .user_service
-        .find_by_email(&payload.email)
-        .await?
-        .is_some()
-    {
-        return Err(ApiError::Domain(DomainError::UserAlreadyExists(
-            payload.email,
-        )));
+        .verify_totp(user_id, &payload.code)
+        .await
+        .map_err(|e| match e {
+            DomainError::Unauthorized(msg) => ApiError::Unauthorized(msg),
+            e => ApiError::Domain(e),
+        })?;
+
+    session
+        .remove::<Uuid>(PENDING_2FA_KEY)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    session
+        .remove::<u32>(PENDING_2FA_ATTEMPTS_KEY)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    let remember_me = session
+        .remove::<bool>(PENDING_2FA_REMEMBER_KEY)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?
+        .unwrap_or(false);
+    let next = session
+        .remove::<String>(PENDING_2FA_NEXT_KEY)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    let claims = session
+        .remove::<Claims>(PENDING_2FA_CLAIMS_KEY)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?
+        .unwrap_or_default();
+
+    state.user_service.enforce_session_limit(user.id).await?;
+    session::login(&mut auth_session, &crate::auth::AuthUser::new(user.clone())).await?;
+    if !claims.is_empty() {
+        session::store_claims(&session, claims).await?;
+    }
+    if remember_me {
+        remember(&session, state.config.remember_me_days);
     }
+    record_login(&state.user_service, &session, user.id, ip, &headers).await;
 
-    // Note: In a real app, you would hash the password here.
-    // This template uses a simplified User::new which doesn't take password.
-    // You should extend User to handle passwords or use an OIDC flow.
-    let email = Email::try_from(payload.email).map_err(|e| ApiError::Validation(e.to_string()))?;
+    state.user_service.record_login(user.id).await?;
 
-    // Using email as subject for local auth for now
+    if let Some(next) = next {
+        return Ok(Redirect::to(&next).into_response());
+    }
+    Ok(ApiJson(UserResponse {
+        id: user.id,
+        email: user.email.into_inner(),
+        created_at: user.created_at,
+    })
+    .into_response())
+}
+
+/// Start an OIDC login: remember a fresh [`OidcLoginAttempt`], then send the
+/// browser to the provider, which returns it to [`oidc_callback`]
+async fn oidc_login(
+    State(state): State<AppState>,
+    Query(query): Query<OidcLoginQuery>,
+    auth_session: crate::auth::AuthSession,
+) -> Result<Response, ApiError> {
+    let provider = oidc_provider(&state)?;
+    let next = query
+        .next
+        .map(|next| crate::redirect::resolve(Some(&next), &state.config.redirect_allowlist))
+        .transpose()?;
+    let attempt = OidcLoginAttempt::generate();
+
+    let session = &auth_session.session;
+    session
+        .insert(OIDC_ATTEMPT_KEY, &attempt)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    // Replace whatever an earlier, abandoned login left
+    match &next {
+        Some(next) => session.insert(OIDC_NEXT_KEY, next).await,
+        None => session.remove::<String>(OIDC_NEXT_KEY).await.map(|_| ()),
+    }
+    .map_err(|e| ApiError::Internal(e.to_string()))?;
+    match &query.invite_code {
+        Some(code) => session.insert(OIDC_INVITE_KEY, code).await,
+        None => session.remove::<String>(OIDC_INVITE_KEY).await.map(|_| ()),
+    }
+    .map_err(|e| ApiError::Internal(e.to_string()))?;
+
+    Ok(Redirect::to(&provider.authorization_url(&attempt)).into_response())
+}
+
+/// Finish an OIDC login with the code the provider sent back; redirects like
+/// [`login`] when it was started with a `?next=` target
+async fn oidc_callback(
+    State(state): State<AppState>,
+    ip: ClientIp,
+    headers: HeaderMap,
+    Query(callback): Query<OidcCallbackQuery>,
+    mut auth_session: crate::auth::AuthSession,
+) -> Result<Response, ApiError> {
+    let provider = oidc_provider(&state)?;
+    let session = auth_session.session.clone();
+    // Taken out either way, so a callback can't be replayed
+    let attempt = session
+        .remove::<OidcLoginAttempt>(OIDC_ATTEMPT_KEY)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    let next = session
+        .remove::<String>(OIDC_NEXT_KEY)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    let invite_code = session
+        .remove::<String>(OIDC_INVITE_KEY)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    let Some(attempt) = attempt.filter(|attempt| attempt.state == callback.state) else {
+        return Err(ApiError::Unauthorized(
+            "Sign-in expired or was started elsewhere, try again".to_string(),
+        ));
+    };
+    if let Some(error) = callback.error {
+        return Err(ApiError::Unauthorized(format!(
+            "The sign-in provider refused the login: {}",
+            error
+        )));
+    }
+    let code = callback
+        .code
+        .ok_or_else(|| ApiError::invalid_field("code", "Missing authorization code"))?;
+
+    let claims = provider.claims(&code, &attempt).await?;
     let user = state
         .user_service
-        .find_or_create(&email.as_ref().to_string(), email.as_ref())
+        .find_or_create_from_claims(&claims, invite_code.as_deref())
         .await?;
+    LoginPolicy::new(state.config.require_verified_email)
+        .with_auth_mode(state.auth_mode)
+        .check(&user)?;
+    let claims = state.user_service.session_claims(&claims);
+
+    // The provider vouched for the user, but two-factor still applies
+    if user.requires_totp() {
+        return start_two_factor(&session, user.id, false, next, claims).await;
+    }
+
+    state.user_service.enforce_session_limit(user.id).await?;
+    session::login(&mut auth_session, &crate::auth::AuthUser::new(user.clone())).await?;
+    session::store_claims(&session, claims).await?;
+    record_login(&state.user_service, &session, user.id, ip, &headers).await;
+
+    state.user_service.record_login(user.id).await?;
+
+    if let Some(next) = next {
+        return Ok(Redirect::to(&next).into_response());
+    }
+    Ok(ApiJson(UserResponse {
+        id: user.id,
+        email: user.email.into_inner(),
+        created_at: user.created_at,
+    })
+    .into_response())
+}
+
+/// The configured OIDC provider, or why the OIDC routes can't be used
+fn oidc_provider(state: &AppState) -> Result<Arc<dyn OidcProvider>, ApiError> {
+    state
+        .oidc
+        .clone()
+        .ok_or_else(|| ApiError::Forbidden("OIDC login is not configured".to_string()))
+}
+
+async fn enroll_two_factor(
+    State(state): State<AppState>,
+    auth_session: crate::auth::AuthSession,
+) -> Result<impl IntoResponse, ApiError> {
+    let user = auth_session
+        .user
+        .ok_or(ApiError::Unauthorized("Not logged in".to_string()))?;
+
+    let enrollment = state.user_service.start_totp_enrollment(user.0.id).await?;
+
+    Ok(ApiJson(TotpEnrollmentResponse {
+        secret: enrollment.secret,
+        otpauth_uri: enrollment.otpauth_uri,
+    }))
+}
+
+async fn confirm_two_factor(
+    State(state): State<AppState>,
+    auth_session: crate::auth::AuthSession,
+    ApiJson(payload): ApiJson<TotpCodeRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let user = auth_session
+        .user
+        .ok_or(ApiError::Unauthorized("Not logged in".to_string()))?;
+
+    state
+        .user_service
+        .confirm_totp(user.0.id, &payload.code)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn register(
+    State(state): State<AppState>,
+    ip: ClientIp,
+    headers: HeaderMap,
+    mut auth_session: crate::auth::AuthSession,
+    ApiJson(payload): ApiJson<RegisterRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    payload.validate()?;
+    if let Some(captcha) = &state.captcha {
+        captcha.check(payload.captcha_token.as_deref()).await?;
+    }
+
+    let command = NewUserCommand::try_from(payload)?;
+    let warnings = state.user_service.registration_warnings(&command);
+    let user = state.user_service.register(command).await?;
 
     // Log the user in
-    let auth_user = crate::auth::AuthUser(user.clone());
+    let auth_user = crate::auth::AuthUser::new(user.clone());
 
-    auth_session
-        .login(&auth_user)
-        .await
-        .map_err(|_| ApiError::Internal("Login failed".to_string()))?;
+    session::login(&mut auth_session, &auth_user).await?;
+    record_login(
+        &state.user_service,
+        &auth_session.session,
+        user.id,
+        ip,
+        &headers,
+    )
+    .await;
 
     Ok((
         StatusCode::CREATED,
-        Json(UserResponse {
-            id: user.id,
-            email: user.email.into_inner(),
-            created_at: user.created_at,
+        [(header::LOCATION, users::location(user.id))],
+        ApiJson(RegisterResponse {
+            user: UserResponse {
+                id: user.id,
+                email: user.email.into_inner(),
+                created_at: user.created_at,
+            },
+            warnings,
         }),
     ))
 }
 
+/// Email a password reset link.
+///
+/// Answers 202 whether or not the account exists, and hides delivery failures
+/// too, as only real accounts can have them; with `enumeration_protection`
+/// off both are reported instead.
+async fn request_password_reset(
+    State(state): State<AppState>,
+    ApiJson(payload): ApiJson<PasswordResetRequest>,
+) -> Result<StatusCode, ApiError> {
+    let email = Email::try_from(payload.email)?;
+
+    match state.user_service.send_password_reset(email.as_ref()).await {
+        Ok(true) => {}
+        Ok(false) if state.config.enumeration_protection => {}
+        Ok(false) => {
+            return Err(ApiError::invalid_field(
+                "email",
+                "No account with a password for this email",
+            ));
+        }
+        Err(e) if state.config.enumeration_protection => {
+            ApiError::Domain(e).log(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+async fn confirm_password_reset(
+    State(state): State<AppState>,
+    ApiJson(payload): ApiJson<ConfirmPasswordResetRequest>,
+) -> Result<StatusCode, ApiError> {
+    let password = Password::try_from(payload.password)?;
+    state
+        .user_service
+        .reset_password(&payload.token, &password)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Change the signed-in user's password.
+///
+/// The session moves to a new id and stays signed in; the user's other
+/// sessions end, as they no longer match the password.
+async fn change_password(
+    State(state): State<AppState>,
+    ip: ClientIp,
+    headers: HeaderMap,
+    mut auth_session: crate::auth::AuthSession,
+    ApiJson(payload): ApiJson<ChangePasswordRequest>,
+) -> Result<StatusCode, ApiError> {
+    let user = auth_session
+        .user
+        .clone()
+        .ok_or(ApiError::Unauthorized("Not logged in".to_string()))?;
+    let password = Password::try_from(payload.new_password)
+        .map_err(|e| ApiError::invalid_field("new_password", e))?;
+
+    let verified = auth_session
+        .authenticate(crate::auth::Credentials {
+            email: user.0.email.to_string(),
+            password: payload.current_password,
+            remember_me: false,
+        })
+        .await
+        .map_err(|e| match e {
+            crate::auth::AuthSessionError::Backend(crate::auth::AuthError::Domain(e)) => {
+                ApiError::Domain(e)
+            }
+            e => ApiError::Internal(e.to_string()),
+        })?;
+    if verified.is_none() {
+        return Err(ApiError::invalid_field(
+            "current_password",
+            "Current password is incorrect",
+        ));
+    }
+
+    let user = state
+        .user_service
+        .change_password(user.0.id, &password)
+        .await?;
+    let user_id = user.id;
+    session::rotate(&mut auth_session, user, &state.user_service, ip, &headers).await?;
+    if let Some(session_id) = auth_session.session.id() {
+        state
+            .user_service
+            .revoke_other_sessions(user_id, &session_id.to_string())
+            .await?;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Send a new verification link to the logged-in user, or to `email`.
+///
+/// By email the answer is always 204, whether or not the account exists or
+/// was just sent a link; logged-in users get 429 within the cooldown instead.
+async fn resend_verification(
+    State(state): State<AppState>,
+    auth_session: crate::auth::AuthSession,
+    ApiJson(payload): ApiJson<ResendVerificationRequest>,
+) -> Result<StatusCode, ApiError> {
+    match (auth_session.user, payload.email) {
+        (Some(user), _) => state.user_service.resend_verification(user.0.id).await?,
+        (None, Some(email)) => {
+            let email = Email::try_from(email)?;
+            state
+                .user_service
+                .resend_verification_by_email(email.as_ref())
+                .await?
+        }
+        (None, None) => {
+            return Err(ApiError::invalid_field(
+                "email",
+                "email is required when not logged in",
+            ));
+        }
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn verify_email(
+    State(state): State<AppState>,
+    ApiJson(payload): ApiJson<VerifyEmailRequest>,
+) -> Result<StatusCode, ApiError> {
+    state.user_service.verify_email(&payload.token).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
 async fn logout(mut auth_session: crate::auth::AuthSession) -> impl IntoResponse {
     match auth_session.logout().await {
         Ok(_) => StatusCode::OK,
@@ -104,14 +652,1147 @@ async fn logout(mut auth_session: crate::auth::AuthSession) -> impl IntoResponse
     }
 }
 
-async fn me(auth_session: crate::auth::AuthSession) -> Result<impl IntoResponse, ApiError> {
+async fn me(
+    auth_session: crate::auth::AuthSession,
+    csrf_token: Option<Extension<CsrfToken>>,
+) -> Result<impl IntoResponse, ApiError> {
+    let me = current_user(&auth_session, csrf_token)
+        .await?
+        .ok_or(ApiError::Unauthorized("Not logged in".to_string()))?;
+    Ok(ApiJson(me))
+}
+
+/// Apply a JSON Merge Patch to the signed-in user's profile
+async fn update_profile(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    auth_session: crate::auth::AuthSession,
+    csrf_token: Option<Extension<CsrfToken>>,
+    ApiJson(patch): ApiJson<ProfilePatch>,
+) -> Result<impl IntoResponse, ApiError> {
+    let merge_patch = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|essence| essence.trim().eq_ignore_ascii_case(MERGE_PATCH_JSON));
+    if !merge_patch {
+        return Err(ApiError::UnsupportedMediaType(format!(
+            "Expected `Content-Type: {}`",
+            MERGE_PATCH_JSON
+        )));
+    }
+    let user = auth_session
+        .user
+        .as_ref()
+        .ok_or(ApiError::Unauthorized("Not logged in".to_string()))?;
+
+    let user = state
+        .user_service
+        .update_profile(user.0.id, UpdateProfileCommand::from(patch))
+        .await?;
+    let me = current_user(&auth_session, csrf_token)
+        .await?
+        .ok_or(ApiError::Unauthorized("Not logged in".to_string()))?;
+    Ok(ApiJson(MeResponse {
+        display_name: user.display_name,
+        ..me
+    }))
+}
+
+/// The signed-in user's preferences, `{}` until they set any
+async fn preferences(
+    auth_session: crate::auth::AuthSession,
+) -> Result<impl IntoResponse, ApiError> {
+    let user = auth_session
+        .user
+        .ok_or(ApiError::Unauthorized("Not logged in".to_string()))?;
+    Ok(ApiJson(user.0.preferences))
+}
+
+/// Replace the signed-in user's preferences with the body, a JSON object
+async fn update_preferences(
+    State(state): State<AppState>,
+    auth_session: crate::auth::AuthSession,
+    ApiJson(preferences): ApiJson<serde_json::Value>,
+) -> Result<impl IntoResponse, ApiError> {
     let user = auth_session
         .user
         .ok_or(ApiError::Unauthorized("Not logged in".to_string()))?;
+    let user = state
+        .user_service
+        .update_preferences(user.0.id, preferences)
+        .await?;
+    Ok(ApiJson(user.preferences))
+}
+
+/// The body of `/auth/me`, or `None` when signed out
+pub(crate) async fn current_user(
+    auth_session: &crate::auth::AuthSession,
+    csrf_token: Option<Extension<CsrfToken>>,
+) -> Result<Option<MeResponse>, ApiError> {
+    let Some(user) = auth_session.user.clone() else {
+        return Ok(None);
+    };
+
+    let impersonated_by = auth_session
+        .session
+        .get::<Uuid>(IMPERSONATOR_KEY)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
 
-    Ok(Json(UserResponse {
-        id: user.0.id,
-        email: user.0.email.into_inner(),
-        created_at: user.0.created_at,
+    let display_name = user.0.display_name.clone();
+    let claims = user.claims().clone();
+    Ok(Some(MeResponse {
+        user: UserResponse::from(user.0),
+        impersonated_by,
+        csrf_token: csrf_token.map(|Extension(CsrfToken(token))| token),
+        display_name,
+        claims,
     }))
 }
+
+async fn stop_impersonation(
+    State(state): State<AppState>,
+    ip: ClientIp,
+    headers: HeaderMap,
+    mut auth_session: crate::auth::AuthSession,
+) -> Result<impl IntoResponse, ApiError> {
+    let user = auth_session
+        .user
+        .clone()
+        .ok_or(ApiError::Unauthorized("Not logged in".to_string()))?;
+
+    let admin_id = auth_session
+        .session
+        .remove::<Uuid>(IMPERSONATOR_KEY)
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?
+        .ok_or_else(|| ApiError::invalid_field(REQUEST_FIELD, "Not impersonating"))?;
+
+    let admin = state
+        .user_service
+        .stop_impersonation(admin_id, user.0.id)
+        .await?;
+
+    session::login(
+        &mut auth_session,
+        &crate::auth::AuthUser::new(admin.clone()),
+    )
+    .await?;
+    record_login(
+        &state.user_service,
+        &auth_session.session,
+        admin.id,
+        ip,
+        &headers,
+    )
+    .await;
+
+    Ok(ApiJson(UserResponse {
+        id: admin.id,
+        email: admin.email.into_inner(),
+        created_at: admin.created_at,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, extract::Request};
+    use domain::UserService;
+    use infra::db::{ConnectionSettings, DatabaseConfig, create_pool};
+    use infra::factory::{
+        build_api_key_repository, build_audit_log_repository, build_user_repository,
+    };
+    use tower::ServiceExt;
+
+    use crate::config::Config;
+
+    async fn app(auth_mode: AuthMode) -> Router {
+        let db_config = DatabaseConfig {
+            url: "sqlite::memory:".to_string(),
+            max_connections: 1,
+            min_connections: 1,
+            acquire_timeout: std::time::Duration::from_secs(5),
+        };
+        let pool = create_pool(db_config, &ConnectionSettings::default())
+            .await
+            .unwrap();
+        infra::run_migrations(&pool).await.unwrap();
+        let user_service = UserService::new(
+            build_user_repository(&pool).await.unwrap(),
+            build_api_key_repository(&pool).await.unwrap(),
+            build_audit_log_repository(&pool).await.unwrap(),
+        );
+        let state = AppState::new(user_service, Config::default()).with_auth_mode(auth_mode);
+
+        crate::routes::fallback::with_fallbacks(Router::new().nest("/auth", router(auth_mode)))
+            .with_state(state)
+    }
+
+    async fn post_status(app: &Router, path: &str) -> StatusCode {
+        let request = Request::post(path)
+            .header("content-type", "application/json")
+            .body(Body::from(
+                r#"{"email":"user@example.com","password":"secret1"}"#,
+            ))
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_oidc_mode_removes_password_endpoints() {
+        let app = app(AuthMode::Oidc).await;
+        assert_eq!(
+            post_status(&app, "/auth/login").await,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            post_status(&app, "/auth/register").await,
+            StatusCode::NOT_FOUND
+        );
+        // Session endpoints used after SSO stay mounted
+        assert_ne!(
+            post_status(&app, "/auth/logout").await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    /// `Max-Age` of the session cookie set by a login
+    async fn login_cookie_max_age(app: &Router, remember_me: bool) -> i64 {
+        let body = serde_json::json!({
+            "email": "alice@example.com",
+            "password": "correct horse",
+            "remember_me": remember_me,
+        });
+        let request = Request::post("/api/v1/auth/login")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let cookie = response.headers()[axum::http::header::SET_COOKIE]
+            .to_str()
+            .unwrap();
+        cookie
+            .split(';')
+            .find_map(|attribute| attribute.trim().strip_prefix("Max-Age="))
+            .expect("persistent cookie")
+            .parse()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_remember_me_extends_the_session_cookie() {
+        let (app, state) = crate::test_support::build_test_app().await;
+        let register = Request::post("/api/v1/auth/register")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                r#"{"email": "alice@example.com", "password": "correct horse"}"#,
+            ))
+            .unwrap();
+        app.clone().oneshot(register).await.unwrap();
+
+        let normal = login_cookie_max_age(&app, false).await;
+        let remembered = login_cookie_max_age(&app, true).await;
+        assert!(remembered > normal);
+
+        let days = i64::from(state.config.remember_me_days);
+        assert!((remembered - days * 24 * 60 * 60).abs() < 60);
+    }
+
+    #[tokio::test]
+    async fn test_disposable_email_registers_with_a_warning() {
+        let (app, _) = crate::test_support::build_test_app().await;
+        let register = |email: &str| {
+            Request::post("/api/v1/auth/register")
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    serde_json::json!({"email": email, "password": "correct horse battery"})
+                        .to_string(),
+                ))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(register("throwaway@mailinator.com"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["email"], "throwaway@mailinator.com");
+        let warnings = body["warnings"].as_array().unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].as_str().unwrap().contains("disposable"));
+
+        let response = app.oneshot(register("alice@example.com")).await.unwrap();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert!(body.get("warnings").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_profile_merge_patch_keeps_clears_and_sets() {
+        let (app, _) = crate::test_support::build_test_app().await;
+        let register = Request::post("/api/v1/auth/register")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                r#"{"email": "alice@example.com", "password": "correct horse"}"#,
+            ))
+            .unwrap();
+        let response = app.clone().oneshot(register).await.unwrap();
+        let cookie = response.headers()[header::SET_COOKIE]
+            .to_str()
+            .unwrap()
+            .split(';')
+            .next()
+            .unwrap()
+            .to_string();
+        let patch = |content_type: &str, body: serde_json::Value| {
+            let request = Request::patch("/api/v1/auth/me")
+                .header(header::CONTENT_TYPE, content_type)
+                .header(header::COOKIE, &cookie)
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap_or_default();
+                (status, body)
+            }
+        };
+
+        let (status, body) = patch(
+            MERGE_PATCH_JSON,
+            serde_json::json!({"display_name": "  Ada  Lovelace "}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["display_name"], "Ada Lovelace");
+
+        let (status, body) = patch(MERGE_PATCH_JSON, serde_json::json!({})).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["display_name"], "Ada Lovelace");
+
+        let (status, body) =
+            patch(MERGE_PATCH_JSON, serde_json::json!({"display_name": "   "})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["fields"]["display_name"].is_array());
+
+        let (status, _) = patch(
+            "application/json",
+            serde_json::json!({"display_name": null}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let (status, body) =
+            patch(MERGE_PATCH_JSON, serde_json::json!({"display_name": null})).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["display_name"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_resend_verification_is_rate_limited_without_revealing_accounts() {
+        let (app, _) = crate::test_support::build_test_app().await;
+        let post = |path: &str, body: serde_json::Value, cookie: Option<&str>| {
+            let mut request = Request::post(path).header("content-type", "application/json");
+            if let Some(cookie) = cookie {
+                request = request.header(header::COOKIE, cookie);
+            }
+            request.body(Body::from(body.to_string())).unwrap()
+        };
+        let resend = "/api/v1/auth/resend-verification";
+
+        let response = app
+            .clone()
+            .oneshot(post(
+                "/api/v1/auth/register",
+                serde_json::json!({"email": "alice@example.com", "password": "correct horse"}),
+                None,
+            ))
+            .await
+            .unwrap();
+        let cookie = response.headers()[header::SET_COOKIE]
+            .to_str()
+            .unwrap()
+            .split(';')
+            .next()
+            .unwrap()
+            .to_string();
+
+        // Unknown and known accounts look the same
+        for email in ["nobody@example.com", "alice@example.com"] {
+            let response = app
+                .clone()
+                .oneshot(post(resend, serde_json::json!({"email": email}), None))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        }
+
+        // Alice was just sent a link, so only the logged-in request learns of the cooldown
+        let response = app
+            .clone()
+            .oneshot(post(resend, serde_json::json!({}), Some(&cookie)))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key(header::RETRY_AFTER));
+
+        let response = app
+            .oneshot(post(
+                resend,
+                serde_json::json!({"email": "alice@example.com"}),
+                None,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+
+    /// Status and body of a JSON POST
+    async fn post_json(app: &Router, path: &str, body: serde_json::Value) -> (StatusCode, Vec<u8>) {
+        let request = Request::post(path)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, bytes.to_vec())
+    }
+
+    /// App with alice registered, and the login and reset answers for her
+    /// (with a wrong password) and for an unknown email
+    async fn enumeration_probe(
+        enumeration_protection: bool,
+    ) -> [((StatusCode, Vec<u8>), (StatusCode, Vec<u8>)); 2] {
+        let config = crate::config::Config {
+            enumeration_protection,
+            ..crate::test_support::test_config()
+        };
+        let pool = crate::test_support::test_pool().await;
+        let (app, _) = crate::test_support::build_test_app_with(pool, config).await;
+        let (status, _) = post_json(
+            &app,
+            "/api/v1/auth/register",
+            serde_json::json!({"email": "alice@example.com", "password": "correct horse"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let login = |email: &'static str| {
+            post_json(
+                &app,
+                "/api/v1/auth/login",
+                serde_json::json!({"email": email, "password": "wrong horse"}),
+            )
+        };
+        let reset = |email: &'static str| {
+            post_json(
+                &app,
+                "/api/v1/auth/password-reset",
+                serde_json::json!({"email": email}),
+            )
+        };
+        [
+            (
+                login("alice@example.com").await,
+                login("nobody@example.com").await,
+            ),
+            (
+                reset("alice@example.com").await,
+                reset("nobody@example.com").await,
+            ),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_unknown_emails_get_the_same_login_and_reset_answers() {
+        let [login, reset] = enumeration_probe(true).await;
+
+        assert_eq!(login.0, login.1);
+        assert_eq!(login.0.0, StatusCode::BAD_REQUEST);
+        assert_eq!(reset.0, reset.1);
+        assert_eq!(reset.0.0, StatusCode::ACCEPTED);
+    }
+
+    #[tokio::test]
+    async fn test_enumeration_protection_can_be_turned_off() {
+        let [login, reset] = enumeration_probe(false).await;
+
+        assert_eq!(login.0.0, login.1.0);
+        assert_ne!(login.0.1, login.1.1);
+        assert_eq!(reset.0.0, StatusCode::ACCEPTED);
+        assert_eq!(reset.1.0, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_login_redirects_only_to_allowed_targets() {
+        let (app, _) = crate::test_support::build_test_app().await;
+        let credentials =
+            serde_json::json!({"email": "alice@example.com", "password": "correct horse"});
+        let (status, _) = post_json(&app, "/api/v1/auth/register", credentials.clone()).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let login = |next: &str| {
+            Request::post(format!("/api/v1/auth/login?next={next}"))
+                .header("content-type", "application/json")
+                .body(Body::from(credentials.to_string()))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(login("/settings")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(response.headers()[header::LOCATION], "/settings");
+
+        let response = app
+            .oneshot(login("https%3A%2F%2Fevil.com%2F"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(!response.headers().contains_key(header::SET_COOKIE));
+    }
+
+    #[tokio::test]
+    async fn test_validation_errors_have_one_shape_over_http() {
+        use crate::test_support::TestClient;
+
+        let (app, _) = crate::test_support::build_test_app().await;
+        let mut client = TestClient::new(app);
+        let fields = |response: &crate::test_support::TestResponse| {
+            assert_eq!(response.status, StatusCode::BAD_REQUEST, "{:?}", response);
+            let body = response.json();
+            assert_eq!(body["error"], "Validation error");
+            let mut fields: Vec<String> = body["fields"]
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect();
+            fields.sort();
+            fields
+        };
+
+        // `validate` runs first and reports every field at once
+        let invalid = client
+            .post_json(
+                "/api/v1/auth/register",
+                serde_json::json!({"email": "not-an-email", "password": "abc"}),
+            )
+            .await;
+        assert_eq!(fields(&invalid), ["email", "password"]);
+
+        let missing = client
+            .post_json(
+                "/api/v1/auth/login",
+                serde_json::json!({"email": "alice@example.com"}),
+            )
+            .await;
+        assert_eq!(fields(&missing), ["password"]);
+
+        client
+            .post_json(
+                "/api/v1/auth/register",
+                serde_json::json!({"email": "alice@example.com", "password": "correct horse"}),
+            )
+            .await;
+        let wrong = client
+            .post_json(
+                "/api/v1/auth/login",
+                serde_json::json!({"email": "alice@example.com", "password": "wrong horse"}),
+            )
+            .await;
+        assert_eq!(fields(&wrong), [REQUEST_FIELD]);
+
+        let redirect = client
+            .post_json(
+                "/api/v1/auth/login?next=https%3A%2F%2Fevil.com%2F",
+                serde_json::json!({"email": "alice@example.com", "password": "correct horse"}),
+            )
+            .await;
+        assert_eq!(fields(&redirect), ["next"]);
+
+        // Domain validation errors too, e.g. two-factor without a key configured
+        client.login("alice@example.com", "correct horse").await;
+        let domain = client
+            .post_json("/api/v1/auth/2fa/enroll", serde_json::json!({}))
+            .await;
+        assert_eq!(fields(&domain), [REQUEST_FIELD]);
+    }
+
+    #[tokio::test]
+    async fn test_first_registered_user_becomes_admin() {
+        let config = crate::config::Config {
+            first_user_is_admin: true,
+            ..crate::test_support::test_config()
+        };
+        let pool = crate::test_support::test_pool().await;
+        let (app, state) = crate::test_support::build_test_app_with(pool, config).await;
+
+        for email in ["first@example.com", "second@example.com"] {
+            let (status, _) = post_json(
+                &app,
+                "/api/v1/auth/register",
+                serde_json::json!({"email": email, "password": "correct horse"}),
+            )
+            .await;
+            assert_eq!(status, StatusCode::CREATED);
+        }
+
+        let role = |email: &'static str| {
+            let service = &state.user_service;
+            async move { service.find_by_email(email).await.unwrap().unwrap().role }
+        };
+        assert_eq!(role("first@example.com").await, domain::Role::Admin);
+        assert_eq!(role("second@example.com").await, domain::Role::User);
+    }
+
+    #[tokio::test]
+    async fn test_both_mode_keeps_password_endpoints() {
+        let app = app(AuthMode::Both).await;
+        assert_ne!(
+            post_status(&app, "/auth/login").await,
+            StatusCode::NOT_FOUND
+        );
+        assert_ne!(
+            post_status(&app, "/auth/register").await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn test_email_available_answers_with_a_boolean_and_is_rate_limited() {
+        let config = Config {
+            email_check_limit: 3,
+            ..crate::test_support::test_config()
+        };
+        let pool = crate::test_support::test_pool().await;
+        let (app, _) = crate::test_support::build_test_app_with(pool, config).await;
+        let (status, _) = post_json(
+            &app,
+            "/api/v1/auth/register",
+            serde_json::json!({"email": "alice@example.com", "password": "correct horse"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let check = |email: &str| {
+            Request::get(format!("/api/v1/auth/email-available?email={}", email))
+                .body(Body::empty())
+                .unwrap()
+        };
+        for (email, available) in [
+            ("alice@example.com", false),
+            ("bob@example.com", true),
+            ("carol@example.com", true),
+        ] {
+            let response = app.clone().oneshot(check(email)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+            assert_eq!(body, serde_json::json!({"available": available}));
+        }
+
+        let response = app.oneshot(check("dave@example.com")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key(header::RETRY_AFTER));
+    }
+
+    #[tokio::test]
+    async fn test_preferences_are_stored_per_user() {
+        use crate::test_support::TestClient;
+
+        let (app, _) = crate::test_support::build_test_app().await;
+        let mut client = TestClient::new(app.clone());
+        client
+            .post_json(
+                "/api/v1/auth/register",
+                serde_json::json!({"email": "prefs@example.com", "password": "correct horse"}),
+            )
+            .await;
+        let put = |body: serde_json::Value| {
+            Request::put("/api/v1/auth/me/preferences")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+
+        let empty = client.get("/api/v1/auth/me/preferences").await;
+        assert_eq!(empty.status, StatusCode::OK);
+        assert_eq!(empty.json(), serde_json::json!({}));
+
+        let preferences = serde_json::json!({"theme": "dark", "language": "pl"});
+        let saved = client.send(put(preferences.clone())).await;
+        assert_eq!(saved.status, StatusCode::OK);
+        assert_eq!(saved.json(), preferences);
+        let stored = client.get("/api/v1/auth/me/preferences").await;
+        assert_eq!(stored.json(), preferences);
+
+        let oversized = serde_json::json!({"notes": "x".repeat(domain::MAX_PREFERENCES_BYTES)});
+        let rejected = client.send(put(oversized)).await;
+        assert_eq!(rejected.status, StatusCode::BAD_REQUEST);
+        let stored = client.get("/api/v1/auth/me/preferences").await;
+        assert_eq!(stored.json(), preferences);
+
+        let anonymous = TestClient::new(app)
+            .get("/api/v1/auth/me/preferences")
+            .await;
+        assert_eq!(anonymous.status, StatusCode::UNAUTHORIZED);
+    }
+
+    /// Authenticator code for the step `steps_ahead` steps after the current one
+    #[cfg(feature = "totp")]
+    fn totp_code(otpauth_uri: &str, steps_ahead: u64) -> String {
+        let totp = totp_rs::TOTP::from_url(otpauth_uri).unwrap();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        totp.generate(now + steps_ahead * totp.step)
+    }
+
+    #[cfg(feature = "totp")]
+    #[tokio::test]
+    async fn test_two_factor_login_asks_for_a_code_and_rejects_replays() {
+        use crate::test_support::{TestClient, build_test_app_with, test_config, test_pool};
+
+        let config = Config {
+            totp_encryption_key: Some(
+                "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f".to_string(),
+            ),
+            ..test_config()
+        };
+        let (app, _) = build_test_app_with(test_pool().await, config).await;
+        let mut client = TestClient::new(app);
+        let credentials =
+            serde_json::json!({ "email": "alice@example.com", "password": "correct horse" });
+        client
+            .post_json("/api/v1/auth/register", credentials.clone())
+            .await;
+        let enrollment = client
+            .post_json("/api/v1/auth/2fa/enroll", serde_json::json!({}))
+            .await
+            .json();
+        let otpauth_uri = enrollment["otpauth_uri"].as_str().unwrap();
+        let enrollment_code = totp_code(otpauth_uri, 0);
+        let confirm = client
+            .post_json(
+                "/api/v1/auth/2fa/confirm",
+                serde_json::json!({ "code": enrollment_code }),
+            )
+            .await;
+        assert_eq!(confirm.status, StatusCode::NO_CONTENT);
+        client
+            .post_json("/api/v1/auth/logout", serde_json::json!({}))
+            .await;
+
+        let login = client
+            .post_json("/api/v1/auth/login", credentials.clone())
+            .await;
+        assert_eq!(login.status, StatusCode::ACCEPTED);
+        assert_eq!(login.json()["two_factor_required"], true);
+        let me = client
+            .post_json("/api/v1/auth/me", serde_json::json!({}))
+            .await;
+        assert_eq!(me.status, StatusCode::UNAUTHORIZED);
+
+        // Already used to confirm the enrollment
+        let replayed = client
+            .post_json(
+                "/api/v1/auth/2fa",
+                serde_json::json!({ "code": enrollment_code }),
+            )
+            .await;
+        assert_eq!(replayed.status, StatusCode::UNAUTHORIZED);
+
+        // Within the allowed clock skew
+        let code = totp_code(otpauth_uri, 1);
+        let verified = client
+            .post_json("/api/v1/auth/2fa", serde_json::json!({ "code": code }))
+            .await;
+        assert_eq!(verified.status, StatusCode::OK);
+        let me = client
+            .post_json("/api/v1/auth/me", serde_json::json!({}))
+            .await;
+        assert_eq!(me.json()["email"], "alice@example.com");
+
+        client
+            .post_json("/api/v1/auth/logout", serde_json::json!({}))
+            .await;
+        let login = client.post_json("/api/v1/auth/login", credentials).await;
+        assert_eq!(login.status, StatusCode::ACCEPTED);
+        let replayed = client
+            .post_json("/api/v1/auth/2fa", serde_json::json!({ "code": code }))
+            .await;
+        assert_eq!(replayed.status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[cfg(feature = "totp")]
+    #[tokio::test]
+    async fn test_two_factor_login_follows_the_redirect_target() {
+        use crate::test_support::{TestClient, build_test_app_with, test_config, test_pool};
+
+        let config = Config {
+            totp_encryption_key: Some(
+                "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f".to_string(),
+            ),
+            ..test_config()
+        };
+        let (app, _) = build_test_app_with(test_pool().await, config).await;
+        let mut client = TestClient::new(app);
+        let credentials =
+            serde_json::json!({ "email": "alice@example.com", "password": "correct horse" });
+        client
+            .post_json("/api/v1/auth/register", credentials.clone())
+            .await;
+        let enrollment = client
+            .post_json("/api/v1/auth/2fa/enroll", serde_json::json!({}))
+            .await
+            .json();
+        let otpauth_uri = enrollment["otpauth_uri"].as_str().unwrap();
+        client
+            .post_json(
+                "/api/v1/auth/2fa/confirm",
+                serde_json::json!({ "code": totp_code(otpauth_uri, 0) }),
+            )
+            .await;
+        client
+            .post_json("/api/v1/auth/logout", serde_json::json!({}))
+            .await;
+
+        let login = client
+            .post_json("/api/v1/auth/login?next=/settings", credentials)
+            .await;
+        assert_eq!(login.status, StatusCode::ACCEPTED);
+        let verified = client
+            .post_json(
+                "/api/v1/auth/2fa",
+                serde_json::json!({ "code": totp_code(otpauth_uri, 1) }),
+            )
+            .await;
+        assert_eq!(verified.status, StatusCode::SEE_OTHER);
+        assert_eq!(verified.headers[header::LOCATION], "/settings");
+    }
+
+    /// Settings for an OIDC provider at `address`
+    fn oidc_config(address: &str) -> Config {
+        Config {
+            oidc_authorization_url: Some(format!("{}/authorize", address)),
+            oidc_token_url: Some(format!("{}/token", address)),
+            oidc_userinfo_url: Some(format!("{}/userinfo", address)),
+            oidc_client_id: Some("k-template".to_string()),
+            oidc_client_secret: Some("secret".to_string()),
+            oidc_redirect_url: Some("http://localhost/api/v1/auth/oidc/callback".to_string()),
+            oidc_timeout_secs: 1,
+            ..crate::test_support::test_config()
+        }
+    }
+
+    /// A provider on a local port that answers userinfo with `claims`.
+    ///
+    /// It takes the codes [`oidc_round_trip`] makes up, `<nonce>.<code
+    /// challenge>`, and only issues tokens to the matching code verifier.
+    async fn mock_provider(claims: serde_json::Value) -> String {
+        use base64::Engine;
+        use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        use sha2::{Digest, Sha256};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let subject = claims["sub"].clone();
+        let token =
+            move |axum::Form(form): axum::Form<std::collections::HashMap<String, String>>| {
+                let subject = subject.clone();
+                async move {
+                    let (nonce, challenge) = form["code"].split_once('.').unwrap();
+                    let verified = URL_SAFE_NO_PAD.encode(Sha256::digest(&form["code_verifier"]));
+                    if verified != challenge {
+                        let error = serde_json::json!({"error": "invalid_grant"});
+                        return (StatusCode::BAD_REQUEST, axum::Json(error));
+                    }
+                    let id_token = serde_json::json!({
+                        "sub": subject,
+                        "aud": "k-template",
+                        "nonce": nonce,
+                    });
+                    let id_token = format!(
+                        "e30.{}.signature",
+                        URL_SAFE_NO_PAD.encode(id_token.to_string())
+                    );
+                    let tokens = serde_json::json!({"access_token": "token", "id_token": id_token});
+                    (StatusCode::OK, axum::Json(tokens))
+                }
+            };
+        let provider = Router::new().route("/token", post(token)).route(
+            "/userinfo",
+            get(move || {
+                let claims = claims.clone();
+                async move { axum::Json(claims) }
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, provider).await.unwrap() });
+        address
+    }
+
+    /// Start an OIDC login and come back from the provider, as a browser would
+    async fn oidc_round_trip(
+        client: &mut crate::test_support::TestClient,
+    ) -> crate::test_support::TestResponse {
+        let login = client.get("/api/v1/auth/oidc/login").await;
+        assert_eq!(login.status, StatusCode::SEE_OTHER);
+        let location = login.headers[header::LOCATION].to_str().unwrap();
+        let query: std::collections::HashMap<_, _> = location
+            .split_once('?')
+            .unwrap()
+            .1
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .collect();
+        client
+            .get(&format!(
+                "/api/v1/auth/oidc/callback?code={}.{}&state={}",
+                query["nonce"], query["code_challenge"], query["state"]
+            ))
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_stalled_provider_fails_the_login_with_bad_gateway() {
+        use crate::test_support::TestClient;
+
+        // Accepts connections and never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let provider = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut open = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                open.push(socket);
+            }
+        });
+        let pool = crate::test_support::test_pool().await;
+        let (app, _) = crate::test_support::build_test_app_with(pool, oidc_config(&provider)).await;
+        let mut client = TestClient::new(app);
+
+        let callback = oidc_round_trip(&mut client).await;
+
+        assert_eq!(callback.status, StatusCode::BAD_GATEWAY);
+        assert_eq!(callback.json()["code"], "provider_unavailable");
+        assert!(callback.headers.contains_key(header::RETRY_AFTER));
+    }
+
+    #[tokio::test]
+    async fn test_oidc_claims_last_until_the_next_login() {
+        use crate::test_support::TestClient;
+
+        let provider = mock_provider(serde_json::json!({
+            "sub": "idp|ada",
+            "email": "ada@example.com",
+            "groups": ["admins"],
+            "tenant": "acme",
+        }))
+        .await;
+        let config = Config {
+            oidc_session_claims: vec!["groups".to_string()],
+            ..oidc_config(&provider)
+        };
+        let pool = crate::test_support::test_pool().await;
+        let (app, _) = crate::test_support::build_test_app_with(pool, config).await;
+        TestClient::new(app.clone())
+            .post_json(
+                "/api/v1/auth/register",
+                serde_json::json!({"email": "bob@example.com", "password": "correct horse"}),
+            )
+            .await;
+        let mut client = TestClient::new(app);
+
+        let callback = oidc_round_trip(&mut client).await;
+        assert_eq!(callback.status, StatusCode::OK, "{:?}", callback);
+        assert_eq!(callback.json()["email"], "ada@example.com");
+        let me = client
+            .post_json("/api/v1/auth/me", serde_json::json!({}))
+            .await
+            .json();
+        assert_eq!(me["claims"], serde_json::json!({"groups": ["admins"]}));
+
+        // The login keeps the session's data, but none of Ada's claims
+        client.login("bob@example.com", "correct horse").await;
+        let me = client
+            .post_json("/api/v1/auth/me", serde_json::json!({}))
+            .await
+            .json();
+        assert_eq!(me["email"], "bob@example.com");
+        assert!(me.get("claims").is_none(), "{}", me);
+    }
+
+    #[cfg(feature = "totp")]
+    #[tokio::test]
+    async fn test_oidc_login_asks_for_the_two_factor_code() {
+        use crate::test_support::TestClient;
+
+        let provider = mock_provider(serde_json::json!({
+            "sub": "idp|ada",
+            "email": "ada@example.com",
+            "groups": ["admins"],
+        }))
+        .await;
+        let config = Config {
+            oidc_session_claims: vec!["groups".to_string()],
+            totp_encryption_key: Some(
+                "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f".to_string(),
+            ),
+            ..oidc_config(&provider)
+        };
+        let pool = crate::test_support::test_pool().await;
+        let (app, _) = crate::test_support::build_test_app_with(pool, config).await;
+        let mut client = TestClient::new(app);
+        oidc_round_trip(&mut client).await;
+        let enrollment = client
+            .post_json("/api/v1/auth/2fa/enroll", serde_json::json!({}))
+            .await
+            .json();
+        let otpauth_uri = enrollment["otpauth_uri"].as_str().unwrap();
+        client
+            .post_json(
+                "/api/v1/auth/2fa/confirm",
+                serde_json::json!({ "code": totp_code(otpauth_uri, 0) }),
+            )
+            .await;
+        client
+            .post_json("/api/v1/auth/logout", serde_json::json!({}))
+            .await;
+
+        let callback = oidc_round_trip(&mut client).await;
+        assert_eq!(callback.status, StatusCode::ACCEPTED);
+        let me = client
+            .post_json("/api/v1/auth/me", serde_json::json!({}))
+            .await;
+        assert_eq!(me.status, StatusCode::UNAUTHORIZED);
+
+        let verified = client
+            .post_json(
+                "/api/v1/auth/2fa",
+                serde_json::json!({ "code": totp_code(otpauth_uri, 1) }),
+            )
+            .await;
+        assert_eq!(verified.status, StatusCode::OK);
+        let me = client
+            .post_json("/api/v1/auth/me", serde_json::json!({}))
+            .await
+            .json();
+        assert_eq!(me["email"], "ada@example.com");
+        assert_eq!(me["claims"], serde_json::json!({"groups": ["admins"]}));
+    }
+
+    #[tokio::test]
+    async fn test_new_oidc_account_needs_an_invite_when_required() {
+        use crate::test_support::TestClient;
+
+        let provider = mock_provider(serde_json::json!({
+            "sub": "idp|ada",
+            "email": "ada@example.com",
+        }))
+        .await;
+        let config = Config {
+            require_invite_code: true,
+            ..oidc_config(&provider)
+        };
+        let pool = crate::test_support::test_pool().await;
+        let (app, _) = crate::test_support::build_test_app_with(pool, config).await;
+        let mut client = TestClient::new(app);
+
+        let callback = oidc_round_trip(&mut client).await;
+        assert_eq!(callback.status, StatusCode::FORBIDDEN, "{:?}", callback);
+        assert_eq!(callback.json()["code"], "invalid_invite_code");
+        let me = client
+            .post_json("/api/v1/auth/me", serde_json::json!({}))
+            .await;
+        assert_eq!(me.status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_password_change_rotates_the_session() {
+        use crate::test_support::TestClient;
+
+        let (app, _) = crate::test_support::build_test_app().await;
+        let mut client = TestClient::new(app.clone());
+        let mut laptop = TestClient::new(app.clone());
+        TestClient::new(app.clone())
+            .post_json(
+                "/api/v1/auth/register",
+                serde_json::json!({"email": "alice@example.com", "password": "correct horse"}),
+            )
+            .await;
+        laptop.login("alice@example.com", "correct horse").await;
+        let session_cookie = |headers: &axum::http::HeaderMap| {
+            headers[header::SET_COOKIE]
+                .to_str()
+                .unwrap()
+                .split(';')
+                .next()
+                .unwrap()
+                .to_string()
+        };
+        let before = session_cookie(
+            &client
+                .login("alice@example.com", "correct horse")
+                .await
+                .headers,
+        );
+
+        let wrong = client
+            .post_json(
+                "/api/v1/auth/password",
+                serde_json::json!({
+                    "current_password": "wrong horse",
+                    "new_password": "battery staple",
+                }),
+            )
+            .await;
+        assert_eq!(wrong.status, StatusCode::BAD_REQUEST);
+
+        let changed = client
+            .post_json(
+                "/api/v1/auth/password",
+                serde_json::json!({
+                    "current_password": "correct horse",
+                    "new_password": "battery staple",
+                }),
+            )
+            .await;
+        assert_eq!(changed.status, StatusCode::NO_CONTENT);
+        assert_ne!(session_cookie(&changed.headers), before);
+
+        let me = client
+            .post_json("/api/v1/auth/me", serde_json::json!({}))
+            .await;
+        assert_eq!(me.status, StatusCode::OK);
+        // Other sessions end with the old password
+        let me = laptop
+            .post_json("/api/v1/auth/me", serde_json::json!({}))
+            .await;
+        assert_eq!(me.status, StatusCode::UNAUTHORIZED);
+
+        let replayed = Request::post("/api/v1/auth/me")
+            .header(header::COOKIE, before)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(replayed).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,100 @@
+//! Bootstrap
+//!
+//! Everything a frontend needs on load — who is signed in, what they may do and
+//! the public config — in one round trip instead of three.
+
+use axum::{Router, extract::Extension, extract::State, routing::get};
+
+use crate::{
+    dto::{BootstrapResponse, PermissionsResponse},
+    error::ApiError,
+    json::ApiJson,
+    middleware::csrf::CsrfToken,
+    routes::{auth, config},
+    state::AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/", get(bootstrap))
+}
+
+/// Works signed out too, answering with `user: null`
+async fn bootstrap(
+    State(state): State<AppState>,
+    auth_session: crate::auth::AuthSession,
+    csrf_token: Option<Extension<CsrfToken>>,
+) -> Result<ApiJson<BootstrapResponse>, ApiError> {
+    let user = auth::current_user(&auth_session, csrf_token).await?;
+    let permissions = PermissionsResponse::for_user(auth_session.user.as_ref().map(|user| &user.0));
+
+    Ok(ApiJson(BootstrapResponse {
+        user,
+        permissions,
+        config: config::public_config(&state),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode, header};
+    use tower::ServiceExt;
+
+    use crate::test_support::build_test_app;
+
+    async fn bootstrap(app: &axum::Router, cookie: Option<&str>) -> serde_json::Value {
+        let mut request = Request::get("/api/v1/bootstrap");
+        if let Some(cookie) = cookie {
+            request = request.header(header::COOKIE, cookie);
+        }
+        let response = app
+            .clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_bootstrap_signed_out() {
+        let (app, _) = build_test_app().await;
+
+        let body = bootstrap(&app, None).await;
+        assert!(body["user"].is_null());
+        assert!(body["permissions"]["role"].is_null());
+        assert_eq!(body["permissions"]["admin"], false);
+        assert_eq!(body["config"]["allow_registration"], true);
+        assert_eq!(body["config"]["auth_mode"], "both");
+    }
+
+    #[tokio::test]
+    async fn test_bootstrap_signed_in() {
+        let (app, _) = build_test_app().await;
+        let register = Request::post("/api/v1/auth/register")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                r#"{"email": "alice@example.com", "password": "correct horse"}"#,
+            ))
+            .unwrap();
+        let response = app.clone().oneshot(register).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let cookie = response.headers()[header::SET_COOKIE]
+            .to_str()
+            .unwrap()
+            .split(';')
+            .next()
+            .unwrap()
+            .to_string();
+
+        let body = bootstrap(&app, Some(&cookie)).await;
+        assert_eq!(body["user"]["email"], "alice@example.com");
+        assert!(body["user"]["id"].is_string());
+        assert_eq!(body["permissions"]["role"], "user");
+        assert_eq!(body["permissions"]["admin"], false);
+        assert_eq!(body["config"]["allow_registration"], true);
+    }
+}
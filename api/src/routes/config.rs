@@ -1,13 +1,31 @@
-use axum::{Json, Router, routing::get};
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::{Router, extract::State, routing::get};
 use crate::dto::ConfigResponse;
+use crate::json::ApiJson;
 use crate::state::AppState;
 
 pub fn router() -> Router<AppState> {
     Router::new().route("/", get(get_config))
 }
 
-async fn get_config() -> Json<ConfigResponse> {
-    Json(ConfigResponse {
-        allow_registration: true, // Default to true for template
+async fn get_config(State(state): State<AppState>) -> impl IntoResponse {
+    let response = public_config(&state);
+
+    let ttl = state.config_cache.ttl().as_secs();
+    let cache_control = if ttl == 0 {
+        "no-cache".to_string()
+    } else {
+        format!("public, max-age={}", ttl)
+    };
+    ([(header::CACHE_CONTROL, cache_control)], ApiJson(response))
+}
+
+/// The public config, served from the cache
+pub(crate) fn public_config(state: &AppState) -> ConfigResponse {
+    state.config_cache.get_or_insert_with(|| ConfigResponse {
+        // Registration is local-only; SSO users are created on first login
+        allow_registration: state.auth_mode.allows_password(),
+        auth_mode: state.auth_mode,
     })
 }
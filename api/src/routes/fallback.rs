@@ -0,0 +1,83 @@
+//! JSON responses for unknown routes and unsupported methods
+//!
+//! Without these, axum answers with an empty body, which breaks clients that
+//! always parse JSON.
+
+use axum::{Router, http::StatusCode, response::IntoResponse};
+
+use crate::error::ErrorResponse;
+use crate::json::ApiJson;
+
+/// Install the 404 and 405 fallbacks; call after all routes are added
+pub fn with_fallbacks<S: Clone + Send + Sync + 'static>(router: Router<S>) -> Router<S> {
+    router
+        .fallback(route_not_found)
+        .method_not_allowed_fallback(method_not_allowed)
+}
+
+fn error(status: StatusCode, error: &str, code: &'static str) -> impl IntoResponse {
+    (
+        status,
+        ApiJson(ErrorResponse {
+            error: error.to_string(),
+            code: Some(code),
+            conflict_field: None,
+            details: None,
+            fields: None,
+        }),
+    )
+}
+
+async fn route_not_found() -> impl IntoResponse {
+    error(StatusCode::NOT_FOUND, "not_found", "route_not_found")
+}
+
+async fn method_not_allowed() -> impl IntoResponse {
+    error(
+        StatusCode::METHOD_NOT_ALLOWED,
+        "method_not_allowed",
+        "method_not_allowed",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, extract::Request, routing::get};
+    use tower::ServiceExt;
+
+    fn app() -> Router {
+        let api = Router::new().route("/known", get(|| async { "ok" }));
+        with_fallbacks(Router::new().nest("/api/v1", api))
+    }
+
+    async fn send(request: Request) -> (StatusCode, serde_json::Value) {
+        let response = app().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_unknown_path_returns_json_404() {
+        let request = Request::get("/api/v1/nope").body(Body::empty()).unwrap();
+        let (status, body) = send(request).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(
+            body,
+            serde_json::json!({"error": "not_found", "code": "route_not_found"})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wrong_method_returns_json_405() {
+        let request = Request::delete("/api/v1/known")
+            .body(Body::empty())
+            .unwrap();
+        let (status, body) = send(request).await;
+        assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(body["code"], "method_not_allowed");
+    }
+}
@@ -0,0 +1,141 @@
+//! Health check
+//!
+//! Reports `starting` with 503 until startup (database warm-up) completes, so
+//! orchestrators hold traffic back until the instance is ready.
+//! `/health/details` also checks dependencies that can fail on their own, such
+//! as a session store on a separate backend.
+
+use std::sync::atomic::Ordering;
+
+use axum::{Router, extract::State, http::StatusCode, response::IntoResponse, routing::get};
+use serde::Serialize;
+
+use crate::json::ApiJson;
+use crate::state::AppState;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/health", get(health))
+        .route("/health/details", get(health_details))
+}
+
+#[derive(Debug, Serialize)]
+struct HealthResponse {
+    status: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct HealthDetailsResponse {
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    session_store: Option<&'static str>,
+}
+
+async fn health(State(state): State<AppState>) -> impl IntoResponse {
+    if state.ready.load(Ordering::Acquire) {
+        (StatusCode::OK, ApiJson(HealthResponse { status: "ok" }))
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            ApiJson(HealthResponse { status: "starting" }),
+        )
+    }
+}
+
+async fn health_details(State(state): State<AppState>) -> impl IntoResponse {
+    let session_store = match &state.session_store {
+        Some(store) => Some(match store.health().await {
+            Ok(()) => "up",
+            Err(e) => {
+                tracing::warn!("Session store health check failed: {}", e);
+                "down"
+            }
+        }),
+        None => None,
+    };
+
+    let (code, status) = if !state.ready.load(Ordering::Acquire) {
+        (StatusCode::SERVICE_UNAVAILABLE, "starting")
+    } else if session_store == Some("down") {
+        (StatusCode::SERVICE_UNAVAILABLE, "degraded")
+    } else {
+        (StatusCode::OK, "ok")
+    };
+
+    (
+        code,
+        ApiJson(HealthDetailsResponse {
+            status,
+            session_store,
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use axum::{body::Body, extract::Request};
+    use domain::UserService;
+    use infra::db::{ConnectionSettings, DatabaseConfig, create_pool};
+    use infra::factory::{
+        build_api_key_repository, build_audit_log_repository, build_user_repository,
+    };
+    use infra::session_store::MemoryStore;
+    use tower::ServiceExt;
+
+    use crate::config::Config;
+
+    async fn state() -> AppState {
+        let db_config = DatabaseConfig {
+            url: "sqlite::memory:".to_string(),
+            max_connections: 1,
+            min_connections: 1,
+            acquire_timeout: std::time::Duration::from_secs(5),
+        };
+        let pool = create_pool(db_config, &ConnectionSettings::default())
+            .await
+            .unwrap();
+        let user_service = UserService::new(
+            build_user_repository(&pool).await.unwrap(),
+            build_api_key_repository(&pool).await.unwrap(),
+            build_audit_log_repository(&pool).await.unwrap(),
+        );
+        AppState::new(user_service, Config::default())
+    }
+
+    async fn details(state: AppState) -> (StatusCode, serde_json::Value) {
+        let request = Request::get("/health/details").body(Body::empty()).unwrap();
+        let response = router().with_state(state).oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_details_report_session_store_up() {
+        let state = state()
+            .await
+            .with_session_store(Arc::new(MemoryStore::default()));
+        state.mark_ready();
+
+        let (status, body) = details(state).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            serde_json::json!({"status": "ok", "session_store": "up"})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_details_omit_unconfigured_session_store() {
+        let state = state().await;
+        state.mark_ready();
+
+        let (_, body) = details(state).await;
+        assert_eq!(body, serde_json::json!({"status": "ok"}));
+    }
+}
@@ -4,13 +4,36 @@
 
 use crate::state::AppState;
 use axum::Router;
+use domain::AuthMode;
 
+pub mod admin;
+pub mod api_keys;
 pub mod auth;
+pub mod bootstrap;
 pub mod config;
+pub mod fallback;
+pub mod health;
+pub mod users;
+
+/// Where [`api_v1_router`] is mounted
+pub const API_V1_PREFIX: &str = "/api/v1";
+
+/// Where [`api_v2_router`] is mounted
+pub const API_V2_PREFIX: &str = "/api/v2";
 
 /// Construct the API v1 router
-pub fn api_v1_router() -> Router<AppState> {
+pub fn api_v1_router(auth_mode: AuthMode) -> Router<AppState> {
     Router::new()
-        .nest("/auth", auth::router())
+        .nest("/admin", admin::router())
+        .nest("/auth", auth::router(auth_mode))
+        .nest("/api-keys", api_keys::router())
+        .nest("/bootstrap", bootstrap::router())
         .nest("/config", config::router())
+        .nest("/users", users::router())
+}
+
+/// Construct the API v2 router, holding only endpoints whose response
+/// changed incompatibly since v1
+pub fn api_v2_router() -> Router<AppState> {
+    Router::new().nest("/api-keys", api_keys::router_v2())
 }
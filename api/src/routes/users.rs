@@ -0,0 +1,99 @@
+use axum::{
+    Router,
+    extract::{Path, State},
+    response::IntoResponse,
+    routing::get,
+};
+use domain::Role;
+use uuid::Uuid;
+
+use crate::{
+    auth::require_role, dto::UserResponse, error::ApiError, json::ApiJson, state::AppState,
+};
+
+use super::API_V1_PREFIX;
+
+/// User routes
+pub fn router() -> Router<AppState> {
+    Router::new().route("/{id}", get(get_user))
+}
+
+/// Path of the user resource, as sent in `Location` headers
+pub fn location(id: Uuid) -> String {
+    format!("{}/users/{}", API_V1_PREFIX, id)
+}
+
+/// A user, visible to themselves and to admins
+async fn get_user(
+    State(state): State<AppState>,
+    auth_session: crate::auth::AuthSession,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    let current = auth_session
+        .user
+        .ok_or(ApiError::Unauthorized("Not logged in".to_string()))?;
+    if current.0.id != id {
+        require_role(Some(&current.0), Role::Admin)?;
+    }
+
+    let user = state.user_service.find_by_id(id).await?;
+    Ok(ApiJson(UserResponse {
+        id: user.id,
+        email: user.email.into_inner(),
+        created_at: user.created_at,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode, header};
+    use tower::ServiceExt;
+
+    use crate::test_support::build_test_app;
+
+    #[tokio::test]
+    async fn test_register_points_location_at_the_new_user() {
+        let (app, _) = build_test_app().await;
+        let register = Request::post("/api/v1/auth/register")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                r#"{"email": "alice@example.com", "password": "correct horse"}"#,
+            ))
+            .unwrap();
+
+        let response = app.clone().oneshot(register).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let location = response.headers()[header::LOCATION]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let cookie = response.headers()[header::SET_COOKIE]
+            .to_str()
+            .unwrap()
+            .split(';')
+            .next()
+            .unwrap()
+            .to_string();
+
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(
+            location,
+            format!("/api/v1/users/{}", body["id"].as_str().unwrap())
+        );
+
+        let get = Request::get(&location)
+            .header(header::COOKIE, cookie)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(get).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let anonymous = Request::get(&location).body(Body::empty()).unwrap();
+        let response = app.oneshot(anonymous).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
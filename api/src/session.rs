@@ -0,0 +1,263 @@
+//! Session cookie configuration
+//!
+//! Builds the session layer from config. An explicit cookie domain shares the
+//! session across subdomains (e.g. `app.example.com` and `api.example.com`),
+//! so it is validated up front rather than silently producing a cookie the
+//! browser drops.
+//!
+//! Also records who logged in to which session, for `/admin/sessions`,
+//! rotates session ids after sensitive account changes and keeps selected
+//! OIDC claims, which [`attach_claims`] puts on the [`AuthUser`].
+
+use std::net::IpAddr;
+
+use axum::extract::Request;
+use axum::http::{HeaderMap, header};
+use axum::middleware::Next;
+use axum::response::Response;
+use chrono::{DateTime, Utc};
+use domain::{SessionInfo, User, UserService};
+use infra::session_store::{Expiry, Session, SessionManagerLayer, SessionStore};
+use time::Duration;
+use uuid::Uuid;
+
+use crate::auth::{AuthSession, AuthUser, Claims};
+use crate::client_ip::ClientIp;
+use crate::config::Config;
+use crate::error::ApiError;
+
+/// Sessions expire after this long without a request
+const SESSION_INACTIVITY_DAYS: i64 = 7;
+
+/// Session key holding the claims kept from the user's ID token
+pub const OIDC_CLAIMS_KEY: &str = "oidc_claims";
+
+/// Keep `session` for `days` regardless of activity, for "remember me" logins
+pub fn remember(session: &Session, days: u32) {
+    session.set_expiry(Some(Expiry::AtDateTime(
+        time::OffsetDateTime::now_utc() + Duration::days(days.into()),
+    )));
+}
+
+/// Session layer with the cookie attributes from `config`
+pub fn session_layer<S: SessionStore>(
+    store: S,
+    config: &Config,
+) -> anyhow::Result<SessionManagerLayer<S>> {
+    let mut layer = SessionManagerLayer::new(store)
+        .with_secure(config.secure_cookie)
+        .with_expiry(Expiry::OnInactivity(Duration::days(
+            SESSION_INACTIVITY_DAYS,
+        )));
+
+    if let Some(domain) = &config.session_cookie_domain {
+        let domain = validate_cookie_domain(domain, &config.host)
+            .map_err(|e| anyhow::anyhow!("Invalid SESSION_COOKIE_DOMAIN: {}", e))?;
+        layer = layer.with_domain(domain);
+    }
+
+    if !config.session_cookie_path.starts_with('/') {
+        anyhow::bail!(
+            "Invalid SESSION_COOKIE_PATH: {} must start with '/'",
+            config.session_cookie_path
+        );
+    }
+    Ok(layer.with_path(config.session_cookie_path.clone()))
+}
+
+/// Normalize `domain` and check it can be set as a cookie domain.
+///
+/// The domain must be a registrable name (not a bare TLD or an IP), and when
+/// `host` is a hostname, `host` must be that domain or one of its subdomains.
+/// A leading dot is accepted and dropped; browsers ignore it.
+pub fn validate_cookie_domain(domain: &str, host: &str) -> Result<String, String> {
+    let domain = domain.trim().trim_start_matches('.').to_ascii_lowercase();
+
+    if domain.parse::<IpAddr>().is_ok() {
+        return Err(format!("{} is an IP address, not a domain", domain));
+    }
+    let labels: Vec<&str> = domain.split('.').collect();
+    let valid_label = |label: &&str| {
+        !label.is_empty()
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    if labels.len() < 2 || !labels.iter().all(valid_label) {
+        return Err(format!("{} is not a registrable domain", domain));
+    }
+
+    // Bind addresses (0.0.0.0, ::) say nothing about the public host
+    let host = host.trim().to_ascii_lowercase();
+    let is_hostname = host.parse::<IpAddr>().is_err() && host != "localhost";
+    if is_hostname && host != domain && !host.ends_with(&format!(".{}", domain)) {
+        return Err(format!("{} is not a suffix of the host {}", domain, host));
+    }
+
+    Ok(domain)
+}
+
+/// Record the session `user_id` just logged in to.
+///
+/// The session is saved first, since a login cycles the id and the new one is
+/// only assigned on save. Failures are logged and never fail the login.
+pub async fn record_login(
+    user_service: &UserService,
+    session: &Session,
+    user_id: Uuid,
+    ClientIp(ip): ClientIp,
+    headers: &HeaderMap,
+) {
+    if let Err(e) = session.save().await {
+        tracing::warn!(%user_id, "Failed to save session before recording it: {}", e);
+        return;
+    }
+    let Some(session_id) = session.id() else {
+        return;
+    };
+
+    let now = Utc::now();
+    let info = SessionInfo {
+        session_id: session_id.to_string(),
+        user_id,
+        ip: (!ip.is_unspecified()).then(|| ip.to_string()),
+        user_agent: headers
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from),
+        created_at: now,
+        expires_at: DateTime::from_timestamp(session.expiry_date().unix_timestamp(), 0)
+            .unwrap_or(now),
+    };
+    if let Err(e) = user_service.record_session(&info).await {
+        tracing::warn!(%user_id, "Failed to record session: {}", e);
+    }
+}
+
+/// Log `user` in, on a new session id.
+///
+/// axum-login cycles the id but keeps the session's data, so claims kept for
+/// whoever was signed in before are dropped first; an OIDC login stores its
+/// own afterwards with [`store_claims`].
+pub async fn login(auth_session: &mut AuthSession, user: &AuthUser) -> Result<(), ApiError> {
+    auth_session
+        .session
+        .remove::<Claims>(OIDC_CLAIMS_KEY)
+        .await
+        .map_err(|_| ApiError::SessionStoreUnavailable)?;
+    auth_session
+        .login(user)
+        .await
+        .map_err(|_| ApiError::Internal("Login failed".to_string()))
+}
+
+/// Move the signed-in user to a new session id after a sensitive change such
+/// as a new password, so an id captured earlier is worthless.
+///
+/// `user` is the changed user: the session is bound to their password hash,
+/// so logging them in again keeps them signed in.
+pub async fn rotate(
+    auth_session: &mut AuthSession,
+    user: User,
+    user_service: &UserService,
+    ip: ClientIp,
+    headers: &HeaderMap,
+) -> Result<(), ApiError> {
+    auth_session
+        .session
+        .cycle_id()
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    let user_id = user.id;
+    login(auth_session, &AuthUser::new(user)).await?;
+    record_login(user_service, &auth_session.session, user_id, ip, headers).await;
+    Ok(())
+}
+
+/// Keep claims from the provider in the session, after [`login`]; pass them
+/// through [`UserService::session_claims`] so only allowlisted ones are stored
+pub async fn store_claims(session: &Session, claims: Claims) -> Result<(), ApiError> {
+    session
+        .insert(OIDC_CLAIMS_KEY, claims)
+        .await
+        .map_err(|_| ApiError::SessionStoreUnavailable)
+}
+
+/// Middleware putting the claims kept at login on the signed-in [`AuthUser`],
+/// where handlers read them with [`AuthUser::claims`].
+///
+/// Must run inside the auth layer, which loads the user into the request.
+pub async fn attach_claims(mut request: Request, next: Next) -> Result<Response, ApiError> {
+    if let Some(auth_session) = request.extensions_mut().get_mut::<AuthSession>()
+        && let Some(user) = auth_session.user.take()
+    {
+        let claims = auth_session
+            .session
+            .get::<Claims>(OIDC_CLAIMS_KEY)
+            .await
+            .map_err(|_| ApiError::SessionStoreUnavailable)?
+            .unwrap_or_default();
+        auth_session.user = Some(user.with_claims(claims));
+    }
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, extract::Request, http::header, routing::get};
+    use infra::session_store::{MemoryStore, Session};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_layer_applies_cookie_domain_and_path() {
+        let config = Config {
+            session_cookie_domain: Some(".Example.com".to_string()),
+            session_cookie_path: "/api".to_string(),
+            ..Config::default()
+        };
+        let app = Router::new()
+            .route(
+                "/api/login",
+                get(|session: Session| async move {
+                    session.insert("user", 1).await.unwrap();
+                }),
+            )
+            .layer(session_layer(MemoryStore::default(), &config).unwrap());
+
+        let request = Request::get("/api/login").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+
+        assert!(cookie.starts_with("id="));
+        assert!(cookie.contains("Domain=example.com"));
+        assert!(cookie.contains("Path=/api"));
+    }
+
+    #[test]
+    fn test_invalid_cookie_settings_are_rejected() {
+        let config = |domain: &str, path: &str| Config {
+            session_cookie_domain: Some(domain.to_string()),
+            session_cookie_path: path.to_string(),
+            ..Config::default()
+        };
+
+        assert!(session_layer(MemoryStore::default(), &config("com", "/")).is_err());
+        assert!(session_layer(MemoryStore::default(), &config("example.com", "api")).is_err());
+    }
+
+    #[test]
+    fn test_domain_must_cover_the_host() {
+        assert_eq!(
+            validate_cookie_domain(".example.com", "api.example.com").unwrap(),
+            "example.com"
+        );
+        assert!(validate_cookie_domain("example.com", "example.com").is_ok());
+        assert!(validate_cookie_domain("example.com", "0.0.0.0").is_ok());
+
+        assert!(validate_cookie_domain("example.com", "api.other.org").is_err());
+        assert!(validate_cookie_domain("ample.com", "api.example.com").is_err());
+        assert!(validate_cookie_domain("127.0.0.1", "0.0.0.0").is_err());
+        assert!(validate_cookie_domain("exa_mple.com", "0.0.0.0").is_err());
+    }
+}
@@ -0,0 +1,153 @@
+//! Startup summary
+//!
+//! One log event once the server is listening, saying what actually started:
+//! where it listens, which database and features it was built with, how auth
+//! is set up, whether migrations ran and where the config came from.
+
+use std::net::SocketAddr;
+
+use domain::AuthMode;
+use infra::db::DatabaseBackend;
+use serde::Serialize;
+use tracing::info;
+
+use crate::config::Config;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StartupLogFormat {
+    #[default]
+    Human,
+    Json,
+}
+
+impl std::str::FromStr for StartupLogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "human" => Ok(StartupLogFormat::Human),
+            "json" => Ok(StartupLogFormat::Json),
+            other => Err(format!("Unknown startup log format: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationStatus {
+    /// Run at startup
+    Applied,
+    /// Run by a separate job and checked to be current
+    Verified,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StartupSummary {
+    pub address: SocketAddr,
+    /// `sqlite` or `postgres`, from `DATABASE_URL`
+    pub database: &'static str,
+    pub auth_mode: AuthMode,
+    /// Cargo features the binary was built with
+    pub features: Vec<&'static str>,
+    pub migrations: MigrationStatus,
+    /// `environment`, or the `.env` file read on top of it
+    pub config_source: String,
+}
+
+impl StartupSummary {
+    pub fn new(
+        config: &Config,
+        address: SocketAddr,
+        auth_mode: AuthMode,
+        migrations: MigrationStatus,
+    ) -> Self {
+        let database = DatabaseBackend::from_url(&config.database_url)
+            .unwrap_or(DatabaseBackend::Sqlite)
+            .name();
+        let config_source = match &config.env_file {
+            Some(path) => format!("environment + {}", path),
+            None => "environment".to_string(),
+        };
+
+        Self {
+            address,
+            database,
+            auth_mode,
+            features: enabled_features(),
+            migrations,
+            config_source,
+        }
+    }
+
+    pub fn log(&self, format: StartupLogFormat) {
+        match format {
+            StartupLogFormat::Human => info!(
+                address = %self.address,
+                database = self.database,
+                auth_mode = ?self.auth_mode,
+                features = %self.features.join(","),
+                migrations = ?self.migrations,
+                config_source = %self.config_source,
+                "API server running at http://{}",
+                self.address
+            ),
+            StartupLogFormat::Json => info!(
+                "{}",
+                serde_json::to_string(self).expect("startup summary serializes")
+            ),
+        }
+    }
+}
+
+fn enabled_features() -> Vec<&'static str> {
+    [
+        ("sqlite", cfg!(feature = "sqlite")),
+        ("postgres", cfg!(feature = "postgres")),
+        ("postgres-trgm", cfg!(feature = "postgres-trgm")),
+        ("auth-axum-login", cfg!(feature = "auth-axum-login")),
+        ("captcha", cfg!(feature = "captcha")),
+        ("totp", cfg!(feature = "totp")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_reports_what_started() {
+        let config = Config {
+            database_url: "postgres://db.internal/app".to_string(),
+            env_file: Some("/srv/app/.env".to_string()),
+            ..Config::default()
+        };
+        let address: SocketAddr = "0.0.0.0:8080".parse().unwrap();
+
+        let summary =
+            StartupSummary::new(&config, address, AuthMode::Oidc, MigrationStatus::Verified);
+        let json = serde_json::to_value(&summary).unwrap();
+
+        assert_eq!(json["address"], "0.0.0.0:8080");
+        assert_eq!(json["database"], "postgres");
+        assert_eq!(json["auth_mode"], "oidc");
+        assert_eq!(json["migrations"], "verified");
+        assert_eq!(json["config_source"], "environment + /srv/app/.env");
+        let features = json["features"].as_array().unwrap();
+        assert_eq!(
+            features.iter().any(|f| f == "sqlite"),
+            cfg!(feature = "sqlite")
+        );
+
+        let summary = StartupSummary::new(
+            &Config::default(),
+            address,
+            AuthMode::Both,
+            MigrationStatus::Applied,
+        );
+        assert_eq!(summary.database, "sqlite");
+        assert_eq!(summary.config_source, "environment");
+    }
+}
@@ -4,23 +4,93 @@
 
 use axum::extract::FromRef;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
+use crate::cache::TtlCache;
+use crate::client_ip::ClientIpSource;
 use crate::config::Config;
-use domain::UserService;
+use crate::dto::ConfigResponse;
+use crate::json::{JsonContentType, JsonStrictness};
+use crate::middleware::abuse::AbuseMonitor;
+use crate::rate_limit::RateLimiter;
+use domain::{AuthMode, CaptchaGuard, OidcProvider, UserService};
+use infra::session_store::SessionStoreHealth;
 
 #[derive(Clone)]
 pub struct AppState {
     pub user_service: Arc<UserService>,
     pub config: Arc<Config>,
+    pub captcha: Option<Arc<CaptchaGuard>>,
+    /// Serves `/auth/oidc/*`; unset when OIDC isn't configured
+    pub oidc: Option<Arc<dyn OidcProvider>>,
+    pub auth_mode: AuthMode,
+    /// Checked by `/health/details`
+    pub session_store: Option<Arc<dyn SessionStoreHealth>>,
+    /// Per-client traffic, reported at `/admin/abuse`
+    pub abuse_monitor: Arc<AbuseMonitor>,
+    /// Throttles `/auth/email-available` per client
+    pub email_check_limiter: Arc<RateLimiter>,
+    /// Set once startup has finished; `/health` reports `starting` until then
+    pub ready: Arc<AtomicBool>,
+    /// Body of `/config`, cached for `config_cache_secs`
+    pub config_cache: Arc<TtlCache<ConfigResponse>>,
 }
 
 impl AppState {
     pub fn new(user_service: UserService, config: Config) -> Self {
+        let abuse_monitor = AbuseMonitor::new(
+            Duration::from_secs(config.abuse_window_secs),
+            config.abuse_max_clients,
+        );
+        let email_check_limiter = RateLimiter::new(
+            config.email_check_limit,
+            Duration::from_secs(config.email_check_window_secs),
+            config.abuse_max_clients,
+        );
+        let config_cache = TtlCache::new(Duration::from_secs(config.config_cache_secs));
         Self {
             user_service: Arc::new(user_service),
             config: Arc::new(config),
+            captcha: None,
+            oidc: None,
+            auth_mode: AuthMode::default(),
+            session_store: None,
+            abuse_monitor: Arc::new(abuse_monitor),
+            email_check_limiter: Arc::new(email_check_limiter),
+            ready: Arc::new(AtomicBool::new(false)),
+            config_cache: Arc::new(config_cache),
         }
     }
+
+    pub fn mark_ready(&self) {
+        self.ready.store(true, Ordering::Release);
+    }
+
+    /// Require a CAPTCHA token on registration
+    pub fn with_captcha(mut self, captcha: CaptchaGuard) -> Self {
+        self.captcha = Some(Arc::new(captcha));
+        self
+    }
+
+    /// Sign users in through an OIDC provider
+    pub fn with_oidc(mut self, oidc: Arc<dyn OidcProvider>) -> Self {
+        self.oidc = Some(oidc);
+        self
+    }
+
+    pub fn with_auth_mode(mut self, auth_mode: AuthMode) -> Self {
+        self.auth_mode = auth_mode;
+        // `/config` reports the auth mode
+        self.config_cache.invalidate();
+        self
+    }
+
+    /// Report the session store in the detailed health check
+    pub fn with_session_store(mut self, store: Arc<dyn SessionStoreHealth>) -> Self {
+        self.session_store = Some(store);
+        self
+    }
 }
 
 impl FromRef<AppState> for Arc<UserService> {
@@ -34,3 +104,33 @@ impl FromRef<AppState> for Arc<Config> {
         input.config.clone()
     }
 }
+
+impl FromRef<AppState> for JsonStrictness {
+    fn from_ref(input: &AppState) -> Self {
+        if input.config.strict_json {
+            JsonStrictness::Strict
+        } else {
+            JsonStrictness::Lenient
+        }
+    }
+}
+
+impl FromRef<AppState> for JsonContentType {
+    fn from_ref(input: &AppState) -> Self {
+        if input.config.require_json_content_type {
+            JsonContentType::Required
+        } else {
+            JsonContentType::Any
+        }
+    }
+}
+
+impl FromRef<AppState> for ClientIpSource {
+    fn from_ref(input: &AppState) -> Self {
+        if input.config.trust_forwarded_for {
+            ClientIpSource::XForwardedFor
+        } else {
+            ClientIpSource::Peer
+        }
+    }
+}
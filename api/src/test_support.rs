@@ -0,0 +1,308 @@
+//! Helpers for handler and integration tests, behind the `test-utils` feature
+//!
+//! [`build_test_app`] assembles the same router `main` serves, backed by an
+//! in-memory SQLite database, so tests can drive it with
+//! `tower::ServiceExt::oneshot` instead of a listening server. [`TestClient`]
+//! wraps it for tests that need a session across requests.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::Router;
+use axum::body::{Body, Bytes};
+use axum::http::{HeaderMap, HeaderValue, Request, StatusCode, header};
+use domain::{ClaimMapping, DisposableEmailWarning, LoginPolicy, UserService, WeakPasswordWarning};
+use infra::db::{ConnectionSettings, DatabaseConfig, DatabasePool, create_pool};
+use infra::factory::{
+    build_api_key_repository, build_audit_log_repository, build_email_verification_repository,
+    build_invite_repository, build_password_reset_repository, build_session_repository,
+    build_session_store, build_user_repository,
+};
+use infra::session_store::DegradingSessionStore;
+use infra::{LoggingEmailSender, LoggingEventPublisher, run_migrations};
+use tower::ServiceExt;
+
+use crate::auth::{PasswordHashPolicy, setup_auth_layer};
+use crate::config::Config;
+use crate::middleware::session_transport::SessionTransport;
+use crate::state::AppState;
+
+/// Router and state over a fresh, migrated in-memory database
+///
+/// Password hashing uses cheap Argon2 parameters to keep tests fast. Panics
+/// on setup failure, as a test could not continue anyway.
+pub async fn build_test_app() -> (Router, AppState) {
+    build_test_app_on(test_pool().await).await
+}
+
+/// Fresh, migrated in-memory database, for tests that seed rows before
+/// calling [`build_test_app_on`]
+pub async fn test_pool() -> DatabasePool {
+    // One connection: every connection to `sqlite::memory:` is its own database
+    let pool = create_pool(
+        DatabaseConfig {
+            url: "sqlite::memory:".to_string(),
+            max_connections: 1,
+            min_connections: 1,
+            acquire_timeout: Duration::from_secs(30),
+        },
+        &ConnectionSettings::default(),
+    )
+    .await
+    .expect("test database");
+    run_migrations(&pool).await.expect("migrations");
+    pool
+}
+
+/// Like [`build_test_app`], over an existing database
+pub async fn build_test_app_on(pool: DatabasePool) -> (Router, AppState) {
+    build_test_app_with(pool, test_config()).await
+}
+
+/// Configuration [`build_test_app`] uses, as a base for [`build_test_app_with`]
+pub fn test_config() -> Config {
+    Config {
+        database_url: "sqlite::memory:".to_string(),
+        secure_cookie: false,
+        ..Config::default()
+    }
+}
+
+/// Like [`build_test_app_on`], with a custom configuration
+pub async fn build_test_app_with(pool: DatabasePool, config: Config) -> (Router, AppState) {
+    let user_repo = build_user_repository(&pool).await.expect("user repository");
+    let password_policy = PasswordHashPolicy {
+        memory_kib: 8,
+        iterations: 1,
+        parallelism: 1,
+        rehash_on_login: false,
+    };
+    let user_service = UserService::new(
+        user_repo.clone(),
+        build_api_key_repository(&pool)
+            .await
+            .expect("api key repository"),
+        build_audit_log_repository(&pool)
+            .await
+            .expect("audit log repository"),
+    )
+    .with_password_hasher(Arc::new(password_policy))
+    .with_first_user_admin(config.first_user_is_admin)
+    .with_email_canonicalization(crate::email_canonicalization(&config))
+    .with_claim_mapping(ClaimMapping {
+        subject_claim: config.oidc_subject_claim.clone(),
+        email_claim: config.oidc_email_claim.clone(),
+        name_claim: config.oidc_name_claim.clone(),
+        session_claims: config.oidc_session_claims.clone(),
+    })
+    .with_registration_warning(Arc::new(DisposableEmailWarning::default()))
+    .with_registration_warning(Arc::new(WeakPasswordWarning))
+    .with_password_reset(
+        build_password_reset_repository(&pool)
+            .await
+            .expect("password reset repository"),
+        Arc::new(LoggingEmailSender),
+        chrono::Duration::minutes(config.reset_token_ttl_minutes as i64),
+        config.password_reset_url.clone(),
+    )
+    .with_email_verification(
+        build_email_verification_repository(&pool)
+            .await
+            .expect("email verification repository"),
+        Arc::new(LoggingEmailSender),
+        chrono::Duration::minutes(config.verification_token_ttl_minutes as i64),
+        chrono::Duration::seconds(config.verification_resend_cooldown_secs as i64),
+        config.verification_url.clone(),
+    )
+    .with_invites(
+        build_invite_repository(&pool)
+            .await
+            .expect("invite repository"),
+        config.require_invite_code,
+    )
+    .with_session_repository(
+        build_session_repository(&pool)
+            .await
+            .expect("session repository"),
+    );
+    // Enabled by setting `totp_encryption_key`, as in `main`
+    let user_service = crate::configure_totp(user_service, &config).expect("TOTP setup");
+
+    let session_store = build_session_store(&pool).await.expect("session store");
+    let mut state = AppState::new(user_service, config.clone())
+        .with_session_store(Arc::new(session_store.clone()));
+    // Enabled by setting the `oidc_*` endpoints and client, as in `main`
+    if let Some(oidc) = crate::build_oidc_provider(&config).expect("OIDC setup") {
+        state = state.with_oidc(oidc);
+    }
+    state.mark_ready();
+
+    let session_layer =
+        crate::session::session_layer(DegradingSessionStore::new(session_store), &config)
+            .expect("session layer");
+    let auth_layer = setup_auth_layer(
+        session_layer,
+        user_repo,
+        LoginPolicy::new(config.require_verified_email),
+        password_policy,
+        Arc::new(LoggingEventPublisher),
+    )
+    .await
+    .expect("auth layer");
+    let session_transport: SessionTransport =
+        config.session_transport.parse().expect("session transport");
+
+    let app = crate::app_router(state.clone(), auth_layer, &config, session_transport);
+    (app, state)
+}
+
+/// A response with its body already read
+#[derive(Debug)]
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl TestResponse {
+    /// The body parsed as JSON; panics if it is not
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_slice(&self.body).expect("JSON response body")
+    }
+}
+
+/// Sends requests to a test app like a browser would, replaying the cookies
+/// it was given, so a session started by [`login`](Self::login) carries over
+pub struct TestClient {
+    app: Router,
+    cookies: BTreeMap<String, String>,
+}
+
+impl TestClient {
+    pub fn new(app: Router) -> Self {
+        Self {
+            app,
+            cookies: BTreeMap::new(),
+        }
+    }
+
+    /// Log in with a password; panics unless it succeeds
+    pub async fn login(&mut self, email: &str, password: &str) -> TestResponse {
+        let credentials = serde_json::json!({ "email": email, "password": password });
+        let response = self.post_json("/api/v1/auth/login", credentials).await;
+        assert_eq!(
+            response.status,
+            StatusCode::OK,
+            "login failed: {:?}",
+            response
+        );
+        response
+    }
+
+    pub async fn get(&mut self, uri: &str) -> TestResponse {
+        self.send(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+    }
+
+    pub async fn post_json(&mut self, uri: &str, body: serde_json::Value) -> TestResponse {
+        let request = Request::post(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        self.send(request).await
+    }
+
+    /// Send any request with the stored cookies, keeping the ones it sets
+    pub async fn send(&mut self, mut request: Request<Body>) -> TestResponse {
+        if !self.cookies.is_empty() {
+            let cookies = self
+                .cookies
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect::<Vec<_>>()
+                .join("; ");
+            let value = HeaderValue::from_str(&cookies).expect("cookie header");
+            request.headers_mut().insert(header::COOKIE, value);
+        }
+
+        let response = self.app.clone().oneshot(request).await.unwrap();
+        for set_cookie in response.headers().get_all(header::SET_COOKIE) {
+            let pair = set_cookie.to_str().unwrap().split(';').next().unwrap();
+            if let Some((name, value)) = pair.trim().split_once('=') {
+                if value.is_empty() {
+                    self.cookies.remove(name);
+                } else {
+                    self.cookies.insert(name.to_string(), value.to_string());
+                }
+            }
+        }
+
+        let (parts, body) = response.into_parts();
+        TestResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body: axum::body::to_bytes(body, usize::MAX).await.unwrap(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use domain::SessionFilter;
+
+    fn json(uri: &str, body: serde_json::Value) -> Request<Body> {
+        Request::post(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_login_sets_a_session_cookie() {
+        let (app, state) = build_test_app().await;
+        let credentials = serde_json::json!({
+            "email": "alice@example.com",
+            "password": "correct horse",
+        });
+
+        let response = app
+            .clone()
+            .oneshot(json("/api/v1/auth/register", credentials.clone()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = app
+            .clone()
+            .oneshot(json("/api/v1/auth/login", credentials))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let cookie = response
+            .headers()
+            .get(header::SET_COOKIE)
+            .expect("session cookie")
+            .to_str()
+            .unwrap()
+            .split(';')
+            .next()
+            .unwrap()
+            .to_string();
+
+        let me = Request::post("/api/v1/auth/me")
+            .header(header::COOKIE, cookie)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(me).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let (sessions, _) = state
+            .user_service
+            .list_sessions(&SessionFilter::default(), 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(sessions.len(), 2);
+    }
+}
@@ -0,0 +1,177 @@
+//! Timestamp serialization for API responses
+//!
+//! Response timestamps are RFC 3339 with millisecond precision, in UTC
+//! (`2024-01-02T03:04:05.000Z`) unless a display zone is configured, or Unix
+//! epoch milliseconds when the request asks for them. UUIDs are always
+//! hyphenated lowercase strings.
+//!
+//! The style is chosen per request by the
+//! [`timestamp_format`](crate::middleware::timestamp_format) middleware.
+
+use std::future::Future;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use chrono_tz::Tz;
+use serde::Serializer;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimestampFormat {
+    #[default]
+    Rfc3339,
+    EpochMillis,
+}
+
+impl std::str::FromStr for TimestampFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('-', "_").as_str() {
+            "rfc3339" => Ok(TimestampFormat::Rfc3339),
+            "epoch_millis" => Ok(TimestampFormat::EpochMillis),
+            other => Err(format!("Unknown timestamp format: {}", other)),
+        }
+    }
+}
+
+/// How response timestamps are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimestampStyle {
+    pub format: TimestampFormat,
+    /// Zone RFC 3339 timestamps are shown in, with its offset
+    pub zone: Tz,
+}
+
+impl Default for TimestampStyle {
+    fn default() -> Self {
+        TimestampFormat::default().into()
+    }
+}
+
+impl From<TimestampFormat> for TimestampStyle {
+    fn from(format: TimestampFormat) -> Self {
+        Self {
+            format,
+            zone: Tz::UTC,
+        }
+    }
+}
+
+/// Parse an IANA zone name such as `Europe/Warsaw`
+pub fn parse_zone(name: &str) -> Result<Tz, String> {
+    name.parse()
+        .map_err(|_| format!("Unknown time zone: {}", name))
+}
+
+tokio::task_local! {
+    static STYLE: TimestampStyle;
+}
+
+/// Run `f` with responses serializing timestamps in `style`
+pub async fn scope<F: Future>(style: impl Into<TimestampStyle>, f: F) -> F::Output {
+    STYLE.scope(style.into(), f).await
+}
+
+/// The style of the current request, to carry into work that outlives
+/// its handler, such as a streamed body
+pub fn current() -> TimestampStyle {
+    STYLE.try_with(|style| *style).unwrap_or_default()
+}
+
+/// Like [`scope`], for synchronous work
+pub fn scope_sync<R>(style: TimestampStyle, f: impl FnOnce() -> R) -> R {
+    STYLE.sync_scope(style, f)
+}
+
+/// `serialize_with` for `DateTime<Utc>`
+pub fn serialize<S: Serializer>(value: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+    let style = current();
+    match style.format {
+        TimestampFormat::Rfc3339 => serializer.serialize_str(
+            &value
+                .with_timezone(&style.zone)
+                .to_rfc3339_opts(SecondsFormat::Millis, true),
+        ),
+        TimestampFormat::EpochMillis => serializer.serialize_i64(value.timestamp_millis()),
+    }
+}
+
+/// `serialize_with` for `Option<DateTime<Utc>>`
+pub fn serialize_option<S: Serializer>(
+    value: &Option<DateTime<Utc>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(value) => serialize(value, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dto::UserResponse;
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    fn user() -> UserResponse {
+        UserResponse {
+            id: Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap(),
+            email: "user@example.com".to_string(),
+            created_at: Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_default_is_rfc3339() {
+        let json = serde_json::to_value(user()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "id": "67e55044-10b1-426f-9247-bb680e5fe0c8",
+                "email": "user@example.com",
+                "created_at": "2024-01-02T03:04:05.000Z",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_epoch_millis() {
+        let json = scope(TimestampFormat::EpochMillis, async {
+            serde_json::to_value(user()).unwrap()
+        })
+        .await;
+        assert_eq!(json["created_at"], 1_704_164_645_000_i64);
+        assert_eq!(json["id"], "67e55044-10b1-426f-9247-bb680e5fe0c8");
+    }
+
+    #[tokio::test]
+    async fn test_rfc3339_in_configured_zone() {
+        let style = TimestampStyle {
+            format: TimestampFormat::Rfc3339,
+            zone: parse_zone("Europe/Warsaw").unwrap(),
+        };
+        let json = scope(style, async { serde_json::to_value(user()).unwrap() }).await;
+        assert_eq!(json["created_at"], "2024-01-02T04:04:05.000+01:00");
+
+        // Daylight saving time moves the offset
+        let mut summer = user();
+        summer.created_at = Utc.with_ymd_and_hms(2024, 7, 2, 3, 4, 5).unwrap();
+        let json = scope(style, async { serde_json::to_value(summer).unwrap() }).await;
+        assert_eq!(json["created_at"], "2024-07-02T05:04:05.000+02:00");
+
+        assert!(parse_zone("Mars/Olympus").is_err());
+    }
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!(
+            "epoch-millis".parse::<TimestampFormat>(),
+            Ok(TimestampFormat::EpochMillis)
+        );
+        assert_eq!(
+            "RFC3339".parse::<TimestampFormat>(),
+            Ok(TimestampFormat::Rfc3339)
+        );
+        assert!("unix".parse::<TimestampFormat>().is_err());
+    }
+}
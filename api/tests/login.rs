@@ -0,0 +1,29 @@
+//! Drives the app the way a downstream integration test would, through the
+//! `test-utils` exports
+
+use api::test_support::{TestClient, build_test_app};
+use axum::http::StatusCode;
+
+#[tokio::test]
+async fn test_client_keeps_the_session() {
+    let (app, _) = build_test_app().await;
+    let mut client = TestClient::new(app);
+
+    let register = client
+        .post_json(
+            "/api/v1/auth/register",
+            serde_json::json!({ "email": "alice@example.com", "password": "correct horse" }),
+        )
+        .await;
+    assert_eq!(register.status, StatusCode::CREATED);
+    client
+        .post_json("/api/v1/auth/logout", serde_json::json!({}))
+        .await;
+
+    client.login("alice@example.com", "correct horse").await;
+    let me = client
+        .post_json("/api/v1/auth/me", serde_json::json!({}))
+        .await;
+    assert_eq!(me.status, StatusCode::OK);
+    assert_eq!(me.json()["email"], "alice@example.com");
+}
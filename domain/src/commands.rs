@@ -0,0 +1,61 @@
+//! Commands
+//!
+//! Validated inputs for service operations. Adapters convert their request
+//! types into these, so validation happens once, at the boundary.
+
+use crate::value_objects::{DisplayName, Email, Password, ValidationError};
+
+/// Longest password accepted at login; anything longer can't be a real one
+pub const MAX_LOGIN_PASSWORD_LENGTH: usize = 1024;
+
+/// Register a local account
+#[derive(Debug, Clone)]
+pub struct NewUserCommand {
+    pub email: Email,
+    pub password: Password,
+    /// Needed when registration is invite-only
+    pub invite_code: Option<String>,
+}
+
+/// Change a user's profile; fields left `None` stay as they are
+#[derive(Debug, Clone, Default)]
+pub struct UpdateProfileCommand {
+    /// `Some(None)` removes the name
+    pub display_name: Option<Option<DisplayName>>,
+}
+
+/// Log in with email and password.
+///
+/// The password is taken as typed: the [`Password`] policy only applies when
+/// one is set, so tightening it never locks out existing accounts.
+#[derive(Clone)]
+pub struct LoginCommand {
+    pub email: Email,
+    pub password: String,
+}
+
+impl LoginCommand {
+    /// Rejects only empty and absurdly long passwords
+    pub fn new(email: Email, password: String) -> Result<Self, ValidationError> {
+        if password.is_empty() {
+            return Err(ValidationError::EmptyPassword);
+        }
+        if password.len() > MAX_LOGIN_PASSWORD_LENGTH {
+            return Err(ValidationError::PasswordTooLong {
+                max: MAX_LOGIN_PASSWORD_LENGTH,
+                actual: password.len(),
+            });
+        }
+        Ok(Self { email, password })
+    }
+}
+
+// Keep the password out of logs
+impl std::fmt::Debug for LoginCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoginCommand")
+            .field("email", &self.email)
+            .field("password", &"***")
+            .finish()
+    }
+}
@@ -3,9 +3,12 @@
 //! This module contains pure domain types with no I/O dependencies.
 //! These represent the core business concepts of the application.
 
-pub use crate::value_objects::{Email, UserId};
+use crate::errors::{DomainError, DomainResult};
+pub use crate::value_objects::{ApiKeyId, Email, Role, UserId};
+use crate::value_objects::{DisplayName, ValidationError};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
 /// A user in the system.
@@ -14,47 +17,197 @@ use uuid::Uuid;
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub id: UserId,
+    /// Stored and compared exactly as issued. OIDC subjects are case-sensitive,
+    /// so unlike `email` this is never lowercased, trimmed or otherwise normalized.
     pub subject: String,
     pub email: Email,
+    /// The email in the form uniqueness is checked on, set when the account
+    /// was registered under [`EmailCanonicalization`](crate::EmailCanonicalization)
+    pub canonical_email: Option<String>,
     pub password_hash: Option<String>,
+    pub email_verified: bool,
+    pub role: Role,
     pub created_at: DateTime<Utc>,
+    pub last_login_at: Option<DateTime<Utc>>,
+    /// Set when the account has been soft-deleted
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Encrypted TOTP secret; present once enrollment has started
+    pub totp_secret: Option<String>,
+    /// Whether login requires a TOTP code
+    pub totp_enabled: bool,
+    /// Settings the app keeps for the user; always a JSON object
+    #[serde(default = "no_preferences")]
+    pub preferences: serde_json::Value,
+    /// Shown in place of the email, once the user picks one
+    #[serde(default)]
+    pub display_name: Option<DisplayName>,
+}
+
+/// Maximum length of an OIDC subject; longer values are rejected rather than stored
+pub const MAX_SUBJECT_LENGTH: usize = 255;
+
+/// Maximum serialized size of a user's preferences
+pub const MAX_PREFERENCES_BYTES: usize = 16 * 1024;
+
+/// Preferences of a user who hasn't set any
+fn no_preferences() -> serde_json::Value {
+    serde_json::Value::Object(serde_json::Map::new())
 }
 
 impl User {
-    pub fn new(subject: impl Into<String>, email: Email) -> Self {
-        Self {
+    pub fn new(subject: impl Into<String>, email: Email) -> Result<Self, ValidationError> {
+        let subject = subject.into();
+        Self::check_subject(&subject)?;
+
+        Ok(Self {
             id: Uuid::new_v4(),
-            subject: subject.into(),
+            subject,
             email,
+            canonical_email: None,
             password_hash: None,
+            email_verified: false,
+            role: Role::User,
             created_at: Utc::now(),
+            last_login_at: None,
+            deleted_at: None,
+            totp_secret: None,
+            totp_enabled: false,
+            preferences: no_preferences(),
+            display_name: None,
+        })
+    }
+
+    /// Check that `subject` is non-empty and fits within [`MAX_SUBJECT_LENGTH`]
+    pub fn check_subject(subject: &str) -> Result<(), ValidationError> {
+        if subject.is_empty() {
+            return Err(ValidationError::EmptySubject);
+        }
+        if subject.len() > MAX_SUBJECT_LENGTH {
+            return Err(ValidationError::SubjectTooLong {
+                max: MAX_SUBJECT_LENGTH,
+                actual: subject.len(),
+            });
         }
+        Ok(())
     }
 
-    pub fn with_id(
+    /// Rebuild a stored user, checking the invariants [`new`](Self::new)
+    /// enforces, so a corrupt row fails at the repository instead of
+    /// turning into an invalid `User`
+    pub fn reconstitute(
         id: Uuid,
         subject: impl Into<String>,
         email: Email,
         password_hash: Option<String>,
+        email_verified: bool,
+        role: Role,
         created_at: DateTime<Utc>,
-    ) -> Self {
-        Self {
+    ) -> DomainResult<Self> {
+        let subject = subject.into();
+        Self::check_subject(&subject).map_err(|e| {
+            DomainError::RepositoryError(format!("Invalid subject in DB for user {}: {}", id, e))
+        })?;
+
+        Ok(Self {
             id,
-            subject: subject.into(),
+            subject,
             email,
+            canonical_email: None,
             password_hash,
+            email_verified,
+            role,
             created_at,
-        }
+            last_login_at: None,
+            deleted_at: None,
+            totp_secret: None,
+            totp_enabled: false,
+            preferences: no_preferences(),
+            display_name: None,
+        })
     }
 
+    /// Local users get a generated subject, which always fits
     pub fn new_local(email: Email, password_hash: impl Into<String>) -> Self {
         Self {
             id: Uuid::new_v4(),
             subject: format!("local|{}", Uuid::new_v4()),
             email,
+            canonical_email: None,
             password_hash: Some(password_hash.into()),
+            email_verified: false,
+            role: Role::User,
             created_at: Utc::now(),
+            last_login_at: None,
+            deleted_at: None,
+            totp_secret: None,
+            totp_enabled: false,
+            preferences: no_preferences(),
+            display_name: None,
+        }
+    }
+
+    /// Whether the user signs in with a local password (as opposed to OIDC)
+    pub fn is_local(&self) -> bool {
+        self.password_hash.is_some()
+    }
+
+    /// Whether the subject comes from a sign-in provider rather than being
+    /// generated for a local account
+    pub fn has_provider_subject(&self) -> bool {
+        !self.subject.starts_with("local|")
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.role == Role::Admin
+    }
+
+    /// Whether login requires a second, TOTP step
+    pub fn requires_totp(&self) -> bool {
+        self.totp_enabled && self.totp_secret.is_some()
+    }
+
+    pub fn mark_email_verified(&mut self) {
+        self.email_verified = true;
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+
+    /// Swap the email of a deleted account for a placeholder, so a new account
+    /// can take the address without tripping the unique email index
+    pub fn release_email(&mut self) -> Result<(), ValidationError> {
+        self.email = Email::new(format!("{}@deleted.invalid", self.id.simple()))?;
+        self.canonical_email = None;
+        Ok(())
+    }
+
+    /// Swap the subject for a generated local one, so another account can be
+    /// linked to the provider identity
+    pub fn release_subject(&mut self) {
+        self.subject = format!("local|{}", Uuid::new_v4());
+    }
+
+    /// The last moment the account was known to be in use
+    pub fn last_active_at(&self) -> DateTime<Utc> {
+        self.last_login_at.unwrap_or(self.created_at)
+    }
+
+    /// Replace the user's preferences with `preferences`, which must be a JSON
+    /// object of at most [`MAX_PREFERENCES_BYTES`] once serialized
+    pub fn set_preferences(&mut self, preferences: serde_json::Value) -> DomainResult<()> {
+        if !preferences.is_object() {
+            return Err(DomainError::validation("Preferences must be a JSON object"));
+        }
+        let size = preferences.to_string().len();
+        if size > MAX_PREFERENCES_BYTES {
+            return Err(DomainError::validation(format!(
+                "Preferences must be at most {} bytes, got {}",
+                MAX_PREFERENCES_BYTES, size
+            )));
         }
+        self.preferences = preferences;
+        Ok(())
     }
 
     /// Helper to get email as string
@@ -62,3 +215,411 @@ impl User {
         self.email.as_ref()
     }
 }
+
+/// An API key for programmatic access on behalf of a user.
+///
+/// Only the SHA-256 hash of the key is stored; the raw key is handed out once at creation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: ApiKeyId,
+    pub user_id: UserId,
+    pub key_hash: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKey {
+    /// Generate a new key for the user, returning the entity and the raw key
+    pub fn generate(user_id: UserId, expires_at: Option<DateTime<Utc>>) -> (Self, String) {
+        let raw_key = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let key = Self {
+            id: Uuid::new_v4(),
+            user_id,
+            key_hash: Self::hash_key(&raw_key),
+            created_at: Utc::now(),
+            expires_at,
+            last_used_at: None,
+        };
+
+        (key, raw_key)
+    }
+
+    /// Hash a raw key the same way it is stored
+    pub fn hash_key(raw_key: &str) -> String {
+        format!("{:x}", Sha256::digest(raw_key.as_bytes()))
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// A single-use token letting a user choose a new password.
+///
+/// As with API keys, only the SHA-256 hash is stored; the raw token is handed out once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasswordResetToken {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub token_hash: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl PasswordResetToken {
+    /// Generate a token valid for `ttl`, returning the entity and the raw token
+    pub fn generate(user_id: UserId, ttl: chrono::Duration) -> (Self, String) {
+        let raw_token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let created_at = Utc::now();
+        let token = Self {
+            id: Uuid::new_v4(),
+            user_id,
+            token_hash: Self::hash_token(&raw_token),
+            created_at,
+            expires_at: created_at + ttl,
+        };
+
+        (token, raw_token)
+    }
+
+    /// Hash a raw token the same way it is stored
+    pub fn hash_token(raw_token: &str) -> String {
+        format!("{:x}", Sha256::digest(raw_token.as_bytes()))
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at <= now
+    }
+}
+
+/// A single-use token confirming that a user owns their email address.
+///
+/// Stored hashed like [`PasswordResetToken`]; `created_at` of the newest one
+/// also paces how often a new verification email can be sent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailVerificationToken {
+    pub id: Uuid,
+    pub user_id: UserId,
+    pub token_hash: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl EmailVerificationToken {
+    /// Generate a token valid for `ttl`, returning the entity and the raw token
+    pub fn generate(user_id: UserId, ttl: chrono::Duration) -> (Self, String) {
+        let raw_token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let created_at = Utc::now();
+        let token = Self {
+            id: Uuid::new_v4(),
+            user_id,
+            token_hash: Self::hash_token(&raw_token),
+            created_at,
+            expires_at: created_at + ttl,
+        };
+
+        (token, raw_token)
+    }
+
+    /// Hash a raw token the same way it is stored
+    pub fn hash_token(raw_token: &str) -> String {
+        format!("{:x}", Sha256::digest(raw_token.as_bytes()))
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at <= now
+    }
+}
+
+/// The one-time values of an OIDC login, kept in the session from the
+/// redirect to the provider until its callback.
+///
+/// `state` ties the callback to this browser, `nonce` the ID token to this
+/// login, and `code_verifier` (PKCE) the code to whoever started it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OidcLoginAttempt {
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+impl OidcLoginAttempt {
+    pub fn generate() -> Self {
+        Self {
+            state: Uuid::new_v4().simple().to_string(),
+            nonce: Uuid::new_v4().simple().to_string(),
+            // 64 characters, within the 43 to 128 PKCE asks for
+            code_verifier: format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple()),
+        }
+    }
+}
+
+/// A single-use code letting someone register while invites are required
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InviteCode {
+    pub code: String,
+    /// The admin who minted it
+    pub created_by: UserId,
+    pub created_at: DateTime<Utc>,
+    /// The account registered with it, once used
+    pub used_by: Option<UserId>,
+    pub used_at: Option<DateTime<Utc>>,
+}
+
+impl InviteCode {
+    /// A fresh, unused code
+    pub fn generate(created_by: UserId) -> Self {
+        Self {
+            code: Uuid::new_v4().simple().to_string(),
+            created_by,
+            created_at: Utc::now(),
+            used_by: None,
+            used_at: None,
+        }
+    }
+
+    pub fn is_used(&self) -> bool {
+        self.used_by.is_some()
+    }
+}
+
+/// A logged-in session, as shown to admins.
+///
+/// Client details are whatever was recorded at login; either may be missing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionInfo {
+    /// The session store's id for the session
+    pub session_id: String,
+    pub user_id: UserId,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Filters for listing sessions; unset fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct SessionFilter {
+    pub user_id: Option<UserId>,
+}
+
+/// Actions recorded in the audit log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    ImpersonationStarted,
+    ImpersonationStopped,
+    UserSoftDeleted,
+    UserErased,
+    /// The actor absorbed the target, a duplicate account
+    AccountsMerged,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::ImpersonationStarted => "impersonation_started",
+            AuditAction::ImpersonationStopped => "impersonation_stopped",
+            AuditAction::UserSoftDeleted => "user_soft_deleted",
+            AuditAction::UserErased => "user_erased",
+            AuditAction::AccountsMerged => "accounts_merged",
+        }
+    }
+}
+
+impl std::str::FromStr for AuditAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "impersonation_started" => Ok(AuditAction::ImpersonationStarted),
+            "impersonation_stopped" => Ok(AuditAction::ImpersonationStopped),
+            "user_soft_deleted" => Ok(AuditAction::UserSoftDeleted),
+            "user_erased" => Ok(AuditAction::UserErased),
+            "accounts_merged" => Ok(AuditAction::AccountsMerged),
+            other => Err(format!("Unknown audit action: {}", other)),
+        }
+    }
+}
+
+/// Actor recorded for actions performed by the system itself (e.g. background jobs)
+pub const SYSTEM_ACTOR_ID: UserId = Uuid::nil();
+
+/// A record of a privileged action, kept for accountability.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: Uuid,
+    /// The user who performed the action
+    pub actor_id: UserId,
+    pub action: AuditAction,
+    /// The user the action was performed on, if any
+    pub target_id: Option<UserId>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AuditEntry {
+    pub fn new(actor_id: UserId, action: AuditAction, target_id: Option<UserId>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            actor_id,
+            action,
+            target_id,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// Filters for searching the audit log; unset fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct AuditLogFilter {
+    /// Matches entries where the user is either the actor or the target
+    pub user_id: Option<UserId>,
+    pub action: Option<AuditAction>,
+    /// Inclusive lower bound on `created_at`
+    pub from: Option<DateTime<Utc>>,
+    /// Exclusive upper bound on `created_at`
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl AuditLogFilter {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        self.user_id
+            .is_none_or(|id| entry.actor_id == id || entry.target_id == Some(id))
+            && self.action.is_none_or(|action| entry.action == action)
+            && self.from.is_none_or(|from| entry.created_at >= from)
+            && self.to.is_none_or(|to| entry.created_at < to)
+    }
+}
+
+/// How a user search term is matched against email addresses
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailMatchMode {
+    /// Emails starting with the term; served by the email index
+    #[default]
+    Prefix,
+    /// Emails containing the term anywhere; scans the table unless a trigram index exists
+    Contains,
+}
+
+impl EmailMatchMode {
+    pub fn matches(&self, email: &str, term: &str) -> bool {
+        match self {
+            EmailMatchMode::Prefix => email.starts_with(term),
+            EmailMatchMode::Contains => email.contains(term),
+        }
+    }
+}
+
+/// Filters for listing users; unset fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct UserFilter {
+    pub role: Option<Role>,
+    /// `true` matches users not soft-deleted, `false` only soft-deleted ones
+    pub is_active: Option<bool>,
+    /// Half-open `[from, to)` range on `created_at`
+    pub created_between: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    /// Case-insensitive substring of the email, matched literally
+    pub email_contains: Option<String>,
+}
+
+impl UserFilter {
+    pub fn matches(&self, user: &User) -> bool {
+        self.role.is_none_or(|role| user.role == role)
+            && self
+                .is_active
+                .is_none_or(|active| user.is_deleted() != active)
+            && self
+                .created_between
+                .is_none_or(|(from, to)| user.created_at >= from && user.created_at < to)
+            && self.email_contains.as_ref().is_none_or(|term| {
+                EmailMatchMode::Contains.matches(user.email_str(), &term.to_lowercase())
+            })
+    }
+}
+
+/// Why a password login was refused, as reported by [`OutboxEvent::login_failed`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginFailureReason {
+    UnknownUser,
+    /// The account signs in through SSO only
+    NoPassword,
+    WrongPassword,
+    /// The account has been deleted
+    Inactive,
+    EmailNotVerified,
+}
+
+/// An event awaiting delivery through the transactional outbox.
+///
+/// Written in the same transaction as the change it describes, then delivered
+/// at least once by the dispatcher.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxEvent {
+    pub id: Uuid,
+    pub topic: String,
+    pub payload: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+    pub attempts: u32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+impl OutboxEvent {
+    /// Topic of [`OutboxEvent::user_created`]
+    pub const USER_CREATED: &'static str = "user.created";
+
+    /// Topic of [`OutboxEvent::login_failed`]
+    pub const LOGIN_FAILED: &'static str = "user.login_failed";
+
+    pub fn new(topic: impl Into<String>, payload: serde_json::Value) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4(),
+            topic: topic.into(),
+            payload,
+            created_at: now,
+            attempts: 0,
+            next_attempt_at: now,
+            delivered_at: None,
+            last_error: None,
+        }
+    }
+
+    /// Event announcing a newly created user
+    pub fn user_created(user: &User) -> Self {
+        Self::new(
+            Self::USER_CREATED,
+            serde_json::json!({
+                "user_id": user.id,
+                "email": user.email_str(),
+            }),
+        )
+    }
+
+    /// Event recording a refused password login, for security monitoring.
+    ///
+    /// Published straight away rather than through the outbox, as nothing is
+    /// written on a failed login.
+    pub fn login_failed(email: &str, reason: LoginFailureReason) -> Self {
+        Self::new(
+            Self::LOGIN_FAILED,
+            serde_json::json!({
+                "email": email,
+                "reason": reason,
+            }),
+        )
+    }
+}
+
+/// An email ready to hand to an [`EmailSender`](crate::ports::EmailSender)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailMessage {
+    pub to: String,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
@@ -14,10 +14,22 @@ pub enum DomainError {
     #[error("User not found: {0}")]
     UserNotFound(Uuid),
 
+    /// The requested API key was not found
+    #[error("API key not found: {0}")]
+    ApiKeyNotFound(Uuid),
+
     /// User with this email/subject already exists
     #[error("User already exists: {0}")]
     UserAlreadyExists(String),
 
+    /// Another user is already registered with this email
+    #[error("Email already exists: {0}")]
+    EmailAlreadyExists(String),
+
+    /// Another user is already linked to this subject
+    #[error("Subject already exists: {0}")]
+    SubjectAlreadyExists(String),
+
     /// A validation error occurred
     #[error("Validation error: {0}")]
     ValidationError(String),
@@ -26,6 +38,26 @@ pub enum DomainError {
     #[error("Unauthorized: {0}")]
     Unauthorized(String),
 
+    /// The user must verify their email before logging in
+    #[error("Email not verified: {0}")]
+    EmailNotVerified(String),
+
+    /// The user already has the maximum number of active sessions
+    #[error("Session limit reached: {0} active sessions")]
+    SessionLimitReached(u32),
+
+    /// The user already has the maximum number of active API keys
+    #[error("API key limit reached: {0} active keys")]
+    ApiKeyLimitReached(u32),
+
+    /// Registration needs an invite, and the given code is missing, unknown or used
+    #[error("Invalid invite code: {0}")]
+    InvalidInviteCode(String),
+
+    /// Too many attempts; retry after the given number of seconds
+    #[error("Too many requests, retry in {0}s")]
+    RateLimited(u64),
+
     /// A repository/infrastructure error occurred
     #[error("Repository error: {0}")]
     RepositoryError(String),
@@ -33,6 +65,11 @@ pub enum DomainError {
     /// An infrastructure adapter error occurred
     #[error("Infrastructure error: {0}")]
     InfrastructureError(String),
+
+    /// The identity provider did not answer in time or failed; unlike a
+    /// rejected login, retrying later may work
+    #[error("Identity provider unavailable: {0}")]
+    ProviderUnavailable(String),
 }
 
 impl DomainError {
@@ -48,12 +85,71 @@ impl DomainError {
 
     /// Check if this error indicates a "not found" condition
     pub fn is_not_found(&self) -> bool {
-        matches!(self, DomainError::UserNotFound(_))
+        matches!(
+            self,
+            DomainError::UserNotFound(_) | DomainError::ApiKeyNotFound(_)
+        )
     }
 
     /// Check if this error indicates a conflict (already exists)
     pub fn is_conflict(&self) -> bool {
-        matches!(self, DomainError::UserAlreadyExists(_))
+        matches!(
+            self,
+            DomainError::UserAlreadyExists(_)
+                | DomainError::EmailAlreadyExists(_)
+                | DomainError::SubjectAlreadyExists(_)
+        )
+    }
+
+    /// Variant name, for structured logs (e.g. `error.kind="RepositoryError"`)
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DomainError::UserNotFound(_) => "UserNotFound",
+            DomainError::ApiKeyNotFound(_) => "ApiKeyNotFound",
+            DomainError::UserAlreadyExists(_) => "UserAlreadyExists",
+            DomainError::EmailAlreadyExists(_) => "EmailAlreadyExists",
+            DomainError::SubjectAlreadyExists(_) => "SubjectAlreadyExists",
+            DomainError::ValidationError(_) => "ValidationError",
+            DomainError::Unauthorized(_) => "Unauthorized",
+            DomainError::EmailNotVerified(_) => "EmailNotVerified",
+            DomainError::SessionLimitReached(_) => "SessionLimitReached",
+            DomainError::ApiKeyLimitReached(_) => "ApiKeyLimitReached",
+            DomainError::InvalidInviteCode(_) => "InvalidInviteCode",
+            DomainError::RateLimited(_) => "RateLimited",
+            DomainError::RepositoryError(_) => "RepositoryError",
+            DomainError::InfrastructureError(_) => "InfrastructureError",
+            DomainError::ProviderUnavailable(_) => "ProviderUnavailable",
+        }
+    }
+
+    /// The error's payload without the `Display` prefix
+    pub fn detail(&self) -> String {
+        match self {
+            DomainError::UserNotFound(id) | DomainError::ApiKeyNotFound(id) => id.to_string(),
+            DomainError::SessionLimitReached(max) | DomainError::ApiKeyLimitReached(max) => {
+                max.to_string()
+            }
+            DomainError::RateLimited(secs) => secs.to_string(),
+            DomainError::UserAlreadyExists(detail)
+            | DomainError::EmailAlreadyExists(detail)
+            | DomainError::SubjectAlreadyExists(detail)
+            | DomainError::ValidationError(detail)
+            | DomainError::Unauthorized(detail)
+            | DomainError::EmailNotVerified(detail)
+            | DomainError::InvalidInviteCode(detail)
+            | DomainError::RepositoryError(detail)
+            | DomainError::InfrastructureError(detail)
+            | DomainError::ProviderUnavailable(detail) => detail.clone(),
+        }
+    }
+
+    /// The user field that caused a conflict, if known
+    pub fn conflict_field(&self) -> Option<&'static str> {
+        match self {
+            DomainError::EmailAlreadyExists(_) => Some("email"),
+            DomainError::SubjectAlreadyExists(_) => Some("subject"),
+            _ => None,
+        }
     }
 }
 
@@ -65,3 +161,75 @@ impl From<crate::value_objects::ValidationError> for DomainError {
 
 /// Result type alias for domain operations
 pub type DomainResult<T> = Result<T, DomainError>;
+
+/// Extension trait for turning repository lookups into domain results
+pub trait OptionExt<T> {
+    /// Map `None` to [`DomainError::UserNotFound`]
+    fn or_not_found(self, id: Uuid) -> DomainResult<T>;
+
+    /// Map `None` to the error produced by `f`
+    fn or_domain_err<F>(self, f: F) -> DomainResult<T>
+    where
+        F: FnOnce() -> DomainError;
+}
+
+impl<T> OptionExt<T> for Option<T> {
+    #[inline]
+    fn or_not_found(self, id: Uuid) -> DomainResult<T> {
+        self.ok_or(DomainError::UserNotFound(id))
+    }
+
+    #[inline]
+    fn or_domain_err<F>(self, f: F) -> DomainResult<T>
+    where
+        F: FnOnce() -> DomainError,
+    {
+        self.ok_or_else(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conflict_fields() {
+        let email = DomainError::EmailAlreadyExists("a@b.com".into());
+        let subject = DomainError::SubjectAlreadyExists("oidc|1".into());
+        assert!(email.is_conflict() && subject.is_conflict());
+        assert_eq!(email.conflict_field(), Some("email"));
+        assert_eq!(subject.conflict_field(), Some("subject"));
+        assert_eq!(
+            DomainError::UserAlreadyExists("x".into()).conflict_field(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_kind_and_detail() {
+        let error = DomainError::RepositoryError("connection reset".into());
+        assert_eq!(error.kind(), "RepositoryError");
+        assert_eq!(error.detail(), "connection reset");
+
+        let id = Uuid::new_v4();
+        assert_eq!(DomainError::UserNotFound(id).detail(), id.to_string());
+    }
+
+    #[test]
+    fn test_none_or_not_found() {
+        let id = Uuid::new_v4();
+        let result: DomainResult<()> = None.or_not_found(id);
+        assert!(matches!(result, Err(DomainError::UserNotFound(found)) if found == id));
+    }
+
+    #[test]
+    fn test_some_or_not_found() {
+        assert_eq!(Some(42).or_not_found(Uuid::new_v4()).unwrap(), 42);
+    }
+
+    #[test]
+    fn test_none_or_domain_err() {
+        let result: DomainResult<()> = None.or_domain_err(|| DomainError::validation("missing"));
+        assert!(matches!(result, Err(DomainError::ValidationError(_))));
+    }
+}
@@ -3,15 +3,28 @@
 //! This crate contains the core business logic, entities, and repository interfaces.
 //! It is completely independent of the infrastructure layer (databases, HTTP, etc.).
 
+pub mod commands;
 pub mod entities;
 pub mod errors;
+pub mod policies;
+pub mod ports;
 pub mod repositories;
 pub mod services;
 pub mod value_objects;
 
 // Re-export commonly used types
+pub use commands::{LoginCommand, NewUserCommand, UpdateProfileCommand};
 pub use entities::*;
-pub use errors::{DomainError, DomainResult};
+pub use errors::{DomainError, DomainResult, OptionExt};
+pub use policies::{
+    AuthMode, ClaimMapping, DisposableEmailWarning, EmailCanonicalization, LoginPolicy,
+    OidcIdentity, RetentionMode, RetentionPolicy, SessionLimit, SessionLimitMode,
+    WeakPasswordWarning,
+};
+pub use ports::*;
 pub use repositories::*;
-pub use services::UserService;
+pub use services::{
+    CaptchaGuard, OutboxDispatcher, PrunedTokens, TotpEnrollment, UserService,
+    WelcomeEmailPublisher, WelcomeEmailTemplate,
+};
 pub use value_objects::*;
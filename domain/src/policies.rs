@@ -0,0 +1,660 @@
+//! Domain Policies
+//!
+//! Configurable business rules that are applied by adapters and services.
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use crate::commands::NewUserCommand;
+use crate::entities::User;
+use crate::errors::{DomainError, DomainResult};
+use crate::ports::RegistrationWarning;
+use crate::value_objects::{DisplayName, Email};
+
+/// Which ways of signing in are enabled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMode {
+    /// Email and password only
+    Local,
+    /// Single sign-on only; local login and registration are off
+    Oidc,
+    #[default]
+    Both,
+}
+
+impl AuthMode {
+    pub fn allows_password(&self) -> bool {
+        matches!(self, AuthMode::Local | AuthMode::Both)
+    }
+
+    pub fn allows_oidc(&self) -> bool {
+        matches!(self, AuthMode::Oidc | AuthMode::Both)
+    }
+}
+
+impl std::str::FromStr for AuthMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "local" => Ok(AuthMode::Local),
+            "oidc" => Ok(AuthMode::Oidc),
+            "both" => Ok(AuthMode::Both),
+            other => Err(format!("Unknown auth mode: {}", other)),
+        }
+    }
+}
+
+/// Rules deciding whether an authenticated user may start a session
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoginPolicy {
+    /// Block local users until their email has been verified
+    pub require_verified_email: bool,
+    pub auth_mode: AuthMode,
+}
+
+impl LoginPolicy {
+    pub fn new(require_verified_email: bool) -> Self {
+        Self {
+            require_verified_email,
+            auth_mode: AuthMode::default(),
+        }
+    }
+
+    pub fn with_auth_mode(mut self, auth_mode: AuthMode) -> Self {
+        self.auth_mode = auth_mode;
+        self
+    }
+
+    /// Check that email and password sign-in is enabled
+    pub fn check_password_login(&self) -> DomainResult<()> {
+        if !self.auth_mode.allows_password() {
+            return Err(DomainError::unauthorized("Password login is disabled"));
+        }
+        Ok(())
+    }
+
+    /// Check that the user is allowed to log in.
+    ///
+    /// OIDC users are exempt: their email is vouched for by the provider.
+    pub fn check(&self, user: &User) -> DomainResult<()> {
+        if user.is_deleted() {
+            return Err(DomainError::unauthorized("Account has been deleted"));
+        }
+
+        if self.require_verified_email && user.is_local() && !user.email_verified {
+            return Err(DomainError::EmailNotVerified(user.email_str().to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+/// Well-known throwaway mail providers
+const DISPOSABLE_EMAIL_DOMAINS: &[&str] = &[
+    "10minutemail.com",
+    "guerrillamail.com",
+    "mailinator.com",
+    "sharklasers.com",
+    "tempmail.com",
+    "temp-mail.org",
+    "throwawaymail.com",
+    "trashmail.com",
+    "yopmail.com",
+];
+
+/// Warns about addresses at disposable mail providers, which may stop
+/// receiving mail (and password resets) at any time
+#[derive(Debug, Clone)]
+pub struct DisposableEmailWarning {
+    domains: HashSet<String>,
+}
+
+impl Default for DisposableEmailWarning {
+    fn default() -> Self {
+        Self::new(DISPOSABLE_EMAIL_DOMAINS.iter().copied())
+    }
+}
+
+impl DisposableEmailWarning {
+    pub fn new<'a>(domains: impl IntoIterator<Item = &'a str>) -> Self {
+        Self {
+            domains: domains.into_iter().map(str::to_lowercase).collect(),
+        }
+    }
+}
+
+impl RegistrationWarning for DisposableEmailWarning {
+    fn check(&self, command: &NewUserCommand) -> Option<String> {
+        let (_, domain) = command.email.as_ref().rsplit_once('@')?;
+        self.domains.contains(domain).then(|| {
+            format!(
+                "{} is a disposable email provider; you may lose access to this account",
+                domain
+            )
+        })
+    }
+}
+
+/// Rules folding addresses that reach the same mailbox into one canonical
+/// form, so `ada+news@gmail.com` cannot register next to `ada@gmail.com`.
+///
+/// Off by default. Only uniqueness checks use the canonical form; the address
+/// is stored and shown as entered.
+#[derive(Debug, Clone, Default)]
+pub struct EmailCanonicalization {
+    /// Drop everything from the first `+` of the local part
+    pub strip_plus_tags: bool,
+    /// Providers that ignore dots in the local part, e.g. `gmail.com`
+    pub dot_insensitive_domains: HashSet<String>,
+}
+
+impl EmailCanonicalization {
+    pub fn new<'a>(
+        strip_plus_tags: bool,
+        dot_insensitive_domains: impl IntoIterator<Item = &'a str>,
+    ) -> Self {
+        Self {
+            strip_plus_tags,
+            dot_insensitive_domains: dot_insensitive_domains
+                .into_iter()
+                .map(str::to_lowercase)
+                .collect(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.strip_plus_tags || !self.dot_insensitive_domains.is_empty()
+    }
+
+    /// The form two addresses share when they reach the same mailbox
+    pub fn canonical(&self, email: &Email) -> String {
+        let Some((local, domain)) = email.as_ref().rsplit_once('@') else {
+            return email.to_string();
+        };
+        let mut local = local;
+        if self.strip_plus_tags
+            && let Some((base, _)) = local.split_once('+')
+            && !base.is_empty()
+        {
+            local = base;
+        }
+        let local = if self.dot_insensitive_domains.contains(domain) {
+            local.replace('.', "")
+        } else {
+            local.to_string()
+        };
+        format!("{}@{}", local, domain)
+    }
+}
+
+/// Passwords shorter than this get a warning, though they are accepted
+pub const RECOMMENDED_PASSWORD_LENGTH: usize = 12;
+
+/// Warns about passwords that pass validation but are easy to guess
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WeakPasswordWarning;
+
+impl RegistrationWarning for WeakPasswordWarning {
+    fn check(&self, command: &NewUserCommand) -> Option<String> {
+        let password = command.password.as_ref();
+        let classes = [
+            password.chars().any(|c| c.is_lowercase()),
+            password.chars().any(|c| c.is_uppercase()),
+            password.chars().any(|c| c.is_numeric()),
+            password.chars().any(|c| !c.is_alphanumeric()),
+        ]
+        .into_iter()
+        .filter(|present| *present)
+        .count();
+
+        (password.chars().count() < RECOMMENDED_PASSWORD_LENGTH && classes < 3).then(|| {
+            format!(
+                "Weak password; use at least {} characters or mix letters, digits and symbols",
+                RECOMMENDED_PASSWORD_LENGTH
+            )
+        })
+    }
+}
+
+/// What happens to accounts that exceed the retention period
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RetentionMode {
+    /// Mark the account deleted but keep the row
+    #[default]
+    SoftDelete,
+    /// Remove the account entirely
+    Erase,
+}
+
+impl std::str::FromStr for RetentionMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "soft_delete" => Ok(RetentionMode::SoftDelete),
+            "erase" => Ok(RetentionMode::Erase),
+            other => Err(format!("Unknown retention mode: {}", other)),
+        }
+    }
+}
+
+/// What a login does when the user is at the session limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SessionLimitMode {
+    /// Refuse the new login
+    #[default]
+    Reject,
+    /// End the user's oldest sessions to make room
+    EvictOldest,
+}
+
+impl std::str::FromStr for SessionLimitMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(SessionLimitMode::Reject),
+            "evict_oldest" => Ok(SessionLimitMode::EvictOldest),
+            other => Err(format!("Unknown session limit mode: {}", other)),
+        }
+    }
+}
+
+/// Cap on concurrent sessions per user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionLimit {
+    pub max: u32,
+    pub mode: SessionLimitMode,
+}
+
+/// Data retention rules for inactive accounts
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    pub inactive_for: Duration,
+    pub mode: RetentionMode,
+    /// Emails of the machine accounts integrations use, which often never log in
+    pub service_accounts: HashSet<String>,
+}
+
+impl RetentionPolicy {
+    pub fn new(inactive_days: u32, mode: RetentionMode) -> Self {
+        Self {
+            inactive_for: Duration::days(inactive_days as i64),
+            mode,
+            service_accounts: HashSet::new(),
+        }
+    }
+
+    pub fn with_service_accounts(mut self, emails: impl IntoIterator<Item = Email>) -> Self {
+        self.service_accounts = emails.into_iter().map(Email::into_inner).collect();
+        self
+    }
+
+    /// Accounts last active before this moment are stale
+    pub fn cutoff(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        now - self.inactive_for
+    }
+
+    /// Admins and verified service accounts are never processed
+    pub fn is_exempt(&self, user: &User) -> bool {
+        user.is_admin() || (user.email_verified && self.service_accounts.contains(user.email_str()))
+    }
+}
+
+/// Identity extracted from verified OIDC ID token claims
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OidcIdentity {
+    pub subject: String,
+    pub email: Email,
+    /// The provider's `email_verified` claim; an unverified email never
+    /// links the login to an existing account
+    pub email_verified: bool,
+    pub name: Option<String>,
+}
+
+/// Which ID token claims hold the subject, email and display name.
+///
+/// Claim names may be dotted paths into nested objects (`profile.email`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClaimMapping {
+    pub subject_claim: String,
+    pub email_claim: String,
+    pub name_claim: String,
+    /// Further claims kept in the session, e.g. `groups` or `org_id`
+    pub session_claims: Vec<String>,
+}
+
+/// Serialized size allowed for the claims kept in a session
+pub const MAX_SESSION_CLAIMS_BYTES: usize = 4096;
+
+impl Default for ClaimMapping {
+    fn default() -> Self {
+        Self {
+            subject_claim: "sub".to_string(),
+            email_claim: "email".to_string(),
+            name_claim: "name".to_string(),
+            session_claims: Vec::new(),
+        }
+    }
+}
+
+impl ClaimMapping {
+    /// Extract the identity from already-verified claims.
+    ///
+    /// The subject and email are required; the name is optional. The email is
+    /// normalized, while the subject is kept verbatim since it is case-sensitive.
+    pub fn identity(&self, claims: &serde_json::Value) -> DomainResult<OidcIdentity> {
+        let subject = Self::claim(claims, &self.subject_claim).ok_or_else(|| {
+            DomainError::validation(format!("Missing `{}` claim", self.subject_claim))
+        })?;
+        let email = Self::claim(claims, &self.email_claim).ok_or_else(|| {
+            DomainError::validation(format!("Missing `{}` claim", self.email_claim))
+        })?;
+
+        Ok(OidcIdentity {
+            subject,
+            email: Email::try_from(email)?,
+            // Some IdPs send the flag as a string
+            email_verified: matches!(
+                Self::lookup(claims, "email_verified"),
+                Some(serde_json::Value::Bool(true))
+            ) || Self::claim(claims, "email_verified").as_deref() == Some("true"),
+            name: Self::claim(claims, &self.name_claim)
+                .and_then(|name| DisplayName::new(name).ok())
+                .map(DisplayName::into_inner),
+        })
+    }
+
+    /// The allowlisted [`session_claims`](Self::session_claims) present in `claims`.
+    ///
+    /// Claims are taken in allowlist order until [`MAX_SESSION_CLAIMS_BYTES`]
+    /// is used up; one that doesn't fit is left out.
+    pub fn select_session_claims(
+        &self,
+        claims: &serde_json::Value,
+    ) -> serde_json::Map<String, serde_json::Value> {
+        let mut selected = serde_json::Map::new();
+        let mut size = 0;
+        for name in &self.session_claims {
+            let Some(value) = Self::lookup(claims, name) else {
+                continue;
+            };
+            let value_size = name.len() + value.to_string().len();
+            if size + value_size > MAX_SESSION_CLAIMS_BYTES {
+                continue;
+            }
+            size += value_size;
+            selected.insert(name.clone(), value.clone());
+        }
+        selected
+    }
+
+    fn lookup<'a>(claims: &'a serde_json::Value, name: &str) -> Option<&'a serde_json::Value> {
+        claims.get(name).or_else(|| {
+            name.split('.')
+                .try_fold(claims, |value, key| value.get(key))
+        })
+    }
+
+    /// A non-empty string or number claim
+    fn claim(claims: &serde_json::Value, name: &str) -> Option<String> {
+        let value = match Self::lookup(claims, name)? {
+            serde_json::Value::String(s) => s.clone(),
+            // Some IdPs issue numeric subjects
+            serde_json::Value::Number(n) => n.to_string(),
+            _ => return None,
+        };
+        Some(value).filter(|v| !v.trim().is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value_objects::Email;
+
+    fn local_user() -> User {
+        User::new_local(Email::try_from("local@example.com").unwrap(), "hash")
+    }
+
+    #[test]
+    fn test_unverified_local_user_is_blocked() {
+        let policy = LoginPolicy::new(true);
+        let result = policy.check(&local_user());
+        assert!(matches!(result, Err(DomainError::EmailNotVerified(_))));
+    }
+
+    #[test]
+    fn test_verified_local_user_is_allowed() {
+        let policy = LoginPolicy::new(true);
+        let mut user = local_user();
+        user.mark_email_verified();
+        assert!(policy.check(&user).is_ok());
+    }
+
+    #[test]
+    fn test_oidc_user_is_exempt() {
+        let policy = LoginPolicy::new(true);
+        let user = User::new("oidc|123", Email::try_from("oidc@example.com").unwrap()).unwrap();
+        assert!(policy.check(&user).is_ok());
+    }
+
+    #[test]
+    fn test_deleted_user_is_blocked() {
+        let mut user = local_user();
+        user.deleted_at = Some(chrono::Utc::now());
+        let result = LoginPolicy::default().check(&user);
+        assert!(matches!(result, Err(DomainError::Unauthorized(_))));
+    }
+
+    #[test]
+    fn test_oidc_mode_rejects_password_login() {
+        let policy = LoginPolicy::default().with_auth_mode(AuthMode::Oidc);
+        assert!(matches!(
+            policy.check_password_login(),
+            Err(DomainError::Unauthorized(_))
+        ));
+
+        // SSO users still pass the session checks
+        let user = User::new("oidc|123", Email::try_from("oidc@example.com").unwrap()).unwrap();
+        assert!(policy.check(&user).is_ok());
+        assert!(policy.auth_mode.allows_oidc());
+    }
+
+    #[test]
+    fn test_auth_mode_parses_config_values() {
+        assert_eq!("local".parse(), Ok(AuthMode::Local));
+        assert_eq!("oidc".parse(), Ok(AuthMode::Oidc));
+        assert_eq!("both".parse(), Ok(AuthMode::Both));
+        assert!("sso".parse::<AuthMode>().is_err());
+        assert!(LoginPolicy::default().check_password_login().is_ok());
+    }
+
+    #[test]
+    fn test_disabled_policy_allows_unverified() {
+        let policy = LoginPolicy::default();
+        assert!(policy.check(&local_user()).is_ok());
+    }
+
+    fn command(email: &str, password: &str) -> NewUserCommand {
+        NewUserCommand {
+            email: Email::try_from(email).unwrap(),
+            password: crate::value_objects::Password::new(password).unwrap(),
+            invite_code: None,
+        }
+    }
+
+    #[test]
+    fn test_disposable_email_is_flagged() {
+        let warning = DisposableEmailWarning::default();
+        let flagged = warning.check(&command("someone@Mailinator.com", "Str0ng&Long!pw"));
+        assert!(flagged.unwrap().contains("mailinator.com"));
+        assert!(
+            warning
+                .check(&command("someone@example.com", "Str0ng&Long!pw"))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_weak_password_is_flagged() {
+        let warning = WeakPasswordWarning;
+        assert!(
+            warning
+                .check(&command("a@example.com", "secret1"))
+                .is_some()
+        );
+        // Long enough, or varied enough
+        assert!(
+            warning
+                .check(&command("a@example.com", "correct horse battery"))
+                .is_none()
+        );
+        assert!(
+            warning
+                .check(&command("a@example.com", "S3cret!"))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_default_claim_mapping() {
+        let claims = serde_json::json!({"sub": "abc", "email": "User@Example.com"});
+        let identity = ClaimMapping::default().identity(&claims).unwrap();
+        assert_eq!(identity.subject, "abc");
+        assert_eq!(identity.email.as_ref(), "user@example.com");
+        assert!(!identity.email_verified);
+        assert_eq!(identity.name, None);
+    }
+
+    #[test]
+    fn test_email_verified_claim_is_read() {
+        for (verified, expected) in [
+            (serde_json::json!(true), true),
+            (serde_json::json!("true"), true),
+            (serde_json::json!(false), false),
+            (serde_json::json!("yes"), false),
+        ] {
+            let claims = serde_json::json!({
+                "sub": "abc",
+                "email": "user@example.com",
+                "email_verified": verified,
+            });
+            let identity = ClaimMapping::default().identity(&claims).unwrap();
+            assert_eq!(identity.email_verified, expected, "{}", claims);
+        }
+    }
+
+    #[test]
+    fn test_subject_claim_keeps_its_case() {
+        let claims = serde_json::json!({"sub": "AbC-123", "email": "User@Example.com"});
+        let identity = ClaimMapping::default().identity(&claims).unwrap();
+        assert_eq!(identity.subject, "AbC-123");
+        assert_eq!(identity.email.as_ref(), "user@example.com");
+    }
+
+    #[test]
+    fn test_nonstandard_claims_are_mapped() {
+        let mapping = ClaimMapping {
+            subject_claim: "oid".to_string(),
+            email_claim: "profile.mail".to_string(),
+            name_claim: "display_name".to_string(),
+            ..ClaimMapping::default()
+        };
+        let claims = serde_json::json!({
+            "sub": "ignored",
+            "oid": 42,
+            "profile": {"mail": "mapped@example.com"},
+            "display_name": "  Mapped   User ",
+        });
+
+        let identity = mapping.identity(&claims).unwrap();
+        assert_eq!(identity.subject, "42");
+        assert_eq!(identity.email.as_ref(), "mapped@example.com");
+        assert_eq!(identity.name.as_deref(), Some("Mapped User"));
+    }
+
+    #[test]
+    fn test_missing_required_claim_is_rejected() {
+        let claims = serde_json::json!({"sub": "abc", "upn": "user@example.com"});
+        let result = ClaimMapping::default().identity(&claims);
+        assert!(
+            matches!(result, Err(DomainError::ValidationError(msg)) if msg.contains("`email`"))
+        );
+    }
+
+    #[test]
+    fn test_only_allowlisted_session_claims_are_kept() {
+        let mapping = ClaimMapping {
+            session_claims: vec!["groups".into(), "org.id".into(), "missing".into()],
+            ..ClaimMapping::default()
+        };
+        let claims = serde_json::json!({
+            "sub": "abc",
+            "groups": ["admins", "ops"],
+            "org": {"id": 7, "secret": "x"},
+            "tenant": "other",
+        });
+
+        let selected = mapping.select_session_claims(&claims);
+        assert_eq!(
+            serde_json::Value::Object(selected),
+            serde_json::json!({"groups": ["admins", "ops"], "org.id": 7})
+        );
+    }
+
+    #[test]
+    fn test_oversized_session_claims_are_dropped() {
+        let mapping = ClaimMapping {
+            session_claims: vec!["big".into(), "small".into()],
+            ..ClaimMapping::default()
+        };
+        let claims = serde_json::json!({
+            "big": "x".repeat(MAX_SESSION_CLAIMS_BYTES),
+            "small": "kept",
+        });
+
+        let selected = mapping.select_session_claims(&claims);
+        assert_eq!(selected.keys().collect::<Vec<_>>(), ["small"]);
+    }
+
+    fn canonical(rules: &EmailCanonicalization, email: &str) -> String {
+        rules.canonical(&Email::try_from(email).unwrap())
+    }
+
+    #[test]
+    fn test_canonicalization_is_off_by_default() {
+        let rules = EmailCanonicalization::default();
+        assert!(!rules.is_enabled());
+        assert_eq!(
+            canonical(&rules, "a.da+news@gmail.com"),
+            "a.da+news@gmail.com"
+        );
+    }
+
+    #[test]
+    fn test_plus_tags_are_stripped() {
+        let rules = EmailCanonicalization::new(true, []);
+        assert_eq!(canonical(&rules, "ada+news@example.com"), "ada@example.com");
+        assert_eq!(canonical(&rules, "ada+a+b@example.com"), "ada@example.com");
+        // Nothing would be left of the local part
+        assert_eq!(canonical(&rules, "+ada@example.com"), "+ada@example.com");
+        // Dots matter unless the provider is configured
+        assert_eq!(canonical(&rules, "a.da@gmail.com"), "a.da@gmail.com");
+    }
+
+    #[test]
+    fn test_dots_are_dropped_for_configured_providers_only() {
+        let rules = EmailCanonicalization::new(false, ["GMail.com"]);
+        assert_eq!(canonical(&rules, "a.d.a@gmail.com"), "ada@gmail.com");
+        assert_eq!(canonical(&rules, "a.da@example.com"), "a.da@example.com");
+        assert_eq!(canonical(&rules, "a.da+x@gmail.com"), "ada+x@gmail.com");
+
+        let rules = EmailCanonicalization::new(true, ["gmail.com"]);
+        assert_eq!(canonical(&rules, "a.da+x@gmail.com"), "ada@gmail.com");
+    }
+}
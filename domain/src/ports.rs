@@ -0,0 +1,106 @@
+//! Service ports (traits)
+//!
+//! These traits define the interface for external services that are not persistence.
+
+use async_trait::async_trait;
+
+use crate::commands::NewUserCommand;
+use crate::entities::{EmailMessage, OidcLoginAttempt, OutboxEvent, User};
+use crate::errors::DomainResult;
+use crate::repositories::Transaction;
+use crate::value_objects::Password;
+
+/// Port for verifying CAPTCHA tokens with an external provider
+#[async_trait]
+pub trait CaptchaVerifier: Send + Sync {
+    /// Verify a token.
+    ///
+    /// `Ok(false)` means the provider rejected the token, `Err` that it could not be asked.
+    async fn verify(&self, token: &str) -> DomainResult<bool>;
+}
+
+/// Port for signing users in through an OpenID Connect provider
+#[async_trait]
+pub trait OidcProvider: Send + Sync {
+    /// The provider's login page for `attempt`; it sends the browser back
+    /// with a code and the attempt's `state`
+    fn authorization_url(&self, attempt: &OidcLoginAttempt) -> String;
+
+    /// Claims of the user the provider issued `code` for during `attempt`.
+    ///
+    /// `Err(ProviderUnavailable)` means the provider could not be asked in
+    /// time, `Err(Unauthorized)` that it turned the code down or answered
+    /// for another login.
+    async fn claims(
+        &self,
+        code: &str,
+        attempt: &OidcLoginAttempt,
+    ) -> DomainResult<serde_json::Value>;
+}
+
+/// Port for delivering outbox events to the outside world (broker, webhooks, ...)
+#[async_trait]
+pub trait EventPublisher: Send + Sync {
+    async fn publish(&self, event: &OutboxEvent) -> DomainResult<()>;
+}
+
+/// Port for sending email (SMTP, transactional email APIs, ...)
+#[async_trait]
+pub trait EmailSender: Send + Sync {
+    async fn send(&self, message: &EmailMessage) -> DomainResult<()>;
+}
+
+/// Port for hashing passwords before they are stored
+pub trait PasswordHasher: Send + Sync {
+    fn hash(&self, password: &Password) -> DomainResult<String>;
+}
+
+/// Port for time-based one-time passwords (RFC 6238)
+pub trait TotpProvider: Send + Sync {
+    /// Generate a new base32-encoded secret
+    fn generate_secret(&self) -> DomainResult<String>;
+
+    /// `otpauth://` URI for authenticator apps (usually shown as a QR code)
+    fn provisioning_uri(&self, secret: &str, account: &str) -> DomainResult<String>;
+
+    /// Check a code against the current time, allowing for clock skew.
+    ///
+    /// Returns the time step the code was generated for, or `None` if it
+    /// matches none of the steps allowed.
+    fn verify(&self, secret: &str, code: &str) -> DomainResult<Option<u64>>;
+}
+
+/// Port for encrypting secrets at rest
+pub trait SecretCipher: Send + Sync {
+    fn encrypt(&self, plaintext: &str) -> DomainResult<String>;
+
+    fn decrypt(&self, ciphertext: &str) -> DomainResult<String>;
+}
+
+/// Port for creating an account's default resources (workspace, settings, ...).
+///
+/// Runs inside the transaction that creates the user: an error rolls back the
+/// user as well, so an account never exists without its defaults.
+#[async_trait]
+pub trait RegistrationHook: Send + Sync {
+    async fn on_register(&self, tx: &mut dyn Transaction, user: &User) -> DomainResult<()>;
+}
+
+/// Port for non-fatal registration checks.
+///
+/// Warnings are returned to the client next to the created account and never
+/// block registration; checks that must block belong in validation instead.
+pub trait RegistrationWarning: Send + Sync {
+    /// A message for the client when `command` is allowed but questionable
+    fn check(&self, command: &NewUserCommand) -> Option<String>;
+}
+
+/// Creates nothing
+pub struct NoopRegistrationHook;
+
+#[async_trait]
+impl RegistrationHook for NoopRegistrationHook {
+    async fn on_register(&self, _tx: &mut dyn Transaction, _user: &User) -> DomainResult<()> {
+        Ok(())
+    }
+}
@@ -1,12 +1,39 @@
 //! Reference Repository ports (traits)
-//! 
+//!
 //! These traits define the interface for data persistence.
 
+use std::any::Any;
+
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
-use crate::entities::User;
+use crate::entities::{
+    ApiKey, AuditEntry, AuditLogFilter, EmailMatchMode, EmailVerificationToken, InviteCode,
+    OutboxEvent, PasswordResetToken, SessionFilter, SessionInfo, User, UserFilter,
+};
 use crate::errors::DomainResult;
+use crate::value_objects::DisplayName;
+
+/// An open database transaction.
+///
+/// Dropping it without committing rolls it back. Adapters expose their native
+/// handle through [`as_any_mut`](Self::as_any_mut), so code living next to the
+/// adapter (e.g. a [`RegistrationHook`](crate::ports::RegistrationHook)) can
+/// enlist its own writes.
+#[async_trait]
+pub trait Transaction: Send {
+    fn as_any_mut(&mut self) -> &mut (dyn Any + Send);
+
+    /// Run `callback` once the transaction has committed; it is dropped on
+    /// rollback. For side effects that must not get ahead of the write, such
+    /// as evicting a cache that could otherwise refill from the old row.
+    fn after_commit(&mut self, callback: Box<dyn FnOnce() + Send>);
+
+    async fn commit(self: Box<Self>) -> DomainResult<()>;
+
+    async fn rollback(self: Box<Self>) -> DomainResult<()>;
+}
 
 /// Repository port for User persistence
 #[async_trait]
@@ -14,15 +41,283 @@ pub trait UserRepository: Send + Sync {
     /// Find a user by their internal ID
     async fn find_by_id(&self, id: Uuid) -> DomainResult<Option<User>>;
 
-    /// Find a user by their OIDC subject (used for authentication)
+    /// Find a user by ID to change and [`save`](Self::save) back.
+    ///
+    /// Reads where writes go: unlike [`find_by_id`](Self::find_by_id) it
+    /// never answers from a replica or cache that may lag behind the last save.
+    async fn find_by_id_for_update(&self, id: Uuid) -> DomainResult<Option<User>>;
+
+    /// Find all users with the given IDs; missing IDs are skipped
+    async fn find_by_ids(&self, ids: &[Uuid]) -> DomainResult<Vec<User>>;
+
+    /// Find a user by their OIDC subject (used for authentication).
+    ///
+    /// The match is exact and case-sensitive: `Alice` and `alice` are different subjects.
     async fn find_by_subject(&self, subject: &str) -> DomainResult<Option<User>>;
 
     /// Find a user by their email
     async fn find_by_email(&self, email: &str) -> DomainResult<Option<User>>;
 
+    /// Whether any account, deleted or not, holds `email`; fetches no user data
+    async fn exists_by_email(&self, email: &str) -> DomainResult<bool>;
+
+    /// Find the user whose [`canonical_email`](User::canonical_email) is `canonical`
+    async fn find_by_canonical_email(&self, canonical: &str) -> DomainResult<Option<User>>;
+
+    /// Find users not deleted and inactive since `cutoff`.
+    ///
+    /// Activity is the last login, or account creation for users who never logged in.
+    async fn find_inactive_since(&self, cutoff: DateTime<Utc>) -> DomainResult<Vec<User>>;
+
+    /// Find users whose email matches `term`, ordered by email.
+    ///
+    /// `term` is matched literally; `%` and `_` carry no special meaning.
+    async fn search(
+        &self,
+        term: &str,
+        match_mode: EmailMatchMode,
+        limit: u32,
+        offset: u32,
+    ) -> DomainResult<Vec<User>>;
+
+    /// Count users whose email matches `term`
+    async fn count_matching(&self, term: &str, match_mode: EmailMatchMode) -> DomainResult<u64>;
+
+    /// Users matching every populated field of `filter`, ordered by email
+    async fn find(&self, filter: &UserFilter, limit: u32, offset: u32) -> DomainResult<Vec<User>>;
+
     /// Save a new user or update an existing one
     async fn save(&self, user: &User) -> DomainResult<()>;
 
+    /// Set only a user's display name; `None` removes it.
+    ///
+    /// Every other column keeps its stored value, so a concurrent write to
+    /// the rest of the user isn't overwritten by a stale copy.
+    async fn update_display_name(
+        &self,
+        id: Uuid,
+        display_name: Option<&DisplayName>,
+    ) -> DomainResult<()>;
+
+    /// Save a user and enqueue outbox events atomically, in a single transaction
+    async fn save_with_events(&self, user: &User, events: &[OutboxEvent]) -> DomainResult<()>;
+
+    /// Start a transaction for [`save_in`](Self::save_in)
+    async fn begin(&self) -> DomainResult<Box<dyn Transaction>>;
+
+    /// Save a user and enqueue outbox events inside `tx`; nothing is visible until it commits
+    async fn save_in(
+        &self,
+        tx: &mut dyn Transaction,
+        user: &User,
+        events: &[OutboxEvent],
+    ) -> DomainResult<()>;
+
     /// Delete a user by their ID
     async fn delete(&self, id: Uuid) -> DomainResult<()>;
+
+    /// Delete a user inside `tx`
+    async fn delete_in(&self, tx: &mut dyn Transaction, id: Uuid) -> DomainResult<()>;
+
+    /// Claim the first-admin bootstrap for the user about to be created in `tx`.
+    ///
+    /// Succeeds at most once per database, and only while it holds no users.
+    /// Concurrent claims must not both succeed.
+    async fn claim_first_admin(
+        &self,
+        tx: &mut dyn Transaction,
+        user_id: Uuid,
+    ) -> DomainResult<bool>;
+
+    /// Record `step` as the last TOTP time step accepted for the user.
+    ///
+    /// Returns `false` if that step or a later one was already accepted, i.e.
+    /// the code is being replayed. Concurrent claims of one step must not
+    /// both succeed.
+    async fn claim_totp_step(&self, user_id: Uuid, step: u64) -> DomainResult<bool>;
+}
+
+/// Repository port for API key persistence
+#[async_trait]
+pub trait ApiKeyRepository: Send + Sync {
+    /// Find an API key by its ID
+    async fn find_by_id(&self, id: Uuid) -> DomainResult<Option<ApiKey>>;
+
+    /// Find an API key by the hash of the raw key
+    async fn find_by_hash(&self, key_hash: &str) -> DomainResult<Option<ApiKey>>;
+
+    /// List all API keys belonging to a user
+    async fn find_by_user(&self, user_id: Uuid) -> DomainResult<Vec<ApiKey>>;
+
+    /// Save a new API key or update an existing one
+    async fn save(&self, key: &ApiKey) -> DomainResult<()>;
+
+    /// Insert a new key unless its owner already holds `max` keys unexpired at `now`.
+    ///
+    /// Returns `false` if the limit was reached. Concurrent inserts for one
+    /// user must not together go over it.
+    async fn insert_within_limit(
+        &self,
+        key: &ApiKey,
+        max: u32,
+        now: DateTime<Utc>,
+    ) -> DomainResult<bool>;
+
+    /// Delete an API key by its ID
+    async fn delete(&self, id: Uuid) -> DomainResult<()>;
+
+    /// Give every key owned by `from` to `to`, inside `tx`.
+    ///
+    /// Returns `false`, moving nothing, if `to` would then hold more than
+    /// `max` keys unexpired at `now`.
+    async fn reassign_in(
+        &self,
+        tx: &mut dyn Transaction,
+        from: Uuid,
+        to: Uuid,
+        max: Option<u32>,
+        now: DateTime<Utc>,
+    ) -> DomainResult<bool>;
+}
+
+/// Repository port for password reset tokens
+#[async_trait]
+pub trait PasswordResetTokenRepository: Send + Sync {
+    async fn save(&self, token: &PasswordResetToken) -> DomainResult<()>;
+
+    /// Remove the token with this hash and return it, in one step.
+    ///
+    /// Of two concurrent calls for the same hash, at most one gets the token.
+    async fn take(&self, token_hash: &str) -> DomainResult<Option<PasswordResetToken>>;
+
+    /// Delete all of a user's outstanding tokens
+    async fn delete_for_user(&self, user_id: Uuid) -> DomainResult<()>;
+
+    /// [`delete_for_user`](Self::delete_for_user) inside `tx`
+    async fn delete_for_user_in(&self, tx: &mut dyn Transaction, user_id: Uuid)
+    -> DomainResult<()>;
+
+    /// Delete tokens that expired before `now`, returning how many went
+    async fn delete_expired(&self, now: DateTime<Utc>) -> DomainResult<u64>;
+}
+
+/// Repository port for email verification tokens
+#[async_trait]
+pub trait EmailVerificationTokenRepository: Send + Sync {
+    async fn save(&self, token: &EmailVerificationToken) -> DomainResult<()>;
+
+    /// Remove the token with this hash and return it, in one step.
+    ///
+    /// Of two concurrent calls for the same hash, at most one gets the token.
+    async fn take(&self, token_hash: &str) -> DomainResult<Option<EmailVerificationToken>>;
+
+    /// The user's most recently created token, expired or not
+    async fn latest_for_user(&self, user_id: Uuid) -> DomainResult<Option<EmailVerificationToken>>;
+
+    /// Delete all of a user's outstanding tokens
+    async fn delete_for_user(&self, user_id: Uuid) -> DomainResult<()>;
+
+    /// [`delete_for_user`](Self::delete_for_user) inside `tx`
+    async fn delete_for_user_in(&self, tx: &mut dyn Transaction, user_id: Uuid)
+    -> DomainResult<()>;
+
+    /// Delete tokens that expired before `now`, returning how many went
+    async fn delete_expired(&self, now: DateTime<Utc>) -> DomainResult<u64>;
+}
+
+/// Repository port for invite codes
+#[async_trait]
+pub trait InviteRepository: Send + Sync {
+    async fn save(&self, invite: &InviteCode) -> DomainResult<()>;
+
+    /// One page of codes, newest first
+    async fn list(&self, limit: u32, offset: u32) -> DomainResult<Vec<InviteCode>>;
+
+    async fn count(&self) -> DomainResult<u64>;
+
+    /// Mark an unused code as used by `user_id`, as part of `tx`.
+    ///
+    /// Returns `false` for unknown and already used codes. Of two concurrent
+    /// redemptions of one code, at most one succeeds.
+    async fn redeem_in(
+        &self,
+        tx: &mut dyn Transaction,
+        code: &str,
+        user_id: Uuid,
+    ) -> DomainResult<bool>;
+}
+
+/// Repository port for the metadata of logged-in sessions.
+///
+/// The sessions themselves live in the session store, which owns their expiry:
+/// `expires_at` is read back from there, and sessions it has dropped are not listed.
+#[async_trait]
+pub trait SessionRepository: Send + Sync {
+    /// Record a session that was just logged in; `expires_at` is ignored
+    async fn record(&self, session: &SessionInfo) -> DomainResult<()>;
+
+    /// Sessions matching `filter` that are unexpired at `now`, newest first
+    async fn list(
+        &self,
+        filter: &SessionFilter,
+        now: DateTime<Utc>,
+        limit: u32,
+        offset: u32,
+    ) -> DomainResult<Vec<SessionInfo>>;
+
+    /// Number of sessions `list` would return without paging
+    async fn count(&self, filter: &SessionFilter, now: DateTime<Utc>) -> DomainResult<u64>;
+
+    /// End a session in the session store, along with its metadata
+    async fn revoke(&self, session_id: &str) -> DomainResult<()>;
+
+    /// End every session of a user, inside `tx`
+    async fn revoke_for_user_in(&self, tx: &mut dyn Transaction, user_id: Uuid)
+    -> DomainResult<()>;
+}
+
+/// Repository port for the audit log
+#[async_trait]
+pub trait AuditLogRepository: Send + Sync {
+    /// Append an entry to the audit log
+    async fn record(&self, entry: &AuditEntry) -> DomainResult<()>;
+
+    /// Append an entry inside `tx`, so it is only kept if the recorded change commits
+    async fn record_in(&self, tx: &mut dyn Transaction, entry: &AuditEntry) -> DomainResult<()>;
+
+    /// List entries performed by a user, newest first
+    async fn find_by_actor(&self, actor_id: Uuid) -> DomainResult<Vec<AuditEntry>>;
+
+    /// Entries matching `filter`, newest first
+    async fn search(
+        &self,
+        filter: &AuditLogFilter,
+        limit: u32,
+        offset: u32,
+    ) -> DomainResult<Vec<AuditEntry>>;
+
+    /// Number of entries matching `filter`
+    async fn count(&self, filter: &AuditLogFilter) -> DomainResult<u64>;
+}
+
+/// Repository port for the transactional outbox
+#[async_trait]
+pub trait OutboxRepository: Send + Sync {
+    /// Enqueue an event outside of any other change
+    async fn enqueue(&self, event: &OutboxEvent) -> DomainResult<()>;
+
+    /// Undelivered events due for an attempt at `now`, oldest first
+    async fn fetch_pending(&self, now: DateTime<Utc>, limit: u32)
+    -> DomainResult<Vec<OutboxEvent>>;
+
+    /// Mark an event as delivered
+    async fn mark_delivered(&self, id: Uuid, delivered_at: DateTime<Utc>) -> DomainResult<()>;
+
+    /// Record a failed attempt and when to retry
+    async fn mark_failed(
+        &self,
+        id: Uuid,
+        error: &str,
+        next_attempt_at: DateTime<Utc>,
+    ) -> DomainResult<()>;
 }
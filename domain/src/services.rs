@@ -2,56 +2,4135 @@
 //!
 //! Services contain the business logic of the application.
 
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
 use std::sync::Arc;
 use uuid::Uuid;
 
-use crate::entities::User;
-use crate::errors::{DomainError, DomainResult};
-use crate::repositories::UserRepository;
-use crate::value_objects::Email;
+use crate::commands::{NewUserCommand, UpdateProfileCommand};
+use crate::entities::{
+    ApiKey, AuditAction, AuditEntry, AuditLogFilter, EmailMatchMode, EmailMessage,
+    EmailVerificationToken, InviteCode, OutboxEvent, PasswordResetToken, SYSTEM_ACTOR_ID,
+    SessionFilter, SessionInfo, User, UserFilter,
+};
+use crate::errors::{DomainError, DomainResult, OptionExt};
+use crate::policies::{
+    ClaimMapping, EmailCanonicalization, RetentionMode, RetentionPolicy, SessionLimit,
+    SessionLimitMode,
+};
+use crate::ports::{
+    CaptchaVerifier, EmailSender, EventPublisher, NoopRegistrationHook, PasswordHasher,
+    RegistrationHook, RegistrationWarning, SecretCipher, TotpProvider,
+};
+use crate::repositories::{
+    ApiKeyRepository, AuditLogRepository, EmailVerificationTokenRepository, InviteRepository,
+    OutboxRepository, PasswordResetTokenRepository, SessionRepository, Transaction, UserRepository,
+};
+use crate::value_objects::{ApiKeyId, Email, Password, Role};
 
 /// Service for managing users
 pub struct UserService {
     user_repository: Arc<dyn UserRepository>,
+    api_key_repository: Arc<dyn ApiKeyRepository>,
+    audit_log_repository: Arc<dyn AuditLogRepository>,
+    password_hasher: Option<Arc<dyn PasswordHasher>>,
+    totp: Option<TotpSupport>,
+    password_reset: Option<PasswordResetSupport>,
+    email_verification: Option<EmailVerificationSupport>,
+    invites: Option<InviteSupport>,
+    session_repository: Option<Arc<dyn SessionRepository>>,
+    session_limit: Option<SessionLimit>,
+    max_api_keys: Option<u32>,
+    reuse_deleted_emails: bool,
+    first_user_is_admin: bool,
+    email_canonicalization: EmailCanonicalization,
+    claim_mapping: ClaimMapping,
+    registration_hook: Arc<dyn RegistrationHook>,
+    registration_warnings: Vec<Arc<dyn RegistrationWarning>>,
+}
+
+/// TOTP adapters, present when two-factor authentication is configured
+struct TotpSupport {
+    provider: Arc<dyn TotpProvider>,
+    cipher: Arc<dyn SecretCipher>,
+}
+
+/// Password reset storage and delivery, present when resets are configured
+struct PasswordResetSupport {
+    repository: Arc<dyn PasswordResetTokenRepository>,
+    sender: Arc<dyn EmailSender>,
+    ttl: Duration,
+    url: String,
+}
+
+/// Email asking the recipient to `action` by following `link`
+fn link_email(to: &str, subject: &str, action: &str, link: &str) -> EmailMessage {
+    EmailMessage {
+        to: to.to_string(),
+        subject: subject.to_string(),
+        html_body: format!("<p>{action} by opening <a href=\"{link}\">this link</a>.</p>"),
+        text_body: format!("{action} by opening this link:\n\n{link}\n"),
+    }
+}
+
+/// Verification token storage and delivery, present when verification emails are configured
+struct EmailVerificationSupport {
+    repository: Arc<dyn EmailVerificationTokenRepository>,
+    sender: Arc<dyn EmailSender>,
+    ttl: Duration,
+    cooldown: Duration,
+    url: String,
+}
+
+impl EmailVerificationSupport {
+    fn message(&self, to: &str, raw_token: &str) -> EmailMessage {
+        link_email(
+            to,
+            "Verify your email address",
+            "Confirm your email address",
+            &format!("{}{}", self.url, raw_token),
+        )
+    }
+}
+
+/// Invite code storage, present when invites are configured
+struct InviteSupport {
+    repository: Arc<dyn InviteRepository>,
+    required: bool,
+}
+
+/// Tokens removed by [`UserService::prune_expired_tokens`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PrunedTokens {
+    pub password_reset: u64,
+    pub email_verification: u64,
+}
+
+impl PrunedTokens {
+    pub fn total(&self) -> u64 {
+        self.password_reset + self.email_verification
+    }
+}
+
+/// A started TOTP enrollment, shown to the user once
+#[derive(Debug, Clone)]
+pub struct TotpEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
 }
 
 impl UserService {
-    pub fn new(user_repository: Arc<dyn UserRepository>) -> Self {
-        Self { user_repository }
+    pub fn new(
+        user_repository: Arc<dyn UserRepository>,
+        api_key_repository: Arc<dyn ApiKeyRepository>,
+        audit_log_repository: Arc<dyn AuditLogRepository>,
+    ) -> Self {
+        Self {
+            user_repository,
+            api_key_repository,
+            audit_log_repository,
+            password_hasher: None,
+            totp: None,
+            password_reset: None,
+            email_verification: None,
+            invites: None,
+            session_repository: None,
+            session_limit: None,
+            max_api_keys: None,
+            reuse_deleted_emails: false,
+            first_user_is_admin: false,
+            email_canonicalization: EmailCanonicalization::default(),
+            claim_mapping: ClaimMapping::default(),
+            registration_hook: Arc::new(NoopRegistrationHook),
+            registration_warnings: Vec::new(),
+        }
+    }
+
+    /// Create default resources alongside every new account
+    pub fn with_registration_hook(mut self, hook: Arc<dyn RegistrationHook>) -> Self {
+        self.registration_hook = hook;
+        self
+    }
+
+    /// Add a non-fatal check reported by [`registration_warnings`](Self::registration_warnings)
+    pub fn with_registration_warning(mut self, warning: Arc<dyn RegistrationWarning>) -> Self {
+        self.registration_warnings.push(warning);
+        self
+    }
+
+    /// Read OIDC identities from non-standard claims
+    pub fn with_claim_mapping(mut self, claim_mapping: ClaimMapping) -> Self {
+        self.claim_mapping = claim_mapping;
+        self
+    }
+
+    /// Enable TOTP two-factor authentication
+    pub fn with_totp(
+        mut self,
+        provider: Arc<dyn TotpProvider>,
+        cipher: Arc<dyn SecretCipher>,
+    ) -> Self {
+        self.totp = Some(TotpSupport { provider, cipher });
+        self
+    }
+
+    /// Enable password resets with tokens valid for `ttl`.
+    ///
+    /// Links sent by [`send_password_reset`](Self::send_password_reset) are
+    /// `url` with the raw token appended.
+    pub fn with_password_reset(
+        mut self,
+        repository: Arc<dyn PasswordResetTokenRepository>,
+        sender: Arc<dyn EmailSender>,
+        ttl: Duration,
+        url: impl Into<String>,
+    ) -> Self {
+        self.password_reset = Some(PasswordResetSupport {
+            repository,
+            sender,
+            ttl,
+            url: url.into(),
+        });
+        self
+    }
+
+    /// Enable verification emails with tokens valid for `ttl`, sent at most
+    /// once per `cooldown` to each user.
+    ///
+    /// Links are `url` with the raw token appended, e.g. `https://app/verify?token=`.
+    pub fn with_email_verification(
+        mut self,
+        repository: Arc<dyn EmailVerificationTokenRepository>,
+        sender: Arc<dyn EmailSender>,
+        ttl: Duration,
+        cooldown: Duration,
+        url: impl Into<String>,
+    ) -> Self {
+        self.email_verification = Some(EmailVerificationSupport {
+            repository,
+            sender,
+            ttl,
+            cooldown,
+            url: url.into(),
+        });
+        self
+    }
+
+    /// Let admins mint single-use invite codes.
+    ///
+    /// With `required`, local registration needs an unused code, which the new
+    /// account then uses up; SSO sign-ups are left to the identity provider.
+    pub fn with_invites(mut self, repository: Arc<dyn InviteRepository>, required: bool) -> Self {
+        self.invites = Some(InviteSupport {
+            repository,
+            required,
+        });
+        self
+    }
+
+    /// Keep track of logged-in sessions for admins
+    pub fn with_session_repository(mut self, repository: Arc<dyn SessionRepository>) -> Self {
+        self.session_repository = Some(repository);
+        self
+    }
+
+    /// Cap concurrent sessions per user; needs the session repository
+    pub fn with_session_limit(mut self, limit: SessionLimit) -> Self {
+        self.session_limit = Some(limit);
+        self
+    }
+
+    /// Cap the API keys a user may hold at once; expired keys don't count
+    pub fn with_max_api_keys(mut self, max: u32) -> Self {
+        self.max_api_keys = Some(max);
+        self
+    }
+
+    /// Let new accounts take the email of a soft-deleted one.
+    ///
+    /// The deleted account is never reactivated, since whoever registers now
+    /// may not be its former owner. It keeps its history, but its email is
+    /// replaced by a placeholder ([`User::release_email`]) in the transaction
+    /// creating the new account. Off by default: the email stays taken.
+    pub fn with_deleted_email_reuse(mut self, reuse: bool) -> Self {
+        self.reuse_deleted_emails = reuse;
+        self
+    }
+
+    /// Refuse password registrations whose email reaches an existing account's
+    /// mailbox, e.g. through a plus tag. Only accounts registered while this is
+    /// on carry the canonical form it compares against.
+    pub fn with_email_canonicalization(mut self, rules: EmailCanonicalization) -> Self {
+        self.email_canonicalization = rules;
+        self
+    }
+
+    /// Make the first account ever created an admin, to bootstrap a fresh install.
+    ///
+    /// The claim is taken in the transaction creating the account, so of two
+    /// concurrent registrations on an empty database only one wins. Accounts
+    /// created while this is off never count as first. Off by default.
+    pub fn with_first_user_admin(mut self, enabled: bool) -> Self {
+        self.first_user_is_admin = enabled;
+        self
+    }
+
+    /// Enable local registration with the given hasher
+    pub fn with_password_hasher(mut self, password_hasher: Arc<dyn PasswordHasher>) -> Self {
+        self.password_hasher = Some(password_hasher);
+        self
+    }
+
+    /// Warnings to show alongside a successful registration of `command`
+    pub fn registration_warnings(&self, command: &NewUserCommand) -> Vec<String> {
+        self.registration_warnings
+            .iter()
+            .filter_map(|warning| warning.check(command))
+            .collect()
+    }
+
+    /// Register a local account with a hashed password
+    pub async fn register(&self, command: NewUserCommand) -> DomainResult<User> {
+        let hasher = self.password_hasher.as_ref().ok_or_else(|| {
+            DomainError::InfrastructureError("No password hasher configured".into())
+        })?;
+
+        if command.invite_code.is_none() && self.invites.as_ref().is_some_and(|i| i.required) {
+            return Err(DomainError::InvalidInviteCode(
+                "An invite code is required".into(),
+            ));
+        }
+
+        let canonical = self.canonical_email(&command.email);
+        let existing = match self
+            .user_repository
+            .find_by_email(command.email.as_ref())
+            .await?
+        {
+            Some(user) => Some(user),
+            None => self.find_by_canonical_email(canonical.as_deref()).await?,
+        };
+        let released = self.claim_email(existing, command.email.as_ref())?;
+
+        let mut user = User::new_local(command.email, hasher.hash(&command.password)?);
+        user.canonical_email = canonical;
+        self.create_user(&mut user, released, command.invite_code.as_deref())
+            .await?;
+
+        Ok(user)
+    }
+
+    /// The uniqueness key of `email`, when canonicalization is on
+    fn canonical_email(&self, email: &Email) -> Option<String> {
+        let rules = &self.email_canonicalization;
+        rules.is_enabled().then(|| rules.canonical(email))
+    }
+
+    async fn find_by_canonical_email(&self, canonical: Option<&str>) -> DomainResult<Option<User>> {
+        match canonical {
+            Some(canonical) => {
+                self.user_repository
+                    .find_by_canonical_email(canonical)
+                    .await
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Decide whether the holder of an email stands in the way of a new account.
+    ///
+    /// Returns the deleted account that has to give the address up, if any.
+    fn claim_email(&self, holder: Option<User>, email: &str) -> DomainResult<Option<User>> {
+        match holder {
+            None => Ok(None),
+            Some(user) if user.is_deleted() && self.reuse_deleted_emails => Ok(Some(user)),
+            Some(_) => Err(DomainError::EmailAlreadyExists(email.to_string())),
+        }
+    }
+
+    /// Save a new user, their events and the hook's resources in one transaction.
+    ///
+    /// `released` is a deleted account whose email the new user takes over,
+    /// and `invite_code` is used up when invites are configured. The user
+    /// becomes an admin if they claim the first-user bootstrap.
+    async fn create_user(
+        &self,
+        user: &mut User,
+        released: Option<User>,
+        invite_code: Option<&str>,
+    ) -> DomainResult<()> {
+        let mut tx = self.user_repository.begin().await?;
+        if self.first_user_is_admin
+            && self
+                .user_repository
+                .claim_first_admin(tx.as_mut(), user.id)
+                .await?
+        {
+            user.role = Role::Admin;
+        }
+        if let Some(mut previous) = released {
+            previous.release_email()?;
+            self.user_repository
+                .save_in(tx.as_mut(), &previous, &[])
+                .await?;
+        }
+        self.user_repository
+            .save_in(tx.as_mut(), user, &[OutboxEvent::user_created(user)])
+            .await?;
+        if let (Some(invites), Some(code)) = (&self.invites, invite_code) {
+            let redeemed = invites
+                .repository
+                .redeem_in(tx.as_mut(), code, user.id)
+                .await?;
+            if !redeemed {
+                return Err(DomainError::InvalidInviteCode(
+                    "Unknown or already used".into(),
+                ));
+            }
+        }
+        self.registration_hook
+            .on_register(tx.as_mut(), user)
+            .await?;
+        tx.commit().await
     }
 
+    /// The user signing in as `subject`, linked by `email` or created on first login.
+    ///
+    /// The caller vouches for `email`; logins from a provider go through
+    /// [`find_or_create_from_claims`](Self::find_or_create_from_claims), which
+    /// only links by a verified one.
     pub async fn find_or_create(&self, subject: &str, email: &str) -> DomainResult<User> {
+        self.find_or_link(subject, email, true, None).await
+    }
+
+    /// Subjects and emails are unique in the database, so two concurrent first
+    /// logins can't both create the account: the loser looks it up again and
+    /// gets the winner's.
+    async fn find_or_link(
+        &self,
+        subject: &str,
+        email: &str,
+        email_verified: bool,
+        invite_code: Option<&str>,
+    ) -> DomainResult<User> {
+        match self
+            .find_or_create_once(subject, email, email_verified, invite_code)
+            .await
+        {
+            Err(DomainError::SubjectAlreadyExists(_) | DomainError::EmailAlreadyExists(_)) => {
+                self.find_or_create_once(subject, email, email_verified, invite_code)
+                    .await
+            }
+            result => result,
+        }
+    }
+
+    async fn find_or_create_once(
+        &self,
+        subject: &str,
+        email: &str,
+        email_verified: bool,
+        invite_code: Option<&str>,
+    ) -> DomainResult<User> {
         // 1. Try to find by subject (OIDC id)
         if let Some(user) = self.user_repository.find_by_subject(subject).await? {
             return Ok(user);
         }
 
         // 2. Try to find by email
-        if let Some(mut user) = self.user_repository.find_by_email(email).await? {
-            // Link subject if missing (account linking logic)
+        let existing = self.user_repository.find_by_email(email).await?;
+        if let Some(user) = existing.clone().filter(|u| !u.is_deleted()) {
+            // Link subject if missing (account linking logic). Anyone can put
+            // someone else's address on an account at some providers, so only
+            // a verified one is proof of owning this account.
             if user.subject != subject {
+                if !email_verified {
+                    return Err(DomainError::unauthorized(
+                        "The sign-in provider has not verified this email, so it can't be linked to the existing account",
+                    ));
+                }
+                User::check_subject(subject)?;
+                let mut user = self.find_for_update(user.id).await?;
                 user.subject = subject.to_string();
                 self.user_repository.save(&user).await?;
+                return Ok(user);
             }
             return Ok(user);
         }
 
-        // 3. Create new user
+        // 3. Create new user, unless a live account holds the address or its canonical form
+        if invite_code.is_none() && self.invites.as_ref().is_some_and(|i| i.required) {
+            return Err(DomainError::InvalidInviteCode(
+                "An invite code is required".into(),
+            ));
+        }
         let email = Email::try_from(email)?;
-        let user = User::new(subject, email);
-        self.user_repository.save(&user).await?;
+        let canonical = self.canonical_email(&email);
+        let existing = match existing {
+            Some(user) => Some(user),
+            None => self.find_by_canonical_email(canonical.as_deref()).await?,
+        };
+        let released = self.claim_email(existing, email.as_ref())?;
+
+        let mut user = User::new(subject, email)?;
+        user.canonical_email = canonical;
+        self.create_user(&mut user, released, invite_code).await?;
 
         Ok(user)
     }
 
+    /// [`find_or_create`](Self::find_or_create) for verified OIDC ID token claims,
+    /// read through the configured [`ClaimMapping`].
+    ///
+    /// Only an `email_verified` email links to an existing account, and a new
+    /// account needs `invite_code` when invites are required, as in
+    /// [`register`](Self::register).
+    pub async fn find_or_create_from_claims(
+        &self,
+        claims: &serde_json::Value,
+        invite_code: Option<&str>,
+    ) -> DomainResult<User> {
+        let identity = self.claim_mapping.identity(claims)?;
+        self.find_or_link(
+            &identity.subject,
+            identity.email.as_ref(),
+            identity.email_verified,
+            invite_code,
+        )
+        .await
+    }
+
+    /// Claims from a verified ID token to keep in the session, per the
+    /// [`ClaimMapping`] allowlist
+    pub fn session_claims(
+        &self,
+        claims: &serde_json::Value,
+    ) -> serde_json::Map<String, serde_json::Value> {
+        self.claim_mapping.select_session_claims(claims)
+    }
+
     pub async fn find_by_id(&self, id: Uuid) -> DomainResult<User> {
+        self.user_repository.find_by_id(id).await?.or_not_found(id)
+    }
+
+    /// A user about to be changed and saved back, read from where the save
+    /// goes so it doesn't overwrite a newer write with a stale copy
+    async fn find_for_update(&self, id: Uuid) -> DomainResult<User> {
         self.user_repository
-            .find_by_id(id)
+            .find_by_id_for_update(id)
             .await?
-            .ok_or(DomainError::UserNotFound(id))
+            .or_not_found(id)
     }
 
     pub async fn find_by_email(&self, email: &str) -> DomainResult<Option<User>> {
         self.user_repository.find_by_email(email).await
     }
+
+    /// Whether `email` is free to register, by the same rules as [`register`](Self::register)
+    pub async fn email_available(&self, email: &Email) -> DomainResult<bool> {
+        // Free addresses, the common case, are answered without loading a user
+        let holder = if self.user_repository.exists_by_email(email.as_ref()).await? {
+            self.user_repository.find_by_email(email.as_ref()).await?
+        } else {
+            let canonical = self.canonical_email(email);
+            self.find_by_canonical_email(canonical.as_deref()).await?
+        };
+        match self.claim_email(holder, email.as_ref()) {
+            Ok(_) => Ok(true),
+            Err(DomainError::EmailAlreadyExists(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Record a successful login
+    pub async fn record_login(&self, user_id: Uuid) -> DomainResult<User> {
+        let mut user = self.find_for_update(user_id).await?;
+        user.last_login_at = Some(Utc::now());
+        self.user_repository.save(&user).await?;
+        Ok(user)
+    }
+
+    /// Soft-delete or erase accounts inactive beyond the retention period.
+    ///
+    /// Each account is processed in one transaction with its audit entry. An
+    /// erased account takes its sessions and tokens along, and its API keys
+    /// go through their foreign key. Returns the IDs of the processed users.
+    pub async fn apply_retention(&self, policy: &RetentionPolicy) -> DomainResult<Vec<Uuid>> {
+        let now = Utc::now();
+        let stale = self
+            .user_repository
+            .find_inactive_since(policy.cutoff(now))
+            .await?;
+
+        let mut processed = Vec::new();
+        for user in stale.into_iter().filter(|u| !policy.is_exempt(u)) {
+            let mut tx = self.user_repository.begin().await?;
+            let action = match policy.mode {
+                RetentionMode::SoftDelete => {
+                    let mut user = self.find_for_update(user.id).await?;
+                    user.deleted_at = Some(now);
+                    self.user_repository
+                        .save_in(tx.as_mut(), &user, &[])
+                        .await?;
+                    AuditAction::UserSoftDeleted
+                }
+                RetentionMode::Erase => {
+                    self.erase_in(tx.as_mut(), user.id).await?;
+                    AuditAction::UserErased
+                }
+            };
+
+            let entry = AuditEntry::new(SYSTEM_ACTOR_ID, action, Some(user.id));
+            self.audit_log_repository
+                .record_in(tx.as_mut(), &entry)
+                .await?;
+            tx.commit().await?;
+            processed.push(user.id);
+        }
+
+        Ok(processed)
+    }
+
+    /// Delete a user inside `tx` along with their sessions and tokens
+    async fn erase_in(&self, tx: &mut dyn Transaction, user_id: Uuid) -> DomainResult<()> {
+        if let Some(sessions) = &self.session_repository {
+            sessions.revoke_for_user_in(tx, user_id).await?;
+        }
+        if let Some(reset) = &self.password_reset {
+            reset.repository.delete_for_user_in(tx, user_id).await?;
+        }
+        if let Some(verification) = &self.email_verification {
+            verification
+                .repository
+                .delete_for_user_in(tx, user_id)
+                .await?;
+        }
+        self.user_repository.delete_in(tx, user_id).await
+    }
+
+    /// Load many users in one round trip; missing IDs are skipped
+    pub async fn get_many(&self, ids: &[Uuid]) -> DomainResult<Vec<User>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.user_repository.find_by_ids(ids).await
+    }
+}
+
+impl UserService {
+    /// Create a new API key for the user.
+    ///
+    /// The raw key is returned only here; just its hash is persisted.
+    /// Fails with [`DomainError::ApiKeyLimitReached`] once the user holds the
+    /// maximum number of unexpired keys.
+    pub async fn create_api_key(
+        &self,
+        user_id: Uuid,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> DomainResult<(ApiKeyId, String)> {
+        // Ensure the owner exists
+        self.find_by_id(user_id).await?;
+
+        let (key, raw_key) = ApiKey::generate(user_id, expires_at);
+        match self.max_api_keys {
+            Some(max) => {
+                let inserted = self
+                    .api_key_repository
+                    .insert_within_limit(&key, max, Utc::now())
+                    .await?;
+                if !inserted {
+                    return Err(DomainError::ApiKeyLimitReached(max));
+                }
+            }
+            None => self.api_key_repository.save(&key).await?,
+        }
+
+        Ok((key.id, raw_key))
+    }
+
+    pub async fn list_api_keys(&self, user_id: Uuid) -> DomainResult<Vec<ApiKey>> {
+        self.api_key_repository.find_by_user(user_id).await
+    }
+
+    /// Revoke one of the user's API keys
+    pub async fn revoke_api_key(&self, user_id: Uuid, key_id: ApiKeyId) -> DomainResult<()> {
+        self.api_key_repository
+            .find_by_id(key_id)
+            .await?
+            .filter(|key| key.user_id == user_id)
+            .or_domain_err(|| DomainError::ApiKeyNotFound(key_id))?;
+
+        self.api_key_repository.delete(key_id).await
+    }
+
+    /// Resolve the user owning a raw API key.
+    ///
+    /// Returns `None` for unknown or expired keys and keys of deleted accounts,
+    /// and records the last use otherwise.
+    pub async fn authenticate_api_key(&self, raw_key: &str) -> DomainResult<Option<User>> {
+        let key_hash = ApiKey::hash_key(raw_key);
+        let Some(mut key) = self.api_key_repository.find_by_hash(&key_hash).await? else {
+            return Ok(None);
+        };
+
+        let now = Utc::now();
+        if key.is_expired(now) {
+            return Ok(None);
+        }
+        let Some(user) = self
+            .user_repository
+            .find_by_id(key.user_id)
+            .await?
+            .filter(|user| !user.is_deleted())
+        else {
+            return Ok(None);
+        };
+
+        key.last_used_at = Some(now);
+        self.api_key_repository.save(&key).await?;
+
+        Ok(Some(user))
+    }
+}
+
+impl UserService {
+    /// Let an admin act as another user, recording who did it.
+    ///
+    /// Returns the user to log in as.
+    pub async fn start_impersonation(&self, admin_id: Uuid, target_id: Uuid) -> DomainResult<User> {
+        let admin = self.find_by_id(admin_id).await?;
+        if !admin.is_admin() {
+            return Err(DomainError::unauthorized(
+                "Only admins can impersonate users",
+            ));
+        }
+        if admin_id == target_id {
+            return Err(DomainError::validation("Cannot impersonate yourself"));
+        }
+
+        let target = self.find_by_id(target_id).await?;
+        if target.is_admin() {
+            return Err(DomainError::unauthorized(
+                "Cannot impersonate another admin",
+            ));
+        }
+
+        let entry = AuditEntry::new(admin_id, AuditAction::ImpersonationStarted, Some(target_id));
+        self.audit_log_repository.record(&entry).await?;
+
+        Ok(target)
+    }
+
+    /// End an impersonation, returning the admin to log back in as
+    pub async fn stop_impersonation(&self, admin_id: Uuid, target_id: Uuid) -> DomainResult<User> {
+        let admin = self.find_by_id(admin_id).await?;
+        if !admin.is_admin() {
+            return Err(DomainError::unauthorized(
+                "Only admins can impersonate users",
+            ));
+        }
+
+        let entry = AuditEntry::new(admin_id, AuditAction::ImpersonationStopped, Some(target_id));
+        self.audit_log_repository.record(&entry).await?;
+
+        Ok(admin)
+    }
+
+    /// Fold a duplicate account into the primary one.
+    ///
+    /// The duplicate's API keys and provider subject move to the primary and
+    /// the duplicate is soft-deleted, atomically, with an audit entry. Fails
+    /// with [`DomainError::ApiKeyLimitReached`] if the keys would put the
+    /// primary over the limit. The duplicate's sessions end and its
+    /// reset and verification tokens are dropped rather than moved, since they
+    /// speak for the duplicate's own login and address. Admin accounts are only
+    /// merged when `allow_admins` is set.
+    pub async fn merge_accounts(
+        &self,
+        primary_id: Uuid,
+        duplicate_id: Uuid,
+        allow_admins: bool,
+    ) -> DomainResult<User> {
+        if primary_id == duplicate_id {
+            return Err(DomainError::validation(
+                "Cannot merge an account into itself",
+            ));
+        }
+
+        let mut primary = self.find_for_update(primary_id).await?;
+        let mut duplicate = self.find_for_update(duplicate_id).await?;
+        if primary.is_deleted() {
+            return Err(DomainError::validation(
+                "Cannot merge into a deleted account",
+            ));
+        }
+        if duplicate.is_deleted() {
+            return Err(DomainError::validation("Cannot merge a deleted account"));
+        }
+        if !allow_admins && (primary.is_admin() || duplicate.is_admin()) {
+            return Err(DomainError::unauthorized(
+                "Merging admin accounts requires an override",
+            ));
+        }
+
+        // Both accounts stay reachable through one provider identity at most
+        let takes_subject = duplicate.has_provider_subject();
+        if takes_subject && primary.has_provider_subject() {
+            return Err(DomainError::validation(
+                "Both accounts are linked to a sign-in provider; an account can be linked to only one",
+            ));
+        }
+
+        let mut tx = self.user_repository.begin().await?;
+        let reassigned = self
+            .api_key_repository
+            .reassign_in(
+                tx.as_mut(),
+                duplicate_id,
+                primary_id,
+                self.max_api_keys,
+                Utc::now(),
+            )
+            .await?;
+        if !reassigned {
+            // Only a set limit refuses; dropping `tx` rolls back
+            return Err(DomainError::ApiKeyLimitReached(
+                self.max_api_keys.unwrap_or_default(),
+            ));
+        }
+        if let Some(sessions) = &self.session_repository {
+            sessions
+                .revoke_for_user_in(tx.as_mut(), duplicate_id)
+                .await?;
+        }
+        if let Some(reset) = &self.password_reset {
+            reset
+                .repository
+                .delete_for_user_in(tx.as_mut(), duplicate_id)
+                .await?;
+        }
+        if let Some(verification) = &self.email_verification {
+            verification
+                .repository
+                .delete_for_user_in(tx.as_mut(), duplicate_id)
+                .await?;
+        }
+        duplicate.deleted_at = Some(Utc::now());
+        if takes_subject {
+            primary.subject = duplicate.subject.clone();
+            duplicate.release_subject();
+        }
+        // The duplicate first, so the subject is free when the primary takes it
+        self.user_repository
+            .save_in(tx.as_mut(), &duplicate, &[])
+            .await?;
+        if takes_subject {
+            self.user_repository
+                .save_in(tx.as_mut(), &primary, &[])
+                .await?;
+        }
+        // Filtering the log by either account finds the merge
+        let entry = AuditEntry::new(primary_id, AuditAction::AccountsMerged, Some(duplicate_id));
+        self.audit_log_repository
+            .record_in(tx.as_mut(), &entry)
+            .await?;
+        tx.commit().await?;
+
+        Ok(primary)
+    }
+
+    /// Search the audit log, newest first.
+    ///
+    /// Returns one page of entries and the total number of matches.
+    pub async fn search_audit_log(
+        &self,
+        filter: &AuditLogFilter,
+        limit: u32,
+        offset: u32,
+    ) -> DomainResult<(Vec<AuditEntry>, u64)> {
+        if let (Some(from), Some(to)) = (filter.from, filter.to)
+            && from > to
+        {
+            return Err(DomainError::validation("`from` must not be after `to`"));
+        }
+
+        let entries = self
+            .audit_log_repository
+            .search(filter, limit, offset)
+            .await?;
+        let total = self.audit_log_repository.count(filter).await?;
+        Ok((entries, total))
+    }
+
+    /// Record a new login session; does nothing unless session tracking is enabled
+    pub async fn record_session(&self, session: &SessionInfo) -> DomainResult<()> {
+        match &self.session_repository {
+            Some(repository) => repository.record(session).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Make room for a new session of `user_id` under the session limit.
+    ///
+    /// Call before logging the user in. At the limit, either rejects the
+    /// login or revokes the oldest sessions, per the limit's mode.
+    pub async fn enforce_session_limit(&self, user_id: Uuid) -> DomainResult<()> {
+        let (Some(limit), Some(repository)) = (&self.session_limit, &self.session_repository)
+        else {
+            return Ok(());
+        };
+
+        let filter = SessionFilter {
+            user_id: Some(user_id),
+        };
+        let now = Utc::now();
+        let active = repository.count(&filter, now).await?;
+        if active < u64::from(limit.max) {
+            return Ok(());
+        }
+
+        match limit.mode {
+            SessionLimitMode::Reject => Err(DomainError::SessionLimitReached(limit.max)),
+            SessionLimitMode::EvictOldest => {
+                // Newest first, so everything past the newest `max - 1` goes
+                let keep = limit.max.saturating_sub(1);
+                let excess = u32::try_from(active - u64::from(keep)).unwrap_or(u32::MAX);
+                for session in repository.list(&filter, now, excess, keep).await? {
+                    repository.revoke(&session.session_id).await?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// End every other session of `user_id`, e.g. after a password change.
+    ///
+    /// Returns how many were revoked; does nothing unless session tracking is enabled.
+    pub async fn revoke_other_sessions(&self, user_id: Uuid, keep: &str) -> DomainResult<usize> {
+        let Some(repository) = &self.session_repository else {
+            return Ok(0);
+        };
+
+        let filter = SessionFilter {
+            user_id: Some(user_id),
+        };
+        let sessions = repository.list(&filter, Utc::now(), u32::MAX, 0).await?;
+        let mut revoked = 0;
+        for session in sessions.iter().filter(|s| s.session_id != keep) {
+            repository.revoke(&session.session_id).await?;
+            revoked += 1;
+        }
+        Ok(revoked)
+    }
+
+    /// List active sessions, newest first.
+    ///
+    /// Returns one page of sessions and the total number of matches.
+    pub async fn list_sessions(
+        &self,
+        filter: &SessionFilter,
+        limit: u32,
+        offset: u32,
+    ) -> DomainResult<(Vec<SessionInfo>, u64)> {
+        let repository = self
+            .session_repository
+            .as_ref()
+            .ok_or_else(|| DomainError::validation("Session tracking is not configured"))?;
+
+        let now = Utc::now();
+        let sessions = repository.list(filter, now, limit, offset).await?;
+        let total = repository.count(filter, now).await?;
+        Ok((sessions, total))
+    }
+
+    /// Search users by email, ordered by email.
+    ///
+    /// Returns one page of users and the total number of matches.
+    pub async fn search_users(
+        &self,
+        term: &str,
+        match_mode: EmailMatchMode,
+        limit: u32,
+        offset: u32,
+    ) -> DomainResult<(Vec<User>, u64)> {
+        // Emails are stored lowercased
+        let term = term.trim().to_lowercase();
+
+        let users = self
+            .user_repository
+            .search(&term, match_mode, limit, offset)
+            .await?;
+        let total = self
+            .user_repository
+            .count_matching(&term, match_mode)
+            .await?;
+        Ok((users, total))
+    }
+
+    /// Users matching `filter`, ordered by email
+    pub async fn find_users(
+        &self,
+        filter: &UserFilter,
+        limit: u32,
+        offset: u32,
+    ) -> DomainResult<Vec<User>> {
+        self.user_repository.find(filter, limit, offset).await
+    }
+}
+
+impl UserService {
+    fn totp(&self) -> DomainResult<&TotpSupport> {
+        self.totp
+            .as_ref()
+            .ok_or_else(|| DomainError::validation("Two-factor authentication is not configured"))
+    }
+
+    /// Start TOTP enrollment with a fresh secret.
+    ///
+    /// Two-factor stays disabled until [`confirm_totp`](Self::confirm_totp) succeeds;
+    /// restarting replaces any unconfirmed secret.
+    pub async fn start_totp_enrollment(&self, user_id: Uuid) -> DomainResult<TotpEnrollment> {
+        let totp = self.totp()?;
+        let mut user = self.find_for_update(user_id).await?;
+        if user.totp_enabled {
+            return Err(DomainError::validation(
+                "Two-factor authentication is already enabled",
+            ));
+        }
+
+        let secret = totp.provider.generate_secret()?;
+        let otpauth_uri = totp.provider.provisioning_uri(&secret, user.email_str())?;
+
+        user.totp_secret = Some(totp.cipher.encrypt(&secret)?);
+        self.user_repository.save(&user).await?;
+
+        Ok(TotpEnrollment {
+            secret,
+            otpauth_uri,
+        })
+    }
+
+    /// Confirm enrollment with a code from the authenticator, enabling two-factor
+    pub async fn confirm_totp(&self, user_id: Uuid, code: &str) -> DomainResult<User> {
+        let mut user = self.find_for_update(user_id).await?;
+        if user.totp_enabled {
+            return Err(DomainError::validation(
+                "Two-factor authentication is already enabled",
+            ));
+        }
+
+        let step = self.check_totp_code(&user, code)?;
+        self.claim_totp_step(user.id, step).await?;
+        user.totp_enabled = true;
+        self.user_repository.save(&user).await?;
+        Ok(user)
+    }
+
+    /// Verify the second login step for a user with two-factor enabled
+    pub async fn verify_totp(&self, user_id: Uuid, code: &str) -> DomainResult<User> {
+        let user = self.find_by_id(user_id).await?;
+        if !user.requires_totp() {
+            return Err(DomainError::validation(
+                "Two-factor authentication is not enabled",
+            ));
+        }
+
+        let step = self.check_totp_code(&user, code)?;
+        self.claim_totp_step(user.id, step).await?;
+        Ok(user)
+    }
+
+    /// The time step of a valid `code` for the user's secret
+    fn check_totp_code(&self, user: &User, code: &str) -> DomainResult<u64> {
+        let totp = self.totp()?;
+        let encrypted = user
+            .totp_secret
+            .as_deref()
+            .ok_or_else(|| DomainError::validation("No two-factor enrollment in progress"))?;
+        let secret = totp.cipher.decrypt(encrypted)?;
+
+        totp.provider
+            .verify(&secret, code)?
+            .ok_or_else(|| DomainError::unauthorized("Invalid two-factor code"))
+    }
+
+    /// Accept each time step once, so an intercepted code can't be replayed
+    async fn claim_totp_step(&self, user_id: Uuid, step: u64) -> DomainResult<()> {
+        if self.user_repository.claim_totp_step(user_id, step).await? {
+            Ok(())
+        } else {
+            Err(DomainError::unauthorized(
+                "Two-factor code was already used",
+            ))
+        }
+    }
+}
+
+impl UserService {
+    fn password_reset(&self) -> DomainResult<&PasswordResetSupport> {
+        self.password_reset
+            .as_ref()
+            .ok_or_else(|| DomainError::validation("Password reset is not configured"))
+    }
+
+    /// Issue a reset token for the account with this email.
+    ///
+    /// Returns `None` for unknown, deleted and SSO-only accounts; callers should
+    /// respond the same either way, so the endpoint doesn't reveal who has an account.
+    pub async fn request_password_reset(&self, email: &str) -> DomainResult<Option<String>> {
+        let reset = self.password_reset()?;
+        let Some(user) = self.user_repository.find_by_email(email).await? else {
+            return Ok(None);
+        };
+        if user.is_deleted() || user.password_hash.is_none() {
+            return Ok(None);
+        }
+
+        let (token, raw_token) = PasswordResetToken::generate(user.id, reset.ttl);
+        reset.repository.save(&token).await?;
+        Ok(Some(raw_token))
+    }
+
+    /// Email a reset link to the account with this email, if it can have one.
+    ///
+    /// Returns whether a link was sent; like [`request_password_reset`](Self::request_password_reset)
+    /// this is `false` for unknown, deleted and SSO-only accounts.
+    pub async fn send_password_reset(&self, email: &str) -> DomainResult<bool> {
+        let Some(raw_token) = self.request_password_reset(email).await? else {
+            return Ok(false);
+        };
+        let reset = self.password_reset()?;
+        let message = link_email(
+            email,
+            "Reset your password",
+            "Choose a new password",
+            &format!("{}{}", reset.url, raw_token),
+        );
+        reset.sender.send(&message).await?;
+        Ok(true)
+    }
+
+    /// Set a new password with a reset token.
+    ///
+    /// The token is consumed before anything else, so a replay fails even if
+    /// this request errors later; the user's other tokens are invalidated too.
+    pub async fn reset_password(&self, raw_token: &str, password: &Password) -> DomainResult<User> {
+        let reset = self.password_reset()?;
+        let token = reset
+            .repository
+            .take(&PasswordResetToken::hash_token(raw_token))
+            .await?
+            .filter(|token| !token.is_expired(Utc::now()))
+            .ok_or_else(|| DomainError::unauthorized("Invalid or expired reset token"))?;
+
+        let user = self.find_for_update(token.user_id).await?;
+        if user.is_deleted() {
+            return Err(DomainError::unauthorized("Account has been deleted"));
+        }
+        self.set_password(user, password).await
+    }
+
+    /// Delete expired password reset and verification tokens.
+    ///
+    /// Used tokens are deleted as they are taken, so only expired ones pile
+    /// up; token kinds that aren't configured are skipped.
+    pub async fn prune_expired_tokens(&self) -> DomainResult<PrunedTokens> {
+        let now = Utc::now();
+        let mut pruned = PrunedTokens::default();
+        if let Some(reset) = &self.password_reset {
+            pruned.password_reset = reset.repository.delete_expired(now).await?;
+        }
+        if let Some(verification) = &self.email_verification {
+            pruned.email_verification = verification.repository.delete_expired(now).await?;
+        }
+        Ok(pruned)
+    }
+
+    fn invites(&self) -> DomainResult<&InviteSupport> {
+        self.invites
+            .as_ref()
+            .ok_or_else(|| DomainError::validation("Invites are not configured"))
+    }
+
+    /// Mint a single-use invite code
+    pub async fn create_invite(&self, admin_id: Uuid) -> DomainResult<InviteCode> {
+        let invite = InviteCode::generate(admin_id);
+        self.invites()?.repository.save(&invite).await?;
+        Ok(invite)
+    }
+
+    /// One page of invite codes, newest first, and their total number
+    pub async fn list_invites(
+        &self,
+        limit: u32,
+        offset: u32,
+    ) -> DomainResult<(Vec<InviteCode>, u64)> {
+        let repository = &self.invites()?.repository;
+        let invites = repository.list(limit, offset).await?;
+        let total = repository.count().await?;
+        Ok((invites, total))
+    }
+
+    fn email_verification(&self) -> DomainResult<&EmailVerificationSupport> {
+        self.email_verification
+            .as_ref()
+            .ok_or_else(|| DomainError::validation("Email verification is not configured"))
+    }
+
+    /// Send a logged-in user a new verification email, replacing any earlier link.
+    ///
+    /// Fails with [`DomainError::RateLimited`] within the cooldown of the last one.
+    pub async fn resend_verification(&self, user_id: Uuid) -> DomainResult<()> {
+        let user = self.find_by_id(user_id).await?;
+        if user.email_verified {
+            return Err(DomainError::validation("Email is already verified"));
+        }
+        self.send_verification(&user).await
+    }
+
+    /// Like [`resend_verification`](Self::resend_verification), by email.
+    ///
+    /// Unknown, deleted and verified accounts are skipped, as are ones still
+    /// in their cooldown, all without an error: callers should respond the
+    /// same either way, so the endpoint doesn't reveal who has an account.
+    pub async fn resend_verification_by_email(&self, email: &str) -> DomainResult<()> {
+        self.email_verification()?;
+        let Some(user) = self.user_repository.find_by_email(email).await? else {
+            return Ok(());
+        };
+        if user.is_deleted() || user.email_verified {
+            return Ok(());
+        }
+        match self.send_verification(&user).await {
+            Err(DomainError::RateLimited(_)) => Ok(()),
+            result => result,
+        }
+    }
+
+    async fn send_verification(&self, user: &User) -> DomainResult<()> {
+        let verification = self.email_verification()?;
+        let now = Utc::now();
+        if let Some(latest) = verification.repository.latest_for_user(user.id).await? {
+            let wait = latest.created_at + verification.cooldown - now;
+            if wait > Duration::zero() {
+                // Rounded up, so retrying after the wait always succeeds
+                let secs = (wait.num_milliseconds() + 999) / 1000;
+                return Err(DomainError::RateLimited(secs as u64));
+            }
+        }
+
+        let (token, raw_token) = EmailVerificationToken::generate(user.id, verification.ttl);
+        verification.repository.delete_for_user(user.id).await?;
+        verification.repository.save(&token).await?;
+        verification
+            .sender
+            .send(&verification.message(user.email_str(), &raw_token))
+            .await
+    }
+
+    /// Mark the email of a verification token's owner as verified, consuming the token
+    pub async fn verify_email(&self, raw_token: &str) -> DomainResult<User> {
+        let verification = self.email_verification()?;
+        let token = verification
+            .repository
+            .take(&EmailVerificationToken::hash_token(raw_token))
+            .await?
+            .filter(|token| !token.is_expired(Utc::now()))
+            .ok_or_else(|| DomainError::unauthorized("Invalid or expired verification token"))?;
+
+        let mut user = self.find_for_update(token.user_id).await?;
+        if user.is_deleted() {
+            return Err(DomainError::unauthorized("Account has been deleted"));
+        }
+        user.mark_email_verified();
+        self.user_repository.save(&user).await?;
+        Ok(user)
+    }
+
+    /// Replace a user's preferences; see [`User::set_preferences`] for the limits
+    pub async fn update_preferences(
+        &self,
+        user_id: Uuid,
+        preferences: serde_json::Value,
+    ) -> DomainResult<User> {
+        let mut user = self.find_for_update(user_id).await?;
+        user.set_preferences(preferences)?;
+        self.user_repository.save(&user).await?;
+        Ok(user)
+    }
+
+    /// Apply `command` to a user's profile
+    pub async fn update_profile(
+        &self,
+        user_id: Uuid,
+        command: UpdateProfileCommand,
+    ) -> DomainResult<User> {
+        let mut user = self.find_for_update(user_id).await?;
+        if let Some(display_name) = command.display_name {
+            self.user_repository
+                .update_display_name(user.id, display_name.as_ref())
+                .await?;
+            user.display_name = display_name;
+        }
+        Ok(user)
+    }
+
+    /// Replace a user's password, invalidating their outstanding reset tokens
+    pub async fn change_password(&self, user_id: Uuid, password: &Password) -> DomainResult<User> {
+        let user = self.find_for_update(user_id).await?;
+        self.set_password(user, password).await
+    }
+
+    async fn set_password(&self, mut user: User, password: &Password) -> DomainResult<User> {
+        let hasher = self.password_hasher.as_ref().ok_or_else(|| {
+            DomainError::InfrastructureError("No password hasher configured".into())
+        })?;
+
+        user.password_hash = Some(hasher.hash(password)?);
+        self.user_repository.save(&user).await?;
+        if let Some(reset) = &self.password_reset {
+            reset.repository.delete_for_user(user.id).await?;
+        }
+        Ok(user)
+    }
+}
+
+/// Guards registration behind a CAPTCHA check
+pub struct CaptchaGuard {
+    verifier: Arc<dyn CaptchaVerifier>,
+    strict: bool,
+}
+
+impl CaptchaGuard {
+    /// In `strict` mode provider failures reject the request, otherwise they are let through.
+    pub fn new(verifier: Arc<dyn CaptchaVerifier>, strict: bool) -> Self {
+        Self { verifier, strict }
+    }
+
+    pub async fn check(&self, token: Option<&str>) -> DomainResult<()> {
+        let token = token
+            .map(str::trim)
+            .filter(|token| !token.is_empty())
+            .ok_or_else(|| DomainError::validation("CAPTCHA token is required"))?;
+
+        match self.verifier.verify(token).await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(DomainError::validation("CAPTCHA verification failed")),
+            Err(e) if self.strict => {
+                tracing::error!("CAPTCHA provider error, rejecting: {}", e);
+                Err(DomainError::validation("CAPTCHA could not be verified"))
+            }
+            Err(e) => {
+                tracing::warn!("CAPTCHA provider error, allowing: {}", e);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Delivers pending outbox events through an [`EventPublisher`].
+///
+/// Failed deliveries are retried with exponential backoff.
+pub struct OutboxDispatcher {
+    outbox_repository: Arc<dyn OutboxRepository>,
+    publisher: Arc<dyn EventPublisher>,
+    batch_size: u32,
+}
+
+impl OutboxDispatcher {
+    const BASE_BACKOFF_SECS: i64 = 5;
+    const MAX_BACKOFF_SECS: i64 = 3600;
+
+    pub fn new(
+        outbox_repository: Arc<dyn OutboxRepository>,
+        publisher: Arc<dyn EventPublisher>,
+        batch_size: u32,
+    ) -> Self {
+        Self {
+            outbox_repository,
+            publisher,
+            batch_size,
+        }
+    }
+
+    /// Delay before the next attempt after `attempts` failures
+    pub fn backoff(attempts: u32) -> Duration {
+        let secs = Self::BASE_BACKOFF_SECS
+            .saturating_mul(1_i64 << attempts.min(20))
+            .min(Self::MAX_BACKOFF_SECS);
+        Duration::seconds(secs)
+    }
+
+    /// Attempt one batch of due events.
+    ///
+    /// Returns the number of events delivered.
+    pub async fn dispatch_once(&self) -> DomainResult<usize> {
+        let now = Utc::now();
+        let pending = self
+            .outbox_repository
+            .fetch_pending(now, self.batch_size)
+            .await?;
+
+        let mut delivered = 0;
+        for event in pending {
+            match self.publisher.publish(&event).await {
+                Ok(()) => {
+                    self.outbox_repository
+                        .mark_delivered(event.id, Utc::now())
+                        .await?;
+                    delivered += 1;
+                }
+                Err(e) => {
+                    tracing::warn!(event_id = %event.id, topic = %event.topic, "Outbox delivery failed: {}", e);
+                    let next_attempt_at = now + Self::backoff(event.attempts);
+                    self.outbox_repository
+                        .mark_failed(event.id, &e.to_string(), next_attempt_at)
+                        .await?;
+                }
+            }
+        }
+
+        Ok(delivered)
+    }
+}
+
+/// Welcome email subject and bodies.
+///
+/// `{{email}}` is replaced with the new user's address; any other placeholder
+/// is a rendering error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WelcomeEmailTemplate {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+impl Default for WelcomeEmailTemplate {
+    fn default() -> Self {
+        Self {
+            subject: "Welcome!".to_string(),
+            html: "<p>Hi {{email}},</p><p>Thanks for signing up. Your account is ready.</p>"
+                .to_string(),
+            text: "Hi {{email}},\n\nThanks for signing up. Your account is ready.\n".to_string(),
+        }
+    }
+}
+
+impl WelcomeEmailTemplate {
+    pub fn render(&self, email: &str) -> DomainResult<EmailMessage> {
+        Ok(EmailMessage {
+            to: email.to_string(),
+            subject: Self::fill(&self.subject, email)?,
+            html_body: Self::fill(&self.html, email)?,
+            text_body: Self::fill(&self.text, email)?,
+        })
+    }
+
+    fn fill(template: &str, email: &str) -> DomainResult<String> {
+        let mut output = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find("{{") {
+            output.push_str(&rest[..start]);
+            let end = rest[start..]
+                .find("}}")
+                .ok_or_else(|| DomainError::validation("Unclosed placeholder in template"))?;
+            match rest[start + 2..start + end].trim() {
+                "email" => output.push_str(email),
+                other => {
+                    return Err(DomainError::validation(format!(
+                        "Unknown template placeholder: {}",
+                        other
+                    )));
+                }
+            }
+            rest = &rest[start + end + 2..];
+        }
+        output.push_str(rest);
+        Ok(output)
+    }
+}
+
+/// Publisher sending a welcome email for every `user.created` event, then
+/// forwarding the event to `inner`.
+///
+/// Runs from the outbox, so registration never waits on the mail server and a
+/// failed send is retried. The email goes first because a retry repeats both
+/// steps: a failed send must not leave the event already forwarded. A
+/// template that cannot be rendered is logged and skipped rather than
+/// retried forever.
+pub struct WelcomeEmailPublisher {
+    inner: Arc<dyn EventPublisher>,
+    sender: Arc<dyn EmailSender>,
+    template: WelcomeEmailTemplate,
+}
+
+impl WelcomeEmailPublisher {
+    pub fn new(
+        inner: Arc<dyn EventPublisher>,
+        sender: Arc<dyn EmailSender>,
+        template: WelcomeEmailTemplate,
+    ) -> Self {
+        Self {
+            inner,
+            sender,
+            template,
+        }
+    }
+}
+
+#[async_trait]
+impl EventPublisher for WelcomeEmailPublisher {
+    async fn publish(&self, event: &OutboxEvent) -> DomainResult<()> {
+        self.send_welcome(event).await?;
+        self.inner.publish(event).await
+    }
+}
+
+impl WelcomeEmailPublisher {
+    async fn send_welcome(&self, event: &OutboxEvent) -> DomainResult<()> {
+        if event.topic != OutboxEvent::USER_CREATED {
+            return Ok(());
+        }
+
+        let Some(email) = event.payload.get("email").and_then(|v| v.as_str()) else {
+            tracing::warn!(event_id = %event.id, "user.created event without an email");
+            return Ok(());
+        };
+
+        match self.template.render(email) {
+            Ok(message) => self.sender.send(&message).await,
+            Err(e) => {
+                tracing::error!(event_id = %event.id, "Failed to render welcome email: {}", e);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use chrono::Duration;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use crate::repositories::Transaction;
+    use crate::value_objects::DisplayName;
+
+    #[derive(Default)]
+    struct InMemoryUserRepository {
+        users: Arc<Mutex<Vec<User>>>,
+        outbox: Arc<InMemoryOutboxRepository>,
+        admin_claimed: std::sync::atomic::AtomicBool,
+        totp_steps: Mutex<HashMap<Uuid, u64>>,
+    }
+
+    /// Buffers writes until commit
+    #[derive(Default)]
+    struct InMemoryTransaction {
+        writes: Vec<Box<dyn FnOnce() + Send>>,
+    }
+
+    impl InMemoryTransaction {
+        fn from_dyn(tx: &mut dyn Transaction) -> &mut Self {
+            tx.as_any_mut()
+                .downcast_mut()
+                .expect("in-memory transaction")
+        }
+
+        fn push(&mut self, write: impl FnOnce() + Send + 'static) {
+            self.writes.push(Box::new(write));
+        }
+    }
+
+    #[async_trait]
+    impl Transaction for InMemoryTransaction {
+        fn as_any_mut(&mut self) -> &mut (dyn std::any::Any + Send) {
+            self
+        }
+
+        fn after_commit(&mut self, callback: Box<dyn FnOnce() + Send>) {
+            self.writes.push(callback);
+        }
+
+        async fn commit(self: Box<Self>) -> DomainResult<()> {
+            for write in self.writes {
+                write();
+            }
+            Ok(())
+        }
+
+        async fn rollback(self: Box<Self>) -> DomainResult<()> {
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl UserRepository for InMemoryUserRepository {
+        async fn find_by_id(&self, id: Uuid) -> DomainResult<Option<User>> {
+            let users = self.users.lock().unwrap();
+            Ok(users.iter().find(|u| u.id == id).cloned())
+        }
+
+        async fn find_by_id_for_update(&self, id: Uuid) -> DomainResult<Option<User>> {
+            self.find_by_id(id).await
+        }
+
+        async fn find_by_ids(&self, ids: &[Uuid]) -> DomainResult<Vec<User>> {
+            let users = self.users.lock().unwrap();
+            Ok(users
+                .iter()
+                .filter(|u| ids.contains(&u.id))
+                .cloned()
+                .collect())
+        }
+
+        async fn find_by_subject(&self, subject: &str) -> DomainResult<Option<User>> {
+            let users = self.users.lock().unwrap();
+            Ok(users.iter().find(|u| u.subject == subject).cloned())
+        }
+
+        async fn find_by_email(&self, email: &str) -> DomainResult<Option<User>> {
+            let users = self.users.lock().unwrap();
+            Ok(users.iter().find(|u| u.email_str() == email).cloned())
+        }
+
+        async fn exists_by_email(&self, email: &str) -> DomainResult<bool> {
+            let users = self.users.lock().unwrap();
+            Ok(users.iter().any(|u| u.email_str() == email))
+        }
+
+        async fn find_by_canonical_email(&self, canonical: &str) -> DomainResult<Option<User>> {
+            let users = self.users.lock().unwrap();
+            Ok(users
+                .iter()
+                .find(|u| u.canonical_email.as_deref() == Some(canonical))
+                .cloned())
+        }
+
+        async fn find_inactive_since(&self, cutoff: DateTime<Utc>) -> DomainResult<Vec<User>> {
+            let users = self.users.lock().unwrap();
+            Ok(users
+                .iter()
+                .filter(|u| !u.is_deleted() && u.last_active_at() < cutoff)
+                .cloned()
+                .collect())
+        }
+
+        async fn search(
+            &self,
+            term: &str,
+            match_mode: EmailMatchMode,
+            limit: u32,
+            offset: u32,
+        ) -> DomainResult<Vec<User>> {
+            let mut users: Vec<User> = self
+                .users
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|u| match_mode.matches(u.email_str(), term))
+                .cloned()
+                .collect();
+            users.sort_by(|a, b| a.email_str().cmp(b.email_str()));
+            Ok(users
+                .into_iter()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .collect())
+        }
+
+        async fn count_matching(
+            &self,
+            term: &str,
+            match_mode: EmailMatchMode,
+        ) -> DomainResult<u64> {
+            let users = self.users.lock().unwrap();
+            Ok(users
+                .iter()
+                .filter(|u| match_mode.matches(u.email_str(), term))
+                .count() as u64)
+        }
+
+        async fn find(
+            &self,
+            filter: &UserFilter,
+            limit: u32,
+            offset: u32,
+        ) -> DomainResult<Vec<User>> {
+            let mut users: Vec<User> = self
+                .users
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|u| filter.matches(u))
+                .cloned()
+                .collect();
+            users.sort_by(|a, b| a.email_str().cmp(b.email_str()));
+            Ok(users
+                .into_iter()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .collect())
+        }
+
+        async fn save(&self, user: &User) -> DomainResult<()> {
+            let mut users = self.users.lock().unwrap();
+            users.retain(|u| u.id != user.id);
+            users.push(user.clone());
+            Ok(())
+        }
+
+        async fn save_with_events(&self, user: &User, events: &[OutboxEvent]) -> DomainResult<()> {
+            self.save(user).await?;
+            for event in events {
+                self.outbox.enqueue(event).await?;
+            }
+            Ok(())
+        }
+
+        async fn begin(&self) -> DomainResult<Box<dyn Transaction>> {
+            Ok(Box::new(InMemoryTransaction::default()))
+        }
+
+        async fn save_in(
+            &self,
+            tx: &mut dyn Transaction,
+            user: &User,
+            events: &[OutboxEvent],
+        ) -> DomainResult<()> {
+            let (users, outbox) = (self.users.clone(), self.outbox.clone());
+            let (user, events) = (user.clone(), events.to_vec());
+            InMemoryTransaction::from_dyn(tx).push(move || {
+                let mut users = users.lock().unwrap();
+                users.retain(|u| u.id != user.id);
+                users.push(user);
+                outbox.events.lock().unwrap().extend(events);
+            });
+            Ok(())
+        }
+
+        async fn update_display_name(
+            &self,
+            id: Uuid,
+            display_name: Option<&DisplayName>,
+        ) -> DomainResult<()> {
+            if let Some(user) = self.users.lock().unwrap().iter_mut().find(|u| u.id == id) {
+                user.display_name = display_name.cloned();
+            }
+            Ok(())
+        }
+
+        async fn delete(&self, id: Uuid) -> DomainResult<()> {
+            self.users.lock().unwrap().retain(|u| u.id != id);
+            Ok(())
+        }
+
+        async fn delete_in(&self, tx: &mut dyn Transaction, id: Uuid) -> DomainResult<()> {
+            let users = self.users.clone();
+            InMemoryTransaction::from_dyn(tx).push(move || {
+                users.lock().unwrap().retain(|u| u.id != id);
+            });
+            Ok(())
+        }
+
+        async fn claim_first_admin(
+            &self,
+            _tx: &mut dyn Transaction,
+            _user_id: Uuid,
+        ) -> DomainResult<bool> {
+            use std::sync::atomic::Ordering;
+
+            let users = self.users.lock().unwrap();
+            Ok(users.is_empty()
+                && self
+                    .admin_claimed
+                    .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok())
+        }
+
+        async fn claim_totp_step(&self, user_id: Uuid, step: u64) -> DomainResult<bool> {
+            let mut steps = self.totp_steps.lock().unwrap();
+            if steps.get(&user_id).is_some_and(|last| *last >= step) {
+                return Ok(false);
+            }
+            steps.insert(user_id, step);
+            Ok(true)
+        }
+    }
+
+    #[derive(Default)]
+    struct InMemoryApiKeyRepository {
+        keys: Arc<Mutex<Vec<ApiKey>>>,
+    }
+
+    #[async_trait]
+    impl ApiKeyRepository for InMemoryApiKeyRepository {
+        async fn find_by_id(&self, id: Uuid) -> DomainResult<Option<ApiKey>> {
+            let keys = self.keys.lock().unwrap();
+            Ok(keys.iter().find(|k| k.id == id).cloned())
+        }
+
+        async fn find_by_hash(&self, key_hash: &str) -> DomainResult<Option<ApiKey>> {
+            let keys = self.keys.lock().unwrap();
+            Ok(keys.iter().find(|k| k.key_hash == key_hash).cloned())
+        }
+
+        async fn find_by_user(&self, user_id: Uuid) -> DomainResult<Vec<ApiKey>> {
+            let keys = self.keys.lock().unwrap();
+            Ok(keys
+                .iter()
+                .filter(|k| k.user_id == user_id)
+                .cloned()
+                .collect())
+        }
+
+        async fn save(&self, key: &ApiKey) -> DomainResult<()> {
+            let mut keys = self.keys.lock().unwrap();
+            keys.retain(|k| k.id != key.id);
+            keys.push(key.clone());
+            Ok(())
+        }
+
+        async fn insert_within_limit(
+            &self,
+            key: &ApiKey,
+            max: u32,
+            now: DateTime<Utc>,
+        ) -> DomainResult<bool> {
+            let mut keys = self.keys.lock().unwrap();
+            let active = keys
+                .iter()
+                .filter(|k| k.user_id == key.user_id && !k.is_expired(now))
+                .count();
+            if active >= max as usize {
+                return Ok(false);
+            }
+            keys.push(key.clone());
+            Ok(true)
+        }
+
+        async fn delete(&self, id: Uuid) -> DomainResult<()> {
+            self.keys.lock().unwrap().retain(|k| k.id != id);
+            Ok(())
+        }
+
+        async fn reassign_in(
+            &self,
+            tx: &mut dyn Transaction,
+            from: Uuid,
+            to: Uuid,
+            max: Option<u32>,
+            now: DateTime<Utc>,
+        ) -> DomainResult<bool> {
+            if let Some(max) = max {
+                let active = self
+                    .keys
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter(|k| (k.user_id == from || k.user_id == to) && !k.is_expired(now))
+                    .count();
+                if active > max as usize {
+                    return Ok(false);
+                }
+            }
+            let keys = self.keys.clone();
+            InMemoryTransaction::from_dyn(tx).push(move || {
+                for key in keys.lock().unwrap().iter_mut() {
+                    if key.user_id == from {
+                        key.user_id = to;
+                    }
+                }
+            });
+            Ok(true)
+        }
+    }
+
+    #[derive(Default)]
+    struct InMemoryAuditLogRepository {
+        entries: Arc<Mutex<Vec<AuditEntry>>>,
+    }
+
+    #[async_trait]
+    impl AuditLogRepository for InMemoryAuditLogRepository {
+        async fn record(&self, entry: &AuditEntry) -> DomainResult<()> {
+            self.entries.lock().unwrap().push(entry.clone());
+            Ok(())
+        }
+
+        async fn record_in(
+            &self,
+            tx: &mut dyn Transaction,
+            entry: &AuditEntry,
+        ) -> DomainResult<()> {
+            let (entries, entry) = (self.entries.clone(), entry.clone());
+            InMemoryTransaction::from_dyn(tx).push(move || entries.lock().unwrap().push(entry));
+            Ok(())
+        }
+
+        async fn find_by_actor(&self, actor_id: Uuid) -> DomainResult<Vec<AuditEntry>> {
+            let entries = self.entries.lock().unwrap();
+            Ok(entries
+                .iter()
+                .rev()
+                .filter(|e| e.actor_id == actor_id)
+                .cloned()
+                .collect())
+        }
+
+        async fn search(
+            &self,
+            filter: &AuditLogFilter,
+            limit: u32,
+            offset: u32,
+        ) -> DomainResult<Vec<AuditEntry>> {
+            let mut entries: Vec<AuditEntry> = self
+                .entries
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|e| filter.matches(e))
+                .cloned()
+                .collect();
+            entries.sort_by_key(|e| std::cmp::Reverse(e.created_at));
+            Ok(entries
+                .into_iter()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .collect())
+        }
+
+        async fn count(&self, filter: &AuditLogFilter) -> DomainResult<u64> {
+            let entries = self.entries.lock().unwrap();
+            Ok(entries.iter().filter(|e| filter.matches(e)).count() as u64)
+        }
+    }
+
+    #[derive(Default)]
+    struct InMemoryPasswordResetTokenRepository {
+        tokens: Arc<Mutex<Vec<PasswordResetToken>>>,
+    }
+
+    #[async_trait]
+    impl PasswordResetTokenRepository for InMemoryPasswordResetTokenRepository {
+        async fn save(&self, token: &PasswordResetToken) -> DomainResult<()> {
+            self.tokens.lock().unwrap().push(token.clone());
+            Ok(())
+        }
+
+        async fn take(&self, token_hash: &str) -> DomainResult<Option<PasswordResetToken>> {
+            let mut tokens = self.tokens.lock().unwrap();
+            let index = tokens.iter().position(|t| t.token_hash == token_hash);
+            Ok(index.map(|index| tokens.remove(index)))
+        }
+
+        async fn delete_for_user(&self, user_id: Uuid) -> DomainResult<()> {
+            self.tokens.lock().unwrap().retain(|t| t.user_id != user_id);
+            Ok(())
+        }
+
+        async fn delete_for_user_in(
+            &self,
+            tx: &mut dyn Transaction,
+            user_id: Uuid,
+        ) -> DomainResult<()> {
+            let tokens = self.tokens.clone();
+            InMemoryTransaction::from_dyn(tx).push(move || {
+                tokens.lock().unwrap().retain(|t| t.user_id != user_id);
+            });
+            Ok(())
+        }
+
+        async fn delete_expired(&self, now: DateTime<Utc>) -> DomainResult<u64> {
+            let mut tokens = self.tokens.lock().unwrap();
+            let before = tokens.len();
+            tokens.retain(|t| t.expires_at >= now);
+            Ok((before - tokens.len()) as u64)
+        }
+    }
+
+    #[derive(Default)]
+    struct InMemoryEmailVerificationTokenRepository {
+        tokens: Arc<Mutex<Vec<EmailVerificationToken>>>,
+    }
+
+    #[async_trait]
+    impl EmailVerificationTokenRepository for InMemoryEmailVerificationTokenRepository {
+        async fn save(&self, token: &EmailVerificationToken) -> DomainResult<()> {
+            self.tokens.lock().unwrap().push(token.clone());
+            Ok(())
+        }
+
+        async fn take(&self, token_hash: &str) -> DomainResult<Option<EmailVerificationToken>> {
+            let mut tokens = self.tokens.lock().unwrap();
+            let index = tokens.iter().position(|t| t.token_hash == token_hash);
+            Ok(index.map(|index| tokens.remove(index)))
+        }
+
+        async fn latest_for_user(
+            &self,
+            user_id: Uuid,
+        ) -> DomainResult<Option<EmailVerificationToken>> {
+            let tokens = self.tokens.lock().unwrap();
+            Ok(tokens
+                .iter()
+                .filter(|t| t.user_id == user_id)
+                .max_by_key(|t| t.created_at)
+                .cloned())
+        }
+
+        async fn delete_for_user(&self, user_id: Uuid) -> DomainResult<()> {
+            self.tokens.lock().unwrap().retain(|t| t.user_id != user_id);
+            Ok(())
+        }
+
+        async fn delete_for_user_in(
+            &self,
+            tx: &mut dyn Transaction,
+            user_id: Uuid,
+        ) -> DomainResult<()> {
+            let tokens = self.tokens.clone();
+            InMemoryTransaction::from_dyn(tx).push(move || {
+                tokens.lock().unwrap().retain(|t| t.user_id != user_id);
+            });
+            Ok(())
+        }
+
+        async fn delete_expired(&self, now: DateTime<Utc>) -> DomainResult<u64> {
+            let mut tokens = self.tokens.lock().unwrap();
+            let before = tokens.len();
+            tokens.retain(|t| t.expires_at >= now);
+            Ok((before - tokens.len()) as u64)
+        }
+    }
+
+    /// Sessions never expire here; `list` is newest first like the adapters
+    #[derive(Default)]
+    struct InMemorySessionRepository {
+        sessions: Arc<Mutex<Vec<SessionInfo>>>,
+    }
+
+    #[async_trait]
+    impl SessionRepository for InMemorySessionRepository {
+        async fn record(&self, session: &SessionInfo) -> DomainResult<()> {
+            self.sessions.lock().unwrap().push(session.clone());
+            Ok(())
+        }
+
+        async fn list(
+            &self,
+            filter: &SessionFilter,
+            _now: DateTime<Utc>,
+            limit: u32,
+            offset: u32,
+        ) -> DomainResult<Vec<SessionInfo>> {
+            let mut sessions: Vec<SessionInfo> = self
+                .sessions
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|s| filter.user_id.is_none_or(|id| s.user_id == id))
+                .cloned()
+                .collect();
+            sessions.sort_by_key(|s| std::cmp::Reverse(s.created_at));
+            Ok(sessions
+                .into_iter()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .collect())
+        }
+
+        async fn count(&self, filter: &SessionFilter, now: DateTime<Utc>) -> DomainResult<u64> {
+            Ok(self.list(filter, now, u32::MAX, 0).await?.len() as u64)
+        }
+
+        async fn revoke(&self, session_id: &str) -> DomainResult<()> {
+            self.sessions
+                .lock()
+                .unwrap()
+                .retain(|s| s.session_id != session_id);
+            Ok(())
+        }
+
+        async fn revoke_for_user_in(
+            &self,
+            tx: &mut dyn Transaction,
+            user_id: Uuid,
+        ) -> DomainResult<()> {
+            let sessions = self.sessions.clone();
+            InMemoryTransaction::from_dyn(tx).push(move || {
+                sessions.lock().unwrap().retain(|s| s.user_id != user_id);
+            });
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    struct InMemoryOutboxRepository {
+        events: Mutex<Vec<OutboxEvent>>,
+    }
+
+    #[async_trait]
+    impl OutboxRepository for InMemoryOutboxRepository {
+        async fn enqueue(&self, event: &OutboxEvent) -> DomainResult<()> {
+            self.events.lock().unwrap().push(event.clone());
+            Ok(())
+        }
+
+        async fn fetch_pending(
+            &self,
+            now: DateTime<Utc>,
+            limit: u32,
+        ) -> DomainResult<Vec<OutboxEvent>> {
+            let events = self.events.lock().unwrap();
+            Ok(events
+                .iter()
+                .filter(|e| e.delivered_at.is_none() && e.next_attempt_at <= now)
+                .take(limit as usize)
+                .cloned()
+                .collect())
+        }
+
+        async fn mark_delivered(&self, id: Uuid, delivered_at: DateTime<Utc>) -> DomainResult<()> {
+            let mut events = self.events.lock().unwrap();
+            if let Some(event) = events.iter_mut().find(|e| e.id == id) {
+                event.delivered_at = Some(delivered_at);
+            }
+            Ok(())
+        }
+
+        async fn mark_failed(
+            &self,
+            id: Uuid,
+            error: &str,
+            next_attempt_at: DateTime<Utc>,
+        ) -> DomainResult<()> {
+            let mut events = self.events.lock().unwrap();
+            if let Some(event) = events.iter_mut().find(|e| e.id == id) {
+                event.attempts += 1;
+                event.last_error = Some(error.to_string());
+                event.next_attempt_at = next_attempt_at;
+            }
+            Ok(())
+        }
+    }
+
+    async fn setup() -> (UserService, User) {
+        let service = UserService::new(
+            Arc::new(InMemoryUserRepository::default()),
+            Arc::new(InMemoryApiKeyRepository::default()),
+            Arc::new(InMemoryAuditLogRepository::default()),
+        );
+        let user = service
+            .find_or_create("test|1", "user@example.com")
+            .await
+            .unwrap();
+        (service, user)
+    }
+
+    #[tokio::test]
+    async fn test_overlong_subject_is_rejected() {
+        let (service, user) = setup().await;
+        let subject = "s".repeat(crate::entities::MAX_SUBJECT_LENGTH + 1);
+
+        let result = service.find_or_create(&subject, "new@example.com").await;
+        assert!(matches!(result, Err(DomainError::ValidationError(_))));
+
+        // Linking an existing account checks the subject too
+        let result = service.find_or_create(&subject, user.email_str()).await;
+        assert!(matches!(result, Err(DomainError::ValidationError(_))));
+        let stored = service.find_by_id(user.id).await.unwrap();
+        assert_eq!(stored.subject, "test|1");
+    }
+
+    #[tokio::test]
+    async fn test_subjects_differing_in_case_are_distinct_users() {
+        let (service, _) = setup().await;
+
+        let upper = service
+            .find_or_create("oidc|Alice", "upper@example.com")
+            .await
+            .unwrap();
+        let lower = service
+            .find_or_create("oidc|alice", "lower@example.com")
+            .await
+            .unwrap();
+        assert_ne!(upper.id, lower.id);
+        assert_eq!(upper.subject, "oidc|Alice");
+        assert_eq!(lower.subject, "oidc|alice");
+
+        let again = service
+            .find_or_create("oidc|Alice", "upper@example.com")
+            .await
+            .unwrap();
+        assert_eq!(again.id, upper.id);
+    }
+
+    #[test]
+    fn test_subject_length_is_capped() {
+        use crate::entities::MAX_SUBJECT_LENGTH;
+        use crate::value_objects::ValidationError;
+
+        let email = Email::try_from("user@example.com").unwrap();
+        assert!(User::new("s".repeat(MAX_SUBJECT_LENGTH), email.clone()).is_ok());
+        assert_eq!(
+            User::new("s".repeat(MAX_SUBJECT_LENGTH + 1), email).unwrap_err(),
+            ValidationError::SubjectTooLong {
+                max: MAX_SUBJECT_LENGTH,
+                actual: MAX_SUBJECT_LENGTH + 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_reconstituting_an_empty_subject_fails() {
+        let email = Email::try_from("user@example.com").unwrap();
+        let reconstitute = |subject: &str| {
+            User::reconstitute(
+                Uuid::new_v4(),
+                subject,
+                email.clone(),
+                None,
+                false,
+                Role::User,
+                Utc::now(),
+            )
+        };
+
+        assert!(reconstitute("oidc|1").is_ok());
+        assert!(matches!(
+            reconstitute(""),
+            Err(DomainError::RepositoryError(_))
+        ));
+        assert_eq!(
+            User::new("", email.clone()).unwrap_err(),
+            crate::value_objects::ValidationError::EmptySubject
+        );
+    }
+
+    mod get_many_tests {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_get_many_skips_missing() {
+            let (service, user) = setup().await;
+
+            let users = service.get_many(&[user.id, Uuid::new_v4()]).await.unwrap();
+            assert_eq!(users.len(), 1);
+            assert_eq!(users[0].id, user.id);
+
+            assert!(service.get_many(&[]).await.unwrap().is_empty());
+        }
+    }
+
+    mod retention_tests {
+        use super::welcome_email_tests::CapturingSender;
+        use super::*;
+        use crate::value_objects::Role;
+
+        async fn seed(service: &UserService, subject: &str, role: Role, days_ago: i64) -> User {
+            let mut user = service
+                .find_or_create(subject, &format!("{}@example.com", subject))
+                .await
+                .unwrap();
+            user.role = role;
+            user.email_verified = true;
+            user.last_login_at = Some(Utc::now() - Duration::days(days_ago));
+            service.user_repository.save(&user).await.unwrap();
+            user
+        }
+
+        #[tokio::test]
+        async fn test_only_stale_non_admins_are_processed() {
+            let (service, _) = setup().await;
+            let stale = seed(&service, "stale", Role::User, 400).await;
+            let fresh = seed(&service, "fresh", Role::User, 10).await;
+            let admin = seed(&service, "admin", Role::Admin, 400).await;
+            let bot = seed(&service, "bot", Role::User, 400).await;
+            let mut unverified_bot = seed(&service, "unverified-bot", Role::User, 400).await;
+            unverified_bot.email_verified = false;
+            service.user_repository.save(&unverified_bot).await.unwrap();
+
+            let policy = RetentionPolicy::new(365, RetentionMode::SoftDelete)
+                .with_service_accounts([bot.email.clone(), unverified_bot.email.clone()]);
+            let mut processed = service.apply_retention(&policy).await.unwrap();
+            processed.sort();
+            let mut expected = vec![stale.id, unverified_bot.id];
+            expected.sort();
+            assert_eq!(processed, expected);
+
+            assert!(service.find_by_id(stale.id).await.unwrap().is_deleted());
+            for user in [fresh, admin, bot] {
+                assert!(!service.find_by_id(user.id).await.unwrap().is_deleted());
+            }
+
+            let entries = service
+                .audit_log_repository
+                .find_by_actor(SYSTEM_ACTOR_ID)
+                .await
+                .unwrap();
+            assert_eq!(entries.len(), 2);
+            assert!(
+                entries
+                    .iter()
+                    .all(|entry| entry.action == AuditAction::UserSoftDeleted)
+            );
+            assert!(
+                entries
+                    .iter()
+                    .any(|entry| entry.target_id == Some(stale.id))
+            );
+        }
+
+        #[tokio::test]
+        async fn test_erase_mode_removes_user_with_sessions_and_tokens() {
+            let (service, user) = setup().await;
+            let sessions = Arc::new(InMemorySessionRepository::default());
+            let resets = Arc::new(InMemoryPasswordResetTokenRepository::default());
+            let service = service
+                .with_session_repository(sessions.clone())
+                .with_password_reset(
+                    resets.clone(),
+                    Arc::new(CapturingSender::default()),
+                    Duration::hours(1),
+                    "r=",
+                );
+            let stale = seed(&service, "stale", Role::User, 400).await;
+            for user_id in [user.id, stale.id] {
+                let session = SessionInfo {
+                    session_id: user_id.to_string(),
+                    user_id,
+                    ip: None,
+                    user_agent: None,
+                    created_at: Utc::now(),
+                    expires_at: Utc::now() + Duration::days(1),
+                };
+                service.record_session(&session).await.unwrap();
+                let (token, _) = PasswordResetToken::generate(user_id, Duration::hours(1));
+                resets.save(&token).await.unwrap();
+            }
+
+            let policy = RetentionPolicy::new(365, RetentionMode::Erase);
+            service.apply_retention(&policy).await.unwrap();
+
+            let sessions: Vec<_> = sessions.sessions.lock().unwrap().clone();
+            assert_eq!(
+                sessions.iter().map(|s| s.user_id).collect::<Vec<_>>(),
+                [user.id]
+            );
+            let resets: Vec<_> = resets.tokens.lock().unwrap().clone();
+            assert_eq!(
+                resets.iter().map(|t| t.user_id).collect::<Vec<_>>(),
+                [user.id]
+            );
+
+            assert!(
+                service
+                    .find_by_id(stale.id)
+                    .await
+                    .unwrap_err()
+                    .is_not_found()
+            );
+            // Already processed users are not picked up again
+            assert!(service.apply_retention(&policy).await.unwrap().is_empty());
+        }
+
+        #[tokio::test]
+        async fn test_record_login_refreshes_activity() {
+            let (service, user) = setup().await;
+            assert!(user.last_login_at.is_none());
+
+            let user = service.record_login(user.id).await.unwrap();
+            assert!(user.last_login_at.is_some());
+        }
+    }
+
+    mod impersonation_tests {
+        use super::*;
+        use crate::value_objects::Role;
+
+        async fn setup_admin() -> (UserService, User, User) {
+            let (service, target) = setup().await;
+            let mut admin = service
+                .find_or_create("test|admin", "admin@example.com")
+                .await
+                .unwrap();
+            admin.role = Role::Admin;
+            service.user_repository.save(&admin).await.unwrap();
+            (service, admin, target)
+        }
+
+        #[tokio::test]
+        async fn test_impersonation_switches_effective_user() {
+            let (service, admin, target) = setup_admin().await;
+
+            let effective = service
+                .start_impersonation(admin.id, target.id)
+                .await
+                .unwrap();
+            assert_eq!(effective.id, target.id);
+
+            let restored = service
+                .stop_impersonation(admin.id, target.id)
+                .await
+                .unwrap();
+            assert_eq!(restored.id, admin.id);
+        }
+
+        #[tokio::test]
+        async fn test_impersonation_is_audited() {
+            let (service, admin, target) = setup_admin().await;
+
+            service
+                .start_impersonation(admin.id, target.id)
+                .await
+                .unwrap();
+
+            let entries = service
+                .audit_log_repository
+                .find_by_actor(admin.id)
+                .await
+                .unwrap();
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].actor_id, admin.id);
+            assert_eq!(entries[0].target_id, Some(target.id));
+            assert_eq!(entries[0].action, AuditAction::ImpersonationStarted);
+        }
+
+        #[tokio::test]
+        async fn test_non_admin_cannot_impersonate() {
+            let (service, user) = setup().await;
+            let other = service
+                .find_or_create("test|2", "other@example.com")
+                .await
+                .unwrap();
+
+            let result = service.start_impersonation(user.id, other.id).await;
+            assert!(matches!(result, Err(DomainError::Unauthorized(_))));
+        }
+    }
+
+    mod merge_tests {
+        use super::welcome_email_tests::CapturingSender;
+        use super::*;
+        use crate::value_objects::Role;
+
+        /// A password account, free to take the duplicate's provider subject
+        async fn local_user(service: &UserService, email: &str) -> User {
+            let user = User::new_local(Email::try_from(email).unwrap(), "hash");
+            service.user_repository.save(&user).await.unwrap();
+            user
+        }
+
+        #[tokio::test]
+        async fn test_merge_soft_deletes_duplicate_and_keeps_primary() {
+            let (service, _) = setup().await;
+            let primary = local_user(&service, "primary@example.com").await;
+            let duplicate = service
+                .find_or_create("other|1", "dup@example.com")
+                .await
+                .unwrap();
+            service.create_api_key(duplicate.id, None).await.unwrap();
+
+            let merged = service
+                .merge_accounts(primary.id, duplicate.id, false)
+                .await
+                .unwrap();
+            assert_eq!(merged.id, primary.id);
+
+            assert!(service.find_by_id(duplicate.id).await.unwrap().is_deleted());
+            let found = service.find_by_email(primary.email_str()).await.unwrap();
+            assert_eq!(found.map(|u| u.id), Some(primary.id));
+            // Its address stays with the deleted duplicate, its provider
+            // identity now signs in to the primary
+            let found = service.find_by_email("dup@example.com").await.unwrap();
+            assert_eq!(
+                found.map(|u| (u.id, u.is_deleted())),
+                Some((duplicate.id, true))
+            );
+            let found = service
+                .user_repository
+                .find_by_subject("other|1")
+                .await
+                .unwrap();
+            assert_eq!(found.map(|u| u.id), Some(primary.id));
+            let user = service.find_or_create("other|1", "dup@example.com").await;
+            assert_eq!(user.unwrap().id, primary.id);
+
+            assert_eq!(service.list_api_keys(primary.id).await.unwrap().len(), 1);
+            let entries = service
+                .audit_log_repository
+                .find_by_actor(primary.id)
+                .await
+                .unwrap();
+            assert_eq!(entries[0].action, AuditAction::AccountsMerged);
+            assert_eq!(entries[0].target_id, Some(duplicate.id));
+        }
+
+        #[tokio::test]
+        async fn test_merging_two_provider_accounts_is_rejected() {
+            let (service, primary) = setup().await;
+            let duplicate = service
+                .find_or_create("other|1", "dup@example.com")
+                .await
+                .unwrap();
+
+            let result = service
+                .merge_accounts(primary.id, duplicate.id, false)
+                .await;
+            assert!(matches!(result, Err(DomainError::ValidationError(_))));
+            assert!(!service.find_by_id(duplicate.id).await.unwrap().is_deleted());
+        }
+
+        #[tokio::test]
+        async fn test_merge_over_the_key_limit_changes_nothing() {
+            let (service, _) = setup().await;
+            let service = service.with_max_api_keys(2);
+            let primary = local_user(&service, "primary@example.com").await;
+            let duplicate = service
+                .find_or_create("other|1", "dup@example.com")
+                .await
+                .unwrap();
+            for user_id in [primary.id, primary.id, duplicate.id] {
+                service.create_api_key(user_id, None).await.unwrap();
+            }
+
+            let result = service
+                .merge_accounts(primary.id, duplicate.id, false)
+                .await;
+            assert!(matches!(result, Err(DomainError::ApiKeyLimitReached(2))));
+            assert_eq!(service.list_api_keys(duplicate.id).await.unwrap().len(), 1);
+            let duplicate = service.find_by_id(duplicate.id).await.unwrap();
+            assert!(!duplicate.is_deleted());
+            assert_eq!(duplicate.subject, "other|1");
+        }
+
+        #[tokio::test]
+        async fn test_merging_admins_requires_override() {
+            let (service, _) = setup().await;
+            let primary = local_user(&service, "primary@example.com").await;
+            let mut admin = service
+                .find_or_create("test|admin", "admin@example.com")
+                .await
+                .unwrap();
+            admin.role = Role::Admin;
+            service.user_repository.save(&admin).await.unwrap();
+
+            let result = service.merge_accounts(primary.id, admin.id, false).await;
+            assert!(matches!(result, Err(DomainError::Unauthorized(_))));
+            assert!(service.find_by_id(admin.id).await.is_ok());
+
+            service
+                .merge_accounts(primary.id, admin.id, true)
+                .await
+                .unwrap();
+            assert!(service.find_by_id(admin.id).await.unwrap().is_deleted());
+        }
+
+        #[tokio::test]
+        async fn test_merge_into_self_is_rejected() {
+            let (service, user) = setup().await;
+            let result = service.merge_accounts(user.id, user.id, true).await;
+            assert!(matches!(result, Err(DomainError::ValidationError(_))));
+        }
+
+        #[tokio::test]
+        async fn test_merging_a_deleted_duplicate_is_rejected() {
+            let (service, _) = setup().await;
+            let primary = local_user(&service, "primary@example.com").await;
+            let duplicate = service
+                .find_or_create("other|1", "dup@example.com")
+                .await
+                .unwrap();
+            service
+                .merge_accounts(primary.id, duplicate.id, false)
+                .await
+                .unwrap();
+
+            let result = service
+                .merge_accounts(primary.id, duplicate.id, false)
+                .await;
+            assert!(matches!(result, Err(DomainError::ValidationError(_))));
+        }
+
+        #[tokio::test]
+        async fn test_merge_ends_duplicate_sessions_and_drops_its_tokens() {
+            let (service, _) = setup().await;
+            let primary = local_user(&service, "primary@example.com").await;
+            let sessions = Arc::new(InMemorySessionRepository::default());
+            let resets = Arc::new(InMemoryPasswordResetTokenRepository::default());
+            let verifications = Arc::new(InMemoryEmailVerificationTokenRepository::default());
+            let sender = Arc::new(CapturingSender::default());
+            let service = service
+                .with_session_repository(sessions.clone())
+                .with_password_reset(resets.clone(), sender.clone(), Duration::hours(1), "r=")
+                .with_email_verification(
+                    verifications.clone(),
+                    sender,
+                    Duration::hours(24),
+                    Duration::zero(),
+                    "v=",
+                );
+            let duplicate = service
+                .find_or_create("other|1", "dup@example.com")
+                .await
+                .unwrap();
+
+            for user_id in [primary.id, duplicate.id] {
+                let session = SessionInfo {
+                    session_id: user_id.to_string(),
+                    user_id,
+                    ip: None,
+                    user_agent: None,
+                    created_at: Utc::now(),
+                    expires_at: Utc::now() + Duration::days(1),
+                };
+                service.record_session(&session).await.unwrap();
+                let (token, _) = PasswordResetToken::generate(user_id, Duration::hours(1));
+                resets.save(&token).await.unwrap();
+                let (token, _) = EmailVerificationToken::generate(user_id, Duration::hours(1));
+                verifications.save(&token).await.unwrap();
+            }
+
+            service
+                .merge_accounts(primary.id, duplicate.id, false)
+                .await
+                .unwrap();
+
+            let sessions: Vec<_> = sessions.sessions.lock().unwrap().clone();
+            assert_eq!(
+                sessions.iter().map(|s| s.user_id).collect::<Vec<_>>(),
+                [primary.id]
+            );
+            let resets: Vec<_> = resets.tokens.lock().unwrap().clone();
+            assert_eq!(
+                resets.iter().map(|t| t.user_id).collect::<Vec<_>>(),
+                [primary.id]
+            );
+            let verifications: Vec<_> = verifications.tokens.lock().unwrap().clone();
+            assert_eq!(
+                verifications.iter().map(|t| t.user_id).collect::<Vec<_>>(),
+                [primary.id]
+            );
+        }
+    }
+
+    mod captcha_tests {
+        use super::*;
+
+        /// Accepts only the token "valid"; an unavailable verifier simulates a provider outage
+        struct MockVerifier {
+            available: bool,
+        }
+
+        #[async_trait]
+        impl CaptchaVerifier for MockVerifier {
+            async fn verify(&self, token: &str) -> DomainResult<bool> {
+                if !self.available {
+                    return Err(DomainError::InfrastructureError("timeout".into()));
+                }
+                Ok(token == "valid")
+            }
+        }
+
+        fn guard(available: bool, strict: bool) -> CaptchaGuard {
+            CaptchaGuard::new(Arc::new(MockVerifier { available }), strict)
+        }
+
+        #[tokio::test]
+        async fn test_valid_token_is_accepted() {
+            assert!(guard(true, true).check(Some("valid")).await.is_ok());
+        }
+
+        #[tokio::test]
+        async fn test_invalid_token_is_rejected() {
+            let result = guard(true, false).check(Some("bogus")).await;
+            assert!(matches!(result, Err(DomainError::ValidationError(_))));
+        }
+
+        #[tokio::test]
+        async fn test_missing_token_is_rejected() {
+            let result = guard(true, false).check(None).await;
+            assert!(matches!(result, Err(DomainError::ValidationError(_))));
+        }
+
+        #[tokio::test]
+        async fn test_provider_error_fails_closed_when_strict() {
+            assert!(guard(false, true).check(Some("valid")).await.is_err());
+        }
+
+        #[tokio::test]
+        async fn test_provider_error_fails_open_when_lenient() {
+            assert!(guard(false, false).check(Some("valid")).await.is_ok());
+        }
+    }
+
+    mod preferences_tests {
+        use super::*;
+        use crate::entities::MAX_PREFERENCES_BYTES;
+
+        #[tokio::test]
+        async fn test_preferences_start_empty_and_are_replaced() {
+            let (service, user) = setup().await;
+            assert_eq!(user.preferences, serde_json::json!({}));
+
+            let preferences = serde_json::json!({"theme": "dark", "pinned": [1, 2]});
+            service
+                .update_preferences(user.id, preferences.clone())
+                .await
+                .unwrap();
+
+            let stored = service.find_by_id(user.id).await.unwrap();
+            assert_eq!(stored.preferences, preferences);
+        }
+
+        #[tokio::test]
+        async fn test_oversized_or_non_object_preferences_are_rejected() {
+            let (service, user) = setup().await;
+
+            let oversized = serde_json::json!({"notes": "x".repeat(MAX_PREFERENCES_BYTES)});
+            let result = service.update_preferences(user.id, oversized).await;
+            assert!(matches!(result, Err(DomainError::ValidationError(_))));
+
+            let result = service
+                .update_preferences(user.id, serde_json::json!(["dark"]))
+                .await;
+            assert!(matches!(result, Err(DomainError::ValidationError(_))));
+
+            let stored = service.find_by_id(user.id).await.unwrap();
+            assert_eq!(stored.preferences, serde_json::json!({}));
+        }
+    }
+
+    mod profile_tests {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_profile_fields_change_only_when_mentioned() {
+            let (service, user) = setup().await;
+            assert_eq!(user.display_name, None);
+            let ada = DisplayName::new("Ada").unwrap();
+
+            let set = UpdateProfileCommand {
+                display_name: Some(Some(ada.clone())),
+            };
+            service.update_profile(user.id, set).await.unwrap();
+            let stored = service.find_by_id(user.id).await.unwrap();
+            assert_eq!(stored.display_name, Some(ada.clone()));
+
+            let untouched = UpdateProfileCommand::default();
+            service.update_profile(user.id, untouched).await.unwrap();
+            let stored = service.find_by_id(user.id).await.unwrap();
+            assert_eq!(stored.display_name, Some(ada));
+
+            let cleared = UpdateProfileCommand {
+                display_name: Some(None),
+            };
+            service.update_profile(user.id, cleared).await.unwrap();
+            let stored = service.find_by_id(user.id).await.unwrap();
+            assert_eq!(stored.display_name, None);
+        }
+    }
+
+    mod api_key_tests {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_create_authenticate_revoke() {
+            let (service, user) = setup().await;
+
+            let (key_id, raw_key) = service.create_api_key(user.id, None).await.unwrap();
+
+            let authenticated = service.authenticate_api_key(&raw_key).await.unwrap();
+            assert_eq!(authenticated.unwrap().id, user.id);
+
+            let keys = service.list_api_keys(user.id).await.unwrap();
+            assert_eq!(keys.len(), 1);
+            assert!(keys[0].last_used_at.is_some());
+
+            service.revoke_api_key(user.id, key_id).await.unwrap();
+            assert!(
+                service
+                    .authenticate_api_key(&raw_key)
+                    .await
+                    .unwrap()
+                    .is_none()
+            );
+        }
+
+        #[tokio::test]
+        async fn test_key_limit_counts_active_keys() {
+            let (service, user) = setup().await;
+            let service = service.with_max_api_keys(2);
+
+            let expired = Utc::now() - Duration::minutes(1);
+            service
+                .create_api_key(user.id, Some(expired))
+                .await
+                .unwrap();
+            let (first, _) = service.create_api_key(user.id, None).await.unwrap();
+            service.create_api_key(user.id, None).await.unwrap();
+
+            let result = service.create_api_key(user.id, None).await;
+            assert!(matches!(result, Err(DomainError::ApiKeyLimitReached(2))));
+
+            service.revoke_api_key(user.id, first).await.unwrap();
+            assert!(service.create_api_key(user.id, None).await.is_ok());
+        }
+
+        #[tokio::test]
+        async fn test_raw_key_is_not_stored() {
+            let (service, user) = setup().await;
+
+            let (_, raw_key) = service.create_api_key(user.id, None).await.unwrap();
+
+            let keys = service.list_api_keys(user.id).await.unwrap();
+            let stored = format!("{:?}", keys);
+            assert!(!stored.contains(&raw_key));
+            assert_eq!(keys[0].key_hash, ApiKey::hash_key(&raw_key));
+        }
+
+        #[tokio::test]
+        async fn test_expired_key_is_rejected() {
+            let (service, user) = setup().await;
+
+            let expired = Utc::now() - Duration::minutes(1);
+            let (_, raw_key) = service
+                .create_api_key(user.id, Some(expired))
+                .await
+                .unwrap();
+
+            assert!(
+                service
+                    .authenticate_api_key(&raw_key)
+                    .await
+                    .unwrap()
+                    .is_none()
+            );
+        }
+
+        #[tokio::test]
+        async fn test_deleted_users_key_is_rejected() {
+            let (service, mut user) = setup().await;
+            let (_, raw_key) = service.create_api_key(user.id, None).await.unwrap();
+
+            user.deleted_at = Some(Utc::now());
+            service.user_repository.save(&user).await.unwrap();
+
+            assert!(
+                service
+                    .authenticate_api_key(&raw_key)
+                    .await
+                    .unwrap()
+                    .is_none()
+            );
+            let keys = service.list_api_keys(user.id).await.unwrap();
+            assert!(keys[0].last_used_at.is_none());
+        }
+
+        #[tokio::test]
+        async fn test_revoke_other_users_key_fails() {
+            let (service, user) = setup().await;
+            let other = service
+                .find_or_create("test|2", "other@example.com")
+                .await
+                .unwrap();
+
+            let (key_id, _) = service.create_api_key(user.id, None).await.unwrap();
+
+            let result = service.revoke_api_key(other.id, key_id).await;
+            assert!(matches!(result, Err(DomainError::ApiKeyNotFound(_))));
+        }
+    }
+
+    mod welcome_email_tests {
+        use super::*;
+
+        #[derive(Default)]
+        pub(super) struct CapturingSender {
+            pub(super) sent: Mutex<Vec<EmailMessage>>,
+        }
+
+        #[async_trait]
+        impl EmailSender for CapturingSender {
+            async fn send(&self, message: &EmailMessage) -> DomainResult<()> {
+                self.sent.lock().unwrap().push(message.clone());
+                Ok(())
+            }
+        }
+
+        struct NoopPublisher;
+
+        #[async_trait]
+        impl EventPublisher for NoopPublisher {
+            async fn publish(&self, _event: &OutboxEvent) -> DomainResult<()> {
+                Ok(())
+            }
+        }
+
+        fn setup_welcome(
+            template: WelcomeEmailTemplate,
+        ) -> (UserService, Arc<CapturingSender>, OutboxDispatcher) {
+            let outbox = Arc::new(InMemoryOutboxRepository::default());
+            let users = InMemoryUserRepository {
+                outbox: outbox.clone(),
+                ..Default::default()
+            };
+            let service = UserService::new(
+                Arc::new(users),
+                Arc::new(InMemoryApiKeyRepository::default()),
+                Arc::new(InMemoryAuditLogRepository::default()),
+            );
+            let sender = Arc::new(CapturingSender::default());
+            let publisher =
+                WelcomeEmailPublisher::new(Arc::new(NoopPublisher), sender.clone(), template);
+            let dispatcher = OutboxDispatcher::new(outbox, Arc::new(publisher), 10);
+            (service, sender, dispatcher)
+        }
+
+        #[tokio::test]
+        async fn test_new_user_gets_welcome_email() {
+            let (service, sender, dispatcher) = setup_welcome(WelcomeEmailTemplate::default());
+            service
+                .find_or_create("oidc|welcome", "welcome@example.com")
+                .await
+                .unwrap();
+
+            assert!(sender.sent.lock().unwrap().is_empty());
+            assert_eq!(dispatcher.dispatch_once().await.unwrap(), 1);
+
+            let sent = sender.sent.lock().unwrap();
+            assert_eq!(sent.len(), 1);
+            assert_eq!(sent[0].to, "welcome@example.com");
+            assert!(sent[0].html_body.contains("welcome@example.com"));
+            assert!(sent[0].text_body.contains("welcome@example.com"));
+        }
+
+        #[tokio::test]
+        async fn test_render_error_is_logged_not_retried() {
+            let template = WelcomeEmailTemplate {
+                subject: "Hi {{name}}".to_string(),
+                ..Default::default()
+            };
+            let (service, sender, dispatcher) = setup_welcome(template);
+            service
+                .find_or_create("oidc|broken", "broken@example.com")
+                .await
+                .unwrap();
+
+            assert_eq!(dispatcher.dispatch_once().await.unwrap(), 1);
+            assert!(sender.sent.lock().unwrap().is_empty());
+        }
+
+        /// Fails its first send
+        #[derive(Default)]
+        struct FlakySender {
+            attempts: Mutex<u32>,
+        }
+
+        #[async_trait]
+        impl EmailSender for FlakySender {
+            async fn send(&self, _message: &EmailMessage) -> DomainResult<()> {
+                let mut attempts = self.attempts.lock().unwrap();
+                *attempts += 1;
+                if *attempts == 1 {
+                    return Err(DomainError::InfrastructureError("mail server down".into()));
+                }
+                Ok(())
+            }
+        }
+
+        #[derive(Default)]
+        struct CountingPublisher {
+            published: Mutex<u32>,
+        }
+
+        #[async_trait]
+        impl EventPublisher for CountingPublisher {
+            async fn publish(&self, _event: &OutboxEvent) -> DomainResult<()> {
+                *self.published.lock().unwrap() += 1;
+                Ok(())
+            }
+        }
+
+        #[tokio::test]
+        async fn test_failed_send_is_retried_without_forwarding_twice() {
+            let inner = Arc::new(CountingPublisher::default());
+            let publisher = WelcomeEmailPublisher::new(
+                inner.clone(),
+                Arc::new(FlakySender::default()),
+                WelcomeEmailTemplate::default(),
+            );
+            let event = OutboxEvent::new(
+                OutboxEvent::USER_CREATED,
+                serde_json::json!({ "email": "retry@example.com" }),
+            );
+
+            assert!(publisher.publish(&event).await.is_err());
+            assert_eq!(*inner.published.lock().unwrap(), 0);
+
+            // As the outbox retries it
+            publisher.publish(&event).await.unwrap();
+            assert_eq!(*inner.published.lock().unwrap(), 1);
+        }
+
+        #[test]
+        fn test_template_rejects_unclosed_placeholder() {
+            let template = WelcomeEmailTemplate {
+                text: "Hi {{email".to_string(),
+                ..Default::default()
+            };
+            assert!(template.render("a@example.com").is_err());
+        }
+    }
+
+    mod outbox_tests {
+        use super::*;
+        use crate::ports::EventPublisher;
+
+        struct MockPublisher {
+            fail: bool,
+            published: Mutex<Vec<String>>,
+        }
+
+        #[async_trait]
+        impl EventPublisher for MockPublisher {
+            async fn publish(&self, event: &OutboxEvent) -> DomainResult<()> {
+                if self.fail {
+                    return Err(DomainError::InfrastructureError("broker down".into()));
+                }
+                self.published.lock().unwrap().push(event.topic.clone());
+                Ok(())
+            }
+        }
+
+        fn setup_outbox(
+            fail: bool,
+        ) -> (UserService, Arc<InMemoryOutboxRepository>, OutboxDispatcher) {
+            let outbox = Arc::new(InMemoryOutboxRepository::default());
+            let users = InMemoryUserRepository {
+                outbox: outbox.clone(),
+                ..Default::default()
+            };
+            let service = UserService::new(
+                Arc::new(users),
+                Arc::new(InMemoryApiKeyRepository::default()),
+                Arc::new(InMemoryAuditLogRepository::default()),
+            );
+            let publisher = Arc::new(MockPublisher {
+                fail,
+                published: Mutex::new(Vec::new()),
+            });
+            let dispatcher = OutboxDispatcher::new(outbox.clone(), publisher, 10);
+            (service, outbox, dispatcher)
+        }
+
+        #[tokio::test]
+        async fn test_user_creation_event_is_delivered() {
+            let (service, outbox, dispatcher) = setup_outbox(false);
+            service
+                .find_or_create("outbox|1", "outbox@example.com")
+                .await
+                .unwrap();
+
+            assert_eq!(dispatcher.dispatch_once().await.unwrap(), 1);
+
+            let events = outbox.events.lock().unwrap();
+            assert_eq!(events.len(), 1);
+            assert_eq!(events[0].topic, "user.created");
+            assert!(events[0].delivered_at.is_some());
+        }
+
+        #[tokio::test]
+        async fn test_failed_delivery_is_retried_later() {
+            let (service, outbox, dispatcher) = setup_outbox(true);
+            service
+                .find_or_create("outbox|2", "retry@example.com")
+                .await
+                .unwrap();
+
+            assert_eq!(dispatcher.dispatch_once().await.unwrap(), 0);
+            {
+                let events = outbox.events.lock().unwrap();
+                assert_eq!(events[0].attempts, 1);
+                assert!(events[0].next_attempt_at > Utc::now());
+                assert!(events[0].delivered_at.is_none());
+            }
+
+            // Not due yet, so nothing is attempted
+            assert_eq!(dispatcher.dispatch_once().await.unwrap(), 0);
+            assert_eq!(outbox.events.lock().unwrap()[0].attempts, 1);
+        }
+
+        #[test]
+        fn test_backoff_grows_and_is_capped() {
+            assert_eq!(OutboxDispatcher::backoff(0), Duration::seconds(5));
+            assert_eq!(OutboxDispatcher::backoff(3), Duration::seconds(40));
+            assert_eq!(OutboxDispatcher::backoff(30), Duration::seconds(3600));
+        }
+    }
+
+    mod register_tests {
+        use super::*;
+        use crate::value_objects::{Email, Password};
+
+        pub(super) struct ReversingHasher;
+
+        impl PasswordHasher for ReversingHasher {
+            fn hash(&self, password: &Password) -> DomainResult<String> {
+                Ok(password.as_ref().chars().rev().collect())
+            }
+        }
+
+        fn command(email: &str) -> NewUserCommand {
+            NewUserCommand {
+                email: Email::try_from(email).unwrap(),
+                password: Password::try_from("hunter22").unwrap(),
+                invite_code: None,
+            }
+        }
+
+        #[tokio::test]
+        async fn test_register_stores_hash() {
+            let (service, _) = setup().await;
+            let service = service.with_password_hasher(Arc::new(ReversingHasher));
+
+            let user = service.register(command("new@example.com")).await.unwrap();
+            assert!(user.is_local());
+            assert_eq!(user.password_hash.as_deref(), Some("22retnuh"));
+        }
+
+        #[tokio::test]
+        async fn test_register_rejects_taken_email() {
+            let (service, existing) = setup().await;
+            let service = service.with_password_hasher(Arc::new(ReversingHasher));
+
+            let result = service.register(command(existing.email_str())).await;
+            assert!(matches!(result, Err(DomainError::EmailAlreadyExists(_))));
+        }
+
+        #[tokio::test]
+        async fn test_canonical_email_is_unique_but_the_original_is_kept() {
+            let (service, _) = setup().await;
+            let service = service
+                .with_password_hasher(Arc::new(ReversingHasher))
+                .with_email_canonicalization(EmailCanonicalization::new(true, ["gmail.com"]));
+
+            let user = service
+                .register(command("a.da+news@gmail.com"))
+                .await
+                .unwrap();
+            assert_eq!(user.email_str(), "a.da+news@gmail.com");
+            assert_eq!(user.canonical_email.as_deref(), Some("ada@gmail.com"));
+
+            let result = service.register(command("ada@gmail.com")).await;
+            assert!(matches!(result, Err(DomainError::EmailAlreadyExists(_))));
+            let available = Email::try_from("ad.a+other@gmail.com").unwrap();
+            assert!(!service.email_available(&available).await.unwrap());
+        }
+
+        #[tokio::test]
+        async fn test_email_of_deleted_account_is_available_when_reusable() {
+            let (service, mut existing) = setup().await;
+            existing.deleted_at = Some(Utc::now());
+            service.user_repository.save(&existing).await.unwrap();
+            let email = Email::try_from(existing.email_str()).unwrap();
+
+            assert!(!service.email_available(&email).await.unwrap());
+            let service = service.with_deleted_email_reuse(true);
+            assert!(service.email_available(&email).await.unwrap());
+        }
+
+        #[tokio::test]
+        async fn test_oidc_accounts_get_a_canonical_email_too() {
+            let (service, _) = setup().await;
+            let service = service
+                .with_password_hasher(Arc::new(ReversingHasher))
+                .with_email_canonicalization(EmailCanonicalization::new(true, ["gmail.com"]));
+
+            let user = service
+                .find_or_create("oidc|ada", "a.da+news@gmail.com")
+                .await
+                .unwrap();
+            assert_eq!(user.canonical_email.as_deref(), Some("ada@gmail.com"));
+
+            let result = service.register(command("ada@gmail.com")).await;
+            assert!(matches!(result, Err(DomainError::EmailAlreadyExists(_))));
+            let result = service.find_or_create("oidc|other", "ada@gmail.com").await;
+            assert!(matches!(result, Err(DomainError::EmailAlreadyExists(_))));
+        }
+
+        #[tokio::test]
+        async fn test_without_canonicalization_variants_are_distinct() {
+            let (service, _) = setup().await;
+            let service = service.with_password_hasher(Arc::new(ReversingHasher));
+
+            let user = service
+                .register(command("ada+news@gmail.com"))
+                .await
+                .unwrap();
+            assert!(user.canonical_email.is_none());
+            assert!(service.register(command("ada@gmail.com")).await.is_ok());
+        }
+
+        /// The service with `existing` soft-deleted
+        async fn setup_deleted(reuse: bool) -> (UserService, User) {
+            let (service, mut existing) = setup().await;
+            existing.deleted_at = Some(Utc::now());
+            service.user_repository.save(&existing).await.unwrap();
+
+            let service = service
+                .with_password_hasher(Arc::new(ReversingHasher))
+                .with_deleted_email_reuse(reuse);
+            (service, existing)
+        }
+
+        #[tokio::test]
+        async fn test_deleted_email_stays_taken_by_default() {
+            let (service, deleted) = setup_deleted(false).await;
+
+            let result = service.register(command(deleted.email_str())).await;
+            assert!(matches!(result, Err(DomainError::EmailAlreadyExists(_))));
+
+            // SSO doesn't link a new subject to the deleted account either
+            let result = service.find_or_create("oidc|2", deleted.email_str()).await;
+            assert!(matches!(result, Err(DomainError::EmailAlreadyExists(_))));
+            let stored = service.find_by_id(deleted.id).await.unwrap();
+            assert_eq!(stored.subject, deleted.subject);
+        }
+
+        #[tokio::test]
+        async fn test_deleted_email_can_be_reused() {
+            let (service, deleted) = setup_deleted(true).await;
+
+            let user = service
+                .register(command(deleted.email_str()))
+                .await
+                .unwrap();
+            assert_ne!(user.id, deleted.id);
+            assert!(!user.is_deleted());
+            let found = service.find_by_email(deleted.email_str()).await.unwrap();
+            assert_eq!(found.map(|u| u.id), Some(user.id));
+
+            // The old account is kept, under a placeholder address
+            let previous = service.find_by_id(deleted.id).await.unwrap();
+            assert!(previous.is_deleted());
+            assert_eq!(
+                previous.email_str(),
+                format!("{}@deleted.invalid", deleted.id.simple())
+            );
+        }
+
+        #[tokio::test]
+        async fn test_sso_reuses_deleted_email_as_new_account() {
+            let (service, deleted) = setup_deleted(true).await;
+
+            let user = service
+                .find_or_create("oidc|2", deleted.email_str())
+                .await
+                .unwrap();
+            assert_ne!(user.id, deleted.id);
+            assert_eq!(user.subject, "oidc|2");
+
+            // Live accounts are still linked rather than duplicated
+            let again = service
+                .find_or_create("oidc|3", deleted.email_str())
+                .await
+                .unwrap();
+            assert_eq!(again.id, user.id);
+        }
+
+        #[tokio::test]
+        async fn test_first_user_becomes_admin_when_enabled() {
+            let service = UserService::new(
+                Arc::new(InMemoryUserRepository::default()),
+                Arc::new(InMemoryApiKeyRepository::default()),
+                Arc::new(InMemoryAuditLogRepository::default()),
+            )
+            .with_password_hasher(Arc::new(ReversingHasher))
+            .with_first_user_admin(true);
+
+            let first = service
+                .register(command("first@example.com"))
+                .await
+                .unwrap();
+            assert_eq!(first.role, Role::Admin);
+            let stored = service.find_by_id(first.id).await.unwrap();
+            assert_eq!(stored.role, Role::Admin);
+
+            let second = service
+                .register(command("second@example.com"))
+                .await
+                .unwrap();
+            assert_eq!(second.role, Role::User);
+        }
+
+        #[tokio::test]
+        async fn test_first_user_admin_ignores_existing_users() {
+            // `setup` already created an account
+            let (service, _) = setup().await;
+            let service = service
+                .with_password_hasher(Arc::new(ReversingHasher))
+                .with_first_user_admin(true);
+
+            let user = service.register(command("new@example.com")).await.unwrap();
+            assert_eq!(user.role, Role::User);
+        }
+
+        #[derive(Default)]
+        struct InMemoryInviteRepository {
+            invites: Arc<Mutex<Vec<InviteCode>>>,
+        }
+
+        #[async_trait]
+        impl InviteRepository for InMemoryInviteRepository {
+            async fn save(&self, invite: &InviteCode) -> DomainResult<()> {
+                self.invites.lock().unwrap().push(invite.clone());
+                Ok(())
+            }
+
+            async fn list(&self, limit: u32, offset: u32) -> DomainResult<Vec<InviteCode>> {
+                let invites = self.invites.lock().unwrap();
+                Ok(invites
+                    .iter()
+                    .rev()
+                    .skip(offset as usize)
+                    .take(limit as usize)
+                    .cloned()
+                    .collect())
+            }
+
+            async fn count(&self) -> DomainResult<u64> {
+                Ok(self.invites.lock().unwrap().len() as u64)
+            }
+
+            async fn redeem_in(
+                &self,
+                tx: &mut dyn Transaction,
+                code: &str,
+                user_id: Uuid,
+            ) -> DomainResult<bool> {
+                let usable = self
+                    .invites
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .any(|invite| invite.code == code && !invite.is_used());
+                if usable {
+                    let (invites, code) = (self.invites.clone(), code.to_string());
+                    InMemoryTransaction::from_dyn(tx).push(move || {
+                        let mut invites = invites.lock().unwrap();
+                        let invite = invites.iter_mut().find(|i| i.code == code).unwrap();
+                        invite.used_by = Some(user_id);
+                        invite.used_at = Some(Utc::now());
+                    });
+                }
+                Ok(usable)
+            }
+        }
+
+        #[tokio::test]
+        async fn test_invite_code_is_required_and_single_use() {
+            let (service, admin) = setup().await;
+            let service = service
+                .with_password_hasher(Arc::new(ReversingHasher))
+                .with_invites(Arc::new(InMemoryInviteRepository::default()), true);
+
+            let result = service.register(command("uninvited@example.com")).await;
+            assert!(matches!(result, Err(DomainError::InvalidInviteCode(_))));
+
+            let invite = service.create_invite(admin.id).await.unwrap();
+            let with_code = |email: &str| NewUserCommand {
+                invite_code: Some(invite.code.clone()),
+                ..command(email)
+            };
+            let user = service
+                .register(with_code("first@example.com"))
+                .await
+                .unwrap();
+
+            let result = service.register(with_code("second@example.com")).await;
+            assert!(matches!(result, Err(DomainError::InvalidInviteCode(_))));
+            let found = service.find_by_email("second@example.com").await.unwrap();
+            assert!(found.is_none());
+
+            let (invites, total) = service.list_invites(10, 0).await.unwrap();
+            assert_eq!(total, 1);
+            assert_eq!(invites[0].used_by, Some(user.id));
+            assert_eq!(invites[0].created_by, admin.id);
+        }
+
+        #[tokio::test]
+        async fn test_new_sso_account_needs_an_invite_code() {
+            let (service, admin) = setup().await;
+            let service = service.with_invites(Arc::new(InMemoryInviteRepository::default()), true);
+            let claims = serde_json::json!({"sub": "sso|new", "email": "sso@example.com"});
+
+            let result = service.find_or_create_from_claims(&claims, None).await;
+            assert!(matches!(result, Err(DomainError::InvalidInviteCode(_))));
+
+            let invite = service.create_invite(admin.id).await.unwrap();
+            let user = service
+                .find_or_create_from_claims(&claims, Some(&invite.code))
+                .await
+                .unwrap();
+            let (invites, _) = service.list_invites(10, 0).await.unwrap();
+            assert_eq!(invites[0].used_by, Some(user.id));
+
+            // Existing accounts sign in without one
+            let again = service
+                .find_or_create_from_claims(&claims, None)
+                .await
+                .unwrap();
+            assert_eq!(again.id, user.id);
+        }
+
+        /// Creates a workspace per user, failing afterwards when `fail` is set
+        #[derive(Default)]
+        struct WorkspaceHook {
+            workspaces: Arc<Mutex<Vec<Uuid>>>,
+            fail: bool,
+        }
+
+        #[async_trait]
+        impl RegistrationHook for WorkspaceHook {
+            async fn on_register(&self, tx: &mut dyn Transaction, user: &User) -> DomainResult<()> {
+                let (workspaces, owner) = (self.workspaces.clone(), user.id);
+                InMemoryTransaction::from_dyn(tx)
+                    .push(move || workspaces.lock().unwrap().push(owner));
+
+                if self.fail {
+                    return Err(DomainError::validation("Workspace quota exceeded"));
+                }
+                Ok(())
+            }
+        }
+
+        #[tokio::test]
+        async fn test_registration_hook_commits_with_user() {
+            let (service, _) = setup().await;
+            let hook = Arc::new(WorkspaceHook::default());
+            let service = service
+                .with_password_hasher(Arc::new(ReversingHasher))
+                .with_registration_hook(hook.clone());
+
+            let user = service.register(command("new@example.com")).await.unwrap();
+            assert!(service.find_by_id(user.id).await.is_ok());
+            assert_eq!(*hook.workspaces.lock().unwrap(), vec![user.id]);
+
+            let sso = service
+                .find_or_create("oidc|new", "sso@example.com")
+                .await
+                .unwrap();
+            assert_eq!(*hook.workspaces.lock().unwrap(), vec![user.id, sso.id]);
+        }
+
+        #[tokio::test]
+        async fn test_failed_registration_hook_rolls_back_user() {
+            let (service, _) = setup().await;
+            let hook = Arc::new(WorkspaceHook {
+                fail: true,
+                ..Default::default()
+            });
+            let service = service
+                .with_password_hasher(Arc::new(ReversingHasher))
+                .with_registration_hook(hook.clone());
+
+            let result = service.register(command("new@example.com")).await;
+            assert!(matches!(result, Err(DomainError::ValidationError(_))));
+            assert!(
+                service
+                    .find_by_email("new@example.com")
+                    .await
+                    .unwrap()
+                    .is_none()
+            );
+            assert!(hook.workspaces.lock().unwrap().is_empty());
+        }
+
+        /// Another login for the same subject commits first, so this one hits the unique index
+        struct ConcurrentLogin {
+            repository: Arc<dyn UserRepository>,
+            winner: Mutex<Option<User>>,
+        }
+
+        #[async_trait]
+        impl RegistrationHook for ConcurrentLogin {
+            async fn on_register(
+                &self,
+                _tx: &mut dyn Transaction,
+                user: &User,
+            ) -> DomainResult<()> {
+                let Some(winner) = self.winner.lock().unwrap().take() else {
+                    return Ok(());
+                };
+                self.repository.save(&winner).await?;
+                Err(DomainError::SubjectAlreadyExists(user.subject.clone()))
+            }
+        }
+
+        #[tokio::test]
+        async fn test_losing_a_first_login_race_returns_the_winner() {
+            let (service, _) = setup().await;
+            let winner =
+                User::new("oidc|race", Email::try_from("race@example.com").unwrap()).unwrap();
+            let repository = service.user_repository.clone();
+            let service = service.with_registration_hook(Arc::new(ConcurrentLogin {
+                repository,
+                winner: Mutex::new(Some(winner.clone())),
+            }));
+
+            let user = service
+                .find_or_create("oidc|race", "race@example.com")
+                .await
+                .unwrap();
+            assert_eq!(user.id, winner.id);
+        }
+    }
+
+    mod password_reset_tests {
+        use super::register_tests::ReversingHasher;
+        use super::welcome_email_tests::CapturingSender;
+        use super::*;
+
+        async fn setup_reset(ttl: Duration) -> (UserService, User) {
+            let (service, user, _) = setup_reset_with_sender(ttl).await;
+            (service, user)
+        }
+
+        async fn setup_reset_with_sender(
+            ttl: Duration,
+        ) -> (UserService, User, Arc<CapturingSender>) {
+            let (service, _) = setup().await;
+            let sender = Arc::new(CapturingSender::default());
+            let service = service
+                .with_password_hasher(Arc::new(ReversingHasher))
+                .with_password_reset(
+                    Arc::new(InMemoryPasswordResetTokenRepository::default()),
+                    sender.clone(),
+                    ttl,
+                    "https://app.example.com/reset?token=",
+                );
+            let user = service
+                .register(NewUserCommand {
+                    email: Email::try_from("local@example.com").unwrap(),
+                    password: Password::try_from("hunter22").unwrap(),
+                    invite_code: None,
+                })
+                .await
+                .unwrap();
+            (service, user, sender)
+        }
+
+        fn password(value: &str) -> Password {
+            Password::try_from(value).unwrap()
+        }
+
+        #[tokio::test]
+        async fn test_reset_token_sets_password_once() {
+            let (service, user) = setup_reset(Duration::minutes(30)).await;
+            let token = service
+                .request_password_reset(user.email_str())
+                .await
+                .unwrap()
+                .unwrap();
+
+            let updated = service
+                .reset_password(&token, &password("newpass1"))
+                .await
+                .unwrap();
+            assert_eq!(updated.password_hash.as_deref(), Some("1ssapwen"));
+
+            let replay = service.reset_password(&token, &password("otherpass")).await;
+            assert!(matches!(replay, Err(DomainError::Unauthorized(_))));
+            let stored = service.find_by_id(user.id).await.unwrap();
+            assert_eq!(stored.password_hash.as_deref(), Some("1ssapwen"));
+        }
+
+        #[tokio::test]
+        async fn test_expired_reset_token_is_rejected() {
+            let (service, user) = setup_reset(Duration::zero()).await;
+            let token = service
+                .request_password_reset(user.email_str())
+                .await
+                .unwrap()
+                .unwrap();
+
+            let result = service.reset_password(&token, &password("newpass1")).await;
+            assert!(matches!(result, Err(DomainError::Unauthorized(_))));
+        }
+
+        #[tokio::test]
+        async fn test_prune_removes_expired_reset_tokens() {
+            let (service, user) = setup_reset(Duration::zero()).await;
+            let token = service
+                .request_password_reset(user.email_str())
+                .await
+                .unwrap()
+                .unwrap();
+
+            let pruned = service.prune_expired_tokens().await.unwrap();
+            assert_eq!(
+                pruned,
+                PrunedTokens {
+                    password_reset: 1,
+                    email_verification: 0
+                }
+            );
+            let result = service.reset_password(&token, &password("newpass1")).await;
+            assert!(matches!(result, Err(DomainError::Unauthorized(_))));
+        }
+
+        #[tokio::test]
+        async fn test_using_one_token_invalidates_the_others() {
+            let (service, user) = setup_reset(Duration::minutes(30)).await;
+            let first = service.request_password_reset(user.email_str()).await;
+            let second = service.request_password_reset(user.email_str()).await;
+
+            service
+                .reset_password(&second.unwrap().unwrap(), &password("newpass1"))
+                .await
+                .unwrap();
+            let result = service
+                .reset_password(&first.unwrap().unwrap(), &password("otherpass"))
+                .await;
+            assert!(matches!(result, Err(DomainError::Unauthorized(_))));
+        }
+
+        #[tokio::test]
+        async fn test_password_change_invalidates_outstanding_tokens() {
+            let (service, user) = setup_reset(Duration::minutes(30)).await;
+            let token = service
+                .request_password_reset(user.email_str())
+                .await
+                .unwrap()
+                .unwrap();
+
+            service
+                .change_password(user.id, &password("changed1"))
+                .await
+                .unwrap();
+
+            let result = service.reset_password(&token, &password("newpass1")).await;
+            assert!(matches!(result, Err(DomainError::Unauthorized(_))));
+        }
+
+        #[tokio::test]
+        async fn test_reset_link_is_emailed_to_local_accounts_only() {
+            let (service, user, sender) = setup_reset_with_sender(Duration::minutes(30)).await;
+
+            assert!(service.send_password_reset(user.email_str()).await.unwrap());
+            assert!(
+                !service
+                    .send_password_reset("user@example.com")
+                    .await
+                    .unwrap()
+            );
+            assert!(
+                !service
+                    .send_password_reset("nobody@example.com")
+                    .await
+                    .unwrap()
+            );
+
+            let token = {
+                let sent = sender.sent.lock().unwrap();
+                assert_eq!(sent.len(), 1);
+                assert_eq!(sent[0].to, user.email_str());
+                let start = sent[0].text_body.find("?token=").unwrap() + "?token=".len();
+                sent[0].text_body[start..].trim().to_string()
+            };
+            service
+                .reset_password(&token, &password("newpass1"))
+                .await
+                .unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_sso_and_unknown_accounts_get_no_token() {
+            let (service, _) = setup_reset(Duration::minutes(30)).await;
+            // Created by `setup` through SSO, without a password
+            let sso = service.request_password_reset("user@example.com").await;
+            assert!(sso.unwrap().is_none());
+            let unknown = service.request_password_reset("nobody@example.com").await;
+            assert!(unknown.unwrap().is_none());
+        }
+    }
+
+    mod email_verification_tests {
+        use super::welcome_email_tests::CapturingSender;
+        use super::*;
+
+        const URL: &str = "https://app.example.com/verify?token=";
+
+        async fn setup_verification(
+            cooldown: Duration,
+        ) -> (UserService, User, Arc<CapturingSender>) {
+            let (service, user) = setup().await;
+            let sender = Arc::new(CapturingSender::default());
+            let service = service.with_email_verification(
+                Arc::new(InMemoryEmailVerificationTokenRepository::default()),
+                sender.clone(),
+                Duration::hours(24),
+                cooldown,
+                URL,
+            );
+            (service, user, sender)
+        }
+
+        fn sent_token(sender: &CapturingSender) -> String {
+            let sent = sender.sent.lock().unwrap();
+            let body = &sent.last().expect("an email was sent").text_body;
+            let start = body.find(URL).unwrap() + URL.len();
+            body[start..].trim().to_string()
+        }
+
+        #[tokio::test]
+        async fn test_resent_link_verifies_the_email() {
+            let (service, user, sender) = setup_verification(Duration::zero()).await;
+            service.resend_verification(user.id).await.unwrap();
+            let first = sent_token(&sender);
+            service.resend_verification(user.id).await.unwrap();
+            let second = sent_token(&sender);
+
+            // Resending replaces the earlier link
+            let result = service.verify_email(&first).await;
+            assert!(matches!(result, Err(DomainError::Unauthorized(_))));
+
+            let verified = service.verify_email(&second).await.unwrap();
+            assert!(verified.email_verified);
+            let result = service.resend_verification(user.id).await;
+            assert!(matches!(result, Err(DomainError::ValidationError(_))));
+        }
+
+        #[tokio::test]
+        async fn test_resend_within_cooldown_is_rate_limited() {
+            let (service, user, sender) = setup_verification(Duration::seconds(60)).await;
+            service.resend_verification(user.id).await.unwrap();
+
+            let result = service.resend_verification(user.id).await;
+            assert!(matches!(
+                result,
+                Err(DomainError::RateLimited(secs)) if secs > 0 && secs <= 60
+            ));
+
+            // By email the cooldown applies too, but silently
+            service
+                .resend_verification_by_email(user.email_str())
+                .await
+                .unwrap();
+            assert_eq!(sender.sent.lock().unwrap().len(), 1);
+        }
+
+        #[tokio::test]
+        async fn test_resend_by_email_does_not_reveal_accounts() {
+            let (service, user, sender) = setup_verification(Duration::zero()).await;
+
+            service
+                .resend_verification_by_email("nobody@example.com")
+                .await
+                .unwrap();
+            assert!(sender.sent.lock().unwrap().is_empty());
+
+            service
+                .resend_verification_by_email(user.email_str())
+                .await
+                .unwrap();
+            let sent = sender.sent.lock().unwrap();
+            assert_eq!(sent.len(), 1);
+            assert_eq!(sent[0].to, user.email_str());
+        }
+    }
+
+    mod totp_tests {
+        use super::*;
+
+        const VALID_CODE: &str = "123456";
+        /// Valid too, one time step after [`VALID_CODE`]
+        const NEXT_CODE: &str = "234567";
+
+        /// Accepts only [`VALID_CODE`] and [`NEXT_CODE`]
+        struct FixedCodeProvider;
+
+        impl TotpProvider for FixedCodeProvider {
+            fn generate_secret(&self) -> DomainResult<String> {
+                Ok("JBSWY3DPEHPK3PXP".to_string())
+            }
+
+            fn provisioning_uri(&self, secret: &str, account: &str) -> DomainResult<String> {
+                Ok(format!("otpauth://totp/test:{}?secret={}", account, secret))
+            }
+
+            fn verify(&self, _secret: &str, code: &str) -> DomainResult<Option<u64>> {
+                Ok(match code {
+                    VALID_CODE => Some(1_000),
+                    NEXT_CODE => Some(1_001),
+                    _ => None,
+                })
+            }
+        }
+
+        /// Reversible stand-in for real encryption
+        struct PrefixCipher;
+
+        impl SecretCipher for PrefixCipher {
+            fn encrypt(&self, plaintext: &str) -> DomainResult<String> {
+                Ok(format!("enc:{}", plaintext))
+            }
+
+            fn decrypt(&self, ciphertext: &str) -> DomainResult<String> {
+                ciphertext
+                    .strip_prefix("enc:")
+                    .map(str::to_string)
+                    .ok_or_else(|| DomainError::InfrastructureError("bad ciphertext".into()))
+            }
+        }
+
+        async fn setup_totp() -> (UserService, User) {
+            let (service, user) = setup().await;
+            let service = service.with_totp(Arc::new(FixedCodeProvider), Arc::new(PrefixCipher));
+            (service, user)
+        }
+
+        #[tokio::test]
+        async fn test_enroll_confirm_and_verify() {
+            let (service, user) = setup_totp().await;
+
+            let enrollment = service.start_totp_enrollment(user.id).await.unwrap();
+            assert!(enrollment.otpauth_uri.contains(&enrollment.secret));
+
+            let stored = service.find_by_id(user.id).await.unwrap();
+            assert_eq!(stored.totp_secret.as_deref(), Some("enc:JBSWY3DPEHPK3PXP"));
+            assert!(!stored.requires_totp());
+
+            service.confirm_totp(user.id, VALID_CODE).await.unwrap();
+            assert!(service.find_by_id(user.id).await.unwrap().requires_totp());
+
+            let verified = service.verify_totp(user.id, NEXT_CODE).await.unwrap();
+            assert_eq!(verified.id, user.id);
+        }
+
+        #[tokio::test]
+        async fn test_accepted_codes_cannot_be_replayed() {
+            let (service, user) = setup_totp().await;
+            service.start_totp_enrollment(user.id).await.unwrap();
+            service.confirm_totp(user.id, VALID_CODE).await.unwrap();
+
+            // The enrollment code can't be reused to log in
+            let result = service.verify_totp(user.id, VALID_CODE).await;
+            assert!(matches!(result, Err(DomainError::Unauthorized(_))));
+
+            service.verify_totp(user.id, NEXT_CODE).await.unwrap();
+            let result = service.verify_totp(user.id, NEXT_CODE).await;
+            assert!(matches!(result, Err(DomainError::Unauthorized(_))));
+        }
+
+        #[tokio::test]
+        async fn test_wrong_code_is_rejected() {
+            let (service, user) = setup_totp().await;
+            service.start_totp_enrollment(user.id).await.unwrap();
+
+            let result = service.confirm_totp(user.id, "000000").await;
+            assert!(matches!(result, Err(DomainError::Unauthorized(_))));
+            assert!(!service.find_by_id(user.id).await.unwrap().totp_enabled);
+
+            service.confirm_totp(user.id, VALID_CODE).await.unwrap();
+            let result = service.verify_totp(user.id, "000000").await;
+            assert!(matches!(result, Err(DomainError::Unauthorized(_))));
+        }
+
+        #[tokio::test]
+        async fn test_verify_requires_enabled_totp() {
+            let (service, user) = setup_totp().await;
+            let result = service.verify_totp(user.id, VALID_CODE).await;
+            assert!(matches!(result, Err(DomainError::ValidationError(_))));
+        }
+    }
+
+    mod claims_tests {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_find_or_create_uses_mapped_claims() {
+            let (service, _) = setup().await;
+            let service = service.with_claim_mapping(ClaimMapping {
+                subject_claim: "oid".to_string(),
+                email_claim: "upn".to_string(),
+                ..Default::default()
+            });
+            let claims = serde_json::json!({"oid": "tenant-user-1", "upn": "Mapped@Example.com"});
+
+            let user = service
+                .find_or_create_from_claims(&claims, None)
+                .await
+                .unwrap();
+            assert_eq!(user.subject, "tenant-user-1");
+            assert_eq!(user.email_str(), "mapped@example.com");
+
+            let again = service
+                .find_or_create_from_claims(&claims, None)
+                .await
+                .unwrap();
+            assert_eq!(again.id, user.id);
+        }
+
+        #[tokio::test]
+        async fn test_missing_claim_is_validation_error() {
+            let (service, _) = setup().await;
+            let claims = serde_json::json!({"sub": "abc"});
+            let result = service.find_or_create_from_claims(&claims, None).await;
+            assert!(matches!(result, Err(DomainError::ValidationError(_))));
+        }
+
+        #[tokio::test]
+        async fn test_unverified_email_does_not_link_an_existing_account() {
+            let (service, user) = setup().await;
+            let claims = serde_json::json!({"sub": "attacker|1", "email": user.email_str()});
+
+            let result = service.find_or_create_from_claims(&claims, None).await;
+            assert!(matches!(result, Err(DomainError::Unauthorized(_))));
+            let found = service.find_by_id(user.id).await.unwrap();
+            assert_eq!(found.subject, user.subject);
+
+            let verified = serde_json::json!({
+                "sub": "other|1",
+                "email": user.email_str(),
+                "email_verified": true,
+            });
+            let linked = service
+                .find_or_create_from_claims(&verified, None)
+                .await
+                .unwrap();
+            assert_eq!(linked.id, user.id);
+            assert_eq!(linked.subject, "other|1");
+        }
+    }
+
+    mod user_search_tests {
+        use super::*;
+        use crate::value_objects::Role;
+
+        async fn seed(service: &UserService) {
+            for email in ["anna@example.com", "annabel@test.org", "joanna@example.com"] {
+                service
+                    .find_or_create(&format!("sub-{}", email), email)
+                    .await
+                    .unwrap();
+            }
+        }
+
+        fn emails(users: &[User]) -> Vec<&str> {
+            users.iter().map(User::email_str).collect()
+        }
+
+        #[tokio::test]
+        async fn test_prefix_and_contains_return_expected_subsets() {
+            let (service, _) = setup().await;
+            seed(&service).await;
+
+            let (users, total) = service
+                .search_users("ANNA", EmailMatchMode::Prefix, 10, 0)
+                .await
+                .unwrap();
+            assert_eq!(emails(&users), ["anna@example.com", "annabel@test.org"]);
+            assert_eq!(total, 2);
+
+            let (users, total) = service
+                .search_users("anna", EmailMatchMode::Contains, 10, 0)
+                .await
+                .unwrap();
+            assert_eq!(
+                emails(&users),
+                ["anna@example.com", "annabel@test.org", "joanna@example.com"]
+            );
+            assert_eq!(total, 3);
+        }
+
+        #[tokio::test]
+        async fn test_search_pages_through_matches() {
+            let (service, _) = setup().await;
+            seed(&service).await;
+
+            let (users, total) = service
+                .search_users("anna", EmailMatchMode::Contains, 1, 1)
+                .await
+                .unwrap();
+            assert_eq!(emails(&users), ["annabel@test.org"]);
+            assert_eq!(total, 3);
+        }
+
+        #[tokio::test]
+        async fn test_find_users_combines_filter_fields() {
+            let (service, _) = setup().await;
+            seed(&service).await;
+
+            let repo = &service.user_repository;
+            let mut admin = repo
+                .find_by_email("annabel@test.org")
+                .await
+                .unwrap()
+                .unwrap();
+            admin.role = Role::Admin;
+            repo.save(&admin).await.unwrap();
+            let mut deleted = repo
+                .find_by_email("anna@example.com")
+                .await
+                .unwrap()
+                .unwrap();
+            deleted.role = Role::Admin;
+            deleted.deleted_at = Some(Utc::now());
+            repo.save(&deleted).await.unwrap();
+
+            let filter = UserFilter {
+                role: Some(Role::Admin),
+                is_active: Some(true),
+                created_between: Some((Utc::now() - Duration::hours(1), Utc::now())),
+                email_contains: Some("ANNA".to_string()),
+            };
+            let users = service.find_users(&filter, 10, 0).await.unwrap();
+            assert_eq!(emails(&users), ["annabel@test.org"]);
+
+            let filter = UserFilter {
+                created_between: Some((Utc::now(), Utc::now() + Duration::hours(1))),
+                ..UserFilter::default()
+            };
+            assert!(service.find_users(&filter, 10, 0).await.unwrap().is_empty());
+        }
+
+        #[tokio::test]
+        async fn test_empty_filter_returns_all_users() {
+            let (service, _) = setup().await;
+            seed(&service).await;
+
+            let users = service
+                .find_users(&UserFilter::default(), 10, 0)
+                .await
+                .unwrap();
+            assert_eq!(
+                emails(&users),
+                [
+                    "anna@example.com",
+                    "annabel@test.org",
+                    "joanna@example.com",
+                    "user@example.com"
+                ]
+            );
+        }
+    }
+
+    mod audit_search_tests {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_search_rejects_inverted_range() {
+            let (service, _) = setup().await;
+            let now = Utc::now();
+            let filter = AuditLogFilter {
+                from: Some(now),
+                to: Some(now - Duration::hours(1)),
+                ..Default::default()
+            };
+
+            let result = service.search_audit_log(&filter, 10, 0).await;
+            assert!(matches!(result, Err(DomainError::ValidationError(_))));
+        }
+
+        #[test]
+        fn test_filter_matches_actor_or_target() {
+            let actor = Uuid::new_v4();
+            let target = Uuid::new_v4();
+            let entry = AuditEntry::new(actor, AuditAction::UserSoftDeleted, Some(target));
+
+            let by_user = |id| AuditLogFilter {
+                user_id: Some(id),
+                ..Default::default()
+            };
+            assert!(by_user(actor).matches(&entry));
+            assert!(by_user(target).matches(&entry));
+            assert!(!by_user(Uuid::new_v4()).matches(&entry));
+
+            let by_action = AuditLogFilter {
+                action: Some(AuditAction::UserErased),
+                ..Default::default()
+            };
+            assert!(!by_action.matches(&entry));
+        }
+    }
+
+    mod session_limits {
+        use super::*;
+
+        /// The service limited to two sessions, with `user` holding both
+        async fn setup_limited(mode: SessionLimitMode) -> (UserService, User) {
+            let (service, user) = setup().await;
+            let service = service
+                .with_session_repository(Arc::new(InMemorySessionRepository::default()))
+                .with_session_limit(SessionLimit { max: 2, mode });
+
+            let now = Utc::now();
+            for (id, age) in [("oldest", 2), ("newer", 1)] {
+                let session = SessionInfo {
+                    session_id: id.to_string(),
+                    user_id: user.id,
+                    ip: None,
+                    user_agent: None,
+                    created_at: now - Duration::hours(age),
+                    expires_at: now + Duration::days(1),
+                };
+                service.record_session(&session).await.unwrap();
+            }
+            (service, user)
+        }
+
+        async fn session_ids(service: &UserService) -> Vec<String> {
+            let (sessions, _) = service
+                .list_sessions(&SessionFilter::default(), 10, 0)
+                .await
+                .unwrap();
+            sessions.into_iter().map(|s| s.session_id).collect()
+        }
+
+        #[tokio::test]
+        async fn test_login_at_limit_is_rejected() {
+            let (service, user) = setup_limited(SessionLimitMode::Reject).await;
+
+            let result = service.enforce_session_limit(user.id).await;
+            assert!(matches!(result, Err(DomainError::SessionLimitReached(2))));
+            assert_eq!(session_ids(&service).await, ["newer", "oldest"]);
+
+            // Other users are not affected
+            assert!(service.enforce_session_limit(Uuid::new_v4()).await.is_ok());
+        }
+
+        #[tokio::test]
+        async fn test_login_at_limit_evicts_the_oldest_session() {
+            let (service, user) = setup_limited(SessionLimitMode::EvictOldest).await;
+
+            service.enforce_session_limit(user.id).await.unwrap();
+            assert_eq!(session_ids(&service).await, ["newer"]);
+        }
+
+        #[tokio::test]
+        async fn test_revoke_other_sessions_keeps_the_current_one() {
+            let (service, user) = setup_limited(SessionLimitMode::Reject).await;
+
+            let revoked = service
+                .revoke_other_sessions(user.id, "newer")
+                .await
+                .unwrap();
+            assert_eq!(revoked, 1);
+            assert_eq!(session_ids(&service).await, ["newer"]);
+        }
+
+        #[test]
+        fn test_session_limit_mode_parses() {
+            assert_eq!(
+                "evict_oldest".parse::<SessionLimitMode>(),
+                Ok(SessionLimitMode::EvictOldest)
+            );
+            assert!("oldest".parse::<SessionLimitMode>().is_err());
+        }
+    }
 }
@@ -6,9 +6,11 @@
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt;
 use thiserror::Error;
+use unicode_normalization::UnicodeNormalization;
 use uuid::Uuid;
 
 pub type UserId = Uuid;
+pub type ApiKeyId = Uuid;
 
 // ============================================================================
 // Validation Error
@@ -20,8 +22,49 @@ pub enum ValidationError {
     #[error("Invalid email format: {0}")]
     InvalidEmail(String),
 
+    #[error("Email must be at most {max} characters, got {actual}")]
+    EmailTooLong { max: usize, actual: usize },
+
     #[error("Password must be at least {min} characters, got {actual}")]
     PasswordTooShort { min: usize, actual: usize },
+
+    #[error("Password must not be empty")]
+    EmptyPassword,
+
+    #[error("Password must be at most {max} characters, got {actual}")]
+    PasswordTooLong { max: usize, actual: usize },
+
+    #[error("Subject must not be empty")]
+    EmptySubject,
+
+    #[error("Subject must be at most {max} characters, got {actual}")]
+    SubjectTooLong { max: usize, actual: usize },
+
+    #[error("Invalid role: {0}")]
+    InvalidRole(String),
+
+    #[error("Display name must not be empty")]
+    EmptyDisplayName,
+
+    #[error("Display name must be at most {max} characters, got {actual}")]
+    DisplayNameTooLong { max: usize, actual: usize },
+}
+
+impl ValidationError {
+    /// Name of the input field the error is about
+    pub fn field(&self) -> &'static str {
+        match self {
+            ValidationError::InvalidEmail(_) | ValidationError::EmailTooLong { .. } => "email",
+            ValidationError::PasswordTooShort { .. }
+            | ValidationError::EmptyPassword
+            | ValidationError::PasswordTooLong { .. } => "password",
+            ValidationError::EmptySubject | ValidationError::SubjectTooLong { .. } => "subject",
+            ValidationError::InvalidRole(_) => "role",
+            ValidationError::EmptyDisplayName | ValidationError::DisplayNameTooLong { .. } => {
+                "display_name"
+            }
+        }
+    }
 }
 
 // ============================================================================
@@ -30,15 +73,32 @@ pub enum ValidationError {
 
 /// A validated email address.
 ///
-/// Simple validation: must contain exactly one `@` with non-empty parts on both sides.
+/// Simple validation: must contain exactly one `@` with non-empty parts on both sides,
+/// within the RFC 5321 length limits.
+///
+/// Normalization: surrounding whitespace is trimmed, the address is converted to
+/// Unicode NFC and lowercased, so equivalent spellings map to the same account.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Email(String);
 
+/// Maximum length of an email address (RFC 5321 path limit, minus the angle brackets)
+pub const MAX_EMAIL_LENGTH: usize = 254;
+
+/// Maximum length of the part before the `@` (RFC 5321)
+pub const MAX_EMAIL_LOCAL_LENGTH: usize = 64;
+
 impl Email {
     /// Minimum validation: contains @ with non-empty local and domain parts
     pub fn new(value: impl Into<String>) -> Result<Self, ValidationError> {
         let value = value.into();
-        let trimmed = value.trim().to_lowercase();
+        let trimmed = value.trim().nfc().collect::<String>().to_lowercase();
+
+        if trimmed.len() > MAX_EMAIL_LENGTH {
+            return Err(ValidationError::EmailTooLong {
+                max: MAX_EMAIL_LENGTH,
+                actual: trimmed.len(),
+            });
+        }
 
         // Basic email validation
         let parts: Vec<&str> = trimmed.split('@').collect();
@@ -51,6 +111,10 @@ impl Email {
             return Err(ValidationError::InvalidEmail(value));
         }
 
+        if parts[0].len() > MAX_EMAIL_LOCAL_LENGTH {
+            return Err(ValidationError::InvalidEmail(value));
+        }
+
         Ok(Self(trimmed))
     }
 
@@ -58,6 +122,23 @@ impl Email {
     pub fn into_inner(self) -> String {
         self.0
     }
+
+    /// The address with all but the first character of the local part hidden,
+    /// e.g. `j***@example.com`; the mask length does not leak the original's
+    pub fn masked(&self) -> String {
+        let (local, domain) = self.0.split_once('@').unwrap_or((&self.0, ""));
+        let first = local.chars().next().unwrap_or_default();
+        format!("{}***@{}", first, domain)
+    }
+
+    /// Serialize as [`masked`](Self::masked), for logs and admin views; opt in with
+    /// `#[serde(serialize_with = "Email::serialize_masked")]`
+    pub fn serialize_masked<S: Serializer>(
+        email: &Email,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&email.masked())
+    }
 }
 
 impl AsRef<str> for Email {
@@ -171,6 +252,139 @@ impl<'de> Deserialize<'de> for Password {
 
 // Note: Password should NOT implement Serialize to prevent accidental exposure
 
+// ============================================================================
+// DisplayName
+// ============================================================================
+
+/// A human-readable name shown in place of the email address.
+///
+/// Normalization: surrounding whitespace is trimmed, internal whitespace runs are
+/// collapsed to a single space and the text is converted to Unicode NFC. Case is
+/// preserved.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DisplayName(String);
+
+/// Maximum length of a display name, in characters
+pub const MAX_DISPLAY_NAME_LENGTH: usize = 100;
+
+impl DisplayName {
+    pub fn new(value: impl AsRef<str>) -> Result<Self, ValidationError> {
+        let collapsed = value
+            .as_ref()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let normalized: String = collapsed.nfc().collect();
+
+        if normalized.is_empty() {
+            return Err(ValidationError::EmptyDisplayName);
+        }
+
+        let length = normalized.chars().count();
+        if length > MAX_DISPLAY_NAME_LENGTH {
+            return Err(ValidationError::DisplayNameTooLong {
+                max: MAX_DISPLAY_NAME_LENGTH,
+                actual: length,
+            });
+        }
+
+        Ok(Self(normalized))
+    }
+
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+impl AsRef<str> for DisplayName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for DisplayName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl TryFrom<String> for DisplayName {
+    type Error = ValidationError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+impl TryFrom<&str> for DisplayName {
+    type Error = ValidationError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+impl Serialize for DisplayName {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for DisplayName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Self::new(s).map_err(serde::de::Error::custom)
+    }
+}
+
+// ============================================================================
+// Role
+// ============================================================================
+
+/// Authorization role of a user
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Admin => "admin",
+        }
+    }
+
+    /// Whether this role grants at least the access of `required`
+    pub fn satisfies(&self, required: Role) -> bool {
+        match required {
+            Role::User => true,
+            Role::Admin => *self == Role::Admin,
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = ValidationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(Role::User),
+            "admin" => Ok(Role::Admin),
+            other => Err(ValidationError::InvalidRole(other.to_string())),
+        }
+    }
+}
+
 // ============================================================================
 // Tests
 // ============================================================================
@@ -214,6 +428,127 @@ mod tests {
         fn test_invalid_email_no_dot_in_domain() {
             assert!(Email::new("user@localhost").is_err());
         }
+
+        #[test]
+        fn test_overlong_email_is_rejected() {
+            let domain = format!("{}.com", "d".repeat(250));
+            assert_eq!(
+                Email::new(format!("user@{}", domain)),
+                Err(ValidationError::EmailTooLong {
+                    max: MAX_EMAIL_LENGTH,
+                    actual: 259
+                })
+            );
+
+            let local = "l".repeat(MAX_EMAIL_LOCAL_LENGTH + 1);
+            assert!(matches!(
+                Email::new(format!("{}@example.com", local)),
+                Err(ValidationError::InvalidEmail(_))
+            ));
+            let local = "l".repeat(MAX_EMAIL_LOCAL_LENGTH);
+            assert!(Email::new(format!("{}@example.com", local)).is_ok());
+        }
+
+        #[test]
+        fn test_masked_keeps_first_character_and_domain() {
+            let email = Email::new("jonathan@example.com").unwrap();
+            assert_eq!(email.masked(), "j***@example.com");
+
+            let email = Email::new("j@example.com").unwrap();
+            assert_eq!(email.masked(), "j***@example.com");
+
+            let email = Email::new("\u{e9}mile@example.com").unwrap();
+            assert_eq!(email.masked(), "\u{e9}***@example.com");
+        }
+
+        #[test]
+        fn test_serialization_is_unmasked_unless_opted_in() {
+            #[derive(Serialize)]
+            struct Entry {
+                #[serde(serialize_with = "Email::serialize_masked")]
+                email: Email,
+            }
+
+            let email = Email::new("jonathan@example.com").unwrap();
+            assert_eq!(
+                serde_json::to_value(&email).unwrap(),
+                "jonathan@example.com"
+            );
+            assert_eq!(
+                serde_json::to_value(Entry { email }).unwrap(),
+                serde_json::json!({ "email": "j***@example.com" })
+            );
+        }
+
+        #[test]
+        fn test_email_unicode_forms_are_equal() {
+            let composed = Email::new("jos\u{e9}@example.com").unwrap();
+            let decomposed = Email::new("jose\u{301}@example.com").unwrap();
+            assert_eq!(composed, decomposed);
+        }
+    }
+
+    mod display_name_tests {
+        use super::*;
+
+        #[test]
+        fn test_display_name_normalizes_whitespace() {
+            let name = DisplayName::new("  Ada \t  Lovelace\n").unwrap();
+            assert_eq!(name.as_ref(), "Ada Lovelace");
+        }
+
+        #[test]
+        fn test_display_name_keeps_case() {
+            let name = DisplayName::new("McDonald").unwrap();
+            assert_eq!(name.as_ref(), "McDonald");
+        }
+
+        #[test]
+        fn test_display_name_unicode_forms_are_equal() {
+            let composed = DisplayName::new("jos\u{e9}").unwrap();
+            let decomposed = DisplayName::new("jose\u{301}").unwrap();
+            assert_eq!(composed, decomposed);
+            assert_eq!(decomposed.as_ref(), "jos\u{e9}");
+        }
+
+        #[test]
+        fn test_invalid_display_name() {
+            assert_eq!(
+                DisplayName::new("   "),
+                Err(ValidationError::EmptyDisplayName)
+            );
+            assert_eq!(
+                DisplayName::new("\u{e9}".repeat(MAX_DISPLAY_NAME_LENGTH + 1)),
+                Err(ValidationError::DisplayNameTooLong {
+                    max: MAX_DISPLAY_NAME_LENGTH,
+                    actual: MAX_DISPLAY_NAME_LENGTH + 1
+                })
+            );
+        }
+    }
+
+    mod role_tests {
+        use super::*;
+
+        #[test]
+        fn test_role_round_trip() {
+            for role in [Role::User, Role::Admin] {
+                assert_eq!(role.as_str().parse::<Role>().unwrap(), role);
+            }
+        }
+
+        #[test]
+        fn test_role_satisfies() {
+            assert!(Role::Admin.satisfies(Role::Admin));
+            assert!(Role::Admin.satisfies(Role::User));
+            assert!(Role::User.satisfies(Role::User));
+            assert!(!Role::User.satisfies(Role::Admin));
+        }
+
+        #[test]
+        fn test_invalid_role() {
+            assert!("superuser".parse::<Role>().is_err());
+        }
     }
 
     mod password_tests {
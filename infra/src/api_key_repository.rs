@@ -0,0 +1,544 @@
+//! SQLite and PostgreSQL implementations of ApiKeyRepository
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::datetime::{format_db_datetime, parse_db_datetime, parse_optional_db_datetime};
+#[cfg(feature = "postgres")]
+use crate::transaction::PostgresTransaction;
+#[cfg(feature = "sqlite")]
+use crate::transaction::SqliteTransaction;
+use domain::{ApiKey, ApiKeyRepository, DomainError, DomainResult, Transaction};
+
+/// SQLite adapter for ApiKeyRepository
+#[cfg(feature = "sqlite")]
+#[derive(Clone)]
+pub struct SqliteApiKeyRepository {
+    pool: sqlx::SqlitePool,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteApiKeyRepository {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+/// Row type for API key query results
+#[derive(Debug, FromRow)]
+struct ApiKeyRow {
+    id: String,
+    user_id: String,
+    key_hash: String,
+    created_at: String,
+    expires_at: Option<String>,
+    last_used_at: Option<String>,
+}
+
+fn parse_uuid(value: &str) -> DomainResult<Uuid> {
+    Uuid::parse_str(value).map_err(|e| DomainError::RepositoryError(format!("Invalid UUID: {}", e)))
+}
+
+impl TryFrom<ApiKeyRow> for ApiKey {
+    type Error = DomainError;
+
+    fn try_from(row: ApiKeyRow) -> Result<Self, Self::Error> {
+        Ok(ApiKey {
+            id: parse_uuid(&row.id)?,
+            user_id: parse_uuid(&row.user_id)?,
+            key_hash: row.key_hash,
+            created_at: parse_db_datetime(&row.created_at)?,
+            expires_at: parse_optional_db_datetime(row.expires_at.as_deref())?,
+            last_used_at: parse_optional_db_datetime(row.last_used_at.as_deref())?,
+        })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl ApiKeyRepository for SqliteApiKeyRepository {
+    async fn find_by_id(&self, id: Uuid) -> DomainResult<Option<ApiKey>> {
+        let row: Option<ApiKeyRow> = sqlx::query_as(
+            "SELECT id, user_id, key_hash, created_at, expires_at, last_used_at FROM api_keys WHERE id = ?",
+        )
+        .bind(id.to_string())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        row.map(ApiKey::try_from).transpose()
+    }
+
+    async fn find_by_hash(&self, key_hash: &str) -> DomainResult<Option<ApiKey>> {
+        let row: Option<ApiKeyRow> = sqlx::query_as(
+            "SELECT id, user_id, key_hash, created_at, expires_at, last_used_at FROM api_keys WHERE key_hash = ?",
+        )
+        .bind(key_hash)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        row.map(ApiKey::try_from).transpose()
+    }
+
+    async fn find_by_user(&self, user_id: Uuid) -> DomainResult<Vec<ApiKey>> {
+        let rows: Vec<ApiKeyRow> = sqlx::query_as(
+            "SELECT id, user_id, key_hash, created_at, expires_at, last_used_at FROM api_keys WHERE user_id = ? ORDER BY created_at",
+        )
+        .bind(user_id.to_string())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        rows.into_iter().map(ApiKey::try_from).collect()
+    }
+
+    async fn save(&self, key: &ApiKey) -> DomainResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO api_keys (id, user_id, key_hash, created_at, expires_at, last_used_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT(id) DO UPDATE SET
+                expires_at = excluded.expires_at,
+                last_used_at = excluded.last_used_at
+            "#,
+        )
+        .bind(key.id.to_string())
+        .bind(key.user_id.to_string())
+        .bind(&key.key_hash)
+        .bind(format_db_datetime(&key.created_at))
+        .bind(key.expires_at.as_ref().map(format_db_datetime))
+        .bind(key.last_used_at.as_ref().map(format_db_datetime))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn insert_within_limit(
+        &self,
+        key: &ApiKey,
+        max: u32,
+        now: DateTime<Utc>,
+    ) -> DomainResult<bool> {
+        // One statement: SQLite takes the write lock before counting
+        let result = sqlx::query(
+            r#"
+            INSERT INTO api_keys (id, user_id, key_hash, created_at, expires_at, last_used_at)
+            SELECT ?, ?, ?, ?, ?, ?
+            WHERE (
+                SELECT COUNT(*) FROM api_keys
+                WHERE user_id = ? AND (expires_at IS NULL OR julianday(expires_at) > julianday(?))
+            ) < ?
+            "#,
+        )
+        .bind(key.id.to_string())
+        .bind(key.user_id.to_string())
+        .bind(&key.key_hash)
+        .bind(format_db_datetime(&key.created_at))
+        .bind(key.expires_at.as_ref().map(format_db_datetime))
+        .bind(key.last_used_at.as_ref().map(format_db_datetime))
+        .bind(key.user_id.to_string())
+        .bind(format_db_datetime(&now))
+        .bind(i64::from(max))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn delete(&self, id: Uuid) -> DomainResult<()> {
+        sqlx::query("DELETE FROM api_keys WHERE id = ?")
+            .bind(id.to_string())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn reassign_in(
+        &self,
+        tx: &mut dyn Transaction,
+        from: Uuid,
+        to: Uuid,
+        max: Option<u32>,
+        now: DateTime<Utc>,
+    ) -> DomainResult<bool> {
+        let tx = &mut SqliteTransaction::from_dyn(tx)?.0;
+        if let Some(max) = max {
+            let active: i64 = sqlx::query_scalar(
+                r#"
+                SELECT COUNT(*) FROM api_keys
+                WHERE user_id IN (?, ?)
+                    AND (expires_at IS NULL OR julianday(expires_at) > julianday(?))
+                "#,
+            )
+            .bind(from.to_string())
+            .bind(to.to_string())
+            .bind(format_db_datetime(&now))
+            .fetch_one(&mut **tx)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+            if active > i64::from(max) {
+                return Ok(false);
+            }
+        }
+
+        sqlx::query("UPDATE api_keys SET user_id = ? WHERE user_id = ?")
+            .bind(to.to_string())
+            .bind(from.to_string())
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(true)
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::SqliteUserRepository;
+    use crate::db::run_migrations;
+    use chrono::Utc;
+    use domain::{Email, User, UserRepository};
+    use k_core::db::{DatabaseConfig, DatabasePool, connect};
+
+    async fn setup_test_db() -> sqlx::SqlitePool {
+        let config = DatabaseConfig::default();
+        let db_pool = connect(&config).await.expect("Failed to create pool");
+
+        run_migrations(&db_pool).await.unwrap();
+
+        match db_pool {
+            DatabasePool::Sqlite(pool) => pool,
+        }
+    }
+
+    async fn create_user(pool: &sqlx::SqlitePool) -> User {
+        let user = User::new("test|key", Email::try_from("key@example.com").unwrap()).unwrap();
+        SqliteUserRepository::new(pool.clone())
+            .save(&user)
+            .await
+            .unwrap();
+        user
+    }
+
+    #[tokio::test]
+    async fn test_save_and_find_by_hash() {
+        let pool = setup_test_db().await;
+        let user = create_user(&pool).await;
+        let repo = SqliteApiKeyRepository::new(pool);
+
+        let (key, raw_key) = ApiKey::generate(user.id, None);
+        repo.save(&key).await.unwrap();
+
+        let found = repo
+            .find_by_hash(&ApiKey::hash_key(&raw_key))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.id, key.id);
+        assert_eq!(found.user_id, user.id);
+        assert!(found.expires_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_list_and_delete() {
+        let pool = setup_test_db().await;
+        let user = create_user(&pool).await;
+        let repo = SqliteApiKeyRepository::new(pool);
+
+        let (first, _) = ApiKey::generate(user.id, None);
+        let (second, _) = ApiKey::generate(user.id, Some(Utc::now()));
+        repo.save(&first).await.unwrap();
+        repo.save(&second).await.unwrap();
+
+        assert_eq!(repo.find_by_user(user.id).await.unwrap().len(), 2);
+
+        repo.delete(first.id).await.unwrap();
+        let remaining = repo.find_by_user(user.id).await.unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].id, second.id);
+    }
+
+    #[tokio::test]
+    async fn test_insert_within_limit_skips_expired_keys() {
+        let pool = setup_test_db().await;
+        let user = create_user(&pool).await;
+        let repo = SqliteApiKeyRepository::new(pool);
+        let now = Utc::now();
+
+        let (expired, _) = ApiKey::generate(user.id, Some(now - chrono::Duration::hours(1)));
+        repo.save(&expired).await.unwrap();
+        let (first, _) = ApiKey::generate(user.id, None);
+        let (second, _) = ApiKey::generate(user.id, Some(now + chrono::Duration::hours(1)));
+        assert!(repo.insert_within_limit(&first, 2, now).await.unwrap());
+        assert!(repo.insert_within_limit(&second, 2, now).await.unwrap());
+
+        let (third, _) = ApiKey::generate(user.id, None);
+        assert!(!repo.insert_within_limit(&third, 2, now).await.unwrap());
+        assert!(repo.find_by_id(third.id).await.unwrap().is_none());
+    }
+}
+
+/// PostgreSQL adapter for ApiKeyRepository
+#[cfg(feature = "postgres")]
+#[derive(Clone)]
+pub struct PostgresApiKeyRepository {
+    pool: sqlx::Pool<sqlx::Postgres>,
+}
+
+#[cfg(feature = "postgres")]
+impl PostgresApiKeyRepository {
+    pub fn new(pool: sqlx::Pool<sqlx::Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[cfg(feature = "postgres")]
+#[async_trait]
+impl ApiKeyRepository for PostgresApiKeyRepository {
+    async fn find_by_id(&self, id: Uuid) -> DomainResult<Option<ApiKey>> {
+        let row: Option<ApiKeyRow> = sqlx::query_as(
+            "SELECT id, user_id::text AS user_id, key_hash, created_at, expires_at, last_used_at FROM api_keys WHERE id = $1",
+        )
+        .bind(id.to_string())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        row.map(ApiKey::try_from).transpose()
+    }
+
+    async fn find_by_hash(&self, key_hash: &str) -> DomainResult<Option<ApiKey>> {
+        let row: Option<ApiKeyRow> = sqlx::query_as(
+            "SELECT id, user_id::text AS user_id, key_hash, created_at, expires_at, last_used_at FROM api_keys WHERE key_hash = $1",
+        )
+        .bind(key_hash)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        row.map(ApiKey::try_from).transpose()
+    }
+
+    async fn find_by_user(&self, user_id: Uuid) -> DomainResult<Vec<ApiKey>> {
+        let rows: Vec<ApiKeyRow> = sqlx::query_as(
+            "SELECT id, user_id::text AS user_id, key_hash, created_at, expires_at, last_used_at FROM api_keys WHERE user_id = $1::uuid ORDER BY created_at",
+        )
+        .bind(user_id.to_string())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        rows.into_iter().map(ApiKey::try_from).collect()
+    }
+
+    async fn save(&self, key: &ApiKey) -> DomainResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO api_keys (id, user_id, key_hash, created_at, expires_at, last_used_at)
+            VALUES ($1, $2::uuid, $3, $4, $5, $6)
+            ON CONFLICT(id) DO UPDATE SET
+                expires_at = excluded.expires_at,
+                last_used_at = excluded.last_used_at
+            "#,
+        )
+        .bind(key.id.to_string())
+        .bind(key.user_id.to_string())
+        .bind(&key.key_hash)
+        .bind(format_db_datetime(&key.created_at))
+        .bind(key.expires_at.as_ref().map(format_db_datetime))
+        .bind(key.last_used_at.as_ref().map(format_db_datetime))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn insert_within_limit(
+        &self,
+        key: &ApiKey,
+        max: u32,
+        now: DateTime<Utc>,
+    ) -> DomainResult<bool> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+        // Under read committed two counts could both pass; locking the owner
+        // makes concurrent inserts for one user take turns
+        sqlx::query("SELECT 1 FROM users WHERE id = $1::uuid FOR UPDATE")
+            .bind(key.user_id.to_string())
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO api_keys (id, user_id, key_hash, created_at, expires_at, last_used_at)
+            SELECT $1, $2::uuid, $3, $4, $5, $6
+            WHERE (
+                SELECT COUNT(*) FROM api_keys
+                WHERE user_id = $2::uuid
+                    AND (expires_at IS NULL OR expires_at::timestamptz > $7::timestamptz)
+            ) < $8
+            "#,
+        )
+        .bind(key.id.to_string())
+        .bind(key.user_id.to_string())
+        .bind(&key.key_hash)
+        .bind(format_db_datetime(&key.created_at))
+        .bind(key.expires_at.as_ref().map(format_db_datetime))
+        .bind(key.last_used_at.as_ref().map(format_db_datetime))
+        .bind(format_db_datetime(&now))
+        .bind(i64::from(max))
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        tx.commit()
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn delete(&self, id: Uuid) -> DomainResult<()> {
+        sqlx::query("DELETE FROM api_keys WHERE id = $1")
+            .bind(id.to_string())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn reassign_in(
+        &self,
+        tx: &mut dyn Transaction,
+        from: Uuid,
+        to: Uuid,
+        max: Option<u32>,
+        now: DateTime<Utc>,
+    ) -> DomainResult<bool> {
+        let tx = &mut PostgresTransaction::from_dyn(tx)?.0;
+        if let Some(max) = max {
+            // The same lock `insert_within_limit` takes, so a key created for
+            // `to` meanwhile is counted
+            sqlx::query("SELECT 1 FROM users WHERE id = $1::uuid FOR UPDATE")
+                .bind(to.to_string())
+                .execute(&mut **tx)
+                .await
+                .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+            let active: i64 = sqlx::query_scalar(
+                r#"
+                SELECT COUNT(*) FROM api_keys
+                WHERE user_id IN ($1::uuid, $2::uuid)
+                    AND (expires_at IS NULL OR expires_at::timestamptz > $3::timestamptz)
+                "#,
+            )
+            .bind(from.to_string())
+            .bind(to.to_string())
+            .bind(format_db_datetime(&now))
+            .fetch_one(&mut **tx)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+            if active > i64::from(max) {
+                return Ok(false);
+            }
+        }
+
+        sqlx::query("UPDATE api_keys SET user_id = $1::uuid WHERE user_id = $2::uuid")
+            .bind(to.to_string())
+            .bind(from.to_string())
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(true)
+    }
+}
+
+#[cfg(all(test, feature = "postgres"))]
+mod postgres_tests {
+    use super::*;
+    use crate::db::{ConnectionSettings, create_pool, run_migrations};
+    use crate::user_repository::PostgresUserRepository;
+    use domain::{Email, User, UserRepository};
+    use k_core::db::{DatabaseConfig, DatabasePool};
+    use std::time::Duration;
+
+    /// Needs a disposable database: `TEST_POSTGRES_URL=postgres://... cargo test --features postgres`
+    #[tokio::test]
+    async fn test_concurrent_inserts_stay_within_limit() {
+        let Ok(url) = std::env::var("TEST_POSTGRES_URL") else {
+            return;
+        };
+        let config = DatabaseConfig {
+            url,
+            max_connections: 5,
+            min_connections: 1,
+            acquire_timeout: Duration::from_secs(30),
+        };
+        let pool = create_pool(config, &ConnectionSettings::default())
+            .await
+            .unwrap();
+        run_migrations(&pool).await.unwrap();
+        let pool = match pool {
+            DatabasePool::Postgres(pool) => pool,
+            #[allow(unreachable_patterns)]
+            _ => panic!("expected a Postgres pool"),
+        };
+
+        let tag = Uuid::new_v4().simple().to_string();
+        let email = Email::try_from(format!("keys-{tag}@example.com")).unwrap();
+        let user = User::new(format!("oidc|{tag}"), email).unwrap();
+        let users = PostgresUserRepository::new(pool.clone());
+        users.save(&user).await.unwrap();
+        let repo = PostgresApiKeyRepository::new(pool);
+
+        let attempts = (0..5).map(|_| {
+            let repo = repo.clone();
+            let (key, _) = ApiKey::generate(user.id, None);
+            tokio::spawn(async move { repo.insert_within_limit(&key, 2, Utc::now()).await })
+        });
+        let mut inserted = 0;
+        for attempt in attempts.collect::<Vec<_>>() {
+            inserted += attempt.await.unwrap().unwrap() as usize;
+        }
+        assert_eq!(inserted, 2);
+        assert_eq!(repo.find_by_user(user.id).await.unwrap().len(), 2);
+
+        // Reassigning counts against the same limit
+        let email = Email::try_from(format!("dup-{tag}@example.com")).unwrap();
+        let duplicate = User::new(format!("other|{tag}"), email).unwrap();
+        users.save(&duplicate).await.unwrap();
+        let (key, _) = ApiKey::generate(duplicate.id, None);
+        repo.save(&key).await.unwrap();
+        let mut tx = users.begin().await.unwrap();
+        let moved = repo
+            .reassign_in(tx.as_mut(), duplicate.id, user.id, Some(2), Utc::now())
+            .await
+            .unwrap();
+        assert!(!moved);
+        let moved = repo
+            .reassign_in(tx.as_mut(), duplicate.id, user.id, Some(3), Utc::now())
+            .await
+            .unwrap();
+        assert!(moved);
+        tx.commit().await.unwrap();
+        assert_eq!(repo.find_by_user(user.id).await.unwrap().len(), 3);
+
+        // Keys go with their owner
+        users.delete(user.id).await.unwrap();
+        assert!(repo.find_by_user(user.id).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,450 @@
+//! SQLite and PostgreSQL implementations of AuditLogRepository
+
+use async_trait::async_trait;
+use sqlx::{FromRow, QueryBuilder};
+use uuid::Uuid;
+
+use crate::datetime::{format_db_datetime, parse_db_datetime};
+#[cfg(feature = "postgres")]
+use crate::transaction::PostgresTransaction;
+#[cfg(feature = "sqlite")]
+use crate::transaction::SqliteTransaction;
+use domain::{
+    AuditEntry, AuditLogFilter, AuditLogRepository, DomainError, DomainResult, Transaction,
+};
+
+/// SQLite adapter for AuditLogRepository
+#[cfg(feature = "sqlite")]
+#[derive(Clone)]
+pub struct SqliteAuditLogRepository {
+    pool: sqlx::SqlitePool,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteAuditLogRepository {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+/// Row type for audit log query results
+#[derive(Debug, FromRow)]
+struct AuditEntryRow {
+    id: String,
+    actor_id: String,
+    action: String,
+    target_id: Option<String>,
+    created_at: String,
+}
+
+fn parse_uuid(value: &str) -> DomainResult<Uuid> {
+    Uuid::parse_str(value).map_err(|e| DomainError::RepositoryError(format!("Invalid UUID: {}", e)))
+}
+
+/// Columns selected for [`AuditEntryRow`]
+const AUDIT_COLUMNS: &str = "id, actor_id, action, target_id, created_at";
+
+impl TryFrom<AuditEntryRow> for AuditEntry {
+    type Error = DomainError;
+
+    fn try_from(row: AuditEntryRow) -> Result<Self, Self::Error> {
+        let created_at = parse_db_datetime(&row.created_at)?;
+        let action = row
+            .action
+            .parse()
+            .map_err(|e| DomainError::RepositoryError(format!("Invalid audit action: {}", e)))?;
+
+        Ok(AuditEntry {
+            id: parse_uuid(&row.id)?,
+            actor_id: parse_uuid(&row.actor_id)?,
+            action,
+            target_id: row.target_id.as_deref().map(parse_uuid).transpose()?,
+            created_at,
+        })
+    }
+}
+
+/// Append `WHERE` conditions for `filter` to a SQLite query
+#[cfg(feature = "sqlite")]
+fn push_sqlite_filters(query: &mut QueryBuilder<'_, sqlx::Sqlite>, filter: &AuditLogFilter) {
+    query.push(" WHERE 1 = 1");
+    if let Some(user_id) = filter.user_id {
+        query
+            .push(" AND (actor_id = ")
+            .push_bind(user_id.to_string())
+            .push(" OR target_id = ")
+            .push_bind(user_id.to_string())
+            .push(")");
+    }
+    if let Some(action) = filter.action {
+        query.push(" AND action = ").push_bind(action.as_str());
+    }
+    if let Some(from) = filter.from {
+        query
+            .push(" AND julianday(created_at) >= julianday(")
+            .push_bind(format_db_datetime(&from))
+            .push(")");
+    }
+    if let Some(to) = filter.to {
+        query
+            .push(" AND julianday(created_at) < julianday(")
+            .push_bind(format_db_datetime(&to))
+            .push(")");
+    }
+}
+
+/// Insert an entry using any Sqlite executor, so it can join a caller's transaction
+#[cfg(feature = "sqlite")]
+async fn insert_sqlite<'e, E>(executor: E, entry: &AuditEntry) -> Result<(), sqlx::Error>
+where
+    E: sqlx::SqliteExecutor<'e>,
+{
+    sqlx::query(
+        "INSERT INTO audit_log (id, actor_id, action, target_id, created_at) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(entry.id.to_string())
+    .bind(entry.actor_id.to_string())
+    .bind(entry.action.as_str())
+    .bind(entry.target_id.map(|id| id.to_string()))
+    .bind(format_db_datetime(&entry.created_at))
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl AuditLogRepository for SqliteAuditLogRepository {
+    async fn record(&self, entry: &AuditEntry) -> DomainResult<()> {
+        insert_sqlite(&self.pool, entry)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))
+    }
+
+    async fn record_in(&self, tx: &mut dyn Transaction, entry: &AuditEntry) -> DomainResult<()> {
+        let tx = &mut SqliteTransaction::from_dyn(tx)?.0;
+        insert_sqlite(&mut **tx, entry)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))
+    }
+
+    async fn find_by_actor(&self, actor_id: Uuid) -> DomainResult<Vec<AuditEntry>> {
+        let rows: Vec<AuditEntryRow> = sqlx::query_as(&format!(
+            "SELECT {} FROM audit_log WHERE actor_id = ? ORDER BY created_at DESC",
+            AUDIT_COLUMNS
+        ))
+        .bind(actor_id.to_string())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        rows.into_iter().map(AuditEntry::try_from).collect()
+    }
+
+    async fn search(
+        &self,
+        filter: &AuditLogFilter,
+        limit: u32,
+        offset: u32,
+    ) -> DomainResult<Vec<AuditEntry>> {
+        let mut query =
+            QueryBuilder::<sqlx::Sqlite>::new(format!("SELECT {} FROM audit_log", AUDIT_COLUMNS));
+        push_sqlite_filters(&mut query, filter);
+        query
+            .push(" ORDER BY julianday(created_at) DESC, id LIMIT ")
+            .push_bind(i64::from(limit))
+            .push(" OFFSET ")
+            .push_bind(i64::from(offset));
+
+        let rows: Vec<AuditEntryRow> = query
+            .build_query_as()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        rows.into_iter().map(AuditEntry::try_from).collect()
+    }
+
+    async fn count(&self, filter: &AuditLogFilter) -> DomainResult<u64> {
+        let mut query = QueryBuilder::<sqlx::Sqlite>::new("SELECT COUNT(*) FROM audit_log");
+        push_sqlite_filters(&mut query, filter);
+
+        let count: i64 = query
+            .build_query_scalar()
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(count as u64)
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::db::run_migrations;
+    use chrono::{Duration, Utc};
+    use domain::AuditAction;
+    use k_core::db::{DatabaseConfig, DatabasePool, connect};
+
+    async fn setup_test_db() -> sqlx::SqlitePool {
+        let config = DatabaseConfig::default();
+        let db_pool = connect(&config).await.expect("Failed to create pool");
+
+        run_migrations(&db_pool).await.unwrap();
+
+        match db_pool {
+            DatabasePool::Sqlite(pool) => pool,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_record_and_find_by_actor() {
+        let pool = setup_test_db().await;
+        let repo = SqliteAuditLogRepository::new(pool);
+
+        let admin_id = Uuid::new_v4();
+        let target_id = Uuid::new_v4();
+        let entry = AuditEntry::new(admin_id, AuditAction::ImpersonationStarted, Some(target_id));
+        repo.record(&entry).await.unwrap();
+
+        let entries = repo.find_by_actor(admin_id).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].action, AuditAction::ImpersonationStarted);
+        assert_eq!(entries[0].target_id, Some(target_id));
+
+        assert!(repo.find_by_actor(target_id).await.unwrap().is_empty());
+    }
+
+    /// Record an entry with an explicit timestamp, `minutes_ago` before now
+    async fn seed(
+        repo: &SqliteAuditLogRepository,
+        actor_id: Uuid,
+        action: AuditAction,
+        target_id: Option<Uuid>,
+        minutes_ago: i64,
+    ) -> AuditEntry {
+        let mut entry = AuditEntry::new(actor_id, action, target_id);
+        entry.created_at = Utc::now() - Duration::minutes(minutes_ago);
+        repo.record(&entry).await.unwrap();
+        entry
+    }
+
+    #[tokio::test]
+    async fn test_search_with_combined_filters() {
+        let pool = setup_test_db().await;
+        let repo = SqliteAuditLogRepository::new(pool);
+
+        let admin = Uuid::new_v4();
+        let user = Uuid::new_v4();
+        let other = Uuid::new_v4();
+        seed(
+            &repo,
+            admin,
+            AuditAction::ImpersonationStarted,
+            Some(user),
+            50,
+        )
+        .await;
+        let match_recent = seed(
+            &repo,
+            admin,
+            AuditAction::ImpersonationStarted,
+            Some(user),
+            10,
+        )
+        .await;
+        seed(
+            &repo,
+            admin,
+            AuditAction::ImpersonationStopped,
+            Some(user),
+            9,
+        )
+        .await;
+        seed(
+            &repo,
+            admin,
+            AuditAction::ImpersonationStarted,
+            Some(other),
+            8,
+        )
+        .await;
+
+        let filter = AuditLogFilter {
+            user_id: Some(user),
+            action: Some(AuditAction::ImpersonationStarted),
+            from: Some(Utc::now() - Duration::minutes(30)),
+            to: Some(Utc::now()),
+        };
+
+        let entries = repo.search(&filter, 10, 0).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].id, match_recent.id);
+        assert_eq!(repo.count(&filter).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_search_paginates_newest_first() {
+        let pool = setup_test_db().await;
+        let repo = SqliteAuditLogRepository::new(pool);
+
+        let admin = Uuid::new_v4();
+        let mut seeded = Vec::new();
+        for minutes_ago in 1..=5 {
+            seeded.push(
+                seed(
+                    &repo,
+                    admin,
+                    AuditAction::UserSoftDeleted,
+                    None,
+                    minutes_ago,
+                )
+                .await,
+            );
+        }
+
+        let filter = AuditLogFilter::default();
+        let first = repo.search(&filter, 2, 0).await.unwrap();
+        let last = repo.search(&filter, 2, 4).await.unwrap();
+
+        assert_eq!(repo.count(&filter).await.unwrap(), 5);
+        assert_eq!(
+            first.iter().map(|e| e.id).collect::<Vec<_>>(),
+            vec![seeded[0].id, seeded[1].id]
+        );
+        assert_eq!(last.len(), 1);
+        assert_eq!(last[0].id, seeded[4].id);
+    }
+}
+
+/// PostgreSQL adapter for AuditLogRepository
+#[cfg(feature = "postgres")]
+#[derive(Clone)]
+pub struct PostgresAuditLogRepository {
+    pool: sqlx::Pool<sqlx::Postgres>,
+}
+
+#[cfg(feature = "postgres")]
+impl PostgresAuditLogRepository {
+    pub fn new(pool: sqlx::Pool<sqlx::Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+/// Append `WHERE` conditions for `filter` to a Postgres query
+#[cfg(feature = "postgres")]
+fn push_postgres_filters(query: &mut QueryBuilder<'_, sqlx::Postgres>, filter: &AuditLogFilter) {
+    query.push(" WHERE 1 = 1");
+    if let Some(user_id) = filter.user_id {
+        query
+            .push(" AND (actor_id = ")
+            .push_bind(user_id.to_string())
+            .push(" OR target_id = ")
+            .push_bind(user_id.to_string())
+            .push(")");
+    }
+    if let Some(action) = filter.action {
+        query.push(" AND action = ").push_bind(action.as_str());
+    }
+    if let Some(from) = filter.from {
+        query
+            .push(" AND created_at::timestamptz >= ")
+            .push_bind(format_db_datetime(&from))
+            .push("::timestamptz");
+    }
+    if let Some(to) = filter.to {
+        query
+            .push(" AND created_at::timestamptz < ")
+            .push_bind(format_db_datetime(&to))
+            .push("::timestamptz");
+    }
+}
+
+/// Insert an entry using any Postgres executor, so it can join a caller's transaction
+#[cfg(feature = "postgres")]
+async fn insert_postgres<'e, E>(executor: E, entry: &AuditEntry) -> Result<(), sqlx::Error>
+where
+    E: sqlx::PgExecutor<'e>,
+{
+    sqlx::query(
+        "INSERT INTO audit_log (id, actor_id, action, target_id, created_at) VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(entry.id.to_string())
+    .bind(entry.actor_id.to_string())
+    .bind(entry.action.as_str())
+    .bind(entry.target_id.map(|id| id.to_string()))
+    .bind(format_db_datetime(&entry.created_at))
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(feature = "postgres")]
+#[async_trait]
+impl AuditLogRepository for PostgresAuditLogRepository {
+    async fn record(&self, entry: &AuditEntry) -> DomainResult<()> {
+        insert_postgres(&self.pool, entry)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))
+    }
+
+    async fn record_in(&self, tx: &mut dyn Transaction, entry: &AuditEntry) -> DomainResult<()> {
+        let tx = &mut PostgresTransaction::from_dyn(tx)?.0;
+        insert_postgres(&mut **tx, entry)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))
+    }
+
+    async fn find_by_actor(&self, actor_id: Uuid) -> DomainResult<Vec<AuditEntry>> {
+        let rows: Vec<AuditEntryRow> = sqlx::query_as(&format!(
+            "SELECT {} FROM audit_log WHERE actor_id = $1 ORDER BY created_at DESC",
+            AUDIT_COLUMNS
+        ))
+        .bind(actor_id.to_string())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        rows.into_iter().map(AuditEntry::try_from).collect()
+    }
+
+    async fn search(
+        &self,
+        filter: &AuditLogFilter,
+        limit: u32,
+        offset: u32,
+    ) -> DomainResult<Vec<AuditEntry>> {
+        let mut query =
+            QueryBuilder::<sqlx::Postgres>::new(format!("SELECT {} FROM audit_log", AUDIT_COLUMNS));
+        push_postgres_filters(&mut query, filter);
+        query
+            .push(" ORDER BY created_at::timestamptz DESC, id LIMIT ")
+            .push_bind(i64::from(limit))
+            .push(" OFFSET ")
+            .push_bind(i64::from(offset));
+
+        let rows: Vec<AuditEntryRow> = query
+            .build_query_as()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        rows.into_iter().map(AuditEntry::try_from).collect()
+    }
+
+    async fn count(&self, filter: &AuditLogFilter) -> DomainResult<u64> {
+        let mut query = QueryBuilder::<sqlx::Postgres>::new("SELECT COUNT(*) FROM audit_log");
+        push_postgres_filters(&mut query, filter);
+
+        let count: i64 = query
+            .build_query_scalar()
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(count as u64)
+    }
+}
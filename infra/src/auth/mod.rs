@@ -2,50 +2,179 @@
 //!
 //! This module contains the concrete implementation of authentication mechanisms.
 
+#[cfg(feature = "auth-axum-login")]
+pub mod password;
+
 #[cfg(feature = "auth-axum-login")]
 pub mod backend {
-    use std::sync::Arc;
+    use std::sync::{Arc, OnceLock};
 
     use axum_login::{AuthnBackend, UserId};
     use password_auth::verify_password;
-    use serde::{Deserialize, Serialize};
+    use serde::Deserialize;
+    use sha2::{Digest, Sha256};
     use tower_sessions::SessionManagerLayer;
     use uuid::Uuid;
 
-    use domain::{User, UserRepository};
+    use domain::{
+        DomainError, EventPublisher, LoginFailureReason, LoginPolicy, OutboxEvent, User,
+        UserRepository,
+    };
 
+    use super::password::PasswordHashPolicy;
     // We use the same session store as defined in infra
-    use crate::session_store::InfraSessionStore;
+    use crate::session_store::DegradingSessionStore;
+
+    /// What axum-login writes to the session for a logged-in user.
+    ///
+    /// Only the id and a digest of the password hash, never the hash itself.
+    /// The digest changes with the password and so invalidates older sessions.
+    /// Anything else, role included, is read fresh by
+    /// [`AuthBackend::get_user`], so it can't go stale in the session.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct SessionIdentity {
+        pub id: Uuid,
+        pub auth_hash: Vec<u8>,
+    }
+
+    impl From<&User> for SessionIdentity {
+        fn from(user: &User) -> Self {
+            let auth_hash = user
+                .password_hash
+                .as_ref()
+                .map(|hash| Sha256::digest(hash.as_bytes()).to_vec())
+                .unwrap_or_default();
+            Self {
+                id: user.id,
+                auth_hash,
+            }
+        }
+    }
 
-    /// Wrapper around domain User to implement AuthUser
-    #[derive(Debug, Clone, Serialize, Deserialize)]
-    pub struct AuthUser(pub User);
+    /// Wrapper around domain User to implement AuthUser.
+    ///
+    /// The full user lives only in memory, rehydrated by
+    /// [`AuthBackend::get_user`] on each request; the session keeps just what
+    /// [`SessionIdentity`] holds.
+    #[derive(Debug, Clone)]
+    pub struct AuthUser(pub User, SessionIdentity, Claims);
+
+    /// Claims from the OIDC provider, by name
+    pub type Claims = serde_json::Map<String, serde_json::Value>;
+
+    impl AuthUser {
+        pub fn new(user: User) -> Self {
+            let identity = SessionIdentity::from(&user);
+            Self(user, identity, Claims::new())
+        }
+
+        /// Attach the OIDC claims kept in the user's session
+        pub fn with_claims(mut self, claims: Claims) -> Self {
+            self.2 = claims;
+            self
+        }
+
+        pub fn identity(&self) -> &SessionIdentity {
+            &self.1
+        }
+
+        /// OIDC claims kept at login; empty after a password login
+        pub fn claims(&self) -> &Claims {
+            &self.2
+        }
+    }
 
     impl axum_login::AuthUser for AuthUser {
         type Id = Uuid;
 
         fn id(&self) -> Self::Id {
-            self.0.id
+            self.1.id
         }
 
         fn session_auth_hash(&self) -> &[u8] {
-            // Use password hash to invalidate sessions if password changes
-            self.0
-                .password_hash
-                .as_ref()
-                .map(|s| s.as_bytes())
-                .unwrap_or(&[])
+            &self.1.auth_hash
         }
     }
 
     #[derive(Clone)]
     pub struct AuthBackend {
         pub user_repo: Arc<dyn UserRepository>,
+        pub login_policy: LoginPolicy,
+        pub password_policy: PasswordHashPolicy,
+        /// Checked when no account matches, so unknown emails answer as slowly
+        /// as wrong passwords; hashed on first use
+        dummy_hash: Arc<OnceLock<Option<String>>>,
+        /// Told about refused logins
+        events: Option<Arc<dyn EventPublisher>>,
     }
 
     impl AuthBackend {
-        pub fn new(user_repo: Arc<dyn UserRepository>) -> Self {
-            Self { user_repo }
+        pub fn new(
+            user_repo: Arc<dyn UserRepository>,
+            login_policy: LoginPolicy,
+            password_policy: PasswordHashPolicy,
+        ) -> Self {
+            Self {
+                user_repo,
+                login_policy,
+                password_policy,
+                dummy_hash: Arc::default(),
+                events: None,
+            }
+        }
+
+        /// Publish [`OutboxEvent::login_failed`] for every refused login
+        pub fn with_events(mut self, events: Arc<dyn EventPublisher>) -> Self {
+            self.events = Some(events);
+            self
+        }
+
+        /// Report a refused login; the caller answers the same whatever the reason
+        async fn login_failed(&self, email: &str, reason: LoginFailureReason) {
+            let Some(events) = &self.events else {
+                return;
+            };
+            let event = OutboxEvent::login_failed(email, reason);
+            if let Err(e) = events.publish(&event).await {
+                tracing::warn!(event_id = %event.id, "Failed to publish login failure: {}", e);
+            }
+        }
+
+        /// Spend the time a password check would, without a real hash to check
+        fn verify_dummy(&self, password: &str) {
+            let hash = self
+                .dummy_hash
+                .get_or_init(|| self.password_policy.hash("not a real password").ok());
+            if let Some(hash) = hash {
+                let _ = verify_password(password, hash);
+            }
+        }
+
+        /// Upgrade a hash created with weaker parameters.
+        ///
+        /// Failures are logged and do not fail the login.
+        async fn rehash_if_needed(&self, user: &mut User, password: &str) {
+            let weak = user
+                .password_hash
+                .as_deref()
+                .is_some_and(|hash| self.password_policy.needs_rehash(hash));
+            if !self.password_policy.rehash_on_login || !weak {
+                return;
+            }
+
+            let hash = match self.password_policy.hash(password) {
+                Ok(hash) => hash,
+                Err(e) => {
+                    tracing::warn!(user_id = %user.id, "Password rehash failed: {}", e);
+                    return;
+                }
+            };
+
+            let previous = user.password_hash.replace(hash);
+            if let Err(e) = self.user_repo.save(user).await {
+                tracing::warn!(user_id = %user.id, "Failed to store rehashed password: {}", e);
+                user.password_hash = previous;
+            }
         }
     }
 
@@ -53,12 +182,17 @@ pub mod backend {
     pub struct Credentials {
         pub email: String,
         pub password: String,
+        /// Asks for an extended session; the caller sets the expiry after login
+        #[serde(default)]
+        pub remember_me: bool,
     }
 
     #[derive(Debug, thiserror::Error)]
     pub enum AuthError {
         #[error(transparent)]
         Anyhow(#[from] anyhow::Error),
+        #[error(transparent)]
+        Domain(#[from] DomainError),
     }
 
     impl AuthnBackend for AuthBackend {
@@ -70,22 +204,44 @@ pub mod backend {
             &self,
             creds: Self::Credentials,
         ) -> Result<Option<Self::User>, Self::Error> {
+            self.login_policy.check_password_login()?;
+
             let user = self
                 .user_repo
                 .find_by_email(&creds.email)
                 .await
                 .map_err(|e| AuthError::Anyhow(anyhow::anyhow!(e)))?;
 
-            if let Some(user) = user {
-                if let Some(hash) = &user.password_hash {
-                    // Verify password
-                    if verify_password(&creds.password, hash).is_ok() {
-                        return Ok(Some(AuthUser(user)));
-                    }
-                }
+            // Unknown and SSO-only accounts fail like a wrong password, in about as long
+            let Some(mut user) = user else {
+                self.verify_dummy(&creds.password);
+                self.login_failed(&creds.email, LoginFailureReason::UnknownUser)
+                    .await;
+                return Ok(None);
+            };
+            let Some(hash) = user.password_hash.clone() else {
+                self.verify_dummy(&creds.password);
+                self.login_failed(&creds.email, LoginFailureReason::NoPassword)
+                    .await;
+                return Ok(None);
+            };
+
+            if verify_password(&creds.password, &hash).is_err() {
+                self.login_failed(&creds.email, LoginFailureReason::WrongPassword)
+                    .await;
+                return Ok(None);
             }
 
-            Ok(None)
+            if let Err(e) = self.login_policy.check(&user) {
+                let reason = match e {
+                    DomainError::EmailNotVerified(_) => LoginFailureReason::EmailNotVerified,
+                    _ => LoginFailureReason::Inactive,
+                };
+                self.login_failed(&creds.email, reason).await;
+                return Err(e.into());
+            }
+            self.rehash_if_needed(&mut user, &creds.password).await;
+            Ok(Some(AuthUser::new(user)))
         }
 
         async fn get_user(
@@ -98,20 +254,187 @@ pub mod backend {
                 .await
                 .map_err(|e| AuthError::Anyhow(anyhow::anyhow!(e)))?;
 
-            Ok(user.map(AuthUser))
+            // Soft-deleted users lose their sessions
+            Ok(user.filter(|u| !u.is_deleted()).map(AuthUser::new))
         }
     }
 
     pub type AuthSession = axum_login::AuthSession<AuthBackend>;
-    pub type AuthManagerLayer = axum_login::AuthManagerLayer<AuthBackend, InfraSessionStore>;
+    pub type AuthSessionError = axum_login::Error<AuthBackend>;
+    pub type AuthManagerLayer = axum_login::AuthManagerLayer<AuthBackend, DegradingSessionStore>;
 
     pub async fn setup_auth_layer(
-        session_layer: SessionManagerLayer<InfraSessionStore>,
+        session_layer: SessionManagerLayer<DegradingSessionStore>,
         user_repo: Arc<dyn UserRepository>,
+        login_policy: LoginPolicy,
+        password_policy: PasswordHashPolicy,
+        events: Arc<dyn EventPublisher>,
     ) -> Result<AuthManagerLayer, AuthError> {
-        let backend = AuthBackend::new(user_repo);
+        let backend =
+            AuthBackend::new(user_repo, login_policy, password_policy).with_events(events);
 
         let auth_layer = axum_login::AuthManagerLayerBuilder::new(backend, session_layer).build();
         Ok(auth_layer)
     }
+
+    #[cfg(all(test, feature = "sqlite"))]
+    mod tests {
+        use super::*;
+        use crate::SqliteUserRepository;
+        use crate::db::{
+            ConnectionSettings, DatabaseConfig, DatabasePool, create_pool, run_migrations,
+        };
+        use domain::{AuthMode, Email};
+
+        async fn setup_repo() -> Arc<dyn UserRepository> {
+            let db_pool = create_pool(DatabaseConfig::default(), &ConnectionSettings::default())
+                .await
+                .expect("Failed to create pool");
+            run_migrations(&db_pool).await.unwrap();
+
+            match db_pool {
+                DatabasePool::Sqlite(pool) => Arc::new(SqliteUserRepository::new(pool)),
+            }
+        }
+
+        #[tokio::test]
+        async fn test_login_upgrades_weak_hash() {
+            let repo = setup_repo().await;
+            let weak = PasswordHashPolicy {
+                memory_kib: 8,
+                iterations: 1,
+                parallelism: 1,
+                rehash_on_login: true,
+            };
+
+            let mut user =
+                User::new("local|1", Email::try_from("old@example.com").unwrap()).unwrap();
+            user.password_hash = Some(weak.hash("hunter2").unwrap());
+            repo.save(&user).await.unwrap();
+
+            let policy = PasswordHashPolicy::default();
+            let backend = AuthBackend::new(repo.clone(), LoginPolicy::default(), policy);
+            let creds = Credentials {
+                email: "old@example.com".into(),
+                password: "hunter2".into(),
+                remember_me: false,
+            };
+            assert!(backend.authenticate(creds).await.unwrap().is_some());
+
+            let stored = repo.find_by_id(user.id).await.unwrap().unwrap();
+            let hash = stored.password_hash.unwrap();
+            assert!(!policy.needs_rehash(&hash));
+            assert!(verify_password("hunter2", &hash).is_ok());
+        }
+
+        /// Keeps every event it is given
+        #[derive(Default)]
+        struct RecordingPublisher(std::sync::Mutex<Vec<OutboxEvent>>);
+
+        #[async_trait::async_trait]
+        impl EventPublisher for RecordingPublisher {
+            async fn publish(&self, event: &OutboxEvent) -> domain::DomainResult<()> {
+                self.0.lock().unwrap().push(event.clone());
+                Ok(())
+            }
+        }
+
+        #[tokio::test]
+        async fn test_failed_login_publishes_its_reason() {
+            let repo = setup_repo().await;
+            let policy = PasswordHashPolicy {
+                memory_kib: 8,
+                iterations: 1,
+                parallelism: 1,
+                rehash_on_login: false,
+            };
+            let mut user =
+                User::new("local|1", Email::try_from("local@example.com").unwrap()).unwrap();
+            user.password_hash = Some(policy.hash("hunter2").unwrap());
+            repo.save(&user).await.unwrap();
+
+            let events = Arc::new(RecordingPublisher::default());
+            let backend =
+                AuthBackend::new(repo, LoginPolicy::default(), policy).with_events(events.clone());
+            let login = |email: &str, password: &str| Credentials {
+                email: email.into(),
+                password: password.into(),
+                remember_me: false,
+            };
+
+            let wrong = backend.authenticate(login("local@example.com", "hunter3"));
+            assert!(wrong.await.unwrap().is_none());
+            let unknown = backend.authenticate(login("nobody@example.com", "hunter2"));
+            assert!(unknown.await.unwrap().is_none());
+            let right = backend.authenticate(login("local@example.com", "hunter2"));
+            assert!(right.await.unwrap().is_some());
+
+            let events = events.0.lock().unwrap();
+            let payloads: Vec<_> = events
+                .iter()
+                .inspect(|event| assert_eq!(event.topic, OutboxEvent::LOGIN_FAILED))
+                .map(|event| event.payload.clone())
+                .collect();
+            assert_eq!(
+                payloads,
+                vec![
+                    serde_json::json!({"email": "local@example.com", "reason": "wrong_password"}),
+                    serde_json::json!({"email": "nobody@example.com", "reason": "unknown_user"}),
+                ]
+            );
+        }
+
+        #[test]
+        fn test_session_identity_leaves_out_the_password_hash() {
+            let policy = PasswordHashPolicy {
+                memory_kib: 8,
+                iterations: 1,
+                parallelism: 1,
+                rehash_on_login: false,
+            };
+            let mut user =
+                User::new("local|1", Email::try_from("local@example.com").unwrap()).unwrap();
+            let hash = policy.hash("hunter2").unwrap();
+            user.password_hash = Some(hash.clone());
+
+            let auth_user = AuthUser::new(user.clone());
+            assert_eq!(auth_user.identity().id, user.id);
+            let session_hash = axum_login::AuthUser::session_auth_hash(&auth_user);
+            assert_ne!(session_hash, hash.as_bytes());
+
+            // A new password still changes the session hash
+            user.password_hash = Some(policy.hash("hunter3").unwrap());
+            let changed = AuthUser::new(user);
+            assert_ne!(
+                axum_login::AuthUser::session_auth_hash(&changed),
+                session_hash
+            );
+        }
+
+        #[tokio::test]
+        async fn test_oidc_mode_rejects_password_credentials() {
+            let repo = setup_repo().await;
+            let policy = PasswordHashPolicy::default();
+            let mut user =
+                User::new("local|1", Email::try_from("local@example.com").unwrap()).unwrap();
+            user.password_hash = Some(policy.hash("hunter2").unwrap());
+            repo.save(&user).await.unwrap();
+
+            let login_policy = LoginPolicy::default().with_auth_mode(AuthMode::Oidc);
+            let backend = AuthBackend::new(repo, login_policy, policy);
+            let creds = Credentials {
+                email: "local@example.com".into(),
+                password: "hunter2".into(),
+                remember_me: false,
+            };
+            assert!(matches!(
+                backend.authenticate(creds).await,
+                Err(AuthError::Domain(DomainError::Unauthorized(_)))
+            ));
+
+            // Sessions of SSO users still resolve
+            let found = backend.get_user(&user.id).await.unwrap();
+            assert!(found.is_some());
+        }
+    }
 }
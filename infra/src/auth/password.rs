@@ -0,0 +1,107 @@
+//! Password hashing policy
+//!
+//! Argon2id parameters used for new hashes, and detection of stored hashes
+//! created with weaker parameters.
+
+use argon2::password_hash::SaltString;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, Version};
+
+use domain::{DomainError, DomainResult, Password};
+
+/// Argon2id parameters for password hashes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PasswordHashPolicy {
+    /// Memory cost in KiB
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    /// Upgrade weaker hashes after a successful login
+    pub rehash_on_login: bool,
+}
+
+impl Default for PasswordHashPolicy {
+    fn default() -> Self {
+        Self {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+            rehash_on_login: true,
+        }
+    }
+}
+
+impl PasswordHashPolicy {
+    fn params(&self) -> anyhow::Result<Params> {
+        Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+            .map_err(|e| anyhow::anyhow!("Invalid Argon2 parameters: {}", e))
+    }
+
+    /// Hash a password with the current parameters
+    pub fn hash(&self, password: &str) -> anyhow::Result<String> {
+        let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params()?);
+        let salt = SaltString::generate(&mut OsRng);
+
+        let hash = argon2
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|e| anyhow::anyhow!("Failed to hash password: {}", e))?;
+        Ok(hash.to_string())
+    }
+
+    /// Whether a stored hash is weaker than this policy.
+    ///
+    /// Hashes that cannot be parsed are left alone; they cannot have been verified anyway.
+    pub fn needs_rehash(&self, hash: &str) -> bool {
+        let Ok(parsed) = PasswordHash::new(hash) else {
+            return false;
+        };
+        if parsed.algorithm != argon2::ARGON2ID_IDENT {
+            return true;
+        }
+
+        match Params::try_from(&parsed) {
+            Ok(params) => {
+                params.m_cost() < self.memory_kib
+                    || params.t_cost() < self.iterations
+                    || params.p_cost() < self.parallelism
+            }
+            Err(_) => true,
+        }
+    }
+}
+
+impl domain::PasswordHasher for PasswordHashPolicy {
+    fn hash(&self, password: &Password) -> DomainResult<String> {
+        PasswordHashPolicy::hash(self, password.as_ref())
+            .map_err(|e| DomainError::InfrastructureError(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weak() -> PasswordHashPolicy {
+        PasswordHashPolicy {
+            memory_kib: 8,
+            iterations: 1,
+            parallelism: 1,
+            rehash_on_login: true,
+        }
+    }
+
+    #[test]
+    fn test_weaker_hash_needs_rehash() {
+        let hash = weak().hash("hunter2").unwrap();
+        assert!(PasswordHashPolicy::default().needs_rehash(&hash));
+        assert!(!weak().needs_rehash(&hash));
+    }
+
+    #[test]
+    fn test_current_hash_is_kept() {
+        let policy = PasswordHashPolicy::default();
+        let hash = policy.hash("hunter2").unwrap();
+        assert!(!policy.needs_rehash(&hash));
+        assert!(password_auth::verify_password("hunter2", &hash).is_ok());
+    }
+}
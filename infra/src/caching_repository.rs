@@ -0,0 +1,472 @@
+//! In-memory cache for UserRepository
+//!
+//! Every authenticated request loads its user by id, and OIDC logins look
+//! users up by subject, so the auth backend reads both from memory for a
+//! short time to live. Everything else, including the service's own
+//! read-modify-write cycles, goes to the database through an
+//! [`EvictingUserRepository`], which drops written users from the cache once
+//! their write commits. Writes made elsewhere, e.g. by another instance, show
+//! up once the TTL runs out.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use domain::{
+    DisplayName, DomainResult, EmailMatchMode, OutboxEvent, Transaction, User, UserFilter,
+    UserRepository,
+};
+
+#[derive(Default)]
+struct Entries {
+    users: HashMap<Uuid, (Instant, User)>,
+    /// Subject index into `users`
+    subjects: HashMap<String, Uuid>,
+}
+
+impl Entries {
+    /// Drop a user along with its subject index entry
+    fn remove(&mut self, id: Uuid) {
+        if let Some((_, user)) = self.users.remove(&id)
+            && self.subjects.get(&user.subject) == Some(&id)
+        {
+            self.subjects.remove(&user.subject);
+        }
+    }
+
+    /// Forget a written user, and whoever held its subject before: when an
+    /// account is linked the subject moves from one user to another
+    fn evict(&mut self, id: Uuid, subject: Option<&str>) {
+        self.remove(id);
+        if let Some(previous) = subject.and_then(|subject| self.subjects.remove(subject)) {
+            self.remove(previous);
+        }
+    }
+}
+
+/// Caches users by id and by subject in front of another repository.
+///
+/// Meant for the auth backend only; hand [`evicting`](Self::evicting) to
+/// everything else so its writes reach this cache.
+pub struct CachingUserRepository {
+    writes: EvictingUserRepository,
+    ttl: Duration,
+    capacity: usize,
+}
+
+impl CachingUserRepository {
+    /// Default number of cached users
+    pub const DEFAULT_CAPACITY: usize = 10_000;
+
+    /// A zero `ttl` disables caching
+    pub fn new(inner: Arc<dyn UserRepository>, ttl: Duration) -> Self {
+        Self {
+            writes: EvictingUserRepository {
+                inner,
+                entries: Arc::new(Mutex::new(Entries::default())),
+            },
+            ttl,
+            capacity: Self::DEFAULT_CAPACITY,
+        }
+    }
+
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// The uncached repository, evicting the users it writes from this cache
+    pub fn evicting(&self) -> EvictingUserRepository {
+        self.writes.clone()
+    }
+
+    fn inner(&self) -> &Arc<dyn UserRepository> {
+        &self.writes.inner
+    }
+
+    fn cached(&self, id: Uuid) -> Option<User> {
+        let entries = self.writes.entries.lock().unwrap();
+        Self::fresh(&entries, id, self.ttl)
+    }
+
+    fn cached_by_subject(&self, subject: &str) -> Option<User> {
+        let entries = self.writes.entries.lock().unwrap();
+        let id = *entries.subjects.get(subject)?;
+        Self::fresh(&entries, id, self.ttl).filter(|user| user.subject == subject)
+    }
+
+    fn fresh(entries: &Entries, id: Uuid, ttl: Duration) -> Option<User> {
+        let (stored_at, user) = entries.users.get(&id)?;
+        (stored_at.elapsed() < ttl).then(|| user.clone())
+    }
+
+    fn store(&self, user: &User) {
+        if self.ttl.is_zero() || self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.writes.entries.lock().unwrap();
+        entries.remove(user.id);
+        let Entries { users, subjects } = &mut *entries;
+        if users.len() >= self.capacity {
+            users.retain(|_, (stored_at, _)| stored_at.elapsed() < self.ttl);
+            // Still full of live entries: start over rather than track recency
+            if users.len() >= self.capacity {
+                users.clear();
+            }
+            subjects.retain(|_, id| users.contains_key(id));
+        }
+        users.insert(user.id, (Instant::now(), user.clone()));
+        subjects.insert(user.subject.clone(), user.id);
+    }
+}
+
+#[async_trait]
+impl UserRepository for CachingUserRepository {
+    async fn find_by_id(&self, id: Uuid) -> DomainResult<Option<User>> {
+        if let Some(user) = self.cached(id) {
+            return Ok(Some(user));
+        }
+        let user = self.inner().find_by_id(id).await?;
+        if let Some(user) = &user {
+            self.store(user);
+        }
+        Ok(user)
+    }
+
+    async fn find_by_id_for_update(&self, id: Uuid) -> DomainResult<Option<User>> {
+        self.writes.find_by_id_for_update(id).await
+    }
+
+    async fn find_by_ids(&self, ids: &[Uuid]) -> DomainResult<Vec<User>> {
+        self.writes.find_by_ids(ids).await
+    }
+
+    async fn find_by_subject(&self, subject: &str) -> DomainResult<Option<User>> {
+        if let Some(user) = self.cached_by_subject(subject) {
+            return Ok(Some(user));
+        }
+        let user = self.inner().find_by_subject(subject).await?;
+        if let Some(user) = &user {
+            self.store(user);
+        }
+        Ok(user)
+    }
+
+    async fn find_by_email(&self, email: &str) -> DomainResult<Option<User>> {
+        self.writes.find_by_email(email).await
+    }
+
+    async fn exists_by_email(&self, email: &str) -> DomainResult<bool> {
+        self.writes.exists_by_email(email).await
+    }
+
+    async fn find_by_canonical_email(&self, canonical: &str) -> DomainResult<Option<User>> {
+        self.writes.find_by_canonical_email(canonical).await
+    }
+
+    async fn find_inactive_since(&self, cutoff: DateTime<Utc>) -> DomainResult<Vec<User>> {
+        self.writes.find_inactive_since(cutoff).await
+    }
+
+    async fn search(
+        &self,
+        term: &str,
+        match_mode: EmailMatchMode,
+        limit: u32,
+        offset: u32,
+    ) -> DomainResult<Vec<User>> {
+        self.writes.search(term, match_mode, limit, offset).await
+    }
+
+    async fn count_matching(&self, term: &str, match_mode: EmailMatchMode) -> DomainResult<u64> {
+        self.writes.count_matching(term, match_mode).await
+    }
+
+    async fn find(&self, filter: &UserFilter, limit: u32, offset: u32) -> DomainResult<Vec<User>> {
+        self.writes.find(filter, limit, offset).await
+    }
+
+    async fn save(&self, user: &User) -> DomainResult<()> {
+        self.writes.save(user).await
+    }
+
+    async fn save_with_events(&self, user: &User, events: &[OutboxEvent]) -> DomainResult<()> {
+        self.writes.save_with_events(user, events).await
+    }
+
+    async fn begin(&self) -> DomainResult<Box<dyn Transaction>> {
+        self.writes.begin().await
+    }
+
+    async fn save_in(
+        &self,
+        tx: &mut dyn Transaction,
+        user: &User,
+        events: &[OutboxEvent],
+    ) -> DomainResult<()> {
+        self.writes.save_in(tx, user, events).await
+    }
+
+    async fn update_display_name(
+        &self,
+        id: Uuid,
+        display_name: Option<&DisplayName>,
+    ) -> DomainResult<()> {
+        self.writes.update_display_name(id, display_name).await
+    }
+
+    async fn delete(&self, id: Uuid) -> DomainResult<()> {
+        self.writes.delete(id).await
+    }
+
+    async fn delete_in(&self, tx: &mut dyn Transaction, id: Uuid) -> DomainResult<()> {
+        self.writes.delete_in(tx, id).await
+    }
+
+    async fn claim_first_admin(
+        &self,
+        tx: &mut dyn Transaction,
+        user_id: Uuid,
+    ) -> DomainResult<bool> {
+        self.writes.claim_first_admin(tx, user_id).await
+    }
+
+    async fn claim_totp_step(&self, user_id: Uuid, step: u64) -> DomainResult<bool> {
+        self.writes.claim_totp_step(user_id, step).await
+    }
+}
+
+/// Reads and writes straight through to the database, evicting each written
+/// user from a [`CachingUserRepository`] once the write is committed
+#[derive(Clone)]
+pub struct EvictingUserRepository {
+    inner: Arc<dyn UserRepository>,
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl EvictingUserRepository {
+    fn evict(&self, id: Uuid, subject: Option<&str>) {
+        self.entries.lock().unwrap().evict(id, subject);
+    }
+
+    /// Evict once `tx` commits: evicting earlier would let a concurrent read
+    /// cache the old row again before the new one is visible
+    fn evict_after_commit(&self, tx: &mut dyn Transaction, id: Uuid, subject: Option<&str>) {
+        let entries = self.entries.clone();
+        let subject = subject.map(String::from);
+        tx.after_commit(Box::new(move || {
+            entries.lock().unwrap().evict(id, subject.as_deref());
+        }));
+    }
+}
+
+#[async_trait]
+impl UserRepository for EvictingUserRepository {
+    async fn find_by_id(&self, id: Uuid) -> DomainResult<Option<User>> {
+        self.inner.find_by_id(id).await
+    }
+
+    async fn find_by_id_for_update(&self, id: Uuid) -> DomainResult<Option<User>> {
+        self.inner.find_by_id_for_update(id).await
+    }
+
+    async fn find_by_ids(&self, ids: &[Uuid]) -> DomainResult<Vec<User>> {
+        self.inner.find_by_ids(ids).await
+    }
+
+    async fn find_by_subject(&self, subject: &str) -> DomainResult<Option<User>> {
+        self.inner.find_by_subject(subject).await
+    }
+
+    async fn find_by_email(&self, email: &str) -> DomainResult<Option<User>> {
+        self.inner.find_by_email(email).await
+    }
+
+    async fn exists_by_email(&self, email: &str) -> DomainResult<bool> {
+        self.inner.exists_by_email(email).await
+    }
+
+    async fn find_by_canonical_email(&self, canonical: &str) -> DomainResult<Option<User>> {
+        self.inner.find_by_canonical_email(canonical).await
+    }
+
+    async fn find_inactive_since(&self, cutoff: DateTime<Utc>) -> DomainResult<Vec<User>> {
+        self.inner.find_inactive_since(cutoff).await
+    }
+
+    async fn search(
+        &self,
+        term: &str,
+        match_mode: EmailMatchMode,
+        limit: u32,
+        offset: u32,
+    ) -> DomainResult<Vec<User>> {
+        self.inner.search(term, match_mode, limit, offset).await
+    }
+
+    async fn count_matching(&self, term: &str, match_mode: EmailMatchMode) -> DomainResult<u64> {
+        self.inner.count_matching(term, match_mode).await
+    }
+
+    async fn find(&self, filter: &UserFilter, limit: u32, offset: u32) -> DomainResult<Vec<User>> {
+        self.inner.find(filter, limit, offset).await
+    }
+
+    async fn save(&self, user: &User) -> DomainResult<()> {
+        self.inner.save(user).await?;
+        self.evict(user.id, Some(&user.subject));
+        Ok(())
+    }
+
+    async fn save_with_events(&self, user: &User, events: &[OutboxEvent]) -> DomainResult<()> {
+        self.inner.save_with_events(user, events).await?;
+        self.evict(user.id, Some(&user.subject));
+        Ok(())
+    }
+
+    async fn begin(&self) -> DomainResult<Box<dyn Transaction>> {
+        self.inner.begin().await
+    }
+
+    async fn save_in(
+        &self,
+        tx: &mut dyn Transaction,
+        user: &User,
+        events: &[OutboxEvent],
+    ) -> DomainResult<()> {
+        self.inner.save_in(tx, user, events).await?;
+        self.evict_after_commit(tx, user.id, Some(&user.subject));
+        Ok(())
+    }
+
+    async fn update_display_name(
+        &self,
+        id: Uuid,
+        display_name: Option<&DisplayName>,
+    ) -> DomainResult<()> {
+        self.inner.update_display_name(id, display_name).await?;
+        self.evict(id, None);
+        Ok(())
+    }
+
+    async fn delete(&self, id: Uuid) -> DomainResult<()> {
+        self.inner.delete(id).await?;
+        self.evict(id, None);
+        Ok(())
+    }
+
+    async fn delete_in(&self, tx: &mut dyn Transaction, id: Uuid) -> DomainResult<()> {
+        self.inner.delete_in(tx, id).await?;
+        self.evict_after_commit(tx, id, None);
+        Ok(())
+    }
+
+    async fn claim_first_admin(
+        &self,
+        tx: &mut dyn Transaction,
+        user_id: Uuid,
+    ) -> DomainResult<bool> {
+        let claimed = self.inner.claim_first_admin(tx, user_id).await?;
+        self.evict_after_commit(tx, user_id, None);
+        Ok(claimed)
+    }
+
+    async fn claim_totp_step(&self, user_id: Uuid, step: u64) -> DomainResult<bool> {
+        // The step isn't part of the cached user, so nothing to evict
+        self.inner.claim_totp_step(user_id, step).await
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::SqliteUserRepository;
+    use crate::db::{
+        ConnectionSettings, DatabaseConfig, DatabasePool, create_pool, run_migrations,
+    };
+    use domain::Email;
+
+    async fn setup_repo() -> Arc<SqliteUserRepository> {
+        let db_pool = create_pool(DatabaseConfig::default(), &ConnectionSettings::default())
+            .await
+            .expect("Failed to create pool");
+        run_migrations(&db_pool).await.unwrap();
+
+        match db_pool {
+            DatabasePool::Sqlite(pool) => Arc::new(SqliteUserRepository::new(pool)),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subject_lookup_is_cached_until_the_subject_moves() {
+        let inner = setup_repo().await;
+        let cache = CachingUserRepository::new(inner.clone(), Duration::from_secs(60));
+
+        let mut alice =
+            User::new("oidc|shared", Email::try_from("alice@example.com").unwrap()).unwrap();
+        inner.save(&alice).await.unwrap();
+        let found = cache.find_by_subject("oidc|shared").await.unwrap();
+        assert_eq!(found.map(|u| u.id), Some(alice.id));
+
+        // Changed behind the cache's back, so still served from memory
+        let mut stale = alice.clone();
+        stale.email = Email::try_from("renamed@example.com").unwrap();
+        inner.save(&stale).await.unwrap();
+        let found = cache.find_by_subject("oidc|shared").await.unwrap().unwrap();
+        assert_eq!(found.email_str(), "alice@example.com");
+
+        // Linking moves the subject to another account
+        alice.subject = "local|alice".to_string();
+        cache.save(&alice).await.unwrap();
+        let bob = User::new("oidc|shared", Email::try_from("bob@example.com").unwrap()).unwrap();
+        cache.save(&bob).await.unwrap();
+
+        let found = cache.find_by_subject("oidc|shared").await.unwrap();
+        assert_eq!(found.map(|u| u.id), Some(bob.id));
+        let found = cache.find_by_id(alice.id).await.unwrap().unwrap();
+        assert_eq!(found.subject, "local|alice");
+    }
+
+    #[tokio::test]
+    async fn test_transactional_writes_evict_once_committed() {
+        // A file, so a read can run beside the open write transaction
+        let path = std::env::temp_dir().join(format!("cache-{}.db", Uuid::new_v4()));
+        let config = DatabaseConfig {
+            url: format!("sqlite:{}?mode=rwc", path.display()),
+            max_connections: 2,
+            min_connections: 1,
+            acquire_timeout: Duration::from_secs(30),
+        };
+        let db_pool = create_pool(config, &ConnectionSettings::default())
+            .await
+            .unwrap();
+        run_migrations(&db_pool).await.unwrap();
+        let inner = match db_pool {
+            DatabasePool::Sqlite(pool) => Arc::new(SqliteUserRepository::new(pool)),
+        };
+        let cache = CachingUserRepository::new(inner.clone(), Duration::from_secs(60));
+        let writer = cache.evicting();
+
+        let mut user =
+            User::new("oidc|tx", Email::try_from("before@example.com").unwrap()).unwrap();
+        inner.save(&user).await.unwrap();
+        cache.find_by_id(user.id).await.unwrap();
+
+        user.email = Email::try_from("after@example.com").unwrap();
+        let mut tx = writer.begin().await.unwrap();
+        writer.save_in(tx.as_mut(), &user, &[]).await.unwrap();
+        // Not committed yet: the old row is all there is to read
+        let found = cache.find_by_id(user.id).await.unwrap().unwrap();
+        assert_eq!(found.email_str(), "before@example.com");
+
+        tx.commit().await.unwrap();
+        let found = cache.find_by_id(user.id).await.unwrap().unwrap();
+        assert_eq!(found.email_str(), "after@example.com");
+
+        let _ = std::fs::remove_file(path);
+    }
+}
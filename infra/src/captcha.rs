@@ -0,0 +1,98 @@
+//! HTTP implementation of CaptchaVerifier
+//!
+//! hCaptcha and Cloudflare Turnstile share the same `siteverify` protocol.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use domain::{CaptchaVerifier, DomainError, DomainResult};
+
+/// Supported CAPTCHA providers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptchaProvider {
+    HCaptcha,
+    Turnstile,
+}
+
+impl CaptchaProvider {
+    fn verify_url(self) -> &'static str {
+        match self {
+            CaptchaProvider::HCaptcha => "https://api.hcaptcha.com/siteverify",
+            CaptchaProvider::Turnstile => {
+                "https://challenges.cloudflare.com/turnstile/v0/siteverify"
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for CaptchaProvider {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "hcaptcha" => Ok(CaptchaProvider::HCaptcha),
+            "turnstile" => Ok(CaptchaProvider::Turnstile),
+            other => Err(format!("Unknown CAPTCHA provider: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SiteVerifyResponse {
+    success: bool,
+    #[serde(rename = "error-codes", default)]
+    error_codes: Vec<String>,
+}
+
+/// Verifies tokens against the provider's `siteverify` endpoint
+#[derive(Clone)]
+pub struct HttpCaptchaVerifier {
+    client: reqwest::Client,
+    provider: CaptchaProvider,
+    secret: String,
+}
+
+impl HttpCaptchaVerifier {
+    pub fn new(
+        provider: CaptchaProvider,
+        secret: impl Into<String>,
+        timeout: Duration,
+    ) -> Result<Self, reqwest::Error> {
+        let client = reqwest::Client::builder().timeout(timeout).build()?;
+
+        Ok(Self {
+            client,
+            provider,
+            secret: secret.into(),
+        })
+    }
+}
+
+#[async_trait]
+impl CaptchaVerifier for HttpCaptchaVerifier {
+    async fn verify(&self, token: &str) -> DomainResult<bool> {
+        let response: SiteVerifyResponse = self
+            .client
+            .post(self.provider.verify_url())
+            .form(&[("secret", self.secret.as_str()), ("response", token)])
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| {
+                DomainError::InfrastructureError(format!("CAPTCHA request failed: {}", e))
+            })?
+            .json()
+            .await
+            .map_err(|e| {
+                DomainError::InfrastructureError(format!("Invalid CAPTCHA response: {}", e))
+            })?;
+
+        if !response.success {
+            tracing::debug!("CAPTCHA rejected: {:?}", response.error_codes);
+        }
+
+        Ok(response.success)
+    }
+}
@@ -0,0 +1,110 @@
+//! Timestamp conversion for database columns
+//!
+//! Timestamps are stored as RFC3339 text. Rows written by SQLite itself
+//! (e.g. `CURRENT_TIMESTAMP` defaults) use `%Y-%m-%d %H:%M:%S` in UTC instead,
+//! so reads accept both.
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+use domain::{DomainError, DomainResult};
+
+/// Format used by SQLite's `CURRENT_TIMESTAMP` and `datetime()`
+const SQLITE_DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+fn parse(value: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&Utc))
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(value, SQLITE_DATETIME_FORMAT).map(|dt| dt.and_utc())
+        })
+}
+
+/// Parse a timestamp read from the database
+pub(crate) fn parse_db_datetime(value: &str) -> DomainResult<DateTime<Utc>> {
+    parse(value)
+        .map_err(|e| DomainError::RepositoryError(format!("Invalid datetime '{}': {}", value, e)))
+}
+
+/// Parse a nullable timestamp column
+pub(crate) fn parse_optional_db_datetime(
+    value: Option<&str>,
+) -> DomainResult<Option<DateTime<Utc>>> {
+    value.map(parse_db_datetime).transpose()
+}
+
+/// Like [`parse_db_datetime`], naming the column and row in the error so a
+/// corrupt value can be found, e.g. `users.created_at` of a user id
+pub(crate) fn parse_db_datetime_in(
+    value: &str,
+    column: &str,
+    row_id: &str,
+) -> DomainResult<DateTime<Utc>> {
+    parse(value).map_err(|e| {
+        DomainError::RepositoryError(format!(
+            "Invalid datetime '{}' in {} of row {}: {}",
+            value, column, row_id, e
+        ))
+    })
+}
+
+/// Like [`parse_optional_db_datetime`], naming the column and row in the error
+pub(crate) fn parse_optional_db_datetime_in(
+    value: Option<&str>,
+    column: &str,
+    row_id: &str,
+) -> DomainResult<Option<DateTime<Utc>>> {
+    value
+        .map(|value| parse_db_datetime_in(value, column, row_id))
+        .transpose()
+}
+
+/// Format a timestamp for writing to the database
+pub(crate) fn format_db_datetime(value: &DateTime<Utc>) -> String {
+    value.to_rfc3339()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_parses_rfc3339() {
+        let parsed = parse_db_datetime("2024-03-01T12:30:45+02:00").unwrap();
+        assert_eq!(
+            parsed,
+            Utc.with_ymd_and_hms(2024, 3, 1, 10, 30, 45).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parses_sqlite_format_as_utc() {
+        let parsed = parse_db_datetime("2024-03-01 12:30:45").unwrap();
+        assert_eq!(
+            parsed,
+            Utc.with_ymd_and_hms(2024, 3, 1, 12, 30, 45).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_string() {
+        let err = parse_db_datetime("yesterday").unwrap_err();
+        assert!(matches!(err, DomainError::RepositoryError(_)));
+    }
+
+    #[test]
+    fn test_format_round_trips() {
+        let now = Utc::now();
+        assert_eq!(parse_db_datetime(&format_db_datetime(&now)).unwrap(), now);
+    }
+
+    #[test]
+    fn test_optional_passes_through_null() {
+        assert_eq!(parse_optional_db_datetime(None).unwrap(), None);
+        assert!(
+            parse_optional_db_datetime(Some("2024-03-01 12:30:45"))
+                .unwrap()
+                .is_some()
+        );
+    }
+}
@@ -1,17 +1,1128 @@
-pub use k_core::db::DatabasePool;
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+use futures_core::future::BoxFuture;
+use sqlx::ConnectOptions;
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+use sqlx::error::BoxDynError;
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+use sqlx::migrate::{Migrate, MigrateError, Migration, MigrationSource, Migrator};
+use sqlx::pool::PoolOptions;
+
+use crate::InfraError;
+use crate::dialect::Dialect;
+
+pub use k_core::db::{DatabaseConfig, DatabasePool};
+pub use log::LevelFilter;
+
+/// The database server behind a [`DatabasePool`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DatabaseBackend {
+    Sqlite,
+    Postgres,
+}
+
+impl DatabaseBackend {
+    /// The backend a connection URL points at, whether or not its feature is enabled
+    pub fn from_url(url: &str) -> Option<Self> {
+        if url.starts_with("sqlite:") {
+            Some(DatabaseBackend::Sqlite)
+        } else if url.starts_with("postgres:") || url.starts_with("postgresql:") {
+            Some(DatabaseBackend::Postgres)
+        } else {
+            None
+        }
+    }
+
+    /// `sqlite` or `postgres`, as named by the cargo features
+    pub fn name(self) -> &'static str {
+        match self {
+            DatabaseBackend::Sqlite => "sqlite",
+            DatabaseBackend::Postgres => "postgres",
+        }
+    }
+
+    /// Whether UUIDs have a column type of their own; SQLite stores them as TEXT
+    pub fn supports_native_uuid(self) -> bool {
+        matches!(self, DatabaseBackend::Postgres)
+    }
+
+    /// How bind parameters are written in queries
+    pub fn placeholder_style(self) -> Dialect {
+        match self {
+            DatabaseBackend::Sqlite => Dialect::Sqlite,
+            DatabaseBackend::Postgres => Dialect::Postgres,
+        }
+    }
+}
+
+/// [`DatabasePool`] comes from k-core, so its capabilities are added here
+pub trait DatabasePoolExt {
+    fn backend(&self) -> DatabaseBackend;
+}
+
+impl DatabasePoolExt for DatabasePool {
+    fn backend(&self) -> DatabaseBackend {
+        match self {
+            #[cfg(feature = "sqlite")]
+            DatabasePool::Sqlite(_) => DatabaseBackend::Sqlite,
+            #[cfg(feature = "postgres")]
+            DatabasePool::Postgres(_) => DatabaseBackend::Postgres,
+            #[allow(unreachable_patterns)]
+            _ => unreachable!("no database backend enabled"),
+        }
+    }
+}
+
+/// SQL statement logging.
+///
+/// sqlx logs the statement text only; values are always bound as parameters,
+/// so secrets never appear in the log.
+#[derive(Debug, Clone, Copy)]
+pub struct SqlLogging {
+    pub level: LevelFilter,
+    /// Statements slower than this are logged at `warn`
+    pub slow_threshold: Duration,
+}
+
+/// Settings applied to every connection the pool opens
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionSettings {
+    /// Postgres `statement_timeout` / SQLite `busy_timeout`
+    pub statement_timeout: Option<Duration>,
+    /// Statement logging; off when `None`
+    pub sql_logging: Option<SqlLogging>,
+    /// Close connections idle for longer than this; sqlx default when `None`
+    pub idle_timeout: Option<Duration>,
+    /// Recycle connections older than this; sqlx default when `None`
+    pub max_lifetime: Option<Duration>,
+    /// Postgres `sslmode` (`require`, `verify-full`, ...), overriding the URL's;
+    /// ignored by SQLite
+    pub ssl_mode: Option<String>,
+    /// CA certificate Postgres servers are verified against; ignored by SQLite
+    pub ssl_root_cert: Option<PathBuf>,
+}
+
+impl ConnectionSettings {
+    fn apply<O: ConnectOptions>(&self, options: O) -> O {
+        match self.sql_logging {
+            Some(logging) => options
+                .log_statements(logging.level)
+                .log_slow_statements(LevelFilter::Warn, logging.slow_threshold),
+            None => options.disable_statement_logging(),
+        }
+    }
+}
+
+/// Validate pool tuning and open a connection pool.
+pub async fn create_pool(
+    config: DatabaseConfig,
+    settings: &ConnectionSettings,
+) -> Result<DatabasePool, sqlx::Error> {
+    let config = validate_config(config)?;
+
+    match DatabaseBackend::from_url(&config.url) {
+        #[cfg(feature = "sqlite")]
+        Some(DatabaseBackend::Sqlite) => {
+            let options = sqlite_options(&config.url, settings)?;
+            let pool = pool_options(&config, settings)
+                .connect_with(options)
+                .await?;
+            Ok(DatabasePool::Sqlite(pool))
+        }
+        #[cfg(feature = "postgres")]
+        Some(DatabaseBackend::Postgres) => {
+            let options = postgres_options(&config.url, settings)?;
+            let pool = pool_options(&config, settings)
+                .connect_with(options)
+                .await?;
+            Ok(DatabasePool::Postgres(pool))
+        }
+        #[allow(unreachable_patterns)]
+        _ => Err(InfraError::BackendMismatch { url: config.url }.into()),
+    }
+}
+
+fn pool_options<DB: sqlx::Database>(
+    config: &DatabaseConfig,
+    settings: &ConnectionSettings,
+) -> PoolOptions<DB> {
+    let mut options = PoolOptions::new()
+        .max_connections(config.max_connections)
+        .min_connections(config.min_connections)
+        .acquire_timeout(config.acquire_timeout);
+    if let Some(timeout) = settings.idle_timeout {
+        options = options.idle_timeout(timeout);
+    }
+    if let Some(lifetime) = settings.max_lifetime {
+        options = options.max_lifetime(lifetime);
+    }
+    options
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite_options(
+    url: &str,
+    settings: &ConnectionSettings,
+) -> Result<sqlx::sqlite::SqliteConnectOptions, sqlx::Error> {
+    let mut options = sqlx::sqlite::SqliteConnectOptions::from_str(url)?;
+    if let Some(timeout) = settings.statement_timeout {
+        options = options.busy_timeout(timeout);
+    }
+    Ok(settings.apply(options))
+}
+
+#[cfg(feature = "postgres")]
+fn postgres_options(
+    url: &str,
+    settings: &ConnectionSettings,
+) -> Result<sqlx::postgres::PgConnectOptions, sqlx::Error> {
+    let mut options = sqlx::postgres::PgConnectOptions::from_str(url)?;
+    if let Some(timeout) = settings.statement_timeout {
+        options = options.options([("statement_timeout", timeout.as_millis().to_string())]);
+    }
+    if let Some(mode) = &settings.ssl_mode {
+        options = options.ssl_mode(mode.parse()?);
+    }
+    if let Some(path) = &settings.ssl_root_cert {
+        // sqlx only reads the file once it connects; fail at startup instead
+        std::fs::read(path).map_err(|e| InfraError::UnreadableCertificate {
+            path: path.display().to_string(),
+            reason: e.to_string(),
+        })?;
+        options = options.ssl_root_cert(path);
+    }
+    Ok(settings.apply(options))
+}
+
+/// Open `connections` connections up front, so the first requests don't pay
+/// connection-establishment latency.
+///
+/// The connections are held together, then returned to the pool idle.
+pub async fn warm_up(
+    pool: &DatabasePool,
+    connections: u32,
+    timeout: Duration,
+) -> Result<(), sqlx::Error> {
+    let warm = async {
+        match pool {
+            #[cfg(feature = "sqlite")]
+            DatabasePool::Sqlite(pool) => acquire_all(pool, connections).await,
+            #[cfg(feature = "postgres")]
+            DatabasePool::Postgres(pool) => acquire_all(pool, connections).await,
+            #[allow(unreachable_patterns)]
+            _ => Err(InfraError::NoBackendEnabled.into()),
+        }
+    };
+
+    tokio::time::timeout(timeout, warm)
+        .await
+        .map_err(|_| sqlx::Error::PoolTimedOut)?
+}
+
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+async fn acquire_all<DB: sqlx::Database>(
+    pool: &sqlx::Pool<DB>,
+    connections: u32,
+) -> Result<(), sqlx::Error> {
+    let mut held = Vec::with_capacity(connections as usize);
+    for _ in 0..connections {
+        held.push(pool.acquire().await?);
+    }
+    Ok(())
+}
+
+/// Reject inconsistent pool settings before they reach the driver.
+///
+/// In-memory SQLite gives every connection its own database, so the pool
+/// is clamped to a single connection there.
+pub fn validate_config(mut config: DatabaseConfig) -> Result<DatabaseConfig, sqlx::Error> {
+    if config.min_connections > config.max_connections {
+        return Err(sqlx::Error::Configuration(
+            format!(
+                "min_connections ({}) must not exceed max_connections ({})",
+                config.min_connections, config.max_connections
+            )
+            .into(),
+        ));
+    }
+
+    if config.acquire_timeout == Duration::ZERO {
+        return Err(sqlx::Error::Configuration(
+            "acquire_timeout must be greater than zero".into(),
+        ));
+    }
+
+    if is_sqlite_memory(&config.url) && config.max_connections > 1 {
+        tracing::warn!(
+            "In-memory SQLite requires a single connection, forcing max_connections=1 (was {})",
+            config.max_connections
+        );
+        config.max_connections = 1;
+        config.min_connections = config.min_connections.min(1);
+    }
+
+    Ok(config)
+}
+
+fn is_sqlite_memory(url: &str) -> bool {
+    url.starts_with("sqlite:") && (url.contains(":memory:") || url.contains("mode=memory"))
+}
+
+/// Kept out of the migrations so `pg_trgm` is only required when the feature is on
+#[cfg(feature = "postgres-trgm")]
+const TRIGRAM_INDEX: &str = r#"
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+CREATE INDEX IF NOT EXISTS idx_users_email_trgm ON users USING gin (email gin_trgm_ops);
+"#;
+
+// Each backend has its own migrations folder
+#[cfg(feature = "sqlite")]
+static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("../migrations_sqlite");
+#[cfg(feature = "postgres")]
+static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("../migrations_postgres");
 
 pub async fn run_migrations(pool: &DatabasePool) -> Result<(), sqlx::Error> {
+    run_migrations_from(pool, None).await
+}
+
+/// Retrying migrations that fail on a locked database, e.g. while another
+/// instance is migrating or a long transaction holds a table
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MigrationRetry {
+    /// Attempts after the first; retrying is off at 0
+    pub attempts: u32,
+    pub delay: Duration,
+}
+
+/// Run the embedded migrations along with those in `extra_dir`.
+///
+/// Lets downstream projects keep migrations for their own tables outside
+/// this crate, as `<VERSION>_<DESCRIPTION>.sql` files read at startup. Both
+/// sets share one history and are applied in version order, so once extra
+/// migrations ran, the directory must be passed on every later run.
+pub async fn run_migrations_from(
+    pool: &DatabasePool,
+    extra_dir: Option<&Path>,
+) -> Result<(), sqlx::Error> {
+    run_migrations_with_retry(pool, extra_dir, MigrationRetry::default()).await
+}
+
+/// Like [`run_migrations_from`], retrying lock failures as `retry` allows.
+///
+/// A migration that fails is reported as [`InfraError::MigrationFailed`],
+/// naming its version and whether it may have been partially applied.
+pub async fn run_migrations_with_retry(
+    pool: &DatabasePool,
+    extra_dir: Option<&Path>,
+    retry: MigrationRetry,
+) -> Result<(), sqlx::Error> {
     match pool {
         #[cfg(feature = "sqlite")]
         DatabasePool::Sqlite(pool) => {
-            // Point specifically to the sqlite folder
-            sqlx::migrate!("../migrations_sqlite").run(pool).await?;
+            let migrator = migrator(&SQLITE_MIGRATOR, extra_dir).await?;
+            // SQLite runs every migration in a transaction, `-- no-transaction` or not
+            retrying(retry, || migrator.run(pool))
+                .await
+                .map_err(|e| diagnose(&migrator, e, |_| false))?;
         }
         #[cfg(feature = "postgres")]
         DatabasePool::Postgres(pool) => {
-            // Point specifically to the postgres folder
-            sqlx::migrate!("../migrations_postgres").run(pool).await?;
+            let migrator = migrator(&POSTGRES_MIGRATOR, extra_dir).await?;
+            retrying(retry, || migrator.run(pool))
+                .await
+                .map_err(|e| diagnose(&migrator, e, |migration| migration.no_tx))?;
+            #[cfg(feature = "postgres-trgm")]
+            sqlx::raw_sql(TRIGRAM_INDEX).execute(pool).await?;
         }
+        #[allow(unreachable_patterns)]
+        _ => return Err(InfraError::NoBackendEnabled.into()),
     }
     Ok(())
 }
+
+/// Migrations not yet applied to the database, as `<version> <description>`.
+///
+/// Like `sqlx migrate info`, this creates the empty bookkeeping table if it is
+/// missing, but never touches the schema itself.
+pub async fn pending_migrations(
+    pool: &DatabasePool,
+    extra_dir: Option<&Path>,
+) -> Result<Vec<String>, sqlx::Error> {
+    match pool {
+        #[cfg(feature = "sqlite")]
+        DatabasePool::Sqlite(pool) => {
+            pending(&migrator(&SQLITE_MIGRATOR, extra_dir).await?, pool).await
+        }
+        #[cfg(feature = "postgres")]
+        DatabasePool::Postgres(pool) => {
+            pending(&migrator(&POSTGRES_MIGRATOR, extra_dir).await?, pool).await
+        }
+        #[allow(unreachable_patterns)]
+        _ => Err(InfraError::NoBackendEnabled.into()),
+    }
+}
+
+/// Fail with [`InfraError::PendingMigrations`] unless the schema is up to date.
+///
+/// Used instead of [`run_migrations_from`] when migrations are applied by a separate job.
+pub async fn verify_migrations(
+    pool: &DatabasePool,
+    extra_dir: Option<&Path>,
+) -> Result<(), sqlx::Error> {
+    let pending = pending_migrations(pool, extra_dir).await?;
+    if !pending.is_empty() {
+        return Err(InfraError::PendingMigrations { pending }.into());
+    }
+    Ok(())
+}
+
+/// Columns of `users` the app reads on every login
+const REQUIRED_USER_COLUMNS: [&str; 5] = ["id", "subject", "email", "password_hash", "created_at"];
+
+/// Fail with [`InfraError::SchemaMismatch`] unless `users` has the columns the
+/// app relies on, so a hand-edited or drifted schema is caught at startup
+/// rather than by the first request.
+///
+/// The probe selects them with `LIMIT 0`, reading no rows; when it fails,
+/// each column is probed alone to name the missing ones. Gives up with
+/// `PoolTimedOut` after `timeout`.
+pub async fn verify_schema(pool: &DatabasePool, timeout: Duration) -> Result<(), sqlx::Error> {
+    let probe = async {
+        let all = format!(
+            "SELECT {} FROM users LIMIT 0",
+            REQUIRED_USER_COLUMNS.join(", ")
+        );
+        match execute(pool, &all).await {
+            Ok(()) => return Ok(Vec::new()),
+            Err(sqlx::Error::Database(_)) => {}
+            Err(e) => return Err(e),
+        }
+
+        let mut missing = Vec::new();
+        for column in REQUIRED_USER_COLUMNS {
+            match execute(pool, &format!("SELECT {} FROM users LIMIT 0", column)).await {
+                Ok(()) => {}
+                Err(sqlx::Error::Database(_)) => missing.push(column.to_string()),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(missing)
+    };
+
+    let missing = tokio::time::timeout(timeout, probe)
+        .await
+        .map_err(|_| sqlx::Error::PoolTimedOut)??;
+    if !missing.is_empty() {
+        return Err(InfraError::SchemaMismatch {
+            table: "users".to_string(),
+            missing,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Run a statement, discarding its result
+async fn execute(pool: &DatabasePool, sql: &str) -> Result<(), sqlx::Error> {
+    match pool {
+        #[cfg(feature = "sqlite")]
+        DatabasePool::Sqlite(pool) => sqlx::query(sql).execute(pool).await.map(drop),
+        #[cfg(feature = "postgres")]
+        DatabasePool::Postgres(pool) => sqlx::query(sql).execute(pool).await.map(drop),
+        #[allow(unreachable_patterns)]
+        _ => Err(InfraError::NoBackendEnabled.into()),
+    }
+}
+
+/// Run `migrate`, again after `retry.delay` while it fails on a lock
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+async fn retrying<F, Fut>(retry: MigrationRetry, mut migrate: F) -> Result<(), MigrateError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<(), MigrateError>>,
+{
+    let mut attempt = 0;
+    loop {
+        match migrate().await {
+            Err(error) if attempt < retry.attempts && is_lock_error(&error) => {
+                attempt += 1;
+                tracing::warn!(
+                    "Migrations blocked by a lock ({}), retrying in {:?} ({}/{})",
+                    error,
+                    retry.delay,
+                    attempt,
+                    retry.attempts
+                );
+                tokio::time::sleep(retry.delay).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Whether `error` is the database refusing a lock rather than the migration being wrong
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+fn is_lock_error(error: &MigrateError) -> bool {
+    let source = match error {
+        MigrateError::Execute(source) | MigrateError::ExecuteMigration(source, _) => source,
+        _ => return false,
+    };
+    let Some(code) = source.as_database_error().and_then(|e| e.code()) else {
+        return false;
+    };
+    match &*code {
+        // Postgres lock_not_available and deadlock_detected
+        "55P03" | "40P01" => true,
+        // SQLITE_BUSY and SQLITE_LOCKED, extended codes included
+        code => code
+            .parse::<i32>()
+            .is_ok_and(|code| matches!(code & 0xff, 5 | 6)),
+    }
+}
+
+/// Replace sqlx's errors for a failed or half-applied migration with ones
+/// naming the migration and what to do about it
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+fn diagnose(
+    migrator: &Migrator,
+    error: MigrateError,
+    outside_transaction: impl Fn(&Migration) -> bool,
+) -> sqlx::Error {
+    match error {
+        MigrateError::ExecuteMigration(source, version) => {
+            let migration = migrator
+                .iter()
+                .find(|m| m.version == version && !m.migration_type.is_down_migration());
+            InfraError::MigrationFailed {
+                version,
+                description: migration
+                    .map(|m| m.description.to_string())
+                    .unwrap_or_default(),
+                partially_applied: migration.is_some_and(outside_transaction),
+                reason: source.to_string(),
+            }
+            .into()
+        }
+        MigrateError::Dirty(version) => InfraError::DirtyMigration { version }.into(),
+        error => error.into(),
+    }
+}
+
+/// The embedded migrations merged with those in `extra_dir`, by version
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+async fn migrator(
+    embedded: &'static Migrator,
+    extra_dir: Option<&Path>,
+) -> Result<Migrator, sqlx::Error> {
+    let source = MergedMigrations {
+        embedded,
+        extra_dir: extra_dir.map(Path::to_path_buf),
+    };
+    Ok(Migrator::new(source).await?)
+}
+
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+#[derive(Debug)]
+struct MergedMigrations {
+    embedded: &'static Migrator,
+    extra_dir: Option<PathBuf>,
+}
+
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+impl MigrationSource<'static> for MergedMigrations {
+    fn resolve(self) -> BoxFuture<'static, Result<Vec<Migration>, BoxDynError>> {
+        Box::pin(async move {
+            let mut migrations: Vec<Migration> = self.embedded.iter().cloned().collect();
+            if let Some(dir) = self.extra_dir {
+                migrations.extend(dir.resolve().await?);
+            }
+
+            // Up and down scripts of one migration share a version
+            let mut seen = HashSet::new();
+            for migration in &migrations {
+                let key = (
+                    migration.version,
+                    migration.migration_type.is_down_migration(),
+                );
+                if !seen.insert(key) {
+                    return Err(InfraError::DuplicateMigration {
+                        version: migration.version,
+                    }
+                    .into());
+                }
+            }
+
+            migrations.sort_by_key(|migration| migration.version);
+            Ok(migrations)
+        })
+    }
+}
+
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+async fn pending<DB>(migrator: &Migrator, pool: &sqlx::Pool<DB>) -> Result<Vec<String>, sqlx::Error>
+where
+    DB: sqlx::Database,
+    DB::Connection: Migrate,
+{
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    let applied: HashSet<i64> = conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect();
+
+    Ok(migrator
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration() && !applied.contains(&m.version))
+        .map(|m| format!("{} {}", m.version, m.description))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(url: &str, min_connections: u32, max_connections: u32) -> DatabaseConfig {
+        DatabaseConfig {
+            url: url.to_string(),
+            max_connections,
+            min_connections,
+            acquire_timeout: Duration::from_secs(30),
+        }
+    }
+
+    #[test]
+    fn test_min_exceeding_max_is_rejected() {
+        let result = validate_config(config("sqlite:data.db", 10, 5));
+        assert!(matches!(result, Err(sqlx::Error::Configuration(_))));
+    }
+
+    #[test]
+    fn test_zero_acquire_timeout_is_rejected() {
+        let mut config = config("sqlite:data.db", 1, 5);
+        config.acquire_timeout = Duration::ZERO;
+        assert!(matches!(
+            validate_config(config),
+            Err(sqlx::Error::Configuration(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_backend_is_typed_error() {
+        let result = create_pool(
+            config("mysql://localhost/app", 1, 5),
+            &ConnectionSettings::default(),
+        )
+        .await;
+        let Err(sqlx::Error::Configuration(source)) = result else {
+            panic!("expected a configuration error");
+        };
+        assert!(matches!(
+            source.downcast_ref::<InfraError>(),
+            Some(InfraError::BackendMismatch { .. })
+        ));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_pool_options_apply_connection_recycling() {
+        let settings = ConnectionSettings {
+            idle_timeout: Some(Duration::from_secs(5)),
+            max_lifetime: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        let options: PoolOptions<sqlx::Sqlite> =
+            pool_options(&config("sqlite:data.db", 1, 5), &settings);
+        assert_eq!(options.get_idle_timeout(), Some(Duration::from_secs(5)));
+        assert_eq!(options.get_max_lifetime(), Some(Duration::from_secs(60)));
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn test_postgres_options_apply_ssl_settings() {
+        use sqlx::postgres::PgSslMode;
+
+        let url = "postgres://app@localhost/app?sslmode=disable";
+        let options = postgres_options(url, &ConnectionSettings::default()).unwrap();
+        assert!(matches!(options.get_ssl_mode(), PgSslMode::Disable));
+
+        let cert = std::env::temp_dir().join(format!("root-{}.crt", uuid::Uuid::new_v4()));
+        std::fs::write(&cert, "-----BEGIN CERTIFICATE-----\n").unwrap();
+        let settings = ConnectionSettings {
+            ssl_mode: Some("verify-full".to_string()),
+            ssl_root_cert: Some(cert.clone()),
+            ..Default::default()
+        };
+        let options = postgres_options(url, &settings).unwrap();
+        assert!(matches!(options.get_ssl_mode(), PgSslMode::VerifyFull));
+        std::fs::remove_file(&cert).unwrap();
+
+        let settings = ConnectionSettings {
+            ssl_mode: Some("sometimes".to_string()),
+            ..Default::default()
+        };
+        assert!(postgres_options(url, &settings).is_err());
+    }
+
+    #[cfg(feature = "postgres")]
+    #[test]
+    fn test_unreadable_root_cert_is_rejected() {
+        let settings = ConnectionSettings {
+            ssl_root_cert: Some(PathBuf::from("/nonexistent/root.crt")),
+            ..Default::default()
+        };
+        let result = postgres_options("postgres://app@localhost/app", &settings);
+        let Err(sqlx::Error::Configuration(source)) = result else {
+            panic!("expected a configuration error");
+        };
+        assert!(matches!(
+            source.downcast_ref::<InfraError>(),
+            Some(InfraError::UnreadableCertificate { .. })
+        ));
+    }
+
+    #[test]
+    fn test_backend_capabilities() {
+        let sqlite = DatabaseBackend::Sqlite;
+        assert_eq!(sqlite.name(), "sqlite");
+        assert!(!sqlite.supports_native_uuid());
+        assert_eq!(sqlite.placeholder_style(), Dialect::Sqlite);
+
+        let postgres = DatabaseBackend::Postgres;
+        assert_eq!(postgres.name(), "postgres");
+        assert!(postgres.supports_native_uuid());
+        assert_eq!(postgres.placeholder_style(), Dialect::Postgres);
+    }
+
+    #[test]
+    fn test_backend_from_url() {
+        assert_eq!(
+            DatabaseBackend::from_url("sqlite::memory:"),
+            Some(DatabaseBackend::Sqlite)
+        );
+        assert_eq!(
+            DatabaseBackend::from_url("postgresql://localhost/app"),
+            Some(DatabaseBackend::Postgres)
+        );
+        assert_eq!(DatabaseBackend::from_url("mysql://localhost/app"), None);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_sqlite_pool_reports_its_backend() {
+        let pool = create_pool(
+            config("sqlite::memory:", 1, 1),
+            &ConnectionSettings::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(pool.backend(), DatabaseBackend::Sqlite);
+    }
+
+    #[test]
+    fn test_in_memory_sqlite_is_single_connection() {
+        let config = validate_config(config("sqlite::memory:", 1, 5)).unwrap();
+        assert_eq!(config.max_connections, 1);
+        assert_eq!(config.min_connections, 1);
+    }
+
+    #[test]
+    fn test_file_sqlite_keeps_pool_size() {
+        let config = validate_config(config("sqlite:data.db?mode=rwc", 1, 5)).unwrap();
+        assert_eq!(config.max_connections, 5);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_sqlite_lock_wait_times_out() {
+        let path = std::env::temp_dir().join(format!("busy-{}.db", uuid::Uuid::new_v4()));
+        let url = format!("sqlite:{}?mode=rwc", path.display());
+        let settings = ConnectionSettings {
+            statement_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        let pool = create_pool(config(&url, 1, 2), &settings).await.unwrap();
+        let pool = match pool {
+            DatabasePool::Sqlite(pool) => pool,
+            #[allow(unreachable_patterns)]
+            _ => panic!("expected a SQLite pool"),
+        };
+
+        let mut holder = pool.acquire().await.unwrap();
+        sqlx::query("BEGIN IMMEDIATE")
+            .execute(&mut *holder)
+            .await
+            .unwrap();
+
+        // Well below sqlx's default 5s busy timeout
+        let mut waiter = pool.acquire().await.unwrap();
+        let result = tokio::time::timeout(
+            Duration::from_secs(2),
+            sqlx::query("BEGIN IMMEDIATE").execute(&mut *waiter),
+        )
+        .await
+        .expect("lock wait should time out instead of hanging");
+        assert!(result.is_err());
+
+        drop(waiter);
+        drop(holder);
+        pool.close().await;
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_warm_up_opens_min_connections() {
+        let path = std::env::temp_dir().join(format!("warm-{}.db", uuid::Uuid::new_v4()));
+        let url = format!("sqlite:{}?mode=rwc", path.display());
+        let pool = create_pool(config(&url, 3, 5), &ConnectionSettings::default())
+            .await
+            .unwrap();
+
+        warm_up(&pool, 3, Duration::from_secs(5)).await.unwrap();
+
+        let pool = match pool {
+            DatabasePool::Sqlite(pool) => pool,
+            #[allow(unreachable_patterns)]
+            _ => panic!("expected a SQLite pool"),
+        };
+        // Released connections go back to the idle set in the background, so
+        // count the open ones
+        assert!(pool.size() >= 3, "open: {}", pool.size());
+
+        pool.close().await;
+        let _ = std::fs::remove_file(path);
+    }
+
+    /// Debug output of the options the pool from `create_pool` connects with
+    #[cfg(feature = "sqlite")]
+    async fn pool_connect_options(settings: &ConnectionSettings) -> String {
+        let pool = create_pool(config("sqlite::memory:", 1, 1), settings)
+            .await
+            .unwrap();
+        match pool {
+            DatabasePool::Sqlite(pool) => format!("{:?}", pool.connect_options()),
+            #[allow(unreachable_patterns)]
+            _ => panic!("expected a SQLite pool"),
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_connect_options_reflect_sql_logging() {
+        let settings = ConnectionSettings {
+            statement_timeout: None,
+            sql_logging: Some(SqlLogging {
+                level: LevelFilter::Info,
+                slow_threshold: Duration::from_millis(250),
+            }),
+            ..Default::default()
+        };
+        let options = pool_connect_options(&settings).await;
+        assert!(options.contains("statements_level: Info"), "{}", options);
+        assert!(
+            options.contains("slow_statements_level: Warn"),
+            "{}",
+            options
+        );
+        assert!(
+            options.contains("slow_statements_duration: 250ms"),
+            "{}",
+            options
+        );
+
+        let options = pool_connect_options(&ConnectionSettings::default()).await;
+        assert!(options.contains("statements_level: Off"), "{}", options);
+        assert!(
+            options.contains("slow_statements_level: Off"),
+            "{}",
+            options
+        );
+    }
+
+    /// Run with `TEST_POSTGRES_URL=postgres://... cargo test --features postgres -- --ignored`
+    #[cfg(feature = "postgres")]
+    #[tokio::test]
+    #[ignore = "needs a running Postgres server in TEST_POSTGRES_URL"]
+    async fn test_postgres_statement_timeout() {
+        let url = std::env::var("TEST_POSTGRES_URL").expect("TEST_POSTGRES_URL is not set");
+        let settings = ConnectionSettings {
+            statement_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        let pool = create_pool(config(&url, 1, 2), &settings).await.unwrap();
+        let pool = match pool {
+            DatabasePool::Postgres(pool) => pool,
+            #[allow(unreachable_patterns)]
+            _ => panic!("expected a Postgres pool"),
+        };
+
+        let result = tokio::time::timeout(
+            Duration::from_secs(5),
+            sqlx::query("SELECT pg_sleep(2)").execute(&pool),
+        )
+        .await
+        .expect("query should be cancelled instead of hanging");
+        assert!(result.is_err());
+    }
+
+    /// Save and load a session without calling the store's own `migrate()`
+    #[cfg(any(feature = "sqlite", feature = "postgres"))]
+    async fn assert_sessions_round_trip(pool: &DatabasePool) {
+        use tower_sessions::SessionStore;
+        use tower_sessions::session::{Id, Record};
+
+        let store = crate::factory::build_session_store(pool).await.unwrap();
+        let mut record = Record {
+            id: Id::default(),
+            data: [("user_id".to_string(), serde_json::json!(42))].into(),
+            expiry_date: time::OffsetDateTime::now_utc() + time::Duration::hours(1),
+        };
+        store.create(&mut record).await.unwrap();
+
+        let loaded = store.load(&record.id).await.unwrap();
+        assert_eq!(loaded.map(|r| r.data), Some(record.data));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_sqlite_migrations_create_session_table() {
+        let pool = create_pool(
+            config("sqlite::memory:", 1, 1),
+            &ConnectionSettings::default(),
+        )
+        .await
+        .unwrap();
+        run_migrations(&pool).await.unwrap();
+        assert_sessions_round_trip(&pool).await;
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_schema_check_names_missing_columns() {
+        let pool = create_pool(
+            config("sqlite::memory:", 1, 1),
+            &ConnectionSettings::default(),
+        )
+        .await
+        .unwrap();
+        run_migrations(&pool).await.unwrap();
+        let timeout = Duration::from_secs(5);
+        verify_schema(&pool, timeout).await.unwrap();
+
+        execute(&pool, "ALTER TABLE users DROP COLUMN password_hash")
+            .await
+            .unwrap();
+        execute(
+            &pool,
+            "ALTER TABLE users RENAME COLUMN created_at TO created",
+        )
+        .await
+        .unwrap();
+
+        let Err(sqlx::Error::Configuration(source)) = verify_schema(&pool, timeout).await else {
+            panic!("expected a configuration error");
+        };
+        assert_eq!(
+            source.downcast_ref::<InfraError>(),
+            Some(&InfraError::SchemaMismatch {
+                table: "users".to_string(),
+                missing: vec!["password_hash".to_string(), "created_at".to_string()],
+            })
+        );
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_verify_refuses_pending_migrations() {
+        let pool = create_pool(
+            config("sqlite::memory:", 1, 1),
+            &ConnectionSettings::default(),
+        )
+        .await
+        .unwrap();
+
+        let Err(sqlx::Error::Configuration(source)) = verify_migrations(&pool, None).await else {
+            panic!("expected pending migrations to be reported");
+        };
+        let Some(InfraError::PendingMigrations { pending }) = source.downcast_ref::<InfraError>()
+        else {
+            panic!("expected InfraError::PendingMigrations");
+        };
+        assert_eq!(pending.len(), SQLITE_MIGRATOR.iter().count());
+
+        // Verifying does not migrate
+        assert_eq!(
+            pending_migrations(&pool, None).await.unwrap().len(),
+            pending.len()
+        );
+
+        run_migrations(&pool).await.unwrap();
+        assert!(pending_migrations(&pool, None).await.unwrap().is_empty());
+        verify_migrations(&pool, None).await.unwrap();
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_extra_migrations_run_from_a_directory() {
+        let dir = std::env::temp_dir().join(format!("migrations-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        // Out of order on purpose: applied by version, not by file name or listing
+        std::fs::write(
+            dir.join("20990102000000_add_widget_color.sql"),
+            "ALTER TABLE widgets ADD COLUMN color TEXT;",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("20990101000000_create_widgets.sql"),
+            "CREATE TABLE widgets (id TEXT PRIMARY KEY NOT NULL);",
+        )
+        .unwrap();
+        let pool = create_pool(
+            config("sqlite::memory:", 1, 1),
+            &ConnectionSettings::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            pending_migrations(&pool, Some(&dir)).await.unwrap().len(),
+            SQLITE_MIGRATOR.iter().count() + 2
+        );
+        run_migrations_from(&pool, Some(&dir)).await.unwrap();
+        verify_migrations(&pool, Some(&dir)).await.unwrap();
+        let sqlite = match &pool {
+            DatabasePool::Sqlite(pool) => pool,
+            #[allow(unreachable_patterns)]
+            _ => panic!("expected a SQLite pool"),
+        };
+        sqlx::query("INSERT INTO widgets (id, color) VALUES ('w', 'red')")
+            .execute(sqlite)
+            .await
+            .unwrap();
+
+        // A version the embedded migrations already use is refused
+        let embedded = SQLITE_MIGRATOR.iter().next().unwrap().version;
+        std::fs::write(dir.join(format!("{}_clash.sql", embedded)), "SELECT 1;").unwrap();
+        let Err(sqlx::Error::Migrate(error)) = run_migrations_from(&pool, Some(&dir)).await else {
+            panic!("expected the duplicate version to be refused");
+        };
+        assert!(error.to_string().contains(&embedded.to_string()));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_failed_migration_names_its_version() {
+        let dir = std::env::temp_dir().join(format!("migrations-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(
+            dir.join("20990101000000_broken.sql"),
+            "CREATE TABLE widgets (id TEXT PRIMARY KEY NOT NULL); SELEC 1;",
+        )
+        .unwrap();
+        let pool = create_pool(
+            config("sqlite::memory:", 1, 1),
+            &ConnectionSettings::default(),
+        )
+        .await
+        .unwrap();
+
+        let Err(sqlx::Error::Configuration(source)) = run_migrations_from(&pool, Some(&dir)).await
+        else {
+            panic!("expected the failed migration to be reported");
+        };
+        let Some(InfraError::MigrationFailed {
+            version,
+            description,
+            partially_applied,
+            ..
+        }) = source.downcast_ref::<InfraError>()
+        else {
+            panic!("expected InfraError::MigrationFailed");
+        };
+        assert_eq!(*version, 20990101000000);
+        assert_eq!(description, "broken");
+        assert!(!partially_applied);
+
+        // Rolled back, and the embedded migrations before it are kept
+        assert_eq!(
+            pending_migrations(&pool, Some(&dir)).await.unwrap(),
+            vec!["20990101000000 broken".to_string()]
+        );
+        let sqlite = match &pool {
+            DatabasePool::Sqlite(pool) => pool,
+            #[allow(unreachable_patterns)]
+            _ => panic!("expected a SQLite pool"),
+        };
+        let widgets: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM sqlite_master WHERE name = 'widgets'")
+                .fetch_one(sqlite)
+                .await
+                .unwrap();
+        assert_eq!(widgets, 0);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_locked_database_is_retried() {
+        let path = std::env::temp_dir().join(format!("locked-{}.db", uuid::Uuid::new_v4()));
+        let url = format!("sqlite:{}?mode=rwc", path.display());
+        let settings = ConnectionSettings {
+            statement_timeout: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let pool = create_pool(config(&url, 1, 2), &settings).await.unwrap();
+        let sqlite = match &pool {
+            DatabasePool::Sqlite(pool) => pool.clone(),
+            #[allow(unreachable_patterns)]
+            _ => panic!("expected a SQLite pool"),
+        };
+
+        let mut holder = sqlite.acquire().await.unwrap();
+        sqlx::query("BEGIN EXCLUSIVE")
+            .execute(&mut *holder)
+            .await
+            .unwrap();
+        assert!(run_migrations(&pool).await.is_err());
+
+        let release = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            sqlx::query("ROLLBACK").execute(&mut *holder).await.unwrap();
+        });
+        let retry = MigrationRetry {
+            attempts: 20,
+            delay: Duration::from_millis(25),
+        };
+        run_migrations_with_retry(&pool, None, retry).await.unwrap();
+        release.await.unwrap();
+        assert!(pending_migrations(&pool, None).await.unwrap().is_empty());
+
+        sqlite.close().await;
+        let _ = std::fs::remove_file(path);
+    }
+
+    /// Needs a disposable database: `TEST_POSTGRES_URL=postgres://... cargo test --features postgres`
+    #[cfg(feature = "postgres")]
+    #[tokio::test]
+    async fn test_postgres_migrations_create_session_table() {
+        let Ok(url) = std::env::var("TEST_POSTGRES_URL") else {
+            return;
+        };
+        let pool = create_pool(config(&url, 1, 2), &ConnectionSettings::default())
+            .await
+            .unwrap();
+        run_migrations(&pool).await.unwrap();
+        assert_sessions_round_trip(&pool).await;
+    }
+}
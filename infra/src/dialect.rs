@@ -0,0 +1,54 @@
+//! SQL dialect differences between the supported backends
+//!
+//! Queries shared by the SQLite and Postgres adapters are written once and
+//! rendered per dialect, so the two copies cannot drift apart.
+
+/// Bind parameter style of a backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    /// `?`, bound in order
+    Sqlite,
+    /// `$1`, `$2`, ... numbered from 1
+    Postgres,
+}
+
+impl Dialect {
+    /// Placeholder for the `n`th bind parameter, counting from 1
+    pub fn placeholder(self, n: usize) -> String {
+        match self {
+            Dialect::Sqlite => "?".to_string(),
+            Dialect::Postgres => format!("${}", n),
+        }
+    }
+
+    /// Comma-separated placeholders for parameters `1..=count`, e.g. for `VALUES (...)`
+    pub fn placeholders(self, count: usize) -> String {
+        (1..=count)
+            .map(|n| self.placeholder(n))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sqlite_placeholders_are_positional() {
+        assert_eq!(Dialect::Sqlite.placeholder(3), "?");
+        assert_eq!(Dialect::Sqlite.placeholders(3), "?, ?, ?");
+    }
+
+    #[test]
+    fn test_postgres_placeholders_are_numbered() {
+        assert_eq!(Dialect::Postgres.placeholder(3), "$3");
+        assert_eq!(Dialect::Postgres.placeholders(3), "$1, $2, $3");
+    }
+
+    #[test]
+    fn test_no_placeholders_for_no_parameters() {
+        assert_eq!(Dialect::Sqlite.placeholders(0), "");
+        assert_eq!(Dialect::Postgres.placeholders(0), "");
+    }
+}
@@ -0,0 +1,20 @@
+//! EmailSender adapters
+
+use async_trait::async_trait;
+
+use domain::{DomainResult, EmailMessage, EmailSender};
+
+/// Sender that only logs messages.
+///
+/// Default until an SMTP or transactional email sender is configured.
+#[derive(Debug, Clone, Default)]
+pub struct LoggingEmailSender;
+
+#[async_trait]
+impl EmailSender for LoggingEmailSender {
+    async fn send(&self, message: &EmailMessage) -> DomainResult<()> {
+        tracing::info!(to = %message.to, subject = %message.subject, "Email sent");
+        tracing::debug!(to = %message.to, body = %message.text_body, "Email body");
+        Ok(())
+    }
+}
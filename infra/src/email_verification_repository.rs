@@ -0,0 +1,308 @@
+//! SQLite and PostgreSQL implementations of EmailVerificationTokenRepository
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::datetime::{format_db_datetime, parse_db_datetime};
+#[cfg(feature = "postgres")]
+use crate::transaction::PostgresTransaction;
+#[cfg(feature = "sqlite")]
+use crate::transaction::SqliteTransaction;
+use domain::{
+    DomainError, DomainResult, EmailVerificationToken, EmailVerificationTokenRepository,
+    Transaction,
+};
+
+/// SQLite adapter for EmailVerificationTokenRepository
+#[cfg(feature = "sqlite")]
+#[derive(Clone)]
+pub struct SqliteEmailVerificationTokenRepository {
+    pool: sqlx::SqlitePool,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteEmailVerificationTokenRepository {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+/// Row type for email verification token query results
+#[derive(Debug, FromRow)]
+struct EmailVerificationTokenRow {
+    id: String,
+    user_id: String,
+    token_hash: String,
+    created_at: String,
+    expires_at: String,
+}
+
+fn parse_uuid(value: &str) -> DomainResult<Uuid> {
+    Uuid::parse_str(value).map_err(|e| DomainError::RepositoryError(format!("Invalid UUID: {}", e)))
+}
+
+impl TryFrom<EmailVerificationTokenRow> for EmailVerificationToken {
+    type Error = DomainError;
+
+    fn try_from(row: EmailVerificationTokenRow) -> Result<Self, Self::Error> {
+        Ok(EmailVerificationToken {
+            id: parse_uuid(&row.id)?,
+            user_id: parse_uuid(&row.user_id)?,
+            token_hash: row.token_hash,
+            created_at: parse_db_datetime(&row.created_at)?,
+            expires_at: parse_db_datetime(&row.expires_at)?,
+        })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl EmailVerificationTokenRepository for SqliteEmailVerificationTokenRepository {
+    async fn save(&self, token: &EmailVerificationToken) -> DomainResult<()> {
+        sqlx::query(
+            "INSERT INTO email_verification_tokens (id, user_id, token_hash, created_at, expires_at) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(token.id.to_string())
+        .bind(token.user_id.to_string())
+        .bind(&token.token_hash)
+        .bind(format_db_datetime(&token.created_at))
+        .bind(format_db_datetime(&token.expires_at))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn take(&self, token_hash: &str) -> DomainResult<Option<EmailVerificationToken>> {
+        // A single DELETE, so concurrent takes can't both see the row
+        let row: Option<EmailVerificationTokenRow> = sqlx::query_as(
+            "DELETE FROM email_verification_tokens WHERE token_hash = ? RETURNING id, user_id, token_hash, created_at, expires_at",
+        )
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        row.map(EmailVerificationToken::try_from).transpose()
+    }
+
+    async fn latest_for_user(&self, user_id: Uuid) -> DomainResult<Option<EmailVerificationToken>> {
+        let row: Option<EmailVerificationTokenRow> = sqlx::query_as(
+            "SELECT id, user_id, token_hash, created_at, expires_at FROM email_verification_tokens WHERE user_id = ? ORDER BY julianday(created_at) DESC LIMIT 1",
+        )
+        .bind(user_id.to_string())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        row.map(EmailVerificationToken::try_from).transpose()
+    }
+
+    async fn delete_for_user(&self, user_id: Uuid) -> DomainResult<()> {
+        sqlx::query("DELETE FROM email_verification_tokens WHERE user_id = ?")
+            .bind(user_id.to_string())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn delete_for_user_in(
+        &self,
+        tx: &mut dyn Transaction,
+        user_id: Uuid,
+    ) -> DomainResult<()> {
+        let tx = &mut SqliteTransaction::from_dyn(tx)?.0;
+        sqlx::query("DELETE FROM email_verification_tokens WHERE user_id = ?")
+            .bind(user_id.to_string())
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn delete_expired(&self, now: DateTime<Utc>) -> DomainResult<u64> {
+        // julianday() accepts both RFC3339 and SQLite's native datetime format
+        let result = sqlx::query(
+            "DELETE FROM email_verification_tokens WHERE julianday(expires_at) < julianday(?)",
+        )
+        .bind(format_db_datetime(&now))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::SqliteUserRepository;
+    use crate::db::run_migrations;
+    use chrono::Duration;
+    use domain::{Email, User, UserRepository};
+    use k_core::db::{DatabaseConfig, DatabasePool, connect};
+
+    async fn setup_test_db() -> sqlx::SqlitePool {
+        let config = DatabaseConfig::default();
+        let db_pool = connect(&config).await.expect("Failed to create pool");
+
+        run_migrations(&db_pool).await.unwrap();
+
+        match db_pool {
+            DatabasePool::Sqlite(pool) => pool,
+        }
+    }
+
+    async fn create_user(pool: &sqlx::SqlitePool) -> User {
+        let user = User::new_local(Email::try_from("verify@example.com").unwrap(), "hash");
+        SqliteUserRepository::new(pool.clone())
+            .save(&user)
+            .await
+            .unwrap();
+        user
+    }
+
+    #[tokio::test]
+    async fn test_token_can_only_be_taken_once() {
+        let pool = setup_test_db().await;
+        let user = create_user(&pool).await;
+        let repo = SqliteEmailVerificationTokenRepository::new(pool);
+
+        let (token, raw_token) = EmailVerificationToken::generate(user.id, Duration::hours(24));
+        repo.save(&token).await.unwrap();
+
+        let hash = EmailVerificationToken::hash_token(&raw_token);
+        let taken = repo.take(&hash).await.unwrap().unwrap();
+        assert_eq!(taken.id, token.id);
+        assert_eq!(taken.user_id, user.id);
+        assert_eq!(
+            taken.expires_at.timestamp_millis(),
+            token.expires_at.timestamp_millis()
+        );
+
+        assert!(repo.take(&hash).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_latest_for_user_is_the_newest_token() {
+        let pool = setup_test_db().await;
+        let user = create_user(&pool).await;
+        let repo = SqliteEmailVerificationTokenRepository::new(pool);
+        assert!(repo.latest_for_user(user.id).await.unwrap().is_none());
+
+        let (older, _) = EmailVerificationToken::generate(user.id, Duration::hours(24));
+        let (mut newer, _) = EmailVerificationToken::generate(user.id, Duration::hours(24));
+        newer.created_at = older.created_at + Duration::seconds(5);
+        repo.save(&newer).await.unwrap();
+        repo.save(&older).await.unwrap();
+
+        let latest = repo.latest_for_user(user.id).await.unwrap().unwrap();
+        assert_eq!(latest.id, newer.id);
+
+        repo.delete_for_user(user.id).await.unwrap();
+        assert!(repo.latest_for_user(user.id).await.unwrap().is_none());
+    }
+}
+
+/// PostgreSQL adapter for EmailVerificationTokenRepository
+#[cfg(feature = "postgres")]
+#[derive(Clone)]
+pub struct PostgresEmailVerificationTokenRepository {
+    pool: sqlx::Pool<sqlx::Postgres>,
+}
+
+#[cfg(feature = "postgres")]
+impl PostgresEmailVerificationTokenRepository {
+    pub fn new(pool: sqlx::Pool<sqlx::Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[cfg(feature = "postgres")]
+#[async_trait]
+impl EmailVerificationTokenRepository for PostgresEmailVerificationTokenRepository {
+    async fn save(&self, token: &EmailVerificationToken) -> DomainResult<()> {
+        sqlx::query(
+            "INSERT INTO email_verification_tokens (id, user_id, token_hash, created_at, expires_at) VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(token.id.to_string())
+        .bind(token.user_id.to_string())
+        .bind(&token.token_hash)
+        .bind(format_db_datetime(&token.created_at))
+        .bind(format_db_datetime(&token.expires_at))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn take(&self, token_hash: &str) -> DomainResult<Option<EmailVerificationToken>> {
+        // A single DELETE, so concurrent takes can't both see the row
+        let row: Option<EmailVerificationTokenRow> = sqlx::query_as(
+            "DELETE FROM email_verification_tokens WHERE token_hash = $1 RETURNING id, user_id, token_hash, created_at, expires_at",
+        )
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        row.map(EmailVerificationToken::try_from).transpose()
+    }
+
+    async fn latest_for_user(&self, user_id: Uuid) -> DomainResult<Option<EmailVerificationToken>> {
+        let row: Option<EmailVerificationTokenRow> = sqlx::query_as(
+            "SELECT id, user_id, token_hash, created_at, expires_at FROM email_verification_tokens WHERE user_id = $1 ORDER BY created_at::timestamptz DESC LIMIT 1",
+        )
+        .bind(user_id.to_string())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        row.map(EmailVerificationToken::try_from).transpose()
+    }
+
+    async fn delete_for_user(&self, user_id: Uuid) -> DomainResult<()> {
+        sqlx::query("DELETE FROM email_verification_tokens WHERE user_id = $1")
+            .bind(user_id.to_string())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn delete_for_user_in(
+        &self,
+        tx: &mut dyn Transaction,
+        user_id: Uuid,
+    ) -> DomainResult<()> {
+        let tx = &mut PostgresTransaction::from_dyn(tx)?.0;
+        sqlx::query("DELETE FROM email_verification_tokens WHERE user_id = $1")
+            .bind(user_id.to_string())
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn delete_expired(&self, now: DateTime<Utc>) -> DomainResult<u64> {
+        let result = sqlx::query(
+            "DELETE FROM email_verification_tokens WHERE expires_at::timestamptz < $1::timestamptz",
+        )
+        .bind(format_db_datetime(&now))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(result.rows_affected())
+    }
+}
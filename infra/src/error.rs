@@ -0,0 +1,116 @@
+//! Infrastructure errors
+//!
+//! Misconfiguration detected at runtime, such as a database URL for a backend
+//! that was not compiled in.
+
+use domain::DomainError;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum InfraError {
+    #[error("No database feature enabled")]
+    NoBackendEnabled,
+    #[error("Database URL `{url}` does not match any enabled database feature")]
+    BackendMismatch { url: String },
+    #[error("Database schema is behind, pending migrations: {}", pending.join(", "))]
+    PendingMigrations { pending: Vec<String> },
+    #[error("Database table `{table}` is missing columns: {}", missing.join(", "))]
+    SchemaMismatch { table: String, missing: Vec<String> },
+    #[error("Cannot read SSL root certificate `{path}`: {reason}")]
+    UnreadableCertificate { path: String, reason: String },
+    #[error("Migration version {version} is defined more than once")]
+    DuplicateMigration { version: i64 },
+    #[error(
+        "Migration {version} ({description}) failed: {reason}; {}",
+        if *partially_applied {
+            "it ran outside a transaction and may be partially applied, repair the schema by hand before restarting"
+        } else {
+            "it was rolled back, fix it and restart"
+        }
+    )]
+    MigrationFailed {
+        version: i64,
+        description: String,
+        partially_applied: bool,
+        reason: String,
+    },
+    #[error(
+        "Migration {version} is partially applied; repair the schema and delete its row from `_sqlx_migrations` before restarting"
+    )]
+    DirtyMigration { version: i64 },
+}
+
+impl From<InfraError> for DomainError {
+    fn from(error: InfraError) -> Self {
+        DomainError::InfrastructureError(error.to_string())
+    }
+}
+
+impl From<InfraError> for sqlx::Error {
+    fn from(error: InfraError) -> Self {
+        sqlx::Error::Configuration(Box::new(error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_messages() {
+        assert_eq!(
+            InfraError::NoBackendEnabled.to_string(),
+            "No database feature enabled"
+        );
+
+        let error = InfraError::BackendMismatch {
+            url: "mysql://localhost/app".to_string(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "Database URL `mysql://localhost/app` does not match any enabled database feature"
+        );
+
+        let error = InfraError::PendingMigrations {
+            pending: vec!["20240210000000 create password reset tokens".to_string()],
+        };
+        assert_eq!(
+            error.to_string(),
+            "Database schema is behind, pending migrations: 20240210000000 create password reset tokens"
+        );
+
+        let error = InfraError::SchemaMismatch {
+            table: "users".to_string(),
+            missing: vec!["password_hash".to_string(), "created_at".to_string()],
+        };
+        assert_eq!(
+            error.to_string(),
+            "Database table `users` is missing columns: password_hash, created_at"
+        );
+
+        let error = InfraError::MigrationFailed {
+            version: 20240214000000,
+            description: "create invite codes".to_string(),
+            partially_applied: true,
+            reason: "database is locked".to_string(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "Migration 20240214000000 (create invite codes) failed: database is locked; it ran outside a transaction and may be partially applied, repair the schema by hand before restarting"
+        );
+    }
+
+    #[test]
+    fn test_converts_to_matchable_errors() {
+        let domain: DomainError = InfraError::NoBackendEnabled.into();
+        assert!(matches!(domain, DomainError::InfrastructureError(_)));
+
+        let sqlx_error: sqlx::Error = InfraError::NoBackendEnabled.into();
+        let sqlx::Error::Configuration(source) = sqlx_error else {
+            panic!("expected a configuration error");
+        };
+        assert_eq!(
+            source.downcast_ref::<InfraError>(),
+            Some(&InfraError::NoBackendEnabled)
+        );
+    }
+}
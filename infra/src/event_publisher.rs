@@ -0,0 +1,25 @@
+//! EventPublisher adapters
+
+use async_trait::async_trait;
+
+use domain::{DomainResult, EventPublisher, OutboxEvent};
+
+/// Publisher that only logs events.
+///
+/// Default sink until a broker or webhook publisher is configured.
+#[derive(Debug, Clone, Default)]
+pub struct LoggingEventPublisher;
+
+#[async_trait]
+impl EventPublisher for LoggingEventPublisher {
+    async fn publish(&self, event: &OutboxEvent) -> DomainResult<()> {
+        // Not the payload, which can hold an email address
+        tracing::info!(
+            event_id = %event.id,
+            topic = %event.topic,
+            user_id = event.payload.get("user_id").and_then(|id| id.as_str()),
+            "Outbox event published"
+        );
+        Ok(())
+    }
+}
@@ -1,9 +1,17 @@
 use std::sync::Arc;
 
-#[cfg(feature = "sqlite")]
-use crate::SqliteUserRepository;
 use crate::db::DatabasePool;
-use domain::UserRepository;
+use crate::{InfraError, RoutingUserRepository};
+#[cfg(feature = "sqlite")]
+use crate::{
+    SqliteApiKeyRepository, SqliteAuditLogRepository, SqliteEmailVerificationTokenRepository,
+    SqliteInviteRepository, SqliteOutboxRepository, SqlitePasswordResetTokenRepository,
+    SqliteSessionRepository, SqliteUserRepository,
+};
+use domain::{
+    ApiKeyRepository, AuditLogRepository, EmailVerificationTokenRepository, InviteRepository,
+    OutboxRepository, PasswordResetTokenRepository, SessionRepository, UserRepository,
+};
 
 use k_core::session::store::InfraSessionStore;
 
@@ -11,8 +19,8 @@ use k_core::session::store::InfraSessionStore;
 pub enum FactoryError {
     #[error("Database error: {0}")]
     Database(#[from] sqlx::Error),
-    #[error("Not implemented: {0}")]
-    NotImplemented(String),
+    #[error(transparent)]
+    Backend(#[from] InfraError),
     #[error("Infrastructure error: {0}")]
     Infrastructure(#[from] domain::DomainError),
 }
@@ -28,12 +36,142 @@ pub async fn build_user_repository(pool: &DatabasePool) -> FactoryResult<Arc<dyn
             crate::user_repository::PostgresUserRepository::new(pool.clone()),
         )),
         #[allow(unreachable_patterns)]
-        _ => Err(FactoryError::NotImplemented(
-            "No database feature enabled".to_string(),
+        _ => Err(InfraError::NoBackendEnabled.into()),
+    }
+}
+
+/// `primary` reading from `replica` when one is configured
+pub async fn build_routed_user_repository(
+    primary: Arc<dyn UserRepository>,
+    replica: Option<&DatabasePool>,
+) -> FactoryResult<Arc<dyn UserRepository>> {
+    match replica {
+        Some(replica) => Ok(Arc::new(RoutingUserRepository::new(
+            primary,
+            build_user_repository(replica).await?,
+        ))),
+        None => Ok(primary),
+    }
+}
+
+pub async fn build_api_key_repository(
+    pool: &DatabasePool,
+) -> FactoryResult<Arc<dyn ApiKeyRepository>> {
+    match pool {
+        #[cfg(feature = "sqlite")]
+        DatabasePool::Sqlite(pool) => Ok(Arc::new(SqliteApiKeyRepository::new(pool.clone()))),
+        #[cfg(feature = "postgres")]
+        DatabasePool::Postgres(pool) => Ok(Arc::new(
+            crate::api_key_repository::PostgresApiKeyRepository::new(pool.clone()),
+        )),
+        #[allow(unreachable_patterns)]
+        _ => Err(InfraError::NoBackendEnabled.into()),
+    }
+}
+
+pub async fn build_audit_log_repository(
+    pool: &DatabasePool,
+) -> FactoryResult<Arc<dyn AuditLogRepository>> {
+    match pool {
+        #[cfg(feature = "sqlite")]
+        DatabasePool::Sqlite(pool) => Ok(Arc::new(SqliteAuditLogRepository::new(pool.clone()))),
+        #[cfg(feature = "postgres")]
+        DatabasePool::Postgres(pool) => Ok(Arc::new(
+            crate::audit_log_repository::PostgresAuditLogRepository::new(pool.clone()),
+        )),
+        #[allow(unreachable_patterns)]
+        _ => Err(InfraError::NoBackendEnabled.into()),
+    }
+}
+
+pub async fn build_outbox_repository(
+    pool: &DatabasePool,
+) -> FactoryResult<Arc<dyn OutboxRepository>> {
+    match pool {
+        #[cfg(feature = "sqlite")]
+        DatabasePool::Sqlite(pool) => Ok(Arc::new(SqliteOutboxRepository::new(pool.clone()))),
+        #[cfg(feature = "postgres")]
+        DatabasePool::Postgres(pool) => Ok(Arc::new(
+            crate::outbox_repository::PostgresOutboxRepository::new(pool.clone()),
         )),
+        #[allow(unreachable_patterns)]
+        _ => Err(InfraError::NoBackendEnabled.into()),
     }
 }
 
+pub async fn build_password_reset_repository(
+    pool: &DatabasePool,
+) -> FactoryResult<Arc<dyn PasswordResetTokenRepository>> {
+    match pool {
+        #[cfg(feature = "sqlite")]
+        DatabasePool::Sqlite(pool) => Ok(Arc::new(SqlitePasswordResetTokenRepository::new(
+            pool.clone(),
+        ))),
+        #[cfg(feature = "postgres")]
+        DatabasePool::Postgres(pool) => Ok(Arc::new(
+            crate::password_reset_repository::PostgresPasswordResetTokenRepository::new(
+                pool.clone(),
+            ),
+        )),
+        #[allow(unreachable_patterns)]
+        _ => Err(InfraError::NoBackendEnabled.into()),
+    }
+}
+
+pub async fn build_email_verification_repository(
+    pool: &DatabasePool,
+) -> FactoryResult<Arc<dyn EmailVerificationTokenRepository>> {
+    match pool {
+        #[cfg(feature = "sqlite")]
+        DatabasePool::Sqlite(pool) => Ok(Arc::new(SqliteEmailVerificationTokenRepository::new(
+            pool.clone(),
+        ))),
+        #[cfg(feature = "postgres")]
+        DatabasePool::Postgres(pool) => Ok(Arc::new(
+            crate::email_verification_repository::PostgresEmailVerificationTokenRepository::new(
+                pool.clone(),
+            ),
+        )),
+        #[allow(unreachable_patterns)]
+        _ => Err(InfraError::NoBackendEnabled.into()),
+    }
+}
+
+pub async fn build_invite_repository(
+    pool: &DatabasePool,
+) -> FactoryResult<Arc<dyn InviteRepository>> {
+    match pool {
+        #[cfg(feature = "sqlite")]
+        DatabasePool::Sqlite(pool) => Ok(Arc::new(SqliteInviteRepository::new(pool.clone()))),
+        #[cfg(feature = "postgres")]
+        DatabasePool::Postgres(pool) => Ok(Arc::new(
+            crate::invite_repository::PostgresInviteRepository::new(pool.clone()),
+        )),
+        #[allow(unreachable_patterns)]
+        _ => Err(InfraError::NoBackendEnabled.into()),
+    }
+}
+
+/// Session metadata, joined with the table of [`build_session_store`]
+pub async fn build_session_repository(
+    pool: &DatabasePool,
+) -> FactoryResult<Arc<dyn SessionRepository>> {
+    match pool {
+        #[cfg(feature = "sqlite")]
+        DatabasePool::Sqlite(pool) => Ok(Arc::new(SqliteSessionRepository::new(pool.clone()))),
+        #[cfg(feature = "postgres")]
+        DatabasePool::Postgres(pool) => Ok(Arc::new(
+            crate::session_repository::PostgresSessionRepository::new(pool.clone()),
+        )),
+        #[allow(unreachable_patterns)]
+        _ => Err(InfraError::NoBackendEnabled.into()),
+    }
+}
+
+/// Session store on the application database.
+///
+/// Its table is created by [`run_migrations`](crate::run_migrations); calling
+/// the store's own `migrate()` is not needed.
 pub async fn build_session_store(
     pool: &DatabasePool,
 ) -> FactoryResult<crate::session_store::InfraSessionStore> {
@@ -46,5 +184,7 @@ pub async fn build_session_store(
         DatabasePool::Postgres(p) => {
             InfraSessionStore::Postgres(tower_sessions_sqlx_store::PostgresStore::new(p.clone()))
         }
+        #[allow(unreachable_patterns)]
+        _ => return Err(InfraError::NoBackendEnabled.into()),
     })
 }
@@ -0,0 +1,254 @@
+//! SQLite and PostgreSQL implementations of InviteRepository
+
+use async_trait::async_trait;
+use chrono::Utc;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::datetime::{format_db_datetime, parse_db_datetime, parse_optional_db_datetime};
+#[cfg(feature = "postgres")]
+use crate::transaction::PostgresTransaction;
+#[cfg(feature = "sqlite")]
+use crate::transaction::SqliteTransaction;
+use domain::{DomainError, DomainResult, InviteCode, InviteRepository, Transaction};
+
+/// SQLite adapter for InviteRepository
+#[cfg(feature = "sqlite")]
+#[derive(Clone)]
+pub struct SqliteInviteRepository {
+    pool: sqlx::SqlitePool,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteInviteRepository {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+/// Row type for invite code query results
+#[derive(Debug, FromRow)]
+struct InviteCodeRow {
+    code: String,
+    created_by: String,
+    created_at: String,
+    used_by: Option<String>,
+    used_at: Option<String>,
+}
+
+fn parse_uuid(value: &str) -> DomainResult<Uuid> {
+    Uuid::parse_str(value).map_err(|e| DomainError::RepositoryError(format!("Invalid UUID: {}", e)))
+}
+
+impl TryFrom<InviteCodeRow> for InviteCode {
+    type Error = DomainError;
+
+    fn try_from(row: InviteCodeRow) -> Result<Self, Self::Error> {
+        Ok(InviteCode {
+            code: row.code,
+            created_by: parse_uuid(&row.created_by)?,
+            created_at: parse_db_datetime(&row.created_at)?,
+            used_by: row.used_by.as_deref().map(parse_uuid).transpose()?,
+            used_at: parse_optional_db_datetime(row.used_at.as_deref())?,
+        })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl InviteRepository for SqliteInviteRepository {
+    async fn save(&self, invite: &InviteCode) -> DomainResult<()> {
+        sqlx::query(
+            "INSERT INTO invite_codes (code, created_by, created_at, used_by, used_at) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&invite.code)
+        .bind(invite.created_by.to_string())
+        .bind(format_db_datetime(&invite.created_at))
+        .bind(invite.used_by.map(|id| id.to_string()))
+        .bind(invite.used_at.as_ref().map(format_db_datetime))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn list(&self, limit: u32, offset: u32) -> DomainResult<Vec<InviteCode>> {
+        let rows: Vec<InviteCodeRow> = sqlx::query_as(
+            "SELECT code, created_by, created_at, used_by, used_at FROM invite_codes ORDER BY julianday(created_at) DESC, code LIMIT ? OFFSET ?",
+        )
+        .bind(i64::from(limit))
+        .bind(i64::from(offset))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        rows.into_iter().map(InviteCode::try_from).collect()
+    }
+
+    async fn count(&self) -> DomainResult<u64> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM invite_codes")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(count as u64)
+    }
+
+    async fn redeem_in(
+        &self,
+        tx: &mut dyn Transaction,
+        code: &str,
+        user_id: Uuid,
+    ) -> DomainResult<bool> {
+        let tx = &mut SqliteTransaction::from_dyn(tx)?.0;
+        let result = sqlx::query(
+            "UPDATE invite_codes SET used_by = ?, used_at = ? WHERE code = ? AND used_by IS NULL",
+        )
+        .bind(user_id.to_string())
+        .bind(format_db_datetime(&Utc::now()))
+        .bind(code)
+        .execute(&mut **tx)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::db::run_migrations;
+    use k_core::db::{DatabaseConfig, DatabasePool, connect};
+
+    async fn setup_test_db() -> sqlx::SqlitePool {
+        let config = DatabaseConfig::default();
+        let db_pool = connect(&config).await.expect("Failed to create pool");
+
+        run_migrations(&db_pool).await.unwrap();
+
+        match db_pool {
+            DatabasePool::Sqlite(pool) => pool,
+        }
+    }
+
+    async fn redeem(repo: &SqliteInviteRepository, code: &str, user_id: Uuid) -> bool {
+        let mut tx = SqliteTransaction::new(repo.pool.begin().await.unwrap());
+        let redeemed = repo.redeem_in(&mut tx, code, user_id).await.unwrap();
+        tx.0.commit().await.unwrap();
+        redeemed
+    }
+
+    #[tokio::test]
+    async fn test_code_can_only_be_redeemed_once() {
+        let repo = SqliteInviteRepository::new(setup_test_db().await);
+        let invite = InviteCode::generate(Uuid::new_v4());
+        repo.save(&invite).await.unwrap();
+
+        let user_id = Uuid::new_v4();
+        assert!(redeem(&repo, &invite.code, user_id).await);
+        assert!(!redeem(&repo, &invite.code, Uuid::new_v4()).await);
+        assert!(!redeem(&repo, "unknown", Uuid::new_v4()).await);
+
+        let listed = repo.list(10, 0).await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].used_by, Some(user_id));
+        assert!(listed[0].used_at.is_some());
+        assert_eq!(repo.count().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rolled_back_redemption_keeps_the_code() {
+        let repo = SqliteInviteRepository::new(setup_test_db().await);
+        let invite = InviteCode::generate(Uuid::new_v4());
+        repo.save(&invite).await.unwrap();
+
+        let mut tx = SqliteTransaction::new(repo.pool.begin().await.unwrap());
+        assert!(
+            repo.redeem_in(&mut tx, &invite.code, Uuid::new_v4())
+                .await
+                .unwrap()
+        );
+        tx.0.rollback().await.unwrap();
+
+        assert!(redeem(&repo, &invite.code, Uuid::new_v4()).await);
+    }
+}
+
+/// PostgreSQL adapter for InviteRepository
+#[cfg(feature = "postgres")]
+#[derive(Clone)]
+pub struct PostgresInviteRepository {
+    pool: sqlx::Pool<sqlx::Postgres>,
+}
+
+#[cfg(feature = "postgres")]
+impl PostgresInviteRepository {
+    pub fn new(pool: sqlx::Pool<sqlx::Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[cfg(feature = "postgres")]
+#[async_trait]
+impl InviteRepository for PostgresInviteRepository {
+    async fn save(&self, invite: &InviteCode) -> DomainResult<()> {
+        sqlx::query(
+            "INSERT INTO invite_codes (code, created_by, created_at, used_by, used_at) VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(&invite.code)
+        .bind(invite.created_by.to_string())
+        .bind(format_db_datetime(&invite.created_at))
+        .bind(invite.used_by.map(|id| id.to_string()))
+        .bind(invite.used_at.as_ref().map(format_db_datetime))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn list(&self, limit: u32, offset: u32) -> DomainResult<Vec<InviteCode>> {
+        let rows: Vec<InviteCodeRow> = sqlx::query_as(
+            "SELECT code, created_by, created_at, used_by, used_at FROM invite_codes ORDER BY created_at::timestamptz DESC, code LIMIT $1 OFFSET $2",
+        )
+        .bind(i64::from(limit))
+        .bind(i64::from(offset))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        rows.into_iter().map(InviteCode::try_from).collect()
+    }
+
+    async fn count(&self) -> DomainResult<u64> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM invite_codes")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(count as u64)
+    }
+
+    async fn redeem_in(
+        &self,
+        tx: &mut dyn Transaction,
+        code: &str,
+        user_id: Uuid,
+    ) -> DomainResult<bool> {
+        let tx = &mut PostgresTransaction::from_dyn(tx)?.0;
+        // Row locked by the first redeemer; a concurrent one re-checks `used_by` after it commits
+        let result = sqlx::query(
+            "UPDATE invite_codes SET used_by = $1, used_at = $2 WHERE code = $3 AND used_by IS NULL",
+        )
+        .bind(user_id.to_string())
+        .bind(format_db_datetime(&Utc::now()))
+        .bind(code)
+        .execute(&mut **tx)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+}
@@ -8,19 +8,80 @@
 //! - [`SqliteNoteRepository`] - SQLite adapter for notes with FTS5 search
 //! - [`SqliteUserRepository`] - SQLite adapter for users (OIDC-ready)
 //! - [`SqliteTagRepository`] - SQLite adapter for tags
+//! - [`SqliteApiKeyRepository`] - SQLite adapter for API keys
+//! - [`SqliteAuditLogRepository`] - SQLite adapter for the audit log
+//! - [`SqliteOutboxRepository`] - SQLite adapter for the event outbox
+//! - [`SqlitePasswordResetTokenRepository`] - SQLite adapter for password reset tokens
+//! - [`SqliteEmailVerificationTokenRepository`] - SQLite adapter for email verification tokens
+//! - [`SqliteInviteRepository`] - SQLite adapter for invite codes
+//! - [`SqliteSessionRepository`] - SQLite adapter for session metadata
+//! - [`RoutingUserRepository`] - Sends user reads to a replica and writes to the primary
+//! - [`CachingUserRepository`] - Caches users by id and subject for the auth hot path
+//! - [`EvictingUserRepository`] - Uncached access that evicts written users from that cache
+//! - [`LoggingEventPublisher`] - Event publisher that logs outbox events
+//! - [`LoggingEmailSender`] - Email sender that logs messages
+//! - [`transaction::SqliteTransaction`] - Native handle for registration hooks
 //!
 //! ## Database
 //!
 //! - [`db::create_pool`] - Create a database connection pool
 //! - [`db::run_migrations`] - Run database migrations
+//! - [`db::run_migrations_from`] - Run them along with migrations from a directory
+//! - [`db::run_migrations_with_retry`] - Also retry them while the database is locked
+//! - [`db::verify_migrations`] - Check that migrations were applied by someone else
+//! - [`db::verify_schema`] - Check that `users` has the columns the app reads
+//! - [`db::DatabasePoolExt::backend`] - Which backend a pool talks to, and what it supports
 
+mod api_key_repository;
+mod audit_log_repository;
 pub mod auth;
+mod caching_repository;
+#[cfg(feature = "captcha")]
+pub mod captcha;
+mod datetime;
 pub mod db;
+pub mod dialect;
+mod email_sender;
+mod email_verification_repository;
+mod error;
+mod event_publisher;
 pub mod factory;
+mod invite_repository;
+#[cfg(feature = "oidc")]
+pub mod oidc;
+mod outbox_repository;
+mod password_reset_repository;
+mod routing_repository;
+mod session_repository;
 pub mod session_store;
+#[cfg(feature = "totp")]
+pub mod totp;
+pub mod transaction;
 mod user_repository;
 
 // Re-export for convenience
-pub use db::run_migrations;
+#[cfg(feature = "sqlite")]
+pub use api_key_repository::SqliteApiKeyRepository;
+#[cfg(feature = "sqlite")]
+pub use audit_log_repository::SqliteAuditLogRepository;
+pub use caching_repository::{CachingUserRepository, EvictingUserRepository};
+pub use db::{
+    MigrationRetry, run_migrations, run_migrations_from, run_migrations_with_retry,
+    verify_migrations, verify_schema,
+};
+pub use email_sender::LoggingEmailSender;
+#[cfg(feature = "sqlite")]
+pub use email_verification_repository::SqliteEmailVerificationTokenRepository;
+pub use error::InfraError;
+pub use event_publisher::LoggingEventPublisher;
+#[cfg(feature = "sqlite")]
+pub use invite_repository::SqliteInviteRepository;
+#[cfg(feature = "sqlite")]
+pub use outbox_repository::SqliteOutboxRepository;
+#[cfg(feature = "sqlite")]
+pub use password_reset_repository::SqlitePasswordResetTokenRepository;
+pub use routing_repository::RoutingUserRepository;
+#[cfg(feature = "sqlite")]
+pub use session_repository::SqliteSessionRepository;
 #[cfg(feature = "sqlite")]
 pub use user_repository::SqliteUserRepository;
@@ -0,0 +1,351 @@
+//! HTTP implementation of OidcProvider
+//!
+//! Exchanges the authorization code at the provider's token endpoint, then
+//! reads the user's claims from its userinfo endpoint. Codes are bound to the
+//! login with PKCE, and the ID token must carry the login's nonce and belong
+//! to the same user as the claims. Every request has a
+//! timeout and failures to reach the provider are retried a bounded number of
+//! times, so a stalled provider fails the login with `ProviderUnavailable`
+//! instead of holding it open.
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use reqwest::Url;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use domain::{DomainError, DomainResult, OidcLoginAttempt, OidcProvider};
+
+/// Pause before the first retry, doubled for each further one
+const RETRY_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Provider endpoints and this application's client registration
+#[derive(Debug, Clone)]
+pub struct OidcSettings {
+    pub authorization_url: String,
+    pub token_url: String,
+    pub userinfo_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    /// Where the provider sends the browser back to, the `/auth/oidc/callback` route
+    pub redirect_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    id_token: String,
+}
+
+/// The ID token claims checked against the login
+#[derive(Debug, Deserialize)]
+struct IdTokenClaims {
+    sub: String,
+    aud: Audience,
+    nonce: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Audience {
+    fn contains(&self, client_id: &str) -> bool {
+        match self {
+            Audience::One(aud) => aud == client_id,
+            Audience::Many(auds) => auds.iter().any(|aud| aud == client_id),
+        }
+    }
+}
+
+/// The payload of a JWT, without checking its signature
+fn jwt_payload<T: serde::de::DeserializeOwned>(token: &str) -> Option<T> {
+    let payload = token.split('.').nth(1)?;
+    let bytes = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// The S256 PKCE challenge for `verifier`
+fn code_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+/// Runs the authorization code flow over HTTP
+#[derive(Clone)]
+pub struct HttpOidcProvider {
+    client: reqwest::Client,
+    settings: OidcSettings,
+    authorization_url: Url,
+    retries: u32,
+}
+
+impl HttpOidcProvider {
+    /// `timeout` bounds each request; a failed one is sent at most `retries` more times
+    pub fn new(settings: OidcSettings, timeout: Duration, retries: u32) -> anyhow::Result<Self> {
+        let authorization_url = Url::parse(&settings.authorization_url)?;
+        let client = reqwest::Client::builder()
+            .connect_timeout(timeout)
+            .timeout(timeout)
+            .build()?;
+
+        Ok(Self {
+            client,
+            settings,
+            authorization_url,
+            retries,
+        })
+    }
+
+    /// Send the request `build` makes, again if it fails in a way worth retrying.
+    ///
+    /// Codes are single-use, so a request that is not `idempotent` is only
+    /// repeated when it never reached the provider.
+    async fn send(
+        &self,
+        endpoint: &str,
+        idempotent: bool,
+        build: impl Fn() -> reqwest::RequestBuilder,
+    ) -> DomainResult<reqwest::Response> {
+        let mut attempt = 0;
+        loop {
+            let (failure, retryable) = match build().send().await {
+                Ok(response) if response.status().is_server_error() => {
+                    (format!("answered {}", response.status()), idempotent)
+                }
+                Ok(response) => return Ok(response),
+                Err(e) if e.is_connect() => (e.to_string(), true),
+                Err(e) => (e.to_string(), idempotent),
+            };
+
+            if !retryable || attempt >= self.retries {
+                return Err(DomainError::ProviderUnavailable(format!(
+                    "{} endpoint: {}",
+                    endpoint, failure
+                )));
+            }
+            tracing::debug!(
+                attempt,
+                "OIDC {} request failed, retrying: {}",
+                endpoint,
+                failure
+            );
+            tokio::time::sleep(RETRY_BACKOFF * 2u32.pow(attempt)).await;
+            attempt += 1;
+        }
+    }
+
+    /// Check that the ID token was issued to this client for `attempt`.
+    ///
+    /// It comes straight from the token endpoint over TLS, which vouches for
+    /// it in place of its signature (OIDC Core 3.1.3.7), so it is not verified.
+    fn check_id_token(&self, id_token: &str, attempt: &OidcLoginAttempt) -> DomainResult<String> {
+        let claims: IdTokenClaims = jwt_payload(id_token).ok_or_else(|| {
+            DomainError::ProviderUnavailable("Invalid ID token in the token response".into())
+        })?;
+        if !claims.aud.contains(&self.settings.client_id) {
+            return Err(DomainError::unauthorized(
+                "The ID token was issued to another client",
+            ));
+        }
+        if claims.nonce.as_deref() != Some(attempt.nonce.as_str()) {
+            return Err(DomainError::unauthorized(
+                "The ID token belongs to another sign-in",
+            ));
+        }
+        Ok(claims.sub)
+    }
+}
+
+#[async_trait]
+impl OidcProvider for HttpOidcProvider {
+    fn authorization_url(&self, attempt: &OidcLoginAttempt) -> String {
+        let mut url = self.authorization_url.clone();
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.settings.client_id)
+            .append_pair("redirect_uri", &self.settings.redirect_url)
+            .append_pair("scope", "openid email profile")
+            .append_pair("state", &attempt.state)
+            .append_pair("nonce", &attempt.nonce)
+            .append_pair("code_challenge", &code_challenge(&attempt.code_verifier))
+            .append_pair("code_challenge_method", "S256");
+        url.into()
+    }
+
+    async fn claims(
+        &self,
+        code: &str,
+        attempt: &OidcLoginAttempt,
+    ) -> DomainResult<serde_json::Value> {
+        let settings = &self.settings;
+
+        let response = self
+            .send("token", false, || {
+                self.client
+                    .post(&settings.token_url)
+                    .basic_auth(&settings.client_id, Some(&settings.client_secret))
+                    .form(&[
+                        ("grant_type", "authorization_code"),
+                        ("code", code),
+                        ("redirect_uri", settings.redirect_url.as_str()),
+                        ("code_verifier", attempt.code_verifier.as_str()),
+                    ])
+            })
+            .await?;
+        if response.status().is_client_error() {
+            return Err(DomainError::unauthorized(format!(
+                "The sign-in provider rejected the code ({})",
+                response.status()
+            )));
+        }
+        let tokens: TokenResponse = response.json().await.map_err(|e| {
+            DomainError::ProviderUnavailable(format!("Invalid token response: {}", e))
+        })?;
+        let subject = self.check_id_token(&tokens.id_token, attempt)?;
+
+        let claims: serde_json::Value = self
+            .send("userinfo", true, || {
+                self.client
+                    .get(&settings.userinfo_url)
+                    .bearer_auth(&tokens.access_token)
+            })
+            .await?
+            .error_for_status()
+            .map_err(|e| DomainError::ProviderUnavailable(format!("userinfo endpoint: {}", e)))?
+            .json()
+            .await
+            .map_err(|e| {
+                DomainError::ProviderUnavailable(format!("Invalid userinfo response: {}", e))
+            })?;
+        // Userinfo answers for whoever holds the access token (OIDC Core 5.3.2)
+        if claims.get("sub").and_then(|sub| sub.as_str()) != Some(subject.as_str()) {
+            return Err(DomainError::unauthorized(
+                "The sign-in provider answered for another user",
+            ));
+        }
+        Ok(claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::net::TcpListener;
+
+    /// Address of a provider that accepts connections and never answers,
+    /// and how many connections it has taken
+    async fn stalled_provider() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = connections.clone();
+        tokio::spawn(async move {
+            let mut open = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                open.push(socket);
+            }
+        });
+        (address, connections)
+    }
+
+    fn provider(address: &str) -> HttpOidcProvider {
+        let settings = OidcSettings {
+            authorization_url: format!("{}/authorize", address),
+            token_url: format!("{}/token", address),
+            userinfo_url: format!("{}/userinfo", address),
+            client_id: "k-template".to_string(),
+            client_secret: "secret".to_string(),
+            redirect_url: "http://localhost:3000/api/v1/auth/oidc/callback".to_string(),
+        };
+        HttpOidcProvider::new(settings, Duration::from_millis(100), 2).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_stalled_token_endpoint_is_unavailable_and_not_resent() {
+        let (address, connections) = stalled_provider().await;
+
+        let result = provider(&address)
+            .claims("code", &OidcLoginAttempt::generate())
+            .await;
+
+        assert!(
+            matches!(result, Err(DomainError::ProviderUnavailable(_))),
+            "{:?}",
+            result
+        );
+        // The provider may have redeemed the code, so it is not sent twice
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_authorization_url_carries_the_client_state_nonce_and_challenge() {
+        let attempt = OidcLoginAttempt::generate();
+        let url = provider("https://idp.example.com").authorization_url(&attempt);
+        let url = Url::parse(&url).unwrap();
+        let query: std::collections::HashMap<_, _> = url.query_pairs().into_owned().collect();
+
+        assert_eq!(url.path(), "/authorize");
+        assert_eq!(query["response_type"], "code");
+        assert_eq!(query["client_id"], "k-template");
+        assert_eq!(query["state"], attempt.state);
+        assert_eq!(query["nonce"], attempt.nonce);
+        assert_eq!(query["code_challenge_method"], "S256");
+        assert_eq!(
+            query["code_challenge"],
+            code_challenge(&attempt.code_verifier)
+        );
+        assert!(query["scope"].split(' ').any(|scope| scope == "openid"));
+    }
+
+    #[test]
+    fn test_code_challenge_matches_the_rfc_example() {
+        // RFC 7636, appendix B
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    fn id_token(claims: serde_json::Value) -> String {
+        format!(
+            "{}.{}.signature",
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"RS256"}"#),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        )
+    }
+
+    #[test]
+    fn test_id_token_must_match_the_client_and_nonce() {
+        let provider = provider("https://idp.example.com");
+        let attempt = OidcLoginAttempt::generate();
+
+        let valid = id_token(serde_json::json!({
+            "sub": "user-1",
+            "aud": ["other", "k-template"],
+            "nonce": attempt.nonce,
+        }));
+        assert_eq!(provider.check_id_token(&valid, &attempt).unwrap(), "user-1");
+
+        for claims in [
+            serde_json::json!({"sub": "user-1", "aud": "k-template", "nonce": "replayed"}),
+            serde_json::json!({"sub": "user-1", "aud": "k-template"}),
+            serde_json::json!({"sub": "user-1", "aud": "other", "nonce": attempt.nonce}),
+        ] {
+            let result = provider.check_id_token(&id_token(claims.clone()), &attempt);
+            assert!(
+                matches!(result, Err(DomainError::Unauthorized(_))),
+                "{}",
+                claims
+            );
+        }
+    }
+}
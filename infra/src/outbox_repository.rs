@@ -0,0 +1,347 @@
+//! SQLite and PostgreSQL implementations of OutboxRepository
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::datetime::{format_db_datetime, parse_db_datetime, parse_optional_db_datetime};
+use domain::{DomainError, DomainResult, OutboxEvent, OutboxRepository};
+
+/// SQLite adapter for OutboxRepository
+#[cfg(feature = "sqlite")]
+#[derive(Clone)]
+pub struct SqliteOutboxRepository {
+    pool: sqlx::SqlitePool,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteOutboxRepository {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+/// Columns selected for [`OutboxRow`]
+const OUTBOX_COLUMNS: &str =
+    "id, topic, payload, created_at, attempts, next_attempt_at, delivered_at, last_error";
+
+/// Row type for outbox query results
+#[derive(Debug, FromRow)]
+struct OutboxRow {
+    id: String,
+    topic: String,
+    payload: String,
+    created_at: String,
+    attempts: i64,
+    next_attempt_at: String,
+    delivered_at: Option<String>,
+    last_error: Option<String>,
+}
+
+impl TryFrom<OutboxRow> for OutboxEvent {
+    type Error = DomainError;
+
+    fn try_from(row: OutboxRow) -> Result<Self, Self::Error> {
+        let id = Uuid::parse_str(&row.id)
+            .map_err(|e| DomainError::RepositoryError(format!("Invalid UUID: {}", e)))?;
+        let payload = serde_json::from_str(&row.payload)
+            .map_err(|e| DomainError::RepositoryError(format!("Invalid payload: {}", e)))?;
+
+        Ok(OutboxEvent {
+            id,
+            topic: row.topic,
+            payload,
+            created_at: parse_db_datetime(&row.created_at)?,
+            attempts: u32::try_from(row.attempts).unwrap_or(u32::MAX),
+            next_attempt_at: parse_db_datetime(&row.next_attempt_at)?,
+            delivered_at: parse_optional_db_datetime(row.delivered_at.as_deref())?,
+            last_error: row.last_error,
+        })
+    }
+}
+
+/// Insert an event using any SQLite executor, so it can join a caller's transaction
+#[cfg(feature = "sqlite")]
+pub(crate) async fn insert_sqlite<'e, E>(
+    executor: E,
+    event: &OutboxEvent,
+) -> Result<(), sqlx::Error>
+where
+    E: sqlx::SqliteExecutor<'e>,
+{
+    sqlx::query(
+        r#"
+        INSERT INTO outbox (id, topic, payload, created_at, attempts, next_attempt_at)
+        VALUES (?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(event.id.to_string())
+    .bind(&event.topic)
+    .bind(event.payload.to_string())
+    .bind(format_db_datetime(&event.created_at))
+    .bind(i64::from(event.attempts))
+    .bind(format_db_datetime(&event.next_attempt_at))
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl OutboxRepository for SqliteOutboxRepository {
+    async fn enqueue(&self, event: &OutboxEvent) -> DomainResult<()> {
+        insert_sqlite(&self.pool, event)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))
+    }
+
+    async fn fetch_pending(
+        &self,
+        now: DateTime<Utc>,
+        limit: u32,
+    ) -> DomainResult<Vec<OutboxEvent>> {
+        let rows: Vec<OutboxRow> = sqlx::query_as(&format!(
+            "SELECT {} FROM outbox WHERE delivered_at IS NULL AND julianday(next_attempt_at) <= julianday(?) ORDER BY created_at LIMIT ?",
+            OUTBOX_COLUMNS
+        ))
+        .bind(format_db_datetime(&now))
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        rows.into_iter().map(OutboxEvent::try_from).collect()
+    }
+
+    async fn mark_delivered(&self, id: Uuid, delivered_at: DateTime<Utc>) -> DomainResult<()> {
+        sqlx::query("UPDATE outbox SET delivered_at = ?, last_error = NULL WHERE id = ?")
+            .bind(format_db_datetime(&delivered_at))
+            .bind(id.to_string())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn mark_failed(
+        &self,
+        id: Uuid,
+        error: &str,
+        next_attempt_at: DateTime<Utc>,
+    ) -> DomainResult<()> {
+        sqlx::query(
+            "UPDATE outbox SET attempts = attempts + 1, last_error = ?, next_attempt_at = ? WHERE id = ?",
+        )
+        .bind(error)
+        .bind(format_db_datetime(&next_attempt_at))
+        .bind(id.to_string())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::db::run_migrations;
+    use crate::user_repository::SqliteUserRepository;
+    use chrono::Duration;
+    use domain::{Email, User, UserRepository};
+    use k_core::db::{DatabaseConfig, DatabasePool, connect};
+
+    async fn setup_test_db() -> sqlx::SqlitePool {
+        let config = DatabaseConfig::default();
+        let db_pool = connect(&config).await.expect("Failed to create pool");
+
+        run_migrations(&db_pool).await.unwrap();
+
+        match db_pool {
+            DatabasePool::Sqlite(pool) => pool,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_committed_event_is_picked_up_and_delivered() {
+        let pool = setup_test_db().await;
+        let users = SqliteUserRepository::new(pool.clone());
+        let outbox = SqliteOutboxRepository::new(pool);
+
+        let user = User::new("outbox|1", Email::try_from("outbox@example.com").unwrap()).unwrap();
+        users
+            .save_with_events(&user, &[OutboxEvent::user_created(&user)])
+            .await
+            .unwrap();
+
+        let pending = outbox.fetch_pending(Utc::now(), 10).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].topic, "user.created");
+        assert_eq!(pending[0].payload["user_id"], user.id.to_string());
+
+        outbox
+            .mark_delivered(pending[0].id, Utc::now())
+            .await
+            .unwrap();
+        assert!(
+            outbox
+                .fetch_pending(Utc::now(), 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_event_waits_for_backoff() {
+        let pool = setup_test_db().await;
+        let outbox = SqliteOutboxRepository::new(pool);
+
+        let event = OutboxEvent::new("test.event", serde_json::json!({}));
+        outbox.enqueue(&event).await.unwrap();
+
+        let retry_at = Utc::now() + Duration::minutes(5);
+        outbox
+            .mark_failed(event.id, "boom", retry_at)
+            .await
+            .unwrap();
+        assert!(
+            outbox
+                .fetch_pending(Utc::now(), 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let pending = outbox.fetch_pending(retry_at, 10).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].attempts, 1);
+        assert_eq!(pending[0].last_error.as_deref(), Some("boom"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_user_save_discards_events() {
+        let pool = setup_test_db().await;
+        let users = SqliteUserRepository::new(pool.clone());
+        let outbox = SqliteOutboxRepository::new(pool);
+
+        let first = User::new("outbox|a", Email::try_from("dup@example.com").unwrap()).unwrap();
+        users.save(&first).await.unwrap();
+
+        let second = User::new("outbox|b", Email::try_from("dup@example.com").unwrap()).unwrap();
+        let result = users
+            .save_with_events(&second, &[OutboxEvent::user_created(&second)])
+            .await;
+        assert!(result.is_err());
+        assert!(
+            outbox
+                .fetch_pending(Utc::now(), 10)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
+
+/// PostgreSQL adapter for OutboxRepository
+#[cfg(feature = "postgres")]
+#[derive(Clone)]
+pub struct PostgresOutboxRepository {
+    pool: sqlx::Pool<sqlx::Postgres>,
+}
+
+#[cfg(feature = "postgres")]
+impl PostgresOutboxRepository {
+    pub fn new(pool: sqlx::Pool<sqlx::Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+/// Insert an event using any Postgres executor, so it can join a caller's transaction
+#[cfg(feature = "postgres")]
+pub(crate) async fn insert_postgres<'e, E>(
+    executor: E,
+    event: &OutboxEvent,
+) -> Result<(), sqlx::Error>
+where
+    E: sqlx::PgExecutor<'e>,
+{
+    sqlx::query(
+        r#"
+        INSERT INTO outbox (id, topic, payload, created_at, attempts, next_attempt_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+    )
+    .bind(event.id.to_string())
+    .bind(&event.topic)
+    .bind(event.payload.to_string())
+    .bind(format_db_datetime(&event.created_at))
+    .bind(i64::from(event.attempts))
+    .bind(format_db_datetime(&event.next_attempt_at))
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(feature = "postgres")]
+#[async_trait]
+impl OutboxRepository for PostgresOutboxRepository {
+    async fn enqueue(&self, event: &OutboxEvent) -> DomainResult<()> {
+        insert_postgres(&self.pool, event)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))
+    }
+
+    async fn fetch_pending(
+        &self,
+        now: DateTime<Utc>,
+        limit: u32,
+    ) -> DomainResult<Vec<OutboxEvent>> {
+        let rows: Vec<OutboxRow> = sqlx::query_as(&format!(
+            "SELECT {} FROM outbox WHERE delivered_at IS NULL AND next_attempt_at::timestamptz <= $1::timestamptz ORDER BY created_at LIMIT $2",
+            OUTBOX_COLUMNS
+        ))
+        .bind(format_db_datetime(&now))
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        rows.into_iter().map(OutboxEvent::try_from).collect()
+    }
+
+    async fn mark_delivered(&self, id: Uuid, delivered_at: DateTime<Utc>) -> DomainResult<()> {
+        sqlx::query("UPDATE outbox SET delivered_at = $1, last_error = NULL WHERE id = $2")
+            .bind(format_db_datetime(&delivered_at))
+            .bind(id.to_string())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn mark_failed(
+        &self,
+        id: Uuid,
+        error: &str,
+        next_attempt_at: DateTime<Utc>,
+    ) -> DomainResult<()> {
+        sqlx::query(
+            "UPDATE outbox SET attempts = attempts + 1, last_error = $1, next_attempt_at = $2 WHERE id = $3",
+        )
+        .bind(error)
+        .bind(format_db_datetime(&next_attempt_at))
+        .bind(id.to_string())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,304 @@
+//! SQLite and PostgreSQL implementations of PasswordResetTokenRepository
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::datetime::{format_db_datetime, parse_db_datetime};
+#[cfg(feature = "postgres")]
+use crate::transaction::PostgresTransaction;
+#[cfg(feature = "sqlite")]
+use crate::transaction::SqliteTransaction;
+use domain::{
+    DomainError, DomainResult, PasswordResetToken, PasswordResetTokenRepository, Transaction,
+};
+
+/// SQLite adapter for PasswordResetTokenRepository
+#[cfg(feature = "sqlite")]
+#[derive(Clone)]
+pub struct SqlitePasswordResetTokenRepository {
+    pool: sqlx::SqlitePool,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqlitePasswordResetTokenRepository {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+/// Row type for password reset token query results
+#[derive(Debug, FromRow)]
+struct PasswordResetTokenRow {
+    id: String,
+    user_id: String,
+    token_hash: String,
+    created_at: String,
+    expires_at: String,
+}
+
+fn parse_uuid(value: &str) -> DomainResult<Uuid> {
+    Uuid::parse_str(value).map_err(|e| DomainError::RepositoryError(format!("Invalid UUID: {}", e)))
+}
+
+impl TryFrom<PasswordResetTokenRow> for PasswordResetToken {
+    type Error = DomainError;
+
+    fn try_from(row: PasswordResetTokenRow) -> Result<Self, Self::Error> {
+        Ok(PasswordResetToken {
+            id: parse_uuid(&row.id)?,
+            user_id: parse_uuid(&row.user_id)?,
+            token_hash: row.token_hash,
+            created_at: parse_db_datetime(&row.created_at)?,
+            expires_at: parse_db_datetime(&row.expires_at)?,
+        })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl PasswordResetTokenRepository for SqlitePasswordResetTokenRepository {
+    async fn save(&self, token: &PasswordResetToken) -> DomainResult<()> {
+        sqlx::query(
+            "INSERT INTO password_reset_tokens (id, user_id, token_hash, created_at, expires_at) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(token.id.to_string())
+        .bind(token.user_id.to_string())
+        .bind(&token.token_hash)
+        .bind(format_db_datetime(&token.created_at))
+        .bind(format_db_datetime(&token.expires_at))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn take(&self, token_hash: &str) -> DomainResult<Option<PasswordResetToken>> {
+        // A single DELETE, so concurrent takes can't both see the row
+        let row: Option<PasswordResetTokenRow> = sqlx::query_as(
+            "DELETE FROM password_reset_tokens WHERE token_hash = ? RETURNING id, user_id, token_hash, created_at, expires_at",
+        )
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        row.map(PasswordResetToken::try_from).transpose()
+    }
+
+    async fn delete_for_user(&self, user_id: Uuid) -> DomainResult<()> {
+        sqlx::query("DELETE FROM password_reset_tokens WHERE user_id = ?")
+            .bind(user_id.to_string())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn delete_for_user_in(
+        &self,
+        tx: &mut dyn Transaction,
+        user_id: Uuid,
+    ) -> DomainResult<()> {
+        let tx = &mut SqliteTransaction::from_dyn(tx)?.0;
+        sqlx::query("DELETE FROM password_reset_tokens WHERE user_id = ?")
+            .bind(user_id.to_string())
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn delete_expired(&self, now: DateTime<Utc>) -> DomainResult<u64> {
+        // julianday() accepts both RFC3339 and SQLite's native datetime format
+        let result = sqlx::query(
+            "DELETE FROM password_reset_tokens WHERE julianday(expires_at) < julianday(?)",
+        )
+        .bind(format_db_datetime(&now))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::SqliteUserRepository;
+    use crate::db::run_migrations;
+    use chrono::Duration;
+    use domain::{Email, User, UserRepository};
+    use k_core::db::{DatabaseConfig, DatabasePool, connect};
+
+    async fn setup_test_db() -> sqlx::SqlitePool {
+        let config = DatabaseConfig::default();
+        let db_pool = connect(&config).await.expect("Failed to create pool");
+
+        run_migrations(&db_pool).await.unwrap();
+
+        match db_pool {
+            DatabasePool::Sqlite(pool) => pool,
+        }
+    }
+
+    async fn create_user(pool: &sqlx::SqlitePool) -> User {
+        let user = User::new_local(Email::try_from("reset@example.com").unwrap(), "hash");
+        SqliteUserRepository::new(pool.clone())
+            .save(&user)
+            .await
+            .unwrap();
+        user
+    }
+
+    #[tokio::test]
+    async fn test_token_can_only_be_taken_once() {
+        let pool = setup_test_db().await;
+        let user = create_user(&pool).await;
+        let repo = SqlitePasswordResetTokenRepository::new(pool);
+
+        let (token, raw_token) = PasswordResetToken::generate(user.id, Duration::minutes(30));
+        repo.save(&token).await.unwrap();
+
+        let hash = PasswordResetToken::hash_token(&raw_token);
+        let taken = repo.take(&hash).await.unwrap().unwrap();
+        assert_eq!(taken.id, token.id);
+        assert_eq!(taken.user_id, user.id);
+        assert_eq!(
+            taken.expires_at.timestamp_millis(),
+            token.expires_at.timestamp_millis()
+        );
+
+        assert!(repo.take(&hash).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_delete_for_user_removes_outstanding_tokens() {
+        let pool = setup_test_db().await;
+        let user = create_user(&pool).await;
+        let repo = SqlitePasswordResetTokenRepository::new(pool);
+
+        let (first, first_raw) = PasswordResetToken::generate(user.id, Duration::minutes(30));
+        let (second, second_raw) = PasswordResetToken::generate(user.id, Duration::minutes(30));
+        repo.save(&first).await.unwrap();
+        repo.save(&second).await.unwrap();
+
+        repo.delete_for_user(user.id).await.unwrap();
+
+        for raw in [first_raw, second_raw] {
+            let hash = PasswordResetToken::hash_token(&raw);
+            assert!(repo.take(&hash).await.unwrap().is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delete_expired_keeps_fresh_tokens() {
+        let pool = setup_test_db().await;
+        let user = create_user(&pool).await;
+        let repo = SqlitePasswordResetTokenRepository::new(pool);
+
+        let (expired, expired_raw) = PasswordResetToken::generate(user.id, Duration::minutes(-5));
+        let (fresh, fresh_raw) = PasswordResetToken::generate(user.id, Duration::minutes(30));
+        repo.save(&expired).await.unwrap();
+        repo.save(&fresh).await.unwrap();
+
+        assert_eq!(repo.delete_expired(Utc::now()).await.unwrap(), 1);
+
+        let hash = PasswordResetToken::hash_token(&expired_raw);
+        assert!(repo.take(&hash).await.unwrap().is_none());
+        let hash = PasswordResetToken::hash_token(&fresh_raw);
+        assert_eq!(
+            repo.take(&hash).await.unwrap().map(|t| t.id),
+            Some(fresh.id)
+        );
+    }
+}
+
+/// PostgreSQL adapter for PasswordResetTokenRepository
+#[cfg(feature = "postgres")]
+#[derive(Clone)]
+pub struct PostgresPasswordResetTokenRepository {
+    pool: sqlx::Pool<sqlx::Postgres>,
+}
+
+#[cfg(feature = "postgres")]
+impl PostgresPasswordResetTokenRepository {
+    pub fn new(pool: sqlx::Pool<sqlx::Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[cfg(feature = "postgres")]
+#[async_trait]
+impl PasswordResetTokenRepository for PostgresPasswordResetTokenRepository {
+    async fn save(&self, token: &PasswordResetToken) -> DomainResult<()> {
+        sqlx::query(
+            "INSERT INTO password_reset_tokens (id, user_id, token_hash, created_at, expires_at) VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(token.id.to_string())
+        .bind(token.user_id.to_string())
+        .bind(&token.token_hash)
+        .bind(format_db_datetime(&token.created_at))
+        .bind(format_db_datetime(&token.expires_at))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn take(&self, token_hash: &str) -> DomainResult<Option<PasswordResetToken>> {
+        // A single DELETE, so concurrent takes can't both see the row
+        let row: Option<PasswordResetTokenRow> = sqlx::query_as(
+            "DELETE FROM password_reset_tokens WHERE token_hash = $1 RETURNING id, user_id, token_hash, created_at, expires_at",
+        )
+        .bind(token_hash)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        row.map(PasswordResetToken::try_from).transpose()
+    }
+
+    async fn delete_for_user(&self, user_id: Uuid) -> DomainResult<()> {
+        sqlx::query("DELETE FROM password_reset_tokens WHERE user_id = $1")
+            .bind(user_id.to_string())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn delete_for_user_in(
+        &self,
+        tx: &mut dyn Transaction,
+        user_id: Uuid,
+    ) -> DomainResult<()> {
+        let tx = &mut PostgresTransaction::from_dyn(tx)?.0;
+        sqlx::query("DELETE FROM password_reset_tokens WHERE user_id = $1")
+            .bind(user_id.to_string())
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn delete_expired(&self, now: DateTime<Utc>) -> DomainResult<u64> {
+        let result = sqlx::query(
+            "DELETE FROM password_reset_tokens WHERE expires_at::timestamptz < $1::timestamptz",
+        )
+        .bind(format_db_datetime(&now))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(result.rows_affected())
+    }
+}
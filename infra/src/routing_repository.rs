@@ -0,0 +1,310 @@
+//! Read-replica routing for UserRepository
+//!
+//! Writes go to the primary, reads to the replica. Users written through this
+//! repository are read from the primary for a short window afterwards, so a
+//! just-registered user is visible even while the replica lags behind, and
+//! users read to be changed and saved back always are.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use domain::{
+    DisplayName, DomainResult, EmailMatchMode, OutboxEvent, Transaction, User, UserFilter,
+    UserRepository,
+};
+
+/// A user written recently enough that the replica may not have it yet
+struct RecentWrite {
+    id: Uuid,
+    /// Unknown for deletes
+    email: Option<String>,
+    canonical_email: Option<String>,
+    subject: Option<String>,
+    at: Instant,
+}
+
+/// Routes user reads to a replica and writes to the primary
+pub struct RoutingUserRepository {
+    primary: Arc<dyn UserRepository>,
+    replica: Arc<dyn UserRepository>,
+    /// How long reads of a written user stick to the primary
+    read_your_writes: Duration,
+    recent: Mutex<Vec<RecentWrite>>,
+}
+
+impl RoutingUserRepository {
+    /// Default stickiness window, comfortably above typical replication lag
+    pub const DEFAULT_READ_YOUR_WRITES: Duration = Duration::from_secs(5);
+
+    pub fn new(primary: Arc<dyn UserRepository>, replica: Arc<dyn UserRepository>) -> Self {
+        Self {
+            primary,
+            replica,
+            read_your_writes: Self::DEFAULT_READ_YOUR_WRITES,
+            recent: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn with_read_your_writes(mut self, window: Duration) -> Self {
+        self.read_your_writes = window;
+        self
+    }
+
+    fn remember(&self, id: Uuid, user: Option<&User>) {
+        let mut recent = self.recent.lock().unwrap();
+        // Pruned here too, so a write-heavy, read-light process stays bounded
+        recent.retain(|write| write.at.elapsed() < self.read_your_writes);
+        recent.push(RecentWrite {
+            id,
+            email: user.map(|u| u.email_str().to_string()),
+            canonical_email: user.and_then(|u| u.canonical_email.clone()),
+            subject: user.map(|u| u.subject.clone()),
+            at: Instant::now(),
+        });
+    }
+
+    /// The repository to read from, given a predicate identifying the user
+    fn reader(&self, is_target: impl Fn(&RecentWrite) -> bool) -> &Arc<dyn UserRepository> {
+        let mut recent = self.recent.lock().unwrap();
+        recent.retain(|write| write.at.elapsed() < self.read_your_writes);
+        if recent.iter().any(is_target) {
+            &self.primary
+        } else {
+            &self.replica
+        }
+    }
+}
+
+#[async_trait]
+impl UserRepository for RoutingUserRepository {
+    async fn find_by_id(&self, id: Uuid) -> DomainResult<Option<User>> {
+        self.reader(|write| write.id == id).find_by_id(id).await
+    }
+
+    async fn find_by_id_for_update(&self, id: Uuid) -> DomainResult<Option<User>> {
+        self.primary.find_by_id(id).await
+    }
+
+    async fn find_by_ids(&self, ids: &[Uuid]) -> DomainResult<Vec<User>> {
+        self.reader(|write| ids.contains(&write.id))
+            .find_by_ids(ids)
+            .await
+    }
+
+    async fn find_by_subject(&self, subject: &str) -> DomainResult<Option<User>> {
+        self.reader(|write| write.subject.as_deref() == Some(subject))
+            .find_by_subject(subject)
+            .await
+    }
+
+    async fn find_by_email(&self, email: &str) -> DomainResult<Option<User>> {
+        self.reader(|write| write.email.as_deref() == Some(email))
+            .find_by_email(email)
+            .await
+    }
+
+    async fn exists_by_email(&self, email: &str) -> DomainResult<bool> {
+        self.reader(|write| write.email.as_deref() == Some(email))
+            .exists_by_email(email)
+            .await
+    }
+
+    async fn find_by_canonical_email(&self, canonical: &str) -> DomainResult<Option<User>> {
+        self.reader(|write| write.canonical_email.as_deref() == Some(canonical))
+            .find_by_canonical_email(canonical)
+            .await
+    }
+
+    async fn find_inactive_since(&self, cutoff: DateTime<Utc>) -> DomainResult<Vec<User>> {
+        self.replica.find_inactive_since(cutoff).await
+    }
+
+    async fn search(
+        &self,
+        term: &str,
+        match_mode: EmailMatchMode,
+        limit: u32,
+        offset: u32,
+    ) -> DomainResult<Vec<User>> {
+        self.replica.search(term, match_mode, limit, offset).await
+    }
+
+    async fn count_matching(&self, term: &str, match_mode: EmailMatchMode) -> DomainResult<u64> {
+        self.replica.count_matching(term, match_mode).await
+    }
+
+    async fn find(&self, filter: &UserFilter, limit: u32, offset: u32) -> DomainResult<Vec<User>> {
+        self.replica.find(filter, limit, offset).await
+    }
+
+    async fn save(&self, user: &User) -> DomainResult<()> {
+        self.primary.save(user).await?;
+        self.remember(user.id, Some(user));
+        Ok(())
+    }
+
+    async fn save_with_events(&self, user: &User, events: &[OutboxEvent]) -> DomainResult<()> {
+        self.primary.save_with_events(user, events).await?;
+        self.remember(user.id, Some(user));
+        Ok(())
+    }
+
+    async fn begin(&self) -> DomainResult<Box<dyn Transaction>> {
+        self.primary.begin().await
+    }
+
+    async fn save_in(
+        &self,
+        tx: &mut dyn Transaction,
+        user: &User,
+        events: &[OutboxEvent],
+    ) -> DomainResult<()> {
+        self.primary.save_in(tx, user, events).await?;
+        // Remembered before commit; a rolled-back user just reads from the primary for a while
+        self.remember(user.id, Some(user));
+        Ok(())
+    }
+
+    async fn update_display_name(
+        &self,
+        id: Uuid,
+        display_name: Option<&DisplayName>,
+    ) -> DomainResult<()> {
+        self.primary.update_display_name(id, display_name).await?;
+        self.remember(id, None);
+        Ok(())
+    }
+
+    async fn delete(&self, id: Uuid) -> DomainResult<()> {
+        self.primary.delete(id).await?;
+        self.remember(id, None);
+        Ok(())
+    }
+
+    async fn delete_in(&self, tx: &mut dyn Transaction, id: Uuid) -> DomainResult<()> {
+        self.primary.delete_in(tx, id).await?;
+        self.remember(id, None);
+        Ok(())
+    }
+
+    async fn claim_first_admin(
+        &self,
+        tx: &mut dyn Transaction,
+        user_id: Uuid,
+    ) -> DomainResult<bool> {
+        self.primary.claim_first_admin(tx, user_id).await
+    }
+
+    async fn claim_totp_step(&self, user_id: Uuid, step: u64) -> DomainResult<bool> {
+        self.primary.claim_totp_step(user_id, step).await
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::SqliteUserRepository;
+    use crate::db::{
+        ConnectionSettings, DatabaseConfig, DatabasePool, create_pool, run_migrations,
+    };
+    use domain::Email;
+
+    async fn setup_pool() -> Arc<SqliteUserRepository> {
+        let db_pool = create_pool(DatabaseConfig::default(), &ConnectionSettings::default())
+            .await
+            .expect("Failed to create pool");
+        run_migrations(&db_pool).await.unwrap();
+
+        match db_pool {
+            DatabasePool::Sqlite(pool) => Arc::new(SqliteUserRepository::new(pool)),
+        }
+    }
+
+    fn user(email: &str) -> User {
+        User::new(format!("oidc|{}", email), Email::try_from(email).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_writes_hit_primary_and_reads_hit_replica() {
+        let primary = setup_pool().await;
+        let replica = setup_pool().await;
+        let routing = RoutingUserRepository::new(primary.clone(), replica.clone())
+            .with_read_your_writes(Duration::ZERO);
+
+        let written = user("written@example.com");
+        routing.save(&written).await.unwrap();
+        assert!(primary.find_by_id(written.id).await.unwrap().is_some());
+        assert!(replica.find_by_id(written.id).await.unwrap().is_none());
+
+        let replicated = user("replicated@example.com");
+        replica.save(&replicated).await.unwrap();
+        let found = routing
+            .find_by_email("replicated@example.com")
+            .await
+            .unwrap();
+        assert_eq!(found.map(|u| u.id), Some(replicated.id));
+
+        // Outside the stickiness window the unreplicated write is not visible
+        assert!(routing.find_by_id(written.id).await.unwrap().is_none());
+        let count = routing.count_matching("", EmailMatchMode::Prefix).await;
+        assert_eq!(count.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_just_written_user_is_read_from_primary() {
+        let primary = setup_pool().await;
+        let replica = setup_pool().await;
+        let routing = RoutingUserRepository::new(primary, replica);
+
+        let registered = user("new@example.com");
+        routing.save_with_events(&registered, &[]).await.unwrap();
+
+        let by_id = routing.find_by_id(registered.id).await.unwrap();
+        assert_eq!(by_id.map(|u| u.id), Some(registered.id));
+        let by_email = routing.find_by_email("new@example.com").await.unwrap();
+        assert_eq!(by_email.map(|u| u.id), Some(registered.id));
+        let by_subject = routing.find_by_subject(&registered.subject).await.unwrap();
+        assert!(by_subject.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_reads_for_update_hit_primary() {
+        let primary = setup_pool().await;
+        let replica = setup_pool().await;
+        let routing = RoutingUserRepository::new(primary.clone(), replica.clone())
+            .with_read_your_writes(Duration::ZERO);
+
+        // The replica has yet to see the verification
+        let mut lagging = user("lagging@example.com");
+        replica.save(&lagging).await.unwrap();
+        lagging.mark_email_verified();
+        primary.save(&lagging).await.unwrap();
+
+        let read = routing.find_by_id(lagging.id).await.unwrap().unwrap();
+        assert!(!read.email_verified);
+        let for_update = routing
+            .find_by_id_for_update(lagging.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(for_update.email_verified);
+    }
+
+    #[tokio::test]
+    async fn test_writes_forget_users_past_the_window() {
+        let primary = setup_pool().await;
+        let replica = setup_pool().await;
+        let routing = RoutingUserRepository::new(primary, replica)
+            .with_read_your_writes(Duration::from_millis(20));
+
+        routing.save(&user("first@example.com")).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        routing.save(&user("second@example.com")).await.unwrap();
+
+        assert_eq!(routing.recent.lock().unwrap().len(), 1);
+    }
+}
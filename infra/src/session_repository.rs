@@ -0,0 +1,462 @@
+//! SQLite and PostgreSQL implementations of SessionRepository
+//!
+//! Metadata rows are joined with the session store's table, which holds the
+//! authoritative expiry.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, QueryBuilder};
+use uuid::Uuid;
+
+use crate::datetime::{format_db_datetime, parse_db_datetime};
+#[cfg(feature = "postgres")]
+use crate::transaction::PostgresTransaction;
+#[cfg(feature = "sqlite")]
+use crate::transaction::SqliteTransaction;
+use domain::{
+    DomainError, DomainResult, SessionFilter, SessionInfo, SessionRepository, Transaction,
+};
+
+/// SQLite adapter for SessionRepository
+#[cfg(feature = "sqlite")]
+#[derive(Clone)]
+pub struct SqliteSessionRepository {
+    pool: sqlx::SqlitePool,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteSessionRepository {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+/// Row type for session query results, minus the backend-specific expiry
+#[derive(Debug, FromRow)]
+struct SessionRow {
+    session_id: String,
+    user_id: String,
+    ip: Option<String>,
+    user_agent: Option<String>,
+    created_at: String,
+}
+
+impl SessionRow {
+    fn into_session(self, expires_at: DateTime<Utc>) -> DomainResult<SessionInfo> {
+        Ok(SessionInfo {
+            session_id: self.session_id,
+            user_id: Uuid::parse_str(&self.user_id)
+                .map_err(|e| DomainError::RepositoryError(format!("Invalid UUID: {}", e)))?,
+            ip: self.ip,
+            user_agent: self.user_agent,
+            created_at: parse_db_datetime(&self.created_at)?,
+            expires_at,
+        })
+    }
+}
+
+/// Columns selected for [`SessionRow`]
+const SESSION_COLUMNS: &str = "m.session_id, m.user_id, m.ip, m.user_agent, m.created_at";
+
+#[cfg(feature = "sqlite")]
+#[derive(Debug, FromRow)]
+struct SqliteSessionRow {
+    #[sqlx(flatten)]
+    row: SessionRow,
+    /// Unix seconds, from the text timestamp the SQLite session store writes
+    expiry_date: i64,
+}
+
+/// Active sessions matching `filter`, as a SQLite `FROM ... WHERE` clause
+#[cfg(feature = "sqlite")]
+fn push_sqlite_filters(
+    query: &mut QueryBuilder<'_, sqlx::Sqlite>,
+    filter: &SessionFilter,
+    now: DateTime<Utc>,
+) {
+    query
+        .push(" FROM session_metadata m JOIN tower_sessions s ON s.id = m.session_id")
+        .push(" WHERE julianday(s.expiry_date) > julianday(")
+        .push_bind(format_db_datetime(&now))
+        .push(")");
+    if let Some(user_id) = filter.user_id {
+        query
+            .push(" AND m.user_id = ")
+            .push_bind(user_id.to_string());
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl SessionRepository for SqliteSessionRepository {
+    async fn record(&self, session: &SessionInfo) -> DomainResult<()> {
+        sqlx::query(
+            "INSERT INTO session_metadata (session_id, user_id, ip, user_agent, created_at) VALUES (?, ?, ?, ?, ?)
+             ON CONFLICT(session_id) DO UPDATE SET user_id = excluded.user_id, ip = excluded.ip, user_agent = excluded.user_agent, created_at = excluded.created_at",
+        )
+        .bind(&session.session_id)
+        .bind(session.user_id.to_string())
+        .bind(&session.ip)
+        .bind(&session.user_agent)
+        .bind(format_db_datetime(&session.created_at))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn list(
+        &self,
+        filter: &SessionFilter,
+        now: DateTime<Utc>,
+        limit: u32,
+        offset: u32,
+    ) -> DomainResult<Vec<SessionInfo>> {
+        let mut query = QueryBuilder::<sqlx::Sqlite>::new(format!(
+            "SELECT {}, CAST(strftime('%s', s.expiry_date) AS INTEGER) AS expiry_date",
+            SESSION_COLUMNS
+        ));
+        push_sqlite_filters(&mut query, filter, now);
+        query
+            .push(" ORDER BY julianday(m.created_at) DESC, m.session_id LIMIT ")
+            .push_bind(i64::from(limit))
+            .push(" OFFSET ")
+            .push_bind(i64::from(offset));
+
+        let rows: Vec<SqliteSessionRow> = query
+            .build_query_as()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        rows.into_iter()
+            .map(|row| {
+                let expires_at = DateTime::from_timestamp(row.expiry_date, 0).ok_or_else(|| {
+                    DomainError::RepositoryError(format!(
+                        "Invalid session expiry: {}",
+                        row.expiry_date
+                    ))
+                })?;
+                row.row.into_session(expires_at)
+            })
+            .collect()
+    }
+
+    async fn count(&self, filter: &SessionFilter, now: DateTime<Utc>) -> DomainResult<u64> {
+        let mut query = QueryBuilder::<sqlx::Sqlite>::new("SELECT COUNT(*)");
+        push_sqlite_filters(&mut query, filter, now);
+
+        let count: i64 = query
+            .build_query_scalar()
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(count as u64)
+    }
+
+    async fn revoke(&self, session_id: &str) -> DomainResult<()> {
+        // The metadata row goes with it, by cascade
+        sqlx::query("DELETE FROM tower_sessions WHERE id = ?")
+            .bind(session_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn revoke_for_user_in(
+        &self,
+        tx: &mut dyn Transaction,
+        user_id: Uuid,
+    ) -> DomainResult<()> {
+        let tx = &mut SqliteTransaction::from_dyn(tx)?.0;
+        sqlx::query(
+            "DELETE FROM tower_sessions WHERE id IN (SELECT session_id FROM session_metadata WHERE user_id = ?)",
+        )
+        .bind(user_id.to_string())
+        .execute(&mut **tx)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::SqliteUserRepository;
+    use crate::db::{
+        ConnectionSettings, DatabaseConfig, DatabasePool, create_pool, run_migrations,
+    };
+    use domain::{Email, User, UserRepository};
+    use tower_sessions::SessionStore;
+    use tower_sessions::session::{Id, Record};
+
+    async fn setup_test_db() -> DatabasePool {
+        let db_pool = create_pool(DatabaseConfig::default(), &ConnectionSettings::default())
+            .await
+            .expect("Failed to create pool");
+        run_migrations(&db_pool).await.unwrap();
+        db_pool
+    }
+
+    async fn create_user(pool: &sqlx::SqlitePool, email: &str) -> User {
+        let user = User::new_local(Email::try_from(email).unwrap(), "hash");
+        SqliteUserRepository::new(pool.clone())
+            .save(&user)
+            .await
+            .unwrap();
+        user
+    }
+
+    /// Store a session expiring in `ttl` and return its id
+    async fn create_session(db_pool: &DatabasePool, ttl: time::Duration) -> Id {
+        let store = crate::factory::build_session_store(db_pool).await.unwrap();
+        let mut record = Record {
+            id: Id::default(),
+            data: Default::default(),
+            expiry_date: time::OffsetDateTime::now_utc() + ttl,
+        };
+        store.create(&mut record).await.unwrap();
+        record.id
+    }
+
+    fn session(session_id: Id, user: &User, ip: Option<&str>) -> SessionInfo {
+        SessionInfo {
+            session_id: session_id.to_string(),
+            user_id: user.id,
+            ip: ip.map(String::from),
+            user_agent: Some("curl/8.0".to_string()),
+            created_at: Utc::now(),
+            // Ignored by `record`
+            expires_at: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lists_active_sessions_with_metadata() {
+        let db_pool = setup_test_db().await;
+        let pool = match &db_pool {
+            DatabasePool::Sqlite(pool) => pool.clone(),
+        };
+        let alice = create_user(&pool, "alice@example.com").await;
+        let bob = create_user(&pool, "bob@example.com").await;
+        let repo = SqliteSessionRepository::new(pool);
+
+        let alice_session = create_session(&db_pool, time::Duration::hours(1)).await;
+        let bob_session = create_session(&db_pool, time::Duration::hours(2)).await;
+        let expired = create_session(&db_pool, time::Duration::hours(-1)).await;
+        repo.record(&session(alice_session, &alice, Some("203.0.113.7")))
+            .await
+            .unwrap();
+        repo.record(&session(bob_session, &bob, None))
+            .await
+            .unwrap();
+        repo.record(&session(expired, &alice, None)).await.unwrap();
+
+        let all = SessionFilter::default();
+        let sessions = repo.list(&all, Utc::now(), 10, 0).await.unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(repo.count(&all, Utc::now()).await.unwrap(), 2);
+
+        let found = sessions
+            .iter()
+            .find(|s| s.session_id == alice_session.to_string())
+            .unwrap();
+        assert_eq!(found.user_id, alice.id);
+        assert_eq!(found.ip.as_deref(), Some("203.0.113.7"));
+        assert_eq!(found.user_agent.as_deref(), Some("curl/8.0"));
+        let remaining = found.expires_at - Utc::now();
+        assert!(remaining > chrono::Duration::minutes(59), "{}", remaining);
+
+        let filter = SessionFilter {
+            user_id: Some(bob.id),
+        };
+        let sessions = repo.list(&filter, Utc::now(), 10, 0).await.unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].session_id, bob_session.to_string());
+        assert_eq!(sessions[0].ip, None);
+    }
+
+    #[tokio::test]
+    async fn test_revoke_ends_the_session() {
+        let db_pool = setup_test_db().await;
+        let pool = match &db_pool {
+            DatabasePool::Sqlite(pool) => pool.clone(),
+        };
+        let user = create_user(&pool, "revoked@example.com").await;
+        let repo = SqliteSessionRepository::new(pool);
+
+        let session_id = create_session(&db_pool, time::Duration::hours(1)).await;
+        repo.record(&session(session_id, &user, None))
+            .await
+            .unwrap();
+        repo.revoke(&session_id.to_string()).await.unwrap();
+
+        let store = crate::factory::build_session_store(&db_pool).await.unwrap();
+        assert!(store.load(&session_id).await.unwrap().is_none());
+        let all = SessionFilter::default();
+        assert_eq!(repo.count(&all, Utc::now()).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_metadata_is_removed_with_its_session() {
+        let db_pool = setup_test_db().await;
+        let pool = match &db_pool {
+            DatabasePool::Sqlite(pool) => pool.clone(),
+        };
+        let user = create_user(&pool, "gone@example.com").await;
+        let repo = SqliteSessionRepository::new(pool.clone());
+
+        let session_id = create_session(&db_pool, time::Duration::hours(1)).await;
+        repo.record(&session(session_id, &user, None))
+            .await
+            .unwrap();
+
+        let store = crate::factory::build_session_store(&db_pool).await.unwrap();
+        store.delete(&session_id).await.unwrap();
+
+        let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM session_metadata")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(remaining, 0);
+    }
+}
+
+/// PostgreSQL adapter for SessionRepository
+#[cfg(feature = "postgres")]
+#[derive(Clone)]
+pub struct PostgresSessionRepository {
+    pool: sqlx::Pool<sqlx::Postgres>,
+}
+
+#[cfg(feature = "postgres")]
+impl PostgresSessionRepository {
+    pub fn new(pool: sqlx::Pool<sqlx::Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[cfg(feature = "postgres")]
+#[derive(Debug, FromRow)]
+struct PostgresSessionRow {
+    #[sqlx(flatten)]
+    row: SessionRow,
+    expiry_date: DateTime<Utc>,
+}
+
+/// Active sessions matching `filter`, as a Postgres `FROM ... WHERE` clause
+#[cfg(feature = "postgres")]
+fn push_postgres_filters(
+    query: &mut QueryBuilder<'_, sqlx::Postgres>,
+    filter: &SessionFilter,
+    now: DateTime<Utc>,
+) {
+    query
+        .push(
+            r#" FROM session_metadata m JOIN "tower_sessions"."session" s ON s.id = m.session_id"#,
+        )
+        .push(" WHERE s.expiry_date > ")
+        .push_bind(now);
+    if let Some(user_id) = filter.user_id {
+        query
+            .push(" AND m.user_id = ")
+            .push_bind(user_id.to_string());
+    }
+}
+
+#[cfg(feature = "postgres")]
+#[async_trait]
+impl SessionRepository for PostgresSessionRepository {
+    async fn record(&self, session: &SessionInfo) -> DomainResult<()> {
+        sqlx::query(
+            "INSERT INTO session_metadata (session_id, user_id, ip, user_agent, created_at) VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (session_id) DO UPDATE SET user_id = EXCLUDED.user_id, ip = EXCLUDED.ip, user_agent = EXCLUDED.user_agent, created_at = EXCLUDED.created_at",
+        )
+        .bind(&session.session_id)
+        .bind(session.user_id.to_string())
+        .bind(&session.ip)
+        .bind(&session.user_agent)
+        .bind(format_db_datetime(&session.created_at))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn list(
+        &self,
+        filter: &SessionFilter,
+        now: DateTime<Utc>,
+        limit: u32,
+        offset: u32,
+    ) -> DomainResult<Vec<SessionInfo>> {
+        let mut query = QueryBuilder::<sqlx::Postgres>::new(format!(
+            "SELECT {}, s.expiry_date",
+            SESSION_COLUMNS
+        ));
+        push_postgres_filters(&mut query, filter, now);
+        query
+            .push(" ORDER BY m.created_at::timestamptz DESC, m.session_id LIMIT ")
+            .push_bind(i64::from(limit))
+            .push(" OFFSET ")
+            .push_bind(i64::from(offset));
+
+        let rows: Vec<PostgresSessionRow> = query
+            .build_query_as()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        rows.into_iter()
+            .map(|row| row.row.into_session(row.expiry_date))
+            .collect()
+    }
+
+    async fn count(&self, filter: &SessionFilter, now: DateTime<Utc>) -> DomainResult<u64> {
+        let mut query = QueryBuilder::<sqlx::Postgres>::new("SELECT COUNT(*)");
+        push_postgres_filters(&mut query, filter, now);
+
+        let count: i64 = query
+            .build_query_scalar()
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(count as u64)
+    }
+
+    async fn revoke(&self, session_id: &str) -> DomainResult<()> {
+        // The metadata row goes with it, by cascade
+        sqlx::query(r#"DELETE FROM "tower_sessions"."session" WHERE id = $1"#)
+            .bind(session_id)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn revoke_for_user_in(
+        &self,
+        tx: &mut dyn Transaction,
+        user_id: Uuid,
+    ) -> DomainResult<()> {
+        let tx = &mut PostgresTransaction::from_dyn(tx)?.0;
+        sqlx::query(
+            r#"DELETE FROM "tower_sessions"."session" WHERE id IN (SELECT session_id FROM session_metadata WHERE user_id = $1)"#,
+        )
+        .bind(user_id.to_string())
+        .execute(&mut **tx)
+        .await
+        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+}
@@ -1,2 +1,230 @@
+//! Session storage
+//!
+//! Re-exports the session store types and adds a round-trip health check.
+//!
+//! [`DegradingSessionStore`] keeps requests going while the store's backend
+//! is down: sessions that fail to load are served empty, so the request runs
+//! as anonymous, and [`track_load_failures`] tells the caller it happened.
+//! Within that request, the store refuses to write or delete those sessions,
+//! as the empty stand-in would otherwise replace the real one.
+
+use std::cell::RefCell;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
 pub use k_core::session::store::InfraSessionStore;
-pub use tower_sessions::{Expiry, SessionManagerLayer};
+pub use tower_sessions::session::{Id, Record};
+pub use tower_sessions::{
+    Expiry, MemoryStore, Session, SessionManagerLayer, SessionStore, session_store,
+};
+
+/// Health check for a session store, which may live on its own backend (e.g. Redis)
+#[async_trait]
+pub trait SessionStoreHealth: Send + Sync {
+    /// Save, load and delete a throwaway record
+    async fn health(&self) -> session_store::Result<()>;
+}
+
+#[async_trait]
+impl<S: SessionStore> SessionStoreHealth for S {
+    async fn health(&self) -> session_store::Result<()> {
+        let mut record = Record {
+            id: Id::default(),
+            data: Default::default(),
+            // Expires on its own should the delete below not go through
+            expiry_date: time::OffsetDateTime::now_utc() + time::Duration::minutes(1),
+        };
+        self.create(&mut record).await?;
+        let loaded = self.load(&record.id).await?;
+        self.delete(&record.id).await?;
+
+        match loaded {
+            Some(_) => Ok(()),
+            None => Err(session_store::Error::Backend(
+                "Health check record was not stored".to_string(),
+            )),
+        }
+    }
+}
+
+tokio::task_local! {
+    /// Sessions that failed to load during the current request
+    static LOAD_FAILED: RefCell<Vec<Id>>;
+}
+
+/// Run `future`, also reporting whether a [`DegradingSessionStore`] failed to
+/// load a session meanwhile
+pub async fn track_load_failures<F: Future>(future: F) -> (F::Output, bool) {
+    LOAD_FAILED
+        .scope(RefCell::default(), async move {
+            let output = future.await;
+            (
+                output,
+                LOAD_FAILED.with(|failed| !failed.borrow().is_empty()),
+            )
+        })
+        .await
+}
+
+/// Refuse to touch a session this request only saw as a stand-in
+fn ensure_loaded(session_id: &Id) -> session_store::Result<()> {
+    let failed = LOAD_FAILED
+        .try_with(|failed| failed.borrow().contains(session_id))
+        .unwrap_or(false);
+    if failed {
+        return Err(session_store::Error::Backend(
+            "Session failed to load in this request; leaving it as it is".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Session store that serves a session it cannot load as empty, instead of
+/// failing the request
+#[derive(Debug, Clone)]
+pub struct DegradingSessionStore {
+    inner: Arc<dyn SessionStore>,
+}
+
+impl DegradingSessionStore {
+    pub fn new(inner: impl SessionStore) -> Self {
+        Self {
+            inner: Arc::new(inner),
+        }
+    }
+}
+
+#[async_trait]
+impl SessionStore for DegradingSessionStore {
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        ensure_loaded(&record.id)?;
+        self.inner.create(record).await
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        ensure_loaded(&record.id)?;
+        self.inner.save(record).await
+    }
+
+    async fn load(&self, session_id: &Id) -> session_store::Result<Option<Record>> {
+        match self.inner.load(session_id).await {
+            Ok(record) => Ok(record),
+            Err(e) => {
+                tracing::warn!(error = %e, "Session store unavailable, continuing anonymously");
+                let _ = LOAD_FAILED.try_with(|failed| failed.borrow_mut().push(*session_id));
+                // Same id rather than none, so the client's cookie is not cleared
+                Ok(Some(Record {
+                    id: *session_id,
+                    data: Default::default(),
+                    expiry_date: time::OffsetDateTime::now_utc(),
+                }))
+            }
+        }
+    }
+
+    async fn delete(&self, session_id: &Id) -> session_store::Result<()> {
+        ensure_loaded(session_id)?;
+        self.inner.delete(session_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_store_is_healthy() {
+        let store = MemoryStore::default();
+        store.health().await.unwrap();
+    }
+
+    /// A memory store whose loads fail while `down` is set
+    #[derive(Debug, Clone, Default)]
+    struct FlakyStore {
+        inner: MemoryStore,
+        down: Arc<std::sync::atomic::AtomicBool>,
+    }
+
+    #[async_trait]
+    impl SessionStore for FlakyStore {
+        async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+            self.inner.create(record).await
+        }
+
+        async fn save(&self, record: &Record) -> session_store::Result<()> {
+            self.inner.save(record).await
+        }
+
+        async fn load(&self, session_id: &Id) -> session_store::Result<Option<Record>> {
+            if self.down.load(std::sync::atomic::Ordering::SeqCst) {
+                return Err(session_store::Error::Backend("down".to_string()));
+            }
+            self.inner.load(session_id).await
+        }
+
+        async fn delete(&self, session_id: &Id) -> session_store::Result<()> {
+            self.inner.delete(session_id).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session_that_failed_to_load_is_not_overwritten() {
+        let flaky = FlakyStore::default();
+        let store = DegradingSessionStore::new(flaky.clone());
+        let mut record = Record {
+            id: Id::default(),
+            data: [("user".to_string(), serde_json::json!("alice"))].into(),
+            expiry_date: time::OffsetDateTime::now_utc() + time::Duration::hours(1),
+        };
+        store.create(&mut record).await.unwrap();
+
+        flaky.down.store(true, std::sync::atomic::Ordering::SeqCst);
+        let (writes, failed) = track_load_failures(async {
+            let stand_in = store.load(&record.id).await.unwrap().unwrap();
+            assert!(stand_in.data.is_empty());
+            (
+                store.save(&stand_in).await,
+                store.delete(&stand_in.id).await,
+            )
+        })
+        .await;
+        assert!(failed);
+        assert!(writes.0.is_err() && writes.1.is_err());
+
+        flaky.down.store(false, std::sync::atomic::Ordering::SeqCst);
+        let kept = store.load(&record.id).await.unwrap().unwrap();
+        assert_eq!(kept.data, record.data);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_sqlite_store_is_healthy_and_left_clean() {
+        use crate::db::{ConnectionSettings, DatabaseConfig, DatabasePool, create_pool};
+
+        let config = DatabaseConfig {
+            url: "sqlite::memory:".to_string(),
+            max_connections: 1,
+            min_connections: 1,
+            acquire_timeout: std::time::Duration::from_secs(5),
+        };
+        let pool = create_pool(config, &ConnectionSettings::default())
+            .await
+            .unwrap();
+        crate::run_migrations(&pool).await.unwrap();
+
+        let store = crate::factory::build_session_store(&pool).await.unwrap();
+        store.health().await.unwrap();
+
+        let pool = match pool {
+            DatabasePool::Sqlite(pool) => pool,
+            #[allow(unreachable_patterns)]
+            _ => panic!("expected a SQLite pool"),
+        };
+        let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM tower_sessions")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(remaining, 0);
+    }
+}
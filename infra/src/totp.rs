@@ -0,0 +1,209 @@
+//! TOTP two-factor authentication adapters
+//!
+//! RFC 6238 codes via `totp-rs`, and AES-256-GCM encryption for the secrets
+//! stored on the user row.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use domain::{DomainError, DomainResult, SecretCipher, TotpProvider};
+
+const DIGITS: usize = 6;
+const STEP_SECS: u64 = 30;
+/// Accept codes from one step either side of the current one, to tolerate clock drift
+const SKEW_STEPS: u8 = 1;
+const NONCE_LEN: usize = 12;
+
+fn infra_error(e: impl std::fmt::Display) -> DomainError {
+    DomainError::InfrastructureError(e.to_string())
+}
+
+/// Standard authenticator-app TOTP (SHA-1, 6 digits, 30 second step)
+#[derive(Debug, Clone)]
+pub struct TotpRsProvider {
+    issuer: String,
+}
+
+impl TotpRsProvider {
+    pub fn new(issuer: impl Into<String>) -> Self {
+        Self {
+            issuer: issuer.into(),
+        }
+    }
+
+    /// `skew` is the number of steps either side that `check` accepts
+    fn totp(&self, secret: &str, account: &str, skew: u8) -> DomainResult<TOTP> {
+        let secret = Secret::Encoded(secret.to_string())
+            .to_bytes()
+            .map_err(infra_error)?;
+        TOTP::new(
+            Algorithm::SHA1,
+            DIGITS,
+            skew,
+            STEP_SECS,
+            secret,
+            Some(self.issuer.clone()),
+            account.to_string(),
+        )
+        .map_err(infra_error)
+    }
+}
+
+impl TotpProvider for TotpRsProvider {
+    fn generate_secret(&self) -> DomainResult<String> {
+        Ok(Secret::generate_secret().to_encoded().to_string())
+    }
+
+    fn provisioning_uri(&self, secret: &str, account: &str) -> DomainResult<String> {
+        Ok(self.totp(secret, account, SKEW_STEPS)?.get_url())
+    }
+
+    fn verify(&self, secret: &str, code: &str) -> DomainResult<Option<u64>> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(infra_error)?
+            .as_secs();
+        // Checked one step at a time to learn which step matched
+        let totp = self.totp(secret, "verify", 0)?;
+        Ok(matching_step(&totp, code.trim(), now))
+    }
+}
+
+/// The step within [`SKEW_STEPS`] of `time` that `code` was generated for
+fn matching_step(totp: &TOTP, code: &str, time: u64) -> Option<u64> {
+    let current = time / STEP_SECS;
+    let skew = u64::from(SKEW_STEPS);
+    (current.saturating_sub(skew)..=current + skew).find(|step| totp.check(code, step * STEP_SECS))
+}
+
+/// AES-256-GCM cipher; ciphertexts are stored as hex of `nonce || ciphertext`
+#[derive(Clone)]
+pub struct AesGcmCipher {
+    cipher: Aes256Gcm,
+}
+
+impl AesGcmCipher {
+    /// Build from a 32-byte key given as 64 hex characters
+    pub fn from_hex(key: &str) -> anyhow::Result<Self> {
+        let key = hex::decode(key.trim())
+            .map_err(|e| anyhow::anyhow!("Invalid TOTP encryption key: {}", e))?;
+        let cipher = Aes256Gcm::new_from_slice(&key)
+            .map_err(|_| anyhow::anyhow!("TOTP encryption key must be 32 bytes"))?;
+        Ok(Self { cipher })
+    }
+}
+
+impl SecretCipher for AesGcmCipher {
+    fn encrypt(&self, plaintext: &str) -> DomainResult<String> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(infra_error)?;
+
+        let mut stored = nonce.to_vec();
+        stored.extend_from_slice(&ciphertext);
+        Ok(hex::encode(stored))
+    }
+
+    fn decrypt(&self, ciphertext: &str) -> DomainResult<String> {
+        let bytes = hex::decode(ciphertext).map_err(infra_error)?;
+        if bytes.len() <= NONCE_LEN {
+            return Err(infra_error("Encrypted secret is too short"));
+        }
+
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| infra_error("Failed to decrypt secret"))?;
+        String::from_utf8(plaintext).map_err(infra_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[test]
+    fn test_current_and_skewed_codes_verify() {
+        let provider = TotpRsProvider::new("k-template");
+        let secret = provider.generate_secret().unwrap();
+        let totp = provider.totp(&secret, "user@example.com", 0).unwrap();
+
+        assert!(
+            provider
+                .verify(&secret, &totp.generate(now()))
+                .unwrap()
+                .is_some()
+        );
+        // One step behind, as from a slightly slow phone clock
+        let previous = totp.generate(now() - STEP_SECS);
+        assert!(provider.verify(&secret, &previous).unwrap().is_some());
+
+        let stale = totp.generate(now() - 5 * STEP_SECS);
+        assert_eq!(provider.verify(&secret, &stale).unwrap(), None);
+    }
+
+    #[test]
+    fn test_verify_reports_the_matching_step() {
+        let provider = TotpRsProvider::new("k-template");
+        let secret = provider.generate_secret().unwrap();
+        let totp = provider.totp(&secret, "user@example.com", 0).unwrap();
+        let time = 1_700_000_000;
+        let step = time / STEP_SECS;
+
+        let current = totp.generate(time);
+        assert_eq!(matching_step(&totp, &current, time), Some(step));
+        let previous = totp.generate(time - STEP_SECS);
+        assert_eq!(matching_step(&totp, &previous, time), Some(step - 1));
+        let next = totp.generate(time + STEP_SECS);
+        assert_eq!(matching_step(&totp, &next, time), Some(step + 1));
+    }
+
+    #[test]
+    fn test_provisioning_uri() {
+        let provider = TotpRsProvider::new("k-template");
+        let secret = provider.generate_secret().unwrap();
+        let uri = provider
+            .provisioning_uri(&secret, "user@example.com")
+            .unwrap();
+
+        assert!(uri.starts_with("otpauth://totp/"));
+        assert!(uri.contains(&secret));
+        assert!(uri.contains("issuer=k-template"));
+    }
+
+    #[test]
+    fn test_cipher_round_trip() {
+        let cipher = AesGcmCipher::from_hex(KEY).unwrap();
+        let encrypted = cipher.encrypt("JBSWY3DPEHPK3PXP").unwrap();
+
+        assert!(!encrypted.contains("JBSWY3DPEHPK3PXP"));
+        assert_ne!(encrypted, cipher.encrypt("JBSWY3DPEHPK3PXP").unwrap());
+        assert_eq!(cipher.decrypt(&encrypted).unwrap(), "JBSWY3DPEHPK3PXP");
+    }
+
+    #[test]
+    fn test_cipher_rejects_tampering_and_bad_keys() {
+        let cipher = AesGcmCipher::from_hex(KEY).unwrap();
+        let mut encrypted = cipher.encrypt("secret").unwrap();
+        let last = encrypted.pop().unwrap();
+        encrypted.push(if last == '0' { '1' } else { '0' });
+
+        assert!(cipher.decrypt(&encrypted).is_err());
+        assert!(AesGcmCipher::from_hex("abcd").is_err());
+    }
+}
@@ -0,0 +1,280 @@
+//! sqlx-backed transactions for the domain `Transaction` port
+//!
+//! Registration hooks that write their own tables downcast the domain
+//! transaction with `from_dyn` and run queries on the inner sqlx transaction.
+
+use std::any::Any;
+
+use async_trait::async_trait;
+
+use domain::{DomainError, DomainResult, Transaction};
+
+/// Callbacks registered through [`Transaction::after_commit`]
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+type AfterCommit = Vec<Box<dyn FnOnce() + Send>>;
+
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+fn run_after_commit(callbacks: AfterCommit) {
+    for callback in callbacks {
+        callback();
+    }
+}
+
+/// An open SQLite transaction, with the callbacks to run once it commits
+#[cfg(feature = "sqlite")]
+pub struct SqliteTransaction(pub sqlx::Transaction<'static, sqlx::Sqlite>, AfterCommit);
+
+#[cfg(feature = "sqlite")]
+impl SqliteTransaction {
+    pub fn new(tx: sqlx::Transaction<'static, sqlx::Sqlite>) -> Self {
+        Self(tx, Vec::new())
+    }
+
+    /// The SQLite transaction behind a domain transaction
+    pub fn from_dyn(tx: &mut dyn Transaction) -> DomainResult<&mut Self> {
+        tx.as_any_mut()
+            .downcast_mut()
+            .ok_or_else(|| DomainError::InfrastructureError("Not a SQLite transaction".into()))
+    }
+}
+
+#[cfg(feature = "sqlite")]
+#[async_trait]
+impl Transaction for SqliteTransaction {
+    fn as_any_mut(&mut self) -> &mut (dyn Any + Send) {
+        self
+    }
+
+    fn after_commit(&mut self, callback: Box<dyn FnOnce() + Send>) {
+        self.1.push(callback);
+    }
+
+    async fn commit(self: Box<Self>) -> DomainResult<()> {
+        let Self(tx, callbacks) = *self;
+        tx.commit()
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+        run_after_commit(callbacks);
+        Ok(())
+    }
+
+    async fn rollback(self: Box<Self>) -> DomainResult<()> {
+        self.0
+            .rollback()
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))
+    }
+}
+
+/// An open Postgres transaction, with the callbacks to run once it commits
+#[cfg(feature = "postgres")]
+pub struct PostgresTransaction(pub sqlx::Transaction<'static, sqlx::Postgres>, AfterCommit);
+
+#[cfg(feature = "postgres")]
+impl PostgresTransaction {
+    pub fn new(tx: sqlx::Transaction<'static, sqlx::Postgres>) -> Self {
+        Self(tx, Vec::new())
+    }
+
+    /// The Postgres transaction behind a domain transaction
+    pub fn from_dyn(tx: &mut dyn Transaction) -> DomainResult<&mut Self> {
+        tx.as_any_mut()
+            .downcast_mut()
+            .ok_or_else(|| DomainError::InfrastructureError("Not a Postgres transaction".into()))
+    }
+}
+
+#[cfg(feature = "postgres")]
+#[async_trait]
+impl Transaction for PostgresTransaction {
+    fn as_any_mut(&mut self) -> &mut (dyn Any + Send) {
+        self
+    }
+
+    fn after_commit(&mut self, callback: Box<dyn FnOnce() + Send>) {
+        self.1.push(callback);
+    }
+
+    async fn commit(self: Box<Self>) -> DomainResult<()> {
+        let Self(tx, callbacks) = *self;
+        tx.commit()
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+        run_after_commit(callbacks);
+        Ok(())
+    }
+
+    async fn rollback(self: Box<Self>) -> DomainResult<()> {
+        self.0
+            .rollback()
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::db::{
+        ConnectionSettings, DatabaseConfig, DatabasePool, create_pool, run_migrations,
+    };
+    use crate::{SqliteApiKeyRepository, SqliteAuditLogRepository, SqliteUserRepository};
+    use domain::{Email, RegistrationHook, User, UserRepository, UserService};
+
+    async fn setup_test_db() -> sqlx::SqlitePool {
+        let db_pool = create_pool(DatabaseConfig::default(), &ConnectionSettings::default())
+            .await
+            .expect("Failed to create pool");
+        run_migrations(&db_pool).await.unwrap();
+
+        let pool = match db_pool {
+            DatabasePool::Sqlite(pool) => pool,
+            #[allow(unreachable_patterns)]
+            _ => panic!("expected a SQLite pool"),
+        };
+        sqlx::query("CREATE TABLE workspaces (owner_id TEXT NOT NULL)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool
+    }
+
+    /// Creates a default workspace, then fails when `fail` is set
+    struct WorkspaceHook {
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl RegistrationHook for WorkspaceHook {
+        async fn on_register(&self, tx: &mut dyn Transaction, user: &User) -> DomainResult<()> {
+            let tx = &mut SqliteTransaction::from_dyn(tx)?.0;
+            sqlx::query("INSERT INTO workspaces (owner_id) VALUES (?)")
+                .bind(user.id.to_string())
+                .execute(&mut **tx)
+                .await
+                .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+            if self.fail {
+                return Err(DomainError::validation("Workspace quota exceeded"));
+            }
+            Ok(())
+        }
+    }
+
+    fn service(pool: &sqlx::SqlitePool, hook: WorkspaceHook) -> UserService {
+        UserService::new(
+            Arc::new(SqliteUserRepository::new(pool.clone())),
+            Arc::new(SqliteApiKeyRepository::new(pool.clone())),
+            Arc::new(SqliteAuditLogRepository::new(pool.clone())),
+        )
+        .with_registration_hook(Arc::new(hook))
+    }
+
+    async fn counts(pool: &sqlx::SqlitePool) -> (i64, i64) {
+        let users: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        let workspaces: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM workspaces")
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        (users, workspaces)
+    }
+
+    #[tokio::test]
+    async fn test_hook_records_commit_with_the_user() {
+        let pool = setup_test_db().await;
+        let service = service(&pool, WorkspaceHook { fail: false });
+
+        let user = service
+            .find_or_create("oidc|1", "new@example.com")
+            .await
+            .unwrap();
+
+        assert_eq!(counts(&pool).await, (1, 1));
+        let owner: String = sqlx::query_scalar("SELECT owner_id FROM workspaces")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(owner, user.id.to_string());
+    }
+
+    #[tokio::test]
+    async fn test_failing_hook_rolls_back_the_user() {
+        let pool = setup_test_db().await;
+        let service = service(&pool, WorkspaceHook { fail: true });
+
+        let result = service.find_or_create("oidc|1", "new@example.com").await;
+        assert!(matches!(result, Err(DomainError::ValidationError(_))));
+
+        assert_eq!(counts(&pool).await, (0, 0));
+        let outbox: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM outbox")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(outbox, 0);
+    }
+
+    #[tokio::test]
+    async fn test_merge_keeps_duplicate_keys_on_the_primary() {
+        let pool = setup_test_db().await;
+        let service = service(&pool, WorkspaceHook { fail: false });
+        let users = SqliteUserRepository::new(pool.clone());
+        let primary = User::new_local(Email::try_from("primary@example.com").unwrap(), "hash");
+        users.save(&primary).await.unwrap();
+        let duplicate = service
+            .find_or_create("other|1", "duplicate@example.com")
+            .await
+            .unwrap();
+        let (key_id, _) = service.create_api_key(duplicate.id, None).await.unwrap();
+
+        service
+            .merge_accounts(primary.id, duplicate.id, false)
+            .await
+            .unwrap();
+
+        let keys = service.list_api_keys(primary.id).await.unwrap();
+        assert_eq!(keys.iter().map(|k| k.id).collect::<Vec<_>>(), vec![key_id]);
+        assert!(
+            service
+                .list_api_keys(duplicate.id)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert!(service.find_by_id(duplicate.id).await.unwrap().is_deleted());
+        let found = service.find_by_email("primary@example.com").await.unwrap();
+        assert_eq!(found.map(|u| u.id), Some(primary.id));
+        // The unique subject index lets the primary take the duplicate's
+        let found = users.find_by_subject("other|1").await.unwrap();
+        assert_eq!(found.map(|u| u.id), Some(primary.id));
+    }
+
+    #[tokio::test]
+    async fn test_reused_email_passes_the_unique_index() {
+        let pool = setup_test_db().await;
+        let service = service(&pool, WorkspaceHook { fail: false }).with_deleted_email_reuse(true);
+        let mut deleted = service
+            .find_or_create("oidc|1", "reused@example.com")
+            .await
+            .unwrap();
+        deleted.deleted_at = Some(chrono::Utc::now());
+        SqliteUserRepository::new(pool.clone())
+            .save(&deleted)
+            .await
+            .unwrap();
+
+        let user = service
+            .find_or_create("oidc|2", "reused@example.com")
+            .await
+            .unwrap();
+
+        assert_ne!(user.id, deleted.id);
+        assert_eq!(counts(&pool).await, (2, 2));
+        let found = service.find_by_email("reused@example.com").await.unwrap();
+        assert_eq!(found.map(|u| u.id), Some(user.id));
+    }
+}
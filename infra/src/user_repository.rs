@@ -2,10 +2,23 @@
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use sqlx::{FromRow, SqlitePool};
+#[cfg(feature = "sqlite")]
+use sqlx::SqlitePool;
+use sqlx::query::Query;
+use sqlx::{Database, Encode, FromRow, QueryBuilder, Type};
 use uuid::Uuid;
 
-use domain::{DomainError, DomainResult, Email, User, UserRepository};
+use crate::datetime::{format_db_datetime, parse_db_datetime_in, parse_optional_db_datetime_in};
+use crate::dialect::Dialect;
+use crate::outbox_repository;
+#[cfg(feature = "postgres")]
+use crate::transaction::PostgresTransaction;
+#[cfg(feature = "sqlite")]
+use crate::transaction::SqliteTransaction;
+use domain::{
+    DisplayName, DomainError, DomainResult, Email, EmailMatchMode, OutboxEvent, Role, Transaction,
+    User, UserFilter, UserRepository,
+};
 
 /// SQLite adapter for UserRepository
 #[cfg(feature = "sqlite")]
@@ -19,6 +32,303 @@ impl SqliteUserRepository {
     pub fn new(pool: SqlitePool) -> Self {
         Self { pool }
     }
+
+    /// Insert or update a user using any executor, so it can join a transaction
+    async fn upsert<'e, E>(executor: E, user: &User) -> Result<(), sqlx::Error>
+    where
+        E: sqlx::SqliteExecutor<'e>,
+    {
+        bind_user(sqlx::query(&upsert_user_sql(Dialect::Sqlite)), user)
+            .execute(executor)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Columns selected for [`UserRow`], in the order [`bind_user`] binds them
+const USER_COLUMNS: [&str; 14] = [
+    "id",
+    "subject",
+    "email",
+    "password_hash",
+    "email_verified",
+    "role",
+    "created_at",
+    "last_login_at",
+    "deleted_at",
+    "totp_secret",
+    "totp_enabled",
+    "canonical_email",
+    "preferences",
+    "display_name",
+];
+
+/// Postgres type of the user columns that aren't `TEXT` or `BOOLEAN`.
+///
+/// Their values are bound as text and cast on write, and selected back as
+/// text so [`UserRow`] reads the same on both backends.
+fn postgres_type(column: &str) -> Option<&'static str> {
+    match column {
+        "id" => Some("uuid"),
+        "created_at" => Some("timestamptz"),
+        "preferences" => Some("jsonb"),
+        _ => None,
+    }
+}
+
+/// [`USER_COLUMNS`] as a select list
+fn user_columns(dialect: Dialect) -> String {
+    USER_COLUMNS
+        .iter()
+        .map(|column| match (dialect, postgres_type(column)) {
+            // `::text` would render `+00` offsets, which aren't RFC3339
+            (Dialect::Postgres, Some("timestamptz")) => {
+                format!("to_json({column}) #>> '{{}}' AS {column}")
+            }
+            (Dialect::Postgres, Some(_)) => format!("{column}::text AS {column}"),
+            _ => column.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Placeholder for the `n`th parameter, holding a value of `column`
+fn user_param(dialect: Dialect, column: &str, n: usize) -> String {
+    match (dialect, postgres_type(column)) {
+        (Dialect::Postgres, Some(ty)) => format!("{}::{}", dialect.placeholder(n), ty),
+        _ => dialect.placeholder(n),
+    }
+}
+
+/// Bind every column of [`USER_COLUMNS`] for `user`, in order.
+///
+/// Shared by both backends, so a new column can't be bound on one and
+/// forgotten on the other.
+fn bind_user<'q, DB>(
+    query: Query<'q, DB, <DB as Database>::Arguments<'q>>,
+    user: &User,
+) -> Query<'q, DB, <DB as Database>::Arguments<'q>>
+where
+    DB: Database,
+    String: Encode<'q, DB> + Type<DB>,
+    Option<String>: Encode<'q, DB> + Type<DB>,
+    bool: Encode<'q, DB> + Type<DB>,
+{
+    query
+        .bind(user.id.to_string())
+        .bind(user.subject.clone())
+        .bind(user.email.as_ref().to_string())
+        .bind(user.password_hash.clone())
+        .bind(user.email_verified)
+        .bind(user.role.as_str().to_string())
+        .bind(format_db_datetime(&user.created_at))
+        .bind(user.last_login_at.as_ref().map(format_db_datetime))
+        .bind(user.deleted_at.as_ref().map(format_db_datetime))
+        .bind(user.totp_secret.clone())
+        .bind(user.totp_enabled)
+        .bind(user.canonical_email.clone())
+        .bind(user.preferences.to_string())
+        .bind(
+            user.display_name
+                .as_ref()
+                .map(|name| name.as_ref().to_string()),
+        )
+}
+
+/// `SELECT` of the user whose unique `column` equals the one parameter
+fn select_user_by(dialect: Dialect, column: &str) -> String {
+    format!(
+        "SELECT {} FROM users WHERE {} = {}",
+        user_columns(dialect),
+        column,
+        user_param(dialect, column, 1)
+    )
+}
+
+fn exists_by_email_sql(dialect: Dialect) -> String {
+    format!(
+        "SELECT EXISTS(SELECT 1 FROM users WHERE email = {})",
+        dialect.placeholder(1)
+    )
+}
+
+/// Insert or update a user, binding every column of [`USER_COLUMNS`] in order.
+///
+/// An update rewrites every column except the id and creation time.
+fn upsert_user_sql(dialect: Dialect) -> String {
+    let updates = USER_COLUMNS
+        .iter()
+        .filter(|column| !matches!(**column, "id" | "created_at"))
+        .map(|column| format!("{column} = excluded.{column}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let values = USER_COLUMNS
+        .iter()
+        .enumerate()
+        .map(|(i, column)| user_param(dialect, column, i + 1))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "INSERT INTO users ({}) VALUES ({}) ON CONFLICT(id) DO UPDATE SET {}",
+        USER_COLUMNS.join(", "),
+        values,
+        updates
+    )
+}
+
+/// Email search binding the pattern, limit and offset
+fn search_users_sql(dialect: Dialect) -> String {
+    format!(
+        "SELECT {} FROM users WHERE email LIKE {} ESCAPE '\\' ORDER BY email LIMIT {} OFFSET {}",
+        user_columns(dialect),
+        dialect.placeholder(1),
+        dialect.placeholder(2),
+        dialect.placeholder(3)
+    )
+}
+
+/// Count of [`search_users_sql`] matches, binding the pattern
+fn count_matching_sql(dialect: Dialect) -> String {
+    format!(
+        "SELECT COUNT(*) FROM users WHERE email LIKE {} ESCAPE '\\'",
+        dialect.placeholder(1)
+    )
+}
+
+/// Claim of the first-admin bootstrap, binding the user id and claim time.
+///
+/// Inserts nothing once any user exists or the row was already claimed; a
+/// concurrent claim waits on the primary key and then conflicts.
+fn claim_first_admin_sql(dialect: Dialect) -> String {
+    format!(
+        r#"
+        INSERT INTO admin_bootstrap (id, user_id, claimed_at)
+        SELECT 1, {}, {}
+        WHERE NOT EXISTS (SELECT 1 FROM users)
+        ON CONFLICT(id) DO NOTHING
+        "#,
+        dialect.placeholder(1),
+        dialect.placeholder(2)
+    )
+}
+
+/// Record a TOTP step, binding the step, user id and step again.
+///
+/// Matches no row once that step or a later one was recorded, so of two
+/// concurrent claims only the first to lock the row succeeds.
+fn claim_totp_step_sql(dialect: Dialect) -> String {
+    format!(
+        "UPDATE users SET totp_last_step = {} WHERE id = {} AND (totp_last_step IS NULL OR totp_last_step < {})",
+        dialect.placeholder(1),
+        user_param(dialect, "id", 2),
+        dialect.placeholder(3)
+    )
+}
+
+/// Display name update binding the name and user id
+fn update_display_name_sql(dialect: Dialect) -> String {
+    format!(
+        "UPDATE users SET display_name = {} WHERE id = {}",
+        dialect.placeholder(1),
+        user_param(dialect, "id", 2)
+    )
+}
+
+/// Hard delete binding the user id
+fn delete_user_sql(dialect: Dialect) -> String {
+    format!(
+        "DELETE FROM users WHERE id = {}",
+        user_param(dialect, "id", 1)
+    )
+}
+
+/// Maximum number of IDs bound in a single `IN (...)` query.
+///
+/// Kept well below SQLite's (32766, or 999 on old builds) and Postgres' (65535) parameter limits.
+const MAX_IDS_PER_QUERY: usize = 500;
+
+/// Deduplicate IDs and split them into query-sized chunks
+fn id_chunks(ids: &[Uuid]) -> Vec<Vec<String>> {
+    let mut unique: Vec<String> = ids.iter().map(Uuid::to_string).collect();
+    unique.sort();
+    unique.dedup();
+    unique
+        .chunks(MAX_IDS_PER_QUERY)
+        .map(<[String]>::to_vec)
+        .collect()
+}
+
+/// Build a `LIKE ... ESCAPE '\'` pattern matching `term` literally
+fn like_pattern(term: &str, match_mode: EmailMatchMode) -> String {
+    let escaped = term
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    match match_mode {
+        EmailMatchMode::Prefix => format!("{}%", escaped),
+        EmailMatchMode::Contains => format!("%{}%", escaped),
+    }
+}
+
+/// Append `WHERE` conditions for `filter` to a SQLite query
+#[cfg(feature = "sqlite")]
+fn push_sqlite_filters(query: &mut QueryBuilder<'_, sqlx::Sqlite>, filter: &UserFilter) {
+    query.push(" WHERE 1 = 1");
+    if let Some(role) = filter.role {
+        query.push(" AND role = ").push_bind(role.as_str());
+    }
+    if let Some(active) = filter.is_active {
+        query.push(if active {
+            " AND deleted_at IS NULL"
+        } else {
+            " AND deleted_at IS NOT NULL"
+        });
+    }
+    if let Some((from, to)) = filter.created_between {
+        query
+            .push(" AND julianday(created_at) >= julianday(")
+            .push_bind(format_db_datetime(&from))
+            .push(") AND julianday(created_at) < julianday(")
+            .push_bind(format_db_datetime(&to))
+            .push(")");
+    }
+    if let Some(term) = &filter.email_contains {
+        query
+            .push(" AND email LIKE ")
+            .push_bind(like_pattern(&term.to_lowercase(), EmailMatchMode::Contains))
+            .push(" ESCAPE '\\'");
+    }
+}
+
+/// Append `WHERE` conditions for `filter` to a PostgreSQL query
+#[cfg(feature = "postgres")]
+fn push_postgres_filters(query: &mut QueryBuilder<'_, sqlx::Postgres>, filter: &UserFilter) {
+    query.push(" WHERE 1 = 1");
+    if let Some(role) = filter.role {
+        query.push(" AND role = ").push_bind(role.as_str());
+    }
+    if let Some(active) = filter.is_active {
+        query.push(if active {
+            " AND deleted_at IS NULL"
+        } else {
+            " AND deleted_at IS NOT NULL"
+        });
+    }
+    if let Some((from, to)) = filter.created_between {
+        query
+            .push(" AND created_at >= ")
+            .push_bind(format_db_datetime(&from))
+            .push("::timestamptz AND created_at < ")
+            .push_bind(format_db_datetime(&to))
+            .push("::timestamptz");
+    }
+    if let Some(term) = &filter.email_contains {
+        query
+            .push(" AND email LIKE ")
+            .push_bind(like_pattern(&term.to_lowercase(), EmailMatchMode::Contains))
+            .push(" ESCAPE '\\'");
+    }
 }
 
 /// Row type for SQLite query results
@@ -28,107 +338,306 @@ struct UserRow {
     subject: String,
     email: String,
     password_hash: Option<String>,
+    email_verified: bool,
+    role: String,
     created_at: String,
+    last_login_at: Option<String>,
+    deleted_at: Option<String>,
+    totp_secret: Option<String>,
+    totp_enabled: bool,
+    canonical_email: Option<String>,
+    preferences: String,
+    display_name: Option<String>,
 }
 
 impl TryFrom<UserRow> for User {
     type Error = DomainError;
 
     fn try_from(row: UserRow) -> Result<Self, Self::Error> {
-        let id = Uuid::parse_str(&row.id)
-            .map_err(|e| DomainError::RepositoryError(format!("Invalid UUID: {}", e)))?;
-        let created_at = DateTime::parse_from_rfc3339(&row.created_at)
-            .map(|dt| dt.with_timezone(&Utc))
-            .or_else(|_| {
-                // Fallback for SQLite datetime format
-                chrono::NaiveDateTime::parse_from_str(&row.created_at, "%Y-%m-%d %H:%M:%S")
-                    .map(|dt| dt.and_utc())
-            })
-            .map_err(|e| DomainError::RepositoryError(format!("Invalid datetime: {}", e)))?;
+        let id = Uuid::parse_str(&row.id).map_err(|e| {
+            DomainError::RepositoryError(format!("Invalid UUID '{}': {}", row.id, e))
+        })?;
+        let created_at = parse_db_datetime_in(&row.created_at, "users.created_at", &row.id)?;
+        let last_login_at = parse_optional_db_datetime_in(
+            row.last_login_at.as_deref(),
+            "users.last_login_at",
+            &row.id,
+        )?;
+        let deleted_at =
+            parse_optional_db_datetime_in(row.deleted_at.as_deref(), "users.deleted_at", &row.id)?;
+
+        let preferences = serde_json::from_str(&row.preferences).map_err(|e| {
+            DomainError::RepositoryError(format!(
+                "Invalid preferences in DB for user {}: {}",
+                row.id, e
+            ))
+        })?;
+
+        let role: Role = row.role.parse().map_err(|e| {
+            DomainError::RepositoryError(format!("Invalid role in DB for user {}: {}", row.id, e))
+        })?;
 
         // Parse email from string - it was validated when originally stored
-        let email = Email::try_from(row.email)
-            .map_err(|e| DomainError::RepositoryError(format!("Invalid email in DB: {}", e)))?;
+        let email = Email::try_from(row.email).map_err(|e| {
+            DomainError::RepositoryError(format!("Invalid email in DB for user {}: {}", row.id, e))
+        })?;
 
-        Ok(User::with_id(
+        let display_name = row
+            .display_name
+            .map(DisplayName::new)
+            .transpose()
+            .map_err(|e| {
+                DomainError::RepositoryError(format!(
+                    "Invalid display name in DB for user {}: {}",
+                    row.id, e
+                ))
+            })?;
+
+        let mut user = User::reconstitute(
             id,
             row.subject,
             email,
             row.password_hash,
+            row.email_verified,
+            role,
             created_at,
-        ))
+        )?;
+        user.last_login_at = last_login_at;
+        user.deleted_at = deleted_at;
+        user.totp_secret = row.totp_secret;
+        user.totp_enabled = row.totp_enabled;
+        user.canonical_email = row.canonical_email;
+        user.preferences = preferences;
+        user.display_name = display_name;
+
+        Ok(user)
     }
 }
 
+/// Map a failed write to a domain error, identifying which unique constraint was hit
+fn map_save_error(error: sqlx::Error, user: &User) -> DomainError {
+    if let Some(db_error) = error.as_database_error()
+        && db_error.is_unique_violation()
+    {
+        // Postgres reports the index name, SQLite only names the column in the message
+        let target = db_error.constraint().unwrap_or_else(|| db_error.message());
+        if target.contains("email") {
+            return DomainError::EmailAlreadyExists(user.email_str().to_string());
+        }
+        if target.contains("subject") {
+            return DomainError::SubjectAlreadyExists(user.subject.clone());
+        }
+        return DomainError::UserAlreadyExists(user.email_str().to_string());
+    }
+
+    DomainError::RepositoryError(error.to_string())
+}
+
 #[cfg(feature = "sqlite")]
 #[async_trait]
 impl UserRepository for SqliteUserRepository {
     async fn find_by_id(&self, id: Uuid) -> DomainResult<Option<User>> {
         let id_str = id.to_string();
-        let row: Option<UserRow> = sqlx::query_as(
-            "SELECT id, subject, email, password_hash, created_at FROM users WHERE id = ?",
-        )
-        .bind(&id_str)
-        .fetch_optional(&self.pool)
-        .await
-        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+        let row: Option<UserRow> = sqlx::query_as(&select_user_by(Dialect::Sqlite, "id"))
+            .bind(&id_str)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
 
         row.map(User::try_from).transpose()
     }
 
+    async fn find_by_id_for_update(&self, id: Uuid) -> DomainResult<Option<User>> {
+        self.find_by_id(id).await
+    }
+
+    async fn find_by_ids(&self, ids: &[Uuid]) -> DomainResult<Vec<User>> {
+        let mut users = Vec::with_capacity(ids.len());
+
+        for chunk in id_chunks(ids) {
+            let mut query = QueryBuilder::<sqlx::Sqlite>::new(format!(
+                "SELECT {} FROM users WHERE id IN (",
+                user_columns(Dialect::Sqlite)
+            ));
+            let mut separated = query.separated(", ");
+            for id in chunk {
+                separated.push_bind(id);
+            }
+            separated.push_unseparated(")");
+
+            let rows: Vec<UserRow> = query
+                .build_query_as()
+                .fetch_all(&self.pool)
+                .await
+                .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+            for row in rows {
+                users.push(User::try_from(row)?);
+            }
+        }
+
+        Ok(users)
+    }
+
     async fn find_by_subject(&self, subject: &str) -> DomainResult<Option<User>> {
-        let row: Option<UserRow> = sqlx::query_as(
-            "SELECT id, subject, email, password_hash, created_at FROM users WHERE subject = ?",
-        )
-        .bind(subject)
-        .fetch_optional(&self.pool)
-        .await
-        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+        let row: Option<UserRow> = sqlx::query_as(&select_user_by(Dialect::Sqlite, "subject"))
+            .bind(subject)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
 
         row.map(User::try_from).transpose()
     }
 
     async fn find_by_email(&self, email: &str) -> DomainResult<Option<User>> {
-        let row: Option<UserRow> = sqlx::query_as(
-            "SELECT id, subject, email, password_hash, created_at FROM users WHERE email = ?",
-        )
-        .bind(email)
-        .fetch_optional(&self.pool)
-        .await
-        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+        let row: Option<UserRow> = sqlx::query_as(&select_user_by(Dialect::Sqlite, "email"))
+            .bind(email)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
 
         row.map(User::try_from).transpose()
     }
 
-    async fn save(&self, user: &User) -> DomainResult<()> {
-        let id = user.id.to_string();
-        let created_at = user.created_at.to_rfc3339();
-
-        sqlx::query(
-            r#"
-            INSERT INTO users (id, subject, email, password_hash, created_at)
-            VALUES (?, ?, ?, ?, ?)
-            ON CONFLICT(id) DO UPDATE SET
-                subject = excluded.subject,
-                email = excluded.email,
-                password_hash = excluded.password_hash
-            "#,
-        )
-        .bind(&id)
-        .bind(&user.subject)
-        .bind(user.email.as_ref()) // Use .as_ref() to get the inner &str
-        .bind(&user.password_hash)
-        .bind(&created_at)
-        .execute(&self.pool)
+    async fn exists_by_email(&self, email: &str) -> DomainResult<bool> {
+        sqlx::query_scalar(&exists_by_email_sql(Dialect::Sqlite))
+            .bind(email)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))
+    }
+
+    async fn find_by_canonical_email(&self, canonical: &str) -> DomainResult<Option<User>> {
+        let row: Option<UserRow> =
+            sqlx::query_as(&select_user_by(Dialect::Sqlite, "canonical_email"))
+                .bind(canonical)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        row.map(User::try_from).transpose()
+    }
+
+    async fn find_inactive_since(&self, cutoff: DateTime<Utc>) -> DomainResult<Vec<User>> {
+        // julianday() accepts both RFC3339 and SQLite's native datetime format
+        let rows: Vec<UserRow> = sqlx::query_as(&format!(
+            "SELECT {} FROM users WHERE deleted_at IS NULL AND julianday(COALESCE(last_login_at, created_at)) < julianday(?)",
+            user_columns(Dialect::Sqlite)
+        ))
+        .bind(format_db_datetime(&cutoff))
+        .fetch_all(&self.pool)
         .await
         .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
 
+        rows.into_iter().map(User::try_from).collect()
+    }
+
+    async fn search(
+        &self,
+        term: &str,
+        match_mode: EmailMatchMode,
+        limit: u32,
+        offset: u32,
+    ) -> DomainResult<Vec<User>> {
+        let rows: Vec<UserRow> = sqlx::query_as(&search_users_sql(Dialect::Sqlite))
+            .bind(like_pattern(term, match_mode))
+            .bind(i64::from(limit))
+            .bind(i64::from(offset))
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        rows.into_iter().map(User::try_from).collect()
+    }
+
+    async fn count_matching(&self, term: &str, match_mode: EmailMatchMode) -> DomainResult<u64> {
+        let count: i64 = sqlx::query_scalar(&count_matching_sql(Dialect::Sqlite))
+            .bind(like_pattern(term, match_mode))
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(count as u64)
+    }
+
+    async fn find(&self, filter: &UserFilter, limit: u32, offset: u32) -> DomainResult<Vec<User>> {
+        let mut query = QueryBuilder::<sqlx::Sqlite>::new(format!(
+            "SELECT {} FROM users",
+            user_columns(Dialect::Sqlite)
+        ));
+        push_sqlite_filters(&mut query, filter);
+        query
+            .push(" ORDER BY email LIMIT ")
+            .push_bind(i64::from(limit))
+            .push(" OFFSET ")
+            .push_bind(i64::from(offset));
+
+        let rows: Vec<UserRow> = query
+            .build_query_as()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        rows.into_iter().map(User::try_from).collect()
+    }
+
+    async fn save(&self, user: &User) -> DomainResult<()> {
+        Self::upsert(&self.pool, user)
+            .await
+            .map_err(|e| map_save_error(e, user))
+    }
+
+    async fn save_with_events(&self, user: &User, events: &[OutboxEvent]) -> DomainResult<()> {
+        let mut tx = self.begin().await?;
+        self.save_in(tx.as_mut(), user, events).await?;
+        tx.commit().await
+    }
+
+    async fn begin(&self) -> DomainResult<Box<dyn Transaction>> {
+        let tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+        Ok(Box::new(SqliteTransaction::new(tx)))
+    }
+
+    async fn save_in(
+        &self,
+        tx: &mut dyn Transaction,
+        user: &User,
+        events: &[OutboxEvent],
+    ) -> DomainResult<()> {
+        let tx = &mut SqliteTransaction::from_dyn(tx)?.0;
+        Self::upsert(&mut **tx, user)
+            .await
+            .map_err(|e| map_save_error(e, user))?;
+        for event in events {
+            outbox_repository::insert_sqlite(&mut **tx, event)
+                .await
+                .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    async fn update_display_name(
+        &self,
+        id: Uuid,
+        display_name: Option<&DisplayName>,
+    ) -> DomainResult<()> {
+        sqlx::query(&update_display_name_sql(Dialect::Sqlite))
+            .bind(display_name.map(|name| name.as_ref().to_string()))
+            .bind(id.to_string())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
         Ok(())
     }
 
     async fn delete(&self, id: Uuid) -> DomainResult<()> {
         let id_str = id.to_string();
-        sqlx::query("DELETE FROM users WHERE id = ?")
+        sqlx::query(&delete_user_sql(Dialect::Sqlite))
             .bind(&id_str)
             .execute(&self.pool)
             .await
@@ -136,6 +645,47 @@ impl UserRepository for SqliteUserRepository {
 
         Ok(())
     }
+
+    async fn delete_in(&self, tx: &mut dyn Transaction, id: Uuid) -> DomainResult<()> {
+        let tx = &mut SqliteTransaction::from_dyn(tx)?.0;
+        sqlx::query(&delete_user_sql(Dialect::Sqlite))
+            .bind(id.to_string())
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn claim_first_admin(
+        &self,
+        tx: &mut dyn Transaction,
+        user_id: Uuid,
+    ) -> DomainResult<bool> {
+        let tx = &mut SqliteTransaction::from_dyn(tx)?.0;
+        let result = sqlx::query(&claim_first_admin_sql(Dialect::Sqlite))
+            .bind(user_id.to_string())
+            .bind(format_db_datetime(&Utc::now()))
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn claim_totp_step(&self, user_id: Uuid, step: u64) -> DomainResult<bool> {
+        let step = i64::try_from(step)
+            .map_err(|_| DomainError::validation("TOTP time step out of range"))?;
+        let result = sqlx::query(&claim_totp_step_sql(Dialect::Sqlite))
+            .bind(step)
+            .bind(user_id.to_string())
+            .bind(step)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(result.rows_affected() == 1)
+    }
 }
 
 #[cfg(all(test, feature = "sqlite"))]
@@ -161,7 +711,7 @@ mod tests {
         let repo = SqliteUserRepository::new(pool);
 
         let email = Email::try_from("test@example.com").unwrap();
-        let user = User::new("oidc|123", email);
+        let user = User::new("oidc|123", email).unwrap();
         repo.save(&user).await.unwrap();
 
         let found = repo.find_by_id(user.id).await.unwrap();
@@ -186,6 +736,21 @@ mod tests {
         let found = found.unwrap();
         assert_eq!(found.email_str(), "local@example.com");
         assert_eq!(found.password_hash, Some("hashed_pw".to_string()));
+        assert!(!found.email_verified);
+    }
+
+    #[tokio::test]
+    async fn test_email_verified_round_trip() {
+        let pool = setup_test_db().await;
+        let repo = SqliteUserRepository::new(pool);
+
+        let email = Email::try_from("verified@example.com").unwrap();
+        let mut user = User::new_local(email, "hashed_pw");
+        user.mark_email_verified();
+        repo.save(&user).await.unwrap();
+
+        let found = repo.find_by_id(user.id).await.unwrap().unwrap();
+        assert!(found.email_verified);
     }
 
     #[tokio::test]
@@ -194,7 +759,7 @@ mod tests {
         let repo = SqliteUserRepository::new(pool);
 
         let email = Email::try_from("user@gmail.com").unwrap();
-        let user = User::new("google|456", email);
+        let user = User::new("google|456", email).unwrap();
         repo.save(&user).await.unwrap();
 
         let found = repo.find_by_subject("google|456").await.unwrap();
@@ -202,19 +767,520 @@ mod tests {
         assert_eq!(found.unwrap().id, user.id);
     }
 
+    #[tokio::test]
+    async fn test_exists_by_email() {
+        let pool = setup_test_db().await;
+        let repo = SqliteUserRepository::new(pool);
+
+        let email = Email::try_from("taken@example.com").unwrap();
+        repo.save(&User::new_local(email, "hashed_pw"))
+            .await
+            .unwrap();
+
+        assert!(repo.exists_by_email("taken@example.com").await.unwrap());
+        assert!(!repo.exists_by_email("free@example.com").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_canonical_email_is_found_and_unique() {
+        let pool = setup_test_db().await;
+        let repo = SqliteUserRepository::new(pool);
+
+        let mut user = User::new_local(Email::try_from("a.da+x@gmail.com").unwrap(), "hashed_pw");
+        user.canonical_email = Some("ada@gmail.com".to_string());
+        repo.save(&user).await.unwrap();
+        // Accounts without one never collide
+        for email in ["one@example.com", "two@example.com"] {
+            repo.save(&User::new_local(
+                Email::try_from(email).unwrap(),
+                "hashed_pw",
+            ))
+            .await
+            .unwrap();
+        }
+
+        let found = repo.find_by_canonical_email("ada@gmail.com").await.unwrap();
+        assert_eq!(found.map(|u| u.id), Some(user.id));
+
+        let mut twin = User::new_local(Email::try_from("ada@gmail.com").unwrap(), "hashed_pw");
+        twin.canonical_email = Some("ada@gmail.com".to_string());
+        let result = repo.save(&twin).await;
+        assert!(matches!(result, Err(DomainError::EmailAlreadyExists(_))));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_datetime_names_value_column_and_user() {
+        let pool = setup_test_db().await;
+        let repo = SqliteUserRepository::new(pool.clone());
+
+        let email = Email::try_from("corrupt@example.com").unwrap();
+        let user = User::new_local(email, "hashed_pw");
+        repo.save(&user).await.unwrap();
+        sqlx::query("UPDATE users SET last_login_at = 'last tuesday' WHERE id = ?")
+            .bind(user.id.to_string())
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let err = repo.find_by_id(user.id).await.unwrap_err();
+        let DomainError::RepositoryError(message) = err else {
+            panic!("expected a repository error, got {:?}", err);
+        };
+        assert!(message.contains("'last tuesday'"), "{}", message);
+        assert!(message.contains("users.last_login_at"), "{}", message);
+        assert!(message.contains(&user.id.to_string()), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn test_empty_subject_in_storage_is_rejected() {
+        let pool = setup_test_db().await;
+        let repo = SqliteUserRepository::new(pool.clone());
+
+        let email = Email::try_from("blank@example.com").unwrap();
+        let user = User::new_local(email, "hashed_pw");
+        repo.save(&user).await.unwrap();
+        sqlx::query("UPDATE users SET subject = '' WHERE id = ?")
+            .bind(user.id.to_string())
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let err = repo.find_by_id(user.id).await.unwrap_err();
+        let DomainError::RepositoryError(message) = err else {
+            panic!("expected a repository error, got {:?}", err);
+        };
+        assert!(message.contains(&user.id.to_string()), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn test_preferences_round_trip() {
+        let pool = setup_test_db().await;
+        let repo = SqliteUserRepository::new(pool);
+
+        let mut user =
+            User::new("oidc|prefs", Email::try_from("prefs@example.com").unwrap()).unwrap();
+        repo.save(&user).await.unwrap();
+        let found = repo.find_by_id(user.id).await.unwrap().unwrap();
+        assert_eq!(found.preferences, serde_json::json!({}));
+
+        let preferences = serde_json::json!({"theme": "dark", "layout": {"sidebar": false}});
+        user.set_preferences(preferences.clone()).unwrap();
+        repo.save(&user).await.unwrap();
+
+        let found = repo.find_by_id(user.id).await.unwrap().unwrap();
+        assert_eq!(found.preferences, preferences);
+    }
+
+    #[tokio::test]
+    async fn test_display_name_round_trip() {
+        let pool = setup_test_db().await;
+        let repo = SqliteUserRepository::new(pool);
+
+        let mut user =
+            User::new("oidc|named", Email::try_from("named@example.com").unwrap()).unwrap();
+        repo.save(&user).await.unwrap();
+        let found = repo.find_by_id(user.id).await.unwrap().unwrap();
+        assert_eq!(found.display_name, None);
+
+        user.display_name = Some(DisplayName::new("Ada Lovelace").unwrap());
+        repo.save(&user).await.unwrap();
+        let found = repo.find_by_id(user.id).await.unwrap().unwrap();
+        assert_eq!(found.display_name, user.display_name);
+
+        user.display_name = None;
+        repo.save(&user).await.unwrap();
+        let found = repo.find_by_id(user.id).await.unwrap().unwrap();
+        assert_eq!(found.display_name, None);
+    }
+
+    #[tokio::test]
+    async fn test_display_name_update_leaves_other_columns() {
+        let pool = setup_test_db().await;
+        let repo = SqliteUserRepository::new(pool);
+
+        let mut user = User::new(
+            "oidc|rename",
+            Email::try_from("rename@example.com").unwrap(),
+        )
+        .unwrap();
+        repo.save(&user).await.unwrap();
+        // Changed after the profile update read its copy of the user
+        let preferences = serde_json::json!({"theme": "dark"});
+        user.set_preferences(preferences.clone()).unwrap();
+        repo.save(&user).await.unwrap();
+
+        let name = DisplayName::new("Ada Lovelace").unwrap();
+        repo.update_display_name(user.id, Some(&name))
+            .await
+            .unwrap();
+        let found = repo.find_by_id(user.id).await.unwrap().unwrap();
+        assert_eq!(found.display_name, Some(name));
+        assert_eq!(found.preferences, preferences);
+
+        repo.update_display_name(user.id, None).await.unwrap();
+        let found = repo.find_by_id(user.id).await.unwrap().unwrap();
+        assert_eq!(found.display_name, None);
+    }
+
+    #[tokio::test]
+    async fn test_totp_steps_are_claimed_once() {
+        let pool = setup_test_db().await;
+        let repo = SqliteUserRepository::new(pool);
+        let user = User::new("oidc|totp", Email::try_from("totp@example.com").unwrap()).unwrap();
+        repo.save(&user).await.unwrap();
+
+        assert!(repo.claim_totp_step(user.id, 100).await.unwrap());
+        assert!(!repo.claim_totp_step(user.id, 100).await.unwrap());
+        assert!(!repo.claim_totp_step(user.id, 99).await.unwrap());
+        assert!(repo.claim_totp_step(user.id, 101).await.unwrap());
+
+        // Saving the user leaves the recorded step alone
+        repo.save(&user).await.unwrap();
+        assert!(!repo.claim_totp_step(user.id, 101).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_role_round_trip() {
+        let pool = setup_test_db().await;
+        let repo = SqliteUserRepository::new(pool);
+
+        let mut user =
+            User::new("oidc|admin", Email::try_from("admin@example.com").unwrap()).unwrap();
+        user.role = Role::Admin;
+        repo.save(&user).await.unwrap();
+
+        let found = repo.find_by_id(user.id).await.unwrap().unwrap();
+        assert!(found.is_admin());
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_email_conflict() {
+        let pool = setup_test_db().await;
+        let repo = SqliteUserRepository::new(pool);
+
+        let first = User::new("oidc|1", Email::try_from("dup@example.com").unwrap()).unwrap();
+        let second = User::new("oidc|2", Email::try_from("dup@example.com").unwrap()).unwrap();
+        repo.save(&first).await.unwrap();
+
+        let result = repo.save(&second).await;
+        assert!(matches!(result, Err(DomainError::EmailAlreadyExists(_))));
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_subject_conflict() {
+        let pool = setup_test_db().await;
+        let repo = SqliteUserRepository::new(pool);
+
+        let first = User::new("oidc|same", Email::try_from("one@example.com").unwrap()).unwrap();
+        let second = User::new("oidc|same", Email::try_from("two@example.com").unwrap()).unwrap();
+        repo.save(&first).await.unwrap();
+
+        let result = repo.save(&second).await;
+        assert!(matches!(result, Err(DomainError::SubjectAlreadyExists(_))));
+    }
+
+    #[tokio::test]
+    async fn test_subject_lookup_is_case_sensitive() {
+        let pool = setup_test_db().await;
+        let repo = SqliteUserRepository::new(pool);
+
+        let upper = User::new("oidc|Alice", Email::try_from("one@example.com").unwrap()).unwrap();
+        let lower = User::new("oidc|alice", Email::try_from("two@example.com").unwrap()).unwrap();
+        repo.save(&upper).await.unwrap();
+        repo.save(&lower).await.unwrap();
+
+        let found = repo.find_by_subject("oidc|Alice").await.unwrap().unwrap();
+        assert_eq!(found.id, upper.id);
+        let found = repo.find_by_subject("oidc|alice").await.unwrap().unwrap();
+        assert_eq!(found.id, lower.id);
+        assert!(repo.find_by_subject("OIDC|ALICE").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_find_by_ids_skips_missing() {
+        let pool = setup_test_db().await;
+        let repo = SqliteUserRepository::new(pool);
+
+        let first = User::new("batch|1", Email::try_from("one@batch.com").unwrap()).unwrap();
+        let second = User::new("batch|2", Email::try_from("two@batch.com").unwrap()).unwrap();
+        repo.save(&first).await.unwrap();
+        repo.save(&second).await.unwrap();
+
+        let found = repo
+            .find_by_ids(&[first.id, Uuid::new_v4(), second.id, first.id])
+            .await
+            .unwrap();
+
+        let mut ids: Vec<Uuid> = found.iter().map(|u| u.id).collect();
+        ids.sort();
+        let mut expected = vec![first.id, second.id];
+        expected.sort();
+        assert_eq!(ids, expected);
+    }
+
+    #[tokio::test]
+    async fn test_find_by_ids_chunks_large_input() {
+        let pool = setup_test_db().await;
+        let repo = SqliteUserRepository::new(pool);
+
+        let first = User::new("chunk|1", Email::try_from("one@chunk.com").unwrap()).unwrap();
+        let last = User::new("chunk|2", Email::try_from("two@chunk.com").unwrap()).unwrap();
+        repo.save(&first).await.unwrap();
+        repo.save(&last).await.unwrap();
+
+        // Spread the existing users across several chunks
+        let mut ids = vec![first.id];
+        ids.extend((0..MAX_IDS_PER_QUERY * 2).map(|_| Uuid::new_v4()));
+        ids.push(last.id);
+        assert!(id_chunks(&ids).len() > 2);
+
+        let found = repo.find_by_ids(&ids).await.unwrap();
+        assert_eq!(found.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_find_inactive_since() {
+        let pool = setup_test_db().await;
+        let repo = SqliteUserRepository::new(pool);
+        let now = Utc::now();
+
+        let mut stale =
+            User::new("stale|1", Email::try_from("stale@example.com").unwrap()).unwrap();
+        stale.last_login_at = Some(now - chrono::Duration::days(100));
+        let mut fresh =
+            User::new("fresh|1", Email::try_from("fresh@example.com").unwrap()).unwrap();
+        fresh.last_login_at = Some(now - chrono::Duration::days(1));
+        let mut never =
+            User::new("never|1", Email::try_from("never@example.com").unwrap()).unwrap();
+        never.created_at = now - chrono::Duration::days(100);
+        let mut deleted =
+            User::new("gone|1", Email::try_from("gone@example.com").unwrap()).unwrap();
+        deleted.last_login_at = Some(now - chrono::Duration::days(100));
+        deleted.deleted_at = Some(now);
+        for user in [&stale, &fresh, &never, &deleted] {
+            repo.save(user).await.unwrap();
+        }
+
+        let found = repo
+            .find_inactive_since(now - chrono::Duration::days(30))
+            .await
+            .unwrap();
+        let mut ids: Vec<Uuid> = found.iter().map(|u| u.id).collect();
+        ids.sort();
+        let mut expected = vec![stale.id, never.id];
+        expected.sort();
+        assert_eq!(ids, expected);
+    }
+
     #[tokio::test]
     async fn test_delete_user() {
         let pool = setup_test_db().await;
         let repo = SqliteUserRepository::new(pool);
 
         let email = Email::try_from("delete@test.com").unwrap();
-        let user = User::new("test|789", email);
+        let user = User::new("test|789", email).unwrap();
         repo.save(&user).await.unwrap();
         repo.delete(user.id).await.unwrap();
 
         let found = repo.find_by_id(user.id).await.unwrap();
         assert!(found.is_none());
     }
+
+    async fn save_emails(repo: &SqliteUserRepository, emails: &[&str]) {
+        for (n, email) in emails.iter().enumerate() {
+            let user = User::new(format!("oidc|{}", n), Email::try_from(*email).unwrap()).unwrap();
+            repo.save(&user).await.unwrap();
+        }
+    }
+
+    fn emails(users: &[User]) -> Vec<&str> {
+        users.iter().map(User::email_str).collect()
+    }
+
+    #[tokio::test]
+    async fn test_search_prefix_vs_contains() {
+        let repo = SqliteUserRepository::new(setup_test_db().await);
+        save_emails(
+            &repo,
+            &["anna@example.com", "annabel@test.org", "joanna@example.com"],
+        )
+        .await;
+
+        let found = repo
+            .search("anna", EmailMatchMode::Prefix, 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(emails(&found), ["anna@example.com", "annabel@test.org"]);
+        let count = repo.count_matching("anna", EmailMatchMode::Prefix).await;
+        assert_eq!(count.unwrap(), 2);
+
+        let found = repo
+            .search("anna", EmailMatchMode::Contains, 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(
+            emails(&found),
+            ["anna@example.com", "annabel@test.org", "joanna@example.com"]
+        );
+
+        let page = repo
+            .search("anna", EmailMatchMode::Contains, 1, 2)
+            .await
+            .unwrap();
+        assert_eq!(emails(&page), ["joanna@example.com"]);
+    }
+
+    #[tokio::test]
+    async fn test_search_treats_wildcards_literally() {
+        let repo = SqliteUserRepository::new(setup_test_db().await);
+        save_emails(&repo, &["a_b@example.com", "axb@example.com"]).await;
+
+        let found = repo
+            .search("a_b", EmailMatchMode::Prefix, 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(emails(&found), ["a_b@example.com"]);
+
+        let count = repo.count_matching("%", EmailMatchMode::Contains).await;
+        assert_eq!(count.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_find_with_combined_filters() {
+        let repo = SqliteUserRepository::new(setup_test_db().await);
+        let now = Utc::now();
+
+        let mut admin = User::new("oidc|1", Email::try_from("ada@example.com").unwrap()).unwrap();
+        admin.role = Role::Admin;
+        admin.created_at = now - chrono::Duration::days(5);
+        let mut old_admin =
+            User::new("oidc|2", Email::try_from("adam@example.com").unwrap()).unwrap();
+        old_admin.role = Role::Admin;
+        old_admin.created_at = now - chrono::Duration::days(50);
+        let mut deleted_admin =
+            User::new("oidc|3", Email::try_from("adele@example.com").unwrap()).unwrap();
+        deleted_admin.role = Role::Admin;
+        deleted_admin.created_at = now - chrono::Duration::days(5);
+        deleted_admin.deleted_at = Some(now);
+        let mut member =
+            User::new("oidc|4", Email::try_from("adrian@example.com").unwrap()).unwrap();
+        member.created_at = now - chrono::Duration::days(5);
+        for user in [&admin, &old_admin, &deleted_admin, &member] {
+            repo.save(user).await.unwrap();
+        }
+
+        let filter = UserFilter {
+            role: Some(Role::Admin),
+            is_active: Some(true),
+            created_between: Some((now - chrono::Duration::days(30), now)),
+            email_contains: Some("AD".to_string()),
+        };
+        let found = repo.find(&filter, 10, 0).await.unwrap();
+        assert_eq!(emails(&found), ["ada@example.com"]);
+
+        let filter = UserFilter {
+            is_active: Some(false),
+            ..UserFilter::default()
+        };
+        let found = repo.find(&filter, 10, 0).await.unwrap();
+        assert_eq!(emails(&found), ["adele@example.com"]);
+
+        let filter = UserFilter {
+            email_contains: Some("%".to_string()),
+            ..UserFilter::default()
+        };
+        assert!(repo.find(&filter, 10, 0).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_find_with_empty_filter_returns_all() {
+        let repo = SqliteUserRepository::new(setup_test_db().await);
+        save_emails(&repo, &["b@example.com", "a@example.com", "c@example.com"]).await;
+
+        let found = repo.find(&UserFilter::default(), 10, 0).await.unwrap();
+        assert_eq!(
+            emails(&found),
+            ["a@example.com", "b@example.com", "c@example.com"]
+        );
+
+        let page = repo.find(&UserFilter::default(), 1, 1).await.unwrap();
+        assert_eq!(emails(&page), ["b@example.com"]);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_first_admin_claims_have_one_winner() {
+        use crate::db::{ConnectionSettings, create_pool};
+        use std::time::Duration;
+
+        // A file, so the claims really run on separate connections
+        let path = std::env::temp_dir().join(format!("bootstrap-{}.db", Uuid::new_v4()));
+        let config = DatabaseConfig {
+            url: format!("sqlite:{}?mode=rwc", path.display()),
+            max_connections: 8,
+            min_connections: 1,
+            acquire_timeout: Duration::from_secs(30),
+        };
+        let db_pool = create_pool(config, &ConnectionSettings::default())
+            .await
+            .unwrap();
+        run_migrations(&db_pool).await.unwrap();
+        let pool = match db_pool {
+            DatabasePool::Sqlite(pool) => pool,
+            #[allow(unreachable_patterns)]
+            _ => panic!("expected a SQLite pool"),
+        };
+        let repo = SqliteUserRepository::new(pool.clone());
+
+        let claims = (0..8).map(|i| {
+            let repo = repo.clone();
+            tokio::spawn(async move {
+                let email = Email::try_from(format!("user{}@example.com", i)).unwrap();
+                let mut user = User::new(format!("oidc|{}", i), email).unwrap();
+                let mut tx = repo.begin().await.unwrap();
+                let claimed = repo.claim_first_admin(tx.as_mut(), user.id).await.unwrap();
+                if claimed {
+                    user.role = Role::Admin;
+                }
+                repo.save_in(tx.as_mut(), &user, &[]).await.unwrap();
+                tx.commit().await.unwrap();
+                claimed
+            })
+        });
+        let mut winners = 0;
+        for claim in claims.collect::<Vec<_>>() {
+            winners += usize::from(claim.await.unwrap());
+        }
+        assert_eq!(winners, 1);
+
+        let admins = repo
+            .find(
+                &UserFilter {
+                    role: Some(Role::Admin),
+                    ..Default::default()
+                },
+                10,
+                0,
+            )
+            .await
+            .unwrap();
+        assert_eq!(admins.len(), 1);
+
+        // Deleting every user doesn't reopen the claim
+        for user in repo.find(&UserFilter::default(), 10, 0).await.unwrap() {
+            repo.delete(user.id).await.unwrap();
+        }
+        let mut tx = repo.begin().await.unwrap();
+        assert!(
+            !repo
+                .claim_first_admin(tx.as_mut(), Uuid::new_v4())
+                .await
+                .unwrap()
+        );
+        tx.rollback().await.unwrap();
+
+        pool.close().await;
+        let _ = std::fs::remove_file(path);
+    }
 }
 
 /// PostgreSQL adapter for UserRepository
@@ -229,6 +1295,18 @@ impl PostgresUserRepository {
     pub fn new(pool: sqlx::Pool<sqlx::Postgres>) -> Self {
         Self { pool }
     }
+
+    /// Insert or update a user using any executor, so it can join a transaction
+    async fn upsert<'e, E>(executor: E, user: &User) -> Result<(), sqlx::Error>
+    where
+        E: sqlx::PgExecutor<'e>,
+    {
+        bind_user(sqlx::query(&upsert_user_sql(Dialect::Postgres)), user)
+            .execute(executor)
+            .await?;
+
+        Ok(())
+    }
 }
 
 #[cfg(feature = "postgres")]
@@ -236,70 +1314,206 @@ impl PostgresUserRepository {
 impl UserRepository for PostgresUserRepository {
     async fn find_by_id(&self, id: Uuid) -> DomainResult<Option<User>> {
         let id_str = id.to_string();
-        let row: Option<UserRow> = sqlx::query_as(
-            "SELECT id, subject, email, password_hash, created_at FROM users WHERE id = $1",
-        )
-        .bind(&id_str)
-        .fetch_optional(&self.pool)
-        .await
-        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+        let row: Option<UserRow> = sqlx::query_as(&select_user_by(Dialect::Postgres, "id"))
+            .bind(&id_str)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
 
         row.map(User::try_from).transpose()
     }
 
+    async fn find_by_id_for_update(&self, id: Uuid) -> DomainResult<Option<User>> {
+        self.find_by_id(id).await
+    }
+
+    async fn find_by_ids(&self, ids: &[Uuid]) -> DomainResult<Vec<User>> {
+        let mut users = Vec::with_capacity(ids.len());
+
+        for chunk in id_chunks(ids) {
+            let mut query = QueryBuilder::<sqlx::Postgres>::new(format!(
+                "SELECT {} FROM users WHERE id IN (",
+                user_columns(Dialect::Postgres)
+            ));
+            let mut separated = query.separated(", ");
+            for id in chunk {
+                separated.push_bind(id);
+                separated.push_unseparated("::uuid");
+            }
+            separated.push_unseparated(")");
+
+            let rows: Vec<UserRow> = query
+                .build_query_as()
+                .fetch_all(&self.pool)
+                .await
+                .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+            for row in rows {
+                users.push(User::try_from(row)?);
+            }
+        }
+
+        Ok(users)
+    }
+
     async fn find_by_subject(&self, subject: &str) -> DomainResult<Option<User>> {
-        let row: Option<UserRow> = sqlx::query_as(
-            "SELECT id, subject, email, password_hash, created_at FROM users WHERE subject = $1",
-        )
-        .bind(subject)
-        .fetch_optional(&self.pool)
-        .await
-        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+        let row: Option<UserRow> = sqlx::query_as(&select_user_by(Dialect::Postgres, "subject"))
+            .bind(subject)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
 
         row.map(User::try_from).transpose()
     }
 
     async fn find_by_email(&self, email: &str) -> DomainResult<Option<User>> {
-        let row: Option<UserRow> = sqlx::query_as(
-            "SELECT id, subject, email, password_hash, created_at FROM users WHERE email = $1",
-        )
-        .bind(email)
-        .fetch_optional(&self.pool)
-        .await
-        .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+        let row: Option<UserRow> = sqlx::query_as(&select_user_by(Dialect::Postgres, "email"))
+            .bind(email)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
 
         row.map(User::try_from).transpose()
     }
 
-    async fn save(&self, user: &User) -> DomainResult<()> {
-        let id = user.id.to_string();
-        let created_at = user.created_at.to_rfc3339();
-
-        sqlx::query(
-            r#"
-            INSERT INTO users (id, subject, email, password_hash, created_at)
-            VALUES ($1, $2, $3, $4, $5)
-            ON CONFLICT(id) DO UPDATE SET
-                subject = excluded.subject,
-                email = excluded.email,
-                password_hash = excluded.password_hash
-            "#,
-        )
-        .bind(&id)
-        .bind(&user.subject)
-        .bind(user.email.as_ref())
-        .bind(&user.password_hash)
-        .bind(&created_at)
-        .execute(&self.pool)
+    async fn exists_by_email(&self, email: &str) -> DomainResult<bool> {
+        sqlx::query_scalar(&exists_by_email_sql(Dialect::Postgres))
+            .bind(email)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))
+    }
+
+    async fn find_by_canonical_email(&self, canonical: &str) -> DomainResult<Option<User>> {
+        let row: Option<UserRow> =
+            sqlx::query_as(&select_user_by(Dialect::Postgres, "canonical_email"))
+                .bind(canonical)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        row.map(User::try_from).transpose()
+    }
+
+    async fn find_inactive_since(&self, cutoff: DateTime<Utc>) -> DomainResult<Vec<User>> {
+        let rows: Vec<UserRow> = sqlx::query_as(&format!(
+            "SELECT {} FROM users WHERE deleted_at IS NULL AND COALESCE(last_login_at::timestamptz, created_at) < $1::timestamptz",
+            user_columns(Dialect::Postgres)
+        ))
+        .bind(format_db_datetime(&cutoff))
+        .fetch_all(&self.pool)
         .await
         .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
 
+        rows.into_iter().map(User::try_from).collect()
+    }
+
+    async fn search(
+        &self,
+        term: &str,
+        match_mode: EmailMatchMode,
+        limit: u32,
+        offset: u32,
+    ) -> DomainResult<Vec<User>> {
+        let rows: Vec<UserRow> = sqlx::query_as(&search_users_sql(Dialect::Postgres))
+            .bind(like_pattern(term, match_mode))
+            .bind(i64::from(limit))
+            .bind(i64::from(offset))
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        rows.into_iter().map(User::try_from).collect()
+    }
+
+    async fn count_matching(&self, term: &str, match_mode: EmailMatchMode) -> DomainResult<u64> {
+        let count: i64 = sqlx::query_scalar(&count_matching_sql(Dialect::Postgres))
+            .bind(like_pattern(term, match_mode))
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(count as u64)
+    }
+
+    async fn find(&self, filter: &UserFilter, limit: u32, offset: u32) -> DomainResult<Vec<User>> {
+        let mut query = QueryBuilder::<sqlx::Postgres>::new(format!(
+            "SELECT {} FROM users",
+            user_columns(Dialect::Postgres)
+        ));
+        push_postgres_filters(&mut query, filter);
+        query
+            .push(" ORDER BY email LIMIT ")
+            .push_bind(i64::from(limit))
+            .push(" OFFSET ")
+            .push_bind(i64::from(offset));
+
+        let rows: Vec<UserRow> = query
+            .build_query_as()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        rows.into_iter().map(User::try_from).collect()
+    }
+
+    async fn save(&self, user: &User) -> DomainResult<()> {
+        Self::upsert(&self.pool, user)
+            .await
+            .map_err(|e| map_save_error(e, user))
+    }
+
+    async fn save_with_events(&self, user: &User, events: &[OutboxEvent]) -> DomainResult<()> {
+        let mut tx = self.begin().await?;
+        self.save_in(tx.as_mut(), user, events).await?;
+        tx.commit().await
+    }
+
+    async fn begin(&self) -> DomainResult<Box<dyn Transaction>> {
+        let tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+        Ok(Box::new(PostgresTransaction::new(tx)))
+    }
+
+    async fn save_in(
+        &self,
+        tx: &mut dyn Transaction,
+        user: &User,
+        events: &[OutboxEvent],
+    ) -> DomainResult<()> {
+        let tx = &mut PostgresTransaction::from_dyn(tx)?.0;
+        Self::upsert(&mut **tx, user)
+            .await
+            .map_err(|e| map_save_error(e, user))?;
+        for event in events {
+            outbox_repository::insert_postgres(&mut **tx, event)
+                .await
+                .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    async fn update_display_name(
+        &self,
+        id: Uuid,
+        display_name: Option<&DisplayName>,
+    ) -> DomainResult<()> {
+        sqlx::query(&update_display_name_sql(Dialect::Postgres))
+            .bind(display_name.map(|name| name.as_ref().to_string()))
+            .bind(id.to_string())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
         Ok(())
     }
 
     async fn delete(&self, id: Uuid) -> DomainResult<()> {
         let id_str = id.to_string();
-        sqlx::query("DELETE FROM users WHERE id = $1")
+        sqlx::query(&delete_user_sql(Dialect::Postgres))
             .bind(&id_str)
             .execute(&self.pool)
             .await
@@ -307,4 +1521,133 @@ impl UserRepository for PostgresUserRepository {
 
         Ok(())
     }
+
+    async fn delete_in(&self, tx: &mut dyn Transaction, id: Uuid) -> DomainResult<()> {
+        let tx = &mut PostgresTransaction::from_dyn(tx)?.0;
+        sqlx::query(&delete_user_sql(Dialect::Postgres))
+            .bind(id.to_string())
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn claim_first_admin(
+        &self,
+        tx: &mut dyn Transaction,
+        user_id: Uuid,
+    ) -> DomainResult<bool> {
+        let tx = &mut PostgresTransaction::from_dyn(tx)?.0;
+        let result = sqlx::query(&claim_first_admin_sql(Dialect::Postgres))
+            .bind(user_id.to_string())
+            .bind(format_db_datetime(&Utc::now()))
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn claim_totp_step(&self, user_id: Uuid, step: u64) -> DomainResult<bool> {
+        let step = i64::try_from(step)
+            .map_err(|_| DomainError::validation("TOTP time step out of range"))?;
+        let result = sqlx::query(&claim_totp_step_sql(Dialect::Postgres))
+            .bind(step)
+            .bind(user_id.to_string())
+            .bind(step)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| DomainError::RepositoryError(e.to_string()))?;
+
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+#[cfg(all(test, feature = "postgres"))]
+mod postgres_tests {
+    use super::*;
+    use crate::db::{ConnectionSettings, create_pool, run_migrations};
+    use chrono::SubsecRound;
+    use k_core::db::{DatabaseConfig, DatabasePool};
+    use std::time::Duration;
+
+    /// `None` unless `TEST_POSTGRES_URL` names a disposable database
+    async fn setup_test_db() -> Option<PostgresUserRepository> {
+        let url = std::env::var("TEST_POSTGRES_URL").ok()?;
+        let config = DatabaseConfig {
+            url,
+            max_connections: 2,
+            min_connections: 1,
+            acquire_timeout: Duration::from_secs(30),
+        };
+        let pool = create_pool(config, &ConnectionSettings::default())
+            .await
+            .unwrap();
+        run_migrations(&pool).await.unwrap();
+
+        match pool {
+            DatabasePool::Postgres(pool) => Some(PostgresUserRepository::new(pool)),
+            #[allow(unreachable_patterns)]
+            _ => panic!("expected a Postgres pool"),
+        }
+    }
+
+    /// Needs a disposable database: `TEST_POSTGRES_URL=postgres://... cargo test --features postgres`
+    #[tokio::test]
+    async fn test_typed_columns_round_trip() {
+        let Some(repo) = setup_test_db().await else {
+            return;
+        };
+
+        let tag = Uuid::new_v4().simple().to_string();
+        let email = Email::try_from(format!("pg-{tag}@example.com")).unwrap();
+        let mut user = User::new(format!("oidc|{tag}"), email).unwrap();
+        // timestamptz keeps microseconds
+        user.created_at = user.created_at.trunc_subsecs(6);
+        user.last_login_at = Some(Utc::now().trunc_subsecs(6));
+        user.set_preferences(serde_json::json!({"theme": "dark", "layout": {"sidebar": false}}))
+            .unwrap();
+        user.display_name = Some(DisplayName::new("Ada Lovelace").unwrap());
+        repo.save(&user).await.unwrap();
+        // Saving again takes the ON CONFLICT update path
+        repo.save(&user).await.unwrap();
+
+        let found = repo.find_by_id(user.id).await.unwrap().unwrap();
+        assert_eq!(found.id, user.id);
+        assert_eq!(found.preferences, user.preferences);
+        assert_eq!(found.display_name, user.display_name);
+        assert_eq!(found.created_at, user.created_at);
+        assert_eq!(found.last_login_at, user.last_login_at);
+
+        let by_subject = repo.find_by_subject(&user.subject).await.unwrap().unwrap();
+        assert_eq!(by_subject.id, user.id);
+        let by_ids = repo.find_by_ids(&[user.id, Uuid::new_v4()]).await.unwrap();
+        assert_eq!(by_ids.len(), 1);
+        let filter = UserFilter {
+            created_between: Some((
+                user.created_at,
+                user.created_at + chrono::Duration::seconds(1),
+            )),
+            email_contains: Some(tag.clone()),
+            ..Default::default()
+        };
+        assert_eq!(repo.find(&filter, 10, 0).await.unwrap().len(), 1);
+        let inactive = repo
+            .find_inactive_since(Utc::now() + chrono::Duration::hours(1))
+            .await
+            .unwrap();
+        assert!(inactive.iter().any(|found| found.id == user.id));
+
+        assert!(repo.claim_totp_step(user.id, 100).await.unwrap());
+        assert!(!repo.claim_totp_step(user.id, 100).await.unwrap());
+
+        repo.update_display_name(user.id, None).await.unwrap();
+        let found = repo.find_by_id(user.id).await.unwrap().unwrap();
+        assert_eq!(found.display_name, None);
+        assert_eq!(found.preferences, user.preferences);
+
+        repo.delete(user.id).await.unwrap();
+        assert!(repo.find_by_id(user.id).await.unwrap().is_none());
+    }
 }
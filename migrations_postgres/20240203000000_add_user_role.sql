@@ -0,0 +1,2 @@
+-- Authorization role of the user ('user' or 'admin')
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'user';
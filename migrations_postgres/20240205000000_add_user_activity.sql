@@ -0,0 +1,3 @@
+-- Track account activity and soft deletion
+ALTER TABLE users ADD COLUMN last_login_at TEXT;
+ALTER TABLE users ADD COLUMN deleted_at TEXT;
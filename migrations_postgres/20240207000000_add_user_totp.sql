@@ -0,0 +1,3 @@
+-- TOTP two-factor authentication; the secret is stored encrypted
+ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_secret TEXT;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_enabled BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,2 @@
+-- Lets `email LIKE 'term%'` use an index regardless of the database collation
+CREATE INDEX IF NOT EXISTS idx_users_email_pattern ON users(email text_pattern_ops);
@@ -0,0 +1,9 @@
+-- Session table used by tower-sessions' PostgresStore (default schema and table names).
+-- Matches PostgresStore::migrate(), so deployments that already ran it are unaffected.
+CREATE SCHEMA IF NOT EXISTS "tower_sessions";
+
+CREATE TABLE IF NOT EXISTS "tower_sessions"."session" (
+    id TEXT PRIMARY KEY NOT NULL,
+    data BYTEA NOT NULL,
+    expiry_date TIMESTAMPTZ NOT NULL
+);
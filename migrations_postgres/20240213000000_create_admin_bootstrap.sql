@@ -0,0 +1,8 @@
+-- Create admin_bootstrap table
+-- Holds at most one row: the account that became admin as the first user.
+-- Its primary key makes concurrent claims conflict, so only one succeeds.
+CREATE TABLE IF NOT EXISTS admin_bootstrap (
+    id INTEGER PRIMARY KEY NOT NULL CHECK (id = 1),
+    user_id TEXT NOT NULL,
+    claimed_at TEXT NOT NULL
+);
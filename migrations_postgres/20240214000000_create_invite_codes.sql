@@ -0,0 +1,8 @@
+-- Create invite_codes table
+CREATE TABLE IF NOT EXISTS invite_codes (
+    code TEXT PRIMARY KEY NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    used_by TEXT,
+    used_at TEXT
+);
@@ -0,0 +1,2 @@
+-- Free-form per-user settings, always a JSON object
+ALTER TABLE users ADD COLUMN IF NOT EXISTS preferences JSONB NOT NULL DEFAULT '{}'::jsonb;
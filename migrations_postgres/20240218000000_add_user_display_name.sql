@@ -0,0 +1,2 @@
+-- Name shown in place of the email; unset until the user picks one
+ALTER TABLE users ADD COLUMN IF NOT EXISTS display_name TEXT;
@@ -0,0 +1,2 @@
+-- Track whether the user's email address has been verified
+ALTER TABLE users ADD COLUMN email_verified BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+-- LIKE is case-insensitive in SQLite, so prefix searches only use a NOCASE index
+CREATE INDEX IF NOT EXISTS idx_users_email_nocase ON users(email COLLATE NOCASE);
@@ -0,0 +1,7 @@
+-- Session table used by tower-sessions' SqliteStore (default table name).
+-- Matches SqliteStore::migrate(), so deployments that already ran it are unaffected.
+CREATE TABLE IF NOT EXISTS tower_sessions (
+    id TEXT PRIMARY KEY NOT NULL,
+    data BLOB NOT NULL,
+    expiry_date INTEGER NOT NULL
+);
@@ -0,0 +1,11 @@
+-- Who a session belongs to and where it was opened, for the admin session list.
+-- Rows go away with their session, so the session store stays in charge of expiry.
+CREATE TABLE IF NOT EXISTS session_metadata (
+    session_id TEXT PRIMARY KEY NOT NULL REFERENCES tower_sessions(id) ON DELETE CASCADE,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    ip TEXT,
+    user_agent TEXT,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_session_metadata_user_id ON session_metadata(user_id);
@@ -0,0 +1,4 @@
+-- Uniqueness key for emails under canonicalization (plus tags, dot-insensitive
+-- providers); NULL for accounts registered without it
+ALTER TABLE users ADD COLUMN canonical_email TEXT;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_canonical_email ON users(canonical_email);
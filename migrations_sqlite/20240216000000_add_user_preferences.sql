@@ -0,0 +1,2 @@
+-- Free-form per-user settings, always a JSON object
+ALTER TABLE users ADD COLUMN preferences TEXT NOT NULL DEFAULT '{}';